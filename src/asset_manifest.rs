@@ -0,0 +1,153 @@
+//! The `asset-manifest.json` of the build outputs, through which backend templates & SSR servers
+//! reference the hashed outputs by their names.
+
+use std::collections::{BTreeMap, HashSet};
+
+use anyhow::{Context, Result};
+use serde::Serialize;
+
+use crate::config::RtcBuild;
+use crate::pipelines::integrity_hash;
+use crate::size_report::hash_pattern;
+
+/// The name of the manifest in the dist dir.
+pub const ASSET_MANIFEST_FILE: &str = "asset-manifest.json";
+
+/// The manifest of the outputs of a build.
+#[derive(Debug, Default, Serialize)]
+pub struct AssetManifest {
+    /// The outputs, by their paths without their content hashes, e.g. `app_bg.wasm`.
+    pub files: BTreeMap<String, ManifestEntry>,
+}
+
+/// An output of a build.
+#[derive(Debug, Serialize)]
+pub struct ManifestEntry {
+    /// The path of the output, relative to the dist dir.
+    pub path: String,
+    /// The public URL of the output.
+    pub url: String,
+    /// The size of the output in bytes.
+    pub size: u64,
+    /// The subresource integrity hash of the output.
+    pub integrity: String,
+}
+
+impl AssetManifest {
+    /// Read the given outputs of the staging dist dir, skipping their precompressed siblings.
+    ///
+    /// An output whose name without its hash is taken by another output, e.g. of the same file
+    /// name in another dir, is listed by its path instead.
+    pub async fn generate(cfg: &RtcBuild, paths: impl Iterator<Item = &String>) -> Result<Self> {
+        let paths = paths.collect::<HashSet<_>>();
+        let mut sorted = paths.iter().copied().collect::<Vec<_>>();
+        sorted.sort();
+        let hash_pattern = hash_pattern(cfg);
+        let mut manifest = Self::default();
+        for path in sorted {
+            let is_precompressed = [".gz", ".br"].iter().any(|ext| {
+                path.strip_suffix(ext)
+                    .is_some_and(|original| paths.contains(&original.to_owned()))
+            });
+            if is_precompressed || path == ASSET_MANIFEST_FILE {
+                continue;
+            }
+            let file = cfg.staging_path(path);
+            let bytes = tokio::fs::read(&file)
+                .await
+                .with_context(|| format!("error reading {:?}", file))?;
+            let mut name = match &hash_pattern {
+                Some(pattern) => pattern.replace(path, "$rest").into_owned(),
+                None => path.clone(),
+            };
+            if manifest.files.contains_key(&name) {
+                tracing::warn!(
+                    "{:?} has the same name {:?} as another output, and is listed by its path in \
+                     the asset manifest",
+                    path,
+                    name
+                );
+                name = path.clone();
+            }
+            let entry = ManifestEntry {
+                path: path.clone(),
+                url: format!("{}{}", cfg.public_url, path),
+                size: bytes.len() as u64,
+                integrity: integrity_hash(&bytes),
+            };
+            manifest.files.insert(name, entry);
+        }
+        Ok(manifest)
+    }
+
+    /// Serialize the manifest as pretty JSON, ending with a newline.
+    pub fn to_json(&self) -> Result<Vec<u8>> {
+        let mut json =
+            serde_json::to_vec_pretty(self).context("error serializing asset manifest")?;
+        json.push(b'\n');
+        Ok(json)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use anyhow::{ensure, Context, Result};
+
+    use super::AssetManifest;
+    use crate::config::RtcBuild;
+
+    #[tokio::test]
+    async fn outputs_are_listed_by_name_without_hash() -> Result<()> {
+        // Assemble.
+        let tmpdir = tempfile::tempdir().context("error building tempdir for test")?;
+        let mut cfg = RtcBuild::new_test(tmpdir.path()).await?;
+        cfg.filehash_length = Some(8);
+        cfg.public_url = "/app/".into();
+        let files = [
+            ("app-0123abcd_bg.wasm", "\0asm"),
+            ("app-0123abcd.js", "import"),
+            ("app-0123abcd.js.gz", ""),
+            ("index.html", "<html></html>"),
+            ("img/logo-89ef4567.png", "png"),
+            ("logo-01234567.png", "png"),
+            ("main-0123abcd.css", "a{}"),
+            ("main-4567cdef.css", "b{}"),
+        ];
+        for (path, contents) in files {
+            let file = cfg.staging_dist.join(path);
+            std::fs::create_dir_all(file.parent().context("file without parent")?)?;
+            std::fs::write(file, contents)?;
+        }
+        let paths = files.map(|(path, _)| path.to_owned());
+
+        // Action.
+        let manifest = AssetManifest::generate(&cfg, paths.iter()).await?;
+
+        // Assert.
+        let names = manifest.files.keys().collect::<Vec<_>>();
+        ensure!(
+            names
+                == [
+                    "app.js",
+                    "app_bg.wasm",
+                    "img/logo.png",
+                    "index.html",
+                    "logo.png",
+                    "main-4567cdef.css",
+                    "main.css"
+                ],
+            "unexpected names {:?}",
+            names
+        );
+        let wasm = &manifest.files["app_bg.wasm"];
+        ensure!(
+            wasm.path == "app-0123abcd_bg.wasm"
+                && wasm.url == "/app/app-0123abcd_bg.wasm"
+                && wasm.size == 4
+                && wasm.integrity.starts_with("sha384-"),
+            "unexpected entry {:?}",
+            wasm
+        );
+        Ok(())
+    }
+}
@@ -1,7 +1,8 @@
 (function () {
     var protocol = window.location.protocol === 'https:' ? 'wss:' : 'ws:';
-    var url = protocol + '//' + window.location.host + '/_trunk/ws';
+    var url = protocol + '//' + window.location.host + '{{__TRUNK_WS_PATH__}}';
     var poll_interval = 5000;
+    var overlay_id = '__trunk_error_overlay';
     var reload_upon_connect = () => {
         window.setTimeout(
             () => {
@@ -15,10 +16,61 @@
             },
             poll_interval);
     };
+    var show_error_overlay = (err) => {
+        var overlay = document.getElementById(overlay_id);
+        if (overlay) {
+            overlay.remove();
+        }
+        overlay = document.createElement('div');
+        overlay.id = overlay_id;
+        overlay.style.cssText = 'position:fixed;inset:0;z-index:2147483647;overflow:auto;' +
+            'padding:2em;background:rgba(24,24,24,0.95);color:#e8e8e8;' +
+            'font:14px/1.4 monospace;white-space:pre-wrap;cursor:pointer;';
+        overlay.title = 'Click to dismiss';
+        var title = document.createElement('div');
+        title.style.cssText = 'color:#ff5555;font-size:18px;margin-bottom:1em;';
+        title.textContent = 'Build failed';
+        var error = document.createElement('div');
+        error.textContent = err.output || err.error;
+        overlay.appendChild(title);
+        overlay.appendChild(error);
+        overlay.onclick = () => overlay.remove();
+        document.body.appendChild(overlay);
+    };
+    var replace_css = (urls) => {
+        // Swap the stylesheets in place, using the hrefs of the freshly built HTML.
+        fetch(window.location.href)
+            .then((res) => res.text())
+            .then((html) => {
+                var doc = new DOMParser().parseFromString(html, 'text/html');
+                var fresh = doc.querySelectorAll('link[rel="stylesheet"]');
+                var current = document.querySelectorAll('link[rel="stylesheet"]');
+                if (fresh.length !== current.length) {
+                    window.location.reload();
+                    return;
+                }
+                current.forEach((link, i) => {
+                    var href = fresh[i].getAttribute('href');
+                    if (urls.includes(new URL(href, window.location.href).pathname)) {
+                        link.href = href + (href.includes('?') ? '&' : '?') + '__trunk=' + Date.now();
+                    }
+                });
+            })
+            .catch(() => window.location.reload());
+    };
 
     var ws = new WebSocket(url);
     ws.onmessage = (ev) => {
         const msg = JSON.parse(ev.data);
+        if (msg.reconnect_interval) {
+            poll_interval = msg.reconnect_interval;
+        }
+        if (msg.error) {
+            show_error_overlay(msg.error);
+        }
+        if (msg.css) {
+            replace_css(msg.css);
+        }
         if (msg.reload) {
             window.location.reload();
         }
@@ -1,17 +1,29 @@
 //! Build system & asset pipelines.
 
-use std::path::PathBuf;
+use std::collections::{HashMap, HashSet};
+use std::io::Write;
+use std::path::{Path, PathBuf};
 use std::sync::Arc;
+use std::time::SystemTime;
 
-use anyhow::{Context, Result};
-use futures_util::stream::StreamExt;
+use anyhow::{bail, Context, Result};
+use flate2::write::GzEncoder;
+use flate2::Compression;
+use serde::Serialize;
 use tokio::fs;
-use tokio::sync::mpsc;
-use tokio_stream::wrappers::ReadDirStream;
+use tokio::sync::{broadcast, mpsc};
 
-use crate::common::{remove_dir_all, BUILDING, ERROR, SUCCESS};
-use crate::config::{RtcBuild, STAGE_DIR};
-use crate::pipelines::HtmlPipeline;
+use crate::asset_manifest::{AssetManifest, ASSET_MANIFEST_FILE};
+use crate::common::{dir_files, remove_dir_all, CommandError, BUILDING, ERROR, SUCCESS};
+use crate::config::{CompressionAlgorithm, RtcBuild, UnhashedNames, STAGE_DIR};
+use crate::messages::{self, Message};
+use crate::pipelines::{write_service_worker, HtmlPipeline, PipelineStage, SERVICE_WORKER_FILE};
+use crate::size_report::{hash_pattern, report_sizes};
+
+/// The extensions of the files which are precompressed.
+const PRECOMPRESSED_EXTENSIONS: &[&str] = &["wasm", "js", "css"];
+/// File extensions of stylesheets, changes to which may be hot-replaced.
+const STYLESHEET_EXTENSIONS: [&str; 3] = ["css", "sass", "scss"];
 
 /// A system used for building a Rust WASM app & bundling its assets.
 ///
@@ -24,6 +36,85 @@ pub struct BuildSystem {
     cfg: Arc<RtcBuild>,
     /// HTML build pipeline.
     html_pipeline: Arc<HtmlPipeline>,
+    /// The number of builds which have been started, including the failed & cancelled ones,
+    /// which is the sequential ID of the current build. The IDs of successful builds therefore
+    /// have gaps if builds failed in between.
+    build_count: u64,
+}
+
+/// Metadata on a successful build.
+#[derive(Clone, Debug)]
+pub struct BuildReport {
+    /// The sequential ID of the build, which counts all started builds, including failed ones.
+    pub id: u64,
+    /// The time at which the build started.
+    pub started: SystemTime,
+    /// The time at which the build finished.
+    pub finished: SystemTime,
+    /// The public URLs of all assets which were added or changed by the build.
+    pub changed_assets: Vec<String>,
+}
+
+/// An event of a build, which is sent to the subscribers of the build system.
+///
+/// A build which is cancelled by changes during a watch is followed by the `Started` event of
+/// the next build, instead of `Succeeded` or `Failed`.
+#[derive(Clone, Debug)]
+pub enum BuildEvent {
+    /// A build was started.
+    Started {
+        /// The sequential ID of the build.
+        id: u64,
+        /// The changed paths which triggered the build, which is empty for the initial build.
+        changes: Vec<PathBuf>,
+    },
+    /// A stage of the running build was started.
+    Progress {
+        /// The category of the stage, e.g. `cargo`, `bindgen`, `asset` or `dist`.
+        category: &'static str,
+        /// The name of the stage, e.g. the name of the Rust app or the asset.
+        name: String,
+    },
+    /// The build succeeded.
+    Succeeded {
+        report: BuildReport,
+        /// All files of the dist dir.
+        artifacts: Vec<Artifact>,
+        /// Whether only stylesheets were changed, which may be replaced in place.
+        stylesheets_only: bool,
+    },
+    /// The build failed.
+    Failed {
+        /// The sequential ID of the build.
+        id: u64,
+        /// The chain of the errors of the build, from the outermost context to the root cause.
+        errors: Vec<String>,
+        /// The output of the compiler, if the build failed during compilation.
+        output: Option<String>,
+    },
+}
+
+/// A file of the dist dir.
+#[derive(Clone, Debug, Serialize)]
+pub struct Artifact {
+    /// The path of the file, relative to the dist dir.
+    pub path: String,
+    /// The size of the file in bytes.
+    pub size: u64,
+}
+
+impl Artifact {
+    /// Collect the given paths relative to the dist dir with their sizes, sorted by path.
+    pub fn collect<'a>(dist: &Path, paths: impl Iterator<Item = &'a String>) -> Vec<Self> {
+        let mut artifacts: Vec<_> = paths
+            .map(|path| Self {
+                size: std::fs::metadata(dist.join(path)).map_or(0, |meta| meta.len()),
+                path: path.clone(),
+            })
+            .collect();
+        artifacts.sort_by(|a, b| a.path.cmp(&b.path));
+        artifacts
+    }
 }
 
 impl BuildSystem {
@@ -36,28 +127,99 @@ impl BuildSystem {
         ignore_chan: Option<mpsc::Sender<PathBuf>>,
     ) -> Result<Self> {
         let html_pipeline = Arc::new(HtmlPipeline::new(cfg.clone(), ignore_chan)?);
-        Ok(Self { cfg, html_pipeline })
+        if let Err(err) = cfg.pipeline_cache.prune().await {
+            tracing::warn!("error pruning the cached outputs of pipelines: {:?}", err);
+        }
+        Ok(Self {
+            cfg,
+            html_pipeline,
+            build_count: 0,
+        })
+    }
+
+    /// Subscribe to the events of the builds.
+    pub fn subscribe(&self) -> broadcast::Receiver<BuildEvent> {
+        self.cfg.events.subscribe()
     }
 
     /// Build the application described in the given build data.
+    pub async fn build(&mut self) -> Result<BuildReport> {
+        self.rebuild(&[]).await
+    }
+
+    /// Build the application after the given source paths were changed.
     #[tracing::instrument(level = "trace", skip(self))]
-    pub async fn build(&mut self) -> Result<()> {
+    pub async fn rebuild(&mut self, changes: &[PathBuf]) -> Result<BuildReport> {
         tracing::info!("{} starting build", BUILDING);
+        self.build_count += 1;
+        messages::emit(&Message::BuildStarted {
+            id: self.build_count,
+        });
+        let _ = self.cfg.events.send(BuildEvent::Started {
+            id: self.build_count,
+            changes: changes.to_vec(),
+        });
+        let started = SystemTime::now();
+        if let Some(timings) = &self.cfg.timings {
+            timings.reset();
+        }
+        self.cfg.rust_outputs.clear();
+        self.cfg.font_characters.clear();
+        self.cfg.output_hashes.clear();
+        self.cfg.pipeline_cache.start_build();
+        self.cfg.changes.set(changes);
         let res = self.do_build().await;
+        if let Some(timings) = &self.cfg.timings {
+            if let Err(err) = timings.write_report(self.cfg.reports_dir()).await {
+                tracing::warn!("error writing build timings: {:?}", err);
+            }
+        }
         match res {
-            Ok(_) => {
+            Ok(changed_assets) => {
                 tracing::info!("{} success", SUCCESS);
-                Ok(())
+                let report = BuildReport {
+                    id: self.build_count,
+                    started,
+                    finished: SystemTime::now(),
+                    changed_assets,
+                };
+                let asset_hashes = self.cfg.asset_hashes.get();
+                let artifacts = Artifact::collect(&self.cfg.final_dist, asset_hashes.keys());
+                messages::emit(&Message::BuildFinished {
+                    id: self.build_count,
+                    duration_ms: started.elapsed().unwrap_or_default().as_millis(),
+                    artifacts: &artifacts,
+                    changed_assets: &report.changed_assets,
+                });
+                let _ = self.cfg.events.send(BuildEvent::Succeeded {
+                    stylesheets_only: only_stylesheets_changed(changes, &report),
+                    report: report.clone(),
+                    artifacts,
+                });
+                Ok(report)
             }
             Err(err) => {
                 tracing::error!("{} error\n{:?}", ERROR, err);
+                let errors = messages::error_chain(&err);
+                messages::emit(&Message::BuildFailed {
+                    id: self.build_count,
+                    errors: &errors,
+                });
+                let _ = self.cfg.events.send(BuildEvent::Failed {
+                    id: self.build_count,
+                    errors,
+                    output: err
+                        .chain()
+                        .find_map(|err| err.downcast_ref::<CommandError>())
+                        .map(|err| console::strip_ansi_codes(&err.stderr).into_owned()),
+                });
                 Err(err)
             }
         }
     }
 
-    /// Internal business logic of `build`.
-    async fn do_build(&mut self) -> Result<()> {
+    /// Internal business logic of `build`, returning the public URLs of all changed assets.
+    async fn do_build(&mut self) -> Result<Vec<String>> {
         // Ensure the output dist directories are in place.
         fs::create_dir_all(self.cfg.final_dist.as_path())
             .await
@@ -76,13 +238,215 @@ impl BuildSystem {
             .context("error joining HTML pipeline")?
             .context("error from HTML pipeline")?;
 
+        let timing = self.cfg.time("dist", "hashing");
+        let mut asset_hashes = self
+            .hash_staging_dist()
+            .await
+            .context("error hashing built assets")?;
+        drop(timing);
+
+        // Write the service worker precaching the outputs, unless serving with autoreload.
+        if self.cfg.service_worker && self.cfg.autoreload_ws_path.is_none() {
+            let _timing = self.cfg.time("dist", "service worker");
+            let hash = write_service_worker(&self.cfg, &asset_hashes).await?;
+            asset_hashes.insert(SERVICE_WORKER_FILE.into(), hash);
+        }
+
+        // Write precompressed siblings of the outputs, which static hosts may serve instead.
+        if !self.cfg.precompress.is_empty() {
+            let _timing = self.cfg.time("dist", "precompression");
+            let compressed = self
+                .precompress_staging_dist(&asset_hashes)
+                .await
+                .context("error precompressing built assets")?;
+            asset_hashes.extend(compressed);
+        }
+
+        // Write the manifest of the outputs, which is an output itself.
+        if self.cfg.asset_manifest {
+            let _timing = self.cfg.time("dist", "asset manifest");
+            let manifest = AssetManifest::generate(&self.cfg, asset_hashes.keys())
+                .await?
+                .to_json()?;
+            fs::write(self.cfg.staging_dist.join(ASSET_MANIFEST_FILE), &manifest)
+                .await
+                .context("error writing asset manifest")?;
+            asset_hashes.insert(ASSET_MANIFEST_FILE.into(), seahash::hash(&manifest));
+        }
+
+        // Add the names of the hashed outputs without their hashes, which are not outputs
+        // themselves.
+        if let Some(kind) = self.cfg.unhashed_names {
+            let _timing = self.cfg.time("dist", "unhashed names");
+            self.add_unhashed_names(kind, &asset_hashes)
+                .await
+                .context("error adding unhashed names of outputs")?;
+        }
+
+        // Set the modification times of the outputs, as archives of the dist dir include them.
+        if self.cfg.reproducible {
+            self.set_staging_dist_mtimes()
+                .await
+                .context("error setting modification times of built assets")?;
+        }
+
         // Move distribution from staging dist to final dist
+        let timing = self.cfg.time("dist", "copying to dist");
         self.finalize_dist()
             .await
             .context("error applying built distribution")?;
+        drop(timing);
+
+        // Report the sizes of the final outputs.
+        if self.cfg.size_report {
+            let _timing = self.cfg.time("dist", "size report");
+            if let Err(err) = report_sizes(&self.cfg, asset_hashes.keys()).await {
+                tracing::warn!("error reporting output sizes: {:?}", err);
+            }
+        }
+
+        // Compare against the last build to find all added or changed assets.
+        let previous_hashes = self.cfg.asset_hashes.get();
+        let mut changed_assets = asset_hashes
+            .iter()
+            .filter(|(path, hash)| previous_hashes.get(*path) != Some(*hash))
+            .map(|(path, _)| format!("{}{}", self.cfg.public_url, path))
+            .collect::<Vec<_>>();
+        changed_assets.sort();
+        self.cfg.asset_hashes.set(asset_hashes);
+        Ok(changed_assets)
+    }
+
+    /// Hash the contents of all assets in the staging dist dir, keyed by their relative path.
+    ///
+    /// The hashes recorded by the pipelines are reused for the outputs which are unchanged since,
+    /// so that only the other files are read to hash them. As `build` & `post_build` hooks may
+    /// rewrite outputs in place within the resolution of modification times, all files are read
+    /// if any of these hooks are configured.
+    async fn hash_staging_dist(&self) -> Result<HashMap<String, u64>> {
+        let reuse_hashes = !self
+            .cfg
+            .hooks
+            .iter()
+            .any(|hook| matches!(hook.stage, PipelineStage::Build | PipelineStage::PostBuild));
+        self.cfg
+            .output_hashes
+            .hash_dir(&self.cfg.staging_dist, reuse_hashes)
+            .await
+    }
+
+    /// Add symlinks to, or copies of, the given hashed outputs of the staging dist dir with their
+    /// names without hashes, unless an output has the name already.
+    async fn add_unhashed_names(
+        &self,
+        kind: UnhashedNames,
+        asset_hashes: &HashMap<String, u64>,
+    ) -> Result<()> {
+        let Some(pattern) = hash_pattern(&self.cfg) else {
+            return Ok(());
+        };
+        let mut paths = asset_hashes.keys().collect::<Vec<_>>();
+        paths.sort();
+        let mut names = HashSet::new();
+        for path in paths {
+            let name = pattern.replace(path, "$rest").into_owned();
+            if &name == path || asset_hashes.contains_key(&name) {
+                continue;
+            }
+            if !names.insert(name.clone()) {
+                tracing::warn!(
+                    "{:?} has the same name {:?} as another output, which is kept",
+                    path,
+                    name
+                );
+                continue;
+            }
+            let file = self.cfg.staging_path(path);
+            let link = self.cfg.staging_path(&name);
+            match kind {
+                UnhashedNames::Copy => {
+                    fs::copy(&file, &link)
+                        .await
+                        .with_context(|| format!("error copying {:?} to {:?}", file, link))?;
+                }
+                UnhashedNames::Symlink => {
+                    // The hashed output is in the dir of its name, or in a subdir of it.
+                    let target = link
+                        .parent()
+                        .and_then(|dir| file.strip_prefix(dir).ok())
+                        .with_context(|| format!("{:?} is not next to {:?}", file, link))?;
+                    symlink_file(target, &link)
+                        .await
+                        .with_context(|| format!("error linking {:?} to {:?}", link, file))?;
+                }
+            }
+        }
         Ok(())
     }
 
+    /// Write compressed siblings (`.gz` & `.br`) of the given wasm, js & css files of the staging
+    /// dist dir, using the configured algorithms, returning the hashes of the siblings.
+    async fn precompress_staging_dist(
+        &self,
+        asset_hashes: &HashMap<String, u64>,
+    ) -> Result<HashMap<String, u64>> {
+        let files = asset_hashes.keys().filter(|path| {
+            Path::new(path)
+                .extension()
+                .and_then(|ext| ext.to_str())
+                .is_some_and(|ext| PRECOMPRESSED_EXTENSIONS.contains(&ext))
+        });
+        let mut tasks = Vec::new();
+        for path in files {
+            for algorithm in self.cfg.precompress.iter().copied() {
+                let level = self.cfg.precompress_level;
+                let (staging_dist, path) = (self.cfg.staging_dist.clone(), path.clone());
+                tasks.push(tokio::task::spawn_blocking(move || {
+                    precompress_file(&staging_dist, &path, algorithm, level)
+                }));
+            }
+        }
+        let mut hashes = HashMap::new();
+        for task in tasks {
+            let (path, hash) = task
+                .await
+                .context("error awaiting spawned precompression")??;
+            hashes.insert(path, hash);
+        }
+        Ok(hashes)
+    }
+
+    /// Find all files in the staging dist dir & its subdirs.
+    async fn staging_dist_files(&self) -> Result<Vec<PathBuf>> {
+        dir_files(&self.cfg.staging_dist)
+            .await
+            .context("error reading staging dist dir")
+    }
+
+    /// Set the modification times of the files of the staging dist dir to `SOURCE_DATE_EPOCH`, or
+    /// the Unix epoch if unset. Symlinks are skipped, as they are set with their targets.
+    async fn set_staging_dist_mtimes(&self) -> Result<()> {
+        let mtime = source_date_epoch()?;
+        let files = self.staging_dist_files().await?;
+        tokio::task::spawn_blocking(move || {
+            for path in files {
+                let metadata = std::fs::symlink_metadata(&path)
+                    .with_context(|| format!("error reading metadata of {:?}", path))?;
+                if metadata.file_type().is_symlink() {
+                    continue;
+                }
+                std::fs::File::options()
+                    .write(true)
+                    .open(&path)
+                    .and_then(|file| file.set_modified(mtime))
+                    .with_context(|| format!("error setting modification time of {:?}", path))?;
+            }
+            Ok(())
+        })
+        .await
+        .context("error awaiting modification times of built assets")?
+    }
+
     /// Creates a "staging area" (dist/.stage) for storing intermediate build results.
     async fn prepare_staging_dist(&self) -> Result<()> {
         // Prepare staging area in which we will assemble the latest build
@@ -100,59 +464,111 @@ impl BuildSystem {
     }
 
     /// Moves the contents of dist/.stage into dist, signifying the application
-    /// of a successful build. Also removes dist/.stage afterwards.
+    /// of a successful build. Then removes the stale files of previous builds from dist, unless
+    /// pruning is disabled, and removes dist/.stage.
     #[tracing::instrument(level = "trace", skip(self))]
     async fn finalize_dist(&self) -> Result<()> {
         let staging_dist = self.cfg.staging_dist.clone();
         tracing::info!("applying new distribution");
 
-        // Build succeeded, so delete everything in `dist`, move everything
-        // from `dist/.stage` to `dist`, and then delete `dist/.stage`.
-        self.clean_final().await?;
-        self.move_stage_to_final().await?;
-        fs::remove_dir(staging_dist)
+        // Replace the outputs in `dist` one by one, so that the dev server never misses files
+        // which are in both builds.
+        let outputs = move_stage_to_final(&staging_dist, &self.cfg.final_dist).await?;
+        if self.cfg.prune {
+            prune_final(&self.cfg.final_dist, &outputs).await?;
+        }
+        remove_dir_all(staging_dist)
             .await
             .context("error deleting staging dist dir")?;
 
         Ok(())
     }
+}
 
-    /// Move contents of stage dir to final dist dir.
-    async fn move_stage_to_final(&self) -> Result<()> {
-        let final_dist = self.cfg.final_dist.clone();
-        let staging_dist = self.cfg.staging_dist.clone();
+/// The outputs of a build in the final dist dir, by their paths relative to it.
+#[derive(Debug, Default)]
+struct DistOutputs {
+    /// The files & dirs moved from the staging dist dir.
+    moved: HashSet<PathBuf>,
+    /// The dirs of the final dist dir, into which the contents of the dirs of the same paths in
+    /// the staging dist dir were moved.
+    merged_dirs: HashSet<PathBuf>,
+}
 
-        let mut entries = fs::read_dir(&staging_dist)
+/// Move the contents of the stage dir to the final dist dir, replacing the files & dirs of the
+/// same paths, and merging the contents of the dirs which are in both.
+async fn move_stage_to_final(staging_dist: &Path, final_dist: &Path) -> Result<DistOutputs> {
+    let mut outputs = DistOutputs::default();
+    let mut dirs = vec![PathBuf::new()];
+    while let Some(dir) = dirs.pop() {
+        let mut entries = fs::read_dir(staging_dist.join(&dir))
             .await
-            .map(ReadDirStream::new)
             .context("error reading staging dist dir")?;
-        while let Some(entry) = entries.next().await {
-            let entry = entry.context("error reading contents of staging dist dir")?;
-            let target_path = final_dist.join(entry.file_name());
-
+        while let Some(entry) = entries
+            .next_entry()
+            .await
+            .context("error reading contents of staging dist dir")?
+        {
+            let path = dir.join(entry.file_name());
+            let target_path = final_dist.join(&path);
+            let is_dir = entry
+                .file_type()
+                .await
+                .context("error reading metadata of file in staging dist dir")?
+                .is_dir();
+            let target_type = fs::symlink_metadata(&target_path)
+                .await
+                .ok()
+                .map(|meta| meta.file_type());
+            match target_type {
+                Some(target_type) if target_type.is_dir() && is_dir => {
+                    dirs.push(path.clone());
+                    outputs.merged_dirs.insert(path);
+                    continue;
+                }
+                // A file replaces a file by the rename, but not a dir, nor does a dir a file.
+                Some(target_type) if target_type.is_dir() => remove_dir_all(target_path.clone())
+                    .await
+                    .context("error cleaning final dist")?,
+                Some(_) if is_dir => fs::remove_file(&target_path)
+                    .await
+                    .context("error cleaning final dist")?,
+                _ => {}
+            }
             fs::rename(entry.path(), &target_path)
                 .await
                 .with_context(|| {
                     format!("error moving {:?} to {:?}", &entry.path(), &target_path)
                 })?;
+            outputs.moved.insert(path);
         }
-        Ok(())
     }
+    Ok(outputs)
+}
 
-    /// Clean the contents of the final dist dir.
-    async fn clean_final(&self) -> Result<()> {
-        let final_dist = self.cfg.final_dist.clone();
-
-        let mut entries = fs::read_dir(&final_dist)
+/// Remove the files & dirs of the final dist dir which are not outputs of the build, such as the
+/// hashed outputs of previous builds & the outputs of removed assets.
+async fn prune_final(final_dist: &Path, outputs: &DistOutputs) -> Result<()> {
+    let mut dirs = vec![PathBuf::new()];
+    while let Some(dir) = dirs.pop() {
+        let mut entries = fs::read_dir(final_dist.join(&dir))
             .await
-            .map(ReadDirStream::new)
             .context("error reading final dist dir")?;
-        while let Some(entry) = entries.next().await {
-            let entry = entry.context("error reading contents of final dist dir")?;
-            if entry.file_name() == STAGE_DIR {
+        while let Some(entry) = entries
+            .next_entry()
+            .await
+            .context("error reading contents of final dist dir")?
+        {
+            let path = dir.join(entry.file_name());
+            if path.as_os_str() == STAGE_DIR || outputs.moved.contains(&path) {
+                continue;
+            }
+            if outputs.merged_dirs.contains(&path) {
+                dirs.push(path);
                 continue;
             }
 
+            tracing::debug!("pruning stale {:?} of the dist dir", path);
             let file_type = entry
                 .file_type()
                 .await
@@ -160,13 +576,458 @@ impl BuildSystem {
             if file_type.is_dir() {
                 remove_dir_all(entry.path())
                     .await
-                    .context("error cleaning final dist")?;
-            } else if file_type.is_symlink() || file_type.is_file() {
+                    .context("error pruning final dist")?;
+            } else {
                 fs::remove_file(entry.path())
                     .await
-                    .context("error cleaning final dist")?;
+                    .context("error pruning final dist")?;
             }
         }
+    }
+    Ok(())
+}
+
+/// The time of the `SOURCE_DATE_EPOCH` env var, in seconds since the Unix epoch, or the Unix epoch
+/// if unset.
+fn source_date_epoch() -> Result<SystemTime> {
+    let secs = match std::env::var("SOURCE_DATE_EPOCH") {
+        Ok(secs) => secs
+            .trim()
+            .parse::<u64>()
+            .with_context(|| format!("invalid SOURCE_DATE_EPOCH {:?}", secs))?,
+        Err(_) => 0,
+    };
+    Ok(SystemTime::UNIX_EPOCH + std::time::Duration::from_secs(secs))
+}
+
+/// Write a sibling of the given file of the staging dist dir, compressed using the given algorithm
+/// & level, which defaults to the highest level of the algorithm, returning its path & hash.
+fn precompress_file(
+    staging_dist: &Path,
+    path: &str,
+    algorithm: CompressionAlgorithm,
+    level: Option<u32>,
+) -> Result<(String, u64)> {
+    let ext = match algorithm {
+        CompressionAlgorithm::Gzip => "gz",
+        CompressionAlgorithm::Br => "br",
+        CompressionAlgorithm::Deflate => bail!("deflate is not supported for precompression"),
+    };
+    let mut file = staging_dist.to_owned();
+    file.extend(path.split('/'));
+    let bytes = std::fs::read(&file).with_context(|| format!("error reading {:?}", file))?;
+    let compressed = compress(&bytes, algorithm, level)?;
+
+    let compressed_path = format!("{}.{}", path, ext);
+    let mut compressed_file = staging_dist.to_owned();
+    compressed_file.extend(compressed_path.split('/'));
+    std::fs::write(&compressed_file, &compressed)
+        .with_context(|| format!("error writing {:?}", compressed_file))?;
+    Ok((compressed_path, seahash::hash(&compressed)))
+}
+
+/// Compress the given bytes using the given algorithm & level, which defaults to the highest
+/// level of the algorithm.
+pub fn compress(
+    bytes: &[u8],
+    algorithm: CompressionAlgorithm,
+    level: Option<u32>,
+) -> Result<Vec<u8>> {
+    match algorithm {
+        CompressionAlgorithm::Gzip => {
+            let level = Compression::new(level.unwrap_or(9));
+            let mut encoder = GzEncoder::new(Vec::new(), level);
+            encoder.write_all(bytes)?;
+            Ok(encoder.finish()?)
+        }
+        CompressionAlgorithm::Br => {
+            let mut compressed = Vec::new();
+            let mut writer =
+                brotli::CompressorWriter::new(&mut compressed, 4096, level.unwrap_or(11), 22);
+            writer.write_all(bytes)?;
+            drop(writer);
+            Ok(compressed)
+        }
+        CompressionAlgorithm::Deflate => bail!("deflate is not supported for compression"),
+    }
+}
+
+/// Check if the given changed source paths are all stylesheets, and if the build only changed
+/// stylesheets, apart from the HTML referencing them.
+fn only_stylesheets_changed(changes: &[PathBuf], report: &BuildReport) -> bool {
+    let sources = !changes.is_empty()
+        && changes.iter().all(|path| {
+            path.extension()
+                .and_then(|ext| ext.to_str())
+                .is_some_and(|ext| STYLESHEET_EXTENSIONS.contains(&ext))
+        });
+    let mut stylesheets = report
+        .changed_assets
+        .iter()
+        .filter(|asset| !asset.ends_with(".html"))
+        .peekable();
+    sources && stylesheets.peek().is_some() && stylesheets.all(|asset| asset.ends_with(".css"))
+}
+
+/// Create a symlink at the given path to the given file.
+#[cfg(unix)]
+async fn symlink_file(target: &Path, link: &Path) -> std::io::Result<()> {
+    fs::symlink(target, link).await
+}
+
+/// Create a symlink at the given path to the given file.
+#[cfg(windows)]
+async fn symlink_file(target: &Path, link: &Path) -> std::io::Result<()> {
+    fs::symlink_file(target, link).await
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Read;
+    use std::path::Path;
+    use std::sync::Arc;
+
+    use anyhow::{ensure, Context, Result};
+
+    use super::{move_stage_to_final, prune_final, BuildSystem};
+    use crate::common::dir_files;
+    use crate::config::{
+        CompressionAlgorithm, ConfigOptsHook, FileHashLayout, RtcBuild, UnhashedNames,
+    };
+    use crate::hooks::Hook;
+
+    /// Write the given files to the given dir.
+    fn write_files(dir: &Path, files: &[&str]) -> Result<()> {
+        for file in files {
+            let file = dir.join(file);
+            std::fs::create_dir_all(file.parent().context("file without parent")?)?;
+            std::fs::write(&file, format!("new {}", file.display()))?;
+        }
+        Ok(())
+    }
+
+    /// The paths of the files of the given dir, relative to it, sorted.
+    async fn files(dir: &Path) -> Result<Vec<String>> {
+        let mut files = dir_files(dir)
+            .await?
+            .into_iter()
+            .map(|file| Ok(file.strip_prefix(dir)?.to_string_lossy().replace('\\', "/")))
+            .collect::<Result<Vec<_>>>()?;
+        files.sort();
+        Ok(files)
+    }
+
+    #[tokio::test]
+    async fn stale_files_of_previous_builds_are_pruned() -> Result<()> {
+        // Assemble.
+        let tmpdir = tempfile::tempdir().context("error building tempdir for test")?;
+        let dist = tmpdir.path().join("dist");
+        let staging = dist.join(".stage");
+        write_files(
+            &dist,
+            &[
+                "index.html",
+                "app-1.wasm",
+                "assets/logo.png",
+                "assets/old/logo.png",
+                "removed/file.txt",
+                "icons",
+            ],
+        )?;
+        write_files(
+            &staging,
+            &[
+                "index.html",
+                "app-2.wasm",
+                "assets/logo.png",
+                "icons/favicon.ico",
+            ],
+        )?;
+
+        // Action.
+        let outputs = move_stage_to_final(&staging, &dist).await?;
+        let unpruned = files(&dist).await?;
+        prune_final(&dist, &outputs).await?;
+
+        // Assert.
+        ensure!(
+            unpruned
+                == [
+                    "app-1.wasm",
+                    "app-2.wasm",
+                    "assets/logo.png",
+                    "assets/old/logo.png",
+                    "icons/favicon.ico",
+                    "index.html",
+                    "removed/file.txt"
+                ],
+            "unexpected files before pruning {:?}",
+            unpruned
+        );
+        let pruned = files(&dist).await?;
+        ensure!(
+            pruned
+                == [
+                    "app-2.wasm",
+                    "assets/logo.png",
+                    "icons/favicon.ico",
+                    "index.html"
+                ],
+            "unexpected files after pruning {:?}",
+            pruned
+        );
+        let logo = std::fs::read_to_string(dist.join("assets/logo.png"))?;
+        ensure!(
+            logo == format!("new {}", staging.join("assets/logo.png").display()),
+            "unexpected contents of replaced file {:?}",
+            logo
+        );
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn precompresses_wasm_js_and_css_outputs() -> Result<()> {
+        // Assemble.
+        let tmpdir = tempfile::tempdir().context("error building tempdir for test")?;
+        let mut cfg = RtcBuild::new_test(tmpdir.path()).await?;
+        cfg.precompress = vec![CompressionAlgorithm::Gzip, CompressionAlgorithm::Br];
+        cfg.precompress_level = Some(1);
+        let staging = cfg.staging_dist.clone();
+        write_files(
+            &staging,
+            &[
+                "app_bg.wasm",
+                "app.js",
+                "style.css",
+                "index.html",
+                "logo.svg",
+            ],
+        )?;
+        std::fs::write(&cfg.target, "<html></html>")?;
+        let system = BuildSystem::new(Arc::new(cfg), None).await?;
+
+        // Action.
+        let asset_hashes = system.hash_staging_dist().await?;
+        let compressed = system.precompress_staging_dist(&asset_hashes).await?;
+
+        // Assert.
+        let files = files(&staging).await?;
+        ensure!(
+            files
+                == [
+                    "app.js",
+                    "app.js.br",
+                    "app.js.gz",
+                    "app_bg.wasm",
+                    "app_bg.wasm.br",
+                    "app_bg.wasm.gz",
+                    "index.html",
+                    "logo.svg",
+                    "style.css",
+                    "style.css.br",
+                    "style.css.gz",
+                ],
+            "unexpected files {:?}",
+            files
+        );
+        let original = std::fs::read(staging.join("app.js"))?;
+        let mut gunzipped = Vec::new();
+        flate2::read::GzDecoder::new(&*std::fs::read(staging.join("app.js.gz"))?)
+            .read_to_end(&mut gunzipped)?;
+        let mut unbrotlied = Vec::new();
+        brotli::Decompressor::new(&*std::fs::read(staging.join("app.js.br"))?, 4096)
+            .read_to_end(&mut unbrotlied)?;
+        ensure!(gunzipped == original && unbrotlied == original);
+        ensure!(
+            compressed.get("app.js.gz")
+                == Some(&seahash::hash(&std::fs::read(staging.join("app.js.gz"))?)),
+            "the hashes of the siblings must be returned"
+        );
+        ensure!(compressed.len() == 6);
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn hashing_reuses_the_hashes_recorded_by_pipelines() -> Result<()> {
+        // Assemble.
+        let tmpdir = tempfile::tempdir().context("error building tempdir for test")?;
+        let cfg = RtcBuild::new_test(tmpdir.path()).await?;
+        let (app, other) = (
+            cfg.staging_dist.join("app.js"),
+            cfg.staging_dist.join("other.txt"),
+        );
+        std::fs::write(&app, "real")?;
+        std::fs::write(&other, "other")?;
+        // The recorded hash differs from the one of the contents, to tell whether it is reused.
+        cfg.output_hashes.record(&app, b"fake").await;
+        std::fs::write(&cfg.target, "<html></html>")?;
+        let system = BuildSystem::new(Arc::new(cfg), None).await?;
+
+        // Action.
+        let recorded = system.hash_staging_dist().await?;
+        std::fs::write(&app, "rewritten")?;
+        let rewritten = system.hash_staging_dist().await?;
+
+        // Assert.
+        ensure!(recorded.get("app.js") == Some(&seahash::hash(b"fake")));
+        ensure!(recorded.get("other.txt") == Some(&seahash::hash(b"other")));
+        ensure!(rewritten.get("app.js") == Some(&seahash::hash(b"rewritten")));
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn hashing_reads_all_files_if_build_hooks_may_rewrite_them() -> Result<()> {
+        // Assemble.
+        let tmpdir = tempfile::tempdir().context("error building tempdir for test")?;
+        let mut cfg = RtcBuild::new_test(tmpdir.path()).await?;
+        cfg.hooks = vec![Hook::new(toml::from_str::<ConfigOptsHook>(
+            r#"stage = "post_build"
+command = "true""#,
+        )?)?];
+        let app = cfg.staging_dist.join("app.js");
+        std::fs::write(&app, "real")?;
+        cfg.output_hashes.record(&app, b"fake").await;
+        std::fs::write(&cfg.target, "<html></html>")?;
+        let system = BuildSystem::new(Arc::new(cfg), None).await?;
+
+        // Action.
+        let hashes = system.hash_staging_dist().await?;
+
+        // Assert.
+        ensure!(hashes.get("app.js") == Some(&seahash::hash(b"real")));
+        Ok(())
+    }
+
+    #[cfg(unix)]
+    #[tokio::test]
+    async fn unhashed_names_are_symlinked_to_hashed_outputs() -> Result<()> {
+        // Assemble.
+        let tmpdir = tempfile::tempdir().context("error building tempdir for test")?;
+        let cfg = RtcBuild::new_test(tmpdir.path()).await?;
+        let staging = cfg.staging_dist.clone();
+        write_files(
+            &staging,
+            &[
+                "app-0123456789abcdef.js",
+                "app-0123456789abcdef_bg.wasm",
+                "lib-0123456789abcdef.js",
+                "lib-fedcba9876543210.js",
+                "style-0123456789abcdef.css",
+                "style.css",
+                "index.html",
+            ],
+        )?;
+        std::fs::write(&cfg.target, "<html></html>")?;
+        let system = BuildSystem::new(Arc::new(cfg), None).await?;
+        let asset_hashes = system.hash_staging_dist().await?;
+
+        // Action.
+        system
+            .add_unhashed_names(UnhashedNames::Symlink, &asset_hashes)
+            .await?;
+
+        // Assert.
+        let link = |name: &str| std::fs::read_link(staging.join(name));
+        ensure!(link("app.js")? == Path::new("app-0123456789abcdef.js"));
+        ensure!(link("app_bg.wasm")? == Path::new("app-0123456789abcdef_bg.wasm"));
+        ensure!(
+            link("lib.js")? == Path::new("lib-0123456789abcdef.js"),
+            "the first of the outputs of the same name must be kept"
+        );
+        ensure!(
+            link("style.css").is_err()
+                && std::fs::read_to_string(staging.join("style.css"))?
+                    == format!("new {}", staging.join("style.css").display()),
+            "the outputs having the name already must not be shadowed"
+        );
+        ensure!(
+            std::fs::read(staging.join("app.js"))?
+                == std::fs::read(staging.join("app-0123456789abcdef.js"))?
+        );
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn unhashed_names_are_copies_of_hashed_outputs_in_hash_dirs() -> Result<()> {
+        // Assemble.
+        let tmpdir = tempfile::tempdir().context("error building tempdir for test")?;
+        let mut cfg = RtcBuild::new_test(tmpdir.path()).await?;
+        cfg.filehash_layout = FileHashLayout::Dir;
+        let staging = cfg.staging_dist.clone();
+        write_files(
+            &staging,
+            &[
+                "assets/0123456789abcdef/app.js",
+                "assets/fedcba9876543210/style.css",
+                "assets/fedcba9876543210/logo.svg",
+                "assets/logo.svg",
+                "index.html",
+            ],
+        )?;
+        std::fs::write(&cfg.target, "<html></html>")?;
+        let system = BuildSystem::new(Arc::new(cfg), None).await?;
+        let asset_hashes = system.hash_staging_dist().await?;
+
+        // Action.
+        system
+            .add_unhashed_names(UnhashedNames::Copy, &asset_hashes)
+            .await?;
+
+        // Assert.
+        let files = files(&staging).await?;
+        ensure!(
+            files
+                == [
+                    "assets/0123456789abcdef/app.js",
+                    "assets/app.js",
+                    "assets/fedcba9876543210/logo.svg",
+                    "assets/fedcba9876543210/style.css",
+                    "assets/logo.svg",
+                    "assets/style.css",
+                    "index.html",
+                ],
+            "unexpected files {:?}",
+            files
+        );
+        let copy = std::fs::symlink_metadata(staging.join("assets/app.js"))?;
+        ensure!(copy.is_file(), "the unhashed names must be copies");
+        ensure!(
+            std::fs::read(staging.join("assets/app.js"))?
+                == std::fs::read(staging.join("assets/0123456789abcdef/app.js"))?
+        );
+        ensure!(
+            std::fs::read_to_string(staging.join("assets/logo.svg"))?
+                == format!("new {}", staging.join("assets/logo.svg").display()),
+            "the outputs having the name already must not be shadowed"
+        );
+        Ok(())
+    }
+
+    #[cfg(unix)]
+    #[tokio::test]
+    async fn unhashed_names_are_symlinked_into_hash_dirs() -> Result<()> {
+        // Assemble.
+        let tmpdir = tempfile::tempdir().context("error building tempdir for test")?;
+        let mut cfg = RtcBuild::new_test(tmpdir.path()).await?;
+        cfg.filehash_layout = FileHashLayout::Dir;
+        let staging = cfg.staging_dist.clone();
+        write_files(&staging, &["assets/0123456789abcdef/app.js", "index.html"])?;
+        std::fs::write(&cfg.target, "<html></html>")?;
+        let system = BuildSystem::new(Arc::new(cfg), None).await?;
+        let asset_hashes = system.hash_staging_dist().await?;
+
+        // Action.
+        system
+            .add_unhashed_names(UnhashedNames::Symlink, &asset_hashes)
+            .await?;
+
+        // Assert.
+        let target = std::fs::read_link(staging.join("assets/app.js"))?;
+        ensure!(
+            target == Path::new("0123456789abcdef/app.js"),
+            "the symlinks must be relative to their dir, got {:?}",
+            target
+        );
         Ok(())
     }
 }
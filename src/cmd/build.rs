@@ -12,11 +12,19 @@ use crate::config::{ConfigOpts, ConfigOptsBuild};
 pub struct Build {
     #[command(flatten)]
     pub build: ConfigOptsBuild,
+    /// Rebuild on changes, as of `trunk watch`, but without serving the dist dir, which may be
+    /// served by another server instead. The watch options are taken from the config
+    #[arg(long)]
+    pub watch: bool,
 }
 
 impl Build {
     #[tracing::instrument(level = "trace", skip(self, config))]
     pub async fn run(self, config: Option<PathBuf>) -> Result<()> {
+        if self.watch {
+            let cfg = ConfigOpts::rtc_watch(self.build, Default::default(), config)?;
+            return super::watch::watch(cfg).await;
+        }
         let cfg = ConfigOpts::rtc_build(self.build, config)?;
         let mut system = BuildSystem::new(cfg, None).await?;
         system.build().await?;
@@ -7,6 +7,7 @@ use tokio::process::Command;
 
 use crate::common::remove_dir_all;
 use crate::config::{ConfigOpts, ConfigOptsClean};
+use crate::pipelines::PipelineCache;
 use crate::tools::cache_dir;
 
 /// Clean output artifacts.
@@ -21,12 +22,18 @@ pub struct Clean {
     /// them to be downloaded by Trunk next time they are needed.
     #[arg(short, long)]
     pub tools: bool,
+    /// Optionally clean the cached outputs of the pipelines
+    ///
+    /// These outputs, e.g. of sass, favicons & wasm-opt, are persisted in the cache dir, or the
+    /// `build.cache_dir`, and reused by later builds while their inputs are unchanged.
+    #[arg(long)]
+    pub cache: bool,
 }
 
 impl Clean {
     #[tracing::instrument(level = "trace", skip(self, config))]
     pub async fn run(self, config: Option<PathBuf>) -> Result<()> {
-        let cfg = ConfigOpts::rtc_clean(self.clean, config)?;
+        let cfg = ConfigOpts::rtc_clean(self.clean, config.clone())?;
         let _ = remove_dir_all(cfg.dist.clone()).await;
         if cfg.cargo {
             tracing::debug!("cleaning cargo dir");
@@ -42,6 +49,15 @@ impl Clean {
                 String::from_utf8_lossy(&output.stderr)
             );
         }
+        if self.cache {
+            tracing::debug!("cleaning trunk pipeline cache dir");
+            let cache_dir = ConfigOpts::full(config)?
+                .build
+                .and_then(|build| build.cache_dir);
+            if let Some(path) = PipelineCache::new(cache_dir).dir() {
+                remove_dir_all(path.to_owned()).await?;
+            }
+        }
         if self.tools {
             tracing::debug!("cleaning trunk tools cache dir");
             let path = cache_dir().await.context("error getting cache dir path")?;
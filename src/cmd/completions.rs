@@ -0,0 +1,26 @@
+use anyhow::{Context, Result};
+use clap::{Args, Command};
+
+use crate::completions::{self, Shell};
+use crate::messages;
+
+/// Print the completion script of a shell.
+#[derive(Clone, Debug, Args)]
+#[command(name = "completions")]
+pub struct Completions {
+    /// The shell to print the completion script of
+    #[arg(value_enum)]
+    pub shell: Shell,
+}
+
+impl Completions {
+    #[tracing::instrument(level = "trace", skip(self, cli))]
+    pub async fn run(self, cli: Command) -> Result<()> {
+        let name = cli.get_name();
+        let mut script = Vec::new();
+        completions::write_script(self.shell, name, name, &mut script)
+            .context("error generating completion script")?;
+        messages::print(String::from_utf8_lossy(&script).trim_end());
+        Ok(())
+    }
+}
@@ -0,0 +1,35 @@
+use std::path::PathBuf;
+
+use anyhow::Result;
+use clap::Args;
+
+use crate::config::{ConfigOpts, ConfigOptsBuild, DeployTarget};
+use crate::deploy::DeploySystem;
+
+/// Build the Rust WASM app in release mode & deploy its dist dir to a static host.
+#[derive(Clone, Debug, Args)]
+#[command(name = "deploy")]
+pub struct Deploy {
+    /// The target to deploy to [default: the configured target]
+    #[arg(long, value_enum)]
+    pub to: Option<DeployTarget>,
+    /// Deploy the existing dist dir, instead of building it first
+    #[arg(long)]
+    pub no_build: bool,
+    /// Only print the commands which would deploy the dist dir, without running them
+    #[arg(long)]
+    pub dry_run: bool,
+    #[command(flatten)]
+    pub build: ConfigOptsBuild,
+}
+
+impl Deploy {
+    #[tracing::instrument(level = "trace", skip(self, config))]
+    pub async fn run(mut self, config: Option<PathBuf>) -> Result<()> {
+        self.build.release = true;
+        let cfg = ConfigOpts::rtc_deploy(self.build, self.to, config)?;
+        DeploySystem::new(cfg, self.no_build, self.dry_run)
+            .run()
+            .await
+    }
+}
@@ -1,5 +1,9 @@
 pub mod build;
 pub mod clean;
+pub mod completions;
 pub mod config;
+pub mod deploy;
 pub mod serve;
+pub mod test;
+pub mod tools;
 pub mod watch;
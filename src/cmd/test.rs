@@ -0,0 +1,71 @@
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+use anyhow::Result;
+use clap::Args;
+
+use crate::config::{
+    CargoEnv, CargoFlags, CargoMetadata, CargoProfile, ConfigOpts, ConfigOptsBuild, Features,
+};
+use crate::test_runner::TestSystem;
+use crate::webdriver::Browser;
+
+/// Build the wasm tests of the crate & run them in a headless browser.
+#[derive(Clone, Debug, Args)]
+#[command(name = "test")]
+pub struct Test {
+    #[command(flatten)]
+    pub build: ConfigOptsBuild,
+    /// The browser to run the tests in, driven by chromedriver or geckodriver
+    #[arg(long, value_enum, default_value_t = Browser::Chrome, env = "TRUNK_TEST_BROWSER")]
+    pub browser: Browser,
+    /// The URL of a running WebDriver server to use, instead of spawning chromedriver or
+    /// geckodriver
+    #[arg(long, env = "TRUNK_TEST_WEBDRIVER_URL")]
+    pub webdriver_url: Option<String>,
+    /// Show the browser, instead of running it headless
+    #[arg(long)]
+    pub no_headless: bool,
+    /// The time to wait for the tests of a test binary to finish
+    #[arg(long, value_name = "duration", default_value = "60s", value_parser = humantime::parse_duration)]
+    pub timeout: Duration,
+    /// The args passed to the tests, such as the names of the tests to run
+    #[arg(last = true)]
+    pub args: Vec<String>,
+}
+
+impl Test {
+    #[tracing::instrument(level = "trace", skip(self, config))]
+    pub async fn run(self, config: Option<PathBuf>) -> Result<()> {
+        let cfg =
+            ConfigOpts::effective(self.build, Default::default(), Default::default(), config)?;
+        let build = cfg.build.unwrap_or_default();
+        let cargo_features = Features::from_opts(&build)?;
+        let cargo_profile = CargoProfile::from_opts(&build)?;
+        let cargo_flags = CargoFlags::from_opts(&build);
+
+        // The crate is the one of the target HTML file, as of `trunk build`.
+        let dir = match build.target.as_deref().and_then(Path::parent) {
+            Some(dir) if !dir.as_os_str().is_empty() => dir,
+            _ => Path::new("."),
+        };
+        let project_dir = dir.canonicalize().unwrap_or_else(|_| dir.to_path_buf());
+        let cargo_env = CargoEnv::from_opts(&build, &project_dir)?;
+        let manifest = CargoMetadata::new(&dir.join("Cargo.toml"), cargo_flags, &cargo_env).await?;
+
+        let system = TestSystem {
+            manifest,
+            cargo_profile,
+            cargo_flags,
+            cargo_env,
+            cargo_features,
+            tools: cfg.tools.unwrap_or_default(),
+            browser: self.browser,
+            webdriver_url: self.webdriver_url,
+            headless: !self.no_headless,
+            timeout: self.timeout,
+            args: self.args,
+        };
+        system.run().await
+    }
+}
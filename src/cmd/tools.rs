@@ -0,0 +1,312 @@
+use std::fs::File;
+use std::path::{Path, PathBuf};
+
+use anyhow::{bail, Context, Result};
+use cargo_lock::Lockfile;
+use clap::{Args, Subcommand, ValueEnum};
+use flate2::read::GzDecoder;
+
+use crate::common::remove_dir_all;
+use crate::config::{ConfigOpts, ConfigOptsTools};
+use crate::messages;
+use crate::size_report::format_size;
+use crate::tools::{self, Application};
+
+/// Manage the tools downloaded by Trunk.
+#[derive(Clone, Debug, Args)]
+#[command(name = "tools")]
+pub struct Tools {
+    #[command(subcommand)]
+    action: ToolsSubcommands,
+}
+
+impl Tools {
+    #[tracing::instrument(level = "trace", skip(self, config))]
+    pub async fn run(self, config: Option<PathBuf>) -> Result<()> {
+        let cfg = ConfigOpts::full(config)?.tools.unwrap_or_default();
+        match self.action {
+            ToolsSubcommands::Install(inner) => inner.run(cfg).await,
+            ToolsSubcommands::List => list(&cfg).await,
+            ToolsSubcommands::Clean(inner) => inner.run(&cfg).await,
+            ToolsSubcommands::Update(inner) => inner.run(&cfg).await,
+        }
+    }
+}
+
+#[derive(Clone, Debug, Subcommand)]
+enum ToolsSubcommands {
+    /// Install the pinned versions of tools to the cache, e.g. to preload it for builds without
+    /// network access.
+    Install(Install),
+    /// List the cached versions of the tools and their disk usage.
+    List,
+    /// Remove the cached versions of the tools which are not in use by the project.
+    Clean(Clean),
+    /// Install the latest releases of the tools which are compatible with the versions in use.
+    Update(Update),
+}
+
+/// Install the pinned versions of tools to the cache.
+#[derive(Clone, Debug, Args)]
+struct Install {
+    /// The tools to install [default: all, or all of which `--from` has release archives]
+    #[arg(value_enum)]
+    tools: Vec<Application>,
+    /// A dir or `.tar.gz` tarball with the release archives & checksums of the tools, named as
+    /// their downloads, to install the tools from instead of downloading them
+    #[arg(long, value_name = "DIR|TARBALL")]
+    from: Option<PathBuf>,
+}
+
+impl Install {
+    async fn run(self, mut cfg: ConfigOptsTools) -> Result<()> {
+        let unpacked = match &self.from {
+            Some(from) if from.is_dir() => {
+                cfg.mirror = Some(from.display().to_string());
+                None
+            }
+            Some(from) => {
+                let dir = tools::cache_dir().await?.join(".install-from");
+                unpack(from, &dir).await?;
+                cfg.mirror = Some(dir.display().to_string());
+                Some(dir)
+            }
+            None => None,
+        };
+
+        let res = self.install(&cfg).await;
+        if let Some(dir) = unpacked {
+            remove_dir_all(dir).await?;
+        }
+        res
+    }
+
+    async fn install(&self, cfg: &ConfigOptsTools) -> Result<()> {
+        let apps = match (self.tools.is_empty(), cfg.mirror.as_deref()) {
+            (false, _) => self.tools.clone(),
+            (true, None) => Application::value_variants().to_vec(),
+            // Install only the tools the offline source has release archives of.
+            (true, Some(mirror)) => {
+                let mut apps = Vec::new();
+                for &app in Application::value_variants() {
+                    let file_name = tools::release_file_name(app, version(app, cfg).as_deref())?;
+                    if Path::new(mirror).join(file_name).is_file() {
+                        apps.push(app);
+                    }
+                }
+                if apps.is_empty() {
+                    bail!("no release archives of the tools found in {:?}", mirror);
+                }
+                apps
+            }
+        };
+
+        for app in apps {
+            let version = version(app, cfg);
+            let path = tools::install_cached(app, version.as_deref(), cfg).await?;
+            tracing::info!(path = ?path, "installed {}", app.name());
+        }
+        Ok(())
+    }
+}
+
+/// Remove the cached versions of the tools which are not in use by the project.
+#[derive(Clone, Debug, Args)]
+struct Clean {
+    /// Remove all cached versions, including the ones in use
+    #[arg(long)]
+    all: bool,
+    /// Only show the versions that would be removed
+    #[arg(long)]
+    dry_run: bool,
+}
+
+impl Clean {
+    async fn run(self, cfg: &ConfigOptsTools) -> Result<()> {
+        let (removing, freed_msg) = if self.dry_run {
+            ("would remove", "would free")
+        } else {
+            ("removing", "freed")
+        };
+        let mut freed = 0;
+        for cached in tools::cached_apps().await? {
+            if !self.all && in_use(cached.app, &cached.version, cfg) {
+                continue;
+            }
+            tracing::info!(
+                "{} {} {} ({})",
+                removing,
+                cached.app.name(),
+                cached.version,
+                format_size(cached.size)
+            );
+            if !self.dry_run {
+                remove_dir_all(cached.path).await?;
+            }
+            freed += cached.size;
+        }
+        for path in tools::stale_downloads().await? {
+            tracing::info!(path = ?path, "{} interrupted download", removing);
+            if !self.dry_run {
+                tokio::fs::remove_file(&path)
+                    .await
+                    .with_context(|| format!("error removing {:?}", path))?;
+            }
+        }
+        tracing::info!("{} {} of cached tools", freed_msg, format_size(freed));
+        Ok(())
+    }
+}
+
+/// Install the latest releases of the tools which are compatible with the versions in use.
+#[derive(Clone, Debug, Args)]
+struct Update {
+    /// The tools to update [default: all]
+    #[arg(value_enum)]
+    tools: Vec<Application>,
+}
+
+impl Update {
+    async fn run(self, cfg: &ConfigOptsTools) -> Result<()> {
+        let apps = if self.tools.is_empty() {
+            Application::value_variants().to_vec()
+        } else {
+            self.tools
+        };
+        for app in apps {
+            let current = tools::resolve_version(app, version(app, cfg).as_deref());
+            // The wasm-bindgen CLI must match the version of the crate of the project exactly.
+            let latest = if app == Application::WasmBindgen && lock_version(app).is_some() {
+                current.clone()
+            } else {
+                tools::latest_compatible_version(app, &current, cfg).await?
+            };
+            if latest == current {
+                tracing::info!("{} {} is up to date", app.name(), current);
+                tools::install_cached(app, Some(&current), cfg).await?;
+                continue;
+            }
+            tools::install_cached(app, Some(&latest), cfg).await?;
+            tracing::info!(
+                "installed {} {}, pin it with `{} = {:?}` in the `[tools]` section of `Trunk.toml`",
+                app.name(),
+                latest,
+                config_key(app),
+                latest
+            );
+        }
+        Ok(())
+    }
+}
+
+/// List the cached versions of the tools and their disk usage.
+async fn list(cfg: &ConfigOptsTools) -> Result<()> {
+    let cached = tools::cached_apps().await?;
+    if cached.is_empty() {
+        messages::print(format_args!(
+            "no cached tools in {:?}",
+            tools::cache_dir().await?
+        ));
+        return Ok(());
+    }
+    messages::print(format_args!(
+        "{:<14}{:<16}{:>12}",
+        "TOOL", "VERSION", "SIZE"
+    ));
+    for cached in &cached {
+        let in_use = if in_use(cached.app, &cached.version, cfg) {
+            "  (in use)"
+        } else {
+            ""
+        };
+        messages::print(format_args!(
+            "{:<14}{:<16}{:>12}{}",
+            cached.app.name(),
+            cached.version,
+            format_size(cached.size),
+            in_use
+        ));
+    }
+    let total = cached.iter().map(|cached| cached.size).sum();
+    messages::print(format_args!(
+        "total: {} in {:?}",
+        format_size(total),
+        tools::cache_dir().await?
+    ));
+    Ok(())
+}
+
+/// Whether the given cached version of a tool is the one used by the project.
+fn in_use(app: Application, version: &str, cfg: &ConfigOptsTools) -> bool {
+    tools::resolve_version(app, self::version(app, cfg).as_deref()) == version
+}
+
+/// Get the key of the pinned version of the given tool in the `[tools]` section of `Trunk.toml`.
+fn config_key(app: Application) -> &'static str {
+    match app {
+        Application::Esbuild => "esbuild",
+        Application::Sass => "sass",
+        Application::TailwindCss => "tailwindcss",
+        Application::WasmBindgen => "wasm_bindgen",
+        Application::WasmOpt => "wasm_opt",
+    }
+}
+
+/// Get the version of the given tool to install, which is the pinned version, or for wasm-bindgen
+/// the version of the crate in the `Cargo.lock` of the current dir, if any.
+fn version(app: Application, cfg: &ConfigOptsTools) -> Option<String> {
+    let pinned = match app {
+        Application::Esbuild => cfg.esbuild.clone(),
+        Application::Sass => cfg.sass.clone(),
+        Application::TailwindCss => cfg.tailwindcss.clone(),
+        Application::WasmBindgen => cfg.wasm_bindgen.clone(),
+        Application::WasmOpt => cfg.wasm_opt.clone(),
+    };
+    pinned.or_else(|| lock_version(app))
+}
+
+/// Get the version of the crate of the given tool in the `Cargo.lock` of the current dir, which is
+/// only used for wasm-bindgen.
+fn lock_version(app: Application) -> Option<String> {
+    if app != Application::WasmBindgen {
+        return None;
+    }
+    let lockfile = Lockfile::load("Cargo.lock").ok()?;
+    let name = "wasm-bindgen".parse().ok()?;
+    lockfile
+        .packages
+        .into_iter()
+        .find(|p| p.name == name)
+        .map(|p| p.version.to_string())
+}
+
+/// Unpack the files of the given tarball into the given dir, regardless of their dirs within the
+/// tarball.
+async fn unpack(tarball: &Path, dir: &Path) -> Result<()> {
+    remove_dir_all(dir.to_owned()).await?;
+    let (tarball, dir) = (tarball.to_owned(), dir.to_owned());
+    tokio::task::spawn_blocking(move || {
+        std::fs::create_dir_all(&dir).context("error creating dir to unpack tarball")?;
+        let file =
+            File::open(&tarball).with_context(|| format!("error opening tarball {:?}", tarball))?;
+        let mut archive = tar::Archive::new(GzDecoder::new(file));
+        let entries = archive
+            .entries()
+            .with_context(|| format!("error reading tarball {:?}", tarball))?;
+        for entry in entries {
+            let mut entry = entry.context("error reading tarball entry")?;
+            if !entry.header().entry_type().is_file() {
+                continue;
+            }
+            let path = entry.path().context("invalid tarball entry path")?;
+            let Some(name) = path.file_name().map(ToOwned::to_owned) else {
+                continue;
+            };
+            entry
+                .unpack(dir.join(name))
+                .context("error unpacking tarball entry")?;
+        }
+        Ok(())
+    })
+    .await?
+}
@@ -1,10 +1,11 @@
 use std::path::PathBuf;
+use std::sync::Arc;
 
 use anyhow::{Context, Result};
 use clap::Args;
 use tokio::sync::broadcast;
 
-use crate::config::{ConfigOpts, ConfigOptsBuild, ConfigOptsWatch};
+use crate::config::{ConfigOpts, ConfigOptsBuild, ConfigOptsWatch, RtcWatch};
 use crate::watch::WatchSystem;
 
 /// Build & watch the Rust WASM app and all of its assets.
@@ -20,22 +21,27 @@ pub struct Watch {
 impl Watch {
     #[tracing::instrument(level = "trace", skip(self, config))]
     pub async fn run(self, config: Option<PathBuf>) -> Result<()> {
-        let (shutdown_tx, _shutdown_rx) = broadcast::channel(1);
         let cfg = ConfigOpts::rtc_watch(self.build, self.watch, config)?;
-        let mut system = WatchSystem::new(cfg, shutdown_tx.clone(), None).await?;
+        watch(cfg).await
+    }
+}
 
-        system.build().await.ok();
-        let system_handle = tokio::spawn(system.run());
-        tokio::signal::ctrl_c()
-            .await
-            .context("error awaiting shutdown signal")?;
-        tracing::debug!("received shutdown signal");
-        shutdown_tx.send(()).ok();
-        drop(shutdown_tx); // Ensure other components see the drop to avoid race conditions.
-        system_handle
-            .await
-            .context("error awaiting system shutdown")?;
+/// Build & watch with the given config until the shutdown signal is received.
+pub(crate) async fn watch(cfg: Arc<RtcWatch>) -> Result<()> {
+    let (shutdown_tx, _shutdown_rx) = broadcast::channel(1);
+    let mut system = WatchSystem::new(cfg, shutdown_tx.clone()).await?;
 
-        Ok(())
-    }
+    system.build().await.ok();
+    let system_handle = tokio::spawn(system.run());
+    tokio::signal::ctrl_c()
+        .await
+        .context("error awaiting shutdown signal")?;
+    tracing::debug!("received shutdown signal");
+    shutdown_tx.send(()).ok();
+    drop(shutdown_tx); // Ensure other components see the drop to avoid race conditions.
+    system_handle
+        .await
+        .context("error awaiting system shutdown")?;
+
+    Ok(())
 }
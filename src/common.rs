@@ -4,15 +4,23 @@ use std::convert::Infallible;
 use std::ffi::OsStr;
 use std::fmt::Debug;
 use std::fs::Metadata;
+use std::future::Future;
 use std::io::ErrorKind;
 use std::path::{Path, PathBuf};
+use std::pin::Pin;
 use std::process::Stdio;
+use std::sync::Once;
+use std::task::{Context as TaskContext, Poll};
 
 use anyhow::{anyhow, bail, Context, Result};
 use console::Emoji;
 use once_cell::sync::Lazy;
 use tokio::fs;
+use tokio::io::{AsyncBufReadExt, BufReader};
 use tokio::process::Command;
+use tokio::task::{JoinError, JoinHandle};
+
+use crate::config::{cargo_proxy_env, CargoEnv, ConfigOptsTools, CopyStrategy};
 
 pub static BUILDING: Emoji<'_, '_> = Emoji("📦", "");
 pub static SUCCESS: Emoji<'_, '_> = Emoji("✅", "");
@@ -58,6 +66,65 @@ where
     .context("error copying directory")
 }
 
+/// Copy the given file to the given path with the given strategy, replacing the file of the path.
+///
+/// Files which can not be hard-linked or reflinked, e.g. into another file system, or into one
+/// without reflinks, are copied instead.
+pub fn copy_file(strategy: CopyStrategy, from: &Path, to: &Path) -> Result<()> {
+    let (linking, linked) = match strategy {
+        CopyStrategy::Copy => return copy_file_contents(from, to),
+        CopyStrategy::Hardlink => (
+            "hard-linking",
+            link_file(from, to, |from, to| std::fs::hard_link(from, to)),
+        ),
+        CopyStrategy::Reflink => ("reflinking", link_file(from, to, reflink_file)),
+    };
+    if let Err(err) = linked {
+        static FALLBACK: Once = Once::new();
+        FALLBACK.call_once(|| {
+            tracing::warn!(
+                "error {} {:?}, copying it & any other files which can not be linked instead: {}",
+                linking,
+                from,
+                err
+            )
+        });
+        tracing::debug!("error {} {:?} to {:?}: {}", linking, from, to, err);
+        copy_file_contents(from, to)?;
+    }
+    Ok(())
+}
+
+/// Copy the contents of the given file to the given path.
+fn copy_file_contents(from: &Path, to: &Path) -> Result<()> {
+    std::fs::copy(from, to)
+        .with_context(|| format!("error copying file {:?} to {:?}", from, to))?;
+    Ok(())
+}
+
+/// Link the given file to the given path with the given link function, replacing the file of the
+/// path.
+fn link_file(
+    from: &Path,
+    to: &Path,
+    link: impl FnOnce(&Path, &Path) -> std::io::Result<()>,
+) -> std::io::Result<()> {
+    // Link the target of a symlink, rather than the symlink, whose relative targets would break.
+    let from = std::fs::canonicalize(from)?;
+    match std::fs::remove_file(to) {
+        Err(err) if err.kind() != ErrorKind::NotFound => return Err(err),
+        _ => {}
+    }
+    link(&from, to)
+}
+
+/// Clone the given file to the given path with a reflink, sharing its contents until either is
+/// changed, and with its permissions.
+fn reflink_file(from: &Path, to: &Path) -> std::io::Result<()> {
+    reflink_copy::reflink(from, to)?;
+    std::fs::set_permissions(to, std::fs::metadata(from)?.permissions())
+}
+
 /// A utility function to recursively delete a directory.
 ///
 /// Use this instead of fs::remove_dir_all(...) because of Windows compatibility issues, per
@@ -74,6 +141,34 @@ pub async fn remove_dir_all(from_dir: PathBuf) -> Result<()> {
     .context("error awaiting spawned remove dir call")?
 }
 
+/// List the paths of all files in the given dir & its subdirs.
+pub async fn dir_files(dir: &Path) -> Result<Vec<PathBuf>> {
+    let mut files = Vec::new();
+    let mut dirs = vec![dir.to_owned()];
+    while let Some(dir) = dirs.pop() {
+        let mut entries = fs::read_dir(&dir)
+            .await
+            .with_context(|| format!("error reading dir {:?}", dir))?;
+        while let Some(entry) = entries
+            .next_entry()
+            .await
+            .with_context(|| format!("error reading contents of dir {:?}", dir))?
+        {
+            let path = entry.path();
+            let file_type = entry
+                .file_type()
+                .await
+                .with_context(|| format!("error reading metadata of {:?}", path))?;
+            if file_type.is_dir() {
+                dirs.push(path);
+            } else {
+                files.push(path);
+            }
+        }
+    }
+    Ok(files)
+}
+
 /// Checks if path exists.
 pub async fn path_exists(path: impl AsRef<Path>) -> Result<bool> {
     fs::metadata(path.as_ref())
@@ -117,6 +212,33 @@ pub async fn is_executable(path: impl AsRef<Path>) -> Result<bool> {
         .with_context(|| format!("error checking file mode for file {:?}", path.as_ref()))
 }
 
+/// A handle of a spawned task, which aborts the task when dropped. This ensures that cancelling a
+/// build also cancels all of the tasks spawned by it.
+pub struct AbortOnDrop<T>(pub JoinHandle<T>);
+
+impl<T> Future for AbortOnDrop<T> {
+    type Output = Result<T, JoinError>;
+
+    fn poll(mut self: Pin<&mut Self>, cx: &mut TaskContext<'_>) -> Poll<Self::Output> {
+        Pin::new(&mut self.0).poll(cx)
+    }
+}
+
+impl<T> Drop for AbortOnDrop<T> {
+    fn drop(&mut self) {
+        self.0.abort();
+    }
+}
+
+/// Escape the given text for HTML text & attribute values.
+pub fn escape_html(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+        .replace('\'', "&#39;")
+}
+
 /// Strip the CWD prefix from the given path.
 ///
 /// Returns `target` unmodified if an error is returned from the operation.
@@ -128,7 +250,7 @@ pub fn strip_prefix(target: &Path) -> &Path {
 }
 
 /// Run a global command with the given arguments and make sure it completes successfully. If it
-/// fails an error is returned.
+/// fails an error is returned. The command is killed if the returned future is dropped.
 #[tracing::instrument(level = "trace", skip(name, path, args))]
 pub async fn run_command(
     name: &str,
@@ -140,6 +262,7 @@ pub async fn run_command(
         .args(args)
         .stdout(Stdio::inherit())
         .stderr(Stdio::inherit())
+        .kill_on_drop(true)
         .spawn()
         .with_context(|| format!("error spawning {} call", name))?
         .wait()
@@ -150,3 +273,89 @@ pub async fn run_command(
     }
     Ok(())
 }
+
+/// Run a global command with the given arguments and make sure it completes successfully, while
+/// capturing its stderr output. The output is still forwarded to stderr as it is produced.
+///
+/// If the command fails, the returned error contains a [`CommandError`] with the captured output.
+/// The command is killed if the returned future is dropped.
+#[tracing::instrument(level = "trace", skip(name, path, args))]
+pub async fn run_command_capture_stderr(
+    name: &str,
+    path: &Path,
+    args: &[impl AsRef<OsStr> + Debug],
+) -> Result<()> {
+    tracing::debug!(?args, "{name} args");
+    let mut command = Command::new(path);
+    command.args(args);
+    run_capture_stderr(name, &mut command).await
+}
+
+/// Build a cargo command of the given args, with colored output where supported, the given cargo
+/// env vars, and the proxy of the tools, unless the cargo env vars set another one.
+pub fn cargo_command(args: &[&str], env: &CargoEnv, tools: &ConfigOptsTools) -> Command {
+    let mut command = Command::new("cargo");
+    command.args(args);
+    // The output is captured for reporting build errors, so retain colors where supported.
+    if console::colors_enabled_stderr() {
+        command.arg("--color=always");
+    }
+    tracing::debug!(?args, "cargo args");
+    command.envs(cargo_proxy_env(tools)).envs(env.vars());
+    command
+}
+
+/// Run the given command, like [`run_command_capture_stderr`], for commands which need more than
+/// args, e.g. env vars.
+pub async fn run_capture_stderr(name: &str, command: &mut Command) -> Result<()> {
+    let mut child = command
+        .stdout(Stdio::inherit())
+        .stderr(Stdio::piped())
+        .kill_on_drop(true)
+        .spawn()
+        .with_context(|| format!("error spawning {} call", name))?;
+
+    let mut stderr = String::new();
+    if let Some(child_stderr) = child.stderr.take() {
+        let mut lines = BufReader::new(child_stderr).lines();
+        while let Some(line) = lines
+            .next_line()
+            .await
+            .with_context(|| format!("error reading {} output", name))?
+        {
+            eprintln!("{}", line);
+            stderr.push_str(&line);
+            stderr.push('\n');
+        }
+    }
+
+    let status = child
+        .wait()
+        .await
+        .with_context(|| format!("error during {} call", name))?;
+    if !status.success() {
+        return Err(CommandError {
+            name: name.to_owned(),
+            stderr,
+        }
+        .into());
+    }
+    Ok(())
+}
+
+/// An error from a command which returned a bad status, carrying its captured stderr output.
+#[derive(Debug)]
+pub struct CommandError {
+    /// The name of the command.
+    pub name: String,
+    /// The captured stderr output of the command.
+    pub stderr: String,
+}
+
+impl std::fmt::Display for CommandError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{} call returned a bad status", self.name)
+    }
+}
+
+impl std::error::Error for CommandError {}
@@ -0,0 +1,105 @@
+//! The shell completions of the CLI.
+//!
+//! The completion scripts of the shells register Trunk itself as the completer via
+//! [`clap_complete`], which completes the subcommands & options of the definition of the CLI, the
+//! possible values of options, paths, and the names of the configured profiles for `--profile`.
+
+use std::ffi::OsString;
+use std::io::Write;
+use std::path::PathBuf;
+
+use clap::ValueEnum;
+use clap_complete::env::{Bash, EnvCompleter, Fish, Powershell, Zsh};
+use clap_complete::CompletionCandidate;
+
+use crate::config::ConfigOpts;
+
+/// The env var with which the completion scripts ask Trunk for the completions of a shell.
+pub const COMPLETE_VAR: &str = "COMPLETE";
+
+/// The shells to generate completion scripts for.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, ValueEnum)]
+pub enum Shell {
+    Bash,
+    Zsh,
+    Fish,
+    Powershell,
+}
+
+impl Shell {
+    /// The completer of the shell.
+    fn completer(self) -> &'static dyn EnvCompleter {
+        match self {
+            Self::Bash => &Bash,
+            Self::Zsh => &Zsh,
+            Self::Fish => &Fish,
+            Self::Powershell => &Powershell,
+        }
+    }
+}
+
+/// Write the completion script of the given shell, which completes the given command by calling
+/// the given binary.
+pub fn write_script(
+    shell: Shell,
+    name: &str,
+    bin: &str,
+    buf: &mut dyn Write,
+) -> std::io::Result<()> {
+    shell
+        .completer()
+        .write_registration(COMPLETE_VAR, name, bin, bin, buf)
+}
+
+/// Complete the names of the profiles of the config of the `TRUNK_CONFIG` env var, or of the
+/// current dir.
+pub fn profiles() -> Vec<CompletionCandidate> {
+    profiles_of(std::env::var_os("TRUNK_CONFIG").map(PathBuf::from))
+}
+
+/// Complete the names of the profiles of the given config, of which errors complete nothing.
+fn profiles_of(config: Option<PathBuf>) -> Vec<CompletionCandidate> {
+    ConfigOpts::profile_names(config)
+        .unwrap_or_default()
+        .into_iter()
+        .map(|name| CompletionCandidate::new(OsString::from(name)))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{profiles_of, write_script, Shell};
+
+    #[test]
+    fn scripts_call_back_into_trunk() {
+        for shell in [Shell::Bash, Shell::Zsh, Shell::Fish, Shell::Powershell] {
+            let mut script = Vec::new();
+            write_script(shell, "trunk", "trunk", &mut script).expect("error writing script");
+            let script = String::from_utf8(script).expect("expected a UTF-8 script");
+            assert!(
+                script.contains("COMPLETE") && script.contains("trunk"),
+                "unexpected script of {:?}: {}",
+                shell,
+                script
+            );
+        }
+    }
+
+    #[test]
+    fn profiles_of_the_config() {
+        let tmpdir = tempfile::tempdir().expect("error building tempdir for test");
+        let config = tmpdir.path().join("Trunk.toml");
+        std::fs::write(
+            &config,
+            "[profile.staging.build]\nrelease = true\n\n[profile.dev.build]\nminify = false\n",
+        )
+        .expect("error writing test config");
+
+        let names: Vec<_> = profiles_of(Some(config))
+            .iter()
+            .map(|candidate| candidate.get_value().to_string_lossy().into_owned())
+            .collect();
+        assert_eq!(names, ["dev", "staging"]);
+        assert!(profiles_of(Some(tmpdir.path().join("Missing.toml"))).is_empty());
+    }
+}
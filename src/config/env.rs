@@ -0,0 +1,428 @@
+//! The config layer of the `TRUNK_*` env vars.
+//!
+//! Env vars mirror the `Trunk.toml` config schema: the name of a var is the path of its option,
+//! joined by underscores, e.g. `TRUNK_SERVE_PORT` for `serve.port`, and `TRUNK_PROXY_0_BACKEND`
+//! for the `backend` of the first `[[proxy]]`. As the names of options contain underscores as
+//! well, the names of vars are resolved against the fields of the config types while
+//! deserializing, preferring the longest matching field name. Vars of unknown options are ignored,
+//! just as the unknown keys of `Trunk.toml`.
+//!
+//! The value of a var is read according to the shape of its option:
+//!
+//! - Strings & paths take the value as is, while numbers, chars & enums, e.g.
+//!   `TRUNK_BUILD_FILEHASH_LAYOUT=dir`, are parsed from the trimmed value.
+//! - Booleans are `true`, `1`, `yes` or `on`, and `false`, `0`, `no`, `off` or empty, in any case.
+//! - Optional options are only set if their var, or one of their nested options, is set.
+//! - Lists are comma separated values, e.g. `TRUNK_WATCH_IGNORE=target,node_modules`, inline TOML,
+//!   e.g. `["a", "b"]`, or a var per element, with the index of the element consecutive from 0,
+//!   e.g. `TRUNK_PROXY_0_BACKEND`.
+//! - Tables of options are inline TOML, e.g. `{ style = "compressed" }`, or a var per option, e.g.
+//!   `TRUNK_BUILD_SASS_STYLE`.
+//! - Maps of arbitrary keys are inline TOML, e.g. `{ "X-Frame-Options" = "DENY" }`, or a var per
+//!   entry, whose key is the remainder of the name in its original case, e.g.
+//!   `TRUNK_BUILD_CARGO_ENV_RUSTFLAGS`, which does not work for keys with lowercase letters or
+//!   dashes.
+
+use std::collections::BTreeMap;
+use std::fmt::{self, Display};
+use std::str::FromStr;
+
+use serde::de::value::{MapDeserializer, SeqDeserializer};
+use serde::de::{self, DeserializeOwned, IntoDeserializer, Visitor};
+use serde::{forward_to_deserialize_any, Deserializer};
+
+/// The prefix of the env vars of the config.
+const PREFIX: &str = "TRUNK_";
+
+/// Deserialize the config from the `TRUNK_*` vars of the given env vars.
+pub fn from_vars<T: DeserializeOwned>(
+    vars: impl IntoIterator<Item = (String, String)>,
+) -> Result<T, Error> {
+    let vars = vars
+        .into_iter()
+        .filter_map(|(name, value)| {
+            let key = name.strip_prefix(PREFIX)?.to_ascii_lowercase();
+            Some(EnvVar { key, name, value })
+        })
+        .collect();
+    T::deserialize(EnvNode {
+        path: PREFIX.trim_end_matches('_').to_owned(),
+        vars,
+    })
+}
+
+/// An error deserializing the config from env vars.
+#[derive(Debug)]
+pub struct Error(String);
+
+impl Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.0)
+    }
+}
+
+impl std::error::Error for Error {}
+
+impl de::Error for Error {
+    fn custom<T: Display>(msg: T) -> Self {
+        Self(msg.to_string())
+    }
+}
+
+/// An env var, with the part of its name which is still to be resolved against the config schema.
+#[derive(Clone, Debug)]
+struct EnvVar {
+    /// The unresolved lowercase remainder of the name, which is empty for the value of a node.
+    key: String,
+    /// The full name of the var.
+    name: String,
+    /// The value of the var.
+    value: String,
+}
+
+/// A node of the config schema, with the env vars of its option & nested options.
+#[derive(Debug)]
+struct EnvNode {
+    /// The name of the env var of this node, for error messages.
+    path: String,
+    /// The env vars of this node & its nested options.
+    vars: Vec<EnvVar>,
+}
+
+impl EnvNode {
+    /// The var holding the value of this node itself, if any.
+    fn leaf(&self) -> Option<&EnvVar> {
+        self.vars.iter().find(|var| var.key.is_empty())
+    }
+
+    /// Get the value of this node, failing if only nested options are given.
+    fn value(&self) -> Result<&EnvVar, Error> {
+        self.leaf()
+            .ok_or_else(|| Error(format!("expected a value for {}", self.path)))
+    }
+
+    /// Parse the value of this node.
+    fn parse<T>(&self) -> Result<T, Error>
+    where
+        T: FromStr,
+        T::Err: Display,
+    {
+        let var = self.value()?;
+        var.value.trim().parse().map_err(|err| {
+            Error(format!(
+                "invalid value {:?} of {}: {}",
+                var.value, var.name, err
+            ))
+        })
+    }
+
+    /// Parse the value of this node as inline TOML, if it is a TOML list or table.
+    fn inline_toml(&self) -> Result<Option<toml::Value>, Error> {
+        let Some(var) = self.leaf() else {
+            return Ok(None);
+        };
+        let trimmed = var.value.trim();
+        if !trimmed.starts_with('[') && !trimmed.starts_with('{') {
+            return Ok(None);
+        }
+        let mut table: toml::Table = format!("value = {}", trimmed)
+            .parse()
+            .map_err(|err| Error(format!("invalid TOML value of {}: {}", var.name, err)))?;
+        Ok(table.remove("value"))
+    }
+
+    /// Split the nested options off this node, by the given option names, preferring the longest
+    /// matching name. Without option names, the whole remainder of each var is a map key.
+    fn children(self, fields: Option<&[&str]>) -> Vec<(String, EnvNode)> {
+        let mut children = BTreeMap::<String, Vec<EnvVar>>::new();
+        for mut var in self.vars.into_iter().filter(|var| !var.key.is_empty()) {
+            let field = match fields {
+                Some(fields) => fields
+                    .iter()
+                    .filter(|field| {
+                        var.key == **field
+                            || var
+                                .key
+                                .strip_prefix(**field)
+                                .is_some_and(|rest| rest.starts_with('_'))
+                    })
+                    .max_by_key(|field| field.len())
+                    .map(|field| field.to_string()),
+                // The keys of maps keep their case, which is lost in the lowercase remainder.
+                None => Some(var.name[var.name.len() - var.key.len()..].to_owned()),
+            };
+            let Some(field) = field else {
+                continue;
+            };
+            var.key = var.key[field.len()..].trim_start_matches('_').to_owned();
+            children.entry(field).or_default().push(var);
+        }
+        children
+            .into_iter()
+            .map(|(field, vars)| {
+                let path = format!("{}_{}", self.path, field.to_ascii_uppercase());
+                (field, EnvNode { path, vars })
+            })
+            .collect()
+    }
+
+    /// Split the elements off this node by the indices leading the remainders of the vars.
+    fn elements(self) -> Result<Vec<EnvNode>, Error> {
+        let mut elements = BTreeMap::<usize, Vec<EnvVar>>::new();
+        for mut var in self.vars.into_iter().filter(|var| !var.key.is_empty()) {
+            let (index, rest) = var.key.split_once('_').unwrap_or((&var.key, ""));
+            let index = index.parse().map_err(|_| {
+                Error(format!(
+                    "expected {} to continue with the index of a list element, e.g. {}_0",
+                    var.name, self.path
+                ))
+            })?;
+            var.key = rest.to_owned();
+            elements.entry(index).or_default().push(var);
+        }
+        elements
+            .into_iter()
+            .enumerate()
+            .map(|(expected, (index, vars))| {
+                if index != expected {
+                    return Err(Error(format!(
+                        "the indices of the elements of {} must be consecutive from 0, but {} is \
+                         missing",
+                        self.path, expected
+                    )));
+                }
+                let path = format!("{}_{}", self.path, index);
+                Ok(EnvNode { path, vars })
+            })
+            .collect()
+    }
+
+    /// Whether the nested options of this node are list elements.
+    fn is_list(&self) -> bool {
+        self.vars.iter().any(|var| !var.key.is_empty())
+            && self
+                .vars
+                .iter()
+                .filter(|var| !var.key.is_empty())
+                .all(|var| var.key.starts_with(|c: char| c.is_ascii_digit()))
+    }
+}
+
+/// Prefix an error of the nested options of the given env var with its name, unless it names an env
+/// var already.
+fn context(path: &str, err: Error) -> Error {
+    if err.0.contains(PREFIX) {
+        err
+    } else {
+        Error(format!("{}: {}", path, err.0))
+    }
+}
+
+impl<'de> IntoDeserializer<'de, Error> for EnvNode {
+    type Deserializer = Self;
+
+    fn into_deserializer(self) -> Self {
+        self
+    }
+}
+
+/// Deserialize primitives by parsing the value of the node.
+macro_rules! deserialize_parsed {
+    ($($method:ident => $visit:ident,)*) => {
+        $(
+            fn $method<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Error> {
+                visitor.$visit(self.parse()?)
+            }
+        )*
+    };
+}
+
+impl<'de> Deserializer<'de> for EnvNode {
+    type Error = Error;
+
+    deserialize_parsed! {
+        deserialize_i8 => visit_i8,
+        deserialize_i16 => visit_i16,
+        deserialize_i32 => visit_i32,
+        deserialize_i64 => visit_i64,
+        deserialize_u8 => visit_u8,
+        deserialize_u16 => visit_u16,
+        deserialize_u32 => visit_u32,
+        deserialize_u64 => visit_u64,
+        deserialize_f32 => visit_f32,
+        deserialize_f64 => visit_f64,
+        deserialize_char => visit_char,
+    }
+
+    forward_to_deserialize_any! {
+        i128 u128 bytes byte_buf
+    }
+
+    fn deserialize_any<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Error> {
+        match self.leaf() {
+            Some(_) => match self.inline_toml()? {
+                Some(value) => value
+                    .deserialize_any(visitor)
+                    .map_err(|err| context(&self.path, Error(err.to_string()))),
+                None => self.deserialize_string(visitor),
+            },
+            None if self.is_list() => self.deserialize_seq(visitor),
+            None => self.deserialize_map(visitor),
+        }
+    }
+
+    fn deserialize_bool<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Error> {
+        let var = self.value()?;
+        match var.value.trim().to_ascii_lowercase().as_str() {
+            "true" | "1" | "yes" | "on" => visitor.visit_bool(true),
+            "false" | "0" | "no" | "off" | "" => visitor.visit_bool(false),
+            _ => Err(Error(format!(
+                "invalid value {:?} of {}, expected a boolean",
+                var.value, var.name
+            ))),
+        }
+    }
+
+    fn deserialize_str<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Error> {
+        self.deserialize_string(visitor)
+    }
+
+    fn deserialize_string<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Error> {
+        let value = self.value()?.value.clone();
+        visitor.visit_string(value)
+    }
+
+    fn deserialize_option<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Error> {
+        if self.vars.is_empty() {
+            visitor.visit_none()
+        } else {
+            visitor.visit_some(self)
+        }
+    }
+
+    fn deserialize_unit<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Error> {
+        visitor.visit_unit()
+    }
+
+    fn deserialize_unit_struct<V: Visitor<'de>>(
+        self,
+        _name: &'static str,
+        visitor: V,
+    ) -> Result<V::Value, Error> {
+        visitor.visit_unit()
+    }
+
+    fn deserialize_newtype_struct<V: Visitor<'de>>(
+        self,
+        _name: &'static str,
+        visitor: V,
+    ) -> Result<V::Value, Error> {
+        visitor.visit_newtype_struct(self)
+    }
+
+    fn deserialize_seq<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Error> {
+        if let Some(value) = self.inline_toml()? {
+            return value
+                .deserialize_seq(visitor)
+                .map_err(|err| context(&self.path, Error(err.to_string())));
+        }
+        if let Some(var) = self.leaf() {
+            // A list of comma separated values.
+            let elements = var
+                .value
+                .split(',')
+                .map(str::trim)
+                .filter(|value| !value.is_empty())
+                .map(|value| EnvNode {
+                    path: var.name.clone(),
+                    vars: vec![EnvVar {
+                        key: String::new(),
+                        name: var.name.clone(),
+                        value: value.to_owned(),
+                    }],
+                })
+                .collect::<Vec<_>>();
+            return visitor.visit_seq(SeqDeserializer::new(elements.into_iter()));
+        }
+        let path = self.path.clone();
+        let elements = self.elements()?;
+        let mut seq = SeqDeserializer::new(elements.into_iter());
+        let value = visitor
+            .visit_seq(&mut seq)
+            .map_err(|err| context(&path, err))?;
+        seq.end()?;
+        Ok(value)
+    }
+
+    fn deserialize_tuple<V: Visitor<'de>>(
+        self,
+        _len: usize,
+        visitor: V,
+    ) -> Result<V::Value, Error> {
+        self.deserialize_seq(visitor)
+    }
+
+    fn deserialize_tuple_struct<V: Visitor<'de>>(
+        self,
+        _name: &'static str,
+        _len: usize,
+        visitor: V,
+    ) -> Result<V::Value, Error> {
+        self.deserialize_seq(visitor)
+    }
+
+    fn deserialize_map<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Error> {
+        self.deserialize_table(None, visitor)
+    }
+
+    fn deserialize_struct<V: Visitor<'de>>(
+        self,
+        _name: &'static str,
+        fields: &'static [&'static str],
+        visitor: V,
+    ) -> Result<V::Value, Error> {
+        self.deserialize_table(Some(fields), visitor)
+    }
+
+    fn deserialize_enum<V: Visitor<'de>>(
+        self,
+        _name: &'static str,
+        _variants: &'static [&'static str],
+        visitor: V,
+    ) -> Result<V::Value, Error> {
+        let value: de::value::StringDeserializer<Error> =
+            self.value()?.value.trim().to_owned().into_deserializer();
+        visitor.visit_enum(value)
+    }
+
+    fn deserialize_identifier<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Error> {
+        self.deserialize_string(visitor)
+    }
+
+    fn deserialize_ignored_any<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Error> {
+        visitor.visit_unit()
+    }
+}
+
+impl EnvNode {
+    /// Deserialize a table of the given field names, or a map of arbitrary keys without names.
+    fn deserialize_table<'de, V: Visitor<'de>>(
+        self,
+        fields: Option<&'static [&'static str]>,
+        visitor: V,
+    ) -> Result<V::Value, Error> {
+        if let Some(value) = self.inline_toml()? {
+            return value
+                .deserialize_map(visitor)
+                .map_err(|err| context(&self.path, Error(err.to_string())));
+        }
+        let path = self.path.clone();
+        let children = self.children(fields);
+        let mut map = MapDeserializer::new(children.into_iter());
+        let value = visitor
+            .visit_map(&mut map)
+            .map_err(|err| context(&path, err))?;
+        map.end()?;
+        Ok(value)
+    }
+}
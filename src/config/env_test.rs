@@ -0,0 +1,199 @@
+use std::path::PathBuf;
+use std::time::Duration;
+
+use crate::config::env::from_vars;
+use crate::config::{ConfigOpts, DeployTarget, FileHashLayout, LoadBalancing, SassStyle};
+use crate::pipelines::PipelineStage;
+
+fn config(vars: &[(&str, &str)]) -> Result<ConfigOpts, String> {
+    from_vars(
+        vars.iter()
+            .map(|(name, value)| (name.to_string(), value.to_string())),
+    )
+    .map_err(|err| err.to_string())
+}
+
+#[test]
+fn env_vars_of_section_options() {
+    let cfg = config(&[
+        ("TRUNK_CONFIG", "Other.toml"),
+        ("TRUNK_BUILD_RELEASE", "true"),
+        ("TRUNK_BUILD_PUBLIC_URL", "/app/"),
+        ("TRUNK_SERVE_PORT", "9000"),
+        ("TRUNK_SERVE_PASSWORD", "secret"),
+        ("TRUNK_SERVE_HEADERS", r#"{ "X-Frame-Options" = "DENY" }"#),
+        ("TRUNK_WATCH_IGNORE", "target, node_modules"),
+        ("TRUNK_TOOLS_WASM_BINDGEN", "0.2.87"),
+        ("TRUNK_TOOLS_MIRROR", "https://mirror.example.com/tools"),
+        ("TRUNK_TOOLS_REQUIRE_CHECKSUMS", "true"),
+        ("PATH", "/usr/bin"),
+    ])
+    .expect("unexpected error reading env vars");
+
+    let build = cfg.build.expect("expected build options");
+    assert!(build.release);
+    assert_eq!(build.public_url.as_deref(), Some("/app/"));
+    let serve = cfg.serve.expect("expected serve options");
+    assert_eq!(serve.port, Some(9000));
+    assert_eq!(serve.password.as_deref(), Some("secret"));
+    assert_eq!(
+        serve.headers.get("X-Frame-Options").map(String::as_str),
+        Some("DENY")
+    );
+    let watch = cfg.watch.expect("expected watch options");
+    assert_eq!(
+        watch.ignore,
+        Some(vec![PathBuf::from("target"), PathBuf::from("node_modules")])
+    );
+    let tools = cfg.tools.expect("expected tools options");
+    assert_eq!(tools.wasm_bindgen.as_deref(), Some("0.2.87"));
+    assert_eq!(
+        tools.mirror.as_deref(),
+        Some("https://mirror.example.com/tools")
+    );
+    assert_eq!(tools.require_checksums, Some(true));
+    assert!(cfg.clean.is_none());
+    assert!(cfg.proxy.is_none());
+}
+
+#[test]
+fn env_vars_of_list_elements() {
+    let cfg = config(&[
+        ("TRUNK_PROXY_0_BACKEND", "http://localhost:9000/api/"),
+        ("TRUNK_PROXY_1_BACKEND_0", "http://localhost:9001/"),
+        ("TRUNK_PROXY_1_BACKEND_1", "http://localhost:9002/"),
+        ("TRUNK_PROXY_1_REWRITE", "/v1/"),
+        ("TRUNK_PROXY_1_LOAD_BALANCING", "random"),
+        ("TRUNK_HOOKS_0_STAGE", "pre_build"),
+        ("TRUNK_HOOKS_0_COMMAND", "echo"),
+        ("TRUNK_HOOKS_0_COMMAND_ARGUMENTS", r#"["hello", "world"]"#),
+    ])
+    .expect("unexpected error reading env vars");
+
+    let proxies = cfg.proxy.expect("expected proxies");
+    assert_eq!(proxies.len(), 2);
+    assert_eq!(proxies[0].backend.len(), 1);
+    assert_eq!(proxies[0].rewrite, None);
+    assert_eq!(proxies[1].backend.len(), 2);
+    assert_eq!(proxies[1].rewrite.as_deref(), Some("/v1/"));
+    assert_eq!(proxies[1].load_balancing, LoadBalancing::Random);
+    let hooks = cfg.hooks.expect("expected hooks");
+    assert_eq!(hooks.len(), 1);
+    assert_eq!(hooks[0].stage, PipelineStage::PreBuild);
+    assert_eq!(hooks[0].command, "echo");
+    assert_eq!(hooks[0].command_arguments, ["hello", "world"]);
+}
+
+#[test]
+fn env_vars_of_each_shape() {
+    let cfg = config(&[
+        // Booleans, numbers, enums & values parsed by the options.
+        ("TRUNK_BUILD_SRI", "Yes"),
+        ("TRUNK_BUILD_MINIFY", ""),
+        ("TRUNK_BUILD_JOBS", " 4 "),
+        ("TRUNK_BUILD_FILEHASH_LAYOUT", "dir"),
+        ("TRUNK_WATCH_POLL", "500ms"),
+        // Tables of options, by var per option & as inline TOML.
+        ("TRUNK_BUILD_SASS_STYLE", "compressed"),
+        ("TRUNK_BUILD_SASS_LOAD_PATHS", "vendor,styles"),
+        ("TRUNK_PROXY_0_BACKEND", "http://localhost:9000/"),
+        ("TRUNK_PROXY_0_RETRY", "{ attempts = 5 }"),
+        ("TRUNK_DEPLOY_TO", "github-pages"),
+        ("TRUNK_DEPLOY_GITHUB_PAGES_BRANCH", "pages"),
+        // Maps, by var per entry keeping the case of the keys & as inline TOML.
+        (
+            "TRUNK_BUILD_CARGO_ENV_RUSTFLAGS",
+            "--cfg web_sys_unstable_apis",
+        ),
+        ("TRUNK_BUILD_CARGO_ENV_CARGO_PROFILE_RELEASE_LTO", "true"),
+        (
+            "TRUNK_PROXY_0_REQUEST_HEADERS",
+            r#"{ "X-Api-Key" = "key" }"#,
+        ),
+        // Lists of tables by index, within a section.
+        ("TRUNK_SERVE_STATIC_0_PATH", "/media/"),
+        ("TRUNK_SERVE_STATIC_0_DIR", "media"),
+        // Unknown options are ignored.
+        ("TRUNK_BUILD_UNKNOWN", "value"),
+        ("TRUNK_UNKNOWN", "value"),
+    ])
+    .expect("unexpected error reading env vars");
+
+    let build = cfg.build.expect("expected build options");
+    assert_eq!(build.sri, Some(true));
+    assert_eq!(build.minify, Some(false));
+    assert_eq!(build.jobs, Some(4));
+    assert_eq!(build.filehash_layout, Some(FileHashLayout::Dir));
+    let sass = build.sass.expect("expected sass options");
+    assert_eq!(sass.style, Some(SassStyle::Compressed));
+    assert_eq!(
+        sass.load_paths,
+        [PathBuf::from("vendor"), PathBuf::from("styles")]
+    );
+    let cargo_env = build.cargo_env.expect("expected cargo env vars");
+    assert_eq!(
+        cargo_env.get("RUSTFLAGS").map(String::as_str),
+        Some("--cfg web_sys_unstable_apis")
+    );
+    assert_eq!(
+        cargo_env
+            .get("CARGO_PROFILE_RELEASE_LTO")
+            .map(String::as_str),
+        Some("true")
+    );
+    let watch = cfg.watch.expect("expected watch options");
+    assert_eq!(watch.poll, Some(Duration::from_millis(500)));
+    let proxies = cfg.proxy.expect("expected proxies");
+    let retry = proxies[0].retry.as_ref().expect("expected retry options");
+    assert_eq!(retry.attempts, Some(5));
+    assert_eq!(retry.backoff, None);
+    assert_eq!(
+        proxies[0]
+            .request_headers
+            .get("X-Api-Key")
+            .map(String::as_str),
+        Some("key")
+    );
+    let deploy = cfg.deploy.expect("expected deploy options");
+    assert_eq!(deploy.to, Some(DeployTarget::GithubPages));
+    let github_pages = deploy.github_pages.expect("expected github pages options");
+    assert_eq!(github_pages.branch.as_deref(), Some("pages"));
+    assert_eq!(github_pages.remote, None);
+    let serve = cfg.serve.expect("expected serve options");
+    assert_eq!(serve.static_dirs.len(), 1);
+    assert_eq!(serve.static_dirs[0].path, "/media/");
+    assert_eq!(serve.static_dirs[0].dir, PathBuf::from("media"));
+    assert!(cfg.tools.is_none());
+}
+
+#[test]
+fn err_env_vars_name_the_invalid_var() {
+    let err = config(&[("TRUNK_SERVE_PORT", "eighty")]).expect_err("expected an invalid port");
+    assert!(
+        err.contains("TRUNK_SERVE_PORT"),
+        "unexpected error: {}",
+        err
+    );
+
+    let err =
+        config(&[("TRUNK_PROXY_0_REWRITE", "/api/")]).expect_err("expected a missing backend");
+    assert_eq!(err, "TRUNK_PROXY_0: missing field `backend`");
+
+    let err = config(&[("TRUNK_PROXY_1_BACKEND", "http://localhost:9000/")])
+        .expect_err("expected a gap of the proxy indices");
+    assert!(err.contains("TRUNK_PROXY"), "unexpected error: {}", err);
+
+    let err = config(&[("TRUNK_BUILD_SRI", "maybe")]).expect_err("expected an invalid boolean");
+    assert_eq!(
+        err,
+        r#"invalid value "maybe" of TRUNK_BUILD_SRI, expected a boolean"#
+    );
+
+    let err = config(&[("TRUNK_SERVE_HEADERS", "{ X-Frame-Options = }")])
+        .expect_err("expected invalid inline TOML");
+    assert!(
+        err.starts_with("invalid TOML value of TRUNK_SERVE_HEADERS"),
+        "unexpected error: {}",
+        err
+    );
+}
@@ -1,9 +1,12 @@
-use std::path::Path;
+use std::collections::{HashMap, HashSet};
+use std::path::{Path, PathBuf};
 
 use anyhow::{Context, Result};
 use cargo_metadata::{Metadata, MetadataCommand, Package};
 use tokio::task::spawn_blocking;
 
+use crate::config::{CargoEnv, CargoFlags};
+
 /// A wrapper around the cargo project's metadata.
 #[derive(Clone, Debug)]
 pub struct CargoMetadata {
@@ -16,10 +19,33 @@ pub struct CargoMetadata {
 }
 
 impl CargoMetadata {
-    // Create a new instance from the Cargo.toml at the given path.
-    pub async fn new(manifest: &Path) -> Result<Self> {
+    // Create a new instance from the Cargo.toml at the given path, calling cargo with the flags &
+    // env vars, which may set its target dir.
+    pub async fn new(manifest: &Path, flags: CargoFlags, env: &CargoEnv) -> Result<Self> {
+        Self::with_options(manifest, flags, env, &[]).await
+    }
+
+    // Create a new instance as `new` does, passing the given options to cargo as well, e.g. to
+    // resolve the dependencies of a target platform only.
+    pub async fn with_options(
+        manifest: &Path,
+        flags: CargoFlags,
+        env: &CargoEnv,
+        options: &[&str],
+    ) -> Result<Self> {
         let mut cmd = MetadataCommand::new();
         cmd.manifest_path(dunce::simplified(manifest));
+        cmd.other_options(
+            flags
+                .cargo_args()
+                .into_iter()
+                .chain(options.iter().copied())
+                .map(str::to_owned)
+                .collect::<Vec<_>>(),
+        );
+        for (name, value) in env.vars() {
+            cmd.env(name, value);
+        }
         let metadata = spawn_blocking(move || cmd.exec())
             .await
             .context("error awaiting spawned cargo metadata task")?
@@ -39,4 +65,33 @@ impl CargoMetadata {
             manifest_path,
         })
     }
+
+    /// Get the dirs of all local crates which this package depends on, directly or transitively.
+    pub fn local_dependency_dirs(&self) -> Vec<PathBuf> {
+        let Some(resolve) = &self.metadata.resolve else {
+            return vec![];
+        };
+        let nodes: HashMap<_, _> = resolve.nodes.iter().map(|node| (&node.id, node)).collect();
+        let mut dependencies = HashSet::new();
+        let mut queue = vec![&self.package.id];
+        while let Some(id) = queue.pop() {
+            for dependency in nodes
+                .get(id)
+                .into_iter()
+                .flat_map(|node| &node.dependencies)
+            {
+                if dependencies.insert(dependency) {
+                    queue.push(dependency);
+                }
+            }
+        }
+
+        self.metadata
+            .packages
+            .iter()
+            .filter(|package| package.source.is_none() && dependencies.contains(&package.id))
+            .filter_map(|package| package.manifest_path.parent())
+            .map(|dir| dir.as_std_path().to_owned())
+            .collect()
+    }
 }
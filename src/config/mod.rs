@@ -4,11 +4,17 @@
 //! `Trunk.toml` config file is the base, which is then superseded by environment variables,
 //! which are finally superseded by CLI arguments and options.
 
+mod env;
+#[cfg(test)]
+mod env_test;
 mod manifest;
 mod models;
 #[cfg(test)]
 mod models_test;
 mod rt;
+mod schema;
+#[cfg(test)]
+mod schema_test;
 
 /// The default name of the directory where final build artifacts are
 /// placed after a successful build.
@@ -18,7 +24,15 @@ pub const STAGE_DIR: &str = ".stage";
 
 pub use manifest::CargoMetadata;
 pub use models::{
-    ConfigOpts, ConfigOptsBuild, ConfigOptsClean, ConfigOptsHook, ConfigOptsProxy, ConfigOptsServe,
-    ConfigOptsTools, ConfigOptsWatch,
+    CompressionAlgorithm, ConfigOpts, ConfigOptsBuild, ConfigOptsClean, ConfigOptsDeploy,
+    ConfigOptsDeployGitHubPages, ConfigOptsDeployNetlify, ConfigOptsDeployS3, ConfigOptsHook,
+    ConfigOptsProxy, ConfigOptsProxyRetry, ConfigOptsSass, ConfigOptsServe, ConfigOptsStatic,
+    ConfigOptsTools, ConfigOptsWatch, CopyStrategy, DeployTarget, FileHashLayout, LoadBalancing,
+    ProxyLog, SassStyle, TimingsFormat, UnhashedNames,
+};
+pub use rt::{
+    cargo_proxy_env, CargoEnv, CargoFlags, CargoProfile, DeployTo, Features, RtcBuild, RtcClean,
+    RtcDeploy, RtcServe, RtcWatch,
 };
-pub use rt::{Features, RtcBuild, RtcClean, RtcServe, RtcWatch};
+pub(crate) use rt::{cross_origin_isolation_headers, header_map};
+pub use schema::schema;
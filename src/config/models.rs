@@ -1,27 +1,44 @@
-use std::collections::HashMap;
+use std::collections::{BTreeMap, HashMap};
 use std::net::IpAddr;
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
 use std::str::FromStr;
 use std::sync::Arc;
+use std::time::Duration;
 
-use anyhow::{Context, Result};
+use anyhow::{bail, Context, Result};
+use axum::http::uri::InvalidUri;
 use axum::http::Uri;
-use clap::Args;
-use serde::{Deserialize, Deserializer};
+use clap::{Args, ValueEnum};
+use clap_complete::ArgValueCandidates;
+use schemars::JsonSchema;
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
 
 use crate::common::parse_public_url;
-use crate::config::{RtcBuild, RtcClean, RtcServe, RtcWatch};
+use crate::completions;
+use crate::config::rt::is_glob;
+use crate::config::{env, RtcBuild, RtcClean, RtcDeploy, RtcServe, RtcWatch};
 use crate::pipelines::PipelineStage;
 
 /// Config options for the build system.
-#[derive(Clone, Debug, Default, Deserialize, Args)]
+#[derive(Clone, Debug, Default, Deserialize, Serialize, JsonSchema, Args)]
 pub struct ConfigOptsBuild {
     /// The index HTML file to drive the bundling process [default: index.html]
     pub target: Option<PathBuf>,
+    /// The source HTML files of a multi-page app, which take precedence over `target`. The first
+    /// is the main page, and the other pages must be in its dir or its subdirs [default: none]
+    ///
+    /// These values can only be provided via config file.
+    #[arg(skip)]
+    #[serde(default)]
+    pub targets: Option<Vec<PathBuf>>,
     /// Build in release mode [default: false]
     #[arg(long)]
     #[serde(default)]
     pub release: bool,
+    /// The `[profile.<name>]` section of `Trunk.toml` to overlay the config with
+    /// [default: release in release mode, else dev]
+    #[arg(long, add = ArgValueCandidates::new(completions::profiles))]
+    pub profile: Option<String>,
     /// The output dir for all final assets [default: dist]
     #[arg(short, long)]
     pub dist: Option<PathBuf>,
@@ -40,9 +57,144 @@ pub struct ConfigOptsBuild {
     /// [default: ""]
     #[arg(long)]
     pub features: Option<String>,
+    /// The cargo profile to build the Rust apps with, e.g. a `[profile.release-small]` section of
+    /// `Cargo.toml`, independent of the release mode of Trunk [default: release in release mode,
+    /// else dev]
+    #[arg(long, value_name = "name")]
+    pub cargo_profile: Option<String>,
+    /// Require the `Cargo.lock` to be up to date, passed to cargo [default: false]
+    #[arg(long)]
+    #[serde(default)]
+    pub locked: bool,
+    /// Build without accessing the network, passed to cargo [default: false]
+    #[arg(long)]
+    #[serde(default)]
+    pub offline: bool,
+    /// Require the `Cargo.lock` & the cache of dependencies to be up to date, i.e. both `--locked`
+    /// & `--offline`, passed to cargo [default: false]
+    #[arg(long)]
+    #[serde(default)]
+    pub frozen: bool,
+    /// The number of asset pipelines run concurrently, which is also passed to cargo as the number
+    /// of its parallel jobs [default: unlimited, and the number of CPUs for cargo]
+    #[arg(short, long, value_name = "N")]
+    pub jobs: Option<usize>,
+    /// The cargo target dir of the Rust apps, e.g. `target/trunk-wasm`, so that their builds do
+    /// not invalidate the ones of `cargo check` & rust-analyzer [default: the one of cargo]
+    #[arg(long, value_name = "dir")]
+    pub cargo_target_dir: Option<PathBuf>,
+    /// The dir in which the outputs of expensive pipeline steps, e.g. of sass, favicons &
+    /// wasm-opt, are persisted for later builds, e.g. a dir restored by CI [default:
+    /// `pipelines` in the cache dir of Trunk]
+    #[arg(long, value_name = "dir")]
+    pub cache_dir: Option<PathBuf>,
+    /// Flags passed to rustc when building the Rust apps, e.g. `-C target-feature=+atomics`,
+    /// which are appended to the `RUSTFLAGS` env var, if set [default: None]
+    ///
+    /// These values can only be provided via config file.
+    #[arg(skip)]
+    #[serde(default)]
+    pub rustflags: Option<String>,
+    /// The env vars of the cargo invocations building the Rust apps [default: None]
+    ///
+    /// These values can only be provided via config file.
+    #[arg(skip)]
+    #[serde(default)]
+    pub cargo_env: Option<BTreeMap<String, String>>,
     /// Whether to include hash values in the output file names [default: true]
     #[arg(long)]
     pub filehash: Option<bool>,
+    /// The number of hex digits of the hashes in the output file names, at most 16
+    /// [default: all digits]
+    #[arg(long, value_name = "digits")]
+    pub filehash_length: Option<usize>,
+    /// The layout of hashed output file names [default: suffix]
+    #[arg(long)]
+    pub filehash_layout: Option<FileHashLayout>,
+    /// Add symlinks, or copies, of the hashed outputs with their names without hashes to the dist
+    /// dir, e.g. `app_bg.wasm` for `app-<hash>_bg.wasm`, for tools with fixed paths [default:
+    /// none]
+    #[arg(long)]
+    pub unhashed_names: Option<UnhashedNames>,
+    /// How to copy the files of `copy-file` & `copy-dir` assets to the dist dir, where `hardlink`
+    /// & `reflink` copy the files which can not be linked, e.g. into another file system
+    /// [default: copy]
+    #[arg(long)]
+    pub copy_strategy: Option<CopyStrategy>,
+    /// Whether to add subresource integrity hashes to the emitted script & link tags
+    /// [default: false]
+    #[arg(long)]
+    pub sri: Option<bool>,
+    /// Whether to add the hashes of the inline scripts & styles to the Content-Security-Policy
+    /// meta tag, and write them to `csp.json` [default: false]
+    #[arg(long)]
+    pub csp: Option<bool>,
+    /// Whether to minify the output HTML, JS & CSS [default: true in release mode]
+    #[arg(long)]
+    pub minify: Option<bool>,
+    /// Whether to process the output of all CSS, SASS/SCSS & tailwind pipelines with PostCSS,
+    /// which the `data-postcss` attribute enables per asset [default: false]
+    #[arg(long)]
+    pub postcss: Option<bool>,
+    /// Whether to bundle the imports of all JS & TypeScript assets with esbuild, which the
+    /// `data-bundle` attribute enables per asset [default: false]
+    #[arg(long)]
+    pub bundle: Option<bool>,
+    /// Whether to generate a service worker, which precaches all build artifacts for offline
+    /// support, and register it. It is not registered when serving with autoreload
+    /// [default: false]
+    #[arg(long)]
+    pub service_worker: Option<bool>,
+    /// Whether to write an `asset-manifest.json` to the dist dir, which maps the names of the
+    /// outputs without their hashes to their hashed paths, sizes & integrity hashes
+    /// [default: false]
+    #[arg(long)]
+    pub asset_manifest: Option<bool>,
+    /// Whether to build byte-identical outputs from the same sources, by stripping the local paths
+    /// from the Rust apps, disabling incremental compilation & setting the modification times of
+    /// the outputs to `SOURCE_DATE_EPOCH`, or the Unix epoch [default: false]
+    #[arg(long)]
+    pub reproducible: Option<bool>,
+    /// Do not remove the files of the dist dir which the build did not output, such as the hashed
+    /// outputs of previous builds [default: false]
+    #[arg(long = "no-prune")]
+    #[serde(default)]
+    pub no_prune: bool,
+    /// A comma-separated list of compression algorithms, `gzip` and/or `br`, to write
+    /// precompressed siblings of the wasm, js & css outputs of release builds with [default: none]
+    #[arg(long, value_delimiter = ',', value_name = "algorithm")]
+    pub precompress: Option<Vec<CompressionAlgorithm>>,
+    /// The level to precompress outputs with, at most 9 for gzip & 11 for brotli
+    /// [default: the highest level]
+    #[arg(long, value_name = "level")]
+    pub precompress_level: Option<u32>,
+    /// Whether to print a report of the raw, gzip & brotli sizes of the outputs, and their
+    /// changes since the previous build [default: true in release mode]
+    #[arg(long)]
+    pub size_report: Option<bool>,
+    /// A `.json` or `.html` file to write the size report to as well [default: None]
+    #[arg(long, value_name = "path")]
+    pub size_report_file: Option<PathBuf>,
+    /// Record the durations of the stages of builds, and write them to a report in the cargo
+    /// target dir, as `html` or `json` [default: None, or `html` if given without a format]
+    #[arg(
+        long,
+        value_name = "format",
+        num_args = 0..=1,
+        default_missing_value = "html"
+    )]
+    pub timings: Option<TimingsFormat>,
+    /// The wasm-opt level to optimize Rust apps with in release mode, e.g. `z` for size or `0` to
+    /// skip wasm-opt, which the `data-wasm-opt` attribute overrides [default: None]
+    #[arg(long, value_name = "level")]
+    pub wasm_opt: Option<String>,
+    /// Additional arguments passed to wasm-bindgen for all Rust apps, e.g. `["--weak-refs"]`,
+    /// which precede those of the `data-bindgen-*` attributes [default: []]
+    ///
+    /// These values can only be provided via config file.
+    #[arg(skip)]
+    #[serde(default)]
+    pub bindgen_args: Option<Vec<String>>,
     /// Optional pattern for the app loader script [default: None]
     ///
     /// Patterns should include the sequences `{base}`, `{wasm}`, and `{js}` in order to
@@ -86,21 +238,181 @@ pub struct ConfigOptsBuild {
     ///
     /// These values can only be provided via config file.
     pub pattern_params: Option<HashMap<String, String>>,
+    /// Options of the SASS/SCSS compiler, which the `data-*` attributes of sass/scss assets
+    /// override [default: None]
+    ///
+    /// These values can only be provided via config file.
+    #[arg(skip)]
+    #[serde(default)]
+    pub sass: Option<ConfigOptsSass>,
+    /// Mappings of module specifiers to URLs, e.g. of CDNs, which are added to the import map of
+    /// the output HTML [default: None]
+    ///
+    /// These values can only be provided via config file.
+    #[arg(skip)]
+    #[serde(default)]
+    pub import_map: Option<BTreeMap<String, String>>,
+    /// Globs of the paths of the outputs relative to the dist dir, e.g. `videos/**`, which the
+    /// service worker does not precache, besides the build metadata & outputs over 16 MiB
+    /// [default: none]
+    ///
+    /// These values can only be provided via config file.
+    #[arg(skip)]
+    #[serde(default)]
+    pub service_worker_exclude: Option<Vec<String>>,
+    /// The names of the environment variables which may be injected into the output HTML & the
+    /// `rel="env"` asset at build time [default: none]
+    ///
+    /// These values can only be provided via config file.
+    #[arg(skip)]
+    #[serde(default)]
+    pub env: Option<Vec<String>>,
+    /// Options of the per-locale builds of the output HTML [default: None]
+    ///
+    /// These values can only be provided via config file.
+    #[arg(skip)]
+    #[serde(default)]
+    pub i18n: Option<ConfigOptsI18n>,
+}
+
+/// Config options for the per-locale builds of the output HTML.
+#[derive(Clone, Debug, Default, Deserialize, Serialize, JsonSchema)]
+pub struct ConfigOptsI18n {
+    /// The locales for which the output HTML is built, each into `dist/<locale>/index.html`.
+    #[serde(default)]
+    pub locales: Vec<String>,
+    /// The locale of the output HTML at the root of the dist dir, and of the dev server when
+    /// none of the locales is accepted by the browser [default: the first locale]
+    pub default_locale: Option<String>,
+}
+
+/// Config options for the SASS/SCSS compiler.
+#[derive(Clone, Debug, Default, Deserialize, Serialize, JsonSchema)]
+pub struct ConfigOptsSass {
+    /// Paths in which files loaded with `@use` & `@import` are looked up.
+    #[serde(default)]
+    pub load_paths: Vec<PathBuf>,
+    /// Silence the deprecation warnings of dependencies, i.e. files loaded from load paths.
+    #[serde(default)]
+    pub quiet_deps: bool,
+    /// Embed source maps into the compiled CSS.
+    #[serde(default)]
+    pub source_map: bool,
+    /// The output style of the compiled CSS, which is `compressed` if minifying outputs, and
+    /// `expanded` otherwise, by default.
+    pub style: Option<SassStyle>,
+}
+
+/// The output style of the SASS/SCSS compiler.
+#[derive(Clone, Copy, Debug, Deserialize, Serialize, JsonSchema, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum SassStyle {
+    /// Each selector & declaration on its own line.
+    Expanded,
+    /// As few characters as possible.
+    Compressed,
+}
+
+/// The format of the report of the timings of builds.
+#[derive(Clone, Copy, Debug, Deserialize, Serialize, JsonSchema, PartialEq, Eq, ValueEnum)]
+#[serde(rename_all = "lowercase")]
+pub enum TimingsFormat {
+    /// A timeline of the stages as an HTML page.
+    Html,
+    /// The timings of the stages as JSON.
+    Json,
+}
+
+/// The layout of the names of output files, which include the hashes of their contents.
+#[derive(
+    Clone, Copy, Debug, Default, Deserialize, Serialize, JsonSchema, PartialEq, Eq, ValueEnum,
+)]
+#[serde(rename_all = "lowercase")]
+pub enum FileHashLayout {
+    /// Append the hash to the file stem, e.g. `name-<hash>.ext`.
+    #[default]
+    Suffix,
+    /// Place the file in a dir named by the hash, e.g. `assets/<hash>/name.ext`.
+    Dir,
+}
+
+/// The kinds of files with the names of hashed outputs without their hashes.
+#[derive(Clone, Copy, Debug, Deserialize, Serialize, JsonSchema, PartialEq, Eq, ValueEnum)]
+#[serde(rename_all = "lowercase")]
+pub enum UnhashedNames {
+    /// Relative symlinks to the hashed outputs.
+    Symlink,
+    /// Copies of the hashed outputs, e.g. for hosts which do not follow symlinks.
+    Copy,
+}
+
+/// The strategies of copying the files of `copy-file` & `copy-dir` assets to the dist dir.
+#[derive(
+    Clone, Copy, Debug, Default, Deserialize, Serialize, JsonSchema, PartialEq, Eq, ValueEnum,
+)]
+#[serde(rename_all = "lowercase")]
+pub enum CopyStrategy {
+    /// Copy the contents of the files.
+    #[default]
+    Copy,
+    /// Hard-link the files, which then share their contents with the source files.
+    Hardlink,
+    /// Clone the files with reflinks of copy-on-write file systems, e.g. Btrfs, XFS or APFS.
+    Reflink,
 }
 
 /// Config options for the watch system.
-#[derive(Clone, Debug, Default, Deserialize, Args)]
+#[derive(Clone, Debug, Default, Deserialize, Serialize, JsonSchema, Args)]
 pub struct ConfigOptsWatch {
     /// Watch specific file(s) or folder(s) [default: build target parent folder]
     #[arg(short, long, value_name = "path")]
     pub watch: Option<Vec<PathBuf>>,
-    /// Paths to ignore [default: []]
+    /// Paths or globs to ignore, e.g. `**/*.snap`, relative globs being relative to the dir of the
+    /// config file [default: []]
     #[arg(short, long, value_name = "path")]
     pub ignore: Option<Vec<PathBuf>>,
+    /// Ignore the paths listed in the `.gitignore` files of the watched paths [default: false]
+    #[arg(long)]
+    #[serde(default)]
+    pub gitignore: bool,
+    /// Poll for file system changes at the given interval, e.g. `500ms`, instead of using native
+    /// file system events, which do not work on some network & container file systems
+    /// [default: None]
+    #[arg(long, value_name = "interval", value_parser = humantime::parse_duration)]
+    #[serde(
+        default,
+        deserialize_with = "deserialize_duration",
+        serialize_with = "serialize_duration"
+    )]
+    #[schemars(with = "Option<String>")]
+    pub poll: Option<Duration>,
+    /// The duration for which file system events are collected before triggering a build, e.g.
+    /// `300ms` [default: 25ms]
+    #[arg(long = "watch-debounce", value_name = "duration", value_parser = humantime::parse_duration)]
+    #[serde(
+        default,
+        deserialize_with = "deserialize_duration",
+        serialize_with = "serialize_duration"
+    )]
+    #[schemars(with = "Option<String>")]
+    pub debounce: Option<Duration>,
+    /// Clear the terminal before each build, and print a header with the time & the changed paths
+    /// [default: false]
+    #[arg(long)]
+    #[serde(default)]
+    pub clear_screen: bool,
+    /// Show a desktop notification with the result & duration of each build [default: false]
+    #[arg(long)]
+    #[serde(default)]
+    pub notifications: bool,
+    /// A command to run using the system shell after each successful build, which may be given
+    /// multiple times to run several commands one after another [default: []]
+    #[arg(long, value_name = "command")]
+    pub exec: Option<Vec<String>>,
 }
 
 /// Config options for the serve system.
-#[derive(Clone, Debug, Default, Deserialize, Args)]
+#[derive(Clone, Debug, Default, Deserialize, Serialize, JsonSchema, Args)]
 pub struct ConfigOptsServe {
     /// The address to serve on [default: 127.0.0.1]
     #[arg(long)]
@@ -112,9 +424,29 @@ pub struct ConfigOptsServe {
     #[arg(long)]
     #[serde(default)]
     pub open: bool,
-    /// A URL to which requests will be proxied [default: None]
-    #[arg(long = "proxy-backend")]
-    #[serde(default, deserialize_with = "deserialize_uri")]
+    /// Print a QR code of the network URL of the server, for mobile devices to scan, when
+    /// serving on an unspecified address [default: false]
+    #[arg(long)]
+    #[serde(default)]
+    pub qr: bool,
+    /// Announce the server via mDNS as `<mdns-name>.local`, for devices in the network to reach
+    /// it by name, when serving on an unspecified address [default: false]
+    #[arg(long)]
+    #[serde(default)]
+    pub mdns: bool,
+    /// The name to announce the server as via mDNS, without `.local` [default: the name of the
+    /// dir of the target]
+    #[arg(long = "mdns-name")]
+    pub mdns_name: Option<String>,
+    /// A URL to which requests will be proxied, or `unix:/path/to/socket` to proxy requests to
+    /// a Unix domain socket [default: None]
+    #[arg(long = "proxy-backend", value_parser = parse_backend_uri)]
+    #[serde(
+        default,
+        deserialize_with = "deserialize_uri",
+        serialize_with = "serialize_uri"
+    )]
+    #[schemars(with = "Option<String>")]
     pub proxy_backend: Option<Uri>,
     /// The URI on which to accept requests which are to be rewritten and proxied to backend
     /// [default: None]
@@ -129,20 +461,110 @@ pub struct ConfigOptsServe {
     #[arg(long = "proxy-insecure")]
     #[serde(default)]
     pub proxy_insecure: bool,
+    /// Configure the proxy to send requests to the backend using HTTP/2 [default: false]
+    #[arg(long = "proxy-http2")]
+    #[serde(default)]
+    pub proxy_http2: bool,
+    /// Log the requests & responses of all proxies, which do not configure their own logging
+    /// [default: None]
+    #[arg(long = "proxy-log")]
+    #[serde(default)]
+    pub proxy_log: Option<ProxyLog>,
     /// Disable auto-reload of the web app [default: false]
     #[arg(long = "no-autoreload")]
     #[serde(default)]
     pub no_autoreload: bool,
+    /// The path of the autoreload WebSocket [default: /_trunk/ws]
+    #[arg(long)]
+    pub ws_path: Option<String>,
+    /// The interval in seconds at which to send keepalive pings on the autoreload WebSocket,
+    /// 0 disables pings [default: 30]
+    #[arg(long, value_name = "seconds")]
+    pub ws_ping_interval: Option<u64>,
+    /// Enable TLS encryption, generating a self-signed certificate if neither a TLS key file nor
+    /// a TLS cert file is provided [default: false]
+    #[arg(long)]
+    #[serde(default)]
+    pub tls: bool,
     /// The TLS key file to enable TLS encryption [default: None]
     #[arg(long)]
     pub tls_key_path: Option<PathBuf>,
     /// The TLS cert file to enable TLS encryption [default: None]
     #[arg(long)]
     pub tls_cert_path: Option<PathBuf>,
+    /// The port on which to listen for plain HTTP requests, which will be redirected to HTTPS
+    /// when TLS is enabled [default: None]
+    #[arg(long)]
+    pub tls_redirect_port: Option<u16>,
+    /// The username required to access the server using basic auth [default: None]
+    #[arg(long)]
+    pub username: Option<String>,
+    /// The password required to access the server using basic auth, which is visible to other
+    /// users in the process list, unlike the `--password-file` option or the
+    /// `TRUNK_SERVE_PASSWORD` env var [default: None]
+    #[arg(long)]
+    pub password: Option<String>,
+    /// A file containing the password required to access the server using basic auth, without
+    /// any trailing newline [default: None]
+    #[arg(long, value_name = "path", conflicts_with = "password")]
+    pub password_file: Option<PathBuf>,
+    /// The bearer token required to trigger a build with `POST /_trunk/build`, which is only
+    /// enabled if set [default: None]
+    #[arg(long, value_name = "token")]
+    pub build_token: Option<String>,
+    /// A comma-separated list of compression algorithms which may be used for responses
+    /// [default: none]
+    #[arg(long, value_delimiter = ',', value_name = "algorithm")]
+    pub compression: Option<Vec<CompressionAlgorithm>>,
+    /// Set the `Cross-Origin-Opener-Policy` & `Cross-Origin-Embedder-Policy` headers of all
+    /// static file responses to enable cross-origin isolation, which is required to share memory
+    /// between threads, e.g. of multithreaded wasm [default: false]
+    #[arg(long)]
+    pub cross_origin_isolation: Option<bool>,
+    /// Additional headers to set on all static file responses [default: {}]
+    ///
+    /// These values can only be provided via config file.
+    #[arg(skip)]
+    #[serde(default)]
+    pub headers: HashMap<String, String>,
+    /// The MIME types of static files by their extension, which override the guessed ones, e.g.
+    /// `wasm = "application/wasm"`, which is always enforced [default: {}]
+    ///
+    /// These values can only be provided via config file.
+    #[arg(skip)]
+    #[serde(default)]
+    pub mime_types: HashMap<String, String>,
+    /// Additional directories to be served along with the dist dir [default: []]
+    ///
+    /// These values can only be provided via config file.
+    #[arg(skip)]
+    #[serde(default, rename = "static")]
+    pub static_dirs: Vec<ConfigOptsStatic>,
+}
+
+/// Config options for an additional directory to be served by the serve system.
+#[derive(Clone, Debug, Deserialize, Serialize, JsonSchema)]
+pub struct ConfigOptsStatic {
+    /// The URI path prefix on which the directory is to be served.
+    pub path: String,
+    /// The directory to be served.
+    pub dir: PathBuf,
+}
+
+/// A compression algorithm which the serve system may use for its responses.
+#[derive(Clone, Copy, Debug, Deserialize, Serialize, JsonSchema, PartialEq, Eq, ValueEnum)]
+#[serde(rename_all = "lowercase")]
+pub enum CompressionAlgorithm {
+    /// Brotli compression.
+    Br,
+    /// Gzip compression.
+    Gzip,
+    /// Deflate compression.
+    Deflate,
 }
 
 /// Config options for the serve system.
-#[derive(Clone, Debug, Default, Deserialize, Args)]
+#[derive(Clone, Debug, Default, Deserialize, Serialize, JsonSchema, Args)]
 pub struct ConfigOptsClean {
     /// The output dir for all final assets [default: dist]
     #[arg(short, long)]
@@ -153,9 +575,74 @@ pub struct ConfigOptsClean {
     pub cargo: bool,
 }
 
+/// Config options for the deploy system.
+#[derive(Clone, Debug, Default, Deserialize, Serialize, JsonSchema)]
+pub struct ConfigOptsDeploy {
+    /// The target to deploy to, which is required if more than one target is configured.
+    pub to: Option<DeployTarget>,
+    /// Deploy to an S3 bucket, optionally invalidating a CloudFront distribution.
+    pub s3: Option<ConfigOptsDeployS3>,
+    /// Deploy to the GitHub Pages branch of a git remote.
+    pub github_pages: Option<ConfigOptsDeployGitHubPages>,
+    /// Deploy to a Netlify site.
+    pub netlify: Option<ConfigOptsDeployNetlify>,
+}
+
+/// The targets of the deploy system.
+#[derive(Clone, Copy, Debug, Deserialize, Serialize, JsonSchema, PartialEq, Eq, ValueEnum)]
+#[serde(rename_all = "kebab-case")]
+pub enum DeployTarget {
+    S3,
+    GithubPages,
+    Netlify,
+}
+
+/// Config options for deploying to an S3 bucket with the `aws` CLI.
+#[derive(Clone, Debug, Deserialize, Serialize, JsonSchema)]
+pub struct ConfigOptsDeployS3 {
+    /// The name of the bucket.
+    pub bucket: String,
+    /// The key prefix to upload the files to [default: none]
+    pub prefix: Option<String>,
+    /// The region of the bucket [default: the region of the AWS config]
+    pub region: Option<String>,
+    /// Delete the files of the prefix which are not in the dist dir [default: false]
+    #[serde(default)]
+    pub delete: bool,
+    /// The ID of a CloudFront distribution to invalidate the uploaded paths of [default: none]
+    pub cloudfront_distribution: Option<String>,
+}
+
+/// Config options for deploying to the GitHub Pages branch of a git remote with `git`.
+#[derive(Clone, Debug, Default, Deserialize, Serialize, JsonSchema)]
+pub struct ConfigOptsDeployGitHubPages {
+    /// The git remote, or the URL of a repo, to push to [default: origin]
+    pub remote: Option<String>,
+    /// The branch to commit the dist dir to [default: gh-pages]
+    pub branch: Option<String>,
+    /// The message of the commit [default: Deploy with Trunk]
+    pub message: Option<String>,
+    /// The custom domain of the site, written to a `CNAME` file [default: none]
+    pub cname: Option<String>,
+}
+
+/// Config options for deploying to a Netlify site with the `netlify` CLI.
+#[derive(Clone, Debug, Default, Deserialize, Serialize, JsonSchema)]
+pub struct ConfigOptsDeployNetlify {
+    /// The ID or name of the site [default: the linked site, or `NETLIFY_SITE_ID`]
+    pub site: Option<String>,
+    /// Deploy a draft, instead of the production site [default: false]
+    #[serde(default)]
+    pub draft: bool,
+    /// The message of the deploy [default: none]
+    pub message: Option<String>,
+}
+
 /// Config options for automatic application downloads.
-#[derive(Clone, Debug, Default, Deserialize)]
+#[derive(Clone, Debug, Default, Deserialize, Serialize, JsonSchema)]
 pub struct ConfigOptsTools {
+    /// Version of `esbuild` to use.
+    pub esbuild: Option<String>,
     /// Version of `dart-sass` to use.
     pub sass: Option<String>,
     /// Version of `wasm-bindgen` to use.
@@ -164,6 +651,19 @@ pub struct ConfigOptsTools {
     pub wasm_opt: Option<String>,
     /// Version of `tailwindcss-cli` to use.
     pub tailwindcss: Option<String>,
+    /// The SHA-256 checksums of tool downloads as hex, keyed by the file name of the download,
+    /// which take precedence over the checksums published with the releases.
+    pub checksums: Option<HashMap<String, String>>,
+    /// Fail downloads of tools without a checksum to verify them against, instead of only warning
+    /// [default: false]
+    pub require_checksums: Option<bool>,
+    /// The URL or local dir of a mirror of the release archives & checksums of the tools, which
+    /// are downloaded from there instead, by their file names.
+    pub mirror: Option<String>,
+    /// The URL of the HTTP proxy to download the tools through, and to pass to cargo, instead of
+    /// the proxy of the `HTTPS_PROXY` env var. The hosts of the `NO_PROXY` env var are still
+    /// reached directly.
+    pub proxy: Option<String>,
 }
 
 /// Config options for building proxies.
@@ -171,36 +671,171 @@ pub struct ConfigOptsTools {
 /// NOTE WELL: this configuration type is different from the others inasmuch as it is only used
 /// when parsing the `Trunk.toml` config file. It is not intended to be configured via CLI or env
 /// vars.
-#[derive(Clone, Debug, Deserialize)]
+#[derive(Clone, Debug, Deserialize, Serialize, JsonSchema)]
 pub struct ConfigOptsProxy {
-    /// The URL of the backend to which requests are to be proxied.
-    #[serde(deserialize_with = "deserialize_uri")]
-    pub backend: Uri,
+    /// The URL of the backend to which requests are to be proxied, or `unix:/path/to/socket` to
+    /// proxy requests to a Unix domain socket.
+    ///
+    /// A list of URLs may be given to balance requests across multiple HTTP backends.
+    #[serde(
+        deserialize_with = "deserialize_backends",
+        serialize_with = "serialize_backends"
+    )]
+    #[schemars(with = "Backends")]
+    pub backend: Vec<Uri>,
+    /// The strategy used to select the backend of each request, when multiple backends are given
+    /// [default: round-robin]
+    #[serde(default)]
+    pub load_balancing: LoadBalancing,
     /// An optional URI prefix which is to be used as the base URI for proxying requests, which
     /// defaults to the URI of the backend.
     ///
     /// When a value is specified, requests received on this URI will have this URI segment
     /// replaced with the URI of the `backend`.
     pub rewrite: Option<String>,
+    /// An optional regex based rewrite of the paths of requests sent to the backend, which takes
+    /// precedence over the `rewrite` prefix replacement for all matching paths.
+    pub path_rewrite: Option<ConfigOptsPathRewrite>,
     /// Configure the proxy for handling WebSockets.
     #[serde(default)]
     pub ws: bool,
     /// Configure the proxy to accept insecure certificates.
     #[serde(default)]
     pub insecure: bool,
+    /// Configure the proxy to send requests to the backend using HTTP/2.
+    #[serde(default)]
+    pub http2: bool,
+    /// Configure the proxy to translate gRPC-web requests received from browsers into native gRPC
+    /// requests, which are sent to the backend using HTTP/2 over plain TCP.
+    #[serde(default)]
+    pub grpc_web: bool,
+    /// An optional timeout in seconds to connect to the backend.
+    pub connect_timeout: Option<u64>,
+    /// An optional timeout in seconds to wait for the response of the backend, including any
+    /// retries, after which requests fail with `504 Gateway Timeout`.
+    ///
+    /// The timeout does not apply to streaming the body of the response.
+    pub request_timeout: Option<u64>,
+    /// An optional policy for retrying requests which failed to connect to the backend.
+    pub retry: Option<ConfigOptsProxyRetry>,
+    /// Configure the proxy to preserve the host header of received requests, instead of setting
+    /// the host of the backend.
+    #[serde(default)]
+    pub preserve_host: bool,
+    /// An optional host header to set on all requests sent to the backend, instead of the host of
+    /// the backend.
+    pub host: Option<String>,
+    /// Log the requests & responses of this proxy, which defaults to the `serve.proxy_log` option.
+    pub log: Option<ProxyLog>,
+    /// An optional directory of fixtures to respond with, while the backend is unreachable.
+    ///
+    /// Fixtures are matched by the path of requests below the proxy path, trying the path itself,
+    /// the path with a `.json` extension & an `index.json` file in the path.
+    pub mock_dir: Option<PathBuf>,
+    /// Configure the proxy to rewrite the `Location` headers of responses redirecting to the
+    /// backend, so that they redirect to the proxy instead. Redirects are then passed through to
+    /// the browser, instead of being followed by the proxy.
+    #[serde(default)]
+    pub rewrite_location: bool,
+    /// Configure the proxy to rewrite the `Set-Cookie` headers of responses, removing their domain
+    /// & mapping their path, so that cookies are set for the proxy instead of the backend.
+    #[serde(default)]
+    pub rewrite_cookies: bool,
+    /// Additional headers to set on all requests sent to the backend.
+    #[serde(default)]
+    pub request_headers: HashMap<String, String>,
+    /// Additional headers to set on all responses received from the backend.
+    #[serde(default)]
+    pub response_headers: HashMap<String, String>,
+    /// Set the cross-origin isolation headers on all responses received from the backend, like
+    /// `serve.cross_origin_isolation` does for static files, e.g. for proxied pages which share
+    /// memory between threads.
+    #[serde(default)]
+    pub cross_origin_isolation: bool,
+}
+
+/// The details of proxied requests & responses which are logged.
+#[derive(
+    Clone,
+    Copy,
+    Debug,
+    Deserialize,
+    Serialize,
+    JsonSchema,
+    PartialEq,
+    Eq,
+    PartialOrd,
+    Ord,
+    ValueEnum,
+)]
+#[serde(rename_all = "lowercase")]
+pub enum ProxyLog {
+    /// Log the method, path, status & latency.
+    Summary,
+    /// Additionally log the headers.
+    Headers,
+    /// Additionally log the headers & bodies.
+    Body,
+}
+
+/// The strategy used to select the backend of each request proxied to multiple backends.
+#[derive(Clone, Copy, Debug, Default, Deserialize, Serialize, JsonSchema, PartialEq, Eq)]
+#[serde(rename_all = "kebab-case")]
+pub enum LoadBalancing {
+    /// Select the backends one after the other.
+    #[default]
+    RoundRobin,
+    /// Select a random backend.
+    Random,
+}
+
+/// Config options for retrying proxied requests which failed to connect to the backend.
+#[derive(Clone, Debug, Default, Deserialize, Serialize, JsonSchema)]
+pub struct ConfigOptsProxyRetry {
+    /// The maximum number of attempts to send a request to the backend [default: 3]
+    pub attempts: Option<u32>,
+    /// The backoff in milliseconds before the first retry, which doubles with each retry
+    /// [default: 250]
+    pub backoff: Option<u64>,
+    /// The duration in seconds to wait for the backend to become reachable, before sending
+    /// requests to a backend which has not been reachable so far [default: 0]
+    pub wait: Option<u64>,
+}
+
+/// Config options for a regex based rewrite of proxied request paths.
+#[derive(Clone, Debug, Deserialize, Serialize, JsonSchema)]
+pub struct ConfigOptsPathRewrite {
+    /// The regex pattern matched against the full path of received requests.
+    pub pattern: String,
+    /// The replacement of matched paths, which may reference capture groups as `$1` or `$name`.
+    ///
+    /// The query of received requests is appended to the query of the replacement, if any.
+    pub replacement: String,
 }
 
 /// Config options for build system hooks.
-#[derive(Clone, Debug, Deserialize)]
+#[derive(Clone, Debug, Deserialize, Serialize, JsonSchema)]
 #[serde(rename_all = "snake_case")]
 pub struct ConfigOptsHook {
     /// The stage in the build process to execute this hook.
     pub stage: PipelineStage,
+    /// The pipeline after which a `post_pipeline` hook runs, i.e. `css`, `sass`, `tailwind-css`,
+    /// `js` or `typescript`.
+    pub pipeline: Option<String>,
     /// The command to run for this hook.
     pub command: String,
     /// Any arguments to pass to the command.
     #[serde(default)]
     pub command_arguments: Vec<String>,
+    /// The profiles for which the hook runs, i.e. the names of `[profile.<name>]` sections or the
+    /// build profiles `debug` & `release`, or all if unset.
+    pub profiles: Option<Vec<String>>,
+    /// The operating systems on which the hook runs, e.g. `linux`, `macos` or `windows`, or all
+    /// if unset.
+    pub os: Option<Vec<String>>,
+    /// Globs of paths relative to the source dir, changes to which make the hook run during a
+    /// watch. The hook runs on builds not triggered by changes regardless.
+    pub only_if_changed: Option<Vec<String>>,
 }
 
 /// Deserialize a Uri from a string.
@@ -210,33 +845,150 @@ where
     T: std::convert::From<Uri>,
 {
     let val = String::deserialize(data)?;
-    Uri::from_str(val.as_str())
+    parse_backend_uri(val.as_str())
         .map(Into::into)
         .map_err(|err| serde::de::Error::custom(err.to_string()))
 }
 
+/// Deserialize an optional duration from a human readable string, e.g. `500ms`.
+fn deserialize_duration<'de, D>(data: D) -> std::result::Result<Option<Duration>, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    Option::<String>::deserialize(data)?
+        .map(|val| humantime::parse_duration(&val))
+        .transpose()
+        .map_err(|err| serde::de::Error::custom(err.to_string()))
+}
+
+/// The proxy backends of the config, which are one URI or a list of URIs.
+#[derive(Deserialize, JsonSchema)]
+#[serde(untagged)]
+enum Backends {
+    One(String),
+    Many(Vec<String>),
+}
+
+/// Deserialize the URIs of proxy backends from a single string or a non-empty list of strings.
+fn deserialize_backends<'de, D>(data: D) -> std::result::Result<Vec<Uri>, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    let vals = match Backends::deserialize(data)? {
+        Backends::One(val) => vec![val],
+        Backends::Many(vals) => vals,
+    };
+    if vals.is_empty() {
+        return Err(serde::de::Error::custom(
+            "at least one proxy backend is required",
+        ));
+    }
+    vals.iter()
+        .map(|val| parse_backend_uri(val).map_err(|err| serde::de::Error::custom(err.to_string())))
+        .collect()
+}
+
+/// Serialize an optional Uri as a string.
+fn serialize_uri<S>(val: &Option<Uri>, serializer: S) -> std::result::Result<S::Ok, S::Error>
+where
+    S: Serializer,
+{
+    val.as_ref().map(display_backend_uri).serialize(serializer)
+}
+
+/// Serialize an optional duration as a human readable string, e.g. `500ms`.
+fn serialize_duration<S>(
+    val: &Option<Duration>,
+    serializer: S,
+) -> std::result::Result<S::Ok, S::Error>
+where
+    S: Serializer,
+{
+    val.map(|val| humantime::format_duration(val).to_string())
+        .serialize(serializer)
+}
+
+/// Serialize the URIs of proxy backends as a list of strings.
+fn serialize_backends<S>(vals: &[Uri], serializer: S) -> std::result::Result<S::Ok, S::Error>
+where
+    S: Serializer,
+{
+    serializer.collect_seq(vals.iter().map(display_backend_uri))
+}
+
+/// Parse the URI of a proxy backend.
+///
+/// Unix domain socket backends, given as `unix:/path/to/socket`, are represented as
+/// `unix://localhost/path/to/socket`, as the former is not a valid URI.
+pub(crate) fn parse_backend_uri(val: &str) -> std::result::Result<Uri, InvalidUri> {
+    match val.strip_prefix("unix:") {
+        Some(socket) if socket.starts_with('/') && !socket.starts_with("//") => {
+            Uri::from_str(&format!("unix://localhost{socket}"))
+        }
+        _ => Uri::from_str(val),
+    }
+}
+
+/// Display the URI of a proxy backend as parsed by [`parse_backend_uri`].
+fn display_backend_uri(uri: &Uri) -> String {
+    match crate::proxy::unix_socket_path(uri) {
+        Some(socket) => format!("unix:{}", socket.display()),
+        None => uri.to_string(),
+    }
+}
+
 /// A model of all potential configuration options for the Trunk CLI system.
-#[derive(Clone, Debug, Default, Deserialize)]
+#[derive(Clone, Debug, Default, Deserialize, Serialize, JsonSchema)]
 pub struct ConfigOpts {
     pub build: Option<ConfigOptsBuild>,
     pub watch: Option<ConfigOptsWatch>,
     pub serve: Option<ConfigOptsServe>,
     pub clean: Option<ConfigOptsClean>,
+    pub deploy: Option<ConfigOptsDeploy>,
     pub tools: Option<ConfigOptsTools>,
     pub proxy: Option<Vec<ConfigOptsProxy>>,
     pub hooks: Option<Vec<ConfigOptsHook>>,
+    pub profile: Option<HashMap<String, ConfigOptsProfile>>,
+}
+
+/// Config options of a `[profile.<name>]` section, which overlay the base config when the profile
+/// is selected.
+#[derive(Clone, Debug, Default, Deserialize, Serialize, JsonSchema)]
+pub struct ConfigOptsProfile {
+    pub build: Option<ConfigOptsBuild>,
+    pub watch: Option<ConfigOptsWatch>,
+    pub serve: Option<ConfigOptsServe>,
+}
+
+/// The parts of a `Cargo.toml` manifest with the `[package.metadata.trunk]` &
+/// `[workspace.metadata.trunk]` config.
+#[derive(Deserialize)]
+struct CargoManifest {
+    package: Option<CargoSection>,
+    workspace: Option<CargoSection>,
+}
+
+/// A `[package]` or `[workspace]` section of a `Cargo.toml` manifest.
+#[derive(Deserialize)]
+struct CargoSection {
+    metadata: Option<CargoMetadata>,
+}
+
+#[derive(Deserialize)]
+struct CargoMetadata {
+    trunk: Option<ConfigOpts>,
 }
 
 impl ConfigOpts {
     /// Extract the runtime config for the build system based on all config layers.
     pub fn rtc_build(cli_build: ConfigOptsBuild, config: Option<PathBuf>) -> Result<Arc<RtcBuild>> {
-        let base_layer = Self::file_and_env_layers(config)?;
+        let base_layer = Self::file_and_env_layers(config, Some(&cli_build))?;
         let build_layer = Self::cli_opts_layer_build(cli_build, base_layer);
         let build_opts = build_layer.build.unwrap_or_default();
         let tools_opts = build_layer.tools.unwrap_or_default();
         let hooks_opts = build_layer.hooks.unwrap_or_default();
         Ok(Arc::new(RtcBuild::new(
-            build_opts, tools_opts, hooks_opts, false,
+            build_opts, tools_opts, hooks_opts, None,
         )?))
     }
 
@@ -246,15 +998,17 @@ impl ConfigOpts {
         cli_watch: ConfigOptsWatch,
         config: Option<PathBuf>,
     ) -> Result<Arc<RtcWatch>> {
-        let base_layer = Self::file_and_env_layers(config)?;
+        let config_dir = Self::config_dir(config.as_deref());
+        let base_layer = Self::file_and_env_layers(config, Some(&cli_build))?;
         let build_layer = Self::cli_opts_layer_build(cli_build, base_layer);
-        let watch_layer = Self::cli_opts_layer_watch(cli_watch, build_layer);
+        let mut watch_layer = Self::cli_opts_layer_watch(cli_watch, build_layer);
+        watch_layer.resolve_ignore_globs(&config_dir);
         let build_opts = watch_layer.build.unwrap_or_default();
         let watch_opts = watch_layer.watch.unwrap_or_default();
         let tools_opts = watch_layer.tools.unwrap_or_default();
         let hooks_opts = watch_layer.hooks.unwrap_or_default();
         Ok(Arc::new(RtcWatch::new(
-            build_opts, watch_opts, tools_opts, hooks_opts, false,
+            build_opts, watch_opts, tools_opts, hooks_opts, None,
         )?))
     }
 
@@ -265,10 +1019,7 @@ impl ConfigOpts {
         cli_serve: ConfigOptsServe,
         config: Option<PathBuf>,
     ) -> Result<Arc<RtcServe>> {
-        let base_layer = Self::file_and_env_layers(config)?;
-        let build_layer = Self::cli_opts_layer_build(cli_build, base_layer);
-        let watch_layer = Self::cli_opts_layer_watch(cli_watch, build_layer);
-        let serve_layer = Self::cli_opts_layer_serve(cli_serve, watch_layer);
+        let serve_layer = Self::effective(cli_build, cli_watch, cli_serve, config)?;
         let build_opts = serve_layer.build.unwrap_or_default();
         let watch_opts = serve_layer.watch.unwrap_or_default();
         let serve_opts = serve_layer.serve.unwrap_or_default();
@@ -287,42 +1038,147 @@ impl ConfigOpts {
         ))
     }
 
+    /// Extract the runtime config for the deploy system based on all config layers.
+    pub fn rtc_deploy(
+        cli_build: ConfigOptsBuild,
+        cli_to: Option<DeployTarget>,
+        config: Option<PathBuf>,
+    ) -> Result<Arc<RtcDeploy>> {
+        let base_layer = Self::file_and_env_layers(config, Some(&cli_build))?;
+        let build_layer = Self::cli_opts_layer_build(cli_build, base_layer);
+        let build_opts = build_layer.build.unwrap_or_default();
+        let tools_opts = build_layer.tools.unwrap_or_default();
+        let hooks_opts = build_layer.hooks.unwrap_or_default();
+        let build = Arc::new(RtcBuild::new(build_opts, tools_opts, hooks_opts, None)?);
+        let deploy_opts = build_layer.deploy.unwrap_or_default();
+        Ok(Arc::new(RtcDeploy::new(build, deploy_opts, cli_to)?))
+    }
+
     /// Extract the runtime config for the clean system based on all config layers.
     pub fn rtc_clean(cli_clean: ConfigOptsClean, config: Option<PathBuf>) -> Result<Arc<RtcClean>> {
-        let base_layer = Self::file_and_env_layers(config)?;
+        let base_layer = Self::file_and_env_layers(config, None)?;
         let clean_layer = Self::cli_opts_layer_clean(cli_clean, base_layer);
         let clean_opts = clean_layer.clean.unwrap_or_default();
         Ok(Arc::new(RtcClean::new(clean_opts)))
     }
 
+    /// Return the effective configuration of the serve system based on all config layers.
+    pub fn effective(
+        cli_build: ConfigOptsBuild,
+        cli_watch: ConfigOptsWatch,
+        cli_serve: ConfigOptsServe,
+        config: Option<PathBuf>,
+    ) -> Result<Self> {
+        let config_dir = Self::config_dir(config.as_deref());
+        let base_layer = Self::file_and_env_layers(config, Some(&cli_build))?;
+        let build_layer = Self::cli_opts_layer_build(cli_build, base_layer);
+        let mut watch_layer = Self::cli_opts_layer_watch(cli_watch, build_layer);
+        watch_layer.resolve_ignore_globs(&config_dir);
+        Ok(Self::cli_opts_layer_serve(cli_serve, watch_layer))
+    }
+
+    /// Get the dir of the given config file, which defaults to the `Trunk.toml` of the cwd.
+    fn config_dir(config: Option<&Path>) -> PathBuf {
+        let dir = match config.and_then(Path::parent) {
+            Some(parent) if !parent.as_os_str().is_empty() => parent,
+            _ => Path::new("."),
+        };
+        dir.canonicalize().unwrap_or_else(|_| dir.to_owned())
+    }
+
+    /// Resolve the relative ignore globs of the watch layer against the given config dir.
+    ///
+    /// The globs of config files are resolved against the dir of their file when reading them,
+    /// so this resolves the globs of the env vars & CLI.
+    fn resolve_ignore_globs(&mut self, config_dir: &Path) {
+        let globs = self
+            .watch
+            .iter_mut()
+            .flat_map(|watch| watch.ignore.iter_mut().flatten())
+            .filter(|path| is_glob(path) && path.is_relative());
+        for glob in globs {
+            *glob = config_dir.join(&glob);
+        }
+    }
+
     /// Return the full configuration based on config file & environment variables.
     pub fn full(config: Option<PathBuf>) -> Result<Self> {
-        Self::file_and_env_layers(config)
+        Self::file_and_env_layers(config, None)
+    }
+
+    /// Return the sorted names of the `[profile.<name>]` sections of the config files.
+    pub fn profile_names(config: Option<PathBuf>) -> Result<Vec<String>> {
+        let cfg = Self::from_file(config)?;
+        let mut names: Vec<_> = cfg
+            .profile
+            .into_iter()
+            .flat_map(HashMap::into_keys)
+            .collect();
+        names.sort();
+        Ok(names)
     }
 
     fn cli_opts_layer_build(cli: ConfigOptsBuild, cfg_base: Self) -> Self {
         let opts = ConfigOptsBuild {
             target: cli.target,
+            targets: cli.targets,
             release: cli.release,
+            profile: cli.profile,
             dist: cli.dist,
             public_url: cli.public_url,
             no_default_features: cli.no_default_features,
             all_features: cli.all_features,
             features: cli.features,
+            cargo_profile: cli.cargo_profile,
+            locked: cli.locked,
+            offline: cli.offline,
+            frozen: cli.frozen,
+            jobs: cli.jobs,
+            cargo_target_dir: cli.cargo_target_dir,
+            cache_dir: cli.cache_dir,
+            rustflags: cli.rustflags,
+            cargo_env: cli.cargo_env,
             filehash: cli.filehash,
+            filehash_length: cli.filehash_length,
+            filehash_layout: cli.filehash_layout,
+            unhashed_names: cli.unhashed_names,
+            copy_strategy: cli.copy_strategy,
+            sri: cli.sri,
+            csp: cli.csp,
+            minify: cli.minify,
+            postcss: cli.postcss,
+            bundle: cli.bundle,
+            service_worker: cli.service_worker,
+            asset_manifest: cli.asset_manifest,
+            reproducible: cli.reproducible,
+            no_prune: cli.no_prune,
+            precompress: cli.precompress,
+            precompress_level: cli.precompress_level,
+            size_report: cli.size_report,
+            size_report_file: cli.size_report_file,
+            timings: cli.timings,
+            wasm_opt: cli.wasm_opt,
+            bindgen_args: cli.bindgen_args,
             inject_scripts: cli.inject_scripts,
             pattern_script: cli.pattern_script,
             pattern_preload: cli.pattern_preload,
             pattern_params: cli.pattern_params,
+            sass: cli.sass,
+            import_map: cli.import_map,
+            service_worker_exclude: cli.service_worker_exclude,
+            env: cli.env,
+            i18n: cli.i18n,
         };
         let cfg_build = ConfigOpts {
             build: Some(opts),
             watch: None,
             serve: None,
             clean: None,
+            deploy: None,
             tools: None,
             proxy: None,
             hooks: None,
+            profile: None,
         };
         Self::merge(cfg_base, cfg_build)
     }
@@ -331,15 +1187,23 @@ impl ConfigOpts {
         let opts = ConfigOptsWatch {
             watch: cli.watch,
             ignore: cli.ignore,
+            gitignore: cli.gitignore,
+            poll: cli.poll,
+            debounce: cli.debounce,
+            clear_screen: cli.clear_screen,
+            notifications: cli.notifications,
+            exec: cli.exec,
         };
         let cfg = ConfigOpts {
             build: None,
             watch: Some(opts),
             serve: None,
             clean: None,
+            deploy: None,
             tools: None,
             proxy: None,
             hooks: None,
+            profile: None,
         };
         Self::merge(cfg_base, cfg)
     }
@@ -349,22 +1213,42 @@ impl ConfigOpts {
             address: cli.address,
             port: cli.port,
             open: cli.open,
+            qr: cli.qr,
+            mdns: cli.mdns,
+            mdns_name: cli.mdns_name,
             proxy_backend: cli.proxy_backend,
             proxy_rewrite: cli.proxy_rewrite,
             proxy_insecure: cli.proxy_insecure,
+            proxy_http2: cli.proxy_http2,
+            proxy_log: cli.proxy_log,
             proxy_ws: cli.proxy_ws,
             no_autoreload: cli.no_autoreload,
+            ws_path: cli.ws_path,
+            ws_ping_interval: cli.ws_ping_interval,
+            tls: cli.tls,
             tls_key_path: cli.tls_key_path,
             tls_cert_path: cli.tls_cert_path,
+            tls_redirect_port: cli.tls_redirect_port,
+            username: cli.username,
+            password: cli.password,
+            password_file: cli.password_file,
+            build_token: cli.build_token,
+            compression: cli.compression,
+            cross_origin_isolation: cli.cross_origin_isolation,
+            headers: cli.headers,
+            mime_types: cli.mime_types,
+            static_dirs: cli.static_dirs,
         };
         let cfg = ConfigOpts {
             build: None,
             watch: None,
             serve: Some(opts),
             clean: None,
+            deploy: None,
             tools: None,
             proxy: None,
             hooks: None,
+            profile: None,
         };
         Self::merge(cfg_base, cfg)
     }
@@ -379,134 +1263,339 @@ impl ConfigOpts {
             watch: None,
             serve: None,
             clean: Some(opts),
+            deploy: None,
             tools: None,
             proxy: None,
             hooks: None,
+            profile: None,
         };
         Self::merge(cfg_base, cfg)
     }
 
-    fn file_and_env_layers(path: Option<PathBuf>) -> Result<Self> {
-        let toml_cfg = Self::from_file(path)?;
+    /// Read the config file & env var layers, overlaying the base config of the file with its
+    /// profile selected by `--profile`, or else the `release` or `dev` profile, if defined.
+    fn file_and_env_layers(path: Option<PathBuf>, cli: Option<&ConfigOptsBuild>) -> Result<Self> {
+        let mut toml_cfg = Self::from_file(path)?;
         let env_cfg = Self::from_env().context("error reading trunk env var config")?;
-        let cfg = Self::merge(toml_cfg, env_cfg);
+        let layers = [cli, env_cfg.build.as_ref(), toml_cfg.build.as_ref()];
+        let selected = layers
+            .iter()
+            .flatten()
+            .find_map(|build| build.profile.clone());
+        let release = layers.iter().flatten().any(|build| build.release);
+        let name = selected
+            .clone()
+            .unwrap_or_else(|| if release { "release" } else { "dev" }.into());
+        let profile = toml_cfg
+            .profile
+            .take()
+            .and_then(|mut profiles| profiles.remove(&name));
+        let applied = profile.is_some();
+        if let Some(profile) = profile {
+            let profile_cfg = ConfigOpts {
+                build: profile.build,
+                watch: profile.watch,
+                serve: profile.serve,
+                clean: None,
+                deploy: None,
+                tools: None,
+                proxy: None,
+                hooks: None,
+                profile: None,
+            };
+            toml_cfg = Self::merge(toml_cfg, profile_cfg);
+        } else if let Some(selected) = selected {
+            bail!(
+                "unknown profile {:?}, which has no [profile.{}] section in the Trunk config file",
+                selected,
+                selected
+            );
+        }
+        let mut cfg = Self::merge(toml_cfg, env_cfg);
+        let build = cfg.build.get_or_insert_with(Default::default);
+        // The applied profile is kept for the hooks, and is none if there is no section of it.
+        build.profile = applied.then_some(name.clone());
+        // Selecting the release profile builds in release mode, like `--release`.
+        if name == "release" {
+            build.release = true;
+        }
         Ok(cfg)
     }
 
-    /// Read runtime config from a `Trunk.toml` file at the target path.
+    /// Read runtime config from a `Trunk.toml` file at the target path, layered over the
+    /// `[package.metadata.trunk]` section of the `Cargo.toml` manifest in the same dir, and over
+    /// the config of the cargo workspace root above it, if any.
     ///
-    /// NOTE WELL: any paths specified in a Trunk.toml file must be interpreted as being relative
-    /// to the file itself.
+    /// If the target path is a `Cargo.toml` manifest, only its metadata section is read.
     fn from_file(path: Option<PathBuf>) -> Result<Self> {
-        let mut trunk_toml_path = path.unwrap_or_else(|| "Trunk.toml".into());
-        if !trunk_toml_path.exists() {
+        let path = path.unwrap_or_else(|| "Trunk.toml".into());
+        let dir = match path.parent() {
+            Some(parent) if !parent.as_os_str().is_empty() => parent.to_owned(),
+            _ => PathBuf::from("."),
+        };
+        let workspace_cfg = Self::from_workspace(&dir)?;
+        if path.file_name().is_some_and(|name| name == "Cargo.toml") {
+            let manifest_cfg = Self::read_file(path, Self::from_manifest)?;
+            return Ok(Self::merge(workspace_cfg, manifest_cfg));
+        }
+        let manifest_cfg = Self::read_file(path.with_file_name("Cargo.toml"), Self::from_manifest)?;
+        let toml_cfg = Self::read_file(path, Self::from_toml)?;
+        let cfg = Self::merge(manifest_cfg, toml_cfg);
+        Ok(Self::merge(workspace_cfg, cfg))
+    }
+
+    /// Read the shared config of the cargo workspace root above the given dir from its
+    /// `Trunk.toml`, layered over the `[workspace.metadata.trunk]` section of its manifest.
+    fn from_workspace(dir: &Path) -> Result<Self> {
+        let Ok(dir) = dir.canonicalize() else {
+            return Ok(Default::default());
+        };
+        for root in dir.ancestors().skip(1) {
+            let manifest = root.join("Cargo.toml");
+            if !manifest.is_file() {
+                continue;
+            }
+            let cfg_bytes = std::fs::read_to_string(&manifest)
+                .with_context(|| format!("error reading cargo manifest {:?}", manifest))?;
+            let root_manifest: CargoManifest = toml::from_str(&cfg_bytes).with_context(|| {
+                format!("error reading [workspace.metadata.trunk] of {:?}", manifest)
+            })?;
+            let Some(workspace) = root_manifest.workspace else {
+                continue;
+            };
+            let mut manifest_cfg = workspace
+                .metadata
+                .and_then(|metadata| metadata.trunk)
+                .unwrap_or_default();
+            manifest_cfg.resolve_all_paths(root, &manifest)?;
+            let toml_cfg = Self::read_file(root.join("Trunk.toml"), Self::from_toml)?;
+            return Ok(Self::merge(manifest_cfg, toml_cfg));
+        }
+        Ok(Default::default())
+    }
+
+    /// Read the contents of a `Trunk.toml` file.
+    fn from_toml(cfg_bytes: &str) -> Result<Option<Self>> {
+        let cfg =
+            toml::from_str(cfg_bytes).context("error reading config file contents as TOML data")?;
+        Ok(Some(cfg))
+    }
+
+    /// Read the `[package.metadata.trunk]` section of the contents of a `Cargo.toml` manifest.
+    fn from_manifest(cfg_bytes: &str) -> Result<Option<Self>> {
+        let manifest: CargoManifest = toml::from_str(cfg_bytes)
+            .context("error reading [package.metadata.trunk] of the Cargo.toml manifest")?;
+        Ok(manifest
+            .package
+            .and_then(|package| package.metadata)
+            .and_then(|metadata| metadata.trunk))
+    }
+
+    /// Read runtime config from the file at the target path with the given parser.
+    ///
+    /// NOTE WELL: any paths specified in a config file must be interpreted as being relative
+    /// to the file itself.
+    fn read_file(
+        mut path: PathBuf,
+        parse: impl FnOnce(&str) -> Result<Option<Self>>,
+    ) -> Result<Self> {
+        if !path.exists() {
             return Ok(Default::default());
         }
-        if !trunk_toml_path.is_absolute() {
-            trunk_toml_path = trunk_toml_path.canonicalize().with_context(|| {
+        if !path.is_absolute() {
+            path = path.canonicalize().with_context(|| {
                 format!(
                     "error getting canonical path to Trunk config file {:?}",
-                    &trunk_toml_path
+                    &path
                 )
             })?;
         }
-        let cfg_bytes =
-            std::fs::read_to_string(&trunk_toml_path).context("error reading config file")?;
-        let mut cfg: Self = toml::from_str(&cfg_bytes)
-            .context("error reading config file contents as TOML data")?;
-        if let Some(parent) = trunk_toml_path.parent() {
-            if let Some(build) = cfg.build.as_mut() {
-                if let Some(target) = build.target.as_mut() {
-                    if !target.is_absolute() {
-                        *target =
-                            std::fs::canonicalize(parent.join(&target)).with_context(|| {
-                                format!(
-                                    "error taking canonical path to [build].target {:?} in {:?}",
-                                    target, trunk_toml_path
-                                )
-                            })?;
-                    }
+        let cfg_bytes = std::fs::read_to_string(&path).context("error reading config file")?;
+        let Some(mut cfg) = parse(&cfg_bytes)? else {
+            return Ok(Default::default());
+        };
+        if let Some(parent) = path.parent() {
+            cfg.resolve_all_paths(parent, &path)?;
+        }
+        Ok(cfg)
+    }
+
+    /// Resolve the relative paths of the config & its profiles, which are relative to the given
+    /// dir of the config file at the given path.
+    fn resolve_all_paths(&mut self, parent: &Path, path: &Path) -> Result<()> {
+        self.resolve_paths(parent, path)?;
+        for (name, profile) in self.profile.iter_mut().flatten() {
+            let mut profile_cfg = ConfigOpts {
+                build: profile.build.take(),
+                watch: profile.watch.take(),
+                serve: profile.serve.take(),
+                clean: None,
+                deploy: None,
+                tools: None,
+                proxy: None,
+                hooks: None,
+                profile: None,
+            };
+            profile_cfg
+                .resolve_paths(parent, path)
+                .with_context(|| format!("error reading [profile.{}]", name))?;
+            profile.build = profile_cfg.build;
+            profile.watch = profile_cfg.watch;
+            profile.serve = profile_cfg.serve;
+        }
+        Ok(())
+    }
+
+    /// Resolve the relative paths of the config, which are relative to the given dir of the
+    /// config file at the given path.
+    fn resolve_paths(&mut self, parent: &Path, trunk_toml_path: &Path) -> Result<()> {
+        if let Some(build) = self.build.as_mut() {
+            if let Some(target) = build.target.as_mut() {
+                if !target.is_absolute() {
+                    *target = std::fs::canonicalize(parent.join(&target)).with_context(|| {
+                        format!(
+                            "error taking canonical path to [build].target {:?} in {:?}",
+                            target, trunk_toml_path
+                        )
+                    })?;
                 }
-                if let Some(dist) = build.dist.as_mut() {
-                    if !dist.is_absolute() {
-                        *dist = parent.join(&dist);
-                    }
+            }
+            for target in build.targets.iter_mut().flatten() {
+                if !target.is_absolute() {
+                    *target = parent.join(&target);
                 }
             }
-            if let Some(serve) = cfg.serve.as_mut() {
-                if let Some(tls_key_path) = serve.tls_key_path.as_mut() {
-                    if !tls_key_path.is_absolute() {
-                        *tls_key_path =
-                            std::fs::canonicalize(parent.join(&tls_key_path)).with_context(|| {
-                                format!(
-                                    "error taking canonical path to [serve].tls_key_path {:?} in {:?}",
-                                    tls_key_path, trunk_toml_path
-                                )
-                            })?;
-                    }
+            if let Some(dist) = build.dist.as_mut() {
+                if !dist.is_absolute() {
+                    *dist = parent.join(&dist);
                 }
-                if let Some(tls_cert_path) = serve.tls_cert_path.as_mut() {
-                    if !tls_cert_path.is_absolute() {
-                        *tls_cert_path =
-                            std::fs::canonicalize(parent.join(&tls_cert_path)).with_context(|| {
-                                format!(
-                                    "error taking canonical path to [serve].tls_cert_path {:?} in {:?}",
-                                    tls_cert_path, trunk_toml_path
-                                )
-                            })?;
-                    }
+            }
+            if let Some(file) = build.size_report_file.as_mut() {
+                if !file.is_absolute() {
+                    *file = parent.join(&file);
+                }
+            }
+            if let Some(dir) = build.cargo_target_dir.as_mut() {
+                if !dir.is_absolute() {
+                    *dir = parent.join(&dir);
                 }
             }
-            if let Some(watch) = cfg.watch.as_mut() {
-                if let Some(watch_paths) = watch.watch.as_mut() {
-                    for path in watch_paths.iter_mut() {
-                        if !path.is_absolute() {
-                            *path =
-                                std::fs::canonicalize(parent.join(&path)).with_context(|| {
-                                    format!(
-                                        "error taking canonical path to [watch].watch {:?} in {:?}",
-                                        path, trunk_toml_path
-                                    )
-                                })?;
-                        }
+            if let Some(dir) = build.cache_dir.as_mut() {
+                if !dir.is_absolute() {
+                    *dir = parent.join(&dir);
+                }
+            }
+            if let Some(sass) = build.sass.as_mut() {
+                for load_path in sass.load_paths.iter_mut() {
+                    if !load_path.is_absolute() {
+                        *load_path = parent.join(&load_path);
                     }
                 }
-                if let Some(ignore_paths) = watch.ignore.as_mut() {
-                    for path in ignore_paths.iter_mut() {
-                        if !path.is_absolute() {
-                            *path =
-                                std::fs::canonicalize(parent.join(&path)).with_context(|| {
-                                    format!(
-                                        "error taking canonical path to [watch].ignore {:?} in \
-                                         {:?}",
-                                        path, trunk_toml_path
-                                    )
-                                })?;
-                        }
+            }
+        }
+        if let Some(serve) = self.serve.as_mut() {
+            if let Some(tls_key_path) = serve.tls_key_path.as_mut() {
+                if !tls_key_path.is_absolute() {
+                    *tls_key_path = std::fs::canonicalize(parent.join(&tls_key_path))
+                        .with_context(|| {
+                            format!(
+                                "error taking canonical path to [serve].tls_key_path {:?} in {:?}",
+                                tls_key_path, trunk_toml_path
+                            )
+                        })?;
+                }
+            }
+            if let Some(tls_cert_path) = serve.tls_cert_path.as_mut() {
+                if !tls_cert_path.is_absolute() {
+                    *tls_cert_path = std::fs::canonicalize(parent.join(&tls_cert_path))
+                        .with_context(|| {
+                            format!(
+                                "error taking canonical path to [serve].tls_cert_path {:?} in {:?}",
+                                tls_cert_path, trunk_toml_path
+                            )
+                        })?;
+                }
+            }
+            if let Some(password_file) = serve.password_file.as_mut() {
+                if !password_file.is_absolute() {
+                    *password_file = parent.join(&password_file);
+                }
+            }
+            for static_dir in serve.static_dirs.iter_mut() {
+                if !static_dir.dir.is_absolute() {
+                    static_dir.dir = std::fs::canonicalize(parent.join(&static_dir.dir))
+                        .with_context(|| {
+                            format!(
+                                "error taking canonical path to [[serve.static]].dir {:?} in {:?}",
+                                static_dir.dir, trunk_toml_path
+                            )
+                        })?;
+                }
+            }
+        }
+        if let Some(watch) = self.watch.as_mut() {
+            if let Some(watch_paths) = watch.watch.as_mut() {
+                for path in watch_paths.iter_mut() {
+                    if !path.is_absolute() {
+                        *path = std::fs::canonicalize(parent.join(&path)).with_context(|| {
+                            format!(
+                                "error taking canonical path to [watch].watch {:?} in {:?}",
+                                path, trunk_toml_path
+                            )
+                        })?;
                     }
                 }
             }
-            if let Some(clean) = cfg.clean.as_mut() {
-                if let Some(dist) = clean.dist.as_mut() {
-                    if !dist.is_absolute() {
-                        *dist = parent.join(&dist);
+            if let Some(ignore_paths) = watch.ignore.as_mut() {
+                for path in ignore_paths.iter_mut() {
+                    if is_glob(path) {
+                        *path = parent.join(&path);
+                    } else if !path.is_absolute() {
+                        *path = std::fs::canonicalize(parent.join(&path)).with_context(|| {
+                            format!(
+                                "error taking canonical path to [watch].ignore {:?} in {:?}",
+                                path, trunk_toml_path
+                            )
+                        })?;
                     }
                 }
             }
         }
-        Ok(cfg)
+        for proxy in self.proxy.iter_mut().flatten() {
+            if let Some(mock_dir) = proxy.mock_dir.as_mut() {
+                if !mock_dir.is_absolute() {
+                    *mock_dir =
+                        std::fs::canonicalize(parent.join(&mock_dir)).with_context(|| {
+                            format!(
+                                "error taking canonical path to [[proxy]].mock_dir {:?} in {:?}",
+                                mock_dir, trunk_toml_path
+                            )
+                        })?;
+                }
+            }
+        }
+        if let Some(mirror) = self.tools.as_mut().and_then(|tools| tools.mirror.as_mut()) {
+            let is_url = mirror.contains("://");
+            if !is_url && !Path::new(mirror.as_str()).is_absolute() {
+                *mirror = parent.join(&mirror).display().to_string();
+            }
+        }
+        if let Some(clean) = self.clean.as_mut() {
+            if let Some(dist) = clean.dist.as_mut() {
+                if !dist.is_absolute() {
+                    *dist = parent.join(&dist);
+                }
+            }
+        }
+        Ok(())
     }
 
     fn from_env() -> Result<Self> {
-        Ok(ConfigOpts {
-            build: Some(envy::prefixed("TRUNK_BUILD_").from_env()?),
-            watch: Some(envy::prefixed("TRUNK_WATCH_").from_env()?),
-            serve: Some(envy::prefixed("TRUNK_SERVE_").from_env()?),
-            clean: Some(envy::prefixed("TRUNK_CLEAN_").from_env()?),
-            tools: Some(envy::prefixed("TRUNK_TOOLS_").from_env()?),
-            proxy: None,
-            hooks: None,
-        })
+        // NOTE: profiles are only defined in `Trunk.toml`, and `TRUNK_PROFILE` is set for hooks.
+        let vars = std::env::vars_os()
+            .filter_map(|(name, value)| Some((name.into_string().ok()?, value.into_string().ok()?)))
+            .filter(|(name, _)| name != "TRUNK_PROFILE" && !name.starts_with("TRUNK_PROFILE_"));
+        Ok(env::from_vars(vars)?)
     }
 
     /// Merge the given layers, where the `greater` layer takes precedence.
@@ -516,9 +1605,47 @@ impl ConfigOpts {
             (Some(val), None) | (None, Some(val)) => Some(val),
             (Some(l), Some(mut g)) => {
                 g.target = g.target.or(l.target);
+                g.targets = g.targets.or(l.targets);
+                g.profile = g.profile.or(l.profile);
                 g.dist = g.dist.or(l.dist);
                 g.public_url = g.public_url.or(l.public_url);
+                // NOTE: the cargo features are selected as a whole, so that e.g. `--features`
+                // replaces the `all_features` of the config file, instead of contradicting it.
+                if !g.all_features && !g.no_default_features && g.features.is_none() {
+                    g.all_features = l.all_features;
+                    g.no_default_features = l.no_default_features;
+                    g.features = l.features;
+                }
+                g.cargo_profile = g.cargo_profile.or(l.cargo_profile);
+                g.locked = g.locked || l.locked;
+                g.offline = g.offline || l.offline;
+                g.frozen = g.frozen || l.frozen;
+                g.jobs = g.jobs.or(l.jobs);
+                g.cargo_target_dir = g.cargo_target_dir.or(l.cargo_target_dir);
+                g.cache_dir = g.cache_dir.or(l.cache_dir);
+                g.rustflags = g.rustflags.or(l.rustflags);
+                g.cargo_env = g.cargo_env.or(l.cargo_env);
                 g.filehash = g.filehash.or(l.filehash);
+                g.filehash_length = g.filehash_length.or(l.filehash_length);
+                g.filehash_layout = g.filehash_layout.or(l.filehash_layout);
+                g.unhashed_names = g.unhashed_names.or(l.unhashed_names);
+                g.copy_strategy = g.copy_strategy.or(l.copy_strategy);
+                g.sri = g.sri.or(l.sri);
+                g.csp = g.csp.or(l.csp);
+                g.minify = g.minify.or(l.minify);
+                g.postcss = g.postcss.or(l.postcss);
+                g.bundle = g.bundle.or(l.bundle);
+                g.service_worker = g.service_worker.or(l.service_worker);
+                g.asset_manifest = g.asset_manifest.or(l.asset_manifest);
+                g.reproducible = g.reproducible.or(l.reproducible);
+                g.no_prune = g.no_prune || l.no_prune;
+                g.precompress = g.precompress.or(l.precompress);
+                g.precompress_level = g.precompress_level.or(l.precompress_level);
+                g.size_report = g.size_report.or(l.size_report);
+                g.size_report_file = g.size_report_file.or(l.size_report_file);
+                g.timings = g.timings.or(l.timings);
+                g.wasm_opt = g.wasm_opt.or(l.wasm_opt);
+                g.bindgen_args = g.bindgen_args.or(l.bindgen_args);
                 // NOTE: this can not be disabled in the cascade.
                 if l.release {
                     g.release = true;
@@ -527,6 +1654,11 @@ impl ConfigOpts {
                 g.pattern_preload = g.pattern_preload.or(l.pattern_preload);
                 g.pattern_script = g.pattern_script.or(l.pattern_script);
                 g.pattern_params = g.pattern_params.or(l.pattern_params);
+                g.sass = g.sass.or(l.sass);
+                g.import_map = g.import_map.or(l.import_map);
+                g.service_worker_exclude = g.service_worker_exclude.or(l.service_worker_exclude);
+                g.env = g.env.or(l.env);
+                g.i18n = g.i18n.or(l.i18n);
                 Some(g)
             }
         };
@@ -536,6 +1668,12 @@ impl ConfigOpts {
             (Some(l), Some(mut g)) => {
                 g.watch = g.watch.or(l.watch);
                 g.ignore = g.ignore.or(l.ignore);
+                g.gitignore = g.gitignore || l.gitignore;
+                g.poll = g.poll.or(l.poll);
+                g.debounce = g.debounce.or(l.debounce);
+                g.clear_screen = g.clear_screen || l.clear_screen;
+                g.notifications = g.notifications || l.notifications;
+                g.exec = g.exec.or(l.exec);
                 Some(g)
             }
         };
@@ -548,8 +1686,33 @@ impl ConfigOpts {
                 g.address = g.address.or(l.address);
                 g.port = g.port.or(l.port);
                 g.proxy_ws = g.proxy_ws || l.proxy_ws;
+                g.proxy_http2 = g.proxy_http2 || l.proxy_http2;
+                g.proxy_log = g.proxy_log.or(l.proxy_log);
+                // NOTE: this can not be disabled in the cascade.
+                if l.tls {
+                    g.tls = true;
+                }
                 g.tls_key_path = g.tls_key_path.or(l.tls_key_path);
                 g.tls_cert_path = g.tls_cert_path.or(l.tls_cert_path);
+                g.tls_redirect_port = g.tls_redirect_port.or(l.tls_redirect_port);
+                g.ws_path = g.ws_path.or(l.ws_path);
+                g.mdns_name = g.mdns_name.or(l.mdns_name);
+                g.ws_ping_interval = g.ws_ping_interval.or(l.ws_ping_interval);
+                g.username = g.username.or(l.username);
+                g.password = g.password.or(l.password);
+                g.password_file = g.password_file.or(l.password_file);
+                g.build_token = g.build_token.or(l.build_token);
+                g.compression = g.compression.or(l.compression);
+                g.cross_origin_isolation = g.cross_origin_isolation.or(l.cross_origin_isolation);
+                if g.headers.is_empty() {
+                    g.headers = l.headers;
+                }
+                if g.mime_types.is_empty() {
+                    g.mime_types = l.mime_types;
+                }
+                if g.static_dirs.is_empty() {
+                    g.static_dirs = l.static_dirs;
+                }
                 // NOTE: this can not be disabled in the cascade.
                 if l.no_autoreload {
                     g.no_autoreload = true;
@@ -558,6 +1721,14 @@ impl ConfigOpts {
                 if l.open {
                     g.open = true;
                 }
+                // NOTE: this can not be disabled in the cascade.
+                if l.qr {
+                    g.qr = true;
+                }
+                // NOTE: this can not be disabled in the cascade.
+                if l.mdns {
+                    g.mdns = true;
+                }
                 Some(g)
             }
         };
@@ -565,9 +1736,15 @@ impl ConfigOpts {
             (None, None) => None,
             (Some(val), None) | (None, Some(val)) => Some(val),
             (Some(l), Some(mut g)) => {
+                g.esbuild = g.esbuild.or(l.esbuild);
                 g.sass = g.sass.or(l.sass);
                 g.wasm_bindgen = g.wasm_bindgen.or(l.wasm_bindgen);
                 g.wasm_opt = g.wasm_opt.or(l.wasm_opt);
+                g.tailwindcss = g.tailwindcss.or(l.tailwindcss);
+                g.checksums = g.checksums.or(l.checksums);
+                g.require_checksums = g.require_checksums.or(l.require_checksums);
+                g.mirror = g.mirror.or(l.mirror);
+                g.proxy = g.proxy.or(l.proxy);
                 Some(g)
             }
         };
@@ -583,6 +1760,18 @@ impl ConfigOpts {
                 Some(g)
             }
         };
+        greater.deploy = match (lesser.deploy.take(), greater.deploy.take()) {
+            (None, None) => None,
+            (Some(val), None) | (None, Some(val)) => Some(val),
+            (Some(l), Some(mut g)) => {
+                // NOTE: the options of a target are not merged. Only take the greater target.
+                g.to = g.to.or(l.to);
+                g.s3 = g.s3.or(l.s3);
+                g.github_pages = g.github_pages.or(l.github_pages);
+                g.netlify = g.netlify.or(l.netlify);
+                Some(g)
+            }
+        };
         greater.proxy = match (lesser.proxy.take(), greater.proxy.take()) {
             (None, None) => None,
             (Some(val), None) | (None, Some(val)) => Some(val),
@@ -593,6 +1782,15 @@ impl ConfigOpts {
             (Some(val), None) | (None, Some(val)) => Some(val),
             (Some(_), Some(g)) => Some(g), // No meshing/merging. Only take the greater value.
         };
+        greater.profile = match (lesser.profile.take(), greater.profile.take()) {
+            (None, None) => None,
+            (Some(val), None) | (None, Some(val)) => Some(val),
+            // Profiles of the same name are not merged. Only take the greater profile.
+            (Some(mut l), Some(g)) => {
+                l.extend(g);
+                Some(l)
+            }
+        };
         greater
     }
 }
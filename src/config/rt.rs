@@ -1,16 +1,38 @@
-use std::collections::HashMap;
-use std::net::{IpAddr, Ipv4Addr};
-use std::path::PathBuf;
+use std::collections::{BTreeMap, HashMap};
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr};
+use std::path::{Path, PathBuf};
 use std::sync::Arc;
+use std::time::Duration;
 
-use anyhow::{anyhow, ensure, Context, Result};
+use anyhow::{anyhow, bail, ensure, Context, Result};
+use axum::http::header::{HeaderMap, HeaderName, HeaderValue};
+use axum::http::uri::PathAndQuery;
 use axum::http::Uri;
 use axum_server::tls_rustls::RustlsConfig;
+use directories::ProjectDirs;
+use globset::{Glob, GlobSet, GlobSetBuilder};
+use ignore::gitignore::{Gitignore, GitignoreBuilder};
+use tokio::sync::{broadcast, Semaphore};
 
+use crate::build::BuildEvent;
 use crate::config::{
-    ConfigOptsBuild, ConfigOptsClean, ConfigOptsHook, ConfigOptsProxy, ConfigOptsServe,
-    ConfigOptsTools, ConfigOptsWatch,
+    CompressionAlgorithm, ConfigOptsBuild, ConfigOptsClean, ConfigOptsDeploy,
+    ConfigOptsDeployGitHubPages, ConfigOptsDeployNetlify, ConfigOptsDeployS3, ConfigOptsHook,
+    ConfigOptsProxy, ConfigOptsSass, ConfigOptsServe, ConfigOptsStatic, ConfigOptsTools,
+    ConfigOptsWatch, CopyStrategy, DeployTarget, FileHashLayout, ProxyLog, UnhashedNames,
 };
+use crate::hooks::{ChangedPaths, Hook};
+use crate::mdns;
+use crate::pipelines::{
+    AssetHashes, FontCharacters, OutputHashes, PipelineCache, RustAppOutputs, WasmOptLevel,
+};
+use crate::proxy::unix_socket_path;
+use crate::timings::{TimingGuard, Timings};
+
+/// The default duration for which file system events are collected before triggering a build.
+const DEFAULT_DEBOUNCE: Duration = Duration::from_millis(25);
+/// The default path of the autoreload WebSocket.
+const DEFAULT_WS_PATH: &str = "/_trunk/ws";
 
 /// Config options for the cargo build command
 #[derive(Clone, Debug)]
@@ -26,6 +48,226 @@ pub enum Features {
     },
 }
 
+impl Features {
+    /// Take the cargo features of the given build options.
+    pub fn from_opts(opts: &ConfigOptsBuild) -> Result<Self> {
+        // Highlander-rule: There can be only one (prohibits contradicting arguments):
+        ensure!(
+            !(opts.all_features && (opts.no_default_features || opts.features.is_some())),
+            "Cannot combine --all-features with --no-default-features and/or --features"
+        );
+
+        if opts.all_features {
+            Ok(Self::All)
+        } else {
+            Ok(Self::Custom {
+                features: opts.features.clone(),
+                no_default_features: opts.no_default_features,
+            })
+        }
+    }
+
+    /// The cargo args to select these features.
+    pub fn cargo_args(&self) -> Vec<&str> {
+        match self {
+            Self::All => vec!["--all-features"],
+            Self::Custom {
+                features,
+                no_default_features,
+            } => {
+                let mut args = Vec::new();
+                if *no_default_features {
+                    args.push("--no-default-features");
+                }
+                if let Some(features) = features {
+                    args.push("--features");
+                    args.push(features);
+                }
+                args
+            }
+        }
+    }
+}
+
+/// The cargo profile to build with.
+#[derive(Clone, Debug, Default)]
+pub struct CargoProfile {
+    /// The name of a custom cargo profile, or none for the `dev` & `release` profiles.
+    name: Option<String>,
+    /// Whether the `release` profile is used, unless a custom one is.
+    release: bool,
+}
+
+impl CargoProfile {
+    /// Take the cargo profile of the given build options.
+    pub fn from_opts(opts: &ConfigOptsBuild) -> Result<Self> {
+        if let Some(name) = &opts.cargo_profile {
+            ensure!(
+                !name.is_empty() && !name.contains(['/', '\\']),
+                "invalid cargo profile {:?}",
+                name
+            );
+        }
+        Ok(Self {
+            name: opts.cargo_profile.clone(),
+            release: opts.release,
+        })
+    }
+
+    /// The cargo args to select this profile.
+    pub fn cargo_args(&self) -> Vec<&str> {
+        match &self.name {
+            Some(name) => vec!["--profile", name],
+            None if self.release => vec!["--release"],
+            None => Vec::new(),
+        }
+    }
+
+    /// The name of the dir of this profile in the target dir, where cargo uses `debug` for the
+    /// `dev` profile.
+    pub fn dir_name(&self) -> &str {
+        match self.name.as_deref() {
+            Some("dev" | "test") => "debug",
+            Some("bench") => "release",
+            Some(name) => name,
+            None if self.release => "release",
+            None => "debug",
+        }
+    }
+}
+
+/// The cargo flags restricting the updates of the `Cargo.lock` & the dependencies.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct CargoFlags {
+    /// Use cargo's `--locked` flag.
+    locked: bool,
+    /// Use cargo's `--offline` flag.
+    offline: bool,
+    /// Use cargo's `--frozen` flag.
+    frozen: bool,
+}
+
+impl CargoFlags {
+    /// Take the cargo flags of the given build options.
+    pub fn from_opts(opts: &ConfigOptsBuild) -> Self {
+        Self {
+            locked: opts.locked,
+            offline: opts.offline,
+            frozen: opts.frozen,
+        }
+    }
+
+    /// The cargo args of these flags.
+    pub fn cargo_args(&self) -> Vec<&'static str> {
+        [
+            (self.locked, "--locked"),
+            (self.offline, "--offline"),
+            (self.frozen, "--frozen"),
+        ]
+        .into_iter()
+        .filter_map(|(set, arg)| set.then_some(arg))
+        .collect()
+    }
+}
+
+/// The env vars of the cargo invocations.
+#[derive(Clone, Debug, Default)]
+pub struct CargoEnv {
+    vars: BTreeMap<String, String>,
+}
+
+impl CargoEnv {
+    /// Take the cargo env vars of the given build options, with the `CARGO_TARGET_DIR` of the
+    /// `cargo_target_dir`, the `CARGO_BUILD_JOBS` of the `jobs`, and the `rustflags` appended to
+    /// the `RUSTFLAGS` of the env vars, or of the environment.
+    ///
+    /// Reproducible builds remap the given project dir & the cargo home dir in the paths of the
+    /// Rust apps, and disable incremental compilation.
+    pub fn from_opts(opts: &ConfigOptsBuild, project_dir: &Path) -> Result<Self> {
+        let mut vars = opts.cargo_env.clone().unwrap_or_default();
+        if let Some(name) = vars
+            .keys()
+            .find(|name| name.is_empty() || name.contains(['=', '\0']))
+        {
+            bail!("invalid name {:?} of a cargo env var", name);
+        }
+        if let Some(dir) = &opts.cargo_target_dir {
+            let dir = dir
+                .to_str()
+                .with_context(|| format!("cargo target dir {:?} is not valid UTF-8", dir))?;
+            vars.insert("CARGO_TARGET_DIR".into(), dir.into());
+        }
+        if let Some(jobs) = opts.jobs {
+            ensure!(jobs > 0, "[build].jobs must be at least 1");
+            vars.insert("CARGO_BUILD_JOBS".into(), jobs.to_string());
+        }
+        let mut rustflags = opts.rustflags.clone();
+        if opts.reproducible.unwrap_or(false) {
+            let mut remaps = vec![(project_dir.to_path_buf(), "/src")];
+            if let Some(cargo_home) = cargo_home_dir() {
+                remaps.push((cargo_home, "/cargo"));
+            }
+            // The last matching prefix is remapped, so the longer, more specific, ones go last.
+            remaps.sort_by_key(|(dir, _)| dir.as_os_str().len());
+            let mut flags = remaps
+                .into_iter()
+                .map(|(dir, to)| match dir.to_str() {
+                    // NOTE: cargo splits the `RUSTFLAGS` by whitespace.
+                    Some(dir) if !dir.contains(char::is_whitespace) => {
+                        Ok(format!("--remap-path-prefix={}={}", dir, to))
+                    }
+                    _ => bail!(
+                        "dir {:?} of a reproducible build must be valid UTF-8 without whitespace",
+                        dir
+                    ),
+                })
+                .collect::<Result<Vec<_>>>()?;
+            flags.extend(rustflags.take());
+            rustflags = Some(flags.join(" "));
+            vars.entry("CARGO_INCREMENTAL".into())
+                .or_insert_with(|| "0".into());
+        }
+        if let Some(rustflags) = &rustflags {
+            let base = match vars.get("RUSTFLAGS") {
+                Some(base) => Some(base.clone()),
+                None => std::env::var("RUSTFLAGS").ok(),
+            };
+            let rustflags = match base.filter(|base| !base.trim().is_empty()) {
+                Some(base) => format!("{} {}", base.trim(), rustflags.trim()),
+                None => rustflags.trim().to_owned(),
+            };
+            vars.insert("RUSTFLAGS".into(), rustflags);
+        }
+        Ok(Self { vars })
+    }
+
+    /// The env vars, by their names.
+    pub fn vars(&self) -> &BTreeMap<String, String> {
+        &self.vars
+    }
+}
+
+/// The cargo home dir, of the `CARGO_HOME` env var or in the home dir of the user.
+fn cargo_home_dir() -> Option<PathBuf> {
+    std::env::var_os("CARGO_HOME")
+        .map(PathBuf::from)
+        .or_else(|| directories::BaseDirs::new().map(|dirs| dirs.home_dir().join(".cargo")))
+}
+
+/// The `CARGO_HTTP_PROXY` env var letting cargo fetch the dependencies through the proxy of the
+/// tools, unless the env var is set already.
+///
+/// The proxy is passed by env var rather than by arg, as the args of cargo are logged, while the
+/// proxy URL may hold credentials.
+pub fn cargo_proxy_env(tools: &ConfigOptsTools) -> Option<(&'static str, &str)> {
+    const VAR: &str = "CARGO_HTTP_PROXY";
+    tools
+        .proxy
+        .as_deref()
+        .filter(|_| std::env::var_os(VAR).is_none())
+        .map(|proxy| (VAR, proxy))
+}
+
 /// Runtime config for the build system.
 #[derive(Clone, Debug)]
 pub struct RtcBuild {
@@ -33,30 +275,116 @@ pub struct RtcBuild {
     pub target: PathBuf,
     /// The parent directory of the target index HTML file.
     pub target_parent: PathBuf,
+    /// The canonical paths of the other source HTML files of a multi-page app, in
+    /// `target_parent` or its subdirs.
+    pub pages: Vec<PathBuf>,
     /// Build in release mode.
     pub release: bool,
+    /// The name of the `[profile.<name>]` section the config is overlaid with, if any.
+    pub profile: Option<String>,
     /// The public URL from which assets are to be served.
     pub public_url: String,
     /// If `true`, then files being processed should be hashed and the hash should be
     /// appeneded to the file's name.
     pub filehash: bool,
+    /// The number of hex digits of the hashes in file names, if they are to be shortened.
+    pub filehash_length: Option<usize>,
+    /// The layout of hashed file names.
+    pub filehash_layout: FileHashLayout,
+    /// The kind of files with the names of the hashed outputs without their hashes, if any.
+    pub unhashed_names: Option<UnhashedNames>,
+    /// How the files of `copy-file` & `copy-dir` assets are copied to the dist dir.
+    pub copy_strategy: CopyStrategy,
+    /// If `true`, then the emitted script & link tags get `integrity` attributes with the
+    /// SHA-384 hashes of the files they reference.
+    pub sri: bool,
+    /// If `true`, then the hashes of the inline scripts & styles of the output HTML are added to
+    /// its Content-Security-Policy meta tag, and written to `csp.json`.
+    pub csp: bool,
+    /// If `true`, then the output HTML, the JS of scripts & Rust apps, and the CSS of stylesheets
+    /// are minified.
+    pub minify: bool,
+    /// If `true`, then the output of all CSS pipelines is processed with PostCSS.
+    pub postcss: bool,
+    /// If `true`, then all JS & TypeScript assets are bundled with their imports.
+    pub bundle: bool,
+    /// If `true`, then a service worker precaching all build artifacts is generated & registered.
+    pub service_worker: bool,
+    /// The globs of the paths of the outputs relative to the dist dir, which the service worker
+    /// does not precache.
+    pub service_worker_exclude: GlobSet,
+    /// If `true`, then the `asset-manifest.json` of the outputs is written to the dist dir.
+    pub asset_manifest: bool,
+    /// If `true`, then the outputs are built reproducibly, and their modification times are set to
+    /// `SOURCE_DATE_EPOCH`.
+    pub reproducible: bool,
+    /// If `true`, then the files of the dist dir which the build did not output are removed.
+    pub prune: bool,
+    /// The compression algorithms to write precompressed siblings of the wasm, js & css outputs
+    /// with, which is only done in release mode.
+    pub precompress: Vec<CompressionAlgorithm>,
+    /// The level to precompress outputs with, defaulting to the highest level of each algorithm.
+    pub precompress_level: Option<u32>,
+    /// If `true`, then a report of the sizes of the outputs is printed after the build.
+    pub size_report: bool,
+    /// A `.json` or `.html` file to write the size report to as well.
+    pub size_report_file: Option<PathBuf>,
+    /// The recorder of the timings of the stages of builds, if enabled.
+    pub timings: Option<Arc<Timings>>,
+    /// The permits of the asset pipelines which may run concurrently, if limited.
+    pub pipeline_permits: Option<Arc<Semaphore>>,
+    /// The outputs of the Rust apps built by the current build.
+    pub rust_outputs: Arc<RustAppOutputs>,
+    /// The characters of the pages of the current build, which the subsets of their fonts keep.
+    pub font_characters: Arc<FontCharacters>,
+    /// The content hashes of the outputs written by the pipelines of the current build.
+    pub output_hashes: Arc<OutputHashes>,
+    /// The content hashes of the assets of the last successful build.
+    pub asset_hashes: Arc<AssetHashes>,
+    /// The cached outputs of the expensive pipeline steps, which are reused by later builds.
+    pub pipeline_cache: Arc<PipelineCache>,
+    /// The channel of the events of the builds.
+    pub events: broadcast::Sender<BuildEvent>,
     /// The directory where final build artifacts are placed after a successful build.
     pub final_dist: PathBuf,
     /// The directory used to stage build artifacts during an active build.
     pub staging_dist: PathBuf,
     /// The configuration of the features passed to cargo.
     pub cargo_features: Features,
+    /// The cargo profile to build the Rust apps with.
+    pub cargo_profile: CargoProfile,
+    /// The flags of all cargo invocations for the Rust apps.
+    pub cargo_flags: CargoFlags,
+    /// The env vars of the cargo invocations for the Rust apps.
+    pub cargo_env: CargoEnv,
+    /// The wasm-opt level to optimize Rust apps with in release mode, unless set by the app.
+    pub wasm_opt: Option<WasmOptLevel>,
+    /// Additional arguments passed to wasm-bindgen for all Rust apps.
+    pub bindgen_args: Vec<String>,
     /// Configuration for automatic application download.
     pub tools: ConfigOptsTools,
     /// Build process hooks.
-    pub hooks: Vec<ConfigOptsHook>,
-    /// A bool indicating if the output HTML should have the WebSocket autoloader injected.
+    pub hooks: Vec<Hook>,
+    /// The changed paths which triggered the current build.
+    pub changes: Arc<ChangedPaths>,
+    /// The path of the autoreload WebSocket, if the output HTML should have the WebSocket
+    /// autoloader injected.
     ///
     /// This value is configured via the server config only. If the server is not being used, then
     /// the autoloader will not be injected.
-    pub inject_autoloader: bool,
+    pub autoreload_ws_path: Option<String>,
     /// A bool indicationg if the output HTML should have module preloads and scripts injected.
     pub inject_scripts: bool,
+    /// Options of the SASS/SCSS compiler.
+    pub sass: ConfigOptsSass,
+    /// Mappings of module specifiers to URLs to add to the import map of the output HTML.
+    pub import_map: BTreeMap<String, String>,
+    /// The names of the environment variables which may be injected at build time.
+    pub env: Vec<String>,
+    /// The locales for which the output HTML is built, if any.
+    pub locales: Vec<String>,
+    /// The locale of the output HTML at the root of the dist dir, if building per locale.
+    pub default_locale: Option<String>,
     /// Optional pattern for the app loader script.
     pub pattern_script: Option<String>,
     /// Optional pattern for the app preload element.
@@ -72,16 +400,29 @@ impl RtcBuild {
         opts: ConfigOptsBuild,
         tools: ConfigOptsTools,
         hooks: Vec<ConfigOptsHook>,
-        inject_autoloader: bool,
+        autoreload_ws_path: Option<String>,
     ) -> Result<Self> {
-        // Get the canonical path to the target HTML file.
-        let pre_target = opts.target.clone().unwrap_or_else(|| "index.html".into());
-        let target = pre_target.canonicalize().with_context(|| {
-            format!(
-                "error getting canonical path to source HTML file {:?}",
-                &pre_target
-            )
-        })?;
+        // Get the canonical path to the target HTML file, which is the first of the targets of a
+        // multi-page app.
+        let canonical_target = |target: &PathBuf| {
+            target.canonicalize().with_context(|| {
+                format!(
+                    "error getting canonical path to source HTML file {:?}",
+                    target
+                )
+            })
+        };
+        let mut targets = match opts.targets.as_deref() {
+            Some(targets) if !targets.is_empty() => targets
+                .iter()
+                .map(canonical_target)
+                .collect::<Result<Vec<_>>>()?,
+            _ => vec![canonical_target(
+                &opts.target.clone().unwrap_or_else(|| "index.html".into()),
+            )?],
+        };
+        let target = targets.remove(0);
+        let pages = targets;
 
         // Get the target HTML's parent dir, falling back to OS specific root, as that is the only
         // time where no parent could be determined.
@@ -89,6 +430,29 @@ impl RtcBuild {
             .parent()
             .map(|path| path.to_owned())
             .unwrap_or_else(|| PathBuf::from(std::path::MAIN_SEPARATOR.to_string()));
+        for page in &pages {
+            ensure!(
+                page.starts_with(&target_parent) && page != &target,
+                "[build].targets {:?} must be in the dir of the first target {:?} or its subdirs",
+                page,
+                target_parent
+            );
+        }
+
+        let cargo_features = Features::from_opts(&opts)?;
+        let cargo_profile = CargoProfile::from_opts(&opts)?;
+        let cargo_flags = CargoFlags::from_opts(&opts);
+        let cargo_env = CargoEnv::from_opts(&opts, &target_parent)?;
+        let hooks = hooks
+            .into_iter()
+            .map(Hook::new)
+            .collect::<Result<Vec<_>>>()?;
+        let copy_strategy = opts.copy_strategy.unwrap_or_default();
+        ensure!(
+            !(copy_strategy == CopyStrategy::Hardlink && opts.reproducible.unwrap_or(false)),
+            "[build].copy_strategy = \"hardlink\" can not be used for reproducible builds, which \
+             would set the modification times of the linked source files"
+        );
 
         // Ensure the final dist dir exists and that we have a canonical path to the dir. Normally
         // we would want to avoid such an action at this layer, however to ensure that other layers
@@ -106,40 +470,200 @@ impl RtcBuild {
             .context("error taking canonical path to dist dir")?;
         let staging_dist = final_dist.join(super::STAGE_DIR);
 
-        // Highlander-rule: There can be only one (prohibits contradicting arguments):
+        let wasm_opt = opts
+            .wasm_opt
+            .as_deref()
+            .map(str::parse)
+            .transpose()
+            .context("invalid [build].wasm_opt level")?;
+        let mut precompress = opts.precompress.unwrap_or_default();
         ensure!(
-            !(opts.all_features && (opts.no_default_features || opts.features.is_some())),
-            "Cannot combine --all-features with --no-default-features and/or --features"
+            !precompress.contains(&CompressionAlgorithm::Deflate),
+            "[build].precompress supports `gzip` & `br`, not `deflate`"
         );
+        if let Some(level) = opts.precompress_level {
+            for algorithm in &precompress {
+                let (name, max) = match algorithm {
+                    CompressionAlgorithm::Br => ("br", 11),
+                    _ => ("gzip", 9),
+                };
+                ensure!(
+                    level <= max,
+                    "[build].precompress_level must be at most {} for `{}`, got {}",
+                    max,
+                    name,
+                    level
+                );
+            }
+        }
+        // Only release builds are precompressed, so that the dev server serves fresh outputs
+        // without spending the time to compress them on each change.
+        if !opts.release {
+            precompress.clear();
+        }
+        if let Some(length) = opts.filehash_length {
+            ensure!(
+                (1..=16).contains(&length),
+                "[build].filehash_length must be between 1 and 16, got {}",
+                length
+            );
+        }
 
-        let cargo_features = if opts.all_features {
-            Features::All
-        } else {
-            Features::Custom {
-                features: opts.features,
-                no_default_features: opts.no_default_features,
+        if let Some(file) = opts.size_report_file.as_deref() {
+            ensure!(
+                matches!(
+                    file.extension().and_then(|ext| ext.to_str()),
+                    Some("json" | "html")
+                ),
+                "[build].size_report_file must be a `.json` or `.html` file, got {:?}",
+                file
+            );
+        }
+        let service_worker_exclude = opts
+            .service_worker_exclude
+            .iter()
+            .flatten()
+            .try_fold(GlobSetBuilder::new(), |mut acc, glob| -> Result<_> {
+                acc.add(Glob::new(glob).with_context(|| {
+                    format!("invalid [build].service_worker_exclude glob {:?}", glob)
+                })?);
+                Ok(acc)
+            })?
+            .build()
+            .context("error building [build].service_worker_exclude globs")?;
+        let i18n = opts.i18n.unwrap_or_default();
+        for locale in &i18n.locales {
+            ensure!(
+                !locale.is_empty()
+                    && locale
+                        .chars()
+                        .all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_'),
+                "[build.i18n].locales must be language tags like `en` or `pt-BR`, got {:?}",
+                locale
+            );
+        }
+        let default_locale = match i18n.default_locale {
+            Some(locale) => {
+                ensure!(
+                    i18n.locales.contains(&locale),
+                    "[build.i18n].default_locale {:?} is not one of [build.i18n].locales",
+                    locale
+                );
+                Some(locale)
             }
+            None => i18n.locales.first().cloned(),
         };
 
         Ok(Self {
             target,
             target_parent,
+            pages,
             release: opts.release,
+            profile: opts.profile,
             public_url: opts.public_url.unwrap_or_else(|| "/".into()),
             filehash: opts.filehash.unwrap_or(true),
+            filehash_length: opts.filehash_length,
+            filehash_layout: opts.filehash_layout.unwrap_or_default(),
+            unhashed_names: opts.unhashed_names,
+            copy_strategy,
+            sri: opts.sri.unwrap_or(false),
+            csp: opts.csp.unwrap_or(false),
+            minify: opts.minify.unwrap_or(opts.release),
+            postcss: opts.postcss.unwrap_or(false),
+            bundle: opts.bundle.unwrap_or(false),
+            service_worker: opts.service_worker.unwrap_or(false),
+            service_worker_exclude,
+            asset_manifest: opts.asset_manifest.unwrap_or(false),
+            reproducible: opts.reproducible.unwrap_or(false),
+            prune: !opts.no_prune,
+            precompress,
+            precompress_level: opts.precompress_level,
+            size_report: opts.size_report.unwrap_or(opts.release),
+            size_report_file: opts.size_report_file,
+            timings: opts.timings.map(|format| Arc::new(Timings::new(format))),
+            pipeline_permits: opts
+                .jobs
+                .map(|jobs| Arc::new(Semaphore::new(jobs.min(Semaphore::MAX_PERMITS)))),
+            rust_outputs: Default::default(),
+            font_characters: Default::default(),
+            output_hashes: Default::default(),
+            asset_hashes: Default::default(),
+            pipeline_cache: Arc::new(PipelineCache::new(opts.cache_dir)),
+            events: broadcast::channel(16).0,
             staging_dist,
             final_dist,
             cargo_features,
+            cargo_profile,
+            cargo_flags,
+            cargo_env,
+            wasm_opt,
+            bindgen_args: opts.bindgen_args.unwrap_or_default(),
             tools,
             hooks,
-            inject_autoloader,
+            changes: Default::default(),
+            autoreload_ws_path,
             inject_scripts: opts.inject_scripts.unwrap_or(true),
             pattern_script: opts.pattern_script,
             pattern_preload: opts.pattern_preload,
             pattern_params: opts.pattern_params,
+            sass: opts.sass.unwrap_or_default(),
+            import_map: opts.import_map.unwrap_or_default(),
+            env: opts.env.unwrap_or_default(),
+            locales: i18n.locales,
+            default_locale,
         })
     }
 
+    /// Create the name of an output file, without its extension, from its stem & the hash of
+    /// its contents, according to the file hashing config.
+    ///
+    /// The name may include a dir, which is relative to the dist dir.
+    pub fn hashed_name(&self, stem: &str, hash: u64) -> String {
+        if !self.filehash {
+            return stem.to_owned();
+        }
+        let mut hash = format!("{:x}", hash);
+        if let Some(length) = self.filehash_length {
+            hash.truncate(length);
+        }
+        match self.filehash_layout {
+            FileHashLayout::Suffix => format!("{}-{}", stem, hash),
+            FileHashLayout::Dir => format!("assets/{}/{}", hash, stem),
+        }
+    }
+
+    /// The path of the output with the given name in the staging dist dir, whose dirs are
+    /// separated by `/`, as in the URL of the output.
+    pub fn staging_path(&self, name: &str) -> PathBuf {
+        let mut path = self.staging_dist.clone();
+        path.extend(name.split('/').filter(|segment| !segment.is_empty()));
+        path
+    }
+
+    /// Start a stage of the build, which is sent as a progress event, and timed if timings are
+    /// enabled, which is recorded once the returned guard is dropped.
+    pub fn time(&self, category: &'static str, name: impl Into<String>) -> Option<TimingGuard<'_>> {
+        let name = name.into();
+        tracing::debug!("starting {} {}", category, name);
+        let _ = self.events.send(BuildEvent::Progress {
+            category,
+            name: name.clone(),
+        });
+        self.timings
+            .as_ref()
+            .map(|timings| timings.start(category, name))
+    }
+
+    /// The dir of the cargo target dir in which Trunk keeps the reports of builds.
+    pub fn reports_dir(&self) -> PathBuf {
+        let configured = self.cargo_env.vars().get("CARGO_TARGET_DIR");
+        configured
+            .map(PathBuf::from)
+            .or_else(|| std::env::var_os("CARGO_TARGET_DIR").map(PathBuf::from))
+            .unwrap_or_else(|| self.target_parent.join("target"))
+            .join("trunk")
+    }
+
     /// Construct a new instance for testing.
     #[cfg(test)]
     pub async fn new_test(tmpdir: &std::path::Path) -> Result<Self> {
@@ -153,24 +677,68 @@ impl RtcBuild {
         Ok(Self {
             target,
             target_parent,
+            pages: Vec::new(),
             release: false,
+            profile: None,
             public_url: "/".into(),
             filehash: true,
+            filehash_length: None,
+            filehash_layout: FileHashLayout::Suffix,
+            unhashed_names: None,
+            copy_strategy: CopyStrategy::Copy,
+            sri: false,
+            csp: false,
+            minify: false,
+            postcss: false,
+            bundle: false,
+            service_worker: false,
+            service_worker_exclude: GlobSet::empty(),
+            asset_manifest: false,
+            reproducible: false,
+            prune: true,
+            precompress: Vec::new(),
+            precompress_level: None,
+            size_report: false,
+            size_report_file: None,
+            timings: None,
+            pipeline_permits: None,
+            rust_outputs: Default::default(),
+            font_characters: Default::default(),
+            output_hashes: Default::default(),
+            asset_hashes: Default::default(),
+            pipeline_cache: Default::default(),
+            events: broadcast::channel(16).0,
             final_dist,
             staging_dist,
             cargo_features: Features::All,
+            cargo_profile: Default::default(),
+            cargo_flags: Default::default(),
+            cargo_env: Default::default(),
+            wasm_opt: None,
+            bindgen_args: Vec::new(),
             tools: ConfigOptsTools {
+                esbuild: None,
                 sass: None,
                 wasm_bindgen: None,
                 wasm_opt: None,
                 tailwindcss: None,
+                checksums: None,
+                require_checksums: None,
+                mirror: None,
+                proxy: None,
             },
             hooks: Vec::new(),
-            inject_autoloader: true,
+            changes: Default::default(),
+            autoreload_ws_path: Some(DEFAULT_WS_PATH.into()),
             inject_scripts: true,
             pattern_script: None,
             pattern_preload: None,
             pattern_params: None,
+            sass: Default::default(),
+            import_map: BTreeMap::new(),
+            env: Vec::new(),
+            locales: Vec::new(),
+            default_locale: None,
         })
     }
 }
@@ -184,6 +752,20 @@ pub struct RtcWatch {
     pub paths: Vec<PathBuf>,
     /// Paths to ignore.
     pub ignored_paths: Vec<PathBuf>,
+    /// Globs of absolute paths to ignore.
+    pub ignore_globs: GlobSet,
+    /// The `.gitignore` files which apply to the watched paths.
+    pub gitignores: Vec<Gitignore>,
+    /// The interval at which to poll for file system changes, instead of using native events.
+    pub poll: Option<Duration>,
+    /// The duration for which file system events are collected before triggering a build.
+    pub debounce: Duration,
+    /// Whether to clear the terminal & print a header with the changed paths before each build.
+    pub clear_screen: bool,
+    /// Whether to show a desktop notification with the result of each build.
+    pub notifications: bool,
+    /// Commands to run using the system shell after each successful build.
+    pub exec: Vec<String>,
 }
 
 impl RtcWatch {
@@ -192,9 +774,9 @@ impl RtcWatch {
         opts: ConfigOptsWatch,
         tools: ConfigOptsTools,
         hooks: Vec<ConfigOptsHook>,
-        inject_autoloader: bool,
+        autoreload_ws_path: Option<String>,
     ) -> Result<Self> {
-        let build = Arc::new(RtcBuild::new(build_opts, tools, hooks, inject_autoloader)?);
+        let build = Arc::new(RtcBuild::new(build_opts, tools, hooks, autoreload_ws_path)?);
 
         // Take the canonical path of each of the specified watch targets.
         let mut paths = vec![];
@@ -209,28 +791,60 @@ impl RtcWatch {
             paths.push(build.target_parent.clone());
         }
 
-        // Take the canonical path of each of the specified ignore targets.
-        let mut ignored_paths = match opts.ignore {
-            None => vec![],
-            Some(paths) => {
-                paths
-                    .into_iter()
-                    .try_fold(vec![], |mut acc, path| -> Result<Vec<PathBuf>> {
-                        let canon_path = path
-                            .canonicalize()
-                            .map_err(|_| anyhow!("invalid ignore path provided: {:?}", path))?;
-                        acc.push(canon_path);
-                        Ok(acc)
-                    })?
-            }
-        };
+        // Take the canonical path of each of the specified ignore targets, which are not globs.
+        let (ignore_globs, ignore_paths): (Vec<_>, Vec<_>) = opts
+            .ignore
+            .unwrap_or_default()
+            .into_iter()
+            .partition(|path| is_glob(path));
+        let mut ignored_paths =
+            ignore_paths
+                .into_iter()
+                .try_fold(vec![], |mut acc, path| -> Result<Vec<PathBuf>> {
+                    let canon_path = path
+                        .canonicalize()
+                        .map_err(|_| anyhow!("invalid ignore path provided: {:?}", path))?;
+                    acc.push(canon_path);
+                    Ok(acc)
+                })?;
         // Ensure the final dist dir is always ignored.
         ignored_paths.push(build.final_dist.clone());
 
+        // Match the ignore globs against absolute paths, relative globs having been resolved
+        // against the config dir while loading the config layers.
+        let ignore_globs = ignore_globs
+            .into_iter()
+            .try_fold(GlobSetBuilder::new(), |mut acc, glob| -> Result<_> {
+                acc.add(
+                    Glob::new(&glob.to_string_lossy())
+                        .with_context(|| format!("invalid ignore glob provided: {:?}", glob))?,
+                );
+                Ok(acc)
+            })?
+            .build()
+            .context("error building ignore globs")?;
+        let gitignores = match opts.gitignore {
+            true => gitignores(&paths)?,
+            false => vec![],
+        };
+
+        let debounce = opts.debounce.unwrap_or(DEFAULT_DEBOUNCE);
+        ensure!(
+            !debounce.is_zero(),
+            "the watch debounce duration must not be zero"
+        );
+
         Ok(Self {
             build,
             paths,
             ignored_paths,
+            ignore_globs,
+            gitignores,
+            poll: opts.poll,
+            debounce,
+            clear_screen: opts.clear_screen,
+            notifications: opts.notifications,
+            exec: opts.exec.unwrap_or_default(),
         })
     }
 }
@@ -246,6 +860,10 @@ pub struct RtcServe {
     pub port: u16,
     /// Open a browser tab once the initial build is complete.
     pub open: bool,
+    /// Print a QR code of the network URL of the server.
+    pub qr: bool,
+    /// The name to announce the server as via mDNS, without `.local`, if it is announced.
+    pub mdns: Option<String>,
     /// A URL to which requests will be proxied.
     pub proxy_backend: Option<Uri>,
     /// The URI on which to accept requests which are to be rewritten and proxied to backend.
@@ -254,12 +872,40 @@ pub struct RtcServe {
     pub proxy_ws: bool,
     /// Configure the proxy to accept insecure connections.
     pub proxy_insecure: bool,
+    /// Configure the proxy to send requests to the backend using HTTP/2.
+    pub proxy_http2: bool,
+    /// Log the requests & responses of all proxies, which do not configure their own logging.
+    pub proxy_log: Option<ProxyLog>,
     /// Any proxies configured to run along with the server.
     pub proxies: Option<Vec<ConfigOptsProxy>>,
     /// Whether to disable auto-reload of the web page when a build completes.
     pub no_autoreload: bool,
-    /// The tls config containing the certificate and private key. TLS is activated if both are set.
+    /// The path of the autoreload WebSocket.
+    pub ws_path: String,
+    /// The interval at which to send keepalive pings on the autoreload WebSocket, if enabled.
+    pub ws_ping_interval: Option<Duration>,
+    /// The tls config containing the certificate and private key. TLS is activated if both are
+    /// set, or if TLS is requested, in which case a self-signed certificate is used.
     pub tls: Option<RustlsConfig>,
+    /// The port on which plain HTTP requests are redirected to HTTPS, if TLS is enabled.
+    pub tls_redirect_port: Option<u16>,
+    /// The compression algorithms which may be used for responses. Compression is disabled when
+    /// empty.
+    pub compression: Vec<CompressionAlgorithm>,
+    /// The username & password required to access the server, if basic auth is enabled.
+    pub basic_auth: Option<(String, String)>,
+    /// The bearer token required to trigger a build with `POST /_trunk/build`, if enabled.
+    pub build_token: Option<String>,
+    /// Whether to set the cross-origin isolation headers on all static file responses.
+    pub cross_origin_isolation: bool,
+    /// Additional headers to set on all static file responses, including the cross-origin
+    /// isolation headers, if enabled.
+    pub headers: HeaderMap,
+    /// The MIME types of static files by their lowercase extension, which override the guessed
+    /// ones.
+    pub mime_types: HashMap<String, HeaderValue>,
+    /// Additional directories to be served along with the dist dir.
+    pub static_dirs: Vec<ConfigOptsStatic>,
 }
 
 impl RtcServe {
@@ -271,35 +917,302 @@ impl RtcServe {
         hooks: Vec<ConfigOptsHook>,
         proxies: Option<Vec<ConfigOptsProxy>>,
     ) -> Result<Self> {
+        let ws_path = opts.ws_path.unwrap_or_else(|| DEFAULT_WS_PATH.into());
+        ensure!(
+            ws_path.starts_with('/')
+                && ws_path.parse::<PathAndQuery>().is_ok()
+                && !ws_path.contains(['\'', '\\', '?']),
+            "invalid autoreload WebSocket path {:?}",
+            ws_path
+        );
         let watch = Arc::new(RtcWatch::new(
             build_opts,
             watch_opts,
             tools,
             hooks,
-            !opts.no_autoreload,
+            (!opts.no_autoreload).then(|| ws_path.clone()),
         )?);
         let tls = tls_config(
+            opts.tls,
             absolute_path_if_some(opts.tls_key_path, "tls_key_path")?,
             absolute_path_if_some(opts.tls_cert_path, "tls_cert_path")?,
         )
         .await?;
+        let password = match (opts.password, opts.password_file) {
+            (Some(_), Some(_)) => {
+                return Err(anyhow!(
+                    "basic auth password provided both directly & as a password file"
+                ))
+            }
+            (None, Some(path)) => Some(read_password_file(&path).await?),
+            (password, None) => password,
+        };
+        let basic_auth = match (opts.username, password) {
+            (Some(username), Some(password)) => Some((username, password)),
+            (None, Some(_)) => {
+                return Err(anyhow!("basic auth password provided without username"))
+            }
+            (Some(_), None) => {
+                return Err(anyhow!("basic auth username provided without password"))
+            }
+            (None, None) => None,
+        };
+        if let Some(backend) = &opts.proxy_backend {
+            validate_proxy_backends(
+                std::slice::from_ref(backend),
+                opts.proxy_rewrite.as_deref(),
+                opts.proxy_ws,
+            )?;
+        }
+        for proxy in proxies.iter().flatten() {
+            validate_proxy_backends(&proxy.backend, proxy.rewrite.as_deref(), proxy.ws)?;
+            ensure!(
+                !(proxy.preserve_host && proxy.host.is_some()),
+                "proxy {} can not both preserve & override the host header",
+                proxy.backend[0]
+            );
+            for backend in proxy.backend.iter().filter(|_| proxy.grpc_web) {
+                ensure!(
+                    backend.scheme_str() == Some("http"),
+                    "gRPC-web proxy backend {} must be a plain HTTP backend, as native gRPC \
+                     requests are sent using HTTP/2 over plain TCP",
+                    backend
+                );
+            }
+        }
+        let cross_origin_isolation = opts.cross_origin_isolation.unwrap_or(false);
+        let mut headers = if cross_origin_isolation {
+            cross_origin_isolation_headers()
+        } else {
+            HeaderMap::new()
+        };
+        headers.extend(header_map(opts.headers)?);
+        let mut mime_types = HashMap::from([(
+            "wasm".to_owned(),
+            HeaderValue::from_static("application/wasm"),
+        )]);
+        for (ext, mime_type) in opts.mime_types {
+            let value = HeaderValue::from_str(&mime_type).with_context(|| {
+                format!("invalid MIME type {:?} for extension {:?}", mime_type, ext)
+            })?;
+            mime_types.insert(ext.trim_start_matches('.').to_ascii_lowercase(), value);
+        }
+        let mdns = match opts.mdns_name.filter(|_| opts.mdns) {
+            Some(name) => {
+                ensure!(
+                    mdns::is_valid_name(&name),
+                    "invalid mDNS name {:?}, expected up to 63 letters, digits & hyphens, which \
+                     do not start or end with a hyphen",
+                    name
+                );
+                Some(name)
+            }
+            None if opts.mdns => Some(mdns::default_name(&watch.build.target_parent)),
+            None => None,
+        };
+        let static_dirs = opts
+            .static_dirs
+            .into_iter()
+            .map(|static_dir| {
+                ensure!(
+                    static_dir.path.starts_with('/')
+                        && !static_dir.path.trim_matches('/').is_empty(),
+                    "[[serve.static]].path {:?} must begin with `/` and must not be the root path",
+                    static_dir.path
+                );
+                Ok(ConfigOptsStatic {
+                    dir: absolute_path(static_dir.dir, "static dir")?,
+                    ..static_dir
+                })
+            })
+            .collect::<Result<_>>()?;
         Ok(Self {
             watch,
             address: opts.address.unwrap_or(IpAddr::V4(Ipv4Addr::LOCALHOST)),
             port: opts.port.unwrap_or(8080),
             open: opts.open,
+            qr: opts.qr,
+            mdns,
             proxy_backend: opts.proxy_backend,
             proxy_rewrite: opts.proxy_rewrite,
             proxy_insecure: opts.proxy_insecure,
+            proxy_http2: opts.proxy_http2,
+            proxy_log: opts.proxy_log,
             proxy_ws: opts.proxy_ws,
             proxies,
             no_autoreload: opts.no_autoreload,
+            ws_path,
+            ws_ping_interval: match opts.ws_ping_interval.unwrap_or(30) {
+                0 => None,
+                secs => Some(Duration::from_secs(secs)),
+            },
             tls,
+            tls_redirect_port: opts.tls_redirect_port,
+            compression: opts.compression.unwrap_or_default(),
+            basic_auth,
+            build_token: opts.build_token,
+            cross_origin_isolation,
+            headers,
+            mime_types,
+            static_dirs,
         })
     }
+
+    /// Construct a new instance for testing.
+    #[cfg(test)]
+    pub async fn new_test(tmpdir: &std::path::Path) -> Result<Self> {
+        let build = Arc::new(RtcBuild::new_test(tmpdir).await?);
+        let watch = Arc::new(RtcWatch {
+            paths: vec![build.target_parent.clone()],
+            ignored_paths: Vec::new(),
+            ignore_globs: GlobSet::empty(),
+            gitignores: Vec::new(),
+            poll: None,
+            debounce: DEFAULT_DEBOUNCE,
+            clear_screen: false,
+            notifications: false,
+            exec: Vec::new(),
+            build,
+        });
+        Ok(Self {
+            watch,
+            address: IpAddr::V4(Ipv4Addr::LOCALHOST),
+            port: 0,
+            open: false,
+            qr: false,
+            mdns: None,
+            proxy_backend: None,
+            proxy_rewrite: None,
+            proxy_ws: false,
+            proxy_insecure: false,
+            proxy_http2: false,
+            proxy_log: None,
+            proxies: None,
+            no_autoreload: false,
+            ws_path: DEFAULT_WS_PATH.into(),
+            ws_ping_interval: None,
+            tls: None,
+            tls_redirect_port: None,
+            compression: Vec::new(),
+            basic_auth: None,
+            build_token: None,
+            cross_origin_isolation: false,
+            headers: HeaderMap::new(),
+            mime_types: HashMap::new(),
+            static_dirs: Vec::new(),
+        })
+    }
+}
+
+/// Check if the given ignore path is a glob, rather than a plain path.
+pub(super) fn is_glob(path: &Path) -> bool {
+    path.to_string_lossy().contains(['*', '?', '[', '{'])
+}
+
+/// Load the `.gitignore` files which apply to the given paths, which are found in the paths
+/// themselves & their ancestors, up to the root of the git repository.
+fn gitignores(paths: &[PathBuf]) -> Result<Vec<Gitignore>> {
+    let mut dirs = Vec::new();
+    for path in paths {
+        for dir in path.ancestors() {
+            if dir.join(".gitignore").is_file() && !dirs.iter().any(|known| known == dir) {
+                dirs.push(dir.to_owned());
+            }
+            if dir.join(".git").exists() {
+                break;
+            }
+        }
+    }
+    dirs.into_iter()
+        .map(|dir| {
+            let path = dir.join(".gitignore");
+            let mut builder = GitignoreBuilder::new(&dir);
+            if let Some(err) = builder.add(&path) {
+                return Err(err).with_context(|| format!("error reading {:?}", path));
+            }
+            builder
+                .build()
+                .with_context(|| format!("error parsing {:?}", path))
+        })
+        .collect()
+}
+
+/// Ensure that a proxy to the given backends can be built.
+///
+/// Requests can only be balanced across multiple HTTP backends, and Unix domain socket backends
+/// need to be supported by the platform.
+fn validate_proxy_backends(backends: &[Uri], rewrite: Option<&str>, ws: bool) -> Result<()> {
+    if backends.len() > 1 {
+        ensure!(
+            !ws,
+            "WebSocket proxies can only have a single backend, got {} backends",
+            backends.len()
+        );
+    }
+    for backend in backends {
+        if unix_socket_path(backend).is_some() {
+            ensure!(
+                backends.len() == 1,
+                "proxy backend {} is a Unix domain socket, which can not be combined with other \
+                 backends",
+                backend
+            );
+            validate_unix_socket_backend(backend, rewrite, ws)?;
+        }
+    }
+    Ok(())
+}
+
+/// Ensure that a proxy to the given Unix domain socket backend can be built.
+fn validate_unix_socket_backend(backend: &Uri, rewrite: Option<&str>, ws: bool) -> Result<()> {
+    ensure!(
+        cfg!(unix),
+        "proxy backend {} is a Unix domain socket, which is not supported on this platform",
+        backend
+    );
+    ensure!(
+        !ws,
+        "proxy backend {} is a Unix domain socket, which can not be used to proxy WebSockets",
+        backend
+    );
+    ensure!(
+        rewrite.is_some(),
+        "proxy backend {} is a Unix domain socket, which requires a rewrite to listen on",
+        backend
+    );
+    Ok(())
+}
+
+/// The headers enabling cross-origin isolation of the pages of responses.
+pub(crate) fn cross_origin_isolation_headers() -> HeaderMap {
+    HeaderMap::from_iter([
+        (
+            HeaderName::from_static("cross-origin-opener-policy"),
+            HeaderValue::from_static("same-origin"),
+        ),
+        (
+            HeaderName::from_static("cross-origin-embedder-policy"),
+            HeaderValue::from_static("require-corp"),
+        ),
+    ])
+}
+
+/// Build a header map from the given raw header names & values, ensuring that they are valid.
+pub(crate) fn header_map(headers: HashMap<String, String>) -> Result<HeaderMap> {
+    headers
+        .into_iter()
+        .try_fold(HeaderMap::new(), |mut acc, (name, value)| {
+            let header_name = HeaderName::from_bytes(name.as_bytes())
+                .with_context(|| format!("invalid header name {:?}", name))?;
+            let header_value = HeaderValue::from_str(&value)
+                .with_context(|| format!("invalid value {:?} for header {:?}", value, name))?;
+            acc.insert(header_name, header_value);
+            Ok(acc)
+        })
 }
 
 async fn tls_config(
+    tls: bool,
     tls_key_path: Option<PathBuf>,
     tls_cert_path: Option<PathBuf>,
 ) -> Result<Option<RustlsConfig>, anyhow::Error> {
@@ -314,10 +1227,97 @@ async fn tls_config(
         }
         (None, Some(_)) => Err(anyhow!("TLS cert path provided without key path")),
         (Some(_), None) => Err(anyhow!("TLS key path provided without cert path")),
+        (None, None) if tls => self_signed_tls_config().await.map(Some),
         (None, None) => Ok(None),
     }
 }
 
+/// Load the self-signed TLS certificate from the Trunk data dir, generating it on first use.
+async fn self_signed_tls_config() -> Result<RustlsConfig> {
+    let tls_dir = ProjectDirs::from("dev", "trunkrs", "trunk")
+        .context("failed finding project directory")?
+        .data_dir()
+        .join("tls");
+    self_signed_tls_config_in(&tls_dir).await
+}
+
+/// Load the self-signed TLS certificate from the given dir, generating it on first use.
+///
+/// The generated private key is only readable & writable by its owner.
+pub(super) async fn self_signed_tls_config_in(tls_dir: &Path) -> Result<RustlsConfig> {
+    let tls_key_path = tls_dir.join("localhost-key.pem");
+    let tls_cert_path = tls_dir.join("localhost-cert.pem");
+
+    if !tls_key_path.exists() || !tls_cert_path.exists() {
+        let cert = rcgen::generate_simple_self_signed(vec![
+            "localhost".to_string(),
+            Ipv4Addr::LOCALHOST.to_string(),
+            Ipv6Addr::LOCALHOST.to_string(),
+        ])
+        .context("error generating self-signed TLS certificate")?;
+        let cert_pem = cert
+            .serialize_pem()
+            .context("error serializing self-signed TLS certificate")?;
+        tokio::fs::create_dir_all(tls_dir)
+            .await
+            .with_context(|| format!("error creating TLS directory {:?}", tls_dir))?;
+        write_private_key(&tls_key_path, &cert.serialize_private_key_pem())
+            .await
+            .with_context(|| format!("error writing TLS key {:?}", tls_key_path))?;
+        tokio::fs::write(&tls_cert_path, cert_pem)
+            .await
+            .with_context(|| format!("error writing TLS cert {:?}", tls_cert_path))?;
+        tracing::info!(
+            "🔏 generated self-signed TLS certificate {}",
+            tls_cert_path.display()
+        );
+    }
+
+    tracing::info!(
+        "🔒 using self-signed TLS certificate; add {} to the trusted certificates of your system \
+         or browser to avoid security warnings",
+        tls_cert_path.display()
+    );
+    RustlsConfig::from_pem_file(tls_cert_path, tls_key_path)
+        .await
+        .context("loading self-signed TLS cert/key failed")
+}
+
+/// Read the basic auth password from the given file, ignoring a trailing newline.
+async fn read_password_file(path: &Path) -> Result<String> {
+    let password = tokio::fs::read_to_string(path)
+        .await
+        .with_context(|| format!("error reading basic auth password file {:?}", path))?;
+    let password = password.strip_suffix('\n').unwrap_or(&password);
+    let password = password.strip_suffix('\r').unwrap_or(password);
+    ensure!(
+        !password.is_empty(),
+        "basic auth password file {:?} is empty",
+        path
+    );
+    Ok(password.to_owned())
+}
+
+/// Write the given private key to a new file, which is only accessible by its owner.
+///
+/// Any existing file is removed first, so that the key is never written to a file with broader
+/// permissions.
+async fn write_private_key(path: &Path, key: &str) -> std::io::Result<()> {
+    use tokio::io::AsyncWriteExt;
+
+    match tokio::fs::remove_file(path).await {
+        Err(err) if err.kind() != std::io::ErrorKind::NotFound => return Err(err),
+        _ => {}
+    }
+    let mut opts = tokio::fs::OpenOptions::new();
+    opts.write(true).create_new(true);
+    #[cfg(unix)]
+    opts.mode(0o600);
+    let mut file = opts.open(path).await?;
+    file.write_all(key.as_bytes()).await?;
+    file.sync_all().await
+}
+
 fn absolute_path_if_some(
     maybe_path: Option<PathBuf>,
     file_description: &str,
@@ -354,3 +1354,61 @@ impl RtcClean {
         }
     }
 }
+
+/// Runtime config for the deploy system.
+#[derive(Clone, Debug)]
+pub struct RtcDeploy {
+    /// Runtime config for the build system, whose dist dir is deployed.
+    pub build: Arc<RtcBuild>,
+    /// The target to deploy to, with its options.
+    pub target: DeployTo,
+}
+
+/// A target of the deploy system, with its options.
+#[derive(Clone, Debug)]
+pub enum DeployTo {
+    S3(ConfigOptsDeployS3),
+    GithubPages(ConfigOptsDeployGitHubPages),
+    Netlify(ConfigOptsDeployNetlify),
+}
+
+impl RtcDeploy {
+    pub(super) fn new(
+        build: Arc<RtcBuild>,
+        opts: ConfigOptsDeploy,
+        to: Option<DeployTarget>,
+    ) -> Result<Self> {
+        let configured = [
+            opts.s3.as_ref().map(|_| DeployTarget::S3),
+            opts.github_pages
+                .as_ref()
+                .map(|_| DeployTarget::GithubPages),
+            opts.netlify.as_ref().map(|_| DeployTarget::Netlify),
+        ];
+        let to = match to.or(opts.to) {
+            Some(to) => to,
+            None => match configured.iter().flatten().collect::<Vec<_>>()[..] {
+                [to] => *to,
+                [] => bail!(
+                    "no deploy target configured, add a [deploy.s3], [deploy.github_pages] or \
+                     [deploy.netlify] section to the Trunk config file"
+                ),
+                _ => bail!(
+                    "more than one deploy target configured, select one with `--to` or \
+                     `[deploy].to`"
+                ),
+            },
+        };
+        let target = match to {
+            DeployTarget::S3 => DeployTo::S3(
+                opts.s3
+                    .context("deploy target `s3` requires a [deploy.s3] section with a bucket")?,
+            ),
+            DeployTarget::GithubPages => {
+                DeployTo::GithubPages(opts.github_pages.unwrap_or_default())
+            }
+            DeployTarget::Netlify => DeployTo::Netlify(opts.netlify.unwrap_or_default()),
+        };
+        Ok(Self { build, target })
+    }
+}
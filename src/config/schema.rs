@@ -0,0 +1,20 @@
+//! The JSON Schema of `Trunk.toml`.
+//!
+//! The schema is derived from the serde models of the config, so that it never drifts from them,
+//! with the doc comments of the options as their descriptions. As the loader ignores unknown keys,
+//! so does the schema.
+
+use serde_json::Value;
+
+use crate::config::ConfigOpts;
+
+/// Generate the JSON Schema of `Trunk.toml`.
+pub fn schema() -> Value {
+    let mut schema = schemars::schema_for!(ConfigOpts);
+    schema.insert("title".into(), "Trunk.toml".into());
+    schema.insert(
+        "description".into(),
+        "The config of Trunk, the build system of Rust WASM web apps.".into(),
+    );
+    schema.to_value()
+}
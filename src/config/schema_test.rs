@@ -0,0 +1,111 @@
+use serde_json::Value;
+
+use crate::config::schema;
+
+/// Resolve the given schema if it is a reference to a definition of the root schema, or an
+/// optional one.
+fn resolve<'a>(root: &'a Value, schema: &'a Value) -> &'a Value {
+    if let Some(variants) = schema["anyOf"].as_array() {
+        if let Some(variant) = variants.iter().find(|variant| variant["type"] != "null") {
+            return resolve(root, variant);
+        }
+    }
+    match schema["$ref"].as_str() {
+        Some(reference) => {
+            let name = reference.trim_start_matches("#/$defs/");
+            &root["$defs"][name]
+        }
+        None => schema,
+    }
+}
+
+/// Assert that all keys of the given TOML value at the given path are in the given schema.
+fn assert_known_keys(root: &Value, schema: &Value, value: &toml::Value, path: &str) {
+    let schema = resolve(root, schema);
+    match value {
+        toml::Value::Table(table) => {
+            for (key, value) in table {
+                let path = format!("{}.{}", path, key);
+                let property = match schema["properties"].get(key) {
+                    Some(property) => property,
+                    None if schema["additionalProperties"].is_object() => {
+                        &schema["additionalProperties"]
+                    }
+                    None => panic!("unexpected key {} in schema", path),
+                };
+                assert_known_keys(root, property, value, &path);
+            }
+        }
+        toml::Value::Array(values) if schema["items"].is_object() => {
+            for value in values {
+                assert_known_keys(root, &schema["items"], value, path);
+            }
+        }
+        _ => {}
+    }
+}
+
+#[test]
+fn schema_of_config_models() {
+    let schema = schema();
+    let definitions = &schema["$defs"];
+    assert_eq!(schema["title"], "Trunk.toml");
+    assert_eq!(
+        schema["properties"]["proxy"]["items"]["$ref"],
+        "#/$defs/ConfigOptsProxy"
+    );
+    assert_eq!(definitions["ConfigOptsProxy"]["required"][0], "backend");
+    assert_eq!(
+        definitions["ConfigOptsHook"]["required"]
+            .as_array()
+            .map(Vec::len),
+        Some(2)
+    );
+    assert!(definitions["ConfigOptsBuild"].get("required").is_none());
+
+    let port = &definitions["ConfigOptsServe"]["properties"]["port"];
+    assert!(port["type"]
+        .as_array()
+        .is_some_and(|types| types.contains(&"integer".into())));
+    assert_eq!(port["maximum"], 65535);
+    assert!(port["description"]
+        .as_str()
+        .is_some_and(|description| description.starts_with("The port to serve on")));
+    assert_eq!(
+        definitions["ConfigOptsServe"]["properties"]["static"]["items"]["$ref"],
+        "#/$defs/ConfigOptsStatic"
+    );
+    let poll = &definitions["ConfigOptsWatch"]["properties"]["poll"];
+    assert!(poll["type"]
+        .as_array()
+        .is_some_and(|types| types.contains(&"string".into())));
+    let stage = resolve(
+        &schema,
+        &definitions["ConfigOptsHook"]["properties"]["stage"],
+    );
+    assert!(stage["oneOf"]
+        .as_array()
+        .is_some_and(|stages| stages.iter().any(|stage| stage["const"] == "pre_build")));
+}
+
+#[test]
+fn schema_allows_unknown_keys_like_the_loader() {
+    let schema = schema();
+    let definitions = schema["$defs"].as_object().expect("expected definitions");
+    for (name, definition) in definitions {
+        assert_ne!(
+            definition["additionalProperties"], false,
+            "unexpected additionalProperties of {}",
+            name
+        );
+    }
+    assert_ne!(schema["additionalProperties"], false);
+}
+
+#[test]
+fn schema_knows_example_config() {
+    let schema = schema();
+    let example = std::fs::read_to_string("Trunk.toml").expect("error reading example config");
+    let example: toml::Value = toml::from_str(&example).expect("error parsing example config");
+    assert_known_keys(&schema, &schema, &example, "");
+}
@@ -0,0 +1,254 @@
+//! Deploying the dist dir to static hosts.
+
+use std::path::{Path, PathBuf};
+use std::process::Stdio;
+use std::sync::Arc;
+
+use anyhow::{Context, Result};
+use tokio::fs;
+use tokio::process::Command;
+
+use crate::build::BuildSystem;
+use crate::config::{
+    ConfigOptsDeployGitHubPages, ConfigOptsDeployNetlify, ConfigOptsDeployS3, DeployTo, RtcDeploy,
+};
+use crate::{common, messages};
+
+/// The default message of the commits & deploys.
+const DEFAULT_MESSAGE: &str = "Deploy with Trunk";
+
+/// A system for deploying the dist dir, which first builds it.
+pub struct DeploySystem {
+    cfg: Arc<RtcDeploy>,
+    /// Deploy the existing dist dir, instead of building it first.
+    no_build: bool,
+    /// Only print the commands which would deploy the dist dir.
+    dry_run: bool,
+}
+
+impl DeploySystem {
+    /// Create a new instance.
+    pub fn new(cfg: Arc<RtcDeploy>, no_build: bool, dry_run: bool) -> Self {
+        Self {
+            cfg,
+            no_build,
+            dry_run,
+        }
+    }
+
+    /// Build & deploy the dist dir to the configured target.
+    #[tracing::instrument(level = "trace", skip(self))]
+    pub async fn run(self) -> Result<()> {
+        if !self.no_build {
+            let mut system = BuildSystem::new(self.cfg.build.clone(), None).await?;
+            system.build().await?;
+        }
+        let dist = &self.cfg.build.final_dist;
+        match &self.cfg.target {
+            DeployTo::S3(opts) => self.deploy_s3(dist, opts).await?,
+            DeployTo::GithubPages(opts) => self.deploy_github_pages(dist, opts).await?,
+            DeployTo::Netlify(opts) => self.deploy_netlify(dist, opts).await?,
+        }
+        tracing::info!("deployed {:?}", dist);
+        Ok(())
+    }
+
+    /// Sync the dist dir to the bucket, then invalidate the CloudFront distribution, if any.
+    async fn deploy_s3(&self, dist: &Path, opts: &ConfigOptsDeployS3) -> Result<()> {
+        let prefix = opts.prefix.as_deref().unwrap_or_default().trim_matches('/');
+        let dist = dist.to_string_lossy();
+        let url = format!("s3://{}/{}", opts.bucket, prefix);
+        let mut args = vec!["s3", "sync", &dist, &url];
+        if opts.delete {
+            args.push("--delete");
+        }
+        if let Some(region) = &opts.region {
+            args.extend(["--region", region]);
+        }
+        self.run_command("aws", &args).await?;
+
+        if let Some(distribution) = &opts.cloudfront_distribution {
+            let paths = match prefix {
+                "" => "/*".to_owned(),
+                prefix => format!("/{}/*", prefix),
+            };
+            let args = [
+                "cloudfront",
+                "create-invalidation",
+                "--distribution-id",
+                distribution,
+                "--paths",
+                &paths,
+            ];
+            self.run_command("aws", &args).await?;
+        }
+        Ok(())
+    }
+
+    /// Commit the dist dir to the branch, on top of its history, & push it to the remote.
+    async fn deploy_github_pages(
+        &self,
+        dist: &Path,
+        opts: &ConfigOptsDeployGitHubPages,
+    ) -> Result<()> {
+        let remote = opts.remote.as_deref().unwrap_or("origin");
+        let branch = opts.branch.as_deref().unwrap_or("gh-pages");
+        let url = self.remote_url(remote).await;
+        let dir = std::env::temp_dir().join(format!("trunk-deploy-{}", std::process::id()));
+        let result = self.commit_and_push(dist, opts, &dir, &url, branch).await;
+        if common::path_exists(&dir).await? {
+            common::remove_dir_all(dir).await?;
+        }
+        result
+    }
+
+    /// Commit the dist dir to the branch in a fresh repo in the given dir, & push it to the remote.
+    async fn commit_and_push(
+        &self,
+        dist: &Path,
+        opts: &ConfigOptsDeployGitHubPages,
+        dir: &Path,
+        url: &str,
+        branch: &str,
+    ) -> Result<()> {
+        let dir_arg = dir.to_string_lossy();
+        let git = |args: &[&str]| {
+            let mut full = vec!["-C", &*dir_arg];
+            full.extend(args);
+            full.into_iter().map(str::to_owned).collect::<Vec<_>>()
+        };
+        self.run_command("git", &["init", "-q", &dir_arg]).await?;
+
+        // Commit on top of the existing branch, if any, or start it as an orphan branch.
+        let fetched = self
+            .try_command("git", &git(&["fetch", "-q", "--depth=1", url, branch]))
+            .await?;
+        if fetched {
+            self.run_command("git", &git(&["checkout", "-q", "-B", branch, "FETCH_HEAD"]))
+                .await?;
+            self.run_command("git", &git(&["rm", "-rqf", "--ignore-unmatch", "."]))
+                .await?;
+        } else {
+            self.run_command("git", &git(&["checkout", "-q", "--orphan", branch]))
+                .await?;
+        }
+
+        if !self.dry_run {
+            common::copy_dir_recursive(dist.to_owned(), dir.to_owned()).await?;
+            // Serve the files as they are, including the ones whose names start with `_`.
+            fs::write(dir.join(".nojekyll"), "")
+                .await
+                .context("error writing .nojekyll file")?;
+            if let Some(cname) = &opts.cname {
+                fs::write(dir.join("CNAME"), format!("{}\n", cname))
+                    .await
+                    .context("error writing CNAME file")?;
+            }
+        }
+        self.run_command("git", &git(&["add", "-A"])).await?;
+        let unchanged = !self.dry_run
+            && self
+                .try_command("git", &git(&["diff", "--cached", "--quiet"]))
+                .await?;
+        if unchanged {
+            tracing::info!("no changes to deploy to {}", branch);
+            return Ok(());
+        }
+        // Commit as Trunk unless an identity is configured, which CI runners lack.
+        let has_identity = self
+            .try_command("git", &git(&["config", "user.email"]))
+            .await?;
+        let identity: &[&str] = if has_identity {
+            &[]
+        } else {
+            &["-c", "user.name=Trunk", "-c", "user.email=trunk@localhost"]
+        };
+        let message = opts.message.as_deref().unwrap_or(DEFAULT_MESSAGE);
+        let commit = [identity, &["commit", "-q", "-m", message]].concat();
+        self.run_command("git", &git(&commit)).await?;
+        let refspec = format!("HEAD:refs/heads/{}", branch);
+        self.run_command("git", &git(&["push", "-q", url, &refspec]))
+            .await?;
+        Ok(())
+    }
+
+    /// Get the URL of the given git remote of the project, or the remote itself if it is a URL.
+    async fn remote_url(&self, remote: &str) -> String {
+        let output = Command::new("git")
+            .arg("-C")
+            .arg(&self.cfg.build.target_parent)
+            .args(["remote", "get-url", remote])
+            .stderr(Stdio::null())
+            .output()
+            .await;
+        match output {
+            Ok(output) if output.status.success() => {
+                String::from_utf8_lossy(&output.stdout).trim().to_owned()
+            }
+            _ => remote.to_owned(),
+        }
+    }
+
+    /// Deploy the dist dir to the site.
+    async fn deploy_netlify(&self, dist: &Path, opts: &ConfigOptsDeployNetlify) -> Result<()> {
+        let dist = dist.to_string_lossy();
+        let mut args = vec!["deploy", "--dir", &dist];
+        if !opts.draft {
+            args.push("--prod");
+        }
+        if let Some(site) = &opts.site {
+            args.extend(["--site", site]);
+        }
+        if let Some(message) = &opts.message {
+            args.extend(["--message", message]);
+        }
+        self.run_command("netlify", &args).await
+    }
+
+    /// Run the given command, or only print it on a dry run.
+    async fn run_command(&self, name: &str, args: &[impl AsRef<str>]) -> Result<()> {
+        let args: Vec<&str> = args.iter().map(AsRef::as_ref).collect();
+        if self.dry_run {
+            print_command(name, &args);
+            return Ok(());
+        }
+        let path = find_command(name)?;
+        common::run_command(name, &path, &args).await
+    }
+
+    /// Run the given command, returning if it succeeded, or only print it on a dry run.
+    async fn try_command(&self, name: &str, args: &[impl AsRef<str>]) -> Result<bool> {
+        let args: Vec<&str> = args.iter().map(AsRef::as_ref).collect();
+        if self.dry_run {
+            print_command(name, &args);
+            return Ok(true);
+        }
+        let path = find_command(name)?;
+        let status = Command::new(path)
+            .args(&args)
+            .stdout(Stdio::null())
+            .stderr(Stdio::null())
+            .kill_on_drop(true)
+            .status()
+            .await
+            .with_context(|| format!("error during {} call", name))?;
+        Ok(status.success())
+    }
+}
+
+/// Find the given command in the `PATH`.
+fn find_command(name: &str) -> Result<PathBuf> {
+    which::which(name).with_context(|| format!("could not find {} in the PATH", name))
+}
+
+/// Print the given command of a dry run, quoting the args with whitespace.
+fn print_command(name: &str, args: &[&str]) {
+    let args: Vec<String> = args
+        .iter()
+        .map(|arg| match arg.contains(char::is_whitespace) {
+            true => format!("{:?}", arg),
+            false => (*arg).to_owned(),
+        })
+        .collect();
+    messages::print(format_args!("{} {}", name, args.join(" ")));
+}
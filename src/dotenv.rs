@@ -0,0 +1,27 @@
+//! Loading of the environment variables of `.env` files.
+
+use std::path::Path;
+
+use anyhow::{Context, Result};
+
+/// The `.env` files of the project, where later files take precedence over earlier ones.
+const FILES: [&str; 2] = [".env", ".env.local"];
+
+/// Load the variables of the `.env` & `.env.local` files of the given dir into the environment of
+/// Trunk, and so of cargo, hooks & all other commands it runs. Variables which are set already
+/// take precedence over the files.
+///
+/// As setting env vars is not thread safe, this must be called before any threads are spawned,
+/// i.e. before the tokio runtime is built.
+pub fn load(dir: &Path) -> Result<()> {
+    // NOTE: existing vars are not overridden, so the later files are loaded first.
+    for name in FILES.iter().rev() {
+        let path = dir.join(name);
+        if !path.is_file() {
+            continue;
+        }
+        dotenvy::from_path(&path).with_context(|| format!("error loading env file {:?}", path))?;
+        tracing::debug!("loaded env file {:?}", path);
+    }
+    Ok(())
+}
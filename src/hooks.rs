@@ -1,41 +1,142 @@
+use std::ops::Deref;
+use std::path::{Path, PathBuf};
 use std::process::Stdio;
-use std::sync::Arc;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex, PoisonError};
 
-use anyhow::{bail, Context, Result};
+use anyhow::{bail, ensure, Context, Result};
 use futures_util::stream::{FuturesUnordered, StreamExt};
+use globset::{Glob, GlobSet, GlobSetBuilder};
+use serde::Serialize;
+use tokio::fs;
 use tokio::process::Command;
-use tokio::task::JoinHandle;
 
-use crate::config::RtcBuild;
+use crate::common::AbortOnDrop;
+use crate::config::{ConfigOptsHook, RtcBuild};
 use crate::pipelines::PipelineStage;
 
-/// A `FuturesUnordered` containing a `JoinHandle` for each hook-running task.
-pub type HookHandles = FuturesUnordered<JoinHandle<Result<()>>>;
+/// The pipelines which `post_pipeline` hooks may run after, where `sass` is the one of both SASS &
+/// SCSS assets.
+const PIPELINES: [&str; 5] = ["css", "sass", "tailwind-css", "js", "typescript"];
+
+/// The count of the temporary files of `post_pipeline` hooks, which makes their names unique, as
+/// assets of the same name may be processed concurrently.
+static HOOK_FILES: AtomicU64 = AtomicU64::new(0);
+
+/// A `FuturesUnordered` containing a handle for each hook-running task.
+pub type HookHandles = FuturesUnordered<AbortOnDrop<Result<()>>>;
+
+/// The changed paths which triggered the current build, which the `only_if_changed` conditions of
+/// hooks are matched against.
+#[derive(Debug, Default)]
+pub struct ChangedPaths(Mutex<Vec<PathBuf>>);
+
+impl ChangedPaths {
+    /// Set the changed paths of the current build, which are empty if it was not triggered by
+    /// changes.
+    pub fn set(&self, paths: &[PathBuf]) {
+        *self.0.lock().unwrap_or_else(PoisonError::into_inner) = paths.to_vec();
+    }
+
+    fn get(&self) -> Vec<PathBuf> {
+        self.0
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .clone()
+    }
+}
+
+/// A hook of the runtime config, whose `only_if_changed` globs are built once at config load.
+#[derive(Clone, Debug)]
+pub struct Hook {
+    /// The config of the hook.
+    cfg: ConfigOptsHook,
+    /// The globs of the `only_if_changed` condition of the hook, if any.
+    only_if_changed: Option<GlobSet>,
+}
+
+impl Hook {
+    /// Validate the given hook config & build its globs.
+    pub fn new(cfg: ConfigOptsHook) -> Result<Self> {
+        validate(&cfg)?;
+        let only_if_changed = changed_globs(&cfg)?;
+        Ok(Self {
+            cfg,
+            only_if_changed,
+        })
+    }
+}
+
+impl Deref for Hook {
+    type Target = ConfigOptsHook;
+
+    fn deref(&self) -> &ConfigOptsHook {
+        &self.cfg
+    }
+}
+
+/// The context of the build passed to hooks, as the JSON file of `TRUNK_HOOK_CONTEXT`.
+#[derive(Debug, Serialize)]
+struct HookContext<'a> {
+    stage: PipelineStage,
+    /// Either `debug` or `release`.
+    profile: &'static str,
+    /// The name of the `[profile.<name>]` section the config is overlaid with, if any.
+    config_profile: Option<&'a str>,
+    html_file: &'a Path,
+    source_dir: &'a Path,
+    staging_dir: &'a Path,
+    dist_dir: &'a Path,
+    public_url: &'a str,
+    /// The files of the staging dir, which are only complete in the `post_build` stage.
+    artifacts: Vec<HookArtifact>,
+}
+
+/// A file of the staging dir.
+#[derive(Debug, PartialEq, Eq, Serialize)]
+struct HookArtifact {
+    /// The path of the file, relative to the staging dir.
+    path: String,
+    /// The size of the file in bytes.
+    size: u64,
+    /// Whether the file was added, or changed since the last successful build.
+    changed: bool,
+}
 
 /// Spawns tokio tasks for all hooks configured for the given `HookStage`.
-pub fn spawn_hooks(cfg: Arc<RtcBuild>, stage: PipelineStage) -> HookHandles {
-    let futures: FuturesUnordered<_> = cfg
-        .hooks
-        .iter()
-        .filter(|hook_cfg| hook_cfg.stage == stage)
+pub async fn spawn_hooks(cfg: Arc<RtcBuild>, stage: PipelineStage) -> Result<HookHandles> {
+    let changes = cfg.changes.get();
+    let mut hooks = Vec::new();
+    for hook_cfg in cfg.hooks.iter().filter(|hook_cfg| hook_cfg.stage == stage) {
+        if should_run(&cfg, hook_cfg, &changes) {
+            hooks.push(hook_cfg);
+        } else {
+            tracing::debug!(
+                ?stage,
+                "skipping hook {}, as its conditions are not met",
+                hook_cfg.command
+            );
+        }
+    }
+    if hooks.is_empty() {
+        return Ok(HookHandles::default());
+    }
+    let context_file = write_context(&cfg, stage)
+        .await
+        .context("error writing context of hooks")?;
+    let futures: FuturesUnordered<_> = hooks
+        .into_iter()
         .map(|hook_cfg| {
-            let mut command = Command::new(&hook_cfg.command);
-            command
-                .args(&hook_cfg.command_arguments)
-                .stdout(Stdio::inherit())
-                .stderr(Stdio::inherit())
-                .env("TRUNK_PROFILE", if cfg.release { "release" } else { "debug" })
-                .env("TRUNK_HTML_FILE", &cfg.target)
-                .env("TRUNK_SOURCE_DIR", &cfg.target_parent)
-                .env("TRUNK_STAGING_DIR", &cfg.staging_dist)
-                .env("TRUNK_DIST_DIR", &cfg.final_dist)
-                .env("TRUNK_PUBLIC_URL", &cfg.public_url);
+            let mut command = hook_command(&cfg, hook_cfg);
+            command.env("TRUNK_HOOK_CONTEXT", &context_file);
 
             tracing::info!(command_arguments = ?hook_cfg.command_arguments, "spawned hook {}", hook_cfg.command);
 
             let command_name = hook_cfg.command.clone();
             tracing::info!(?stage, command = %command_name, "spawning hook");
-            tokio::spawn(async move {
+            let cfg = cfg.clone();
+            AbortOnDrop(tokio::spawn(async move {
+                let _timing = cfg.time("hook", format!("{:?} {}", stage, command_name));
                 let status = command
                     .spawn()
                     .with_context(|| format!("error spawning hook call for {}", command_name))?
@@ -47,11 +148,255 @@ pub fn spawn_hooks(cfg: Arc<RtcBuild>, stage: PipelineStage) -> HookHandles {
                 }
                 tracing::info!("finished hook {}", command_name);
                 Ok(())
-            })
+            }))
         })
         .collect();
 
-    futures
+    Ok(futures)
+}
+
+/// Build the command of the given hook, with the env vars of the build.
+fn hook_command(cfg: &RtcBuild, hook: &ConfigOptsHook) -> Command {
+    let mut command = Command::new(&hook.command);
+    command
+        .args(&hook.command_arguments)
+        .stdout(Stdio::inherit())
+        .stderr(Stdio::inherit())
+        .env("TRUNK_STAGE", hook.stage.as_str())
+        .env(
+            "TRUNK_PROFILE",
+            if cfg.release { "release" } else { "debug" },
+        )
+        .env(
+            "TRUNK_CONFIG_PROFILE",
+            cfg.profile.as_deref().unwrap_or_default(),
+        )
+        .env("TRUNK_HTML_FILE", &cfg.target)
+        .env("TRUNK_SOURCE_DIR", &cfg.target_parent)
+        .env("TRUNK_STAGING_DIR", &cfg.staging_dist)
+        .env("TRUNK_DIST_DIR", &cfg.final_dist)
+        .env("TRUNK_PUBLIC_URL", &cfg.public_url)
+        .kill_on_drop(true);
+    command
+}
+
+/// Run the `post_pipeline` hooks of the given pipeline one after another on the output file of
+/// the given source asset, which they may rewrite in place before it is hashed.
+pub async fn run_pipeline_hooks(
+    cfg: &RtcBuild,
+    pipeline: &str,
+    source: &Path,
+    file: &Path,
+) -> Result<()> {
+    let changes = cfg.changes.get();
+    for hook in pipeline_hooks(cfg, pipeline) {
+        if !should_run(cfg, hook, &changes) {
+            tracing::debug!(
+                "skipping hook {}, as its conditions are not met",
+                hook.command
+            );
+            continue;
+        }
+        let rel_path = crate::common::strip_prefix(source);
+        let _timing = cfg.time(
+            "hook",
+            format!("post_pipeline {} {:?}", hook.command, rel_path),
+        );
+        tracing::info!(path = ?rel_path, "running {} hook {}", pipeline, hook.command);
+        let status = hook_command(cfg, hook)
+            .env("TRUNK_PIPELINE", pipeline)
+            .env("TRUNK_ASSET_SOURCE", source)
+            .env("TRUNK_ASSET_FILE", file)
+            .status()
+            .await
+            .with_context(|| format!("error calling hook to {}", hook.command))?;
+        if !status.success() {
+            bail!("hook call to {} returned a bad status", hook.command);
+        }
+    }
+    Ok(())
+}
+
+/// Run the `post_pipeline` hooks of the given pipeline on the given output of the given source
+/// asset, via a unique temporary file in the staging dir ending with the given name, returning
+/// the rewritten output.
+pub async fn rewrite_with_pipeline_hooks(
+    cfg: &RtcBuild,
+    pipeline: &str,
+    source: &Path,
+    file_name: &str,
+    output: Vec<u8>,
+) -> Result<Vec<u8>> {
+    if pipeline_hooks(cfg, pipeline).next().is_none() {
+        return Ok(output);
+    }
+    let id = HOOK_FILES.fetch_add(1, Ordering::Relaxed);
+    let file = cfg.staging_dist.join(format!(".hook-{}-{}", id, file_name));
+    fs::write(&file, output)
+        .await
+        .with_context(|| format!("error writing {:?}", file))?;
+    let res = run_pipeline_hooks(cfg, pipeline, source, &file).await;
+    let output = fs::read(&file)
+        .await
+        .with_context(|| format!("error reading {:?}", file));
+    fs::remove_file(&file)
+        .await
+        .with_context(|| format!("error removing {:?}", file))?;
+    res?;
+    output
+}
+
+/// The `post_pipeline` hooks of the given pipeline.
+fn pipeline_hooks<'a>(cfg: &'a RtcBuild, pipeline: &'a str) -> impl Iterator<Item = &'a Hook> + 'a {
+    cfg.hooks.iter().filter(move |hook| {
+        hook.stage == PipelineStage::PostPipeline && hook.pipeline.as_deref() == Some(pipeline)
+    })
+}
+
+/// Check if the given hook runs for the profile & OS of the build, and the given changed paths.
+fn should_run(cfg: &RtcBuild, hook: &Hook, changes: &[PathBuf]) -> bool {
+    let build_profile = if cfg.release { "release" } else { "debug" };
+    let profile = hook.profiles.as_ref().map_or(true, |profiles| {
+        profiles
+            .iter()
+            .any(|name| name == build_profile || Some(name.as_str()) == cfg.profile.as_deref())
+    });
+    let os = hook
+        .os
+        .as_ref()
+        .map_or(true, |os| os.iter().any(|os| os == std::env::consts::OS));
+    let changed = match &hook.only_if_changed {
+        Some(globs) if !changes.is_empty() => changes.iter().any(|path| {
+            let path = path.strip_prefix(&cfg.target_parent).unwrap_or(path);
+            globs.is_match(path)
+        }),
+        _ => true,
+    };
+    profile && os && changed
+}
+
+/// Validate the given hook, whose `pipeline` is given for `post_pipeline` hooks only.
+fn validate(hook: &ConfigOptsHook) -> Result<()> {
+    match (&hook.stage, &hook.pipeline) {
+        (PipelineStage::PostPipeline, Some(pipeline)) => ensure!(
+            PIPELINES.contains(&pipeline.as_str()),
+            "unknown pipeline {:?} of hook {}, which is one of {}",
+            pipeline,
+            hook.command,
+            PIPELINES.join(", ")
+        ),
+        (PipelineStage::PostPipeline, None) => {
+            bail!("the post_pipeline hook {} has no pipeline", hook.command)
+        }
+        (_, Some(_)) => bail!(
+            "the hook {} has a pipeline, which only post_pipeline hooks have",
+            hook.command
+        ),
+        (_, None) => {}
+    }
+    Ok(())
+}
+
+/// Build the globs of the `only_if_changed` condition of the given hook, if any.
+fn changed_globs(hook: &ConfigOptsHook) -> Result<Option<GlobSet>> {
+    let Some(globs) = &hook.only_if_changed else {
+        return Ok(None);
+    };
+    let mut builder = GlobSetBuilder::new();
+    for glob in globs {
+        builder.add(Glob::new(glob).with_context(|| {
+            format!(
+                "invalid glob {:?} of the only_if_changed of hook {}",
+                glob, hook.command
+            )
+        })?);
+    }
+    Ok(Some(
+        builder
+            .build()
+            .context("error building the only_if_changed globs")?,
+    ))
+}
+
+/// Write the context of the build for the hooks of the given stage to a JSON file of the cargo
+/// target dir, returning its path.
+async fn write_context(cfg: &RtcBuild, stage: PipelineStage) -> Result<PathBuf> {
+    let artifacts = match stage {
+        PipelineStage::PostBuild => collect_artifacts(cfg).await?,
+        PipelineStage::PreBuild | PipelineStage::Build | PipelineStage::PostPipeline => Vec::new(),
+    };
+    let context = HookContext {
+        stage,
+        profile: if cfg.release { "release" } else { "debug" },
+        config_profile: cfg.profile.as_deref(),
+        html_file: &cfg.target,
+        source_dir: &cfg.target_parent,
+        staging_dir: &cfg.staging_dist,
+        dist_dir: &cfg.final_dist,
+        public_url: &cfg.public_url,
+        artifacts,
+    };
+    let dir = cfg.reports_dir().join("hooks");
+    fs::create_dir_all(&dir)
+        .await
+        .with_context(|| format!("error creating dir {:?}", dir))?;
+    let path = dir.join(format!("{}.json", stage.as_str()));
+    let json = serde_json::to_vec_pretty(&context)?;
+    fs::write(&path, json)
+        .await
+        .with_context(|| format!("error writing {:?}", path))?;
+    Ok(path)
+}
+
+/// Collect the files of the staging dir, sorted by path, which are changed if their hash differs
+/// from the one of the asset of the same path of the last successful build, or is missing.
+///
+/// The hashes recorded by the pipelines are reused for the outputs which are unchanged since,
+/// unless `build` hooks may have rewritten them. As the first build has no previous hashes, its
+/// files are compared against the ones of the dist dir instead, which are read only if their
+/// sizes are equal.
+async fn collect_artifacts(cfg: &RtcBuild) -> Result<Vec<HookArtifact>> {
+    let reuse_hashes = !cfg
+        .hooks
+        .iter()
+        .any(|hook| hook.stage == PipelineStage::Build);
+    let hashes = cfg
+        .output_hashes
+        .hash_dir(&cfg.staging_dist, reuse_hashes)
+        .await?;
+    let previous_hashes = cfg.asset_hashes.get();
+    let mut artifacts = Vec::new();
+    for (path, hash) in hashes {
+        let file = cfg.staging_dist.join(&path);
+        let size = fs::metadata(&file)
+            .await
+            .with_context(|| format!("error reading metadata of {:?}", file))?
+            .len();
+        let changed = match previous_hashes.get(&path) {
+            Some(previous) => *previous != hash,
+            None if previous_hashes.is_empty() => {
+                !dist_file_has_hash(&cfg.final_dist.join(&path), size, hash).await
+            }
+            None => true,
+        };
+        artifacts.push(HookArtifact {
+            path,
+            size,
+            changed,
+        });
+    }
+    artifacts.sort_by(|a, b| a.path.cmp(&b.path));
+    Ok(artifacts)
+}
+
+/// Check if the given file of the dist dir has the given size & content hash.
+async fn dist_file_has_hash(file: &Path, size: u64, hash: u64) -> bool {
+    match fs::metadata(file).await {
+        Ok(meta) if meta.len() == size => fs::read(file)
+            .await
+            .is_ok_and(|contents| seahash::hash(&contents) == hash),
+        _ => false,
+    }
 }
 
 /// Waits for all of the given hooks to finish.
@@ -62,3 +407,199 @@ pub async fn wait_hooks(mut futures: HookHandles) -> Result<()> {
 
     Ok(())
 }
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+    use std::path::PathBuf;
+
+    use super::{
+        collect_artifacts, rewrite_with_pipeline_hooks, should_run, validate, Hook, HookArtifact,
+    };
+    use crate::config::{ConfigOptsHook, RtcBuild};
+    use crate::pipelines::PipelineStage;
+
+    #[tokio::test]
+    async fn artifacts_are_changed_unless_equal_in_the_last_build() {
+        let tmpdir = tempfile::tempdir().expect("error building tempdir for test");
+        let cfg = RtcBuild::new_test(tmpdir.path())
+            .await
+            .expect("error building test config");
+        let (staging, dist) = (&cfg.staging_dist, &cfg.final_dist);
+        std::fs::create_dir_all(staging.join("assets")).expect("error creating test dirs");
+        std::fs::write(staging.join("index.html"), "<html></html>").expect("error writing file");
+        std::fs::write(staging.join("assets/app.css"), "body {}").expect("error writing file");
+        std::fs::write(staging.join("app.js"), "new").expect("error writing file");
+        std::fs::write(dist.join("index.html"), "<html></html>").expect("error writing file");
+        std::fs::write(dist.join("app.js"), "old").expect("error writing file");
+        let artifact = |path: &str, size, changed| HookArtifact {
+            path: path.into(),
+            size,
+            changed,
+        };
+
+        // The first build is compared against the dist dir.
+        let artifacts = collect_artifacts(&cfg)
+            .await
+            .expect("error collecting artifacts");
+        assert_eq!(
+            artifacts,
+            [
+                artifact("app.js", 3, true),
+                artifact("assets/app.css", 7, true),
+                artifact("index.html", 13, false),
+            ]
+        );
+
+        // Later builds are compared against the hashes of the last one, not the dist dir.
+        cfg.asset_hashes.set(HashMap::from([
+            ("app.js".to_string(), seahash::hash(b"new")),
+            ("index.html".to_string(), seahash::hash(b"<html>")),
+        ]));
+        let artifacts = collect_artifacts(&cfg)
+            .await
+            .expect("error collecting artifacts");
+        assert_eq!(
+            artifacts,
+            [
+                artifact("app.js", 3, false),
+                artifact("assets/app.css", 7, true),
+                artifact("index.html", 13, true),
+            ]
+        );
+    }
+
+    #[tokio::test]
+    async fn hooks_run_if_their_conditions_are_met() {
+        let tmpdir = tempfile::tempdir().expect("error building tempdir for test");
+        let mut cfg = RtcBuild::new_test(tmpdir.path())
+            .await
+            .expect("error building test config");
+        cfg.profile = Some("staging".into());
+        let hook = ConfigOptsHook {
+            stage: PipelineStage::PostBuild,
+            pipeline: None,
+            command: "optimize-images".into(),
+            command_arguments: Vec::new(),
+            profiles: None,
+            os: None,
+            only_if_changed: None,
+        };
+        let run = |hook: &ConfigOptsHook, changes: &[PathBuf]| {
+            should_run(
+                &cfg,
+                &Hook::new(hook.clone()).expect("error building hook"),
+                changes,
+            )
+        };
+        assert!(run(&hook, &[]));
+
+        let profiles = |profiles: &[&str]| ConfigOptsHook {
+            profiles: Some(profiles.iter().map(|name| name.to_string()).collect()),
+            ..hook.clone()
+        };
+        assert!(run(&profiles(&["staging"]), &[]));
+        assert!(run(&profiles(&["debug"]), &[]));
+        assert!(!run(&profiles(&["release"]), &[]));
+
+        let os = ConfigOptsHook {
+            os: Some(vec!["none".into()]),
+            ..hook.clone()
+        };
+        assert!(!run(&os, &[]));
+
+        let changed = ConfigOptsHook {
+            only_if_changed: Some(vec!["styles/**".into()]),
+            ..hook.clone()
+        };
+        let style = cfg.target_parent.join("styles/main.scss");
+        let source = PathBuf::from("src/main.rs");
+        assert!(run(&changed, &[]));
+        assert!(run(&changed, &[source.clone(), style]));
+        assert!(!run(&changed, &[source]));
+
+        let invalid = ConfigOptsHook {
+            only_if_changed: Some(vec!["styles/[".into()]),
+            ..hook.clone()
+        };
+        assert!(
+            Hook::new(invalid).is_err(),
+            "invalid globs must be rejected at config load"
+        );
+    }
+
+    #[cfg(unix)]
+    #[tokio::test]
+    async fn same_named_assets_are_rewritten_apart() {
+        let tmpdir = tempfile::tempdir().expect("error building tempdir for test");
+        let mut cfg = RtcBuild::new_test(tmpdir.path())
+            .await
+            .expect("error building test config");
+        let hook = ConfigOptsHook {
+            stage: PipelineStage::PostPipeline,
+            pipeline: Some("css".into()),
+            command: "sh".into(),
+            command_arguments: vec![
+                "-c".into(),
+                r#"sleep 0.2 && echo "/* $TRUNK_ASSET_SOURCE */" >> "$TRUNK_ASSET_FILE""#.into(),
+            ],
+            profiles: None,
+            os: None,
+            only_if_changed: None,
+        };
+        cfg.hooks = vec![Hook::new(hook).expect("error building hook")];
+        let (first, second) = (PathBuf::from("a/app.css"), PathBuf::from("b/app.css"));
+
+        let (first_css, second_css) = tokio::join!(
+            rewrite_with_pipeline_hooks(&cfg, "css", &first, "app.css", b"a {}\n".to_vec()),
+            rewrite_with_pipeline_hooks(&cfg, "css", &second, "app.css", b"b {}\n".to_vec()),
+        );
+
+        let first_css = first_css.expect("error rewriting first asset");
+        let second_css = second_css.expect("error rewriting second asset");
+        assert_eq!(
+            String::from_utf8_lossy(&first_css),
+            "a {}\n/* a/app.css */\n"
+        );
+        assert_eq!(
+            String::from_utf8_lossy(&second_css),
+            "b {}\n/* b/app.css */\n"
+        );
+        let leftovers = std::fs::read_dir(&cfg.staging_dist)
+            .expect("error reading staging dir")
+            .count();
+        assert_eq!(leftovers, 0, "the temporary files must be removed");
+    }
+
+    #[test]
+    fn pipelines_are_validated() {
+        let hook = |stage, pipeline: Option<&str>| ConfigOptsHook {
+            stage,
+            pipeline: pipeline.map(Into::into),
+            command: "critical".into(),
+            command_arguments: Vec::new(),
+            profiles: None,
+            os: None,
+            only_if_changed: None,
+        };
+        let err = |hook| {
+            validate(&hook)
+                .expect_err("expected invalid hook")
+                .to_string()
+        };
+        assert!(validate(&hook(PipelineStage::PostPipeline, Some("sass"))).is_ok());
+        assert!(validate(&hook(PipelineStage::Build, None)).is_ok());
+        assert_eq!(
+            err(hook(PipelineStage::PostPipeline, Some("rust"))),
+            r#"unknown pipeline "rust" of hook critical, which is one of css, sass, tailwind-css, js, typescript"#
+        );
+        assert_eq!(
+            err(hook(PipelineStage::PostPipeline, None)),
+            "the post_pipeline hook critical has no pipeline"
+        );
+        assert_eq!(
+            err(hook(PipelineStage::PostBuild, Some("css"))),
+            "the hook critical has a pipeline, which only post_pipeline hooks have"
+        );
+    }
+}
@@ -0,0 +1,82 @@
+//! Build, bundle & ship your Rust WASM application to the web.
+//!
+//! Besides the `trunk` CLI, this crate may be embedded by tools such as Tauri plugins or custom
+//! dev orchestrators, instead of shelling out to the binary. Its API consists of:
+//!
+//! - the [`config`] module, whose `ConfigOpts*` layers give the runtime configs via
+//!   [`ConfigOpts::rtc_build`], [`ConfigOpts::rtc_watch`] & [`ConfigOpts::rtc_serve`], which apply
+//!   the `Trunk.toml` file, the `TRUNK_*` env vars & the given options in the same way as the CLI;
+//! - the [`BuildSystem`], which runs a single build;
+//! - the [`WatchSystem`], which rebuilds on changes;
+//! - the [`ServeSystem`], which rebuilds on changes & serves the dist dir.
+//!
+//! Each of them sends the [`BuildEvent`]s of its builds, i.e. their start, the stages of their
+//! progress & their results, on a broadcast channel, to which `subscribe` subscribes. The watch &
+//! serve systems stop once a value is sent on, or all senders are dropped of, their shutdown
+//! channel.
+//!
+//! ```no_run
+//! use trunk::config::{ConfigOpts, ConfigOptsBuild};
+//! use trunk::{BuildEvent, ServeSystem};
+//!
+//! # async fn serve() -> anyhow::Result<()> {
+//! let cfg = ConfigOpts::rtc_serve(
+//!     ConfigOptsBuild::default(),
+//!     Default::default(),
+//!     Default::default(),
+//!     Some("Trunk.toml".into()),
+//! )
+//! .await?;
+//! let (shutdown_tx, _) = tokio::sync::broadcast::channel(1);
+//! let system = ServeSystem::new(cfg, shutdown_tx.clone()).await?;
+//! let mut events = system.subscribe();
+//! tokio::spawn(async move {
+//!     while let Ok(event) = events.recv().await {
+//!         match event {
+//!             BuildEvent::Started { id, .. } => println!("build {} started", id),
+//!             BuildEvent::Progress { category, name } => println!("{} {}", category, name),
+//!             BuildEvent::Succeeded { artifacts, .. } => println!("built {:?}", artifacts),
+//!             BuildEvent::Failed { errors, .. } => eprintln!("build failed: {:?}", errors),
+//!         }
+//!     }
+//! });
+//! system.run().await
+//! # }
+//! ```
+//!
+//! The other modules, and the ones hidden from the docs, are internals of the CLI, which may
+//! change in any release.
+
+#![deny(clippy::unwrap_used)]
+
+mod asset_manifest;
+pub mod build;
+#[doc(hidden)]
+pub mod cmd;
+mod common;
+pub mod completions;
+pub mod config;
+mod deploy;
+#[doc(hidden)]
+pub mod dotenv;
+mod hooks;
+#[doc(hidden)]
+pub mod logging;
+mod mdns;
+#[doc(hidden)]
+pub mod messages;
+mod pipelines;
+mod proxy;
+mod qr;
+pub mod serve;
+mod size_report;
+mod test_runner;
+mod timings;
+mod tools;
+pub mod watch;
+mod webdriver;
+
+pub use build::{Artifact, BuildEvent, BuildReport, BuildSystem};
+pub use config::ConfigOpts;
+pub use serve::ServeSystem;
+pub use watch::WatchSystem;
@@ -0,0 +1,111 @@
+//! The formats of the logs of Trunk.
+
+use std::fmt::Debug;
+use std::io::Write;
+use std::time::SystemTime;
+
+use clap::ValueEnum;
+use serde_json::{Map, Value};
+use tracing::field::{Field, Visit};
+use tracing::{Event, Subscriber};
+use tracing_subscriber::layer::Context;
+use tracing_subscriber::Layer;
+
+/// The formats of the logs.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, ValueEnum)]
+pub enum LogFormat {
+    /// Compact human readable lines.
+    #[default]
+    Pretty,
+    /// A JSON object per line, with the timestamp, level, message & fields of the event.
+    Json,
+}
+
+/// A layer writing each event as a JSON line to stdout, or stderr.
+pub struct JsonLayer {
+    stderr: bool,
+}
+
+impl JsonLayer {
+    /// Create a new instance, writing to stderr instead of stdout if `stderr` is set.
+    pub fn new(stderr: bool) -> Self {
+        Self { stderr }
+    }
+}
+
+impl<S: Subscriber> Layer<S> for JsonLayer {
+    fn on_event(&self, event: &Event<'_>, _ctx: Context<'_, S>) {
+        let line = json_line(event, SystemTime::now());
+        // NOTE: logging must not fail, so errors of closed pipes are ignored.
+        let _ = if self.stderr {
+            writeln!(std::io::stderr().lock(), "{}", line)
+        } else {
+            writeln!(std::io::stdout().lock(), "{}", line)
+        };
+    }
+}
+
+/// Format the given event as a JSON line.
+fn json_line(event: &Event<'_>, time: SystemTime) -> String {
+    let mut visitor = JsonVisitor::default();
+    event.record(&mut visitor);
+    let meta = event.metadata();
+    let mut line = Map::new();
+    line.insert(
+        "timestamp".into(),
+        humantime::format_rfc3339_millis(time).to_string().into(),
+    );
+    line.insert("level".into(), meta.level().as_str().into());
+    line.insert("target".into(), meta.target().into());
+    line.insert("message".into(), visitor.message.unwrap_or_default().into());
+    if !visitor.fields.is_empty() {
+        line.insert("fields".into(), Value::Object(visitor.fields));
+    }
+    Value::Object(line).to_string()
+}
+
+/// A visitor collecting the message & the other fields of an event.
+#[derive(Default)]
+struct JsonVisitor {
+    message: Option<String>,
+    fields: Map<String, Value>,
+}
+
+impl JsonVisitor {
+    fn insert(&mut self, field: &Field, value: Value) {
+        if field.name() == "message" {
+            self.message = Some(match value {
+                Value::String(message) => message,
+                other => other.to_string(),
+            });
+        } else {
+            self.fields.insert(field.name().to_owned(), value);
+        }
+    }
+}
+
+impl Visit for JsonVisitor {
+    fn record_debug(&mut self, field: &Field, value: &dyn Debug) {
+        self.insert(field, format!("{:?}", value).into());
+    }
+
+    fn record_str(&mut self, field: &Field, value: &str) {
+        self.insert(field, value.into());
+    }
+
+    fn record_i64(&mut self, field: &Field, value: i64) {
+        self.insert(field, value.into());
+    }
+
+    fn record_u64(&mut self, field: &Field, value: u64) {
+        self.insert(field, value.into());
+    }
+
+    fn record_f64(&mut self, field: &Field, value: f64) {
+        self.insert(field, value.into());
+    }
+
+    fn record_bool(&mut self, field: &Field, value: bool) {
+        self.insert(field, value.into());
+    }
+}
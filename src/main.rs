@@ -1,24 +1,20 @@
 #![deny(clippy::unwrap_used)]
 
-mod build;
-mod cmd;
-mod common;
-mod config;
-mod hooks;
-mod pipelines;
-mod proxy;
-mod serve;
-mod tools;
-mod watch;
-
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
 
 use anyhow::{Context, Result};
-use clap::{Parser, Subcommand};
+use clap::{CommandFactory, Parser, Subcommand};
+use tracing_subscriber::fmt::writer::BoxMakeWriter;
 use tracing_subscriber::prelude::*;
+use trunk::logging::{self, LogFormat};
+use trunk::messages::{self, Message, MessageFormat};
+use trunk::{cmd, completions, dotenv};
 
-#[tokio::main]
-async fn main() -> Result<()> {
+fn main() -> Result<()> {
+    // Complete the command line for the completion scripts of the shells, if asked to.
+    clap_complete::CompleteEnv::with_factory(Trunk::command)
+        .var(completions::COMPLETE_VAR)
+        .complete();
     let cli = Trunk::parse();
 
     #[cfg(windows)]
@@ -26,21 +22,52 @@ async fn main() -> Result<()> {
         eprintln!("error enabling ANSI support: {:?}", err);
     }
 
+    // The logs go to stderr when stdout is reserved for the JSON messages.
+    messages::init(cli.message_format);
+    let to_stderr = messages::is_json();
+    let writer = match to_stderr {
+        true => BoxMakeWriter::new(std::io::stderr),
+        false => BoxMakeWriter::new(std::io::stdout),
+    };
+    let (pretty, json) = match cli.log_format {
+        LogFormat::Pretty => (
+            Some(
+                tracing_subscriber::fmt::layer()
+                    .with_writer(writer)
+                    .with_target(false)
+                    .with_level(true)
+                    .compact(),
+            ),
+            None,
+        ),
+        LogFormat::Json => (None, Some(logging::JsonLayer::new(to_stderr))),
+    };
     tracing_subscriber::registry()
         // Filter spans based on the RUST_LOG env var.
         .with(eval_logging(&cli))
-        // Send a copy of all spans to stdout as JSON.
-        .with(
-            tracing_subscriber::fmt::layer()
-                .with_target(false)
-                .with_level(true)
-                .compact(),
-        )
+        // Send a copy of all spans to stdout, or stderr, in the selected format.
+        .with(pretty)
+        .with(json)
         // Install this registry as the global tracing registry.
         .try_init()
         .context("error initializing logging")?;
 
-    cli.run().await
+    // The env files are loaded before the runtime spawns its threads, as setting env vars is not
+    // thread safe.
+    if !cli.no_dotenv {
+        dotenv::load(cli.project_dir())?;
+    }
+    let runtime = tokio::runtime::Builder::new_multi_thread()
+        .enable_all()
+        .build()
+        .context("error building the tokio runtime")?;
+    let res = runtime.block_on(cli.run());
+    if let Err(err) = &res {
+        messages::emit(&Message::Error {
+            errors: messages::error_chain(err),
+        });
+    }
+    res
 }
 
 fn eval_logging(cli: &Trunk) -> tracing_subscriber::EnvFilter {
@@ -58,30 +85,66 @@ fn eval_logging(cli: &Trunk) -> tracing_subscriber::EnvFilter {
 struct Trunk {
     #[command(subcommand)]
     action: TrunkSubcommands,
-    /// Path to the Trunk config file [default: Trunk.toml]
+    /// Path to the Trunk config file, or a Cargo.toml with a [package.metadata.trunk] section
+    /// [default: Trunk.toml]
     #[arg(long, env = "TRUNK_CONFIG", global(true))]
     pub config: Option<PathBuf>,
+    /// Do not load the `.env` & `.env.local` files of the dir of the config file
+    #[arg(long, env = "TRUNK_NO_DOTENV", global(true))]
+    pub no_dotenv: bool,
     /// Enable verbose logging.
     #[arg(short, long, global(true))]
     pub verbose: bool,
     /// Be more quiet, conflicts with --verbose
     #[arg(short, long, global(true), conflicts_with("verbose"))]
     pub quiet: bool,
+    /// The format of the logs
+    #[arg(
+        long,
+        value_enum,
+        default_value_t,
+        env = "TRUNK_LOG_FORMAT",
+        global(true)
+    )]
+    pub log_format: LogFormat,
+    /// The format of the messages of builds & servers, which are printed as JSON lines to stdout
+    /// with `json`, while the logs are written to stderr
+    #[arg(
+        long,
+        value_enum,
+        default_value_t,
+        env = "TRUNK_MESSAGE_FORMAT",
+        global(true)
+    )]
+    pub message_format: MessageFormat,
 }
 
 impl Trunk {
+    /// The dir of the project, which is the dir of the config file.
+    fn project_dir(&self) -> &Path {
+        match self.config.as_deref().and_then(Path::parent) {
+            Some(dir) if !dir.as_os_str().is_empty() => dir,
+            _ => Path::new("."),
+        }
+    }
+
     #[tracing::instrument(level = "trace", skip(self))]
     pub async fn run(self) -> Result<()> {
         match self.action {
             TrunkSubcommands::Build(inner) => inner.run(self.config).await,
             TrunkSubcommands::Clean(inner) => inner.run(self.config).await,
+            TrunkSubcommands::Test(inner) => inner.run(self.config).await,
+            TrunkSubcommands::Deploy(inner) => inner.run(self.config).await,
             TrunkSubcommands::Serve(inner) => inner.run(self.config).await,
             TrunkSubcommands::Watch(inner) => inner.run(self.config).await,
             TrunkSubcommands::Config(inner) => inner.run(self.config).await,
+            TrunkSubcommands::Tools(inner) => inner.run(self.config).await,
+            TrunkSubcommands::Completions(inner) => inner.run(Self::command()).await,
         }
     }
 }
 
+#[allow(clippy::large_enum_variant)]
 #[derive(Subcommand)]
 enum TrunkSubcommands {
     /// Build the Rust WASM app and all of its assets.
@@ -92,8 +155,16 @@ enum TrunkSubcommands {
     Serve(cmd::serve::Serve),
     /// Clean output artifacts.
     Clean(cmd::clean::Clean),
+    /// Build the wasm tests of the crate & run them in a headless browser.
+    Test(cmd::test::Test),
+    /// Build the Rust WASM app in release mode & deploy its dist dir to a static host.
+    Deploy(cmd::deploy::Deploy),
     /// Trunk config controls.
     Config(cmd::config::Config),
+    /// Manage the tools downloaded by Trunk.
+    Tools(cmd::tools::Tools),
+    /// Print the completion script of a shell.
+    Completions(cmd::completions::Completions),
 }
 
 #[cfg(test)]
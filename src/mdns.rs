@@ -0,0 +1,177 @@
+//! The mDNS responder of the server, which announces it in the local network as `<name>.local` &
+//! as a DNS-SD service, so that devices in the network, such as phones & tablets, reach it by name.
+//!
+//! The responder of [mdns-sd](https://docs.rs/mdns-sd) shares the mDNS port with the other
+//! responders of the machine, e.g. Avahi or Bonjour. It probes whether the names are taken by
+//! other devices of the network, renaming the server if so, announces the addresses of each
+//! network interface on that interface only, and says goodbye once the server shuts down.
+
+use std::net::{IpAddr, Ipv4Addr};
+use std::path::Path;
+
+use anyhow::{Context, Result};
+use mdns_sd::{DaemonEvent, ServiceDaemon, ServiceInfo};
+use tokio::sync::broadcast;
+use tokio::task::JoinHandle;
+
+/// The name announced if the dir of the target does not have a valid one.
+const DEFAULT_NAME: &str = "trunk";
+
+/// If the given name is a valid mDNS name, i.e. a DNS label of letters, digits & hyphens.
+pub fn is_valid_name(name: &str) -> bool {
+    (1..=63).contains(&name.len())
+        && name
+            .bytes()
+            .all(|byte| byte.is_ascii_alphanumeric() || byte == b'-')
+        && !name.starts_with('-')
+        && !name.ends_with('-')
+}
+
+/// The default mDNS name of the given dir of the target, i.e. its name in lower case, with hyphens
+/// for the characters other than letters & digits.
+pub fn default_name(dir: &Path) -> String {
+    let name = dir
+        .file_name()
+        .map(|name| name.to_string_lossy().to_ascii_lowercase())
+        .unwrap_or_default()
+        .chars()
+        .map(|c| if c.is_ascii_alphanumeric() { c } else { '-' })
+        .take(63)
+        .collect::<String>();
+    let name = name.trim_matches('-');
+    if is_valid_name(name) {
+        name.to_owned()
+    } else {
+        DEFAULT_NAME.to_owned()
+    }
+}
+
+/// The DNS-SD service of the server announced by the responder.
+#[derive(Debug)]
+pub struct MdnsService(ServiceInfo);
+
+impl MdnsService {
+    /// Create the service of a server of the given name on the given network addresses, which
+    /// serves HTTPS if `tls` is set, and the app on the given path, which is announced in the TXT
+    /// record of the service.
+    pub fn new(
+        name: &str,
+        tls: bool,
+        port: u16,
+        path: &str,
+        addresses: Vec<Ipv4Addr>,
+    ) -> Result<Self> {
+        let service = if tls {
+            "_https._tcp.local."
+        } else {
+            "_http._tcp.local."
+        };
+        let addresses = addresses.into_iter().map(IpAddr::from).collect::<Vec<_>>();
+        let info = ServiceInfo::new(
+            service,
+            name,
+            &format!("{}.local.", name),
+            &addresses[..],
+            port,
+            &[("path", path)][..],
+        )
+        .with_context(|| format!("invalid mDNS name {:?}", name))?;
+        Ok(Self(info))
+    }
+
+    /// The host name of the server, e.g. `myapp.local`, unless it is taken in the network.
+    pub fn host(&self) -> String {
+        self.0.get_hostname().trim_end_matches('.').to_owned()
+    }
+
+    /// Spawn the responder, which announces the server until the given shutdown signal.
+    pub fn spawn(self, mut shutdown_rx: broadcast::Receiver<()>) -> Result<JoinHandle<()>> {
+        let daemon = ServiceDaemon::new().context("error starting mDNS responder")?;
+        let events = daemon
+            .monitor()
+            .context("error monitoring mDNS responder")?;
+        let fullname = self.0.get_fullname().to_owned();
+        daemon
+            .register(self.0)
+            .context("error registering mDNS service")?;
+        Ok(tokio::spawn(async move {
+            loop {
+                tokio::select! {
+                    _ = shutdown_rx.recv() => break,
+                    event = events.recv_async() => match event {
+                        Ok(DaemonEvent::NameChange(change)) => tracing::warn!(
+                            "the mDNS name {} is taken by another device of the network, \
+                             announcing the server as {} instead",
+                            change.original,
+                            change.new_name
+                        ),
+                        Ok(DaemonEvent::Error(err)) => {
+                            tracing::debug!("error of mDNS responder: {}", err)
+                        }
+                        Ok(_) => {}
+                        // The responder stopped, so there is nothing to say goodbye to.
+                        Err(_) => {
+                            let _ = shutdown_rx.recv().await;
+                            return;
+                        }
+                    }
+                }
+            }
+            // Say goodbye, so that the devices of the network drop the records right away.
+            if let Ok(unregistered) = daemon.unregister(&fullname) {
+                let _ = unregistered.recv_async().await;
+            }
+            if let Ok(shutdown) = daemon.shutdown() {
+                let _ = shutdown.recv_async().await;
+            }
+            tracing::debug!("mDNS responder is shutting down");
+        }))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::net::Ipv4Addr;
+    use std::path::Path;
+
+    use anyhow::{ensure, Result};
+
+    use super::{default_name, is_valid_name, MdnsService};
+
+    #[test]
+    fn names_of_target_dirs() {
+        for (dir, name) in [
+            ("/src/site", "site"),
+            ("/src/My_App v2", "my-app-v2"),
+            ("/src/_site_", "site"),
+            ("/", "trunk"),
+            ("/src/日本", "trunk"),
+        ] {
+            assert_eq!(default_name(Path::new(dir)), name, "of {:?}", dir);
+        }
+        assert!(is_valid_name("my-app2"));
+        assert!(!is_valid_name("-app") && !is_valid_name("my.app") && !is_valid_name(""));
+    }
+
+    #[test]
+    fn services_of_servers() -> Result<()> {
+        let addresses = vec![Ipv4Addr::new(192, 168, 1, 23), Ipv4Addr::new(10, 0, 0, 2)];
+        let MdnsService(http) = MdnsService::new("myapp", false, 8080, "/app/", addresses)?;
+        ensure!(http.get_hostname() == "myapp.local.");
+        ensure!(http.get_fullname() == "myapp._http._tcp.local.");
+        ensure!(http.get_port() == 8080);
+        ensure!(http.get_property_val_str("path") == Some("/app/"));
+        let mut addresses = http.get_addresses_v4().into_iter().collect::<Vec<_>>();
+        addresses.sort();
+        ensure!(
+            addresses == [&Ipv4Addr::new(10, 0, 0, 2), &Ipv4Addr::new(192, 168, 1, 23)],
+            "unexpected addresses {:?}",
+            addresses
+        );
+
+        let https = MdnsService::new("myapp", true, 8443, "/", Vec::new())?;
+        ensure!(https.host() == "myapp.local");
+        ensure!(https.0.get_fullname() == "myapp._https._tcp.local.");
+        Ok(())
+    }
+}
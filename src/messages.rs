@@ -0,0 +1,125 @@
+//! The machine-readable messages of `--message-format json`, which are printed as JSON lines to
+//! stdout for IDE extensions & CI wrappers.
+
+use std::fmt::Display;
+use std::io::Write;
+
+use clap::ValueEnum;
+use once_cell::sync::OnceCell;
+use serde::Serialize;
+
+use crate::build::Artifact;
+
+/// The format of the messages, which is set once on start.
+static FORMAT: OnceCell<MessageFormat> = OnceCell::new();
+
+/// The formats of the messages of Trunk.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, ValueEnum)]
+pub enum MessageFormat {
+    /// Only the human readable logs.
+    #[default]
+    Human,
+    /// JSON lines of the messages on stdout, while the logs are written to stderr.
+    Json,
+}
+
+/// Set the format of the messages.
+pub fn init(format: MessageFormat) {
+    let _ = FORMAT.set(format);
+}
+
+/// Whether the messages are printed as JSON.
+pub fn is_json() -> bool {
+    FORMAT.get() == Some(&MessageFormat::Json)
+}
+
+/// A machine-readable message, tagged by its `reason`.
+#[derive(Debug, Serialize)]
+#[serde(tag = "reason", rename_all = "kebab-case")]
+pub enum Message<'a> {
+    /// A build was started.
+    BuildStarted { id: u64 },
+    /// A build succeeded, with all files of the dist dir & the public URLs of the changed ones.
+    BuildFinished {
+        id: u64,
+        duration_ms: u128,
+        artifacts: &'a [Artifact],
+        changed_assets: &'a [String],
+    },
+    /// A build failed, with the chain of its errors.
+    BuildFailed { id: u64, errors: &'a [String] },
+    /// The server is listening at the given URLs.
+    ServerListening { urls: Vec<String> },
+    /// The tests of a test binary printed the given output.
+    TestOutput { binary: &'a str, output: &'a str },
+    /// The tests of a test binary finished.
+    TestFinished { binary: &'a str, passed: bool },
+    /// The command failed, with the chain of its errors.
+    Error { errors: Vec<String> },
+}
+
+/// The chain of the given error, from the outermost context to the root cause.
+pub fn error_chain(err: &anyhow::Error) -> Vec<String> {
+    err.chain().map(ToString::to_string).collect()
+}
+
+/// Print the given human readable output as a line to stdout, or to stderr if stdout is reserved
+/// for the JSON messages.
+pub fn print(output: impl Display) {
+    // NOTE: errors are ignored, e.g. of a closed pipe, as there is nowhere to report them.
+    let _ = if is_json() {
+        writeln!(std::io::stderr().lock(), "{}", output)
+    } else {
+        writeln!(std::io::stdout().lock(), "{}", output)
+    };
+}
+
+/// Print the given message as a JSON line to stdout, if the messages are printed as JSON.
+pub fn emit(message: &Message) {
+    if !is_json() {
+        return;
+    }
+    match serde_json::to_string(message) {
+        Ok(line) => {
+            let mut stdout = std::io::stdout().lock();
+            let _ = writeln!(stdout, "{}", line).and_then(|_| stdout.flush());
+        }
+        Err(err) => tracing::warn!("error serializing message: {}", err),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Message;
+    use crate::build::Artifact;
+
+    #[test]
+    fn serialize_messages() {
+        let changed = ["/app-123.wasm".to_owned()];
+        let message = Message::BuildFinished {
+            id: 2,
+            duration_ms: 1500,
+            artifacts: &[Artifact {
+                path: "app-123.wasm".into(),
+                size: 42,
+            }],
+            changed_assets: &changed,
+        };
+        assert_eq!(
+            serde_json::to_string(&message).expect("error serializing message"),
+            r#"{"reason":"build-finished","id":2,"duration_ms":1500,"artifacts":[{"path":"app-123.wasm","size":42}],"changed_assets":["/app-123.wasm"]}"#
+        );
+        let errors = [
+            "error from HTML pipeline".to_owned(),
+            "missing file".to_owned(),
+        ];
+        let message = Message::BuildFailed {
+            id: 3,
+            errors: &errors,
+        };
+        assert_eq!(
+            serde_json::to_string(&message).expect("error serializing message"),
+            r#"{"reason":"build-failed","id":3,"errors":["error from HTML pipeline","missing file"]}"#
+        );
+    }
+}
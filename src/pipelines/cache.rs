@@ -0,0 +1,326 @@
+//! The cache of the outputs of expensive pipeline steps, such as compiling sass, generating
+//! favicons or running wasm-opt, which later builds reuse while the inputs of the steps are
+//! unchanged.
+//!
+//! The outputs are kept in memory for the next build, and persisted in the cache dir, from which
+//! the builds of later runs of Trunk reuse them. As the cache dir is shared by all projects & may
+//! be restored from elsewhere, e.g. in CI, the persisted outputs are keyed by the SHA-256 digests
+//! of their inputs, and checked against the digests of both on read.
+
+use std::collections::HashMap;
+use std::future::Future;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex, PoisonError};
+use std::time::{Duration, SystemTime};
+
+use anyhow::{Context, Result};
+use directories::ProjectDirs;
+use sha2::{Digest, Sha256};
+use tokio::fs;
+
+/// The name of the dir of the cached outputs in the cache dir of Trunk.
+const PIPELINES_DIR: &str = "pipelines";
+/// The time after which persisted outputs which are not reused are pruned.
+const MAX_AGE: Duration = Duration::from_secs(30 * 24 * 60 * 60);
+/// The time after which the temporary files of interrupted writes are pruned.
+const TMP_MAX_AGE: Duration = Duration::from_secs(24 * 60 * 60);
+/// The total size of the persisted outputs, beyond which the least recently reused are pruned.
+const MAX_SIZE: u64 = 512 * 1024 * 1024;
+/// The length of the SHA-256 digests of the inputs & of the outputs of the persisted files.
+const DIGEST_LEN: usize = 32;
+
+/// The outputs of a cached pipeline step, e.g. the bytes of the files it generated.
+pub type CachedOutputs = Arc<Vec<Vec<u8>>>;
+
+/// The key of the outputs of a pipeline step, the SHA-256 digest of its inputs.
+pub type Key = [u8; DIGEST_LEN];
+
+/// The digest of the inputs of a pipeline step, which keys its outputs in the cache.
+#[derive(Clone)]
+pub struct InputDigest(Sha256);
+
+impl InputDigest {
+    /// Start the digest of the inputs of the given step, e.g. `sass`.
+    ///
+    /// The version of Trunk is an input of all steps, as it may change their outputs.
+    pub fn new(step: &str) -> Self {
+        let mut digest = Self(Sha256::new());
+        digest.add(env!("CARGO_PKG_VERSION")).add(step.as_bytes());
+        digest
+    }
+
+    /// Add the given input, such as an option of the step.
+    pub fn add(&mut self, input: impl AsRef<[u8]>) -> &mut Self {
+        let input = input.as_ref();
+        // Prefix the length, so that the boundaries of the inputs are part of the digest.
+        self.0.update((input.len() as u64).to_le_bytes());
+        self.0.update(input);
+        self
+    }
+
+    /// Add the path & contents of the given file.
+    pub async fn add_file(&mut self, path: &Path) -> Result<&mut Self> {
+        let contents = fs::read(path)
+            .await
+            .with_context(|| format!("error reading input {:?}", path))?;
+        self.add(path.to_string_lossy().as_bytes());
+        Ok(self.add(contents))
+    }
+
+    /// The key of the digested inputs.
+    pub fn finish(&self) -> Key {
+        self.0.clone().finalize().into()
+    }
+}
+
+/// The cache of the outputs of the pipeline steps, by the digests of their inputs.
+///
+/// The outputs of the steps of a build are kept in memory for the next one, while the ones not
+/// reused by a build are dropped once the next one starts. If the cache has a dir, the outputs are
+/// persisted in it as well.
+#[derive(Debug)]
+pub struct PipelineCache {
+    generations: Mutex<Generations>,
+    dir: Option<PathBuf>,
+    /// The total size of the persisted outputs, beyond which they are pruned.
+    max_size: u64,
+}
+
+impl Default for PipelineCache {
+    fn default() -> Self {
+        Self {
+            generations: Default::default(),
+            dir: None,
+            max_size: MAX_SIZE,
+        }
+    }
+}
+
+/// The cached outputs of the current & of the previous build.
+#[derive(Debug, Default)]
+struct Generations {
+    current: HashMap<Key, CachedOutputs>,
+    previous: HashMap<Key, CachedOutputs>,
+}
+
+impl PipelineCache {
+    /// Create a new instance, persisting the outputs in the given dir, or in the cache dir of
+    /// Trunk.
+    pub fn new(dir: Option<PathBuf>) -> Self {
+        let dir = dir.or_else(|| {
+            ProjectDirs::from("dev", "trunkrs", "trunk")
+                .map(|dirs| dirs.cache_dir().join(PIPELINES_DIR))
+        });
+        Self {
+            generations: Default::default(),
+            dir,
+            max_size: MAX_SIZE,
+        }
+    }
+
+    /// Set the total size of the persisted outputs, beyond which they are pruned.
+    #[cfg(test)]
+    pub fn with_max_size(mut self, max_size: u64) -> Self {
+        self.max_size = max_size;
+        self
+    }
+
+    /// The dir in which the outputs are persisted, if any.
+    pub fn dir(&self) -> Option<&Path> {
+        self.dir.as_deref()
+    }
+
+    /// Start a new build, dropping the outputs which the previous build did not reuse.
+    pub fn start_build(&self) {
+        let mut generations = self
+            .generations
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner);
+        generations.previous = std::mem::take(&mut generations.current);
+    }
+
+    /// Get the cached outputs of the given inputs, or run the step & cache its outputs.
+    pub async fn get_or_run<F, Fut>(
+        &self,
+        label: &str,
+        digest: &InputDigest,
+        run: F,
+    ) -> Result<CachedOutputs>
+    where
+        F: FnOnce() -> Fut,
+        Fut: Future<Output = Result<Vec<Vec<u8>>>>,
+    {
+        let key = digest.finish();
+        if let Some(outputs) = self.get(key) {
+            tracing::info!("inputs unchanged, reusing cached {}", label);
+            return Ok(outputs);
+        }
+        if let Some(outputs) = self.read(key).await {
+            tracing::info!("inputs unchanged, reusing persisted {}", label);
+            return Ok(self.insert(key, outputs));
+        }
+        let outputs = run().await?;
+        // The cache is an optimization only, so errors of persisting outputs do not fail builds.
+        if let Err(err) = self.write(key, &outputs).await {
+            tracing::warn!("error persisting cached {}: {:?}", label, err);
+        }
+        Ok(self.insert(key, outputs))
+    }
+
+    /// Get the cached outputs of the given key, which are kept for the next build.
+    fn get(&self, key: Key) -> Option<CachedOutputs> {
+        let mut generations = self
+            .generations
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner);
+        if let Some(outputs) = generations.current.get(&key) {
+            return Some(outputs.clone());
+        }
+        let outputs = generations.previous.remove(&key)?;
+        generations.current.insert(key, outputs.clone());
+        Some(outputs)
+    }
+
+    /// Cache the given outputs of the given key for the next build.
+    fn insert(&self, key: Key, outputs: Vec<Vec<u8>>) -> CachedOutputs {
+        let outputs = Arc::new(outputs);
+        self.generations
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .current
+            .insert(key, outputs.clone());
+        outputs
+    }
+
+    /// The file of the persisted outputs of the given key, if the outputs are persisted.
+    fn file(&self, key: Key) -> Option<PathBuf> {
+        let name = key
+            .iter()
+            .map(|byte| format!("{:02x}", byte))
+            .collect::<String>();
+        Some(self.dir.as_ref()?.join(name))
+    }
+
+    /// Read the persisted outputs of the given key, if any, marking them as reused.
+    async fn read(&self, key: Key) -> Option<Vec<Vec<u8>>> {
+        let file = self.file(key)?;
+        let bytes = fs::read(&file).await.ok()?;
+        let Some(outputs) = decode(&key, &bytes) else {
+            tracing::debug!("ignoring corrupt cache file {:?}", file);
+            return None;
+        };
+        // Refresh the modification time, by which unused outputs are pruned.
+        let _ = std::fs::File::options()
+            .append(true)
+            .open(&file)
+            .and_then(|file| file.set_modified(SystemTime::now()));
+        Some(outputs)
+    }
+
+    /// Persist the given outputs of the given key, if the outputs are persisted.
+    async fn write(&self, key: Key, outputs: &[Vec<u8>]) -> Result<()> {
+        let (Some(dir), Some(file)) = (&self.dir, self.file(key)) else {
+            return Ok(());
+        };
+        fs::create_dir_all(dir)
+            .await
+            .with_context(|| format!("error creating cache dir {:?}", dir))?;
+        // Write to a temporary file first, so that other builds never read partial outputs.
+        let tmp = file.with_extension(format!("{}.tmp", std::process::id()));
+        fs::write(&tmp, encode(&key, outputs))
+            .await
+            .with_context(|| format!("error writing cache file {:?}", tmp))?;
+        if let Err(err) = fs::rename(&tmp, &file).await {
+            let _ = fs::remove_file(&tmp).await;
+            return Err(err).with_context(|| format!("error moving cache file to {:?}", file));
+        }
+        Ok(())
+    }
+
+    /// Remove the persisted outputs which were not reused within the last 30 days, the least
+    /// recently reused ones beyond the total size of 512 MiB, and the temporary files of
+    /// interrupted writes.
+    pub async fn prune(&self) -> Result<()> {
+        let Some(dir) = &self.dir else {
+            return Ok(());
+        };
+        let mut entries = match fs::read_dir(dir).await {
+            Ok(entries) => entries,
+            Err(err) if err.kind() == std::io::ErrorKind::NotFound => return Ok(()),
+            Err(err) => return Err(err).with_context(|| format!("error reading {:?}", dir)),
+        };
+        let now = SystemTime::now();
+        let mut kept = Vec::new();
+        while let Some(entry) = entries
+            .next_entry()
+            .await
+            .with_context(|| format!("error reading contents of {:?}", dir))?
+        {
+            let path = entry.path();
+            let Ok((modified, size)) = entry
+                .metadata()
+                .await
+                .and_then(|meta| Ok((meta.modified()?, meta.len())))
+            else {
+                continue;
+            };
+            let age = now.duration_since(modified).unwrap_or_default();
+            let is_tmp = path.extension().is_some_and(|ext| ext == "tmp");
+            if age > if is_tmp { TMP_MAX_AGE } else { MAX_AGE } {
+                tracing::debug!("pruning cache file {:?}", path);
+                let _ = fs::remove_file(&path).await;
+            } else if !is_tmp {
+                kept.push((modified, size, path));
+            }
+        }
+        // Keep the most recently reused outputs within the total size.
+        kept.sort_by_key(|(modified, ..)| std::cmp::Reverse(*modified));
+        let mut total = 0u64;
+        for (_, size, path) in kept {
+            total = total.saturating_add(size);
+            if total > self.max_size {
+                tracing::debug!("pruning cache file {:?} beyond the size of the cache", path);
+                let _ = fs::remove_file(&path).await;
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Encode the given outputs of the given key, as the key, the digest of the encoded outputs, and
+/// each output as its length in bytes & its bytes.
+fn encode(key: &Key, outputs: &[Vec<u8>]) -> Vec<u8> {
+    let len = 2 * DIGEST_LEN + outputs.iter().map(|output| output.len() + 8).sum::<usize>();
+    let mut bytes = Vec::with_capacity(len);
+    bytes.extend(key);
+    bytes.extend([0; DIGEST_LEN]);
+    for output in outputs {
+        bytes.extend((output.len() as u64).to_le_bytes());
+        bytes.extend(output);
+    }
+    let digest = Sha256::digest(&bytes[2 * DIGEST_LEN..]);
+    bytes[DIGEST_LEN..2 * DIGEST_LEN].copy_from_slice(&digest);
+    bytes
+}
+
+/// Decode the given encoded outputs of the given key, if they are the ones of the key and are not
+/// corrupt.
+fn decode(key: &Key, bytes: &[u8]) -> Option<Vec<Vec<u8>>> {
+    let (encoded_key, rest) = bytes.split_first_chunk::<DIGEST_LEN>()?;
+    let (digest, mut bytes) = rest.split_first_chunk::<DIGEST_LEN>()?;
+    if encoded_key != key || Sha256::digest(bytes)[..] != digest[..] {
+        return None;
+    }
+    let mut outputs = Vec::new();
+    while !bytes.is_empty() {
+        let (len, rest) = bytes.split_first_chunk::<8>()?;
+        let len = usize::try_from(u64::from_le_bytes(*len)).ok()?;
+        if rest.len() < len {
+            return None;
+        }
+        let (output, rest) = rest.split_at(len);
+        outputs.push(output.to_vec());
+        bytes = rest;
+    }
+    Some(outputs)
+}
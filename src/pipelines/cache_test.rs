@@ -0,0 +1,191 @@
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::time::{Duration, SystemTime};
+
+use anyhow::{Context, Result};
+
+use crate::pipelines::cache::*;
+
+#[tokio::test]
+async fn outputs_are_reused_while_inputs_are_unchanged() -> Result<()> {
+    // Assemble.
+    let cache = PipelineCache::default();
+    let runs = AtomicUsize::new(0);
+    let run = |digest: InputDigest| {
+        let cache = &cache;
+        let runs = &runs;
+        async move {
+            cache
+                .get_or_run("test step", &digest, || async {
+                    runs.fetch_add(1, Ordering::SeqCst);
+                    Ok(vec![b"output".to_vec()])
+                })
+                .await
+        }
+    };
+    let digest = |input: &str| {
+        let mut digest = InputDigest::new("test");
+        digest.add(input);
+        digest
+    };
+
+    // Action & Assert.
+    cache.start_build();
+    assert_eq!(run(digest("a")).await?[0], b"output");
+    run(digest("a")).await?;
+    assert_eq!(
+        runs.load(Ordering::SeqCst),
+        1,
+        "unchanged inputs must be reused"
+    );
+    run(digest("b")).await?;
+    assert_eq!(runs.load(Ordering::SeqCst), 2, "changed inputs must be run");
+
+    // The outputs of a build are kept for the next one, which reuses only those of `b`.
+    cache.start_build();
+    run(digest("b")).await?;
+    assert_eq!(runs.load(Ordering::SeqCst), 2);
+    cache.start_build();
+    run(digest("a")).await?;
+    assert_eq!(
+        runs.load(Ordering::SeqCst),
+        3,
+        "outputs not reused by a build are dropped"
+    );
+    Ok(())
+}
+
+#[test]
+fn input_boundaries_are_digested() {
+    let mut ab_c = InputDigest::new("test");
+    ab_c.add("ab").add("c");
+    let mut a_bc = InputDigest::new("test");
+    a_bc.add("a").add("bc");
+    assert_ne!(ab_c.finish(), a_bc.finish());
+}
+
+#[tokio::test]
+async fn outputs_are_persisted_for_later_runs() -> Result<()> {
+    // Assemble.
+    let tmpdir = tempfile::tempdir().context("error building tempdir for test")?;
+    let mut digest = InputDigest::new("test");
+    digest.add("input");
+    let outputs = vec![b"first".to_vec(), Vec::new(), b"third".to_vec()];
+    PipelineCache::new(Some(tmpdir.path().to_owned()))
+        .get_or_run("test step", &digest, || async { Ok(outputs.clone()) })
+        .await?;
+
+    // Action.
+    let cache = PipelineCache::new(Some(tmpdir.path().to_owned()));
+    let persisted = cache
+        .get_or_run("test step", &digest, || async {
+            anyhow::bail!("persisted outputs must be reused")
+        })
+        .await?;
+
+    // Assert.
+    assert_eq!(*persisted, outputs);
+    let file = persisted_files(tmpdir.path())?
+        .pop()
+        .context("expected the persisted file")?;
+    std::fs::write(&file, b"corrupt").context("error corrupting cache file")?;
+    let cache = PipelineCache::new(Some(tmpdir.path().to_owned()));
+    let rerun = cache
+        .get_or_run("test step", &digest, || async {
+            Ok(vec![b"rerun".to_vec()])
+        })
+        .await?;
+    assert_eq!(*rerun, [b"rerun".to_vec()], "corrupt files must be rerun");
+    Ok(())
+}
+
+/// The persisted files of the given cache dir, sorted.
+fn persisted_files(dir: &Path) -> Result<Vec<PathBuf>> {
+    let mut files = std::fs::read_dir(dir)?
+        .map(|entry| Ok(entry?.path()))
+        .collect::<Result<Vec<_>>>()?;
+    files.sort();
+    Ok(files)
+}
+
+/// Persist the given output of the given input in the given cache dir, returning its digest.
+async fn persist(dir: &Path, input: &str, output: &[u8]) -> Result<InputDigest> {
+    let mut digest = InputDigest::new("test");
+    digest.add(input);
+    PipelineCache::new(Some(dir.to_owned()))
+        .get_or_run("test step", &digest, || async { Ok(vec![output.to_vec()]) })
+        .await?;
+    Ok(digest)
+}
+
+#[tokio::test]
+async fn persisted_outputs_of_other_inputs_are_rerun() -> Result<()> {
+    // Assemble.
+    let tmpdir = tempfile::tempdir().context("error building tempdir for test")?;
+    let a = persist(tmpdir.path(), "a", b"output of a").await?;
+    let [file_a] = &persisted_files(tmpdir.path())?[..] else {
+        anyhow::bail!("expected one persisted file");
+    };
+    let b = persist(tmpdir.path(), "b", b"output of b").await?;
+    let file_b = persisted_files(tmpdir.path())?
+        .into_iter()
+        .find(|file| file != file_a)
+        .context("expected the persisted file of b")?;
+    // Swap the contents of the files, as a stale or tampered cache dir may have them.
+    let contents_a = std::fs::read(file_a)?;
+    std::fs::copy(&file_b, file_a)?;
+    std::fs::write(&file_b, contents_a)?;
+
+    // Action.
+    let cache = PipelineCache::new(Some(tmpdir.path().to_owned()));
+    let rerun_a = cache
+        .get_or_run("test step", &a, || async { Ok(vec![b"rerun a".to_vec()]) })
+        .await?;
+    let rerun_b = cache
+        .get_or_run("test step", &b, || async { Ok(vec![b"rerun b".to_vec()]) })
+        .await?;
+
+    // Assert.
+    assert_eq!(*rerun_a, [b"rerun a".to_vec()]);
+    assert_eq!(*rerun_b, [b"rerun b".to_vec()]);
+    Ok(())
+}
+
+#[tokio::test]
+async fn persisted_outputs_are_pruned_beyond_the_max_size() -> Result<()> {
+    // Assemble.
+    let tmpdir = tempfile::tempdir().context("error building tempdir for test")?;
+    let output = [0; 1000];
+    let old = persist(tmpdir.path(), "old", &output).await?;
+    let [old_file] = &persisted_files(tmpdir.path())?[..] else {
+        anyhow::bail!("expected one persisted file");
+    };
+    std::fs::File::options()
+        .append(true)
+        .open(old_file)?
+        .set_modified(SystemTime::now() - Duration::from_secs(60))?;
+    let new = persist(tmpdir.path(), "new", &output).await?;
+    let cache = PipelineCache::new(Some(tmpdir.path().to_owned())).with_max_size(1500);
+
+    // Action.
+    cache.prune().await?;
+
+    // Assert.
+    let files = persisted_files(tmpdir.path())?;
+    assert_eq!(
+        files.len(),
+        1,
+        "the least recently used file must be pruned"
+    );
+    assert!(!files.contains(old_file));
+    cache
+        .get_or_run("test step", &new, || async {
+            anyhow::bail!("the recently used outputs must be kept")
+        })
+        .await?;
+    let rerun = cache
+        .get_or_run("test step", &old, || async { Ok(vec![b"rerun".to_vec()]) })
+        .await?;
+    assert_eq!(*rerun, [b"rerun".to_vec()]);
+    Ok(())
+}
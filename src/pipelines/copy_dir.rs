@@ -1,16 +1,24 @@
 //! Copy-dir asset pipeline.
 
-use std::path::{Component, PathBuf};
+use std::path::{Component, Path, PathBuf};
 use std::sync::Arc;
 
 use anyhow::{Context, Result};
+use globset::{GlobBuilder, GlobSet, GlobSetBuilder};
 use nipper::Document;
 use tokio::fs;
 use tokio::task::JoinHandle;
 
 use super::{Attrs, TrunkAssetPipelineOutput, ATTR_HREF};
-use crate::common::copy_dir_recursive;
-use crate::config::RtcBuild;
+use crate::common::{copy_dir_recursive, copy_file};
+use crate::config::{CopyStrategy, RtcBuild};
+
+/// The attribute of the globs of the files to copy.
+const ATTR_INCLUDE: &str = "data-include";
+/// The attribute of the globs of the files not to copy.
+const ATTR_EXCLUDE: &str = "data-exclude";
+/// The attribute to hash the names of the copied files.
+const ATTR_HASH: &str = "data-hash";
 
 /// A CopyDir asset pipeline.
 pub struct CopyDir {
@@ -22,6 +30,12 @@ pub struct CopyDir {
     path: PathBuf,
     /// Optional target path inside the dist dir.
     target_path: Option<PathBuf>,
+    /// The globs of the files to copy, if not all of them.
+    include: Option<GlobSet>,
+    /// The globs of the files not to copy, if any.
+    exclude: Option<GlobSet>,
+    /// If the names of the copied files should be hashed.
+    hash: bool,
 }
 
 impl CopyDir {
@@ -46,12 +60,24 @@ impl CopyDir {
             .get("data-target-path")
             .map(|val| val.parse())
             .transpose()?;
+        let include = attrs
+            .get(ATTR_INCLUDE)
+            .map(|globs| build_globs(ATTR_INCLUDE, globs))
+            .transpose()?;
+        let exclude = attrs
+            .get(ATTR_EXCLUDE)
+            .map(|globs| build_globs(ATTR_EXCLUDE, globs))
+            .transpose()?;
+        let hash = attrs.contains_key(ATTR_HASH);
 
         Ok(Self {
             id,
             cfg,
             path,
             target_path,
+            include,
+            exclude,
+            hash,
         })
     }
 
@@ -87,7 +113,25 @@ impl CopyDir {
         } else {
             self.cfg.staging_dist.join(dir_name)
         };
-        copy_dir_recursive(canonical_path, dir_out).await?;
+        let strategy = self.cfg.copy_strategy;
+        if self.include.is_none()
+            && self.exclude.is_none()
+            && !self.hash
+            && strategy == CopyStrategy::Copy
+        {
+            copy_dir_recursive(canonical_path, dir_out).await?;
+        } else {
+            let filter = CopyFilter {
+                include: self.include,
+                exclude: self.exclude,
+                cfg: self.hash.then(|| self.cfg.clone()),
+                strategy,
+            };
+            tokio::task::spawn_blocking(move || filter.copy(&canonical_path, &dir_out))
+                .await
+                .context("error awaiting spawned copy dir call")?
+                .context("error copying directory")?;
+        }
 
         tracing::info!(path = ?rel_path, "finished copying directory");
         Ok(TrunkAssetPipelineOutput::CopyDir(CopyDirOutput(self.id)))
@@ -103,3 +147,106 @@ impl CopyDirOutput {
         Ok(())
     }
 }
+
+/// Build the glob set of the given whitespace-separated globs of an attribute.
+///
+/// Globs without a `/` match the file names, others the paths relative to the copied dir.
+fn build_globs(attr: &str, globs: &str) -> Result<GlobSet> {
+    globs
+        .split_whitespace()
+        .try_fold(GlobSetBuilder::new(), |mut builder, glob| -> Result<_> {
+            let glob = if glob.contains('/') {
+                glob.trim_start_matches('/').to_owned()
+            } else {
+                format!("**/{}", glob)
+            };
+            builder.add(
+                GlobBuilder::new(&glob)
+                    .literal_separator(true)
+                    .build()
+                    .with_context(|| format!("invalid glob {:?} of `{}`", glob, attr))?,
+            );
+            Ok(builder)
+        })?
+        .build()
+        .with_context(|| format!("error building globs of `{}`", attr))
+}
+
+/// A filtered copy of a dir, which optionally hashes the names of the copied files, and may link
+/// them instead of copying them.
+pub struct CopyFilter {
+    /// The globs of the files to copy, if not all of them.
+    pub include: Option<GlobSet>,
+    /// The globs of the files not to copy, if any.
+    pub exclude: Option<GlobSet>,
+    /// The runtime build config naming the hashed files, if the names are to be hashed.
+    pub cfg: Option<Arc<RtcBuild>>,
+    /// How the files are copied.
+    pub strategy: CopyStrategy,
+}
+
+impl CopyFilter {
+    /// Check if the file of the given path relative to the copied dir is to be copied.
+    pub fn is_copied(&self, rel_path: &Path) -> bool {
+        self.include
+            .as_ref()
+            .map_or(true, |include| include.is_match(rel_path))
+            && !self
+                .exclude
+                .as_ref()
+                .is_some_and(|exclude| exclude.is_match(rel_path))
+    }
+
+    /// Copy the files of the given dir to the given output dir, keeping their relative paths.
+    pub fn copy(&self, from_dir: &Path, to_dir: &Path) -> Result<()> {
+        self.copy_dir(from_dir, Path::new(""), to_dir)
+    }
+
+    fn copy_dir(&self, from_dir: &Path, rel_dir: &Path, to_dir: &Path) -> Result<()> {
+        let entries = std::fs::read_dir(from_dir.join(rel_dir))
+            .with_context(|| format!("error reading dir {:?}", from_dir.join(rel_dir)))?;
+        for entry in entries {
+            let entry = entry.context("error reading dir entry")?;
+            let rel_path = rel_dir.join(entry.file_name());
+            let path = entry.path();
+            if path.is_dir() {
+                self.copy_dir(from_dir, &rel_path, to_dir)?;
+                continue;
+            }
+            if !self.is_copied(&rel_path) {
+                continue;
+            }
+
+            let Some(cfg) = &self.cfg else {
+                let out = to_dir.join(&rel_path);
+                create_parent_dir(&out)?;
+                copy_file(self.strategy, &path, &out)?;
+                continue;
+            };
+            let bytes =
+                std::fs::read(&path).with_context(|| format!("error reading file {:?}", path))?;
+            let stem = rel_path.file_stem().unwrap_or_default().to_string_lossy();
+            let mut name = cfg.hashed_name(&stem, seahash::hash(&bytes));
+            if let Some(ext) = rel_path.extension() {
+                name = format!("{}.{}", name, ext.to_string_lossy());
+            }
+            let out = to_dir.join(rel_dir).join(name);
+            create_parent_dir(&out)?;
+            match self.strategy {
+                CopyStrategy::Copy => std::fs::write(&out, bytes)
+                    .with_context(|| format!("error writing file {:?}", out))?,
+                strategy => copy_file(strategy, &path, &out)?,
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Create the parent dir of the given file, if it does not exist.
+fn create_parent_dir(file: &Path) -> Result<()> {
+    if let Some(parent) = file.parent() {
+        std::fs::create_dir_all(parent)
+            .with_context(|| format!("error creating dir {:?}", parent))?;
+    }
+    Ok(())
+}
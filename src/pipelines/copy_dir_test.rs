@@ -4,7 +4,7 @@ use std::sync::Arc;
 
 use anyhow::{Context, Result};
 
-use crate::config::RtcBuild;
+use crate::config::{CopyStrategy, RtcBuild};
 use crate::pipelines::copy_dir::*;
 use crate::pipelines::ATTR_HREF;
 
@@ -140,3 +140,104 @@ async fn ok_run_target_path_copy() -> Result<()> {
 
     Ok(())
 }
+
+#[tokio::test]
+async fn ok_run_filtered_hashed_copy() -> Result<()> {
+    // Assemble.
+    let (tmpdir, cfg, asset_dir) = setup_test_config().await?;
+    for file in ["img/a.webp", "img/a.psd", "drafts/b.webp", ".DS_Store"] {
+        let path = asset_dir.join(file);
+        if let Some(parent) = path.parent() {
+            tokio::fs::create_dir_all(parent)
+                .await
+                .context("error creating test dir")?;
+        }
+        tokio::fs::write(&path, file)
+            .await
+            .context("error writing test file contents")?;
+    }
+    let mut attrs = HashMap::new();
+    attrs.insert(ATTR_HREF.into(), "test_dir".into());
+    attrs.insert("data-include".into(), "*.webp".into());
+    attrs.insert("data-exclude".into(), "drafts/**".into());
+    attrs.insert("data-hash".into(), "".into());
+    let cmd = CopyDir::new(cfg.clone(), Arc::new(tmpdir.into_path()), attrs, 0)
+        .await
+        .context("error constructing CopyDir pipeline")?;
+
+    // Action.
+    let _out = cmd
+        .spawn()
+        .await
+        .context("unexpected task join error from pipeline")?
+        .context("unexpected pipeline error")?;
+
+    // Assert.
+    let copy_location_dir = cfg.staging_dist.join("test_dir");
+    let mut copied = vec![];
+    let mut dirs = vec![copy_location_dir.clone()];
+    while let Some(dir) = dirs.pop() {
+        let mut entries = tokio::fs::read_dir(&dir)
+            .await
+            .context("error reading copied dir")?;
+        while let Some(entry) = entries.next_entry().await? {
+            if entry.file_type().await?.is_dir() {
+                dirs.push(entry.path());
+            } else {
+                copied.push(entry.path().strip_prefix(&copy_location_dir)?.to_owned());
+            }
+        }
+    }
+    let hash = format!("{:x}", seahash::hash(b"img/a.webp"));
+    anyhow::ensure!(
+        copied == [PathBuf::from(format!("img/a-{}.webp", hash))],
+        "unexpected copied files {:?}",
+        copied
+    );
+
+    Ok(())
+}
+
+#[cfg(unix)]
+#[tokio::test]
+async fn ok_run_hardlinked_copy() -> Result<()> {
+    use std::os::unix::fs::MetadataExt;
+
+    // Assemble.
+    let tmpdir = tempfile::tempdir().context("error building tempdir for test")?;
+    let mut cfg = RtcBuild::new_test(tmpdir.path()).await?;
+    cfg.copy_strategy = CopyStrategy::Hardlink;
+    let cfg = Arc::new(cfg);
+    let asset_file = tmpdir.path().join("test_dir/models/model.bin");
+    tokio::fs::create_dir_all(asset_file.parent().context("file without parent")?)
+        .await
+        .context("error creating test dir")?;
+    tokio::fs::write(&asset_file, b"weights")
+        .await
+        .context("error writing test file contents")?;
+    let mut attrs = HashMap::new();
+    attrs.insert(ATTR_HREF.into(), "test_dir".into());
+    let cmd = CopyDir::new(cfg.clone(), Arc::new(tmpdir.path().to_owned()), attrs, 0)
+        .await
+        .context("error constructing CopyDir pipeline")?;
+
+    // Action.
+    let _out = cmd
+        .spawn()
+        .await
+        .context("unexpected task join error from pipeline")?
+        .context("unexpected pipeline error")?;
+
+    // Assert.
+    let linked = cfg.staging_dist.join("test_dir/models/model.bin");
+    let (orig, linked) = (
+        tokio::fs::metadata(&asset_file).await?,
+        tokio::fs::metadata(&linked).await?,
+    );
+    anyhow::ensure!(
+        orig.ino() == linked.ino() && orig.dev() == linked.dev(),
+        "expected the copied file to be a hard link of the source file"
+    );
+
+    Ok(())
+}
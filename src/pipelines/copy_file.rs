@@ -7,8 +7,9 @@ use anyhow::{Context, Result};
 use nipper::Document;
 use tokio::task::JoinHandle;
 
-use crate::config::RtcBuild;
-use crate::pipelines::{AssetFile, Attrs, TrunkAssetPipelineOutput, ATTR_HREF};
+use crate::common::copy_file;
+use crate::config::{CopyStrategy, RtcBuild};
+use crate::pipelines::{AssetFile, AssetFileType, Attrs, TrunkAssetPipelineOutput, ATTR_HREF};
 
 /// A CopyFile asset pipeline.
 pub struct CopyFile {
@@ -50,18 +51,40 @@ impl CopyFile {
     async fn run(self) -> Result<TrunkAssetPipelineOutput> {
         let rel_path = crate::common::strip_prefix(&self.asset.path);
         tracing::info!(path = ?rel_path, "copying file");
-        let _ = self.asset.copy(&self.cfg.staging_dist, false).await?;
+        let file = match self.cfg.copy_strategy {
+            CopyStrategy::Copy => {
+                self.asset
+                    .copy(&self.cfg, false, false, AssetFileType::Other)
+                    .await?
+            }
+            strategy => {
+                let file = self.asset.file_name.to_string_lossy().into_owned();
+                let (from, to) = (self.asset.path.clone(), self.cfg.staging_dist.join(&file));
+                tokio::task::spawn_blocking(move || copy_file(strategy, &from, &to))
+                    .await
+                    .context("error awaiting spawned copy file call")??;
+                file
+            }
+        };
         tracing::info!(path = ?rel_path, "finished copying file");
-        Ok(TrunkAssetPipelineOutput::CopyFile(CopyFileOutput(self.id)))
+        Ok(TrunkAssetPipelineOutput::CopyFile(CopyFileOutput {
+            id: self.id,
+            file,
+        }))
     }
 }
 
 /// The output of a CopyFile build pipeline.
-pub struct CopyFileOutput(usize);
+pub struct CopyFileOutput {
+    /// The ID of this pipeline.
+    pub id: usize,
+    /// Name of the copied file.
+    pub file: String,
+}
 
 impl CopyFileOutput {
     pub async fn finalize(self, dom: &mut Document) -> Result<()> {
-        dom.select(&super::trunk_id_selector(self.0)).remove();
+        dom.select(&super::trunk_id_selector(self.id)).remove();
         Ok(())
     }
 }
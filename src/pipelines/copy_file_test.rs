@@ -4,7 +4,7 @@ use std::sync::Arc;
 
 use anyhow::{Context, Result};
 
-use crate::config::RtcBuild;
+use crate::config::{CopyStrategy, RtcBuild};
 use crate::pipelines::copy_file::*;
 use crate::pipelines::ATTR_HREF;
 
@@ -90,3 +90,42 @@ async fn ok_run_basic_copy() -> Result<()> {
 
     Ok(())
 }
+
+#[tokio::test]
+async fn ok_run_reflinked_copy() -> Result<()> {
+    // Assemble.
+    let tmpdir = tempfile::tempdir().context("error building tempdir for test")?;
+    let mut cfg = RtcBuild::new_test(tmpdir.path()).await?;
+    cfg.copy_strategy = CopyStrategy::Reflink;
+    let cfg = Arc::new(cfg);
+    tokio::fs::create_dir_all(&cfg.staging_dist)
+        .await
+        .context("error creating staging dist dir")?;
+    tokio::fs::write(tmpdir.path().join("test_file"), b"abc123")
+        .await
+        .context("error writing test file contents")?;
+    let mut attrs = HashMap::new();
+    attrs.insert(ATTR_HREF.into(), "test_file".into());
+    let cmd = CopyFile::new(cfg.clone(), Arc::new(tmpdir.path().to_owned()), attrs, 0)
+        .await
+        .context("error constructing CopyFile pipeline")?;
+
+    // Action.
+    let _out = cmd
+        .spawn()
+        .await
+        .context("unexpected task join error from pipeline")?
+        .context("unexpected pipeline error")?;
+
+    // Assert, where file systems without reflinks get a copy instead.
+    let copied = tokio::fs::read(cfg.staging_dist.join("test_file"))
+        .await
+        .context("error reading copied file")?;
+    anyhow::ensure!(
+        copied == b"abc123",
+        "unexpected content after reflink {:?}",
+        copied
+    );
+
+    Ok(())
+}
@@ -0,0 +1,108 @@
+//! Content-Security-Policy hashes of inline scripts & styles.
+
+use base64::engine::general_purpose::STANDARD;
+use base64::Engine;
+use nipper::Document;
+use serde::Serialize;
+use sha2::{Digest, Sha256};
+
+/// The selector of the Content-Security-Policy meta tag of a document.
+pub const CSP_META_SELECTOR: &str = r#"html head meta[http-equiv="Content-Security-Policy"]"#;
+/// The name of the file in the dist dir to which the hashes are written.
+pub const CSP_MANIFEST: &str = "csp.json";
+
+/// The hashes of the inline scripts & styles of a document, as CSP source expressions.
+#[derive(Debug, Default, PartialEq, Eq, Serialize)]
+pub struct CspHashes {
+    /// The hashes of all inline scripts.
+    #[serde(rename = "script-src")]
+    pub script_src: Vec<String>,
+    /// The hashes of all inline styles.
+    #[serde(rename = "style-src")]
+    pub style_src: Vec<String>,
+}
+
+impl CspHashes {
+    /// Compute the hashes of the inline scripts & styles of the given document.
+    pub fn from_document(doc: &Document) -> Self {
+        let hashes = |selector: &str| {
+            let mut hashes = Vec::new();
+            for node in doc.select(selector).nodes() {
+                let hash = source_hash(&node.text());
+                if !hashes.contains(&hash) {
+                    hashes.push(hash);
+                }
+            }
+            hashes
+        };
+        Self {
+            script_src: hashes("script:not([src])"),
+            style_src: hashes("style"),
+        }
+    }
+
+    /// Add the hashes to the `script-src(-elem)` & `style-src(-elem)` directives of the given
+    /// policy.
+    ///
+    /// Missing `script-src` & `style-src` directives are added with the sources of `default-src`,
+    /// which they fall back to, so that the policy is not loosened. Without `default-src`, scripts
+    /// & styles are not restricted, and the hashes are not needed.
+    pub fn apply(&self, policy: &str) -> String {
+        let mut directives: Vec<(String, Vec<String>)> = policy
+            .split(';')
+            .filter_map(|directive| {
+                let mut tokens = directive.split_whitespace();
+                let name = tokens.next()?.to_ascii_lowercase();
+                Some((name, tokens.map(String::from).collect()))
+            })
+            .collect();
+
+        let default_src = directives
+            .iter()
+            .find(|(name, _)| name == "default-src")
+            .map(|(_, sources)| sources.clone());
+        for (name, hashes) in [
+            ("script-src", &self.script_src),
+            ("style-src", &self.style_src),
+        ] {
+            if hashes.is_empty() {
+                continue;
+            }
+            let elem = format!("{}-elem", name);
+            if !directives.iter().any(|(n, _)| n == name || *n == elem) {
+                match &default_src {
+                    Some(sources) => directives.push((name.into(), sources.clone())),
+                    None => continue,
+                }
+            }
+            for (_, sources) in directives
+                .iter_mut()
+                .filter(|(n, _)| n == name || *n == elem)
+            {
+                // `'none'` must be the only source of a directive.
+                sources.retain(|source| source != "'none'");
+                for hash in hashes {
+                    if !sources.contains(hash) {
+                        sources.push(hash.clone());
+                    }
+                }
+            }
+        }
+
+        directives
+            .into_iter()
+            .map(|(name, sources)| {
+                std::iter::once(name)
+                    .chain(sources)
+                    .collect::<Vec<_>>()
+                    .join(" ")
+            })
+            .collect::<Vec<_>>()
+            .join("; ")
+    }
+}
+
+/// Create the `'sha256-...'` source expression of the given inline script or style.
+pub fn source_hash(content: &str) -> String {
+    format!("'sha256-{}'", STANDARD.encode(Sha256::digest(content)))
+}
@@ -0,0 +1,74 @@
+use nipper::Document;
+
+use crate::pipelines::csp::*;
+
+#[test]
+fn from_document_hashes_inline_scripts_and_styles() {
+    // Assemble.
+    let doc = Document::from(
+        r#"<html><head><style>body{}</style><script src="/app.js"></script></head><body><script>init();</script><script>init();</script></body></html>"#,
+    );
+
+    // Action.
+    let hashes = CspHashes::from_document(&doc);
+
+    // Assert.
+    assert_eq!(
+        hashes,
+        CspHashes {
+            script_src: vec![source_hash("init();")],
+            style_src: vec![source_hash("body{}")],
+        }
+    );
+}
+
+#[test]
+fn source_hash_is_base64_sha256() {
+    assert_eq!(
+        source_hash("alert('Hello, world.');"),
+        "'sha256-qznLcsROx4GACP2dm0UCKCzCG+HiZ1guq6ZZDob/Tng='"
+    );
+}
+
+#[test]
+fn apply_appends_to_existing_directives() {
+    let hashes = CspHashes {
+        script_src: vec!["'sha256-a'".into()],
+        style_src: vec!["'sha256-b'".into()],
+    };
+
+    let policy =
+        hashes.apply("default-src 'self'; script-src 'self' 'sha256-a'; style-src 'none';");
+
+    assert_eq!(
+        policy,
+        "default-src 'self'; script-src 'self' 'sha256-a'; style-src 'sha256-b'"
+    );
+}
+
+#[test]
+fn apply_adds_missing_directives_from_default_src() {
+    let hashes = CspHashes {
+        script_src: vec!["'sha256-a'".into()],
+        style_src: vec![],
+    };
+
+    let policy = hashes.apply("default-src 'self' https://cdn.example.com; img-src *");
+
+    assert_eq!(
+        policy,
+        "default-src 'self' https://cdn.example.com; img-src *; script-src 'self' https://cdn.example.com 'sha256-a'"
+    );
+}
+
+#[test]
+fn apply_keeps_unrestricted_policies() {
+    let hashes = CspHashes {
+        script_src: vec!["'sha256-a'".into()],
+        style_src: vec!["'sha256-b'".into()],
+    };
+
+    let policy = hashes.apply("img-src *; script-src-elem 'self'");
+
+    assert_eq!(policy, "img-src *; script-src-elem 'self' 'sha256-a'");
+}
@@ -5,10 +5,14 @@ use std::sync::Arc;
 
 use anyhow::{Context, Result};
 use nipper::Document;
+use tokio::fs;
 use tokio::task::JoinHandle;
 
-use super::{AssetFile, Attrs, TrunkAssetPipelineOutput, ATTR_HREF};
+use super::{
+    postcss, AssetFile, AssetFileType, Attrs, TrunkAssetPipelineOutput, ATTR_HREF, ATTR_POSTCSS,
+};
 use crate::config::RtcBuild;
+use crate::hooks;
 
 /// A CSS asset pipeline.
 pub struct Css {
@@ -18,6 +22,10 @@ pub struct Css {
     cfg: Arc<RtcBuild>,
     /// The asset file being processed.
     asset: AssetFile,
+    /// If the CSS should be processed with PostCSS.
+    postcss: bool,
+    /// The dir of the source HTML file, in which the PostCSS config is looked up.
+    html_dir: Arc<PathBuf>,
 }
 
 impl Css {
@@ -36,7 +44,14 @@ impl Css {
         let mut path = PathBuf::new();
         path.extend(href_attr.split('/'));
         let asset = AssetFile::new(&html_dir, path).await?;
-        Ok(Self { id, cfg, asset })
+        let postcss = cfg.postcss || attrs.contains_key(ATTR_POSTCSS);
+        Ok(Self {
+            id,
+            cfg,
+            asset,
+            postcss,
+            html_dir,
+        })
     }
 
     /// Spawn the pipeline for this asset type.
@@ -50,9 +65,42 @@ impl Css {
     async fn run(self) -> Result<TrunkAssetPipelineOutput> {
         let rel_path = crate::common::strip_prefix(&self.asset.path);
         tracing::info!(path = ?rel_path, "copying & hashing css");
+        let css = if self.postcss {
+            // Process the CSS into a temporary file, which is then hashed in place of the asset.
+            let output = self.cfg.staging_dist.join(format!(
+                ".postcss-{}",
+                self.asset.file_name.to_string_lossy()
+            ));
+            postcss::process(&self.html_dir, &self.asset.path, &output).await?;
+            let css = fs::read(&output)
+                .await
+                .context("error reading PostCSS output")?;
+            fs::remove_file(&output)
+                .await
+                .context("error removing PostCSS output")?;
+            css
+        } else {
+            fs::read(&self.asset.path)
+                .await
+                .with_context(|| format!("error reading file for copying {:?}", self.asset.path))?
+        };
+        let css = hooks::rewrite_with_pipeline_hooks(
+            &self.cfg,
+            Self::TYPE_CSS,
+            &self.asset.path,
+            &self.asset.file_name.to_string_lossy(),
+            css,
+        )
+        .await?;
         let file = self
             .asset
-            .copy(&self.cfg.staging_dist, self.cfg.filehash)
+            .write(
+                &self.cfg,
+                css,
+                self.cfg.filehash,
+                self.cfg.minify,
+                AssetFileType::Css,
+            )
             .await?;
         tracing::info!(path = ?rel_path, "finished copying & hashing css");
         Ok(TrunkAssetPipelineOutput::Css(CssOutput {
@@ -75,11 +123,13 @@ pub struct CssOutput {
 
 impl CssOutput {
     pub async fn finalize(self, dom: &mut Document) -> Result<()> {
+        let integrity = super::integrity_attrs(&self.cfg, &self.file).await?;
         dom.select(&super::trunk_id_selector(self.id))
             .replace_with_html(format!(
-                r#"<link rel="stylesheet" href="{base}{file}"/>"#,
+                r#"<link rel="stylesheet" href="{base}{file}"{integrity}/>"#,
                 base = &self.cfg.public_url,
-                file = self.file
+                file = self.file,
+                integrity = integrity,
             ));
         Ok(())
     }
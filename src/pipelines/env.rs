@@ -0,0 +1,179 @@
+//! Build-time environment variable injection.
+
+use std::collections::BTreeMap;
+use std::sync::Arc;
+
+use anyhow::{bail, Context, Result};
+use nipper::Document;
+use once_cell::sync::Lazy;
+use regex::{Captures, Regex};
+use tokio::fs;
+use tokio::task::JoinHandle;
+
+use super::{Attrs, TrunkAssetPipelineOutput, ATTR_IMPORT};
+use crate::common::escape_html;
+use crate::config::RtcBuild;
+
+/// The attribute to emit the environment variables as a JS module.
+const ATTR_MODULE: &str = "data-module";
+/// The name of the generated JS module of the environment variables.
+const MODULE_STEM: &str = "env";
+
+/// The regex of the `{{ env.NAME }}` placeholders of the source HTML.
+static PLACEHOLDER: Lazy<Regex> = Lazy::new(|| {
+    Regex::new(r"\{\{\s*env\.([A-Za-z_][A-Za-z0-9_]*)\s*\}\}")
+        .expect("error compiling env placeholder regex")
+});
+
+/// Get the values of the allowed environment variables which are set.
+pub fn allowed_vars(cfg: &RtcBuild) -> BTreeMap<String, String> {
+    cfg.env
+        .iter()
+        .filter_map(|name| Some((name.clone(), std::env::var(name).ok()?)))
+        .collect()
+}
+
+/// Substitute the `{{ env.NAME }}` placeholders of the given HTML with the HTML-escaped values of
+/// the environment variables, which must be allowed by `build.env`. Unset variables are replaced
+/// with an empty string.
+pub fn substitute(html: &str, cfg: &RtcBuild) -> Result<String> {
+    let mut error = None;
+    let html = PLACEHOLDER.replace_all(html, |captures: &Captures| {
+        let name = &captures[1];
+        if !cfg.env.iter().any(|allowed| allowed == name) {
+            error.get_or_insert_with(|| name.to_owned());
+            return String::new();
+        }
+        match std::env::var(name) {
+            Ok(value) => escape_html(&value),
+            Err(_) => {
+                tracing::warn!("environment variable {} is not set", name);
+                String::new()
+            }
+        }
+    });
+    if let Some(name) = error {
+        bail!(
+            "the environment variable {} is not allowed to be injected, add it to `build.env` to \
+             allow it",
+            name
+        );
+    }
+    Ok(html.into_owned())
+}
+
+/// An environment variables asset pipeline.
+pub struct Env {
+    /// The ID of this pipeline's source HTML element.
+    id: usize,
+    /// Runtime build config.
+    cfg: Arc<RtcBuild>,
+    /// If the variables should be emitted as a JS module instead of an inline script.
+    module: bool,
+    /// The module specifier to map the JS module with in the import map.
+    import: Option<String>,
+}
+
+impl Env {
+    pub const TYPE_ENV: &'static str = "env";
+
+    pub async fn new(cfg: Arc<RtcBuild>, attrs: Attrs, id: usize) -> Result<Self> {
+        let module = attrs.contains_key(ATTR_MODULE);
+        let import = module.then(|| match attrs.get(ATTR_IMPORT).map(|s| s.trim()) {
+            Some(specifier) if !specifier.is_empty() => specifier.to_owned(),
+            _ => MODULE_STEM.to_owned(),
+        });
+        Ok(Self {
+            id,
+            cfg,
+            module,
+            import,
+        })
+    }
+
+    /// Spawn the pipeline for this asset type.
+    #[tracing::instrument(level = "trace", skip(self))]
+    pub fn spawn(self) -> JoinHandle<Result<TrunkAssetPipelineOutput>> {
+        tokio::spawn(self.run())
+    }
+
+    /// Run this pipeline.
+    #[tracing::instrument(level = "trace", skip(self))]
+    async fn run(self) -> Result<TrunkAssetPipelineOutput> {
+        let vars = serde_json::to_string(&allowed_vars(&self.cfg))
+            .context("error serializing environment variables")?
+            // Prevent the values from closing an inline script.
+            .replace("</", "<\\/");
+
+        let env = if self.module {
+            let js = format!("const env = {};\nexport default env;\n", vars);
+            let file_name = if self.cfg.filehash {
+                format!(
+                    "{}.js",
+                    self.cfg
+                        .hashed_name(MODULE_STEM, seahash::hash(js.as_bytes()))
+                )
+            } else {
+                format!("{}.js", MODULE_STEM)
+            };
+            let file_path = self.cfg.staging_path(&file_name);
+            if let Some(parent) = file_path.parent() {
+                fs::create_dir_all(parent)
+                    .await
+                    .context("error creating env pipeline output dir")?;
+            }
+            fs::write(&file_path, &js)
+                .await
+                .context("error writing env pipeline output")?;
+            self.cfg
+                .output_hashes
+                .record(&file_path, js.as_bytes())
+                .await;
+            EnvRef::Module(file_name)
+        } else {
+            EnvRef::Inline(vars)
+        };
+
+        Ok(TrunkAssetPipelineOutput::Env(EnvOutput {
+            cfg: self.cfg.clone(),
+            id: self.id,
+            env,
+            import: self.import,
+        }))
+    }
+}
+
+/// The output of an environment variables pipeline.
+pub struct EnvOutput {
+    /// The runtime build config.
+    pub cfg: Arc<RtcBuild>,
+    /// The ID of this pipeline.
+    pub id: usize,
+    /// The environment variables.
+    pub env: EnvRef,
+    /// The module specifier to map the JS module with in the import map, if any.
+    pub import: Option<String>,
+}
+
+/// The resulting environment variables.
+pub enum EnvRef {
+    /// The JSON of the variables, to be assigned to `window.__ENV__` by an inline script.
+    Inline(String),
+    /// A hashed file reference to the JS module (for `data-module`).
+    Module(String),
+}
+
+impl EnvOutput {
+    pub async fn finalize(self, dom: &mut Document) -> Result<()> {
+        let html = match &self.env {
+            EnvRef::Inline(vars) => format!("<script>window.__ENV__ = {};</script>", vars),
+            EnvRef::Module(file) => format!(
+                r#"<link rel="modulepreload" href="{base}{file}"/>"#,
+                base = &self.cfg.public_url,
+            ),
+        };
+        dom.select(&super::trunk_id_selector(self.id))
+            .replace_with_html(html);
+        Ok(())
+    }
+}
@@ -0,0 +1,57 @@
+use anyhow::{ensure, Context, Result};
+
+use crate::config::RtcBuild;
+use crate::pipelines::env::*;
+
+/// Create a test config allowing the given environment variables.
+async fn test_config(env: &[&str]) -> Result<(tempfile::TempDir, RtcBuild)> {
+    let tmpdir = tempfile::tempdir().context("error building tempdir for test")?;
+    let mut cfg = RtcBuild::new_test(tmpdir.path()).await?;
+    cfg.env = env.iter().map(|name| name.to_string()).collect();
+    Ok((tmpdir, cfg))
+}
+
+#[tokio::test]
+async fn substitute_replaces_allowed_vars() -> Result<()> {
+    // Assemble.
+    let (_tmpdir, cfg) = test_config(&["TRUNK_TEST_ENV_API_URL", "TRUNK_TEST_ENV_UNSET"]).await?;
+    std::env::set_var(
+        "TRUNK_TEST_ENV_API_URL",
+        "https://api.example.com/?a=1&b=\"2\"",
+    );
+
+    // Action.
+    let html = substitute(
+        r#"<meta name="api" content="{{ env.TRUNK_TEST_ENV_API_URL }}"><p>{{env.TRUNK_TEST_ENV_UNSET}}</p>"#,
+        &cfg,
+    )?;
+
+    // Assert.
+    ensure!(
+        html == r#"<meta name="api" content="https://api.example.com/?a=1&amp;b=&quot;2&quot;"><p></p>"#,
+        "unexpected html {}",
+        html
+    );
+    Ok(())
+}
+
+#[tokio::test]
+async fn substitute_rejects_disallowed_vars() -> Result<()> {
+    let (_tmpdir, cfg) = test_config(&[]).await?;
+
+    let res = substitute("<p>{{ env.HOME }}</p>", &cfg);
+
+    ensure!(res.is_err(), "expected an error on a disallowed variable");
+    Ok(())
+}
+
+#[tokio::test]
+async fn allowed_vars_skips_unset_vars() -> Result<()> {
+    let (_tmpdir, cfg) = test_config(&["TRUNK_TEST_ENV_SET", "TRUNK_TEST_ENV_NOT_SET"]).await?;
+    std::env::set_var("TRUNK_TEST_ENV_SET", "1");
+
+    let vars = allowed_vars(&cfg);
+
+    ensure!(vars.len() == 1 && vars["TRUNK_TEST_ENV_SET"] == "1");
+    Ok(())
+}
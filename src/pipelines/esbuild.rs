@@ -0,0 +1,86 @@
+//! Transpilation & bundling of JS and TypeScript assets with esbuild.
+
+use std::path::Path;
+
+use anyhow::{Context, Result};
+use tokio::fs;
+
+use super::AssetFileType;
+use crate::common;
+use crate::config::RtcBuild;
+use crate::tools::{self, Application};
+
+/// The format of a JS output of esbuild.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Format {
+    /// A JS module.
+    Esm,
+    /// A classic script, whose bundled modules are wrapped in an immediately invoked function.
+    Iife,
+}
+
+impl Format {
+    /// The format to bundle a script of the given type as, which keeps modules as modules.
+    pub fn of_script(file_type: AssetFileType) -> Self {
+        match file_type {
+            AssetFileType::Mjs => Self::Esm,
+            _ => Self::Iife,
+        }
+    }
+}
+
+/// Transpile the given JS or TypeScript file with esbuild, returning the resulting JS.
+///
+/// If `bundle` is set, the imports of the file are resolved (relative to the file, or from the
+/// `node_modules` of its dir or one of its parents) and inlined into the output, leaving out all
+/// unused exports. The output is minified if minification is enabled. The given temporary file in
+/// the staging dist dir is written by esbuild, and removed afterwards.
+pub async fn build(
+    cfg: &RtcBuild,
+    input: &Path,
+    temp_file: &str,
+    format: Format,
+    bundle: bool,
+) -> Result<Vec<u8>> {
+    let version = cfg.tools.esbuild.as_deref();
+    let esbuild = tools::get(Application::Esbuild, version, &cfg.tools).await?;
+    run(&esbuild, cfg, input, temp_file, format, bundle).await
+}
+
+/// Run the given esbuild binary, as described for [`build`].
+pub(super) async fn run(
+    esbuild: &Path,
+    cfg: &RtcBuild,
+    input: &Path,
+    temp_file: &str,
+    format: Format,
+    bundle: bool,
+) -> Result<Vec<u8>> {
+    let output = cfg.staging_dist.join(temp_file);
+    let input_str = dunce::simplified(input).display().to_string();
+    let outfile = format!("--outfile={}", dunce::simplified(&output).display());
+    let mut args = vec![
+        input_str.as_str(),
+        &outfile,
+        match format {
+            Format::Esm => "--format=esm",
+            Format::Iife => "--format=iife",
+        },
+        "--log-level=warning",
+    ];
+    if bundle {
+        args.push("--bundle");
+    }
+    if cfg.minify {
+        args.push("--minify");
+    }
+    common::run_command_capture_stderr(Application::Esbuild.name(), esbuild, &args).await?;
+
+    let js = fs::read(&output)
+        .await
+        .context("error reading esbuild output")?;
+    fs::remove_file(&output)
+        .await
+        .context("error removing esbuild output")?;
+    Ok(js)
+}
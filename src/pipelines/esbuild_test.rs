@@ -0,0 +1,139 @@
+#![cfg(unix)]
+
+use std::os::unix::fs::PermissionsExt;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+
+use anyhow::{ensure, Context, Result};
+use nipper::Document;
+
+use crate::config::RtcBuild;
+use crate::pipelines::esbuild::*;
+use crate::pipelines::typescript::TypeScriptOutput;
+use crate::pipelines::AssetFileType;
+
+/// Write a fake esbuild to the given dir, which copies its input to its `--outfile` & records its
+/// args in `args.txt`.
+fn fake_esbuild(dir: &Path) -> Result<PathBuf> {
+    let esbuild = dir.join("esbuild");
+    std::fs::write(
+        &esbuild,
+        "#!/bin/sh\necho \"$@\" > \"$(dirname \"$0\")/args.txt\"\ncp \"$1\" \"${2#--outfile=}\"\n",
+    )?;
+    std::fs::set_permissions(&esbuild, std::fs::Permissions::from_mode(0o755))?;
+    Ok(esbuild)
+}
+
+fn args(dir: &Path) -> Result<String> {
+    let args = std::fs::read_to_string(dir.join("args.txt")).context("error reading args")?;
+    Ok(args.trim().to_owned())
+}
+
+#[tokio::test]
+async fn transpiles_typescript_to_an_unbundled_module() -> Result<()> {
+    // Assemble.
+    let tmpdir = tempfile::tempdir().context("error building tempdir for test")?;
+    let cfg = RtcBuild::new_test(tmpdir.path()).await?;
+    let esbuild = fake_esbuild(tmpdir.path())?;
+    let input = tmpdir.path().join("app.ts");
+    std::fs::write(&input, "export const answer: number = 42;")?;
+
+    // Action.
+    let js = run(
+        &esbuild,
+        &cfg,
+        &input,
+        ".esbuild-app.js",
+        Format::Esm,
+        false,
+    )
+    .await?;
+
+    // Assert.
+    ensure!(js == b"export const answer: number = 42;");
+    ensure!(
+        args(tmpdir.path())?
+            == format!(
+                "{} --outfile={} --format=esm --log-level=warning",
+                input.display(),
+                cfg.staging_dist.join(".esbuild-app.js").display()
+            ),
+        "unexpected args {:?}",
+        args(tmpdir.path())?
+    );
+    ensure!(
+        !cfg.staging_dist.join(".esbuild-app.js").exists(),
+        "the temporary output of esbuild must be removed"
+    );
+    Ok(())
+}
+
+#[tokio::test]
+async fn typescript_outputs_are_emitted_as_module_scripts() -> Result<()> {
+    // Assemble.
+    let tmpdir = tempfile::tempdir().context("error building tempdir for test")?;
+    let cfg = Arc::new(RtcBuild::new_test(tmpdir.path()).await?);
+    let mut dom = Document::from(r#"<html><head><link data-trunk-id="0"></head></html>"#);
+    let output = TypeScriptOutput {
+        cfg: cfg.clone(),
+        id: 0,
+        file: "app-0123456789abcdef.js".into(),
+        import: None,
+    };
+
+    // Action.
+    output.finalize(&mut dom).await?;
+
+    // Assert.
+    let html = dom.html().to_string();
+    ensure!(
+        html.contains(&format!(
+            r#"<script type="module" src="{}app-0123456789abcdef.js"></script>"#,
+            cfg.public_url
+        )),
+        "unexpected html {}",
+        html
+    );
+    ensure!(!html.contains("<link"));
+    Ok(())
+}
+
+#[tokio::test]
+async fn bundles_scripts_in_their_own_format() -> Result<()> {
+    // Assemble.
+    let tmpdir = tempfile::tempdir().context("error building tempdir for test")?;
+    let mut cfg = RtcBuild::new_test(tmpdir.path()).await?;
+    cfg.minify = true;
+    let esbuild = fake_esbuild(tmpdir.path())?;
+    let input = tmpdir.path().join("app.js");
+    std::fs::write(
+        &input,
+        "import { answer } from './answer.js'; console.log(answer);",
+    )?;
+
+    // Action.
+    let js = run(
+        &esbuild,
+        &cfg,
+        &input,
+        ".esbuild-app.js",
+        Format::of_script(AssetFileType::Js),
+        true,
+    )
+    .await?;
+
+    // Assert.
+    ensure!(js == b"import { answer } from './answer.js'; console.log(answer);");
+    ensure!(
+        args(tmpdir.path())?
+            == format!(
+                "{} --outfile={} --format=iife --log-level=warning --bundle --minify",
+                input.display(),
+                cfg.staging_dist.join(".esbuild-app.js").display()
+            ),
+        "unexpected args {:?}",
+        args(tmpdir.path())?
+    );
+    ensure!(Format::of_script(AssetFileType::Mjs) == Format::Esm);
+    Ok(())
+}
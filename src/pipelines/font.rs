@@ -0,0 +1,356 @@
+//! Font asset pipeline.
+
+use std::collections::{BTreeSet, HashMap};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, PoisonError};
+
+use anyhow::{bail, Context, Result};
+use nipper::Document;
+use once_cell::sync::Lazy;
+use regex::Regex;
+use tokio::fs;
+use tokio::task::JoinHandle;
+
+use super::resource_hints::{HintedFile, ResourceHint};
+use super::{AssetFile, AssetFileType, Attrs, TrunkAssetPipelineOutput, ATTR_HREF};
+use crate::common;
+use crate::config::RtcBuild;
+
+/// The attribute to subset the font to the characters used.
+const ATTR_SUBSET: &str = "data-subset";
+/// The attribute of the font family of the generated `@font-face` rule.
+const ATTR_FAMILY: &str = "data-family";
+/// The attribute of the font weight of the generated `@font-face` rule.
+const ATTR_WEIGHT: &str = "data-weight";
+/// The attribute of the font style of the generated `@font-face` rule.
+const ATTR_STYLE: &str = "data-style";
+/// The name of the fontTools subsetter executable.
+const PYFTSUBSET: &str = "pyftsubset";
+
+/// The selector of the fonts which are subset.
+const SUBSET_SELECTOR: &str = r#"link[data-trunk][rel="font"][data-subset]"#;
+
+/// The count of the temporary files of subsetting fonts, which keeps fonts of the same name, e.g.
+/// in different dirs, from overwriting each other's files while they are subset concurrently.
+static SUBSET_FILES: AtomicU64 = AtomicU64::new(0);
+
+/// The regex of a Unicode range of `data-subset`, e.g. `U+0020-007E`.
+static UNICODE_RANGE: Lazy<Regex> = Lazy::new(|| {
+    Regex::new(r"^[Uu]\+[0-9A-Fa-f?]{1,6}(-[0-9A-Fa-f]{1,6})?$")
+        .expect("error compiling unicode range regex")
+});
+
+/// The characters of the pages of the current build, keyed by the canonical paths of the fonts
+/// they subset, which the subsets must keep.
+#[derive(Debug, Default)]
+pub struct FontCharacters(std::sync::Mutex<HashMap<PathBuf, BTreeSet<char>>>);
+
+impl FontCharacters {
+    /// Forget the characters of the previous build.
+    pub fn clear(&self) {
+        self.0
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .clear();
+    }
+
+    /// Record the characters of the text of the given assembled page, i.e. after its partials
+    /// were included & its variables substituted, for each font it subsets. The paths of the
+    /// fonts are relative to the given dir of the page.
+    pub async fn record_page(&self, doc: &Document, html_dir: &Path) {
+        let hrefs = doc
+            .select(SUBSET_SELECTOR)
+            .nodes()
+            .iter()
+            .filter_map(|link| link.attr(ATTR_HREF))
+            .map(|href| href.to_string())
+            .collect::<Vec<_>>();
+        if hrefs.is_empty() {
+            return;
+        }
+        let characters = used_characters(doc);
+        for href in hrefs {
+            let mut path = html_dir.to_owned();
+            path.extend(href.split('/'));
+            // Fonts which are not found are reported by their pipelines.
+            if let Ok(path) = fs::canonicalize(&path).await {
+                self.0
+                    .lock()
+                    .unwrap_or_else(PoisonError::into_inner)
+                    .entry(path)
+                    .or_default()
+                    .extend(&characters);
+            }
+        }
+    }
+
+    /// Get the characters recorded for the font of the given canonical path.
+    pub fn get(&self, font: &Path) -> String {
+        self.0
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .get(font)
+            .map(|characters| characters.iter().collect())
+            .unwrap_or_default()
+    }
+}
+
+/// A font asset pipeline.
+pub struct Font {
+    /// The ID of this pipeline's source HTML element.
+    id: usize,
+    /// Runtime build config.
+    cfg: Arc<RtcBuild>,
+    /// The asset file being processed.
+    asset: AssetFile,
+    /// The Unicode ranges to keep in addition to the characters of the pages, if the font is to
+    /// be subset.
+    subset: Option<Vec<String>>,
+    /// The `@font-face` rule to generate for the font, if any.
+    face: Option<FontFace>,
+}
+
+/// The descriptors of a generated `@font-face` rule.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct FontFace {
+    /// The font family.
+    pub family: String,
+    /// The font weight, if any.
+    pub weight: Option<String>,
+    /// The font style, if any.
+    pub style: Option<String>,
+}
+
+impl Font {
+    pub const TYPE_FONT: &'static str = "font";
+
+    pub async fn new(
+        cfg: Arc<RtcBuild>,
+        html_dir: Arc<PathBuf>,
+        attrs: Attrs,
+        id: usize,
+    ) -> Result<Self> {
+        // Build the path to the target asset.
+        let href_attr = attrs.get(ATTR_HREF).context(
+            r#"required attr `href` missing for <link data-trunk rel="font" .../> element"#,
+        )?;
+        let mut path = PathBuf::new();
+        path.extend(href_attr.split('/'));
+        let asset = AssetFile::new(&html_dir, path).await?;
+        if font_format(asset.ext.as_deref()).is_none() {
+            bail!(
+                r#"unsupported font {:?} of <link data-trunk rel="font" .../>, expected a .woff2, .woff, .ttf or .otf file"#,
+                href_attr
+            );
+        }
+
+        let subset = attrs
+            .get(ATTR_SUBSET)
+            .map(|ranges| {
+                ranges
+                    .split(',')
+                    .map(str::trim)
+                    .filter(|range| !range.is_empty())
+                    .map(|range| {
+                        if !UNICODE_RANGE.is_match(range) {
+                            bail!(
+                                "invalid Unicode range {:?} of `{}`, expected e.g. `U+0020-007E`",
+                                range,
+                                ATTR_SUBSET
+                            );
+                        }
+                        Ok(range.to_owned())
+                    })
+                    .collect::<Result<Vec<_>>>()
+            })
+            .transpose()?;
+
+        let descriptor = |name| {
+            attrs
+                .get(name)
+                .map(|value: &String| value.trim().to_owned())
+                .filter(|value| !value.is_empty())
+        };
+        let face = descriptor(ATTR_FAMILY).map(|family| FontFace {
+            family,
+            weight: descriptor(ATTR_WEIGHT),
+            style: descriptor(ATTR_STYLE),
+        });
+
+        Ok(Self {
+            id,
+            cfg,
+            asset,
+            subset,
+            face,
+        })
+    }
+
+    /// Spawn the pipeline for this asset type.
+    #[tracing::instrument(level = "trace", skip(self))]
+    pub fn spawn(self) -> JoinHandle<Result<TrunkAssetPipelineOutput>> {
+        tokio::spawn(self.run())
+    }
+
+    /// Run this pipeline.
+    #[tracing::instrument(level = "trace", skip(self))]
+    async fn run(self) -> Result<TrunkAssetPipelineOutput> {
+        let rel_path = common::strip_prefix(&self.asset.path);
+        let file = match &self.subset {
+            Some(ranges) => {
+                tracing::info!(path = ?rel_path, "subsetting & hashing font");
+                let font = self.subset(ranges).await?;
+                self.asset
+                    .write(
+                        &self.cfg,
+                        font,
+                        self.cfg.filehash,
+                        false,
+                        AssetFileType::Other,
+                    )
+                    .await?
+            }
+            None => {
+                tracing::info!(path = ?rel_path, "copying & hashing font");
+                self.asset
+                    .copy(&self.cfg, self.cfg.filehash, false, AssetFileType::Other)
+                    .await?
+            }
+        };
+        tracing::info!(path = ?rel_path, "finished processing font");
+        Ok(TrunkAssetPipelineOutput::Font(FontOutput {
+            cfg: self.cfg.clone(),
+            id: self.id,
+            file,
+            face: self.face,
+        }))
+    }
+
+    /// Subset the font with fontTools to the characters of the text of the pages referencing it
+    /// & the given Unicode ranges, keeping the format of the font.
+    async fn subset(&self, ranges: &[String]) -> Result<Vec<u8>> {
+        let text = self.cfg.font_characters.get(&self.asset.path);
+
+        let id = SUBSET_FILES.fetch_add(1, Ordering::Relaxed);
+        let file_name = self.asset.file_name.to_string_lossy();
+        let text_file = self
+            .cfg
+            .staging_dist
+            .join(format!(".subset-{}-{}.txt", id, file_name));
+        let output = self
+            .cfg
+            .staging_dist
+            .join(format!(".subset-{}-{}", id, file_name));
+        fs::write(&text_file, text)
+            .await
+            .context("error writing characters for subsetting font")?;
+
+        let mut args = vec![
+            dunce::simplified(&self.asset.path).display().to_string(),
+            format!("--output-file={}", dunce::simplified(&output).display()),
+            format!("--text-file={}", dunce::simplified(&text_file).display()),
+            "--layout-features=*".to_owned(),
+        ];
+        if !ranges.is_empty() {
+            args.push(format!("--unicodes={}", ranges.join(",")));
+        }
+        match self.asset.ext.as_deref() {
+            Some("woff2") => args.push("--flavor=woff2".to_owned()),
+            Some("woff") => args.push("--flavor=woff".to_owned()),
+            _ => (),
+        }
+        let res = common::run_command_capture_stderr(PYFTSUBSET, &PathBuf::from(PYFTSUBSET), &args)
+            .await
+            .context(
+                "error subsetting font, `pyftsubset` of fontTools (with brotli for WOFF2) must be \
+                 installed",
+            );
+        fs::remove_file(&text_file)
+            .await
+            .context("error removing characters for subsetting font")?;
+        res?;
+
+        let font = fs::read(&output)
+            .await
+            .context("error reading subset font")?;
+        fs::remove_file(&output)
+            .await
+            .context("error removing subset font")?;
+        Ok(font)
+    }
+}
+
+/// Get the distinct characters of the text of the given HTML document, which the subset of a font
+/// must keep.
+pub fn used_characters(doc: &Document) -> BTreeSet<char> {
+    let text = doc.select("html").text();
+    text.chars().filter(|c| !c.is_control()).collect()
+}
+
+/// Get the CSS format of a font file with the given extension, if it is a supported font.
+fn font_format(ext: Option<&str>) -> Option<&'static str> {
+    match ext? {
+        "woff2" => Some("woff2"),
+        "woff" => Some("woff"),
+        "ttf" => Some("truetype"),
+        "otf" => Some("opentype"),
+        _ => None,
+    }
+}
+
+/// The output of a font build pipeline.
+pub struct FontOutput {
+    /// The runtime build config.
+    pub cfg: Arc<RtcBuild>,
+    /// The ID of this pipeline.
+    pub id: usize,
+    /// Name of the finalized output file.
+    pub file: String,
+    /// The `@font-face` rule to generate for the font, if any.
+    pub face: Option<FontFace>,
+}
+
+impl FontOutput {
+    pub async fn finalize(self, dom: &mut Document) -> Result<()> {
+        let mut html = ResourceHint::Preload(None)
+            .render(
+                &self.cfg,
+                &[HintedFile {
+                    file: &self.file,
+                    module: false,
+                }],
+            )
+            .await?;
+        if let Some(face) = &self.face {
+            html.push_str(&format!(
+                "<style>{}</style>",
+                font_face_rule(face, &self.cfg.public_url, &self.file)
+            ));
+        }
+        dom.select(&super::trunk_id_selector(self.id))
+            .replace_with_html(html);
+        Ok(())
+    }
+}
+
+/// Create the `@font-face` rule of the given font file.
+pub fn font_face_rule(face: &FontFace, public_url: &str, file: &str) -> String {
+    // The descriptors must not escape the rule, or the style element.
+    let descriptor = |value: &str| value.replace(['"', '\\', ';', '{', '}', '<', '>'], "");
+    let format = font_format(file.rsplit_once('.').map(|(_, ext)| ext))
+        .map(|format| format!(r#" format("{}")"#, format))
+        .unwrap_or_default();
+    let mut rule = format!(
+        r#"@font-face {{ font-family: "{family}"; src: url("{public_url}{file}"){format}; font-display: swap;"#,
+        family = descriptor(&face.family),
+    );
+    if let Some(weight) = &face.weight {
+        rule.push_str(&format!(" font-weight: {};", descriptor(weight)));
+    }
+    if let Some(style) = &face.style {
+        rule.push_str(&format!(" font-style: {};", descriptor(style)));
+    }
+    rule.push_str(" }");
+    rule
+}
@@ -0,0 +1,125 @@
+use std::collections::HashMap;
+use std::path::PathBuf;
+use std::sync::Arc;
+
+use anyhow::{Context, Result};
+use nipper::Document;
+
+use crate::config::RtcBuild;
+use crate::pipelines::font::*;
+use crate::pipelines::{TrunkAssetPipelineOutput, ATTR_HREF};
+
+/// A fixture for setting up basic test config.
+async fn setup_test_config() -> Result<(tempfile::TempDir, Arc<RtcBuild>, PathBuf)> {
+    let tmpdir = tempfile::tempdir().context("error building tempdir for test")?;
+    let cfg = Arc::new(RtcBuild::new_test(tmpdir.path()).await?);
+    tokio::fs::write(tmpdir.path().join("inter.woff2"), b"wOF2")
+        .await
+        .context("error writing test font")?;
+    tokio::fs::write(tmpdir.path().join("inter.eot"), b"eot")
+        .await
+        .context("error writing test font")?;
+    let html_dir = tmpdir.path().to_path_buf();
+    Ok((tmpdir, cfg, html_dir))
+}
+
+fn attrs(attrs: &[(&str, &str)]) -> HashMap<String, String> {
+    attrs
+        .iter()
+        .map(|(name, value)| (name.to_string(), value.to_string()))
+        .collect()
+}
+
+#[tokio::test]
+async fn err_new_missing_href_or_unsupported_font() -> Result<()> {
+    // Assemble.
+    let (_tmpdir, cfg, html_dir) = setup_test_config().await?;
+    let html_dir = Arc::new(html_dir);
+
+    // Action.
+    let missing = Font::new(cfg.clone(), html_dir.clone(), Default::default(), 0).await;
+    let unsupported = Font::new(cfg, html_dir, attrs(&[(ATTR_HREF, "inter.eot")]), 0).await;
+
+    // Assert.
+    anyhow::ensure!(missing.is_err(), "unexpected success without `href` attr");
+    anyhow::ensure!(unsupported.is_err(), "unexpected success for .eot font");
+    Ok(())
+}
+
+#[tokio::test]
+async fn err_new_invalid_subset_range() -> Result<()> {
+    // Assemble.
+    let (_tmpdir, cfg, html_dir) = setup_test_config().await?;
+    let attrs = attrs(&[
+        (ATTR_HREF, "inter.woff2"),
+        ("data-subset", "U+0020-007E, latin"),
+    ]);
+
+    // Action.
+    let res = Font::new(cfg, Arc::new(html_dir), attrs, 0).await;
+
+    // Assert.
+    anyhow::ensure!(res.is_err(), "unexpected success for invalid Unicode range");
+    Ok(())
+}
+
+#[tokio::test]
+async fn ok_run_copies_and_hashes_font() -> Result<()> {
+    // Assemble.
+    let (_tmpdir, cfg, html_dir) = setup_test_config().await?;
+    let attrs = attrs(&[(ATTR_HREF, "inter.woff2"), ("data-family", "Inter")]);
+    let font = Font::new(cfg.clone(), Arc::new(html_dir), attrs, 0).await?;
+
+    // Action.
+    let out = font
+        .spawn()
+        .await
+        .context("unexpected task join error from pipeline")?
+        .context("unexpected pipeline error")?;
+
+    // Assert.
+    let TrunkAssetPipelineOutput::Font(out) = out else {
+        anyhow::bail!("unexpected output of font pipeline");
+    };
+    anyhow::ensure!(
+        out.file.starts_with("inter-") && out.file.ends_with(".woff2"),
+        "unexpected output file {}",
+        out.file
+    );
+    let copied = tokio::fs::read(cfg.staging_dist.join(&out.file))
+        .await
+        .context("error reading copied font")?;
+    anyhow::ensure!(copied == b"wOF2", "unexpected content of copied font");
+    assert_eq!(
+        out.face,
+        Some(FontFace {
+            family: "Inter".to_owned(),
+            weight: None,
+            style: None,
+        })
+    );
+    Ok(())
+}
+
+#[test]
+fn used_characters_of_html_text() {
+    let doc =
+        Document::from("<html><head><title>Ab</title></head><body><p>bca\n</p></body></html>");
+    assert_eq!(
+        used_characters(&doc).into_iter().collect::<String>(),
+        "Aabc"
+    );
+}
+
+#[test]
+fn font_face_rule_with_descriptors() {
+    let face = FontFace {
+        family: "Inter\"; }".to_owned(),
+        weight: Some("100 900".to_owned()),
+        style: Some("italic".to_owned()),
+    };
+    assert_eq!(
+        font_face_rule(&face, "/", "inter-1234.woff2"),
+        r#"@font-face { font-family: "Inter "; src: url("/inter-1234.woff2") format("woff2"); font-display: swap; font-weight: 100 900; font-style: italic; }"#
+    );
+}
@@ -1,27 +1,36 @@
 //! Source HTML pipelines.
 
-use std::path::PathBuf;
+use std::collections::{BTreeMap, HashSet};
+use std::future::Future;
+use std::path::{Path, PathBuf};
 use std::sync::Arc;
 
-use anyhow::{ensure, Context, Result};
+use anyhow::{anyhow, ensure, Context, Result};
 use futures_util::stream::{FuturesUnordered, StreamExt};
 use nipper::Document;
 use tokio::fs;
 use tokio::runtime::Handle;
-use tokio::sync::mpsc;
-use tokio::task::JoinHandle;
+use tokio::sync::{mpsc, oneshot};
+use tokio::task::JoinError;
 
+use crate::common::AbortOnDrop;
 use crate::config::RtcBuild;
-use crate::hooks::{spawn_hooks, wait_hooks};
+use crate::hooks::{spawn_hooks, wait_hooks, HookHandles};
+use crate::pipelines::csp::{CspHashes, CSP_MANIFEST, CSP_META_SELECTOR};
+use crate::pipelines::minify::minify_html;
 use crate::pipelines::rust::RustApp;
 use crate::pipelines::{
-    Attrs, PipelineStage, TrunkAsset, TrunkAssetPipelineOutput, TrunkAssetReference, TRUNK_ID,
+    env, i18n, import_map, partials, service_worker, Attrs, PipelineStage, TrunkAsset,
+    TrunkAssetPipelineOutput, TrunkAssetReference, TRUNK_ID,
 };
 
 const PUBLIC_URL_MARKER_ATTR: &str = "data-trunk-public-url";
 const RELOAD_SCRIPT: &str = include_str!("../autoreload.js");
 
-type AssetPipelineHandles = FuturesUnordered<JoinHandle<Result<TrunkAssetPipelineOutput>>>;
+/// The handles of the asset pipelines, resolving to the order of each asset in the source HTML,
+/// the rendered resource hints of its output files & its output.
+type AssetPipelineHandles =
+    FuturesUnordered<AbortOnDrop<Result<(usize, String, TrunkAssetPipelineOutput)>>>;
 
 /// An HTML assets build pipeline.
 ///
@@ -30,10 +39,20 @@ type AssetPipelineHandles = FuturesUnordered<JoinHandle<Result<TrunkAssetPipelin
 pub struct HtmlPipeline {
     /// Runtime config.
     cfg: Arc<RtcBuild>,
-    /// The path to the source HTML document from which the output `index.html` will be built.
+    /// The pages whose output HTML is built, the first of which is the main page.
+    pages: Vec<HtmlPage>,
+}
+
+/// A page of the application, whose output HTML is built from a source HTML file.
+struct HtmlPage {
+    /// Runtime config of the page, whose relative public URL is adjusted to the dir of the page.
+    cfg: Arc<RtcBuild>,
+    /// The path to the source HTML document from which the output HTML will be built.
     target_html_path: PathBuf,
     /// The parent directory of `target_html_path`.
     target_html_dir: Arc<PathBuf>,
+    /// The path of the output HTML file relative to the dist dir, e.g. `docs/index.html`.
+    output: String,
     /// An optional channel to be used to communicate ignore paths to the watcher.
     ignore_chan: Option<mpsc::Sender<PathBuf>>,
 }
@@ -41,8 +60,113 @@ pub struct HtmlPipeline {
 impl HtmlPipeline {
     /// Create a new instance.
     pub fn new(cfg: Arc<RtcBuild>, ignore_chan: Option<mpsc::Sender<PathBuf>>) -> Result<Self> {
-        let target_html_path = cfg
-            .target
+        let mut pages = vec![HtmlPage::new(
+            cfg.clone(),
+            &cfg.target,
+            "index.html".to_owned(),
+            ignore_chan.clone(),
+        )?];
+        // The other pages of a multi-page app are output at their paths relative to the main page.
+        for page in &cfg.pages {
+            let output = page
+                .strip_prefix(&cfg.target_parent)
+                .context("error stripping dir of the main page from page")?
+                .components()
+                .filter_map(|segment| segment.as_os_str().to_str())
+                .collect::<Vec<_>>()
+                .join("/");
+            ensure!(
+                pages.iter().all(|other| other.output != output),
+                "multiple pages of [build].targets are output to {:?}",
+                output
+            );
+            pages.push(HtmlPage::new(
+                cfg.clone(),
+                page,
+                output,
+                ignore_chan.clone(),
+            )?);
+        }
+
+        Ok(Self { cfg, pages })
+    }
+
+    /// Spawn a new pipeline.
+    ///
+    /// The pipeline is cancelled if the returned future is dropped before it completes.
+    #[tracing::instrument(level = "trace", skip(self))]
+    pub fn spawn(self: Arc<Self>) -> impl Future<Output = Result<Result<()>, JoinError>> {
+        let (cancel_tx, cancel_rx) = oneshot::channel::<()>();
+        // NOTE WELL: this is a pattern to spawn a blocking thread, and then execute a !Send
+        // future on the current thread. This is needed because nipper's internals are !Send.
+        let handle = tokio::task::spawn_blocking(move || {
+            Handle::current().block_on(async move {
+                tokio::select! {
+                    res = self.run() => res,
+                    // The sender is only dropped without sending, once the pipeline is cancelled.
+                    _ = cancel_rx => Err(anyhow!("HTML pipeline cancelled")),
+                }
+            })
+        });
+        async move {
+            let _cancel_tx = cancel_tx;
+            handle.await
+        }
+    }
+
+    /// Run this pipeline.
+    #[tracing::instrument(level = "trace", skip(self))]
+    async fn run(self: Arc<Self>) -> Result<()> {
+        tracing::info!("spawning asset pipelines");
+
+        // Spawn and wait on pre-build hooks.
+        wait_hooks(spawn_hooks(self.cfg.clone(), PipelineStage::PreBuild).await?).await?;
+
+        // Spawn all build hooks.
+        let mut build_hooks = Some(spawn_hooks(self.cfg.clone(), PipelineStage::Build).await?);
+
+        // Build the pages one after another, so that they share the outputs of their Rust apps.
+        let docs = self.assemble_pages().await?;
+        for (page, doc) in self.pages.iter().zip(docs) {
+            page.run(doc, &mut build_hooks)
+                .await
+                .with_context(|| format!("error building page {}", page.output))?;
+        }
+
+        // Spawn and wait on post-build hooks.
+        wait_hooks(spawn_hooks(self.cfg.clone(), PipelineStage::PostBuild).await?).await?;
+
+        Ok(())
+    }
+
+    /// Assemble the source HTML of all pages up front, recording their characters for the subsets
+    /// of the fonts, which keep the characters of every page referencing them.
+    pub(super) async fn assemble_pages(&self) -> Result<Vec<Document>> {
+        let mut docs = Vec::with_capacity(self.pages.len());
+        for page in &self.pages {
+            let doc = page
+                .assemble()
+                .await
+                .with_context(|| format!("error building page {}", page.output))?;
+            self.cfg
+                .font_characters
+                .record_page(&doc, &page.target_html_dir)
+                .await;
+            docs.push(doc);
+        }
+        Ok(docs)
+    }
+}
+
+impl HtmlPage {
+    /// Create a new instance, output to the given path relative to the dist dir.
+    fn new(
+        cfg: Arc<RtcBuild>,
+        target: &Path,
+        output: String,
+        ignore_chan: Option<mpsc::Sender<PathBuf>>,
+    ) -> Result<Self> {
+        let target_html_path = target
             .canonicalize()
             .context("failed to get canonical path of target HTML file")?;
         let target_html_dir = Arc::new(
@@ -52,33 +176,49 @@ impl HtmlPipeline {
                 .to_owned(),
         );
 
+        // A relative public URL is relative to the output HTML, so pages in subdirs go up to the
+        // root of the dist dir first.
+        let depth = output.matches('/').count();
+        let cfg = if depth > 0 && is_relative_url(&cfg.public_url) {
+            let mut page_cfg = (*cfg).clone();
+            page_cfg.public_url = format!(
+                "{}{}",
+                "../".repeat(depth),
+                cfg.public_url.trim_start_matches("./")
+            );
+            Arc::new(page_cfg)
+        } else {
+            cfg
+        };
+
         Ok(Self {
             cfg,
             target_html_path,
             target_html_dir,
+            output,
             ignore_chan,
         })
     }
 
-    /// Spawn a new pipeline.
-    #[tracing::instrument(level = "trace", skip(self))]
-    pub fn spawn(self: Arc<Self>) -> JoinHandle<Result<()>> {
-        // NOTE WELL: this is a pattern to spawn a blocking thread, and then execute a !Send
-        // future on the current thread. This is needed because nipper's internals are !Send.
-        tokio::task::spawn_blocking(move || Handle::current().block_on(self.run()))
-    }
-
-    /// Run this pipeline.
-    #[tracing::instrument(level = "trace", skip(self))]
-    async fn run(self: Arc<Self>) -> Result<()> {
-        tracing::info!("spawning asset pipelines");
-
-        // Spawn and wait on pre-build hooks.
-        wait_hooks(spawn_hooks(self.cfg.clone(), PipelineStage::PreBuild)).await?;
-
-        // Open the source HTML file for processing.
+    /// Assemble the source HTML of this page, substituting its variables & including its partials.
+    async fn assemble(&self) -> Result<Document> {
         let raw_html = fs::read_to_string(&self.target_html_path).await?;
+        let raw_html = env::substitute(&raw_html, &self.cfg)?;
         let mut target_html = Document::from(&raw_html);
+        partials::include(&mut target_html, &self.target_html_dir, &self.cfg).await?;
+        Ok(target_html)
+    }
+
+    /// Build the output HTML of this page from its assembled source HTML, waiting for the given
+    /// build hooks before finalizing it, unless they were already waited for by a previous page.
+    async fn run(
+        &self,
+        mut target_html: Document,
+        build_hooks: &mut Option<HookHandles>,
+    ) -> Result<()> {
+        if !self.cfg.locales.is_empty() {
+            i18n::mark_locale_elements(&mut target_html);
+        }
 
         // Iterator over all `link[data-trunk]` elements, assigning IDs & building pipelines.
         let mut assets = vec![];
@@ -121,6 +261,8 @@ impl HtmlPipeline {
             };
 
             if let Some(asset_ref) = asset_ref {
+                let label = asset_ref.label();
+                let hint = asset_ref.resource_hint();
                 let asset = TrunkAsset::from_html(
                     self.cfg.clone(),
                     self.target_html_dir.clone(),
@@ -129,7 +271,7 @@ impl HtmlPipeline {
                     id,
                 )
                 .await?;
-                assets.push(asset);
+                assets.push((label, hint, asset));
             }
         }
 
@@ -137,10 +279,6 @@ impl HtmlPipeline {
         let rust_app_nodes = target_html
             .select(r#"link[data-trunk][rel="rust"][data-type="main"], link[data-trunk][rel="rust"]:not([data-type])"#)
             .length();
-        ensure!(
-            rust_app_nodes <= 1,
-            r#"only one <link data-trunk rel="rust" data-type="main" .../> may be specified"#
-        );
         if rust_app_nodes == 0 {
             if let Ok(app) = RustApp::new_default(
                 self.cfg.clone(),
@@ -149,52 +287,215 @@ impl HtmlPipeline {
             )
             .await
             {
-                assets.push(TrunkAsset::RustApp(app));
+                assets.push((
+                    format!("rust {}", app.name()),
+                    None,
+                    TrunkAsset::RustApp(app),
+                ));
             } else {
                 tracing::warn!("no rust project found")
             };
         }
 
+        // Ensure that the output files of the Rust apps are named uniquely.
+        let mut rust_app_names = HashSet::new();
+        for (_, _, asset) in &assets {
+            if let TrunkAsset::RustApp(app) = asset {
+                ensure!(
+                    rust_app_names.insert(app.name()),
+                    r#"multiple <link data-trunk rel="rust" .../> produce output named {:?}, use `data-bin` or `data-target-name` to name them uniquely"#,
+                    app.name()
+                );
+            }
+        }
+
         // Spawn all asset pipelines.
         let mut pipelines: AssetPipelineHandles = FuturesUnordered::new();
-        pipelines.extend(assets.into_iter().map(|asset| asset.spawn()));
-        // Spawn all build hooks.
-        let build_hooks = spawn_hooks(self.cfg.clone(), PipelineStage::Build);
+        pipelines.extend(
+            assets
+                .into_iter()
+                .enumerate()
+                .map(|(order, (label, hint, asset))| {
+                    let cfg = self.cfg.clone();
+                    AbortOnDrop(tokio::spawn(async move {
+                        // Wait for a permit, if the number of concurrent pipelines is limited.
+                        let _permit = match &cfg.pipeline_permits {
+                            Some(permits) => Some(
+                                permits
+                                    .acquire()
+                                    .await
+                                    .context("error acquiring permit of asset pipeline")?,
+                            ),
+                            None => None,
+                        };
+                        let _timing = cfg.time("asset", &label);
+                        let output = AbortOnDrop(asset.spawn())
+                            .await
+                            .context("error awaiting asset pipeline")??;
+                        let hints = match hint {
+                            Some(hint) => {
+                                let files = output.hinted_files();
+                                if files.is_empty() {
+                                    tracing::warn!("{} has no output files to hint", label);
+                                }
+                                hint.render(&cfg, &files).await?
+                            }
+                            None => String::new(),
+                        };
+                        Ok((order, hints, output))
+                    }))
+                }),
+        );
 
         // Finalize asset pipelines.
-        self.finalize_asset_pipelines(&mut target_html, pipelines)
+        let imports = self
+            .finalize_asset_pipelines(&mut target_html, pipelines)
             .await?;
 
-        // Wait for all build hooks to finish.
-        wait_hooks(build_hooks).await?;
+        // Wait for all build hooks to finish, before finalizing the HTML of the first page.
+        if let Some(build_hooks) = build_hooks.take() {
+            wait_hooks(build_hooks).await?;
+        }
+        let timing = self.cfg.time("html", format!("finalize {}", self.output));
 
         // Finalize HTML.
         self.finalize_html(&mut target_html);
 
-        // Assemble a new output index.html file.
-        let output_html = target_html.html().to_string(); // TODO: prettify this output.
-        fs::write(self.cfg.staging_dist.join("index.html"), &output_html)
-            .await
-            .context("error writing finalized HTML output")?;
+        // Add the module mappings to the import map, before hashing it for the CSP.
+        if !imports.is_empty() {
+            import_map::apply(&mut target_html, &imports)?;
+        }
 
-        // Spawn and wait on post-build hooks.
-        wait_hooks(spawn_hooks(self.cfg.clone(), PipelineStage::PostBuild)).await?;
+        // Assemble a new output index.html file, or one per locale.
+        if let Some(default_locale) = self.cfg.default_locale.as_deref() {
+            let html = target_html.html();
+            for locale in &self.cfg.locales {
+                let localized = Document::from(i18n::localize(&html, locale).as_str());
+                self.write_page(&self.cfg.staging_dist.join(locale), localized)
+                    .await
+                    .with_context(|| format!("error writing HTML output of locale {}", locale))?;
+            }
+            let localized = Document::from(i18n::localize(&html, default_locale).as_str());
+            self.write_page(&self.cfg.staging_dist, localized)
+                .await
+                .context("error writing finalized HTML output")?;
+        } else {
+            self.write_page(&self.cfg.staging_dist, target_html)
+                .await
+                .context("error writing finalized HTML output")?;
+        }
 
+        drop(timing);
+        Ok(())
+    }
+
+    /// Write the given finalized document of this page to its path in the given dir.
+    ///
+    /// The hashes of the inline scripts & styles are added to its Content-Security-Policy here, as
+    /// the document of each locale has its own inline scripts & substituted `{{ locale }}`s.
+    async fn write_page(&self, dir: &Path, mut doc: Document) -> Result<()> {
+        if self.cfg.csp {
+            self.finalize_csp(dir, &mut doc).await?;
+        }
+        let output_html = self.output_html(&doc.html()); // TODO: prettify this output.
+        self.write_output(dir, &output_html).await
+    }
+
+    /// Write the given output HTML of this page to its path in the given dir.
+    async fn write_output(&self, dir: &Path, html: &[u8]) -> Result<()> {
+        let mut path = dir.to_owned();
+        path.extend(self.output.split('/'));
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent)
+                .await
+                .with_context(|| format!("error creating dir {:?}", parent))?;
+        }
+        fs::write(&path, html)
+            .await
+            .with_context(|| format!("error writing HTML output {:?}", path))?;
+        self.cfg.output_hashes.record(&path, html).await;
         Ok(())
     }
 
     /// Finalize asset pipelines & prep the DOM for final output.
+    ///
+    /// Returns the configured module mappings of the import map, along with those of the assets.
+    /// The resource hints of the assets are appended to the head.
     async fn finalize_asset_pipelines(
         &self,
         target_html: &mut Document,
         mut pipelines: AssetPipelineHandles,
-    ) -> Result<()> {
+    ) -> Result<BTreeMap<String, String>> {
+        let mut imports = self.cfg.import_map.clone();
+        let mut hints = BTreeMap::new();
+        let mut assets = BTreeMap::new();
         while let Some(asset_res) = pipelines.next().await {
-            let asset = asset_res
+            let (order, asset_hints, asset) = asset_res
                 .context("failed to await asset finalization")?
                 .context("error from asset pipeline")?;
+            if !asset_hints.is_empty() {
+                hints.insert(order, asset_hints);
+            }
+            assets.insert(order, asset);
+        }
+        // Finalize the assets in their order, rather than the one their pipelines finished in, as
+        // some append elements to the document, for a deterministic output.
+        for asset in assets.into_values() {
+            if let Some((specifier, url)) = asset.import() {
+                imports.insert(specifier, url);
+            }
             asset.finalize(target_html).await?;
         }
+        // Add the resource hints in the order of their assets, for a deterministic output.
+        if !hints.is_empty() {
+            target_html
+                .select("head")
+                .append_html(hints.into_values().collect::<String>());
+        }
+        Ok(imports)
+    }
+
+    /// Get the bytes of the given finalized HTML to output, minified if enabled.
+    fn output_html(&self, html: &str) -> Vec<u8> {
+        if self.cfg.minify {
+            minify_html(html.as_bytes())
+        } else {
+            html.as_bytes().to_vec()
+        }
+    }
+
+    /// Add the hashes of the inline scripts & styles of the document to its Content-Security-Policy
+    /// meta tag, if any, and write them to the CSP manifest of the page in the given dir.
+    async fn finalize_csp(&self, dir: &Path, target_html: &mut Document) -> Result<()> {
+        // Hash the inline scripts & styles as they are output, i.e. after minification.
+        let hashes = if self.cfg.minify {
+            let minified = minify_html(target_html.html().as_bytes());
+            CspHashes::from_document(&Document::from(String::from_utf8_lossy(&minified).as_ref()))
+        } else {
+            CspHashes::from_document(target_html)
+        };
+        for meta in target_html.select(CSP_META_SELECTOR).nodes() {
+            let policy = meta.attr("content").unwrap_or_default();
+            meta.set_attr("content", &hashes.apply(&policy));
+        }
+
+        let manifest =
+            serde_json::to_string_pretty(&hashes).context("error serializing CSP hashes")?;
+        // The other pages of a multi-page app have their own manifests, e.g. `admin.csp.json`.
+        let manifest_path = match self.output.strip_suffix(".html") {
+            Some(page) if page != "index" => format!("{}.{}", page, CSP_MANIFEST),
+            _ => CSP_MANIFEST.to_owned(),
+        };
+        let mut path = dir.to_owned();
+        path.extend(manifest_path.split('/'));
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent)
+                .await
+                .with_context(|| format!("error creating dir {:?}", parent))?;
+        }
+        fs::write(path, manifest)
+            .await
+            .context("error writing CSP manifest")?;
         Ok(())
     }
 
@@ -206,11 +507,24 @@ impl HtmlPipeline {
         base_elements.remove_attr(PUBLIC_URL_MARKER_ATTR);
         base_elements.set_attr("href", &self.cfg.public_url);
 
-        // Inject the WebSocket autoloader.
-        if self.cfg.inject_autoloader {
+        // Inject the registration of the service worker.
+        if self.cfg.service_worker {
             target_html
                 .select("body")
-                .append_html(format!("<script>{}</script>", RELOAD_SCRIPT));
+                .append_html(service_worker::registration_script(&self.cfg));
+        }
+
+        // Inject the WebSocket autoloader.
+        if let Some(ws_path) = self.cfg.autoreload_ws_path.as_deref() {
+            target_html.select("body").append_html(format!(
+                "<script>{}</script>",
+                RELOAD_SCRIPT.replace("{{__TRUNK_WS_PATH__}}", ws_path)
+            ));
         }
     }
 }
+
+/// Check if the given public URL is relative, i.e. neither absolute nor root-relative.
+fn is_relative_url(url: &str) -> bool {
+    !url.starts_with('/') && !url.contains("://")
+}
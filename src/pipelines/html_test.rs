@@ -0,0 +1,230 @@
+use std::path::Path;
+use std::sync::Arc;
+
+use anyhow::{ensure, Context, Result};
+
+use crate::config::RtcBuild;
+use crate::pipelines::csp::source_hash;
+use crate::pipelines::{integrity_hash, HtmlPipeline};
+
+/// Build the given source HTML with the given config & read the output HTML.
+async fn build(cfg: RtcBuild, html: &str) -> Result<String> {
+    tokio::fs::write(&cfg.target, html)
+        .await
+        .context("error writing source HTML")?;
+    let staging_dist = cfg.staging_dist.clone();
+    Arc::new(HtmlPipeline::new(Arc::new(cfg), None)?)
+        .spawn()
+        .await??;
+    read_output(&staging_dist).await
+}
+
+async fn read_output(dir: &Path) -> Result<String> {
+    tokio::fs::read_to_string(dir.join("index.html"))
+        .await
+        .context("error reading output HTML")
+}
+
+#[tokio::test]
+async fn injects_configured_autoreload_ws_path() -> Result<()> {
+    // Assemble.
+    let tmpdir = tempfile::tempdir().context("error building tempdir for test")?;
+    let mut cfg = RtcBuild::new_test(tmpdir.path()).await?;
+    cfg.autoreload_ws_path = Some("/dev/reload".into());
+
+    // Action.
+    let html = build(cfg, "<html><head></head><body></body></html>").await?;
+
+    // Assert.
+    ensure!(
+        html.contains("/dev/reload") && !html.contains("/_trunk/ws"),
+        "expected the configured WebSocket path in the output HTML: {}",
+        html
+    );
+    ensure!(
+        !html.contains("__TRUNK_WS_PATH__"),
+        "unexpected placeholder in the output HTML: {}",
+        html
+    );
+    Ok(())
+}
+
+#[tokio::test]
+async fn omits_autoreload_script_if_disabled() -> Result<()> {
+    // Assemble.
+    let tmpdir = tempfile::tempdir().context("error building tempdir for test")?;
+    let mut cfg = RtcBuild::new_test(tmpdir.path()).await?;
+    cfg.autoreload_ws_path = None;
+
+    // Action.
+    let html = build(cfg, "<html><head></head><body></body></html>").await?;
+
+    // Assert.
+    ensure!(
+        !html.contains("WebSocket"),
+        "unexpected autoreload script in the output HTML: {}",
+        html
+    );
+    Ok(())
+}
+
+#[tokio::test]
+async fn rejects_rust_apps_with_the_same_output_name() -> Result<()> {
+    // Assemble.
+    let tmpdir = tempfile::tempdir().context("error building tempdir for test")?;
+    let dir = tmpdir.path();
+    std::fs::create_dir_all(dir.join("src"))?;
+    std::fs::write(
+        dir.join("Cargo.toml"),
+        "[package]\nname = \"app\"\nversion = \"0.1.0\"\nedition = \"2021\"\n",
+    )?;
+    std::fs::write(dir.join("src").join("main.rs"), "fn main() {}")?;
+    let cfg = RtcBuild::new_test(dir).await?;
+
+    // Action.
+    let res = build(
+        cfg,
+        r#"<html><head><link data-trunk rel="rust"><link data-trunk rel="rust" data-cargo-features="admin"></head><body></body></html>"#,
+    )
+    .await;
+
+    // Assert.
+    let err = res.err().context("expected the clashing names to err")?;
+    ensure!(
+        format!("{:#}", err).contains(r#"produce output named "app""#),
+        "unexpected error: {:#}",
+        err
+    );
+    Ok(())
+}
+
+#[tokio::test]
+async fn adds_integrity_hashes_to_the_emitted_tags() -> Result<()> {
+    // Assemble.
+    let tmpdir = tempfile::tempdir().context("error building tempdir for test")?;
+    let mut cfg = RtcBuild::new_test(tmpdir.path()).await?;
+    cfg.sri = true;
+    cfg.filehash = false;
+    tokio::fs::write(tmpdir.path().join("style.css"), "body { margin: 0 }").await?;
+    tokio::fs::write(tmpdir.path().join("app.js"), "console.log(1);").await?;
+
+    // Action.
+    let html = build(
+        cfg,
+        r#"<html><head><link data-trunk rel="css" href="style.css"><script data-trunk src="app.js"></script></head><body></body></html>"#,
+    )
+    .await?;
+
+    // Assert.
+    for expected in [
+        format!(
+            r#"href="/style.css" integrity="{}" crossorigin="anonymous""#,
+            integrity_hash(b"body { margin: 0 }")
+        ),
+        format!(
+            r#"src="/app.js" integrity="{}" crossorigin="anonymous""#,
+            integrity_hash(b"console.log(1);")
+        ),
+    ] {
+        ensure!(
+            html.contains(&expected),
+            "expected {} in: {}",
+            expected,
+            html
+        );
+    }
+    Ok(())
+}
+
+#[tokio::test]
+async fn hashes_the_inline_scripts_of_each_locale_for_the_csp() -> Result<()> {
+    // Assemble.
+    let tmpdir = tempfile::tempdir().context("error building tempdir for test")?;
+    let mut cfg = RtcBuild::new_test(tmpdir.path()).await?;
+    cfg.autoreload_ws_path = None;
+    cfg.csp = true;
+    cfg.locales = vec!["en".into(), "de".into()];
+    cfg.default_locale = Some("en".into());
+    let staging_dist = cfg.staging_dist.clone();
+
+    // Action.
+    let html = build(
+        cfg,
+        r#"<html><head><meta http-equiv="Content-Security-Policy" content="default-src 'self'"></head><body><script>window.locale = "{{ locale }}";</script></body></html>"#,
+    )
+    .await?;
+    let de_html = read_output(&staging_dist.join("de")).await?;
+    let de_manifest = std::fs::read_to_string(staging_dist.join("de").join("csp.json"))?;
+
+    // Assert.
+    let (en_hash, de_hash) = (
+        source_hash(r#"window.locale = "en";"#),
+        source_hash(r#"window.locale = "de";"#),
+    );
+    ensure!(
+        html.contains(&format!("script-src 'self' {}", en_hash)) && !html.contains(&de_hash),
+        "expected the hash of the english script in {}",
+        html
+    );
+    ensure!(
+        de_html.contains(&format!("script-src 'self' {}", de_hash)) && !de_html.contains(&en_hash),
+        "expected the hash of the german script in {}",
+        de_html
+    );
+    ensure!(
+        de_manifest.contains(&de_hash),
+        "unexpected manifest {}",
+        de_manifest
+    );
+    Ok(())
+}
+
+#[tokio::test]
+async fn subsets_fonts_to_the_characters_of_every_page_referencing_them() -> Result<()> {
+    // Assemble.
+    let tmpdir = tempfile::tempdir().context("error building tempdir for test")?;
+    let dir = tmpdir.path();
+    std::fs::create_dir_all(dir.join("docs"))?;
+    std::fs::create_dir_all(dir.join("blog"))?;
+    std::fs::write(dir.join("inter.woff2"), b"wOF2")?;
+    std::fs::write(
+        dir.join("index.html"),
+        r#"<html><head><link data-trunk rel="font" href="inter.woff2" data-subset=""></head><body>Home</body></html>"#,
+    )?;
+    std::fs::write(
+        dir.join("docs").join("index.html"),
+        r#"<html><head><link data-trunk rel="font" href="../inter.woff2" data-subset=""></head><body><link data-trunk rel="include" href="footer.html"></body></html>"#,
+    )?;
+    std::fs::write(dir.join("docs").join("footer.html"), "<p>Ω</p>")?;
+    std::fs::write(
+        dir.join("blog").join("index.html"),
+        "<html><head></head><body>Ж</body></html>",
+    )?;
+    let mut cfg = RtcBuild::new_test(dir).await?;
+    cfg.pages = vec![
+        dir.join("docs").join("index.html"),
+        dir.join("blog").join("index.html"),
+    ];
+    let cfg = Arc::new(cfg);
+
+    // Action.
+    HtmlPipeline::new(cfg.clone(), None)?
+        .assemble_pages()
+        .await?;
+    let characters = cfg
+        .font_characters
+        .get(&dir.join("inter.woff2").canonicalize()?);
+
+    // Assert.
+    ensure!(
+        characters.contains('H') && characters.contains('Ω'),
+        "expected the characters of both pages in {:?}",
+        characters
+    );
+    ensure!(
+        !characters.contains('Ж'),
+        "unexpected characters of the page without the font in {:?}",
+        characters
+    );
+    Ok(())
+}
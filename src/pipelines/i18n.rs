@@ -0,0 +1,140 @@
+//! Per-locale builds of the output HTML.
+
+use std::cmp::Ordering;
+
+use nipper::Document;
+use once_cell::sync::Lazy;
+use regex::Regex;
+
+/// The attribute restricting an element (or asset) to the output HTML of a locale.
+const ATTR_LOCALE: &str = "data-locale";
+/// The opening marker of the section of a locale, followed by the locale & `-->`.
+const SECTION_START: &str = "<!--trunk-locale:";
+/// The closing marker of the section of a locale.
+const SECTION_END: &str = "<!--/trunk-locale-->";
+
+/// The regex of the `{{ locale }}` placeholders of the output HTML.
+static PLACEHOLDER: Lazy<Regex> = Lazy::new(|| {
+    Regex::new(r"\{\{\s*locale\s*\}\}").expect("error compiling locale placeholder regex")
+});
+
+/// Wrap the elements of the given document which are restricted to a locale with `data-locale`
+/// in markers of the section of the locale, which survive the finalization of their assets.
+///
+/// This must be done before the Trunk IDs are assigned, as the elements are replaced.
+pub fn mark_locale_elements(doc: &mut Document) {
+    loop {
+        let mut element = doc.select(&format!("[{}]", ATTR_LOCALE)).first();
+        let Some(node) = element.nodes().first().cloned() else {
+            break;
+        };
+        let locale = node.attr(ATTR_LOCALE).unwrap_or_default().trim().to_owned();
+        node.remove_attr(ATTR_LOCALE);
+        // The document structure can not be restricted to a locale, only elements within it.
+        if matches!(node.node_name().as_deref(), Some("html" | "head" | "body")) {
+            tracing::warn!(
+                "`{}` is not supported on <html>, <head> & <body>",
+                ATTR_LOCALE
+            );
+            continue;
+        }
+        element.replace_with_html(format!(
+            "{}{}-->{}{}",
+            SECTION_START,
+            locale,
+            node.html(),
+            SECTION_END
+        ));
+    }
+}
+
+/// Build the output HTML of the given locale from the finalized HTML with the locale sections,
+/// keeping only the sections of the locale, substituting the `{{ locale }}` placeholders and
+/// setting the `lang` of the document.
+pub fn localize(html: &str, locale: &str) -> String {
+    let html = strip_sections(html, locale);
+    let doc = Document::from(PLACEHOLDER.replace_all(&html, locale).as_ref());
+    doc.select("html").set_attr("lang", locale);
+    doc.html().to_string()
+}
+
+/// Remove the sections of the other locales from the given HTML, along with all markers.
+fn strip_sections(html: &str, locale: &str) -> String {
+    let mut output = String::with_capacity(html.len());
+    // The nested sections, and if each of them is kept.
+    let mut sections = Vec::<bool>::new();
+    let mut rest = html;
+    loop {
+        let start = rest.find(SECTION_START);
+        let end = rest.find(SECTION_END);
+        let (index, marker_len, opening) = match (start, end) {
+            (Some(start), Some(end)) if start < end => (start, SECTION_START.len(), true),
+            (Some(start), None) => (start, SECTION_START.len(), true),
+            (_, Some(end)) => (end, SECTION_END.len(), false),
+            (None, None) => break,
+        };
+        if sections.iter().all(|keep| *keep) {
+            output.push_str(&rest[..index]);
+        }
+        rest = &rest[index + marker_len..];
+        if opening {
+            let (section_locale, after) = rest.split_once("-->").unwrap_or((rest, ""));
+            sections.push(section_locale == locale);
+            rest = after;
+        } else {
+            sections.pop();
+        }
+    }
+    if sections.iter().all(|keep| *keep) {
+        output.push_str(rest);
+    }
+    output
+}
+
+/// Select the locale to serve for the given `Accept-Language` header.
+///
+/// The language ranges of the header are tried in the order of their quality. A range matches a
+/// locale if they are equal, or if one of them is a prefix of the other, e.g. `de-CH` matches
+/// `de`, ignoring case. A wildcard range matches the default locale.
+pub fn negotiate_locale<'a>(
+    accept_language: &str,
+    locales: &'a [String],
+    default_locale: Option<&'a str>,
+) -> Option<&'a str> {
+    let mut ranges = accept_language
+        .split(',')
+        .filter_map(|range| {
+            let mut parts = range.split(';');
+            let tag = parts.next()?.trim();
+            let quality = parts
+                .find_map(|param| param.trim().strip_prefix("q="))
+                .map_or(Some(1.0), |q| q.trim().parse::<f32>().ok())?;
+            (!tag.is_empty() && quality > 0.0).then_some((tag, quality))
+        })
+        .collect::<Vec<_>>();
+    // A stable sort keeps the order of the ranges with equal quality.
+    ranges.sort_by(|(_, a), (_, b)| b.partial_cmp(a).unwrap_or(Ordering::Equal));
+
+    ranges.into_iter().find_map(|(tag, _)| {
+        if tag == "*" {
+            return default_locale;
+        }
+        let tag = tag.to_ascii_lowercase();
+        locales
+            .iter()
+            .find(|locale| locale.to_ascii_lowercase() == tag)
+            .or_else(|| {
+                locales.iter().find(|locale| {
+                    let locale = locale.to_ascii_lowercase();
+                    is_subtag_prefix(&tag, &locale) || is_subtag_prefix(&locale, &tag)
+                })
+            })
+            .map(String::as_str)
+    })
+}
+
+/// Check if the given language tag is a prefix of the other, ending at a subtag boundary.
+fn is_subtag_prefix(prefix: &str, tag: &str) -> bool {
+    tag.strip_prefix(prefix)
+        .is_some_and(|rest| rest.starts_with(['-', '_']))
+}
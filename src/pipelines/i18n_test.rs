@@ -0,0 +1,95 @@
+use anyhow::{ensure, Result};
+use nipper::Document;
+
+use crate::pipelines::i18n::*;
+
+const HTML: &str = r#"<html><head><link data-locale="de" rel="stylesheet" href="/de.css"><link data-locale="fr" rel="stylesheet" href="/fr.css"></head><body><p>{{ locale }}</p></body></html>"#;
+
+/// Mark the locale elements of the given HTML & localize it for the given locale.
+fn build(html: &str, locale: &str) -> String {
+    let mut doc = Document::from(html);
+    mark_locale_elements(&mut doc);
+    localize(&doc.html(), locale)
+}
+
+#[test]
+fn localize_keeps_only_the_elements_of_the_locale() -> Result<()> {
+    let html = build(HTML, "de");
+    ensure!(
+        html.contains(r#"href="/de.css""#),
+        "missing de asset: {}",
+        html
+    );
+    ensure!(
+        !html.contains(r#"href="/fr.css""#),
+        "unexpected fr asset: {}",
+        html
+    );
+    ensure!(!html.contains("data-locale"), "unexpected attr: {}", html);
+    ensure!(
+        !html.contains("trunk-locale"),
+        "unexpected marker: {}",
+        html
+    );
+    Ok(())
+}
+
+#[test]
+fn localize_substitutes_the_locale_and_sets_lang() -> Result<()> {
+    let html = build(HTML, "fr");
+    ensure!(
+        html.contains(r#"<html lang="fr">"#),
+        "missing lang: {}",
+        html
+    );
+    ensure!(html.contains("<p>fr</p>"), "missing locale: {}", html);
+    ensure!(
+        html.contains(r#"href="/fr.css""#),
+        "missing fr asset: {}",
+        html
+    );
+    Ok(())
+}
+
+#[test]
+fn localize_handles_nested_sections() -> Result<()> {
+    let html = r#"<html><head></head><body><div data-locale="de"><p data-locale="fr">fr</p><p>de</p></div><p data-locale="fr">fr</p></body></html>"#;
+    let de = build(html, "de");
+    ensure!(
+        de.contains("<div><p>de</p></div>") && !de.contains(">fr<"),
+        "unexpected de html: {}",
+        de
+    );
+    let fr = build(html, "fr");
+    ensure!(
+        !fr.contains("<div>") && fr.contains("<p>fr</p>"),
+        "unexpected fr html: {}",
+        fr
+    );
+    Ok(())
+}
+
+#[test]
+fn negotiate_locale_uses_quality_and_prefixes() -> Result<()> {
+    let locales = vec!["en".to_owned(), "de".to_owned(), "pt-BR".to_owned()];
+    let cases = [
+        ("de-CH, en;q=0.5", Some("de")),
+        ("fr, en;q=0.1, de;q=0.9", Some("de")),
+        ("pt", Some("pt-BR")),
+        ("PT-br", Some("pt-BR")),
+        ("fr, *;q=0.5", Some("en")),
+        ("de;q=0, fr", None),
+        ("", None),
+    ];
+    for (header, expected) in cases {
+        let locale = negotiate_locale(header, &locales, Some("en"));
+        ensure!(
+            locale == expected,
+            "expected {:?} for {:?}, got {:?}",
+            expected,
+            header,
+            locale
+        );
+    }
+    Ok(())
+}
@@ -1,15 +1,36 @@
 //! Icon asset pipeline.
 
+use std::io::Cursor;
 use std::path::PathBuf;
 use std::sync::Arc;
 
-use anyhow::{Context, Result};
+use anyhow::{bail, Context, Result};
+use image::codecs::ico::{IcoEncoder, IcoFrame};
+use image::imageops::{self, FilterType};
+use image::{ExtendedColorType, ImageFormat, Rgba, RgbaImage};
 use nipper::Document;
+use resvg::tiny_skia::{Pixmap, Transform};
+use resvg::usvg;
+use tokio::fs;
 use tokio::task::JoinHandle;
 
-use super::{AssetFile, Attrs, TrunkAssetPipelineOutput, ATTR_HREF};
+use super::cache::InputDigest;
+use super::{AssetFile, AssetFileType, Attrs, TrunkAssetPipelineOutput, ATTR_HREF};
 use crate::config::RtcBuild;
 
+/// The attribute to generate a set of favicons from the icon.
+const ATTR_FAVICONS: &str = "data-favicons";
+/// The `rel` & size of the PNG icons of a favicon set.
+const FAVICON_PNGS: &[(&str, u32)] = &[
+    ("icon", 16),
+    ("icon", 32),
+    ("apple-touch-icon", 180),
+    ("icon", 192),
+    ("icon", 512),
+];
+/// The sizes of the images of the `.ico` favicon of a favicon set.
+const FAVICON_ICO_SIZES: &[u32] = &[16, 32, 48];
+
 /// An Icon asset pipeline.
 pub struct Icon {
     /// The ID of this pipeline's source HTML element.
@@ -18,6 +39,8 @@ pub struct Icon {
     cfg: Arc<RtcBuild>,
     /// The asset file being processed.
     asset: AssetFile,
+    /// If a set of favicons should be generated from the icon.
+    favicons: bool,
 }
 
 impl Icon {
@@ -36,7 +59,13 @@ impl Icon {
         let mut path = PathBuf::new();
         path.extend(href_attr.split('/'));
         let asset = AssetFile::new(&html_dir, path).await?;
-        Ok(Self { id, cfg, asset })
+        let favicons = attrs.contains_key(ATTR_FAVICONS);
+        Ok(Self {
+            id,
+            cfg,
+            asset,
+            favicons,
+        })
     }
 
     /// Spawn the pipeline for this asset type.
@@ -49,18 +78,231 @@ impl Icon {
     #[tracing::instrument(level = "trace", skip(self))]
     async fn run(self) -> Result<TrunkAssetPipelineOutput> {
         let rel_path = crate::common::strip_prefix(&self.asset.path);
-        tracing::info!(path = ?rel_path, "copying & hashing icon");
-        let file = self
-            .asset
-            .copy(&self.cfg.staging_dist, self.cfg.filehash)
-            .await?;
-        tracing::info!(path = ?rel_path, "finished copying & hashing icon");
+        let links = if self.favicons {
+            tracing::info!(path = ?rel_path, "generating favicons");
+            let links = self.generate_favicons().await?;
+            tracing::info!(path = ?rel_path, "finished generating favicons");
+            links
+        } else {
+            tracing::info!(path = ?rel_path, "copying & hashing icon");
+            let file = self
+                .asset
+                .copy(&self.cfg, self.cfg.filehash, false, AssetFileType::Other)
+                .await?;
+            tracing::info!(path = ?rel_path, "finished copying & hashing icon");
+            vec![IconLink {
+                rel: "icon",
+                mime_type: None,
+                sizes: None,
+                file,
+            }]
+        };
         Ok(TrunkAssetPipelineOutput::Icon(IconOutput {
             cfg: self.cfg.clone(),
             id: self.id,
-            file,
+            links,
         }))
     }
+
+    /// Generate the `.ico` favicon & the PNG icons of a favicon set from the icon, returning the
+    /// links to them. An SVG icon is linked itself as well.
+    async fn generate_favicons(&self) -> Result<Vec<IconLink>> {
+        let is_svg = self.asset.ext.as_deref() == Some("svg");
+        let data = fs::read(&self.asset.path)
+            .await
+            .with_context(|| format!("error reading icon {:?}", self.asset.path))?;
+
+        // Generate the `.ico` & the PNGs, in the order of their sizes, unless the icon is
+        // unchanged.
+        let mut digest = InputDigest::new("favicons");
+        digest.add([u8::from(is_svg)]).add(&data);
+        let label = format!(
+            "favicons of {:?}",
+            crate::common::strip_prefix(&self.asset.path)
+        );
+        let images = self
+            .cfg
+            .pipeline_cache
+            .get_or_run(&label, &digest, || async move {
+                tokio::task::spawn_blocking(move || -> Result<_> {
+                    let source = decode_icon(&data, is_svg)?;
+                    let mut images = vec![encode_ico(&source, FAVICON_ICO_SIZES)?];
+                    for (_, size) in FAVICON_PNGS {
+                        images.push(encode_png(&source, *size)?);
+                    }
+                    Ok(images)
+                })
+                .await
+                .context("error awaiting spawned favicon generation")?
+            })
+            .await?;
+        let (ico, pngs) = images
+            .split_first()
+            .context("error reading cached favicons")?;
+
+        let stem = self.asset.file_stem.to_string_lossy();
+        let mut links = vec![IconLink {
+            rel: "icon",
+            mime_type: None,
+            sizes: Some(
+                FAVICON_ICO_SIZES
+                    .iter()
+                    .map(|size| format!("{0}x{0}", size))
+                    .collect::<Vec<_>>()
+                    .join(" "),
+            ),
+            file: self.write(&stem, "ico", ico).await?,
+        }];
+        if is_svg {
+            links.push(IconLink {
+                rel: "icon",
+                mime_type: Some("image/svg+xml"),
+                sizes: None,
+                file: self
+                    .asset
+                    .copy(&self.cfg, self.cfg.filehash, false, AssetFileType::Other)
+                    .await?,
+            });
+        }
+        for ((rel, size), png) in FAVICON_PNGS.iter().zip(pngs) {
+            let name = format!("{0}-{1}x{1}", stem, size);
+            links.push(IconLink {
+                rel,
+                mime_type: Some("image/png"),
+                sizes: Some(format!("{0}x{0}", size)),
+                file: self.write(&name, "png", png).await?,
+            });
+        }
+        Ok(links)
+    }
+
+    /// Write the given generated icon to the staging dist dir, returning its (hashed) file name.
+    async fn write(&self, stem: &str, ext: &str, bytes: &[u8]) -> Result<String> {
+        let file_name = if self.cfg.filehash {
+            format!(
+                "{}.{}",
+                self.cfg.hashed_name(stem, seahash::hash(bytes)),
+                ext
+            )
+        } else {
+            format!("{}.{}", stem, ext)
+        };
+        let file_path = self.cfg.staging_path(&file_name);
+        if let Some(parent) = file_path.parent() {
+            fs::create_dir_all(parent)
+                .await
+                .context("error creating icon pipeline output dir")?;
+        }
+        fs::write(&file_path, bytes)
+            .await
+            .with_context(|| format!("error writing icon {:?}", file_path))?;
+        self.cfg.output_hashes.record(&file_path, bytes).await;
+        Ok(file_name)
+    }
+}
+
+/// The size in pixels to which SVG icons are rendered, which is the largest favicon size.
+const SVG_RENDER_SIZE: u32 = 512;
+
+/// Decode the given SVG or PNG icon into a square image, centering non-square icons on a
+/// transparent background.
+fn decode_icon(data: &[u8], is_svg: bool) -> Result<RgbaImage> {
+    let image = if is_svg {
+        let tree = usvg::Tree::from_data(data, &usvg::Options::default())
+            .context("error parsing SVG icon")?;
+        let size = tree.size();
+        let scale = SVG_RENDER_SIZE as f32 / size.width().max(size.height());
+        let mut pixmap = Pixmap::new(SVG_RENDER_SIZE, SVG_RENDER_SIZE)
+            .context("error allocating SVG icon canvas")?;
+        let transform = Transform::from_translate(
+            (SVG_RENDER_SIZE as f32 - size.width() * scale) / 2.0,
+            (SVG_RENDER_SIZE as f32 - size.height() * scale) / 2.0,
+        )
+        .pre_scale(scale, scale);
+        resvg::render(&tree, transform, &mut pixmap.as_mut());
+
+        let pixels = pixmap
+            .pixels()
+            .iter()
+            .flat_map(|pixel| {
+                let color = pixel.demultiply();
+                [color.red(), color.green(), color.blue(), color.alpha()]
+            })
+            .collect();
+        match RgbaImage::from_raw(SVG_RENDER_SIZE, SVG_RENDER_SIZE, pixels) {
+            Some(image) => image,
+            None => bail!("error converting rendered SVG icon"),
+        }
+    } else {
+        image::load_from_memory_with_format(data, ImageFormat::Png)
+            .context("error decoding PNG icon, favicons can be generated from SVG & PNG icons")?
+            .into_rgba8()
+    };
+
+    let (width, height) = image.dimensions();
+    if width == height {
+        return Ok(image);
+    }
+    let size = width.max(height);
+    let mut square = RgbaImage::from_pixel(size, size, Rgba([0, 0, 0, 0]));
+    imageops::overlay(
+        &mut square,
+        &image,
+        i64::from((size - width) / 2),
+        i64::from((size - height) / 2),
+    );
+    Ok(square)
+}
+
+/// Resize the given square image to the given size.
+fn resize(image: &RgbaImage, size: u32) -> RgbaImage {
+    if image.width() == size {
+        image.clone()
+    } else {
+        imageops::resize(image, size, size, FilterType::Lanczos3)
+    }
+}
+
+/// Encode the given square image as a PNG of the given size.
+fn encode_png(image: &RgbaImage, size: u32) -> Result<Vec<u8>> {
+    let mut png = Vec::new();
+    resize(image, size)
+        .write_to(&mut Cursor::new(&mut png), ImageFormat::Png)
+        .context("error encoding PNG favicon")?;
+    Ok(png)
+}
+
+/// Encode the given square image as an `.ico` file with images of the given sizes.
+fn encode_ico(image: &RgbaImage, sizes: &[u32]) -> Result<Vec<u8>> {
+    let frames = sizes
+        .iter()
+        .map(|size| {
+            IcoFrame::as_png(
+                resize(image, *size).as_raw(),
+                *size,
+                *size,
+                ExtendedColorType::Rgba8,
+            )
+        })
+        .collect::<Result<Vec<_>, _>>()
+        .context("error encoding `.ico` favicon")?;
+    let mut ico = Vec::new();
+    IcoEncoder::new(&mut ico)
+        .encode_images(&frames)
+        .context("error encoding `.ico` favicon")?;
+    Ok(ico)
+}
+
+/// A link to an icon in the output HTML.
+pub struct IconLink {
+    /// The `rel` of the link.
+    pub rel: &'static str,
+    /// The MIME type of the icon, if it is to be specified.
+    pub mime_type: Option<&'static str>,
+    /// The sizes of the icon, if they are to be specified.
+    pub sizes: Option<String>,
+    /// Name of the finalized icon file.
+    pub file: String,
 }
 
 /// The output of an Icon build pipeline.
@@ -69,18 +311,35 @@ pub struct IconOutput {
     pub cfg: Arc<RtcBuild>,
     /// The ID of this pipeline.
     pub id: usize,
-    /// Name of the finalized output file.
-    pub file: String,
+    /// The links to the finalized icon files.
+    pub links: Vec<IconLink>,
 }
 
 impl IconOutput {
     pub async fn finalize(self, dom: &mut Document) -> Result<()> {
+        let html = self
+            .links
+            .iter()
+            .map(|link| {
+                let mime_type = link
+                    .mime_type
+                    .map(|mime_type| format!(r#" type="{}""#, mime_type))
+                    .unwrap_or_default();
+                let sizes = link
+                    .sizes
+                    .as_ref()
+                    .map(|sizes| format!(r#" sizes="{}""#, sizes))
+                    .unwrap_or_default();
+                format!(
+                    r#"<link rel="{rel}"{mime_type}{sizes} href="{base}{file}"/>"#,
+                    rel = link.rel,
+                    base = &self.cfg.public_url,
+                    file = link.file
+                )
+            })
+            .collect::<String>();
         dom.select(&super::trunk_id_selector(self.id))
-            .replace_with_html(format!(
-                r#"<link rel="icon" href="{base}{file}"/>"#,
-                base = &self.cfg.public_url,
-                file = self.file
-            ));
+            .replace_with_html(html);
         Ok(())
     }
 }
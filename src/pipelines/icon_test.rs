@@ -0,0 +1,90 @@
+use std::collections::HashMap;
+use std::path::PathBuf;
+use std::sync::Arc;
+
+use anyhow::{bail, ensure, Context, Result};
+use image::{Rgba, RgbaImage};
+
+use crate::config::RtcBuild;
+use crate::pipelines::icon::*;
+use crate::pipelines::{TrunkAssetPipelineOutput, ATTR_HREF};
+
+/// A fixture for setting up a test config & a non-square PNG icon.
+async fn setup_test_config() -> Result<(tempfile::TempDir, Arc<RtcBuild>, PathBuf)> {
+    let tmpdir = tempfile::tempdir().context("error building tempdir for test")?;
+    let cfg = RtcBuild::new_test(tmpdir.path()).await?;
+    tokio::fs::create_dir_all(&cfg.staging_dist)
+        .await
+        .context("error creating staging dist dir")?;
+    RgbaImage::from_pixel(64, 32, Rgba([255, 0, 0, 255]))
+        .save(tmpdir.path().join("icon.png"))
+        .context("error writing test icon")?;
+    let html_dir = tmpdir.path().to_owned();
+    Ok((tmpdir, Arc::new(cfg), html_dir))
+}
+
+/// Run the icon pipeline with the given attrs in addition to the `href` of the test icon.
+async fn run_icon(attrs: &[&str]) -> Result<(tempfile::TempDir, Arc<RtcBuild>, Vec<IconLink>)> {
+    let (tmpdir, cfg, html_dir) = setup_test_config().await?;
+    let mut attrs: HashMap<String, String> = attrs
+        .iter()
+        .map(|attr| (attr.to_string(), String::new()))
+        .collect();
+    attrs.insert(ATTR_HREF.into(), "icon.png".into());
+
+    let icon = Icon::new(cfg.clone(), Arc::new(html_dir), attrs, 0).await?;
+    match icon.spawn().await?? {
+        TrunkAssetPipelineOutput::Icon(output) => Ok((tmpdir, cfg, output.links)),
+        _ => bail!("unexpected output of the icon pipeline"),
+    }
+}
+
+#[tokio::test]
+async fn ok_copies_icon() -> Result<()> {
+    // Action.
+    let (_tmpdir, cfg, links) = run_icon(&[]).await?;
+
+    // Assert.
+    ensure!(
+        links.len() == 1,
+        "expected a single link, got {}",
+        links.len()
+    );
+    ensure!(links[0].rel == "icon" && links[0].sizes.is_none());
+    ensure!(cfg.staging_dist.join(&links[0].file).is_file());
+    Ok(())
+}
+
+#[tokio::test]
+async fn ok_generates_favicons() -> Result<()> {
+    // Action.
+    let (_tmpdir, cfg, links) = run_icon(&["data-favicons"]).await?;
+
+    // Assert.
+    let sizes: Vec<_> = links
+        .iter()
+        .map(|link| (link.rel, link.sizes.as_deref().unwrap_or_default()))
+        .collect();
+    ensure!(
+        sizes
+            == [
+                ("icon", "16x16 32x32 48x48"),
+                ("icon", "16x16"),
+                ("icon", "32x32"),
+                ("apple-touch-icon", "180x180"),
+                ("icon", "192x192"),
+                ("icon", "512x512"),
+            ],
+        "unexpected favicon links {:?}",
+        sizes
+    );
+    ensure!(links[0].file.ends_with(".ico"));
+
+    let apple_touch_icon = image::open(cfg.staging_dist.join(&links[3].file))
+        .context("error opening generated apple touch icon")?;
+    ensure!(
+        (apple_touch_icon.width(), apple_touch_icon.height()) == (180, 180),
+        "non-square icons must be centered on a square canvas"
+    );
+    Ok(())
+}
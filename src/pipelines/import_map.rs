@@ -0,0 +1,60 @@
+//! Generation of the import map of the output HTML.
+
+use std::collections::BTreeMap;
+
+use anyhow::{Context, Result};
+use nipper::Document;
+use serde_json::{Map, Value};
+
+/// The selector of the import map of a document.
+const IMPORT_MAP_SELECTOR: &str = r#"script[type="importmap"]"#;
+/// The selector of the module scripts & preloads, which resolve their imports with the import map.
+const MODULE_SELECTOR: &str = r#"script[type="module"], link[rel="modulepreload"]"#;
+
+/// Add the given mappings of module specifiers to URLs to the import map of the given document.
+///
+/// An existing import map of the document is extended, with its own mappings taking precedence.
+/// Otherwise, an import map is inserted in front of the first module script or preload, as it must
+/// precede them, or else after the `<meta charset>` of the document's head, which must stay first.
+pub fn apply(doc: &mut Document, imports: &BTreeMap<String, String>) -> Result<()> {
+    let mut import_map = doc.select(IMPORT_MAP_SELECTOR).first();
+    let mut map = match import_map.nodes().first() {
+        Some(node) => serde_json::from_str(&node.text()).context("error parsing import map")?,
+        None => Value::Object(Map::new()),
+    };
+    let existing = map
+        .as_object_mut()
+        .context("the import map must be a JSON object")?
+        .entry("imports")
+        .or_insert_with(|| Value::Object(Map::new()))
+        .as_object_mut()
+        .context("the `imports` of the import map must be a JSON object")?;
+    for (specifier, url) in imports {
+        existing
+            .entry(specifier)
+            .or_insert_with(|| Value::String(url.clone()));
+    }
+
+    let html = format!(
+        r#"<script type="importmap">{}</script>"#,
+        serde_json::to_string(&map).context("error serializing import map")?
+    );
+    if import_map.length() > 0 {
+        import_map.replace_with_html(html);
+    } else {
+        // Insert the import map in front of the first module script or preload, or after the
+        // charset declaration.
+        let mut module = doc.select(MODULE_SELECTOR).first();
+        let mut charset = doc.select("html head > meta[charset]").first();
+        if let Some(node) = module.nodes().first() {
+            let module_html = node.html();
+            module.replace_with_html(format!("{}{}", html, module_html));
+        } else if let Some(node) = charset.nodes().first() {
+            let charset_html = node.html();
+            charset.replace_with_html(format!("{}{}", charset_html, html));
+        } else {
+            doc.select("html head").append_html(html);
+        }
+    }
+    Ok(())
+}
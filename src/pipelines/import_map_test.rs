@@ -0,0 +1,83 @@
+use std::collections::BTreeMap;
+
+use anyhow::Result;
+use nipper::Document;
+
+use crate::pipelines::import_map::*;
+
+fn imports() -> BTreeMap<String, String> {
+    BTreeMap::from([
+        ("app".to_owned(), "/app-1234.js".to_owned()),
+        (
+            "lit".to_owned(),
+            "https://cdn.example.com/lit.js".to_owned(),
+        ),
+    ])
+}
+
+#[test]
+fn apply_inserts_import_map_before_first_module() -> Result<()> {
+    // Assemble.
+    let mut doc = Document::from(
+        r#"<html><head><meta charset="utf-8"><title>app</title><link rel="modulepreload" href="/lit.js"><script type="module" src="/app-1234.js"></script></head><body></body></html>"#,
+    );
+
+    // Action.
+    apply(&mut doc, &imports())?;
+
+    // Assert.
+    assert_eq!(
+        doc.html().to_string(),
+        r#"<html><head><meta charset="utf-8"><title>app</title><script type="importmap">{"imports":{"app":"/app-1234.js","lit":"https://cdn.example.com/lit.js"}}</script><link rel="modulepreload" href="/lit.js"><script type="module" src="/app-1234.js"></script></head><body></body></html>"#
+    );
+    Ok(())
+}
+
+#[test]
+fn apply_inserts_import_map_after_charset() -> Result<()> {
+    let mut doc = Document::from(
+        r#"<html><head><meta charset="utf-8"><title>app</title></head><body><script src="/classic.js"></script></body></html>"#,
+    );
+
+    apply(&mut doc, &imports())?;
+
+    assert_eq!(
+        doc.html().to_string(),
+        r#"<html><head><meta charset="utf-8"><script type="importmap">{"imports":{"app":"/app-1234.js","lit":"https://cdn.example.com/lit.js"}}</script><title>app</title></head><body><script src="/classic.js"></script></body></html>"#
+    );
+    Ok(())
+}
+
+#[test]
+fn apply_extends_existing_import_map() -> Result<()> {
+    let mut doc = Document::from(
+        r#"<html><head><script type="importmap">{"imports":{"lit":"/vendor/lit.js"},"scopes":{}}</script></head><body></body></html>"#,
+    );
+
+    apply(&mut doc, &imports())?;
+
+    assert_eq!(
+        doc.html().to_string(),
+        r#"<html><head><script type="importmap">{"imports":{"app":"/app-1234.js","lit":"/vendor/lit.js"},"scopes":{}}</script></head><body></body></html>"#
+    );
+    Ok(())
+}
+
+#[test]
+fn apply_appends_import_map_to_empty_head() -> Result<()> {
+    let mut doc = Document::from("<html><head></head><body></body></html>");
+
+    apply(&mut doc, &imports())?;
+
+    assert_eq!(doc.select(r#"head script[type="importmap"]"#).length(), 1);
+    Ok(())
+}
+
+#[test]
+fn apply_rejects_invalid_import_map() {
+    let mut doc = Document::from(
+        r#"<html><head><script type="importmap">{"imports":[]}</script></head><body></body></html>"#,
+    );
+
+    assert!(apply(&mut doc, &imports()).is_err());
+}
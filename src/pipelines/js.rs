@@ -5,10 +5,16 @@ use std::sync::Arc;
 
 use anyhow::{Context, Result};
 use nipper::Document;
+use tokio::fs;
 use tokio::task::JoinHandle;
 
-use super::{AssetFile, Attrs, TrunkAssetPipelineOutput, ATTR_SRC};
+use super::esbuild::{self, Format};
+use super::{
+    AssetFile, AssetFileType, Attrs, TrunkAssetPipelineOutput, ATTR_BUNDLE, ATTR_IMPORT,
+    ATTR_PREFETCH, ATTR_PRELOAD, ATTR_SRC,
+};
 use crate::config::RtcBuild;
+use crate::hooks;
 
 /// A JS asset pipeline.
 pub struct Js {
@@ -20,6 +26,10 @@ pub struct Js {
     asset: AssetFile,
     /// The attributes to be placed on the output script tag.
     attrs: Attrs,
+    /// If the script should be bundled with its imports.
+    bundle: bool,
+    /// The module specifier to map the script with in the import map, if any.
+    import: Option<String>,
 }
 
 impl Js {
@@ -36,16 +46,27 @@ impl Js {
         let mut path = PathBuf::new();
         path.extend(src_attr.split('/'));
         let asset = AssetFile::new(&html_dir, path).await?;
-        // Remove src and data-trunk from attributes.
+        let bundle = cfg.bundle || attrs.contains_key(ATTR_BUNDLE);
+        let import = super::import_specifier(&attrs, &asset);
+        // Remove src, data-bundle, data-import, the resource hints and data-trunk from attributes.
         let attrs = attrs
             .into_iter()
-            .filter(|(x, _)| *x != "src" && !x.starts_with("data-trunk"))
+            .filter(|(x, _)| {
+                *x != "src"
+                    && *x != ATTR_BUNDLE
+                    && *x != ATTR_IMPORT
+                    && *x != ATTR_PRELOAD
+                    && *x != ATTR_PREFETCH
+                    && !x.starts_with("data-trunk")
+            })
             .collect();
         Ok(Self {
             id,
             cfg,
             asset,
             attrs,
+            bundle,
+            import,
         })
     }
 
@@ -59,28 +80,64 @@ impl Js {
     #[tracing::instrument(level = "trace", skip(self))]
     async fn run(self) -> Result<TrunkAssetPipelineOutput> {
         let rel_path = crate::common::strip_prefix(&self.asset.path);
-        tracing::info!(path = ?rel_path, "copying & hashing js");
+        let file_type = match self.attrs.get("type").map(String::as_str) {
+            Some("module") => AssetFileType::Mjs,
+            _ => AssetFileType::Js,
+        };
+        let (js, minify) = if self.bundle {
+            // Bundle the script into a temporary file, which is then hashed in place of the asset.
+            // esbuild minifies the bundle itself, if minification is enabled.
+            tracing::info!(path = ?rel_path, "bundling & hashing js");
+            let temp_file = format!(".esbuild-{}", self.asset.file_name.to_string_lossy());
+            let js = esbuild::build(
+                &self.cfg,
+                &self.asset.path,
+                &temp_file,
+                Format::of_script(file_type),
+                true,
+            )
+            .await?;
+            (js, false)
+        } else {
+            tracing::info!(path = ?rel_path, "copying & hashing js");
+            let js = fs::read(&self.asset.path)
+                .await
+                .with_context(|| format!("error reading file for copying {:?}", self.asset.path))?;
+            (js, self.cfg.minify)
+        };
+        let js = hooks::rewrite_with_pipeline_hooks(
+            &self.cfg,
+            "js",
+            &self.asset.path,
+            &self.asset.file_name.to_string_lossy(),
+            js,
+        )
+        .await?;
         let file = self
             .asset
-            .copy(&self.cfg.staging_dist, self.cfg.filehash)
+            .write(&self.cfg, js, self.cfg.filehash, minify, file_type)
             .await?;
-        tracing::info!(path = ?rel_path, "finished copying & hashing js");
+        tracing::info!(path = ?rel_path, "finished processing js");
         let attrs = Self::attrs_to_string(self.attrs);
         Ok(TrunkAssetPipelineOutput::Js(JsOutput {
             cfg: self.cfg.clone(),
             id: self.id,
             file,
             attrs,
+            import: self.import,
         }))
     }
 
     /// Convert attributes to a string, to be used in JsOutput.
+    ///
+    /// The attributes are sorted by name, for a deterministic output.
     fn attrs_to_string(attrs: Attrs) -> String {
-        attrs
+        let mut attrs = attrs
             .into_iter()
             .map(|(k, v)| format!("{k}=\"{v}\""))
-            .collect::<Vec<_>>()
-            .join(" ")
+            .collect::<Vec<_>>();
+        attrs.sort();
+        attrs.join(" ")
     }
 }
 
@@ -94,16 +151,20 @@ pub struct JsOutput {
     pub file: String,
     /// The attributes to be added to the script tag.
     pub attrs: String,
+    /// The module specifier to map the output file with in the import map, if any.
+    pub import: Option<String>,
 }
 
 impl JsOutput {
     pub async fn finalize(self, dom: &mut Document) -> Result<()> {
+        let integrity = super::integrity_attrs(&self.cfg, &self.file).await?;
         dom.select(&super::trunk_script_id_selector(self.id))
             .replace_with_html(format!(
-                r#"<script {attrs} src="{base}{file}"/>"#,
+                r#"<script {attrs} src="{base}{file}"{integrity}/>"#,
                 attrs = self.attrs,
                 base = &self.cfg.public_url,
-                file = self.file
+                file = self.file,
+                integrity = integrity,
             ));
         Ok(())
     }
@@ -0,0 +1,198 @@
+//! Web app manifest asset pipeline.
+
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+
+use anyhow::{Context, Result};
+use nipper::Document;
+use serde_json::Value;
+use tokio::task::JoinHandle;
+
+use super::{AssetFile, AssetFileType, Attrs, TrunkAssetPipelineOutput, ATTR_HREF};
+use crate::config::RtcBuild;
+
+/// The placeholder of the public URL in the string values of a manifest.
+const PUBLIC_URL_PLACEHOLDER: &str = "{public_url}";
+
+/// A web app manifest asset pipeline.
+pub struct Manifest {
+    /// The ID of this pipeline's source HTML element.
+    id: usize,
+    /// Runtime build config.
+    cfg: Arc<RtcBuild>,
+    /// The asset file being processed.
+    asset: AssetFile,
+    /// The dir of the source HTML file, from which root-relative image paths are resolved.
+    html_dir: Arc<PathBuf>,
+}
+
+impl Manifest {
+    pub const TYPE_MANIFEST: &'static str = "manifest";
+
+    pub async fn new(
+        cfg: Arc<RtcBuild>,
+        html_dir: Arc<PathBuf>,
+        attrs: Attrs,
+        id: usize,
+    ) -> Result<Self> {
+        // Build the path to the target asset.
+        let href_attr = attrs.get(ATTR_HREF).context(
+            r#"required attr `href` missing for <link data-trunk rel="manifest" .../> element"#,
+        )?;
+        let mut path = PathBuf::new();
+        path.extend(href_attr.split('/'));
+        let asset = AssetFile::new(&html_dir, path).await?;
+        Ok(Self {
+            id,
+            cfg,
+            asset,
+            html_dir,
+        })
+    }
+
+    /// Spawn the pipeline for this asset type.
+    #[tracing::instrument(level = "trace", skip(self))]
+    pub fn spawn(self) -> JoinHandle<Result<TrunkAssetPipelineOutput>> {
+        tokio::spawn(self.run())
+    }
+
+    /// Run this pipeline.
+    #[tracing::instrument(level = "trace", skip(self))]
+    async fn run(self) -> Result<TrunkAssetPipelineOutput> {
+        let rel_path = crate::common::strip_prefix(&self.asset.path);
+        tracing::info!(path = ?rel_path, "processing web app manifest");
+        let mut manifest: Value = serde_json::from_str(&self.asset.read_to_string().await?)
+            .with_context(|| format!("error parsing web app manifest {:?}", &self.asset.path))?;
+        self.process(&mut manifest).await?;
+
+        let bytes =
+            serde_json::to_vec_pretty(&manifest).context("error serializing web app manifest")?;
+        let file = self
+            .asset
+            .write(
+                &self.cfg,
+                bytes,
+                self.cfg.filehash,
+                false,
+                AssetFileType::Other,
+            )
+            .await?;
+        tracing::info!(path = ?rel_path, "finished processing web app manifest");
+        Ok(TrunkAssetPipelineOutput::Manifest(ManifestOutput {
+            cfg: self.cfg.clone(),
+            id: self.id,
+            file,
+        }))
+    }
+
+    /// Replace the public URL placeholders of the given manifest, default its `start_url` & `scope`
+    /// to the public URL, and copy the local images it references to the dist dir, rewriting their
+    /// URLs to the hashed files.
+    async fn process(&self, manifest: &mut Value) -> Result<()> {
+        replace_public_url(manifest, &self.cfg.public_url);
+        let manifest = manifest
+            .as_object_mut()
+            .context("the web app manifest must be a JSON object")?;
+        for key in ["start_url", "scope"] {
+            manifest
+                .entry(key)
+                .or_insert_with(|| Value::String(self.cfg.public_url.clone()));
+        }
+
+        let manifest_dir = self.asset.path.parent().unwrap_or_else(|| Path::new(""));
+        for key in ["icons", "screenshots"] {
+            if let Some(images) = manifest.get_mut(key) {
+                self.copy_images(manifest_dir, images).await?;
+            }
+        }
+        if let Some(Value::Array(shortcuts)) = manifest.get_mut("shortcuts") {
+            for shortcut in shortcuts {
+                if let Some(icons) = shortcut.get_mut("icons") {
+                    self.copy_images(manifest_dir, icons).await?;
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Copy the local files of the given manifest image resources to the dist dir, rewriting their
+    /// `src` to the URLs of the hashed files. Relative paths are resolved from the manifest's dir,
+    /// root-relative paths from the source HTML file's dir, and absolute URLs are kept as they are.
+    async fn copy_images(&self, manifest_dir: &Path, images: &mut Value) -> Result<()> {
+        let Value::Array(images) = images else {
+            return Ok(());
+        };
+        for image in images {
+            let Some(Value::String(src)) = image.get_mut("src") else {
+                continue;
+            };
+            if is_absolute_url(src) {
+                continue;
+            }
+            let dir = if src.starts_with('/') {
+                self.html_dir.as_path()
+            } else {
+                manifest_dir
+            };
+            let mut path = PathBuf::new();
+            path.extend(src.split('/').filter(|segment| !segment.is_empty()));
+            let image = AssetFile::new(dir, path).await.with_context(|| {
+                format!("error finding image {:?} of the web app manifest", src)
+            })?;
+            let file = image
+                .copy(&self.cfg, self.cfg.filehash, false, AssetFileType::Other)
+                .await?;
+            *src = format!("{}{}", self.cfg.public_url, file);
+        }
+        Ok(())
+    }
+}
+
+/// Replace the public URL placeholder in all string values of the given JSON value.
+fn replace_public_url(value: &mut Value, public_url: &str) {
+    match value {
+        Value::String(string) if string.contains(PUBLIC_URL_PLACEHOLDER) => {
+            // The public URL ends with a slash, which is not duplicated.
+            *string = string
+                .replace(&format!("{}/", PUBLIC_URL_PLACEHOLDER), public_url)
+                .replace(PUBLIC_URL_PLACEHOLDER, public_url);
+        }
+        Value::Array(values) => values
+            .iter_mut()
+            .for_each(|value| replace_public_url(value, public_url)),
+        Value::Object(values) => values
+            .values_mut()
+            .for_each(|value| replace_public_url(value, public_url)),
+        _ => (),
+    }
+}
+
+/// Check if the given URL is absolute, i.e. has a scheme (including `data:` URLs) or a host.
+fn is_absolute_url(url: &str) -> bool {
+    url.starts_with("//")
+        || url
+            .split_once(':')
+            .is_some_and(|(scheme, _)| scheme.chars().all(|c| c.is_ascii_alphanumeric()))
+}
+
+/// The output of a web app manifest build pipeline.
+pub struct ManifestOutput {
+    /// The runtime build config.
+    pub cfg: Arc<RtcBuild>,
+    /// The ID of this pipeline.
+    pub id: usize,
+    /// Name of the finalized output file.
+    pub file: String,
+}
+
+impl ManifestOutput {
+    pub async fn finalize(self, dom: &mut Document) -> Result<()> {
+        dom.select(&super::trunk_id_selector(self.id))
+            .replace_with_html(format!(
+                r#"<link rel="manifest" href="{base}{file}"/>"#,
+                base = &self.cfg.public_url,
+                file = self.file
+            ));
+        Ok(())
+    }
+}
@@ -0,0 +1,94 @@
+use std::collections::HashMap;
+use std::sync::Arc;
+
+use anyhow::{bail, ensure, Context, Result};
+use serde_json::{json, Value};
+
+use crate::config::RtcBuild;
+use crate::pipelines::manifest::*;
+use crate::pipelines::{TrunkAssetPipelineOutput, ATTR_HREF};
+
+/// Run the manifest pipeline on the given manifest, with an icon at `icons/icon.png`, returning
+/// the output manifest & the staging dist dir.
+async fn run_manifest(manifest: Value) -> Result<(tempfile::TempDir, Arc<RtcBuild>, Value)> {
+    let tmpdir = tempfile::tempdir().context("error building tempdir for test")?;
+    let mut cfg = RtcBuild::new_test(tmpdir.path()).await?;
+    cfg.public_url = "/app/".into();
+    let cfg = Arc::new(cfg);
+    tokio::fs::create_dir_all(&cfg.staging_dist)
+        .await
+        .context("error creating staging dist dir")?;
+    tokio::fs::create_dir_all(tmpdir.path().join("icons"))
+        .await
+        .context("error creating icons dir")?;
+    tokio::fs::write(tmpdir.path().join("icons/icon.png"), b"png")
+        .await
+        .context("error writing test icon")?;
+    tokio::fs::write(tmpdir.path().join("manifest.json"), manifest.to_string())
+        .await
+        .context("error writing test manifest")?;
+
+    let mut attrs = HashMap::new();
+    attrs.insert(ATTR_HREF.into(), "manifest.json".into());
+    let html_dir = Arc::new(tmpdir.path().to_owned());
+    let manifest = Manifest::new(cfg.clone(), html_dir, attrs, 0).await?;
+    let TrunkAssetPipelineOutput::Manifest(output) = manifest.spawn().await?? else {
+        bail!("unexpected output of the manifest pipeline");
+    };
+    let output = tokio::fs::read_to_string(cfg.staging_dist.join(&output.file))
+        .await
+        .context("error reading output manifest")?;
+    let output = serde_json::from_str(&output).context("error parsing output manifest")?;
+    Ok((tmpdir, cfg, output))
+}
+
+#[tokio::test]
+async fn ok_rewrites_icons_to_hashed_files() -> Result<()> {
+    // Action.
+    let (_tmpdir, cfg, output) = run_manifest(json!({
+        "icons": [
+            { "src": "icons/icon.png", "sizes": "48x48" },
+            { "src": "/icons/icon.png", "sizes": "48x48" },
+            { "src": "https://example.com/icon.png", "sizes": "96x96" },
+        ],
+    }))
+    .await?;
+
+    // Assert.
+    let Some(src) = output["icons"][0]["src"].as_str() else {
+        bail!("missing icon src in {}", output);
+    };
+    ensure!(
+        src.starts_with("/app/icon-") && src.ends_with(".png"),
+        "unexpected icon src {:?}",
+        src
+    );
+    ensure!(cfg.staging_dist.join(&src["/app/".len()..]).is_file());
+    ensure!(output["icons"][1]["src"] == src);
+    ensure!(output["icons"][2]["src"] == "https://example.com/icon.png");
+    Ok(())
+}
+
+#[tokio::test]
+async fn ok_applies_public_url() -> Result<()> {
+    // Action.
+    let (_tmpdir, _cfg, output) = run_manifest(json!({
+        "scope": "{public_url}",
+        "shortcuts": [{ "url": "{public_url}/settings" }],
+    }))
+    .await?;
+
+    // Assert.
+    ensure!(
+        output["start_url"] == "/app/",
+        "unexpected output {}",
+        output
+    );
+    ensure!(output["scope"] == "/app/", "unexpected output {}", output);
+    ensure!(
+        output["shortcuts"][0]["url"] == "/app/settings",
+        "unexpected output {}",
+        output
+    );
+    Ok(())
+}
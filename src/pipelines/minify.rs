@@ -0,0 +1,83 @@
+//! Minification of HTML, JS & CSS outputs.
+
+use std::path::{Path, PathBuf};
+
+use anyhow::{Context, Result};
+use lightningcss::stylesheet::{MinifyOptions, ParserOptions, PrinterOptions, StyleSheet};
+use minify_js::{Session, TopLevelMode};
+
+/// Minify the given HTML, including its inline scripts & styles.
+pub fn minify_html(html: &[u8]) -> Vec<u8> {
+    let mut cfg = minify_html::Cfg::spec_compliant();
+    cfg.keep_closing_tags = true;
+    cfg.keep_html_and_head_opening_tags = true;
+    cfg.minify_css = true;
+    cfg.minify_js = true;
+    minify_html::minify(html, &cfg)
+}
+
+/// Minify the given JS, returning it as is if it fails to parse.
+pub fn minify_js(js: Vec<u8>, mode: TopLevelMode) -> Vec<u8> {
+    let session = Session::new();
+    let mut output = Vec::new();
+    match minify_js::minify(&session, mode, &js, &mut output) {
+        Ok(()) => output,
+        Err(err) => {
+            tracing::warn!("failed to minify JS, keeping it as is: {:?}", err);
+            js
+        }
+    }
+}
+
+/// Minify the given CSS, returning it as is if it fails to parse.
+pub fn minify_css(css: Vec<u8>) -> Vec<u8> {
+    let minified = std::str::from_utf8(&css)
+        .map_err(|err| err.to_string())
+        .and_then(|css| {
+            let mut sheet =
+                StyleSheet::parse(css, ParserOptions::default()).map_err(|err| err.to_string())?;
+            sheet
+                .minify(MinifyOptions::default())
+                .map_err(|err| err.to_string())?;
+            sheet
+                .to_css(PrinterOptions {
+                    minify: true,
+                    ..Default::default()
+                })
+                .map(|res| res.code)
+                .map_err(|err| err.to_string())
+        });
+    match minified {
+        Ok(minified) => minified.into_bytes(),
+        Err(err) => {
+            tracing::warn!("failed to minify CSS, keeping it as is: {}", err);
+            css
+        }
+    }
+}
+
+/// Minify all JS modules of the given dir & its subdirs in place.
+pub async fn minify_js_dir(dir: PathBuf) -> Result<()> {
+    fn minify_dir(dir: &Path) -> Result<()> {
+        let entries =
+            std::fs::read_dir(dir).with_context(|| format!("error reading dir {:?}", dir))?;
+        for entry in entries {
+            let path = entry
+                .with_context(|| format!("error reading dir {:?}", dir))?
+                .path();
+            if path.is_dir() {
+                minify_dir(&path)?;
+            } else if path.extension().is_some_and(|ext| ext == "js") {
+                let js = std::fs::read(&path)
+                    .with_context(|| format!("error reading JS file {:?}", path))?;
+                std::fs::write(&path, minify_js(js, TopLevelMode::Module))
+                    .with_context(|| format!("error writing minified JS file {:?}", path))?;
+            }
+        }
+        Ok(())
+    }
+
+    tokio::task::spawn_blocking(move || minify_dir(&dir))
+        .await
+        .context("error awaiting spawned JS minification")?
+}
@@ -0,0 +1,21 @@
+use minify_js::TopLevelMode;
+
+use crate::pipelines::minify::*;
+
+#[test]
+fn minify_css_removes_whitespace() {
+    let css = minify_css(b"body {\n  color: #ff0000;\n}\n".to_vec());
+    assert_eq!(css, b"body{color:red}");
+}
+
+#[test]
+fn minify_js_keeps_invalid_js() {
+    let js = b"function (".to_vec();
+    assert_eq!(minify_js(js.clone(), TopLevelMode::Global), js);
+}
+
+#[test]
+fn minify_html_minifies_inline_scripts() {
+    let html = minify_html(b"<html>\n<head>\n<script>\n  init( 1 );\n</script>\n</head>\n</html>");
+    assert_eq!(html, b"<html><head><script>init(1)</script></head></html>");
+}
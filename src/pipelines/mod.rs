@@ -1,17 +1,71 @@
+mod cache;
+#[cfg(test)]
+mod cache_test;
 mod copy_dir;
 #[cfg(test)]
 mod copy_dir_test;
 mod copy_file;
 #[cfg(test)]
 mod copy_file_test;
+mod csp;
+#[cfg(test)]
+mod csp_test;
 mod css;
+mod env;
+#[cfg(test)]
+mod env_test;
+mod esbuild;
+#[cfg(test)]
+mod esbuild_test;
+mod font;
+#[cfg(test)]
+mod font_test;
 mod html;
+#[cfg(test)]
+mod html_test;
+mod i18n;
+#[cfg(test)]
+mod i18n_test;
 mod icon;
+#[cfg(test)]
+mod icon_test;
+mod import_map;
+#[cfg(test)]
+mod import_map_test;
 mod inline;
 mod js;
+mod manifest;
+#[cfg(test)]
+mod manifest_test;
+mod minify;
+#[cfg(test)]
+mod minify_test;
+mod output_hashes;
+mod partials;
+#[cfg(test)]
+mod partials_test;
+mod postcss;
+#[cfg(test)]
+mod postcss_test;
+mod resource_hints;
+#[cfg(test)]
+mod resource_hints_test;
 mod rust;
+mod rust_licenses;
+#[cfg(test)]
+mod rust_licenses_test;
+#[cfg(test)]
+mod rust_test;
 mod sass;
+#[cfg(test)]
+mod sass_test;
+mod service_worker;
+#[cfg(test)]
+mod service_worker_test;
 mod tailwind_css;
+#[cfg(test)]
+mod tailwind_css_test;
+mod typescript;
 
 use std::collections::HashMap;
 use std::ffi::OsString;
@@ -19,9 +73,20 @@ use std::path::{Path, PathBuf};
 use std::sync::Arc;
 
 use anyhow::{bail, ensure, Context, Result};
+use base64::engine::general_purpose::STANDARD;
+use base64::Engine;
+pub use cache::PipelineCache;
+pub use font::FontCharacters;
 pub use html::HtmlPipeline;
+pub use i18n::negotiate_locale;
+use minify_js::TopLevelMode;
 use nipper::Document;
-use serde::Deserialize;
+pub use output_hashes::{AssetHashes, OutputHashes};
+pub use rust::{find_wasm_bindgen_version, RustAppOutputs, WasmOptLevel};
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+pub use service_worker::{write_service_worker, SERVICE_WORKER_FILE};
+use sha2::{Digest, Sha384};
 use tokio::fs;
 use tokio::sync::mpsc;
 use tokio::task::JoinHandle;
@@ -31,14 +96,25 @@ use crate::config::RtcBuild;
 use crate::pipelines::copy_dir::{CopyDir, CopyDirOutput};
 use crate::pipelines::copy_file::{CopyFile, CopyFileOutput};
 use crate::pipelines::css::{Css, CssOutput};
+use crate::pipelines::env::{Env, EnvOutput, EnvRef};
+use crate::pipelines::font::{Font, FontOutput};
 use crate::pipelines::icon::{Icon, IconOutput};
 use crate::pipelines::inline::{Inline, InlineOutput};
 use crate::pipelines::js::{Js, JsOutput};
+use crate::pipelines::manifest::{Manifest, ManifestOutput};
+use crate::pipelines::resource_hints::{HintedFile, ResourceHint};
 use crate::pipelines::rust::{RustApp, RustAppOutput};
+use crate::pipelines::rust_licenses::{RustLicenses, RustLicensesOutput};
 use crate::pipelines::sass::{Sass, SassOutput};
 use crate::pipelines::tailwind_css::{TailwindCss, TailwindCssOutput};
+use crate::pipelines::typescript::{TypeScript, TypeScriptOutput};
 
+const ATTR_BUNDLE: &str = "data-bundle";
+const ATTR_IMPORT: &str = "data-import";
 const ATTR_INLINE: &str = "data-inline";
+const ATTR_POSTCSS: &str = "data-postcss";
+const ATTR_PRELOAD: &str = "data-preload";
+const ATTR_PREFETCH: &str = "data-prefetch";
 const ATTR_HREF: &str = "href";
 const ATTR_SRC: &str = "src";
 const ATTR_TYPE: &str = "type";
@@ -55,6 +131,30 @@ pub enum TrunkAssetReference {
     Script(Attrs),
 }
 
+impl TrunkAssetReference {
+    /// A label of the asset for humans, e.g. `css style.css`.
+    pub fn label(&self) -> String {
+        let (kind, path) = match self {
+            Self::Link(attrs) => (
+                attrs.get(ATTR_REL).map_or("link", String::as_str),
+                attrs.get(ATTR_HREF),
+            ),
+            Self::Script(attrs) => ("script", attrs.get(ATTR_SRC)),
+        };
+        match path {
+            Some(path) => format!("{} {}", kind, path),
+            None => kind.to_owned(),
+        }
+    }
+
+    /// The resource hint requested for the output files of the asset, if any.
+    pub fn resource_hint(&self) -> Option<ResourceHint> {
+        match self {
+            Self::Link(attrs) | Self::Script(attrs) => ResourceHint::from_attrs(attrs),
+        }
+    }
+}
+
 /// A model of all of the supported Trunk asset links expressed in the source HTML as
 /// `<trunk-link/>` elements.
 ///
@@ -66,12 +166,17 @@ pub enum TrunkAsset {
     Css(Css),
     Sass(Sass),
     TailwindCss(TailwindCss),
+    TypeScript(TypeScript),
     Js(Js),
     Icon(Icon),
+    Font(Font),
+    Env(Env),
+    Manifest(Manifest),
     Inline(Inline),
     CopyFile(CopyFile),
     CopyDir(CopyDir),
     RustApp(RustApp),
+    RustLicenses(RustLicenses),
 }
 
 impl TrunkAsset {
@@ -94,6 +199,11 @@ impl TrunkAsset {
                         Self::Sass(Sass::new(cfg, html_dir, attrs, id).await?)
                     }
                     Icon::TYPE_ICON => Self::Icon(Icon::new(cfg, html_dir, attrs, id).await?),
+                    Font::TYPE_FONT => Self::Font(Font::new(cfg, html_dir, attrs, id).await?),
+                    Env::TYPE_ENV => Self::Env(Env::new(cfg, attrs, id).await?),
+                    Manifest::TYPE_MANIFEST => {
+                        Self::Manifest(Manifest::new(cfg, html_dir, attrs, id).await?)
+                    }
                     Inline::TYPE_INLINE => Self::Inline(Inline::new(html_dir, attrs, id).await?),
                     Css::TYPE_CSS => Self::Css(Css::new(cfg, html_dir, attrs, id).await?),
                     CopyFile::TYPE_COPY_FILE => {
@@ -102,12 +212,18 @@ impl TrunkAsset {
                     CopyDir::TYPE_COPY_DIR => {
                         Self::CopyDir(CopyDir::new(cfg, html_dir, attrs, id).await?)
                     }
-                    RustApp::TYPE_RUST_APP => {
+                    RustApp::TYPE_RUST_APP | RustApp::TYPE_RUST_WORKER => {
                         Self::RustApp(RustApp::new(cfg, html_dir, ignore_chan, attrs, id).await?)
                     }
+                    RustLicenses::TYPE_RUST_LICENSES => {
+                        Self::RustLicenses(RustLicenses::new(cfg, html_dir, attrs, id).await?)
+                    }
                     TailwindCss::TYPE_TAILWIND_CSS => {
                         Self::TailwindCss(TailwindCss::new(cfg, html_dir, attrs, id).await?)
                     }
+                    TypeScript::TYPE_TYPESCRIPT => {
+                        Self::TypeScript(TypeScript::new(cfg, html_dir, attrs, id).await?)
+                    }
                     _ => bail!(
                         r#"unknown <link data-trunk .../> attr value `rel="{}"`; please ensure the value is lowercase and is a supported asset type"#,
                         rel
@@ -126,12 +242,17 @@ impl TrunkAsset {
             Self::Css(inner) => inner.spawn(),
             Self::Sass(inner) => inner.spawn(),
             Self::TailwindCss(inner) => inner.spawn(),
+            Self::TypeScript(inner) => inner.spawn(),
             Self::Js(inner) => inner.spawn(),
             Self::Icon(inner) => inner.spawn(),
+            Self::Font(inner) => inner.spawn(),
+            Self::Env(inner) => inner.spawn(),
+            Self::Manifest(inner) => inner.spawn(),
             Self::Inline(inner) => inner.spawn(),
             Self::CopyFile(inner) => inner.spawn(),
             Self::CopyDir(inner) => inner.spawn(),
             Self::RustApp(inner) => inner.spawn(),
+            Self::RustLicenses(inner) => inner.spawn(),
         }
     }
 }
@@ -141,26 +262,88 @@ pub enum TrunkAssetPipelineOutput {
     Css(CssOutput),
     Sass(SassOutput),
     TailwindCss(TailwindCssOutput),
+    TypeScript(TypeScriptOutput),
     Js(JsOutput),
     Icon(IconOutput),
+    Font(FontOutput),
+    Env(EnvOutput),
+    Manifest(ManifestOutput),
     Inline(InlineOutput),
     CopyFile(CopyFileOutput),
     CopyDir(CopyDirOutput),
     RustApp(RustAppOutput),
+    RustLicenses(RustLicensesOutput),
 }
 
 impl TrunkAssetPipelineOutput {
+    /// The module specifier & URL this output is to be mapped with in the import map, if any.
+    pub fn import(&self) -> Option<(String, String)> {
+        let (cfg, specifier, file) = match self {
+            TrunkAssetPipelineOutput::Js(out) => (&out.cfg, out.import.as_ref()?, &out.file),
+            TrunkAssetPipelineOutput::TypeScript(out) => {
+                (&out.cfg, out.import.as_ref()?, &out.file)
+            }
+            TrunkAssetPipelineOutput::Env(EnvOutput {
+                cfg,
+                env: EnvRef::Module(file),
+                import: Some(import),
+                ..
+            }) => (cfg, import, file),
+            _ => return None,
+        };
+        Some((specifier.clone(), format!("{}{}", cfg.public_url, file)))
+    }
+
+    /// The output files of this output which can be hinted with `data-preload` or
+    /// `data-prefetch`.
+    ///
+    /// The outputs of Rust apps & fonts are always preloaded, and those of inlined assets & dirs
+    /// can not be hinted.
+    pub fn hinted_files(&self) -> Vec<HintedFile<'_>> {
+        fn file(file: &str, module: bool) -> HintedFile<'_> {
+            HintedFile { file, module }
+        }
+        match self {
+            TrunkAssetPipelineOutput::Css(out) => vec![file(&out.file, false)],
+            TrunkAssetPipelineOutput::Sass(SassOutput {
+                css_ref: sass::CssRef::File(css),
+                ..
+            }) => vec![file(css, false)],
+            TrunkAssetPipelineOutput::TailwindCss(TailwindCssOutput {
+                css_ref: tailwind_css::CssRef::File(css),
+                ..
+            }) => vec![file(css, false)],
+            TrunkAssetPipelineOutput::TypeScript(out) => vec![file(&out.file, true)],
+            TrunkAssetPipelineOutput::Js(out) => {
+                vec![file(&out.file, out.attrs.contains(r#"type="module""#))]
+            }
+            TrunkAssetPipelineOutput::Icon(out) => out
+                .links
+                .iter()
+                .map(|link| file(&link.file, false))
+                .collect(),
+            TrunkAssetPipelineOutput::Manifest(out) => vec![file(&out.file, false)],
+            TrunkAssetPipelineOutput::CopyFile(out) => vec![file(&out.file, false)],
+            _ => vec![],
+        }
+    }
+
     pub async fn finalize(self, dom: &mut Document) -> Result<()> {
         match self {
             TrunkAssetPipelineOutput::Css(out) => out.finalize(dom).await,
             TrunkAssetPipelineOutput::Sass(out) => out.finalize(dom).await,
             TrunkAssetPipelineOutput::TailwindCss(out) => out.finalize(dom).await,
+            TrunkAssetPipelineOutput::TypeScript(out) => out.finalize(dom).await,
             TrunkAssetPipelineOutput::Js(out) => out.finalize(dom).await,
             TrunkAssetPipelineOutput::Icon(out) => out.finalize(dom).await,
+            TrunkAssetPipelineOutput::Font(out) => out.finalize(dom).await,
+            TrunkAssetPipelineOutput::Env(out) => out.finalize(dom).await,
+            TrunkAssetPipelineOutput::Manifest(out) => out.finalize(dom).await,
             TrunkAssetPipelineOutput::Inline(out) => out.finalize(dom).await,
             TrunkAssetPipelineOutput::CopyFile(out) => out.finalize(dom).await,
             TrunkAssetPipelineOutput::CopyDir(out) => out.finalize(dom).await,
             TrunkAssetPipelineOutput::RustApp(out) => out.finalize(dom).await,
+            TrunkAssetPipelineOutput::RustLicenses(out) => out.finalize(dom).await,
         }
     }
 }
@@ -222,32 +405,66 @@ impl AssetFile {
         })
     }
 
-    /// Copy this asset to the target dir. If hashing is enabled, create a hash from the file
-    /// contents and include it as hex string in the destination file name.
+    /// Copy this asset to the staging dist dir. If minification is enabled, minify the file
+    /// contents according to the given file type. If hashing is enabled, create a hash from the
+    /// (minified) file contents and include it as hex string in the destination file name, laid
+    /// out according to the file hashing config.
     ///
-    /// The base file name (stripped path, without any parent folders) is returned if the operation
-    /// was successful.
-    pub async fn copy(&self, to_dir: &Path, with_hash: bool) -> Result<String> {
+    /// The file name relative to the staging dist dir (the base file name, unless hashed files are
+    /// laid out in dirs) is returned if the operation was successful.
+    pub async fn copy(
+        &self,
+        cfg: &RtcBuild,
+        with_hash: bool,
+        minify: bool,
+        file_type: AssetFileType,
+    ) -> Result<String> {
         let bytes = fs::read(&self.path)
             .await
             .with_context(|| format!("error reading file for copying {:?}", &self.path))?;
+        self.write(cfg, bytes, with_hash, minify, file_type).await
+    }
+
+    /// Write the given contents, e.g. the processed contents of this asset, to the staging dist
+    /// dir in place of this asset, just as [`AssetFile::copy`] does.
+    pub async fn write(
+        &self,
+        cfg: &RtcBuild,
+        mut bytes: Vec<u8>,
+        with_hash: bool,
+        minify: bool,
+        file_type: AssetFileType,
+    ) -> Result<String> {
+        if minify {
+            bytes = match file_type {
+                AssetFileType::Css => minify::minify_css(bytes),
+                AssetFileType::Js => minify::minify_js(bytes, TopLevelMode::Global),
+                AssetFileType::Mjs => minify::minify_js(bytes, TopLevelMode::Module),
+                AssetFileType::Other => bytes,
+            };
+        }
 
         let file_name = if with_hash {
             format!(
-                "{}-{:x}.{}",
-                &self.file_stem.to_string_lossy(),
-                seahash::hash(bytes.as_ref()),
+                "{}.{}",
+                cfg.hashed_name(&self.file_stem.to_string_lossy(), seahash::hash(&bytes)),
                 &self.ext.as_deref().unwrap_or_default()
             )
         } else {
             self.file_name.to_string_lossy().into_owned()
         };
 
-        let file_path = to_dir.join(&file_name);
+        let file_path = cfg.staging_path(&file_name);
+        if let Some(parent) = file_path.parent() {
+            fs::create_dir_all(parent)
+                .await
+                .with_context(|| format!("error creating dir {:?}", parent))?;
+        }
 
-        fs::write(&file_path, bytes)
+        fs::write(&file_path, &bytes)
             .await
             .with_context(|| format!("error copying file {:?} to {:?}", &self.path, &file_path))?;
+        cfg.output_hashes.record(&file_path, &bytes).await;
 
         Ok(file_name)
     }
@@ -260,11 +477,35 @@ impl AssetFile {
     }
 }
 
+/// Get the module specifier of the `data-import` attribute of a JS or TypeScript asset, which
+/// defaults to the asset's file stem if the attribute has no value.
+fn import_specifier(attrs: &Attrs, asset: &AssetFile) -> Option<String> {
+    attrs
+        .get(ATTR_IMPORT)
+        .map(|specifier| match specifier.trim() {
+            "" => asset.file_stem.to_string_lossy().into_owned(),
+            specifier => specifier.to_owned(),
+        })
+}
+
+/// The type of an asset file, which determines how it is minified.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum AssetFileType {
+    /// A CSS stylesheet.
+    Css,
+    /// A classic JS script.
+    Js,
+    /// A JS module.
+    Mjs,
+    /// Any other file, which is not minified.
+    Other,
+}
+
 /// A stage in the build process.
 ///
 /// This is used to specify when a hook will run.
 #[allow(clippy::enum_variant_names)]
-#[derive(Clone, Copy, Debug, Deserialize, PartialEq, Eq)]
+#[derive(Clone, Copy, Debug, Deserialize, Serialize, JsonSchema, PartialEq, Eq)]
 #[serde(rename_all = "snake_case")]
 pub enum PipelineStage {
     /// The stage before asset builds are executed.
@@ -273,14 +514,55 @@ pub enum PipelineStage {
     Build,
     /// The stage after asset builds are executed.
     PostBuild,
+    /// The stage after the pipeline of an asset, before its output is hashed.
+    PostPipeline,
+}
+
+impl PipelineStage {
+    /// The name of the stage, as in the config.
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Self::PreBuild => "pre_build",
+            Self::Build => "build",
+            Self::PostBuild => "post_build",
+            Self::PostPipeline => "post_pipeline",
+        }
+    }
+}
+
+/// Compute the subresource integrity hash of the given file of the staging dist dir, if
+/// subresource integrity is enabled.
+async fn integrity(cfg: &RtcBuild, file: &str) -> Result<Option<String>> {
+    if !cfg.sri {
+        return Ok(None);
+    }
+    let path = cfg.staging_path(file);
+    let bytes = fs::read(&path)
+        .await
+        .with_context(|| format!("error reading file {:?} for its integrity hash", path))?;
+    Ok(Some(integrity_hash(&bytes)))
+}
+
+/// Compute the subresource integrity hash of the given contents.
+pub fn integrity_hash(bytes: &[u8]) -> String {
+    format!("sha384-{}", STANDARD.encode(Sha384::digest(bytes)))
+}
+
+/// Create the `integrity` & `crossorigin` attributes of a tag referencing the given file of the
+/// staging dist dir, which are empty if subresource integrity is disabled.
+async fn integrity_attrs(cfg: &RtcBuild, file: &str) -> Result<String> {
+    Ok(match integrity(cfg, file).await? {
+        Some(hash) => format!(r#" integrity="{}" crossorigin="anonymous""#, hash),
+        None => String::new(),
+    })
 }
 
 /// Create the CSS selector for selecting a trunk link by ID.
-pub(self) fn trunk_id_selector(id: usize) -> String {
+fn trunk_id_selector(id: usize) -> String {
     format!(r#"link[{}="{}"]"#, TRUNK_ID, id)
 }
 
 /// Create the CSS selector for selecting a trunk script by ID.
-pub(self) fn trunk_script_id_selector(id: usize) -> String {
+fn trunk_script_id_selector(id: usize) -> String {
     format!(r#"script[{}="{}"]"#, TRUNK_ID, id)
 }
@@ -0,0 +1,131 @@
+//! The content hashes of the outputs which the pipelines of the current build wrote to the
+//! staging dist dir, which spare the build system reading the outputs again to hash them, and
+//! the content hashes of the assets of the last successful build.
+
+use std::collections::HashMap;
+use std::fs::Metadata;
+use std::path::{Path, PathBuf};
+use std::sync::{Mutex, PoisonError};
+use std::time::SystemTime;
+
+use anyhow::{Context, Result};
+use tokio::fs;
+
+use crate::common::dir_files;
+
+/// The content hashes of the outputs written by the current build, keyed by their path.
+///
+/// As hooks may rewrite outputs after their pipeline, a hash is only reused while the file has
+/// the size & modification time it had when the hash was recorded.
+#[derive(Debug, Default)]
+pub struct OutputHashes(Mutex<HashMap<PathBuf, RecordedHash>>);
+
+/// The content hash of an output, with the metadata of the file it was recorded for.
+#[derive(Clone, Copy, Debug)]
+struct RecordedHash {
+    hash: u64,
+    len: u64,
+    modified: SystemTime,
+}
+
+impl OutputHashes {
+    /// Forget the hashes of the previous build.
+    pub fn clear(&self) {
+        self.0
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .clear();
+    }
+
+    /// Record the hash of the given contents, which were just written to the given file.
+    ///
+    /// Nothing is recorded if the metadata of the file can not be read, in which case the file is
+    /// read to hash it instead.
+    pub async fn record(&self, path: &Path, contents: &[u8]) {
+        let Some(modified) = fs::metadata(path)
+            .await
+            .ok()
+            .filter(|meta| meta.len() == contents.len() as u64)
+            .and_then(|meta| meta.modified().ok())
+        else {
+            return;
+        };
+        self.0
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .insert(
+                path.to_owned(),
+                RecordedHash {
+                    hash: seahash::hash(contents),
+                    len: contents.len() as u64,
+                    modified,
+                },
+            );
+    }
+
+    /// The recorded hash of the given file, if the file is unchanged since the hash was recorded
+    /// according to its given metadata.
+    pub fn get(&self, path: &Path, meta: &Metadata) -> Option<u64> {
+        let recorded = *self
+            .0
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .get(path)?;
+        let modified = meta.modified().ok()?;
+        (recorded.len == meta.len() && recorded.modified == modified).then_some(recorded.hash)
+    }
+
+    /// Hash the contents of all files of the given dir, keyed by their path relative to it.
+    ///
+    /// If `reuse` is set, the recorded hashes are reused for the files which are unchanged since,
+    /// so that only the other files are read to hash them.
+    pub async fn hash_dir(&self, dir: &Path, reuse: bool) -> Result<HashMap<String, u64>> {
+        let mut hashes = HashMap::new();
+        for path in dir_files(dir)
+            .await
+            .with_context(|| format!("error reading dir {:?}", dir))?
+        {
+            let meta = fs::metadata(&path)
+                .await
+                .with_context(|| format!("error reading metadata of {:?}", path))?;
+            let recorded = reuse.then(|| self.get(&path, &meta)).flatten();
+            let hash = match recorded {
+                Some(hash) => hash,
+                None => seahash::hash(
+                    &fs::read(&path)
+                        .await
+                        .with_context(|| format!("error reading {:?}", path))?,
+                ),
+            };
+            let rel_path = path
+                .strip_prefix(dir)
+                .context("error stripping dir prefix")?
+                .components()
+                .filter_map(|segment| segment.as_os_str().to_str())
+                .collect::<Vec<_>>()
+                .join("/");
+            hashes.insert(rel_path, hash);
+        }
+        Ok(hashes)
+    }
+}
+
+/// The content hashes of all assets of the last successful build, keyed by their path relative
+/// to the dist dir, which are empty before the first one.
+#[derive(Debug, Default)]
+pub struct AssetHashes(Mutex<HashMap<String, u64>>);
+
+impl AssetHashes {
+    /// The hashes of the last successful build.
+    pub fn get(&self) -> HashMap<String, u64> {
+        self.0
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .clone()
+    }
+
+    /// Set the hashes of the build which just succeeded.
+    pub fn set(&self, hashes: HashMap<String, u64>) {
+        *self.0.lock().unwrap_or_else(PoisonError::into_inner) = hashes;
+    }
+}
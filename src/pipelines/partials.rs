@@ -0,0 +1,57 @@
+//! Inclusion of partial HTML files into the source HTML.
+
+use std::path::{Path, PathBuf};
+
+use anyhow::{bail, Context, Result};
+use nipper::{Document, Selection};
+use tokio::fs;
+
+use super::{env, ATTR_HREF};
+use crate::config::RtcBuild;
+
+/// The selector of the links including partials.
+const INCLUDE_SELECTOR: &str = r#"link[data-trunk][rel="include"]"#;
+/// The maximum depth of nested includes, beyond which the includes are assumed to be cyclic.
+const MAX_DEPTH: usize = 32;
+
+/// Replace the `<link data-trunk rel="include" href="...">` elements of the given document with
+/// the contents of the partial HTML files, including nested partials.
+///
+/// The paths of all partials are relative to the dir of the source HTML file, just as the paths of
+/// the assets they contain, which are processed as part of the source HTML.
+pub async fn include(doc: &mut Document, html_dir: &Path, cfg: &RtcBuild) -> Result<()> {
+    for _ in 0..MAX_DEPTH {
+        let includes = doc.select(INCLUDE_SELECTOR);
+        if includes.length() == 0 {
+            return Ok(());
+        }
+        let hrefs = includes
+            .nodes()
+            .iter()
+            .map(|node| node.attr(ATTR_HREF).map(|href| href.to_string()))
+            .collect::<Vec<_>>();
+
+        let mut partials = Vec::with_capacity(hrefs.len());
+        for href in hrefs {
+            let href = href.context(
+                r#"required attr `href` missing for <link data-trunk rel="include" .../> element"#,
+            )?;
+            let mut path = PathBuf::new();
+            path.extend(href.split('/'));
+            let path = html_dir.join(path);
+            let partial = fs::read_to_string(&path)
+                .await
+                .with_context(|| format!("error reading partial {:?}", path))?;
+            partials.push(env::substitute(&partial, cfg)?);
+        }
+
+        for (node, partial) in includes.nodes().iter().zip(partials) {
+            let mut include = Selection::from(node.clone());
+            include.replace_with_html(partial);
+        }
+    }
+    bail!(
+        "partials are nested more than {} levels deep, do they include each other?",
+        MAX_DEPTH
+    )
+}
@@ -0,0 +1,64 @@
+use anyhow::{Context, Result};
+use nipper::Document;
+
+use crate::config::RtcBuild;
+use crate::pipelines::partials::*;
+
+#[tokio::test]
+async fn include_nested_partials() -> Result<()> {
+    // Assemble.
+    let tmpdir = tempfile::tempdir().context("error building tempdir for test")?;
+    let cfg = RtcBuild::new_test(tmpdir.path()).await?;
+    let partials = tmpdir.path().join("partials");
+    tokio::fs::create_dir(&partials)
+        .await
+        .context("error creating partials dir")?;
+    tokio::fs::write(
+        partials.join("head.html"),
+        r#"<meta charset="utf-8"><link data-trunk rel="include" href="partials/style.html">"#,
+    )
+    .await
+    .context("error writing partial")?;
+    tokio::fs::write(
+        partials.join("style.html"),
+        r#"<link data-trunk rel="css" href="style.css">"#,
+    )
+    .await
+    .context("error writing partial")?;
+    let mut doc = Document::from(
+        r#"<html><head><link data-trunk rel="include" href="partials/head.html"></head><body></body></html>"#,
+    );
+
+    // Action.
+    include(&mut doc, tmpdir.path(), &cfg).await?;
+
+    // Assert.
+    assert_eq!(
+        doc.html().to_string(),
+        r#"<html><head><meta charset="utf-8"><link data-trunk="" rel="css" href="style.css"></head><body></body></html>"#
+    );
+    Ok(())
+}
+
+#[tokio::test]
+async fn err_include_cyclic_partials() -> Result<()> {
+    // Assemble.
+    let tmpdir = tempfile::tempdir().context("error building tempdir for test")?;
+    let cfg = RtcBuild::new_test(tmpdir.path()).await?;
+    tokio::fs::write(
+        tmpdir.path().join("nav.html"),
+        r#"<nav><link data-trunk rel="include" href="nav.html"></nav>"#,
+    )
+    .await
+    .context("error writing partial")?;
+    let mut doc = Document::from(
+        r#"<html><body><link data-trunk rel="include" href="nav.html"></body></html>"#,
+    );
+
+    // Action.
+    let res = include(&mut doc, tmpdir.path(), &cfg).await;
+
+    // Assert.
+    anyhow::ensure!(res.is_err(), "unexpected success including cyclic partials");
+    Ok(())
+}
@@ -0,0 +1,68 @@
+//! PostCSS processing of the output of CSS pipelines.
+
+use std::path::{Path, PathBuf};
+
+use anyhow::Result;
+
+use crate::common::{self, path_exists};
+
+/// The file names of PostCSS configs, which are looked up in the dir of the source HTML file.
+const CONFIG_FILE_NAMES: &[&str] = &[
+    "postcss.config.js",
+    "postcss.config.cjs",
+    "postcss.config.mjs",
+    "postcss.config.ts",
+    ".postcssrc",
+    ".postcssrc.json",
+    ".postcssrc.yaml",
+    ".postcssrc.yml",
+    ".postcssrc.js",
+    ".postcssrc.cjs",
+];
+
+/// Process the given CSS file with PostCSS, writing the result to the given output file, which
+/// may be the input file itself.
+///
+/// The plugins of the PostCSS config in the given dir are used, or autoprefixer if there is none.
+/// The PostCSS CLI is taken from the `node_modules` of the given dir or one of its parents, or
+/// from the `PATH`.
+pub async fn process(dir: &Path, input: &Path, output: &Path) -> Result<()> {
+    let postcss = find_postcss(dir).await?;
+    let dir = dunce::simplified(dir).display().to_string();
+    let input = dunce::simplified(input).display().to_string();
+    let output = dunce::simplified(output).display().to_string();
+    let mut args = vec![input.as_str(), "--output", &output, "--no-map"];
+
+    let mut has_config = false;
+    for name in CONFIG_FILE_NAMES {
+        if path_exists(Path::new(&dir).join(name)).await? {
+            has_config = true;
+            break;
+        }
+    }
+    if has_config {
+        args.extend(["--config", &dir]);
+    } else {
+        args.extend(["--use", "autoprefixer"]);
+    }
+
+    tracing::info!(path = ?common::strip_prefix(Path::new(&input)), "processing css with postcss");
+    common::run_command_capture_stderr("postcss", &postcss, &args).await
+}
+
+/// Find the PostCSS CLI in the `node_modules` of the given dir or one of its parents, falling back
+/// to the `postcss` executable on the `PATH`.
+async fn find_postcss(dir: &Path) -> Result<PathBuf> {
+    let name = if cfg!(target_os = "windows") {
+        "postcss.cmd"
+    } else {
+        "postcss"
+    };
+    for dir in dir.ancestors() {
+        let path = dir.join("node_modules").join(".bin").join(name);
+        if path_exists(&path).await? {
+            return Ok(path);
+        }
+    }
+    Ok(PathBuf::from(name))
+}
@@ -0,0 +1,109 @@
+#![cfg(unix)]
+
+use std::os::unix::fs::PermissionsExt;
+use std::path::Path;
+use std::sync::Arc;
+
+use anyhow::{ensure, Context, Result};
+
+use crate::config::RtcBuild;
+use crate::pipelines::postcss::*;
+use crate::pipelines::HtmlPipeline;
+
+/// Install a fake PostCSS CLI to the `node_modules` of the given dir, which prefixes `display:
+/// flex` & records its args in `args.txt`.
+fn install_postcss(dir: &Path) -> Result<()> {
+    let bin = dir.join("node_modules").join(".bin");
+    std::fs::create_dir_all(&bin)?;
+    let postcss = bin.join("postcss");
+    std::fs::write(
+        &postcss,
+        "#!/bin/sh\necho \"$@\" > \"$(dirname \"$0\")/args.txt\"\nsed 's/display: flex/display: \
+         -webkit-box; display: flex/' \"$1\" > \"$3\"\n",
+    )?;
+    std::fs::set_permissions(&postcss, std::fs::Permissions::from_mode(0o755))?;
+    Ok(())
+}
+
+fn args(dir: &Path) -> Result<String> {
+    std::fs::read_to_string(dir.join("node_modules").join(".bin").join("args.txt"))
+        .context("error reading the args of postcss")
+}
+
+#[tokio::test]
+async fn uses_autoprefixer_unless_configured() -> Result<()> {
+    // Assemble.
+    let tmpdir = tempfile::tempdir().context("error building tempdir for test")?;
+    let dir = tmpdir.path().canonicalize()?;
+    let nested = dir.join("crates").join("app");
+    std::fs::create_dir_all(&nested)?;
+    install_postcss(&dir)?;
+    let (input, output) = (nested.join("style.css"), nested.join("out.css"));
+    std::fs::write(&input, "a { display: flex }")?;
+
+    // Action.
+    process(&nested, &input, &output).await?;
+    let default_args = args(&dir)?;
+    std::fs::write(nested.join("postcss.config.js"), "module.exports = {};")?;
+    process(&nested, &input, &output).await?;
+    let config_args = args(&dir)?;
+
+    // Assert.
+    ensure!(std::fs::read_to_string(&output)? == "a { display: -webkit-box; display: flex }");
+    ensure!(
+        default_args.trim()
+            == format!(
+                "{} --output {} --no-map --use autoprefixer",
+                input.display(),
+                output.display()
+            ),
+        "unexpected args {:?}",
+        default_args
+    );
+    ensure!(
+        config_args
+            .trim()
+            .ends_with(&format!("--config {}", nested.display())),
+        "unexpected args {:?}",
+        config_args
+    );
+    Ok(())
+}
+
+#[tokio::test]
+async fn processes_css_assets_with_the_postcss_attr() -> Result<()> {
+    // Assemble.
+    let tmpdir = tempfile::tempdir().context("error building tempdir for test")?;
+    let mut cfg = RtcBuild::new_test(tmpdir.path()).await?;
+    cfg.filehash = false;
+    install_postcss(tmpdir.path())?;
+    std::fs::write(tmpdir.path().join("a.css"), "a { display: flex }")?;
+    std::fs::write(tmpdir.path().join("b.css"), "b { display: flex }")?;
+    std::fs::write(
+        &cfg.target,
+        r#"<html><head><link data-trunk rel="css" href="a.css" data-postcss><link data-trunk rel="css" href="b.css"></head></html>"#,
+    )?;
+    let staging_dist = cfg.staging_dist.clone();
+
+    // Action.
+    Arc::new(HtmlPipeline::new(Arc::new(cfg), None)?)
+        .spawn()
+        .await??;
+
+    // Assert.
+    ensure!(
+        std::fs::read_to_string(staging_dist.join("a.css"))?
+            == "a { display: -webkit-box; display: flex }"
+    );
+    ensure!(
+        std::fs::read_to_string(staging_dist.join("b.css"))? == "b { display: flex }",
+        "assets without the attr must not be processed"
+    );
+    ensure!(
+        !std::fs::read_dir(&staging_dist)?
+            .filter_map(Result::ok)
+            .any(|entry| entry.file_name().to_string_lossy().starts_with(".postcss")),
+        "the temporary output of postcss must be removed"
+    );
+    Ok(())
+}
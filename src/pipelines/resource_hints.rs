@@ -0,0 +1,116 @@
+//! Resource hints for the output files of assets.
+
+use std::path::Path;
+
+use anyhow::Result;
+
+use super::{Attrs, ATTR_PREFETCH, ATTR_PRELOAD};
+use crate::config::RtcBuild;
+
+/// A resource hint requested for the output files of an asset with `data-preload` or
+/// `data-prefetch`, which may give the destination of the files as its value.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum ResourceHint {
+    /// Fetch the files with a high priority, as they are needed by the current page.
+    Preload(Option<String>),
+    /// Fetch the files with a low priority, as they are likely needed by a future navigation.
+    Prefetch(Option<String>),
+}
+
+/// An output file of an asset, to be hinted.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct HintedFile<'a> {
+    /// The name of the file in the dist dir.
+    pub file: &'a str,
+    /// If the file is a JS module, which is fetched in CORS mode.
+    pub module: bool,
+}
+
+impl ResourceHint {
+    /// Get the resource hint requested by the given attrs of an asset, if any.
+    pub fn from_attrs(attrs: &Attrs) -> Option<Self> {
+        let destination = |value: &String| match value.trim() {
+            "" => None,
+            destination => Some(destination.to_owned()),
+        };
+        if let Some(value) = attrs.get(ATTR_PRELOAD) {
+            Some(Self::Preload(destination(value)))
+        } else {
+            attrs
+                .get(ATTR_PREFETCH)
+                .map(|value| Self::Prefetch(destination(value)))
+        }
+    }
+
+    /// Render the `<link>` elements hinting the given output files of an asset.
+    ///
+    /// The destination (`as`) of each file is inferred from its extension, unless it is given
+    /// explicitly. Preloaded JS modules use `modulepreload` instead.
+    pub async fn render(&self, cfg: &RtcBuild, files: &[HintedFile<'_>]) -> Result<String> {
+        let (rel, explicit) = match self {
+            Self::Preload(destination) => ("preload", destination.as_deref()),
+            Self::Prefetch(destination) => ("prefetch", destination.as_deref()),
+        };
+        let mut html = String::new();
+        for file in files {
+            // The hint must match the request of the asset, including its integrity and CORS
+            // mode, to be used for it.
+            let integrity = super::integrity_attrs(cfg, file.file).await?;
+            if file.module && rel == "preload" && explicit.is_none() {
+                html.push_str(&format!(
+                    r#"<link rel="modulepreload" href="{base}{file}"{integrity}/>"#,
+                    base = &cfg.public_url,
+                    file = file.file,
+                ));
+                continue;
+            }
+
+            let (destination, mime_type) = match explicit {
+                Some(destination) => (destination, None),
+                None if file.module => ("script", None),
+                None => infer_destination(file.file),
+            };
+            let mime_type = mime_type
+                .map(|mime_type| format!(r#" type="{}""#, mime_type))
+                .unwrap_or_default();
+            let crossorigin = if integrity.is_empty()
+                && (file.module || matches!(destination, "font" | "fetch"))
+            {
+                " crossorigin"
+            } else {
+                ""
+            };
+            html.push_str(&format!(
+                r#"<link rel="{rel}" href="{base}{file}" as="{destination}"{mime_type}{crossorigin}{integrity}/>"#,
+                base = &cfg.public_url,
+                file = file.file,
+            ));
+        }
+        Ok(html)
+    }
+}
+
+/// Infer the destination (`as`) of a hinted file, and its MIME type if it is needed to skip
+/// unsupported formats, from the extension of the file name.
+pub fn infer_destination(file: &str) -> (&'static str, Option<&'static str>) {
+    let extension = Path::new(file)
+        .extension()
+        .map(|extension| extension.to_string_lossy().to_ascii_lowercase())
+        .unwrap_or_default();
+    match extension.as_str() {
+        "css" => ("style", None),
+        "js" | "mjs" => ("script", None),
+        "woff2" => ("font", Some("font/woff2")),
+        "woff" => ("font", Some("font/woff")),
+        "ttf" => ("font", Some("font/ttf")),
+        "otf" => ("font", Some("font/otf")),
+        "avif" => ("image", Some("image/avif")),
+        "webp" => ("image", Some("image/webp")),
+        "png" | "jpg" | "jpeg" | "gif" | "svg" | "ico" => ("image", None),
+        "mp3" | "ogg" | "wav" | "flac" => ("audio", None),
+        "mp4" | "webm" => ("video", None),
+        "vtt" => ("track", None),
+        "wasm" => ("fetch", Some("application/wasm")),
+        _ => ("fetch", None),
+    }
+}
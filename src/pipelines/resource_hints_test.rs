@@ -0,0 +1,83 @@
+use anyhow::{Context, Result};
+
+use crate::config::RtcBuild;
+use crate::pipelines::resource_hints::*;
+use crate::pipelines::{Attrs, ATTR_PREFETCH, ATTR_PRELOAD};
+
+fn attrs(attrs: &[(&str, &str)]) -> Attrs {
+    attrs
+        .iter()
+        .map(|(name, value)| (name.to_string(), value.to_string()))
+        .collect()
+}
+
+#[test]
+fn from_attrs_reads_the_hint_and_explicit_destination() {
+    assert_eq!(ResourceHint::from_attrs(&attrs(&[("href", "a.css")])), None);
+    assert_eq!(
+        ResourceHint::from_attrs(&attrs(&[(ATTR_PRELOAD, "")])),
+        Some(ResourceHint::Preload(None))
+    );
+    assert_eq!(
+        ResourceHint::from_attrs(&attrs(&[(ATTR_PREFETCH, " image ")])),
+        Some(ResourceHint::Prefetch(Some("image".to_owned())))
+    );
+}
+
+#[test]
+fn infer_destination_from_extension() {
+    assert_eq!(infer_destination("style-1234.css"), ("style", None));
+    assert_eq!(infer_destination("app.JS"), ("script", None));
+    assert_eq!(
+        infer_destination("fonts/inter-1234.woff2"),
+        ("font", Some("font/woff2"))
+    );
+    assert_eq!(infer_destination("logo.svg"), ("image", None));
+    assert_eq!(
+        infer_destination("app_bg.wasm"),
+        ("fetch", Some("application/wasm"))
+    );
+    assert_eq!(infer_destination("data.json"), ("fetch", None));
+}
+
+#[tokio::test]
+async fn render_hints_with_destination_and_cors_mode() -> Result<()> {
+    // Assemble.
+    let tmpdir = tempfile::tempdir().context("error building tempdir for test")?;
+    let cfg = RtcBuild::new_test(tmpdir.path()).await?;
+    let files = [
+        HintedFile {
+            file: "style-1234.css",
+            module: false,
+        },
+        HintedFile {
+            file: "inter-1234.woff2",
+            module: false,
+        },
+        HintedFile {
+            file: "app-1234.js",
+            module: true,
+        },
+    ];
+
+    // Action.
+    let preload = ResourceHint::Preload(None).render(&cfg, &files).await?;
+    let prefetch = ResourceHint::Prefetch(Some("fetch".to_owned()))
+        .render(&cfg, &files[..1])
+        .await?;
+
+    // Assert.
+    assert_eq!(
+        preload,
+        concat!(
+            r#"<link rel="preload" href="/style-1234.css" as="style"/>"#,
+            r#"<link rel="preload" href="/inter-1234.woff2" as="font" type="font/woff2" crossorigin/>"#,
+            r#"<link rel="modulepreload" href="/app-1234.js"/>"#,
+        )
+    );
+    assert_eq!(
+        prefetch,
+        r#"<link rel="prefetch" href="/style-1234.css" as="fetch" crossorigin/>"#
+    );
+    Ok(())
+}
@@ -5,22 +5,61 @@ use std::iter::Iterator;
 use std::path::{Path, PathBuf};
 use std::process::Stdio;
 use std::str::FromStr;
-use std::sync::Arc;
+use std::sync::{Arc, PoisonError};
 
 use anyhow::{anyhow, bail, ensure, Context, Result};
 use cargo_lock::Lockfile;
+use minify_js::TopLevelMode;
 use nipper::Document;
+use once_cell::sync::Lazy;
 use tokio::fs;
 use tokio::io::AsyncWriteExt;
-use tokio::process::Command;
-use tokio::sync::mpsc;
+use tokio::sync::{mpsc, Mutex};
 use tokio::task::JoinHandle;
 
-use super::{Attrs, TrunkAssetPipelineOutput, ATTR_HREF, SNIPPETS_DIR};
+use super::cache::InputDigest;
+use super::minify::{minify_js, minify_js_dir};
+use super::{Attrs, TrunkAssetPipelineOutput, ATTR_HREF, ATTR_REL, SNIPPETS_DIR};
 use crate::common::{self, copy_dir_recursive, path_exists};
 use crate::config::{CargoMetadata, ConfigOptsTools, Features, RtcBuild};
 use crate::tools::{self, Application};
 
+/// The prefix of the attributes passing arbitrary flags to wasm-bindgen.
+const ATTR_BINDGEN_PREFIX: &str = "data-bindgen-";
+
+/// A lock held while building a Rust app with cargo & wasm-bindgen.
+static CARGO_BUILD_LOCK: Lazy<Mutex<()>> = Lazy::new(|| Mutex::new(()));
+
+/// The outputs of the Rust apps built by the current build, keyed by their build options, which the
+/// pages of a multi-page app share instead of building the same app again.
+#[derive(Debug, Default)]
+pub struct RustAppOutputs(std::sync::Mutex<HashMap<String, RustAppOutput>>);
+
+impl RustAppOutputs {
+    /// Forget the outputs of the previous build.
+    pub fn clear(&self) {
+        self.0
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .clear();
+    }
+
+    fn get(&self, key: &str) -> Option<RustAppOutput> {
+        self.0
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .get(key)
+            .cloned()
+    }
+
+    fn insert(&self, key: String, output: RustAppOutput) {
+        self.0
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .insert(key, output);
+    }
+}
+
 /// A Rust application pipeline.
 pub struct RustApp {
     /// The ID of this pipeline's source HTML element.
@@ -49,14 +88,18 @@ pub struct RustApp {
     reference_types: bool,
     /// An option to instruct wasm-bindgen to enable weak references.
     weak_refs: bool,
+    /// Additional arguments to pass to wasm-bindgen, from the config & the `data-bindgen-*` attrs.
+    bindgen_args: Vec<String>,
     /// An optional optimization setting that enables wasm-opt. Can be nothing, `0` (default), `1`,
     /// `2`, `3`, `4`, `s or `z`. Using `0` disables wasm-opt completely.
     wasm_opt: WasmOptLevel,
-    /// Name for the module. Is binary name if given, otherwise it is the name of the cargo
-    /// project.
+    /// Name for the module. Is the target name or the binary name if given, otherwise it is the
+    /// name of the cargo project.
     name: String,
     /// Whether to create a loader shim script
     loader_shim: bool,
+    /// Whether this worker is named with a hash, and spawned by a script injected into the HTML.
+    worker_glue: bool,
 }
 
 /// Describes how the rust application is used.
@@ -85,6 +128,7 @@ impl FromStr for RustAppType {
 
 impl RustApp {
     pub const TYPE_RUST_APP: &'static str = "rust";
+    pub const TYPE_RUST_WORKER: &'static str = "rust-worker";
 
     pub async fn new(
         cfg: Arc<RtcBuild>,
@@ -112,33 +156,53 @@ impl RustApp {
         let keep_debug = attrs.contains_key("data-keep-debug");
         let typescript = attrs.contains_key("data-typescript");
         let no_demangle = attrs.contains_key("data-no-demangle");
-        let app_type = attrs
-            .get("data-type")
-            .map(|s| s.as_str())
-            .unwrap_or("main")
-            .parse()?;
+        // Workers linked with `rel="rust-worker"` are hashed & spawned by an injected script.
+        let worker_glue =
+            attrs.get(ATTR_REL).map(|rel| rel.as_str()) == Some(Self::TYPE_RUST_WORKER);
+        let app_type = match worker_glue {
+            true => RustAppType::Worker,
+            false => attrs
+                .get("data-type")
+                .map(|s| s.as_str())
+                .unwrap_or("main")
+                .parse()?,
+        };
         let reference_types = attrs.contains_key("data-reference-types");
         let weak_refs = attrs.contains_key("data-weak-refs");
-        let wasm_opt = attrs
-            .get("data-wasm-opt")
-            .map(|val| val.parse())
-            .transpose()?
-            .unwrap_or_else(|| {
-                if cfg.release {
-                    Default::default()
+        // Arbitrary flags of wasm-bindgen, e.g. `data-bindgen-split-linked-modules` passes
+        // `--split-linked-modules`, and values are passed as `--flag=value`. They are sorted for a
+        // deterministic order.
+        let mut bindgen_attrs = attrs
+            .iter()
+            .filter_map(|(name, value)| Some((name.strip_prefix(ATTR_BINDGEN_PREFIX)?, value)))
+            .collect::<Vec<_>>();
+        bindgen_attrs.sort();
+        let bindgen_args = cfg
+            .bindgen_args
+            .iter()
+            .cloned()
+            .chain(bindgen_attrs.into_iter().map(|(flag, value)| {
+                if value.is_empty() {
+                    format!("--{}", flag)
                 } else {
-                    WasmOptLevel::Off
+                    format!("--{}={}", flag, value)
                 }
-            });
-        let manifest = CargoMetadata::new(&manifest_href).await?;
+            }))
+            .collect();
+        let wasm_opt = wasm_opt_level(attrs.get("data-wasm-opt").map(String::as_str), &cfg)?;
+        let manifest = CargoMetadata::new(&manifest_href, cfg.cargo_flags, &cfg.cargo_env).await?;
         let id = Some(id);
-        let name = bin.clone().unwrap_or_else(|| manifest.package.name.clone());
+        let name = attrs
+            .get("data-target-name")
+            .or(bin.as_ref())
+            .cloned()
+            .unwrap_or_else(|| manifest.package.name.clone());
 
         let data_features = attrs.get("data-cargo-features").map(|val| val.to_string());
-        let data_all_features = attrs.get("data-cargo-all-features").is_some();
-        let data_no_default_features = attrs.get("data-cargo-no-default-features").is_some();
+        let data_all_features = attrs.contains_key("data-cargo-all-features");
+        let data_no_default_features = attrs.contains_key("data-cargo-no-default-features");
 
-        let loader_shim = attrs.get("data-loader-shim").is_some();
+        let loader_shim = worker_glue || attrs.contains_key("data-loader-shim");
         if loader_shim {
             ensure!(
                 app_type == RustAppType::Worker,
@@ -177,10 +241,12 @@ impl RustApp {
             no_demangle,
             reference_types,
             weak_refs,
+            bindgen_args,
             wasm_opt,
             app_type,
             name,
             loader_shim,
+            worker_glue,
         })
     }
 
@@ -190,8 +256,10 @@ impl RustApp {
         ignore_chan: Option<mpsc::Sender<PathBuf>>,
     ) -> Result<Self> {
         let path = html_dir.join("Cargo.toml");
-        let manifest = CargoMetadata::new(&path).await?;
+        let manifest = CargoMetadata::new(&path, cfg.cargo_flags, &cfg.cargo_env).await?;
         let name = manifest.package.name.clone();
+        let wasm_opt = cfg.wasm_opt.unwrap_or(WasmOptLevel::Off);
+        let bindgen_args = cfg.bindgen_args.clone();
 
         Ok(Self {
             id: None,
@@ -205,10 +273,12 @@ impl RustApp {
             no_demangle: false,
             reference_types: false,
             weak_refs: false,
-            wasm_opt: WasmOptLevel::Off,
+            bindgen_args,
+            wasm_opt,
             app_type: RustAppType::Main,
             name,
             loader_shim: false,
+            worker_glue: false,
         })
     }
 
@@ -218,17 +288,66 @@ impl RustApp {
         tokio::spawn(self.build())
     }
 
+    /// The name of the generated output files, which must be unique per HTML file.
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
     #[tracing::instrument(level = "trace", skip(self))]
     async fn build(mut self) -> Result<TrunkAssetPipelineOutput> {
-        let (wasm, hashed_name) = self.cargo_build().await?;
-        let output = self.wasm_bindgen_build(wasm.as_ref(), &hashed_name).await?;
+        // The pages of a multi-page app reuse the outputs of the same app built for another page.
+        let key = self.output_key();
+        if let Some(mut output) = self.cfg.rust_outputs.get(&key) {
+            tracing::info!("reusing build of {} from another page", &self.name);
+            output.cfg = self.cfg.clone();
+            output.id = self.id;
+            return Ok(TrunkAssetPipelineOutput::RustApp(output));
+        }
+
+        // Apps of the same crate share the cargo output for each of their binaries, which is
+        // overwritten by builds with other features, so it is processed by one app at a time.
+        let output = {
+            let _guard = CARGO_BUILD_LOCK.lock().await;
+            let (wasm, hashed_name) = self.cargo_build().await?;
+            self.wasm_bindgen_build(wasm.as_ref(), &hashed_name).await?
+        };
         self.wasm_opt_build(&output.wasm_output).await?;
+        self.cfg.rust_outputs.insert(key, output.clone());
         Ok(TrunkAssetPipelineOutput::RustApp(output))
     }
 
+    /// The key of the build options of this app, which determine its outputs.
+    fn output_key(&self) -> String {
+        format!(
+            "{:?}",
+            (
+                (
+                    &self.manifest.manifest_path,
+                    &self.bin,
+                    &self.cargo_features,
+                    self.app_type,
+                    &self.name,
+                    &self.bindgen_args,
+                ),
+                (
+                    self.keep_debug,
+                    self.typescript,
+                    self.no_demangle,
+                    self.reference_types,
+                    self.weak_refs,
+                    self.wasm_opt,
+                    self.loader_shim,
+                    self.worker_glue,
+                ),
+            )
+        )
+    }
+
     #[tracing::instrument(level = "trace", skip(self))]
     async fn cargo_build(&mut self) -> Result<(PathBuf, String)> {
         tracing::info!("building {}", &self.manifest.package.name);
+        let cfg = self.cfg.clone();
+        let _timing = cfg.time("cargo", &self.name);
 
         // Spawn the cargo build process.
         let mut args = vec![
@@ -237,58 +356,38 @@ impl RustApp {
             "--manifest-path",
             &self.manifest.manifest_path,
         ];
-        if self.cfg.release {
-            args.push("--release");
-        }
+        args.extend(self.cfg.cargo_profile.cargo_args());
+        args.extend(self.cfg.cargo_flags.cargo_args());
         if let Some(bin) = &self.bin {
             args.push("--bin");
             args.push(bin);
         }
 
-        match &self.cargo_features {
-            Features::All => args.push("--all-features"),
-            Features::Custom {
-                features,
-                no_default_features,
-            } => {
-                if *no_default_features {
-                    args.push("--no-default-features");
-                }
-
-                if let Some(cargo_features) = features {
-                    args.push("--features");
-                    args.push(cargo_features);
-                }
-            }
-        }
-
-        let build_res = common::run_command("cargo", Path::new("cargo"), &args)
-            .await
-            .context("error during cargo build execution");
+        args.extend(self.cargo_features.cargo_args());
 
         // Send cargo's target dir over to the watcher to be ignored. We must do this before
-        // checking for errors, otherwise the dir will never be ignored. If we attempt to do
-        // this pre-build, the canonicalization will fail and will not be ignored.
+        // building, otherwise changes to the dir made by the build would cancel it. The dir is
+        // created first, as its canonicalization would fail otherwise, and it would not be ignored.
+        let target_dir = self.manifest.metadata.target_directory.as_std_path();
+        fs::create_dir_all(target_dir)
+            .await
+            .with_context(|| format!("error creating cargo target dir {:?}", target_dir))?;
         if let Some(chan) = &mut self.ignore_chan {
-            let _ = chan.try_send(
-                self.manifest
-                    .metadata
-                    .target_directory
-                    .clone()
-                    .into_std_path_buf(),
-            );
+            let _ = chan.try_send(target_dir.to_owned());
         }
 
-        // Now propagate any errors which came from the cargo build.
-        build_res?;
+        let cargo = |args: &[&str]| common::cargo_command(args, &cfg.cargo_env, &cfg.tools);
+        common::run_capture_stderr("cargo", &mut cargo(&args))
+            .await
+            .context("error during cargo build execution")?;
 
         // Perform a final cargo invocation on success to get artifact names.
         tracing::info!("fetching cargo artifacts");
         args.push("--message-format=json");
-        let artifacts_out = Command::new("cargo")
-            .args(args.as_slice())
+        let artifacts_out = cargo(&args)
             .stdout(Stdio::piped())
             .stderr(Stdio::piped())
+            .kill_on_drop(true)
             .spawn()
             .context("error spawning cargo build artifacts task")?
             .wait_with_output()
@@ -346,11 +445,7 @@ impl RustApp {
         let wasm_bytes = fs::read(&wasm)
             .await
             .context("error reading wasm file for hash generation")?;
-        let hashed_name = self
-            .cfg
-            .filehash
-            .then(|| format!("{}-{:x}", self.name, seahash::hash(&wasm_bytes)))
-            .unwrap_or_else(|| self.name.clone());
+        let hashed_name = self.cfg.hashed_name(&self.name, seahash::hash(&wasm_bytes));
 
         Ok((wasm.into_std_path_buf(), hashed_name))
     }
@@ -358,31 +453,44 @@ impl RustApp {
     #[tracing::instrument(level = "trace", skip(self, wasm, hashed_name))]
     async fn wasm_bindgen_build(&self, wasm: &Path, hashed_name: &str) -> Result<RustAppOutput> {
         // Skip the hashed file name for workers as their file name must be named at runtime.
-        // Therefore, workers use the Cargo binary name for file naming.
+        // Therefore, workers use the Cargo binary name for file naming, unless they are spawned
+        // by the injected script, which knows the hashed file name.
         let hashed_name = match self.app_type {
-            RustAppType::Main => hashed_name,
-            RustAppType::Worker => &self.name,
+            RustAppType::Worker if !self.worker_glue => &self.name,
+            _ => hashed_name,
         };
 
         let version = find_wasm_bindgen_version(&self.cfg.tools, &self.manifest);
-        let wasm_bindgen = tools::get(Application::WasmBindgen, version.as_deref()).await?;
+        let wasm_bindgen = tools::get(
+            Application::WasmBindgen,
+            version.as_deref(),
+            &self.cfg.tools,
+        )
+        .await?;
 
         // Ensure our output dir is in place.
         let wasm_bindgen_name = Application::WasmBindgen.name();
-        let mode_segment = if self.cfg.release { "release" } else { "debug" };
+        let mode_segment = self.cfg.cargo_profile.dir_name();
         let bindgen_out = self
             .manifest
             .metadata
             .target_directory
             .join(wasm_bindgen_name)
             .join(mode_segment);
-        fs::create_dir_all(bindgen_out.as_path())
+        // Hashed names may include a dir, in which all the outputs are placed, so that they can
+        // reference each other & the snippets by their file names.
+        let (out_dir, out_name) = hashed_name.rsplit_once('/').unwrap_or(("", hashed_name));
+        let bindgen_out_dir = bindgen_out.join(out_dir);
+        fs::create_dir_all(bindgen_out_dir.as_path())
             .await
             .context("error creating wasm-bindgen output dir")?;
+        fs::create_dir_all(self.cfg.staging_path(out_dir))
+            .await
+            .context("error creating wasm-bindgen output dir in stage dir")?;
 
         // Build up args for calling wasm-bindgen.
-        let arg_out_path = format!("--out-dir={}", bindgen_out);
-        let arg_out_name = format!("--out-name={}", &hashed_name);
+        let arg_out_path = format!("--out-dir={}", bindgen_out_dir);
+        let arg_out_name = format!("--out-name={}", out_name);
         let target_wasm = wasm.to_string_lossy().to_string();
         let target_type = match self.app_type {
             RustAppType::Main => "--target=web",
@@ -406,12 +514,15 @@ impl RustApp {
         if !self.typescript {
             args.push("--no-typescript");
         }
+        args.extend(self.bindgen_args.iter().map(String::as_str));
 
         // Invoke wasm-bindgen.
         tracing::info!("calling wasm-bindgen for {}", self.name);
+        let timing = self.cfg.time("bindgen", &self.name);
         common::run_command(wasm_bindgen_name, &wasm_bindgen, &args)
             .await
             .map_err(|err| check_target_not_found_err(err, wasm_bindgen_name))?;
+        drop(timing);
 
         // Copy the generated WASM & JS loader to the dist dir.
         tracing::info!("copying generated wasm-bindgen artifacts");
@@ -419,26 +530,50 @@ impl RustApp {
         let hashed_wasm_name = format!("{}_bg.wasm", &hashed_name);
         let hashed_ts_name = format!("{}.d.ts", &hashed_name);
         let js_loader_path = bindgen_out.join(&hashed_js_name);
-        let js_loader_path_dist = self.cfg.staging_dist.join(&hashed_js_name);
+        let js_loader_path_dist = self.cfg.staging_path(&hashed_js_name);
         let wasm_path = bindgen_out.join(&hashed_wasm_name);
-        let wasm_path_dist = self.cfg.staging_dist.join(&hashed_wasm_name);
+        let wasm_path_dist = self.cfg.staging_path(&hashed_wasm_name);
         let hashed_loader_name = self
             .loader_shim
             .then(|| format!("{}_loader.js", &hashed_name));
         let loader_shim_path = hashed_loader_name
             .as_ref()
-            .map(|m| self.cfg.staging_dist.join(m));
+            .map(|m| self.cfg.staging_path(m));
 
-        fs::copy(js_loader_path, js_loader_path_dist)
+        if self.cfg.minify {
+            let js = fs::read(&js_loader_path)
+                .await
+                .context("error reading JS loader file")?;
+            // Workers are built with the `no-modules` target, whose JS loader is a classic script.
+            let mode = match self.app_type {
+                RustAppType::Main => TopLevelMode::Module,
+                RustAppType::Worker => TopLevelMode::Global,
+            };
+            let js = minify_js(js, mode);
+            fs::write(&js_loader_path_dist, &js)
+                .await
+                .context("error writing minified JS loader file to stage dir")?;
+            self.cfg
+                .output_hashes
+                .record(&js_loader_path_dist, &js)
+                .await;
+        } else {
+            fs::copy(js_loader_path, js_loader_path_dist)
+                .await
+                .context("error copying JS loader file to stage dir")?;
+        }
+        // The wasm file is read & written instead of copied, so that its hash is recorded.
+        let wasm = fs::read(wasm_path)
             .await
-            .context("error copying JS loader file to stage dir")?;
-        fs::copy(wasm_path, wasm_path_dist)
+            .context("error reading wasm file")?;
+        fs::write(&wasm_path_dist, &wasm)
             .await
             .context("error copying wasm file to stage dir")?;
+        self.cfg.output_hashes.record(&wasm_path_dist, &wasm).await;
 
         if self.typescript {
             let ts_path = bindgen_out.join(&hashed_ts_name);
-            let ts_path_dist = self.cfg.staging_dist.join(&hashed_ts_name);
+            let ts_path_dist = self.cfg.staging_path(&hashed_ts_name);
 
             fs::copy(ts_path, ts_path_dist)
                 .await
@@ -453,8 +588,8 @@ impl RustApp {
             loader_f
                 .write_all(
                     format!(
-                        r#"importScripts("./{}");wasm_bindgen("./{}");"#,
-                        hashed_js_name, hashed_wasm_name
+                        r#"importScripts("./{name}.js");wasm_bindgen("./{name}_bg.wasm");"#,
+                        name = out_name
                     )
                     .as_bytes(),
                 )
@@ -473,14 +608,15 @@ impl RustApp {
         };
 
         // Check for any snippets, and copy them over.
-        let snippets_dir = bindgen_out.join(SNIPPETS_DIR);
+        let snippets_dir = bindgen_out_dir.join(SNIPPETS_DIR);
         if path_exists(&snippets_dir).await? {
-            copy_dir_recursive(
-                bindgen_out.join(SNIPPETS_DIR),
-                self.cfg.staging_dist.join(SNIPPETS_DIR),
-            )
-            .await
-            .context("error copying snippets dir to stage dir")?;
+            let snippets_dir_dist = self.cfg.staging_path(out_dir).join(SNIPPETS_DIR);
+            copy_dir_recursive(snippets_dir, snippets_dir_dist.clone())
+                .await
+                .context("error copying snippets dir to stage dir")?;
+            if self.cfg.minify {
+                minify_js_dir(snippets_dir_dist).await?;
+            }
         }
 
         Ok(RustAppOutput {
@@ -491,6 +627,8 @@ impl RustApp {
             ts_output,
             loader_shim_output: hashed_loader_name,
             type_: self.app_type,
+            name: self.name.clone(),
+            worker_glue: self.worker_glue,
         })
     }
 
@@ -507,11 +645,11 @@ impl RustApp {
         }
 
         let version = self.cfg.tools.wasm_opt.as_deref();
-        let wasm_opt = tools::get(Application::WasmOpt, version).await?;
+        let wasm_opt = tools::get(Application::WasmOpt, version, &self.cfg.tools).await?;
 
         // Ensure our output dir is in place.
         let wasm_opt_name = Application::WasmOpt.name();
-        let mode_segment = if self.cfg.release { "release" } else { "debug" };
+        let mode_segment = self.cfg.cargo_profile.dir_name();
         let output = self
             .manifest
             .metadata
@@ -523,32 +661,54 @@ impl RustApp {
             .context("error creating wasm-opt output dir")?;
 
         // Build up args for calling wasm-opt.
-        let output = output.join(hashed_name);
+        let output = output.join(hashed_name.rsplit('/').next().unwrap_or(hashed_name));
         let arg_output = format!("--output={}", output);
         let arg_opt_level = format!("-O{}", self.wasm_opt.as_ref());
-        let target_wasm = self
-            .cfg
-            .staging_dist
-            .join(hashed_name)
-            .to_string_lossy()
-            .to_string();
+        let target_wasm_path = self.cfg.staging_path(hashed_name);
+        let target_wasm = target_wasm_path.to_string_lossy().to_string();
         let mut args: Vec<&str> = vec![&arg_output, &arg_opt_level, &target_wasm];
 
         if self.reference_types {
             args.push("--enable-reference-types");
         }
 
-        // Invoke wasm-opt.
-        tracing::info!("calling wasm-opt");
-        common::run_command(wasm_opt_name, &wasm_opt, &args)
+        // Invoke wasm-opt, unless neither it & its args nor the WASM changed.
+        let wasm = fs::read(&target_wasm_path)
             .await
-            .map_err(|err| check_target_not_found_err(err, wasm_opt_name))?;
+            .context("error reading wasm file for wasm-opt")?;
+        let mut digest = InputDigest::new("wasm-opt");
+        digest.add(wasm_opt.to_string_lossy().as_bytes());
+        for arg in &args {
+            digest.add(arg);
+        }
+        digest.add(&wasm);
+        let label = format!("wasm-opt output of {}", self.name);
+        let outputs = self
+            .cfg
+            .pipeline_cache
+            .get_or_run(&label, &digest, || async {
+                tracing::info!("calling wasm-opt");
+                let timing = self.cfg.time("wasm-opt", &self.name);
+                common::run_command(wasm_opt_name, &wasm_opt, &args)
+                    .await
+                    .map_err(|err| check_target_not_found_err(err, wasm_opt_name))?;
+                drop(timing);
+                let wasm = fs::read(&output)
+                    .await
+                    .context("error reading wasm-opt output")?;
+                Ok(vec![wasm])
+            })
+            .await?;
 
         // Copy the generated WASM file to the dist dir.
         tracing::info!("copying generated wasm-opt artifacts");
-        fs::copy(output, self.cfg.staging_dist.join(hashed_name))
+        fs::write(&target_wasm_path, &outputs[0])
             .await
             .context("error copying wasm file to dist dir")?;
+        self.cfg
+            .output_hashes
+            .record(&target_wasm_path, &outputs[0])
+            .await;
 
         Ok(())
     }
@@ -560,7 +720,7 @@ impl RustApp {
 /// - Located in the `Cargo.lock` if it exists. This is mostly the case as we run `cargo build`
 ///   before even calling this function.
 /// - Located in the `Cargo.toml` as direct dependency of the project.
-fn find_wasm_bindgen_version<'a>(
+pub fn find_wasm_bindgen_version<'a>(
     cfg: &'a ConfigOptsTools,
     manifest: &CargoMetadata,
 ) -> Option<Cow<'a, str>> {
@@ -587,14 +747,28 @@ fn find_wasm_bindgen_version<'a>(
             .map(|p| Cow::from(p.version.to_string()))
     };
 
-    cfg.wasm_bindgen
-        .as_deref()
-        .map(Cow::from)
-        .or_else(find_lock)
-        .or_else(find_manifest)
+    match cfg.wasm_bindgen.as_deref() {
+        Some(pinned) => {
+            // The CLI must match the version of the crate exactly, so warn early about a pinned
+            // version that wasm-bindgen would reject with a less helpful error.
+            if let Some(locked) = find_lock().or_else(find_manifest) {
+                if locked != pinned.trim_start_matches('v') {
+                    tracing::warn!(
+                        "wasm-bindgen {} pinned in `[tools]` does not match the version {} of the \
+                         wasm-bindgen crate used by the project",
+                        pinned,
+                        locked
+                    );
+                }
+            }
+            Some(Cow::from(pinned))
+        }
+        None => find_lock().or_else(find_manifest),
+    }
 }
 
 /// The output of a cargo build pipeline.
+#[derive(Clone, Debug)]
 pub struct RustAppOutput {
     /// The runtime build config.
     pub cfg: Arc<RtcBuild>,
@@ -605,16 +779,24 @@ pub struct RustAppOutput {
     /// The filename of the generated WASM file written to the dist dir.
     pub wasm_output: String,
     /// The filename of the generated .ts file written to the dist dir.
+    #[allow(dead_code)]
     pub ts_output: Option<String>,
     /// The filename of the generated loader shim script for web workers written to the dist dir.
     pub loader_shim_output: Option<String>,
     /// Is this module main or a worker.
     pub type_: RustAppType,
+    /// The name of the module.
+    pub name: String,
+    /// Whether to inject a script spawning this worker.
+    pub worker_glue: bool,
 }
 
 pub fn pattern_evaluate(template: &str, params: &HashMap<String, String>) -> String {
     let mut result = template.to_string();
-    for (k, v) in params.iter() {
+    // Replace the params in the order of their names, for a deterministic output.
+    let mut params = params.iter().collect::<Vec<_>>();
+    params.sort();
+    for (k, v) in params {
         let pattern = format!("{{{}}}", k.as_str());
         if let Some(file_path) = v.strip_prefix('@') {
             if let Ok(contents) = std::fs::read_to_string(file_path) {
@@ -631,7 +813,25 @@ impl RustAppOutput {
     pub async fn finalize(self, dom: &mut Document) -> Result<()> {
         if self.type_ == RustAppType::Worker {
             // Skip the script tag and preload links for workers, and remove the link tag only.
-            // Workers are initialized and managed by the app itself at runtime.
+            // Workers are initialized and managed by the app itself at runtime, possibly using a
+            // function registered by the injected script, which spawns the worker by its name.
+            let loader = self
+                .loader_shim_output
+                .as_ref()
+                .filter(|_| self.worker_glue);
+            if let (Some(id), Some(loader)) = (self.id, loader) {
+                if self.cfg.inject_scripts {
+                    let script = format!(
+                        r#"<script>(window.trunkWorkers=window.trunkWorkers||{{}})[{name:?}]=(options)=>new Worker('{base}{loader}',options);</script>"#,
+                        name = self.name,
+                        base = self.cfg.public_url,
+                        loader = loader,
+                    );
+                    dom.select(&super::trunk_id_selector(id))
+                        .replace_with_html(script);
+                    return Ok(());
+                }
+            }
             if let Some(id) = self.id {
                 dom.select(&super::trunk_id_selector(id)).remove();
             }
@@ -659,17 +859,32 @@ impl RustAppOutput {
         params.insert("base".to_owned(), base.clone());
         params.insert("js".to_owned(), js.clone());
         params.insert("wasm".to_owned(), wasm.clone());
+        let js_integrity = super::integrity(&self.cfg, js).await?;
+        let wasm_integrity = super::integrity(&self.cfg, wasm).await?;
+        if let (Some(js_integrity), Some(wasm_integrity)) = (&js_integrity, &wasm_integrity) {
+            params.insert("js_integrity".to_owned(), js_integrity.clone());
+            params.insert("wasm_integrity".to_owned(), wasm_integrity.clone());
+        }
 
         let preload = match pattern_preload {
             Some(pattern) => pattern_evaluate(pattern, &params),
             None => {
+                let (js_attrs, wasm_attrs) = match (&js_integrity, &wasm_integrity) {
+                    (Some(js), Some(wasm)) => (
+                        format!(r#" integrity="{}" crossorigin="anonymous""#, js),
+                        format!(r#" integrity="{}""#, wasm),
+                    ),
+                    _ => Default::default(),
+                };
                 format!(
                     r#"
-<link rel="preload" href="{base}{wasm}" as="fetch" type="application/wasm" crossorigin>
-<link rel="modulepreload" href="{base}{js}">"#,
+<link rel="preload" href="{base}{wasm}" as="fetch" type="application/wasm" crossorigin{wasm_attrs}>
+<link rel="modulepreload" href="{base}{js}"{js_attrs}>"#,
                     base = base,
                     js = js,
-                    wasm = wasm
+                    wasm = wasm,
+                    js_attrs = js_attrs,
+                    wasm_attrs = wasm_attrs,
                 )
             }
         };
@@ -677,14 +892,22 @@ impl RustAppOutput {
 
         let script = match pattern_script {
             Some(pattern) => pattern_evaluate(pattern, &params),
-            None => {
-                format!(
+            // Fetch the WASM with its integrity hash, so that it is verified just as the preload.
+            None => match &wasm_integrity {
+                Some(integrity) => format!(
+                    r#"<script type="module">import init from '{base}{js}';init(fetch('{base}{wasm}',{{integrity:'{integrity}'}}));</script>"#,
+                    base = base,
+                    js = js,
+                    wasm = wasm,
+                    integrity = integrity,
+                ),
+                None => format!(
                     r#"<script type="module">import init from '{base}{js}';init('{base}{wasm}');</script>"#,
                     base = base,
                     js = js,
                     wasm = wasm,
-                )
-            }
+                ),
+            },
         };
         match self.id {
             Some(id) => dom
@@ -696,10 +919,27 @@ impl RustAppOutput {
     }
 }
 
+/// Get the wasm-opt level of an app from its `data-wasm-opt` attr, falling back to the configured
+/// level, or to the default level in release mode.
+pub(super) fn wasm_opt_level(attr: Option<&str>, cfg: &RtcBuild) -> Result<WasmOptLevel> {
+    Ok(attr
+        .map(str::parse)
+        .transpose()?
+        .or(cfg.wasm_opt)
+        .unwrap_or_else(|| {
+            if cfg.release {
+                Default::default()
+            } else {
+                WasmOptLevel::Off
+            }
+        }))
+}
+
 /// Different optimization levels that can be configured with `wasm-opt`.
-#[derive(PartialEq, Eq)]
-enum WasmOptLevel {
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum WasmOptLevel {
     /// Default optimization passes.
+    #[default]
     Default,
     /// No optimization passes, skipping the wasp-opt step.
     Off,
@@ -751,12 +991,6 @@ impl AsRef<str> for WasmOptLevel {
     }
 }
 
-impl Default for WasmOptLevel {
-    fn default() -> Self {
-        Self::Default
-    }
-}
-
 /// Handle invocation errors indicating that the target binary was not found, simply wrapping the
 /// error in additional context stating more clearly that the target was not found.
 fn check_target_not_found_err(err: anyhow::Error, target: &str) -> anyhow::Error {
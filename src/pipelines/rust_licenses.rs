@@ -0,0 +1,368 @@
+//! Rust licenses asset pipeline, which bundles the license texts of the dependencies compiled
+//! into the WASM of a Rust app.
+
+use std::collections::{HashMap, HashSet};
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+
+use anyhow::{bail, Context, Result};
+use cargo_metadata::{DependencyKind, Metadata, Package, PackageId};
+use nipper::Document;
+use serde::Serialize;
+use tokio::fs;
+use tokio::task::JoinHandle;
+
+use super::{Attrs, TrunkAssetPipelineOutput, ATTR_HREF};
+use crate::common::escape_html;
+use crate::config::{CargoMetadata, RtcBuild};
+
+/// The attribute of the format of the bundle.
+const ATTR_FORMAT: &str = "data-format";
+/// The attribute to inject a `<link rel="license">` to the bundle.
+const ATTR_LINK: &str = "data-link";
+/// The stem of the file name of the bundle.
+const FILE_STEM: &str = "licenses";
+/// The target platform of the WASM, whose dependencies are resolved.
+const WASM_TARGET: &str = "wasm32-unknown-unknown";
+/// The prefixes of the names of the files with license texts, in upper case.
+const LICENSE_FILE_PREFIXES: &[&str] = &["LICENSE", "LICENCE", "COPYING", "NOTICE", "UNLICENSE"];
+
+/// A Rust licenses asset pipeline.
+pub struct RustLicenses {
+    /// The ID of this pipeline's source HTML element.
+    id: usize,
+    /// Runtime build config.
+    cfg: Arc<RtcBuild>,
+    /// The `Cargo.toml` of the Rust app.
+    manifest: PathBuf,
+    /// The format of the bundle.
+    format: LicensesFormat,
+    /// If a `<link rel="license">` to the bundle should be injected.
+    link: bool,
+}
+
+/// The formats of a bundle of licenses.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum LicensesFormat {
+    /// A page listing the dependencies with their license texts.
+    Html,
+    /// The JSON of the dependencies with their license texts.
+    Json,
+}
+
+/// A dependency compiled into the WASM, with its license texts.
+#[derive(Debug, Serialize)]
+pub struct LicensedPackage {
+    /// The name of the package.
+    pub name: String,
+    /// The version of the package.
+    pub version: String,
+    /// The SPDX license expression of the package, if any.
+    pub license: Option<String>,
+    /// The repository of the package, if any.
+    pub repository: Option<String>,
+    /// The authors of the package.
+    pub authors: Vec<String>,
+    /// The license texts of the package.
+    pub license_texts: Vec<LicenseText>,
+}
+
+/// A license text of a package.
+#[derive(Debug, Serialize)]
+pub struct LicenseText {
+    /// The name of the file of the text, e.g. `LICENSE-MIT`.
+    pub file: String,
+    /// The text.
+    pub text: String,
+}
+
+impl RustLicenses {
+    pub const TYPE_RUST_LICENSES: &'static str = "rust-licenses";
+
+    pub async fn new(
+        cfg: Arc<RtcBuild>,
+        html_dir: Arc<PathBuf>,
+        attrs: Attrs,
+        id: usize,
+    ) -> Result<Self> {
+        // The `Cargo.toml` or its dir, as of `<link data-trunk rel="rust" .../>`.
+        let manifest = attrs
+            .get(ATTR_HREF)
+            .map(|attr| {
+                let mut path = PathBuf::new();
+                path.extend(attr.split('/'));
+                if !path.is_absolute() {
+                    path = html_dir.join(path);
+                }
+                if !path.ends_with("Cargo.toml") {
+                    path = path.join("Cargo.toml");
+                }
+                path
+            })
+            .unwrap_or_else(|| html_dir.join("Cargo.toml"));
+        let format = match attrs.get(ATTR_FORMAT).map(String::as_str) {
+            None | Some("html") => LicensesFormat::Html,
+            Some("json") => LicensesFormat::Json,
+            Some(format) => bail!(
+                r#"invalid `data-format` {:?} of <link data-trunk rel="rust-licenses" .../>, expected "html" or "json""#,
+                format
+            ),
+        };
+        Ok(Self {
+            id,
+            cfg,
+            manifest,
+            format,
+            link: attrs.contains_key(ATTR_LINK),
+        })
+    }
+
+    /// Spawn the pipeline for this asset type.
+    #[tracing::instrument(level = "trace", skip(self))]
+    pub fn spawn(self) -> JoinHandle<Result<TrunkAssetPipelineOutput>> {
+        tokio::spawn(self.run())
+    }
+
+    /// Run this pipeline.
+    #[tracing::instrument(level = "trace", skip(self))]
+    async fn run(self) -> Result<TrunkAssetPipelineOutput> {
+        let rel_path = crate::common::strip_prefix(&self.manifest);
+        tracing::info!(path = ?rel_path, "collecting licenses of dependencies");
+        let mut options = vec!["--filter-platform", WASM_TARGET];
+        options.extend(self.cfg.cargo_features.cargo_args());
+        let manifest = CargoMetadata::with_options(
+            &self.manifest,
+            self.cfg.cargo_flags,
+            &self.cfg.cargo_env,
+            &options,
+        )
+        .await?;
+        let packages = licensed_packages(&manifest.metadata, &manifest.package.id).await?;
+        let unlicensed = packages
+            .iter()
+            .filter(|package| package.license_texts.is_empty())
+            .map(|package| format!("{} {}", package.name, package.version))
+            .collect::<Vec<_>>();
+        if !unlicensed.is_empty() {
+            tracing::warn!(
+                "no license texts found for {} dependencies: {}",
+                unlicensed.len(),
+                unlicensed.join(", ")
+            );
+        }
+
+        let (bytes, ext) = match self.format {
+            LicensesFormat::Html => (licenses_html(&packages).into_bytes(), "html"),
+            LicensesFormat::Json => {
+                let mut json =
+                    serde_json::to_vec_pretty(&packages).context("error serializing licenses")?;
+                json.push(b'\n');
+                (json, "json")
+            }
+        };
+        let file = format!(
+            "{}.{}",
+            self.cfg.hashed_name(FILE_STEM, seahash::hash(&bytes)),
+            ext
+        );
+        let file_path = self.cfg.staging_path(&file);
+        if let Some(parent) = file_path.parent() {
+            fs::create_dir_all(parent)
+                .await
+                .context("error creating licenses pipeline output dir")?;
+        }
+        fs::write(&file_path, &bytes)
+            .await
+            .with_context(|| format!("error writing licenses {:?}", file_path))?;
+        self.cfg.output_hashes.record(&file_path, &bytes).await;
+        tracing::info!(path = ?rel_path, "finished collecting licenses of dependencies");
+        Ok(TrunkAssetPipelineOutput::RustLicenses(RustLicensesOutput {
+            cfg: self.cfg.clone(),
+            id: self.id,
+            file,
+            link: self.link,
+        }))
+    }
+}
+
+/// Collect the packages compiled into the WASM of the given root package, i.e. its normal
+/// dependencies, transitively, skipping proc macros & the members of the workspace, with their
+/// license texts, sorted by name & version.
+///
+/// The dependencies must be resolved for the WASM target, whose build dependencies & proc macros
+/// are compiled for the host only.
+pub(super) async fn licensed_packages(
+    metadata: &Metadata,
+    root: &PackageId,
+) -> Result<Vec<LicensedPackage>> {
+    let Some(resolve) = &metadata.resolve else {
+        bail!("error resolving the dependencies of {}", root);
+    };
+    let nodes: HashMap<_, _> = resolve.nodes.iter().map(|node| (&node.id, node)).collect();
+    let packages: HashMap<_, _> = metadata
+        .packages
+        .iter()
+        .map(|package| (&package.id, package))
+        .collect();
+    let is_proc_macro = |package: &Package| {
+        package
+            .targets
+            .iter()
+            .any(|target| target.kind.iter().any(|kind| kind == "proc-macro"))
+    };
+
+    let mut compiled = HashSet::new();
+    let mut queue = vec![root];
+    while let Some(id) = queue.pop() {
+        for dependency in nodes.get(id).into_iter().flat_map(|node| &node.deps) {
+            let is_normal = dependency
+                .dep_kinds
+                .iter()
+                .any(|info| info.kind == DependencyKind::Normal);
+            let is_compiled = packages
+                .get(&dependency.pkg)
+                .is_some_and(|package| !is_proc_macro(package));
+            if is_normal && is_compiled && compiled.insert(&dependency.pkg) {
+                queue.push(&dependency.pkg);
+            }
+        }
+    }
+
+    let mut licensed = Vec::new();
+    for id in compiled {
+        if metadata.workspace_members.contains(id) {
+            continue;
+        }
+        let Some(package) = packages.get(id) else {
+            continue;
+        };
+        licensed.push(LicensedPackage {
+            name: package.name.clone(),
+            version: package.version.to_string(),
+            license: package.license.clone(),
+            repository: package.repository.clone(),
+            authors: package.authors.clone(),
+            license_texts: license_texts(package).await?,
+        });
+    }
+    licensed.sort_by(|a, b| (&a.name, &a.version).cmp(&(&b.name, &b.version)));
+    Ok(licensed)
+}
+
+/// Read the license texts of the given package, i.e. its `license-file` & the files in its dir
+/// named like `LICENSE*`, `COPYING*` or `NOTICE*`, sorted by name.
+async fn license_texts(package: &Package) -> Result<Vec<LicenseText>> {
+    let Some(dir) = package.manifest_path.parent().map(|dir| dir.as_std_path()) else {
+        return Ok(Vec::new());
+    };
+    let mut files = Vec::new();
+    let mut entries = fs::read_dir(dir)
+        .await
+        .with_context(|| format!("error reading package dir {:?}", dir))?;
+    while let Some(entry) = entries
+        .next_entry()
+        .await
+        .with_context(|| format!("error reading contents of package dir {:?}", dir))?
+    {
+        let name = entry.file_name().to_string_lossy().to_uppercase();
+        let is_license = LICENSE_FILE_PREFIXES
+            .iter()
+            .any(|prefix| name.starts_with(prefix));
+        if is_license && entry.path().is_file() {
+            files.push(entry.path());
+        }
+    }
+    if let Some(file) = package.license_file() {
+        let file = file.into_std_path_buf();
+        if !files.contains(&file) && file.is_file() {
+            files.push(file);
+        }
+    }
+    files.sort();
+
+    let mut texts = Vec::new();
+    for file in files {
+        let text = fs::read(&file)
+            .await
+            .with_context(|| format!("error reading license file {:?}", file))?;
+        texts.push(LicenseText {
+            file: file_name(&file),
+            text: String::from_utf8_lossy(&text).into_owned(),
+        });
+    }
+    Ok(texts)
+}
+
+/// The name of the given file.
+fn file_name(file: &Path) -> String {
+    file.file_name()
+        .map(|name| name.to_string_lossy().into_owned())
+        .unwrap_or_default()
+}
+
+/// Render the page of the given packages with their license texts.
+fn licenses_html(packages: &[LicensedPackage]) -> String {
+    let mut html = String::from(
+        "<!DOCTYPE html>\n<html><head><meta \
+         charset=\"utf-8\"><title>Licenses</title></head><body>\n<h1>Licenses of the \
+         dependencies</h1>\n",
+    );
+    for package in packages {
+        html.push_str(&format!(
+            "<section>\n<h2>{} {}</h2>\n",
+            escape_html(&package.name),
+            escape_html(&package.version)
+        ));
+        if let Some(license) = &package.license {
+            html.push_str(&format!("<p>License: {}</p>\n", escape_html(license)));
+        }
+        if let Some(repository) = &package.repository {
+            html.push_str(&format!("<p>Repository: {}</p>\n", escape_html(repository)));
+        }
+        if !package.authors.is_empty() {
+            html.push_str(&format!(
+                "<p>Authors: {}</p>\n",
+                escape_html(&package.authors.join(", "))
+            ));
+        }
+        for text in &package.license_texts {
+            html.push_str(&format!(
+                "<h3>{}</h3>\n<pre>{}</pre>\n",
+                escape_html(&text.file),
+                escape_html(&text.text)
+            ));
+        }
+        html.push_str("</section>\n");
+    }
+    html.push_str("</body></html>\n");
+    html
+}
+
+/// The output of a Rust licenses build pipeline.
+pub struct RustLicensesOutput {
+    /// The runtime build config.
+    pub cfg: Arc<RtcBuild>,
+    /// The ID of this pipeline.
+    pub id: usize,
+    /// Name of the finalized output file.
+    pub file: String,
+    /// If a `<link rel="license">` to the file should be injected.
+    pub link: bool,
+}
+
+impl RustLicensesOutput {
+    pub async fn finalize(self, dom: &mut Document) -> Result<()> {
+        let html = if self.link {
+            format!(
+                r#"<link rel="license" href="{base}{file}"/>"#,
+                base = &self.cfg.public_url,
+                file = self.file
+            )
+        } else {
+            String::new()
+        };
+        dom.select(&super::trunk_id_selector(self.id))
+            .replace_with_html(html);
+        Ok(())
+    }
+}
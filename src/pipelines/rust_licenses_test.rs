@@ -0,0 +1,173 @@
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+
+use anyhow::{ensure, Context, Result};
+use cargo_metadata::{Metadata, PackageId};
+use serde_json::json;
+
+use crate::config::RtcBuild;
+use crate::pipelines::rust_licenses::*;
+
+/// A package of the test metadata, with its dir in the tempdir.
+fn package(dir: &Path, name: &str, kind: &str, license_file: Option<&str>) -> serde_json::Value {
+    json!({
+        "name": name,
+        "version": "1.0.0",
+        "id": format!("{} 1.0.0", name),
+        "license": "MIT",
+        "license_file": license_file,
+        "dependencies": [],
+        "targets": [{"name": name, "kind": [kind], "src_path": dir.join(name).join("src/lib.rs")}],
+        "features": {},
+        "manifest_path": dir.join(name).join("Cargo.toml"),
+    })
+}
+
+/// A node of the resolve of the test metadata, with its dependencies & their kinds.
+fn node(name: &str, deps: &[(&str, Option<&str>)]) -> serde_json::Value {
+    let deps = deps
+        .iter()
+        .map(|(dep, kind)| {
+            json!({
+                "name": dep,
+                "pkg": format!("{} 1.0.0", dep),
+                "dep_kinds": [{"kind": kind, "target": null}],
+            })
+        })
+        .collect::<Vec<_>>();
+    json!({"id": format!("{} 1.0.0", name), "deps": deps, "dependencies": []})
+}
+
+/// A fixture of the metadata of an app in a workspace with a local crate, depending on crates with
+/// license files, a proc macro, a build & a dev dependency.
+async fn setup_test_metadata() -> Result<(tempfile::TempDir, Metadata)> {
+    let tmpdir = tempfile::tempdir().context("error building tempdir for test")?;
+    let dir = tmpdir.path();
+    for (name, files) in [
+        ("app", &["LICENSE"][..]),
+        ("util", &[]),
+        ("serde", &["LICENSE-MIT", "LICENSE-APACHE", "README.md"]),
+        ("serde_derive", &["LICENSE"]),
+        ("itoa", &["license.txt"]),
+        ("legal", &["legal/COPYRIGHT"]),
+        ("cc", &["LICENSE"]),
+        ("tester", &["LICENSE"]),
+        ("unlicensed", &[]),
+    ] {
+        let package_dir = dir.join(name);
+        for file in files {
+            let file = package_dir.join(file);
+            tokio::fs::create_dir_all(file.parent().context("file without parent")?).await?;
+            tokio::fs::write(&file, format!("{} of {}", file.display(), name)).await?;
+        }
+        tokio::fs::create_dir_all(&package_dir).await?;
+    }
+    let metadata = json!({
+        "packages": [
+            package(dir, "app", "cdylib", None),
+            package(dir, "util", "lib", None),
+            package(dir, "serde", "lib", None),
+            package(dir, "serde_derive", "proc-macro", None),
+            package(dir, "itoa", "lib", None),
+            package(dir, "legal", "lib", Some("legal/COPYRIGHT")),
+            package(dir, "cc", "lib", None),
+            package(dir, "tester", "lib", None),
+            package(dir, "unlicensed", "lib", None),
+        ],
+        "workspace_members": ["app 1.0.0", "util 1.0.0"],
+        "resolve": {
+            "nodes": [
+                node("app", &[
+                    ("util", None),
+                    ("serde", None),
+                    ("cc", Some("build")),
+                    ("tester", Some("dev")),
+                ]),
+                node("util", &[("legal", None), ("unlicensed", None)]),
+                node("serde", &[("serde_derive", None), ("itoa", None)]),
+                node("serde_derive", &[("cc", None)]),
+                node("itoa", &[]),
+                node("legal", &[]),
+                node("cc", &[]),
+                node("tester", &[]),
+                node("unlicensed", &[]),
+            ],
+            "root": "app 1.0.0",
+        },
+        "workspace_root": dir,
+        "target_directory": dir.join("target"),
+        "version": 1,
+    });
+    let metadata = serde_json::from_value(metadata).context("error parsing test metadata")?;
+    Ok((tmpdir, metadata))
+}
+
+#[tokio::test]
+async fn licensed_packages_are_the_compiled_dependencies() -> Result<()> {
+    // Assemble.
+    let (tmpdir, metadata) = setup_test_metadata().await?;
+    let root = PackageId {
+        repr: "app 1.0.0".into(),
+    };
+
+    // Action.
+    let packages = licensed_packages(&metadata, &root).await?;
+
+    // Assert.
+    let names = packages
+        .iter()
+        .map(|package| package.name.as_str())
+        .collect::<Vec<_>>();
+    ensure!(
+        names == ["itoa", "legal", "serde", "unlicensed"],
+        "unexpected packages {:?}",
+        names
+    );
+    let files = |name: &str| -> Vec<String> {
+        packages
+            .iter()
+            .filter(|package| package.name == name)
+            .flat_map(|package| package.license_texts.iter().map(|text| text.file.clone()))
+            .collect()
+    };
+    ensure!(
+        files("serde") == ["LICENSE-APACHE", "LICENSE-MIT"],
+        "unexpected license files of serde {:?}",
+        files("serde")
+    );
+    ensure!(
+        files("itoa") == ["license.txt"] && files("legal") == ["COPYRIGHT"],
+        "unexpected license files of itoa {:?} or legal {:?}",
+        files("itoa"),
+        files("legal")
+    );
+    ensure!(
+        files("unlicensed").is_empty(),
+        "unexpected license files of unlicensed {:?}",
+        files("unlicensed")
+    );
+    let itoa = tmpdir.path().join("itoa").join("license.txt");
+    let text = &packages[0].license_texts[0].text;
+    ensure!(
+        text == &format!("{} of itoa", itoa.display()),
+        "unexpected license text {:?}",
+        text
+    );
+    Ok(())
+}
+
+#[tokio::test]
+async fn err_invalid_format() -> Result<()> {
+    // Assemble.
+    let tmpdir = tempfile::tempdir().context("error building tempdir for test")?;
+    let cfg = Arc::new(RtcBuild::new_test(tmpdir.path()).await?);
+    let attrs = HashMap::from([("data-format".to_owned(), "yaml".to_owned())]);
+
+    // Action.
+    let result = RustLicenses::new(cfg, Arc::new(PathBuf::from(tmpdir.path())), attrs, 0).await;
+
+    // Assert.
+    ensure!(result.is_err(), "expected an error for an invalid format");
+    Ok(())
+}
@@ -0,0 +1,224 @@
+use std::path::Path;
+use std::sync::Arc;
+
+use anyhow::{ensure, Context, Result};
+use nipper::Document;
+
+use crate::config::RtcBuild;
+use crate::pipelines::rust::*;
+use crate::pipelines::{integrity_hash, Attrs};
+
+#[tokio::test]
+async fn wasm_opt_level_falls_back_to_config_then_release_mode() -> Result<()> {
+    // Assemble.
+    let tmpdir = tempfile::tempdir().context("error building tempdir for test")?;
+    let mut cfg = RtcBuild::new_test(tmpdir.path()).await?;
+
+    // Action & Assert.
+    ensure!(wasm_opt_level(None, &cfg)? == WasmOptLevel::Off);
+    cfg.release = true;
+    ensure!(wasm_opt_level(None, &cfg)? == WasmOptLevel::Default);
+    cfg.wasm_opt = Some(WasmOptLevel::Z);
+    ensure!(
+        wasm_opt_level(None, &cfg)? == WasmOptLevel::Z,
+        "the configured level must override the release default"
+    );
+    ensure!(
+        wasm_opt_level(Some("0"), &cfg)? == WasmOptLevel::Off,
+        "the attr must override the configured level"
+    );
+    ensure!(wasm_opt_level(Some("fast"), &cfg).is_err());
+    Ok(())
+}
+
+/// Write a crate named `app` with the binaries `a` & `b` to the given dir.
+fn write_crate(dir: &Path) -> Result<()> {
+    std::fs::create_dir_all(dir.join("src").join("bin"))?;
+    std::fs::write(
+        dir.join("Cargo.toml"),
+        "[package]\nname = \"app\"\nversion = \"0.1.0\"\nedition = \"2021\"\n",
+    )?;
+    for bin in ["a", "b"] {
+        std::fs::write(
+            dir.join("src").join("bin").join(format!("{}.rs", bin)),
+            "fn main() {}",
+        )?;
+    }
+    Ok(())
+}
+
+/// The output of a Rust app of the given name, built from the link with the given ID.
+fn output(cfg: &Arc<RtcBuild>, id: usize, name: &str) -> RustAppOutput {
+    RustAppOutput {
+        cfg: cfg.clone(),
+        id: Some(id),
+        js_output: format!("{}-1234.js", name),
+        wasm_output: format!("{}-1234_bg.wasm", name),
+        ts_output: None,
+        loader_shim_output: None,
+        type_: RustAppType::Main,
+        name: name.into(),
+        worker_glue: false,
+    }
+}
+
+#[tokio::test]
+async fn apps_are_named_by_target_name_or_bin() -> Result<()> {
+    // Assemble.
+    let tmpdir = tempfile::tempdir().context("error building tempdir for test")?;
+    let dir = tmpdir.path().canonicalize()?;
+    write_crate(&dir)?;
+    let cfg = Arc::new(RtcBuild::new_test(&dir).await?);
+    let html_dir = Arc::new(dir.clone());
+    let attrs = |attrs: &[(&str, &str)]| -> Attrs {
+        attrs
+            .iter()
+            .map(|(name, value)| (name.to_string(), value.to_string()))
+            .collect()
+    };
+
+    // Action.
+    let mut names = Vec::new();
+    for (id, attrs) in [
+        attrs(&[]),
+        attrs(&[("data-bin", "a")]),
+        attrs(&[("data-bin", "b"), ("data-target-name", "admin")]),
+    ]
+    .into_iter()
+    .enumerate()
+    {
+        let app = RustApp::new(cfg.clone(), html_dir.clone(), None, attrs, id).await?;
+        names.push(app.name().to_owned());
+    }
+
+    // Assert.
+    ensure!(
+        names == ["app", "a", "admin"],
+        "unexpected names {:?}",
+        names
+    );
+    Ok(())
+}
+
+#[tokio::test]
+async fn finalizes_each_app_of_a_page() -> Result<()> {
+    // Assemble.
+    let tmpdir = tempfile::tempdir().context("error building tempdir for test")?;
+    let cfg = Arc::new(RtcBuild::new_test(tmpdir.path()).await?);
+    let mut dom = Document::from(
+        r#"<html><head></head><body><link data-trunk-id="0"><link data-trunk-id="1"></body></html>"#,
+    );
+
+    // Action.
+    output(&cfg, 0, "app").finalize(&mut dom).await?;
+    output(&cfg, 1, "admin").finalize(&mut dom).await?;
+
+    // Assert.
+    let html = dom.html().to_string();
+    for name in ["app", "admin"] {
+        ensure!(
+            html.contains(&format!(
+                "import init from '/{name}-1234.js';init('/{name}-1234_bg.wasm');"
+            )) && html.contains(&format!(
+                r#"<link rel="modulepreload" href="/{name}-1234.js">"#
+            )),
+            "expected the script & preloads of {:?}: {}",
+            name,
+            html
+        );
+    }
+    ensure!(
+        !html.contains("data-trunk-id"),
+        "expected the links to be replaced: {}",
+        html
+    );
+    Ok(())
+}
+
+#[tokio::test]
+async fn injects_a_script_spawning_hashed_workers() -> Result<()> {
+    // Assemble.
+    let tmpdir = tempfile::tempdir().context("error building tempdir for test")?;
+    let cfg = Arc::new(RtcBuild::new_test(tmpdir.path()).await?);
+    let mut dom = Document::from(
+        r#"<html><head></head><body><link data-trunk-id="0"><link data-trunk-id="1"></body></html>"#,
+    );
+    let worker = |id, worker_glue| RustAppOutput {
+        type_: RustAppType::Worker,
+        loader_shim_output: Some("worker-1234_loader.js".into()),
+        worker_glue,
+        ..output(&cfg, id, "worker")
+    };
+
+    // Action.
+    worker(0, true).finalize(&mut dom).await?;
+    worker(1, false).finalize(&mut dom).await?;
+
+    // Assert.
+    let html = dom.html().to_string();
+    ensure!(
+        html.contains(
+            r#"<script>(window.trunkWorkers=window.trunkWorkers||{})["worker"]=(options)=>new Worker('/worker-1234_loader.js',options);</script>"#
+        ),
+        "expected a script spawning the hashed worker: {}",
+        html
+    );
+    ensure!(
+        html.matches("<script>").count() == 1 && !html.contains("data-trunk-id"),
+        "expected the link of the worker without glue to be removed: {}",
+        html
+    );
+    ensure!(
+        !html.contains("modulepreload"),
+        "workers must not be preloaded: {}",
+        html
+    );
+    Ok(())
+}
+
+#[tokio::test]
+async fn adds_integrity_hashes_to_the_app_tags() -> Result<()> {
+    // Assemble.
+    let tmpdir = tempfile::tempdir().context("error building tempdir for test")?;
+    let mut cfg = RtcBuild::new_test(tmpdir.path()).await?;
+    cfg.sri = true;
+    let cfg = Arc::new(cfg);
+    tokio::fs::write(
+        cfg.staging_dist.join("app-1234.js"),
+        "export default () => {};",
+    )
+    .await?;
+    tokio::fs::write(cfg.staging_dist.join("app-1234_bg.wasm"), b"\0asm").await?;
+    let js_integrity = integrity_hash(b"export default () => {};");
+    let wasm_integrity = integrity_hash(b"\0asm");
+    let mut dom =
+        Document::from(r#"<html><head></head><body><link data-trunk-id="0"></body></html>"#);
+
+    // Action.
+    output(&cfg, 0, "app").finalize(&mut dom).await?;
+
+    // Assert.
+    let html = dom.html().to_string();
+    for expected in [
+        format!(
+            r#"<link rel="preload" href="/app-1234_bg.wasm" as="fetch" type="application/wasm" crossorigin="" integrity="{}">"#,
+            wasm_integrity
+        ),
+        format!(
+            r#"<link rel="modulepreload" href="/app-1234.js" integrity="{}" crossorigin="anonymous">"#,
+            js_integrity
+        ),
+        format!(
+            "init(fetch('/app-1234_bg.wasm',{{integrity:'{}'}}));",
+            wasm_integrity
+        ),
+    ] {
+        ensure!(
+            html.contains(&expected),
+            "expected {} in: {}",
+            expected,
+            html
+        );
+    }
+    Ok(())
+}
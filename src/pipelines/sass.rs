@@ -1,17 +1,23 @@
 //! Sass/Scss asset pipeline.
 
-use std::path::PathBuf;
+use std::collections::HashSet;
+use std::path::{Path, PathBuf};
 use std::sync::Arc;
 
-use anyhow::{Context, Result};
+use anyhow::{bail, Context, Result};
 use nipper::Document;
+use once_cell::sync::Lazy;
+use regex::Regex;
 use tokio::fs;
 use tokio::task::JoinHandle;
 
-use super::{AssetFile, Attrs, TrunkAssetPipelineOutput, ATTR_HREF, ATTR_INLINE};
-use crate::common;
-use crate::config::RtcBuild;
+use super::cache::InputDigest;
+use super::{
+    postcss, AssetFile, Attrs, TrunkAssetPipelineOutput, ATTR_HREF, ATTR_INLINE, ATTR_POSTCSS,
+};
+use crate::config::{ConfigOptsSass, RtcBuild, SassStyle};
 use crate::tools::{self, Application};
+use crate::{common, hooks};
 
 /// A sass/scss asset pipeline.
 pub struct Sass {
@@ -23,8 +29,39 @@ pub struct Sass {
     asset: AssetFile,
     /// If the specified SASS/SCSS file should be inlined.
     use_inline: bool,
+    /// If the compiled CSS should be processed with PostCSS.
+    postcss: bool,
+    /// The dir of the source HTML file, in which the PostCSS config is looked up.
+    html_dir: Arc<PathBuf>,
+    /// The options of the SASS/SCSS compiler.
+    options: ConfigOptsSass,
 }
 
+/// The attribute of additional load paths, separated by commas.
+const ATTR_LOAD_PATHS: &str = "data-load-paths";
+/// The attribute to silence the deprecation warnings of dependencies.
+const ATTR_QUIET_DEPS: &str = "data-quiet-deps";
+/// The attribute to embed a source map.
+const ATTR_SOURCE_MAP: &str = "data-source-map";
+/// The attribute of the output style.
+const ATTR_STYLE: &str = "data-style";
+
+/// The rules of sass/scss files loading other files, capturing the quoted URLs of the files.
+static LOAD_RULE: Lazy<Regex> = Lazy::new(|| {
+    Regex::new(r#"@(?:use|forward|import)\s+((?:["'][^"'\n]+["']\s*,?\s*)+)"#)
+        .expect("error compiling sass load rule regex")
+});
+/// A quoted URL of a load rule.
+static QUOTED_URL: Lazy<Regex> =
+    Lazy::new(|| Regex::new(r#"["']([^"'\n]+)["']"#).expect("error compiling quoted URL regex"));
+/// Any rule or function loading other files, including the ones with URLs which `LOAD_RULE` does
+/// not capture, such as the unquoted ones of the indented syntax & `meta.load-css`. Plain CSS
+/// imports of `url(...)` are not loaded by the compiler.
+static ANY_LOAD: Lazy<Regex> = Lazy::new(|| {
+    Regex::new(r"@(?:use|forward|import)\b(?:\s+url\()?|load-css\s*\(")
+        .expect("error compiling sass load regex")
+});
+
 impl Sass {
     pub const TYPE_SASS: &'static str = "sass";
     pub const TYPE_SCSS: &'static str = "scss";
@@ -42,12 +79,40 @@ impl Sass {
         let mut path = PathBuf::new();
         path.extend(href_attr.split('/'));
         let asset = AssetFile::new(&html_dir, path).await?;
-        let use_inline = attrs.get(ATTR_INLINE).is_some();
+        let use_inline = attrs.contains_key(ATTR_INLINE);
+        let postcss = cfg.postcss || attrs.contains_key(ATTR_POSTCSS);
+
+        // Override the configured compiler options with those of the attributes.
+        let mut options = cfg.sass.clone();
+        if let Some(load_paths) = attrs.get(ATTR_LOAD_PATHS) {
+            options.load_paths.extend(
+                load_paths
+                    .split(',')
+                    .map(str::trim)
+                    .filter(|path| !path.is_empty())
+                    .map(|path| html_dir.join(path)),
+            );
+        }
+        options.quiet_deps |= attrs.contains_key(ATTR_QUIET_DEPS);
+        options.source_map |= attrs.contains_key(ATTR_SOURCE_MAP);
+        if let Some(style) = attrs.get(ATTR_STYLE) {
+            options.style = Some(match style.as_str() {
+                "expanded" => SassStyle::Expanded,
+                "compressed" => SassStyle::Compressed,
+                _ => bail!(
+                    r#"invalid `data-style` {:?} of <link data-trunk rel="sass|scss" .../>, expected "expanded" or "compressed""#,
+                    style
+                ),
+            });
+        }
         Ok(Self {
             id,
             cfg,
             asset,
             use_inline,
+            postcss,
+            html_dir,
+            options,
         })
     }
 
@@ -62,24 +127,90 @@ impl Sass {
     async fn run(self) -> Result<TrunkAssetPipelineOutput> {
         // tracing::info!("downloading sass");
         let version = self.cfg.tools.sass.as_deref();
-        let sass = tools::get(Application::Sass, version).await?;
+        let sass = tools::get(Application::Sass, version, &self.cfg.tools).await?;
 
         // Compile the target SASS/SCSS file.
-        let style = if self.cfg.release {
-            "compressed"
-        } else {
-            "expanded"
+        let style = match self.options.style {
+            Some(SassStyle::Expanded) => "expanded",
+            Some(SassStyle::Compressed) => "compressed",
+            None if self.cfg.minify => "compressed",
+            None => "expanded",
         };
         let path_str = dunce::simplified(&self.asset.path).display().to_string();
         let file_name = format!("{}.css", &self.asset.file_stem.to_string_lossy());
         let file_path = dunce::simplified(&self.cfg.staging_dist.join(&file_name))
             .display()
             .to_string();
-        let args = &["--no-source-map", "-s", style, &path_str, &file_path];
+        let mut args = vec!["-s".to_owned(), style.to_owned()];
+        if self.options.source_map {
+            // Embed the source map, which is thereby hashed along with the CSS.
+            args.extend([
+                "--embed-source-map".to_owned(),
+                "--embed-sources".to_owned(),
+            ]);
+        } else {
+            args.push("--no-source-map".to_owned());
+        }
+        if self.options.quiet_deps {
+            args.push("--quiet-deps".to_owned());
+        }
+        for load_path in &self.options.load_paths {
+            args.push(format!(
+                "--load-path={}",
+                dunce::simplified(load_path).display()
+            ));
+        }
+        args.extend([path_str, file_path.clone()]);
 
+        // Compile the file, unless neither the compiler & its args nor the loaded files changed.
+        // The load paths of `SASS_PATH` are used by the compiler after the ones of the args.
         let rel_path = crate::common::strip_prefix(&self.asset.path);
-        tracing::info!(path = ?rel_path, "compiling sass/scss");
-        common::run_command(Application::Sass.name(), &sass, args).await?;
+        let mut digest = InputDigest::new("sass");
+        digest.add(sass.to_string_lossy().as_bytes());
+        for arg in &args {
+            digest.add(arg);
+        }
+        let mut load_paths = self.options.load_paths.clone();
+        if let Some(sass_path) = std::env::var_os("SASS_PATH") {
+            digest.add(sass_path.as_encoded_bytes());
+            load_paths.extend(std::env::split_paths(&sass_path));
+        }
+        let loaded = loaded_files(&self.asset.path, &load_paths).await?;
+        if let Some(loaded) = &loaded {
+            for file in loaded {
+                digest.add_file(file).await?;
+            }
+        }
+        let compile = || async {
+            tracing::info!(path = ?rel_path, "compiling sass/scss");
+            common::run_command(Application::Sass.name(), &sass, &args).await?;
+            let css = fs::read(&file_path)
+                .await
+                .context("error reading compiled sass/scss")?;
+            Ok(vec![css])
+        };
+        let outputs = match loaded {
+            Some(_) => {
+                let label = format!("sass/scss compilation of {:?}", rel_path);
+                self.cfg
+                    .pipeline_cache
+                    .get_or_run(&label, &digest, compile)
+                    .await?
+            }
+            // Without all of the loaded files, it can not be told whether the CSS is stale.
+            None => {
+                tracing::debug!(path = ?rel_path, "not all loaded files are known, not caching");
+                Arc::new(compile().await?)
+            }
+        };
+        fs::write(&file_path, &outputs[0])
+            .await
+            .context("error writing compiled sass/scss")?;
+
+        if self.postcss {
+            postcss::process(&self.html_dir, file_path.as_ref(), file_path.as_ref()).await?;
+        }
+        hooks::run_pipeline_hooks(&self.cfg, "sass", &self.asset.path, file_path.as_ref()).await?;
 
         let css = fs::read_to_string(&file_path).await?;
         fs::remove_file(&file_path).await?;
@@ -92,17 +223,26 @@ impl Sass {
             // Hash the contents to generate a file name, and then write the contents to the dist
             // dir.
             let hash = seahash::hash(css.as_bytes());
-            let file_name = self
-                .cfg
-                .filehash
-                .then(|| format!("{}-{:x}.css", &self.asset.file_stem.to_string_lossy(), hash))
-                .unwrap_or(file_name);
-            let file_path = self.cfg.staging_dist.join(&file_name);
+            let file_name = format!(
+                "{}.css",
+                self.cfg
+                    .hashed_name(&self.asset.file_stem.to_string_lossy(), hash)
+            );
+            let file_path = self.cfg.staging_path(&file_name);
+            if let Some(parent) = file_path.parent() {
+                fs::create_dir_all(parent)
+                    .await
+                    .context("error creating SASS pipeline output dir")?;
+            }
 
             // Write the generated CSS to the filesystem.
-            fs::write(&file_path, css)
+            fs::write(&file_path, &css)
                 .await
                 .context("error writing SASS pipeline output")?;
+            self.cfg
+                .output_hashes
+                .record(&file_path, css.as_bytes())
+                .await;
 
             // Generate a hashed reference to the new CSS file.
             CssRef::File(file_name)
@@ -117,6 +257,102 @@ impl Sass {
     }
 }
 
+/// The given canonical sass/scss file & the files it loads, transitively, relative to the loading
+/// file or in the load paths. Built-in modules & plain CSS imports of remote URLs are skipped.
+///
+/// As the outputs of incomplete files would be reused although a file they load changed, `None`
+/// is returned if a loaded file can not be found, e.g. as it is loaded from a package, or if a
+/// file loads files in ways whose URLs can not be found, such as unquoted imports.
+pub(super) async fn loaded_files(
+    path: &Path,
+    load_paths: &[PathBuf],
+) -> Result<Option<Vec<PathBuf>>> {
+    let mut files = vec![path.to_owned()];
+    let mut seen = files.iter().cloned().collect::<HashSet<_>>();
+    let mut next = 0;
+    while let Some(file) = files.get(next).cloned() {
+        next += 1;
+        let source = fs::read_to_string(&file)
+            .await
+            .with_context(|| format!("error reading sass/scss file {:?}", file))?;
+        let rules = LOAD_RULE.captures_iter(&source).collect::<Vec<_>>();
+        let starts = rules
+            .iter()
+            .filter_map(|rule| rule.get(0).map(|rule| rule.start()))
+            .collect::<HashSet<_>>();
+        let unseen = ANY_LOAD
+            .find_iter(&source)
+            .any(|load| !starts.contains(&load.start()) && !load.as_str().ends_with("url("));
+        if unseen {
+            tracing::debug!("{:?} loads files which can not be found", file);
+            return Ok(None);
+        }
+        let dir = file.parent().unwrap_or_else(|| Path::new(""));
+        let urls = rules.iter().flat_map(|rule| {
+            let urls = rule.get(1).map_or("", |urls| urls.as_str());
+            QUOTED_URL
+                .captures_iter(urls)
+                .filter_map(|url| url.get(1).map(|url| url.as_str()))
+        });
+        for url in urls {
+            if url.starts_with("sass:") || url.contains("//") {
+                continue;
+            }
+            let Some(loaded) = resolve_load(url, dir, load_paths) else {
+                tracing::debug!("the file {:?} loaded by {:?} can not be found", url, file);
+                return Ok(None);
+            };
+            if seen.insert(loaded.clone()) {
+                files.push(loaded);
+            }
+        }
+    }
+    Ok(Some(files))
+}
+
+/// Resolve the given URL of a load rule of a file in the given dir, as the sass compiler does,
+/// i.e. to a file with or without a `_` prefix & one of the sass/scss/css extensions, or to the
+/// index file of a dir. The `pkg:` & `~` URLs of packages are not resolved.
+fn resolve_load(url: &str, dir: &Path, load_paths: &[PathBuf]) -> Option<PathBuf> {
+    if url.starts_with("pkg:") || url.starts_with('~') {
+        return None;
+    }
+    let url = Path::new(url);
+    let name = url.file_name()?.to_str()?;
+    let has_ext = [".scss", ".sass", ".css"]
+        .iter()
+        .any(|ext| name.ends_with(ext));
+    std::iter::once(dir)
+        .chain(load_paths.iter().map(PathBuf::as_path))
+        .find_map(|base| {
+            let path = base.join(url);
+            let parent = path.parent()?;
+            let mut candidates = Vec::new();
+            if has_ext {
+                candidates.extend([path.clone(), parent.join(format!("_{}", name))]);
+            } else {
+                for ext in ["scss", "sass", "css"] {
+                    candidates.extend([
+                        parent.join(format!("{}.{}", name, ext)),
+                        parent.join(format!("_{}.{}", name, ext)),
+                    ]);
+                }
+                for ext in ["scss", "sass", "css"] {
+                    candidates.extend([
+                        path.join(format!("index.{}", ext)),
+                        path.join(format!("_index.{}", ext)),
+                    ]);
+                }
+            }
+            // Canonicalize the file, so that cyclic loads via `..` are found to be seen already.
+            candidates
+                .into_iter()
+                .find(|candidate| candidate.is_file())?
+                .canonicalize()
+                .ok()
+        })
+}
+
 /// The output of a sass/scss build pipeline.
 pub struct SassOutput {
     /// The runtime build config.
@@ -142,8 +378,9 @@ impl SassOutput {
             CssRef::Inline(css) => format!(r#"<style type="text/css">{}</style>"#, css),
             // Link to the CSS file.
             CssRef::File(file) => {
+                let integrity = super::integrity_attrs(&self.cfg, &file).await?;
                 format!(
-                    r#"<link rel="stylesheet" href="{base}{file}"/>"#,
+                    r#"<link rel="stylesheet" href="{base}{file}"{integrity}/>"#,
                     base = &self.cfg.public_url,
                 )
             }
@@ -0,0 +1,87 @@
+use anyhow::{Context, Result};
+
+use crate::pipelines::sass::*;
+
+#[tokio::test]
+async fn loaded_files_are_found_transitively() -> Result<()> {
+    // Assemble.
+    let tmpdir = tempfile::tempdir().context("error building tempdir for test")?;
+    let dir = &tmpdir
+        .path()
+        .canonicalize()
+        .context("error taking canonical tempdir")?;
+    let files = [
+        (
+            "main.scss",
+            "@use 'sass:math';\n@use \"theme\" as t;\n@import 'vendor/reset', \
+             \"//cdn.test/a.css\";\n@import url(b.css);\n",
+        ),
+        ("_theme.scss", "@forward 'colors/index';\n"),
+        ("colors/_index.scss", "@use '../theme';\n$red: #f00;\n"),
+        ("lib/vendor/reset.css", "* { margin: 0 }"),
+        ("unused.scss", ""),
+    ];
+    for (path, contents) in files {
+        let path = dir.join(path);
+        std::fs::create_dir_all(path.parent().context("file without parent")?)?;
+        std::fs::write(path, contents)?;
+    }
+
+    // Action.
+    let loaded = loaded_files(&dir.join("main.scss"), &[dir.join("lib")]).await?;
+
+    // Assert.
+    let loaded = loaded.context("expected all loaded files to be found")?;
+    let loaded = loaded
+        .iter()
+        .map(|path| path.strip_prefix(dir).map(|path| path.to_string_lossy()))
+        .collect::<Result<Vec<_>, _>>()?;
+    assert_eq!(
+        loaded,
+        [
+            "main.scss",
+            "_theme.scss",
+            "lib/vendor/reset.css",
+            "colors/_index.scss"
+        ]
+    );
+    Ok(())
+}
+
+#[tokio::test]
+async fn loaded_files_are_unknown_unless_all_are_found() -> Result<()> {
+    // Assemble.
+    let tmpdir = tempfile::tempdir().context("error building tempdir for test")?;
+    let dir = &tmpdir
+        .path()
+        .canonicalize()
+        .context("error taking canonical tempdir")?;
+    std::fs::write(dir.join("_theme.scss"), "")?;
+    let sources = [
+        ("absent.scss", "@use 'theme';\n@import 'missing';\n"),
+        ("unquoted.sass", "@use 'theme'\n@import theme\n"),
+        ("package.scss", "@use 'pkg:bootstrap';\n"),
+        (
+            "node_modules.scss",
+            "@import '~bootstrap/scss/bootstrap';\n",
+        ),
+        (
+            "load_css.scss",
+            "@use 'sass:meta';\n.a { @include meta.load-css('theme'); }\n",
+        ),
+        ("nested.scss", "@use 'missing';\n"),
+    ];
+    for (path, contents) in sources {
+        std::fs::write(dir.join(path), contents)?;
+    }
+    std::fs::write(dir.join("main.scss"), "@use 'nested';\n")?;
+
+    for (path, _) in sources.iter().chain([&("main.scss", "")]) {
+        // Action.
+        let loaded = loaded_files(&dir.join(path), &[]).await?;
+
+        // Assert.
+        assert_eq!(loaded, None, "the loaded files of {} must be unknown", path);
+    }
+    Ok(())
+}
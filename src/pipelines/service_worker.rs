@@ -0,0 +1,103 @@
+//! Generation of a service worker, which precaches the build artifacts.
+
+use std::collections::HashMap;
+
+use anyhow::{Context, Result};
+use tokio::fs;
+
+use super::csp::CSP_MANIFEST;
+use crate::asset_manifest::ASSET_MANIFEST_FILE;
+use crate::config::RtcBuild;
+
+/// The name of the generated service worker in the dist dir.
+pub const SERVICE_WORKER_FILE: &str = "service-worker.js";
+/// The size in bytes above which outputs are not precached, so that installing the service worker
+/// does not download huge media files.
+pub const PRECACHE_MAX_SIZE: u64 = 16 * 1024 * 1024;
+/// The template of the generated service worker.
+const SERVICE_WORKER_SCRIPT: &str = include_str!("../service_worker.js");
+
+/// Create the script which registers the service worker.
+///
+/// When serving with autoreload, the script unregisters the service workers of previous builds
+/// instead, so that stale precached artifacts do not break autoreload.
+pub fn registration_script(cfg: &RtcBuild) -> String {
+    if cfg.autoreload_ws_path.is_some() {
+        r#"<script>if ('serviceWorker' in navigator) { navigator.serviceWorker.getRegistrations().then((registrations) => registrations.forEach((registration) => registration.unregister())); }</script>"#.to_owned()
+    } else {
+        format!(
+            r#"<script>if ('serviceWorker' in navigator) {{ window.addEventListener('load', () => navigator.serviceWorker.register('{}{}')); }}</script>"#,
+            cfg.public_url, SERVICE_WORKER_FILE
+        )
+    }
+}
+
+/// Write the service worker to the staging dist dir, precaching the given files, which are keyed
+/// by their path relative to the staging dist dir, returning the hash of the service worker.
+///
+/// The build metadata, the files matching the `service_worker_exclude` globs & files larger than
+/// [`PRECACHE_MAX_SIZE`] are not precached. The cache is named after the hash of the precached
+/// files' contents, so that a new build replaces the cache of the previous one.
+pub async fn write_service_worker(cfg: &RtcBuild, files: &HashMap<String, u64>) -> Result<u64> {
+    let mut precached = Vec::new();
+    for (path, hash) in files {
+        if is_metadata(path) || cfg.service_worker_exclude.is_match(path) {
+            continue;
+        }
+        let size = fs::metadata(cfg.staging_path(path))
+            .await
+            .with_context(|| format!("error reading metadata of {:?}", path))?
+            .len();
+        if size > PRECACHE_MAX_SIZE {
+            tracing::warn!(
+                path,
+                size,
+                "not precaching output larger than {} bytes in the service worker",
+                PRECACHE_MAX_SIZE
+            );
+            continue;
+        }
+        precached.push((path, hash));
+    }
+    precached.sort();
+
+    let urls = precached
+        .iter()
+        .map(|(path, _)| format!("{}{}", cfg.public_url, path))
+        .collect::<Vec<_>>();
+    let cache_hash = seahash::hash(
+        precached
+            .iter()
+            .map(|(path, hash)| format!("{}:{:x}", path, hash))
+            .collect::<Vec<_>>()
+            .join("\n")
+            .as_bytes(),
+    );
+    let script = SERVICE_WORKER_SCRIPT
+        .replace("{{__TRUNK_CACHE__}}", &format!("trunk-{:x}", cache_hash))
+        .replace(
+            "{{__TRUNK_PRECACHE__}}",
+            &serde_json::to_string(&urls).context("error serializing precached files")?,
+        )
+        .replace(
+            "{{__TRUNK_INDEX__}}",
+            &format!("{}index.html", cfg.public_url),
+        );
+
+    let path = cfg.staging_dist.join(SERVICE_WORKER_FILE);
+    fs::write(&path, &script)
+        .await
+        .context("error writing service worker")?;
+    cfg.output_hashes.record(&path, script.as_bytes()).await;
+    Ok(seahash::hash(script.as_bytes()))
+}
+
+/// Check if the given output describes the build, rather than being fetched by the app itself,
+/// such as the CSP manifests of the pages, e.g. `admin.csp.json`.
+fn is_metadata(path: &str) -> bool {
+    let file_name = path.rsplit('/').next().unwrap_or(path);
+    path == SERVICE_WORKER_FILE
+        || path == ASSET_MANIFEST_FILE
+        || file_name == CSP_MANIFEST
+        || file_name.ends_with(&format!(".{}", CSP_MANIFEST))
+}
@@ -0,0 +1,101 @@
+use std::collections::HashMap;
+
+use anyhow::{ensure, Context, Result};
+use globset::{Glob, GlobSetBuilder};
+
+use crate::config::RtcBuild;
+use crate::pipelines::service_worker::*;
+
+#[tokio::test]
+async fn write_service_worker_precaches_files() -> Result<()> {
+    // Assemble.
+    let tmpdir = tempfile::tempdir().context("error building tempdir for test")?;
+    let mut cfg = RtcBuild::new_test(tmpdir.path()).await?;
+    cfg.public_url = "/app/".into();
+    let files = write_files(
+        &cfg,
+        &[
+            ("index.html", 1),
+            ("app-1234.js", 2),
+            (SERVICE_WORKER_FILE, 3),
+        ],
+    )?;
+
+    // Action.
+    let hash = write_service_worker(&cfg, &files).await?;
+
+    // Assert.
+    let script = tokio::fs::read_to_string(cfg.staging_dist.join(SERVICE_WORKER_FILE))
+        .await
+        .context("error reading service worker")?;
+    ensure!(
+        script.contains(r#"var PRECACHE = ["/app/app-1234.js","/app/index.html"];"#),
+        "unexpected precached files in {}",
+        script
+    );
+    ensure!(script.contains("var INDEX = '/app/index.html';"));
+    ensure!(
+        !script.contains("{{__TRUNK_"),
+        "unreplaced placeholder in {}",
+        script
+    );
+    ensure!(hash == seahash::hash(script.as_bytes()));
+    Ok(())
+}
+
+#[tokio::test]
+async fn write_service_worker_skips_metadata_excluded_and_large_files() -> Result<()> {
+    // Assemble.
+    let tmpdir = tempfile::tempdir().context("error building tempdir for test")?;
+    let mut cfg = RtcBuild::new_test(tmpdir.path()).await?;
+    cfg.service_worker_exclude = GlobSetBuilder::new().add(Glob::new("videos/**")?).build()?;
+    let files = write_files(
+        &cfg,
+        &[
+            ("app-1234.js", 1),
+            ("csp.json", 1),
+            ("admin.csp.json", 1),
+            ("de/csp.json", 1),
+            ("asset-manifest.json", 1),
+            ("videos/intro.webm", 1),
+            ("huge.bin", PRECACHE_MAX_SIZE + 1),
+        ],
+    )?;
+
+    // Action.
+    write_service_worker(&cfg, &files).await?;
+
+    // Assert.
+    let script = tokio::fs::read_to_string(cfg.staging_dist.join(SERVICE_WORKER_FILE))
+        .await
+        .context("error reading service worker")?;
+    ensure!(
+        script.contains(r#"var PRECACHE = ["/app-1234.js"];"#),
+        "unexpected precached files in {}",
+        script
+    );
+    Ok(())
+}
+
+/// Write files of the given sizes to the staging dist dir, returning their hashes.
+fn write_files(cfg: &RtcBuild, files: &[(&str, u64)]) -> Result<HashMap<String, u64>> {
+    let mut hashes = HashMap::new();
+    for (path, size) in files {
+        let file = cfg.staging_path(path);
+        std::fs::create_dir_all(file.parent().context("file without parent")?)?;
+        std::fs::File::create(&file)?.set_len(*size)?;
+        hashes.insert((*path).to_owned(), *size);
+    }
+    Ok(hashes)
+}
+
+#[tokio::test]
+async fn registration_script_unregisters_with_autoreload() -> Result<()> {
+    let tmpdir = tempfile::tempdir().context("error building tempdir for test")?;
+    let mut cfg = RtcBuild::new_test(tmpdir.path()).await?;
+    ensure!(registration_script(&cfg).contains("unregister()"));
+
+    cfg.autoreload_ws_path = None;
+    ensure!(registration_script(&cfg).contains("register('/service-worker.js')"));
+    Ok(())
+}
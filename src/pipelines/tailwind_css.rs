@@ -1,6 +1,6 @@
 //! Tailwind CSS asset pipeline.
 
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
 use std::sync::Arc;
 
 use anyhow::{Context, Result};
@@ -8,9 +8,12 @@ use nipper::Document;
 use tokio::fs;
 use tokio::task::JoinHandle;
 
-use super::{AssetFile, Attrs, TrunkAssetPipelineOutput, ATTR_HREF, ATTR_INLINE};
-use crate::common;
+use super::{
+    postcss, AssetFile, Attrs, TrunkAssetPipelineOutput, ATTR_HREF, ATTR_INLINE, ATTR_POSTCSS,
+};
+use crate::common::{self, path_exists};
 use crate::config::RtcBuild;
+use crate::hooks;
 use crate::tools::{self, Application};
 
 /// A tailwind css asset pipeline.
@@ -23,8 +26,21 @@ pub struct TailwindCss {
     asset: AssetFile,
     /// If the specified tailwind css file should be inlined.
     use_inline: bool,
+    /// If the compiled CSS should be processed with PostCSS.
+    postcss: bool,
+    /// The dir of the source HTML file, in which the tailwind & PostCSS configs and the content
+    /// are looked up.
+    html_dir: Arc<PathBuf>,
 }
 
+/// The file names of tailwind configs, which are looked up in the dir of the source HTML file.
+const CONFIG_FILE_NAMES: &[&str] = &[
+    "tailwind.config.js",
+    "tailwind.config.cjs",
+    "tailwind.config.mjs",
+    "tailwind.config.ts",
+];
+
 impl TailwindCss {
     pub const TYPE_TAILWIND_CSS: &'static str = "tailwind-css";
 
@@ -41,12 +57,15 @@ impl TailwindCss {
         let mut path = PathBuf::new();
         path.extend(href_attr.split('/'));
         let asset = AssetFile::new(&html_dir, path).await?;
-        let use_inline = attrs.get(ATTR_INLINE).is_some();
+        let use_inline = attrs.contains_key(ATTR_INLINE);
+        let postcss = cfg.postcss || attrs.contains_key(ATTR_POSTCSS);
         Ok(Self {
             id,
             cfg,
             asset,
             use_inline,
+            postcss,
+            html_dir,
         })
     }
 
@@ -60,20 +79,52 @@ impl TailwindCss {
     #[tracing::instrument(level = "trace", skip(self))]
     async fn run(self) -> Result<TrunkAssetPipelineOutput> {
         let version = self.cfg.tools.tailwindcss.as_deref();
-        let tailwind = tools::get(Application::TailwindCss, version).await?;
+        let tailwind = tools::get(Application::TailwindCss, version, &self.cfg.tools).await?;
 
         // Compile the target tailwind css file.
-        let style = if self.cfg.release { "--minify" } else { "" };
         let path_str = dunce::simplified(&self.asset.path).display().to_string();
         let file_name = format!("{}.css", &self.asset.file_stem.to_string_lossy());
         let file_path = dunce::simplified(&self.cfg.staging_dist.join(&file_name))
             .display()
             .to_string();
-        let args = &["--input", &path_str, "--output", &file_path, style];
+        let mut args = vec!["--input", &path_str, "--output", &file_path];
+        if self.cfg.minify {
+            args.push("--minify");
+        }
+
+        // Use the tailwind config next to the source HTML file, as the tailwind CLI only looks it
+        // up in the cwd, or else scan the HTML files & the crate's sources for used classes.
+        let mut config = None;
+        for name in CONFIG_FILE_NAMES {
+            let path = self.html_dir.join(name);
+            if path_exists(&path).await? {
+                config = Some(dunce::simplified(&path).display().to_string());
+                break;
+            }
+        }
+        let content;
+        match &config {
+            Some(config) => args.extend(["--config", config]),
+            None => {
+                content = content_globs(&self.html_dir);
+                args.extend(["--content", &content]);
+            }
+        }
 
         let rel_path = crate::common::strip_prefix(&self.asset.path);
         tracing::info!(path = ?rel_path, "compiling tailwind css");
-        common::run_command(Application::TailwindCss.name(), &tailwind, args).await?;
+        common::run_command(Application::TailwindCss.name(), &tailwind, &args).await?;
+
+        if self.postcss {
+            postcss::process(&self.html_dir, file_path.as_ref(), file_path.as_ref()).await?;
+        }
+        hooks::run_pipeline_hooks(
+            &self.cfg,
+            "tailwind-css",
+            &self.asset.path,
+            file_path.as_ref(),
+        )
+        .await?;
 
         let css = fs::read_to_string(&file_path).await?;
         fs::remove_file(&file_path).await?;
@@ -86,17 +137,26 @@ impl TailwindCss {
             // Hash the contents to generate a file name, and then write the contents to the dist
             // dir.
             let hash = seahash::hash(css.as_bytes());
-            let file_name = self
-                .cfg
-                .filehash
-                .then(|| format!("{}-{:x}.css", &self.asset.file_stem.to_string_lossy(), hash))
-                .unwrap_or(file_name);
-            let file_path = self.cfg.staging_dist.join(&file_name);
+            let file_name = format!(
+                "{}.css",
+                self.cfg
+                    .hashed_name(&self.asset.file_stem.to_string_lossy(), hash)
+            );
+            let file_path = self.cfg.staging_path(&file_name);
+            if let Some(parent) = file_path.parent() {
+                fs::create_dir_all(parent)
+                    .await
+                    .context("error creating tailwind css pipeline output dir")?;
+            }
 
             // Write the generated CSS to the filesystem.
-            fs::write(&file_path, css)
+            fs::write(&file_path, &css)
                 .await
                 .context("error writing tailwind css pipeline output")?;
+            self.cfg
+                .output_hashes
+                .record(&file_path, css.as_bytes())
+                .await;
 
             // Generate a hashed reference to the new CSS file.
             CssRef::File(file_name)
@@ -111,6 +171,18 @@ impl TailwindCss {
     }
 }
 
+/// The comma-separated globs of the files in which the tailwind CLI scans for used classes if there
+/// is no tailwind config, which are the HTML files & the Rust sources of the given dir.
+pub(super) fn content_globs(html_dir: &Path) -> String {
+    let html_dir = dunce::simplified(html_dir);
+    [
+        html_dir.join("*.html"),
+        html_dir.join("src").join("**").join("*.rs"),
+    ]
+    .map(|glob| glob.display().to_string())
+    .join(",")
+}
+
 /// The output of a Tailwind CSS build pipeline.
 pub struct TailwindCssOutput {
     /// The runtime build config.
@@ -136,8 +208,9 @@ impl TailwindCssOutput {
             CssRef::Inline(css) => format!(r#"<style type="text/css">{}</style>"#, css),
             // Link to the CSS file.
             CssRef::File(file) => {
+                let integrity = super::integrity_attrs(&self.cfg, &file).await?;
                 format!(
-                    r#"<link rel="stylesheet" href="{base}{file}"/>"#,
+                    r#"<link rel="stylesheet" href="{base}{file}"{integrity}/>"#,
                     base = &self.cfg.public_url,
                 )
             }
@@ -0,0 +1,21 @@
+use std::path::Path;
+
+use crate::pipelines::tailwind_css::*;
+
+#[cfg(not(target_family = "windows"))]
+#[test]
+fn content_globs_are_joined_to_the_html_dir() {
+    assert_eq!(
+        content_globs(Path::new("/app")),
+        "/app/*.html,/app/src/**/*.rs"
+    );
+}
+
+#[cfg(target_family = "windows")]
+#[test]
+fn content_globs_are_joined_to_the_html_dir() {
+    assert_eq!(
+        content_globs(Path::new(r"C:\app")),
+        r"C:\app\*.html,C:\app\src\**\*.rs"
+    );
+}
@@ -0,0 +1,141 @@
+//! TypeScript asset pipeline.
+
+use std::path::PathBuf;
+use std::sync::Arc;
+
+use anyhow::{Context, Result};
+use nipper::Document;
+use tokio::fs;
+use tokio::task::JoinHandle;
+
+use super::esbuild::{self, Format};
+use super::{AssetFile, Attrs, TrunkAssetPipelineOutput, ATTR_BUNDLE, ATTR_HREF};
+use crate::config::RtcBuild;
+use crate::hooks;
+
+/// A TypeScript asset pipeline.
+pub struct TypeScript {
+    /// The ID of this pipeline's source HTML element.
+    id: usize,
+    /// Runtime build config.
+    cfg: Arc<RtcBuild>,
+    /// The asset file being processed.
+    asset: AssetFile,
+    /// If the imports of the file should be bundled into the output.
+    bundle: bool,
+    /// The module specifier to map the output with in the import map, if any.
+    import: Option<String>,
+}
+
+impl TypeScript {
+    pub const TYPE_TYPESCRIPT: &'static str = "typescript";
+
+    pub async fn new(
+        cfg: Arc<RtcBuild>,
+        html_dir: Arc<PathBuf>,
+        attrs: Attrs,
+        id: usize,
+    ) -> Result<Self> {
+        // Build the path to the target asset.
+        let href_attr = attrs.get(ATTR_HREF).context(
+            r#"required attr `href` missing for <link data-trunk rel="typescript" .../> element"#,
+        )?;
+        let mut path = PathBuf::new();
+        path.extend(href_attr.split('/'));
+        let asset = AssetFile::new(&html_dir, path).await?;
+        let bundle = cfg.bundle || attrs.contains_key(ATTR_BUNDLE);
+        let import = super::import_specifier(&attrs, &asset);
+        Ok(Self {
+            id,
+            cfg,
+            asset,
+            bundle,
+            import,
+        })
+    }
+
+    /// Spawn the pipeline for this asset type.
+    #[tracing::instrument(level = "trace", skip(self))]
+    pub fn spawn(self) -> JoinHandle<Result<TrunkAssetPipelineOutput>> {
+        tokio::spawn(self.run())
+    }
+
+    /// Run this pipeline.
+    #[tracing::instrument(level = "trace", skip(self))]
+    async fn run(self) -> Result<TrunkAssetPipelineOutput> {
+        // Transpile the target TypeScript file to a JS module, without type checking it.
+        let rel_path = crate::common::strip_prefix(&self.asset.path);
+        tracing::info!(path = ?rel_path, "compiling typescript");
+        let file_name = format!("{}.js", &self.asset.file_stem.to_string_lossy());
+        let js = esbuild::build(
+            &self.cfg,
+            &self.asset.path,
+            &format!(".esbuild-{}", file_name),
+            Format::Esm,
+            self.bundle,
+        )
+        .await?;
+        let js = hooks::rewrite_with_pipeline_hooks(
+            &self.cfg,
+            Self::TYPE_TYPESCRIPT,
+            &self.asset.path,
+            &file_name,
+            js,
+        )
+        .await?;
+
+        // Hash the contents to generate a file name, and then write the contents to the dist dir.
+        let file_name = if self.cfg.filehash {
+            format!(
+                "{}.js",
+                self.cfg
+                    .hashed_name(&self.asset.file_stem.to_string_lossy(), seahash::hash(&js))
+            )
+        } else {
+            file_name
+        };
+        let file_path = self.cfg.staging_path(&file_name);
+        if let Some(parent) = file_path.parent() {
+            fs::create_dir_all(parent)
+                .await
+                .context("error creating typescript pipeline output dir")?;
+        }
+        fs::write(&file_path, &js)
+            .await
+            .context("error writing typescript pipeline output")?;
+        self.cfg.output_hashes.record(&file_path, &js).await;
+
+        tracing::info!(path = ?rel_path, "finished compiling typescript");
+        Ok(TrunkAssetPipelineOutput::TypeScript(TypeScriptOutput {
+            cfg: self.cfg.clone(),
+            id: self.id,
+            file: file_name,
+            import: self.import,
+        }))
+    }
+}
+
+/// The output of a TypeScript build pipeline.
+pub struct TypeScriptOutput {
+    /// The runtime build config.
+    pub cfg: Arc<RtcBuild>,
+    /// The ID of this pipeline.
+    pub id: usize,
+    /// Name of the finalized output file.
+    pub file: String,
+    /// The module specifier to map the output file with in the import map, if any.
+    pub import: Option<String>,
+}
+
+impl TypeScriptOutput {
+    pub async fn finalize(self, dom: &mut Document) -> Result<()> {
+        let integrity = super::integrity_attrs(&self.cfg, &self.file).await?;
+        dom.select(&super::trunk_id_selector(self.id))
+            .replace_with_html(format!(
+                r#"<script type="module" src="{base}{file}"{integrity}></script>"#,
+                base = &self.cfg.public_url,
+                file = self.file,
+            ));
+        Ok(())
+    }
+}
@@ -1,31 +1,268 @@
+use std::collections::hash_map::RandomState;
+use std::hash::{BuildHasher, Hasher};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
 use std::sync::Arc;
+use std::time::{Duration, Instant};
 
 use anyhow::Context;
 use axum::body::Body;
 use axum::extract::ws::{Message as MsgAxm, WebSocket, WebSocketUpgrade};
-use axum::extract::State;
-use axum::http::{Request, Response, Uri};
+use axum::extract::{OriginalUri, State};
+use axum::http::request::Parts;
+use axum::http::{Request, Response, StatusCode, Uri, Version};
+use axum::response::{IntoResponse, Response as AxumResponse};
 use axum::routing::{any, get, Router};
 use axum::RequestExt;
 use futures_util::sink::SinkExt;
 use futures_util::stream::StreamExt;
-use reqwest::header::HeaderValue;
+use hyper::client::HttpConnector;
+use regex::Regex;
+use reqwest::header::{HeaderMap, HeaderValue, CONTENT_TYPE, HOST, LOCATION, SET_COOKIE, UPGRADE};
+use tokio::net::TcpStream;
 use tokio_tungstenite::connect_async;
 use tokio_tungstenite::tungstenite::protocol::CloseFrame;
 use tokio_tungstenite::tungstenite::Message as MsgTng;
 use tower_http::trace::TraceLayer;
 
+use crate::config::{ConfigOptsProxyRetry, LoadBalancing, ProxyLog};
 use crate::serve::ServerResult;
 
+mod grpc_web;
+
+/// The interval at which to probe a backend, while waiting for it to become reachable.
+const BACKEND_PROBE_INTERVAL: Duration = Duration::from_millis(100);
+/// The maximum number of bytes of each body chunk which is logged.
+const LOG_BODY_LIMIT: usize = 4096;
+
+/// Options used to build the client of an HTTP proxy.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Hash)]
+pub(crate) struct ProxyClientOptions {
+    /// Accept invalid certificates from the backend.
+    pub insecure: bool,
+    /// Send requests to the backend using HTTP/2 instead of HTTP/1.
+    pub http2: bool,
+    /// An optional timeout in seconds to connect to the backend.
+    pub connect_timeout: Option<u64>,
+    /// Pass redirects through to the browser, instead of following them, so that their `Location`
+    /// headers may be rewritten to target the proxy.
+    pub rewrite_location: bool,
+    /// Send native gRPC requests to the backend using HTTP/2 over plain TCP, translated from the
+    /// gRPC-web requests received from browsers.
+    pub grpc_web: bool,
+}
+
+impl ProxyClientOptions {
+    /// Build a new client with these options.
+    pub fn build(self) -> anyhow::Result<ProxyClient> {
+        if self.grpc_web {
+            let mut connector = HttpConnector::new();
+            connector.set_connect_timeout(self.connect_timeout.map(Duration::from_secs));
+            let client = hyper::Client::builder().http2_only(true).build(connector);
+            return Ok(ProxyClient::Grpc(client));
+        }
+
+        let mut builder = reqwest::ClientBuilder::new();
+        if self.rewrite_location {
+            builder = builder.redirect(reqwest::redirect::Policy::none());
+        }
+        builder = if self.http2 {
+            builder.http2_prior_knowledge()
+        } else {
+            builder.http1_only()
+        };
+        if self.insecure {
+            builder = builder.danger_accept_invalid_certs(true);
+        }
+        if let Some(connect_timeout) = self.connect_timeout {
+            builder = builder.connect_timeout(Duration::from_secs(connect_timeout));
+        }
+        builder
+            .build()
+            .map(ProxyClient::Http)
+            .context("error building proxy client")
+    }
+}
+
+/// The client used to send requests to a proxy backend.
+#[derive(Clone, Debug)]
+pub(crate) enum ProxyClient {
+    /// A client sending requests to an HTTP backend.
+    Http(reqwest::Client),
+    /// A client sending native gRPC requests to an HTTP/2 backend, which preserves the trailers of
+    /// its responses.
+    Grpc(hyper::Client<HttpConnector>),
+    /// A client sending requests to an HTTP backend listening on a Unix domain socket.
+    #[cfg(unix)]
+    Unix {
+        /// The path of the socket to connect to.
+        socket: PathBuf,
+        /// The client used for all requests.
+        client: hyper::Client<hyperlocal::UnixConnector>,
+    },
+}
+
+impl ProxyClient {
+    /// Build a new client for a backend listening on the given Unix domain socket.
+    #[cfg(unix)]
+    pub fn unix(socket: PathBuf) -> Self {
+        let client = hyper::Client::builder().build(hyperlocal::UnixConnector);
+        Self::Unix { socket, client }
+    }
+
+    /// Send the given request to the backend, streaming the body of the response.
+    async fn send(&self, mut req: Request<Body>) -> anyhow::Result<Response<Body>> {
+        // The version is negotiated by the client, regardless of the version of the request.
+        *req.version_mut() = Version::default();
+        match self {
+            Self::Http(client) => {
+                let req = reqwest::Request::try_from(req)
+                    .context("error building outbound request to proxy backend")?;
+                let backend_res = client.execute(req).await?;
+                let mut res = Response::builder().status(backend_res.status());
+                for (key, val) in backend_res.headers() {
+                    res = res.header(key, val);
+                }
+                res.body(Body::wrap_stream(backend_res.bytes_stream()))
+                    .context("error building proxy response")
+            }
+            Self::Grpc(client) => client.request(req).await.map_err(Into::into),
+            #[cfg(unix)]
+            Self::Unix { socket, client } => {
+                let path = req
+                    .uri()
+                    .path_and_query()
+                    .map_or("/", |path_and_query| path_and_query.as_str());
+                *req.uri_mut() = hyperlocal::Uri::new(socket, path).into();
+                client.request(req).await.map_err(Into::into)
+            }
+        }
+    }
+
+    /// Check if a connection to the backend can be established.
+    async fn probe(&self, backend: &Uri) -> bool {
+        match self {
+            Self::Http(_) | Self::Grpc(_) => {
+                let Some(authority) = backend.authority() else {
+                    return false;
+                };
+                let port = authority.port_u16().unwrap_or_else(|| {
+                    if backend.scheme_str() == Some("https") {
+                        443
+                    } else {
+                        80
+                    }
+                });
+                TcpStream::connect((authority.host(), port)).await.is_ok()
+            }
+            #[cfg(unix)]
+            Self::Unix { socket, .. } => tokio::net::UnixStream::connect(socket).await.is_ok(),
+        }
+    }
+}
+
+/// Check if the given error was caused by failing to connect to the backend.
+fn is_connect_error(err: &anyhow::Error) -> bool {
+    err.downcast_ref::<reqwest::Error>()
+        .is_some_and(reqwest::Error::is_connect)
+        || err
+            .downcast_ref::<hyper::Error>()
+            .is_some_and(hyper::Error::is_connect)
+}
+
+/// Get the path of the Unix domain socket of the given backend, if it is one.
+///
+/// Unix domain socket backends are parsed as `unix://localhost/path/to/socket`.
+pub(crate) fn unix_socket_path(backend: &Uri) -> Option<PathBuf> {
+    (backend.scheme_str() == Some("unix")).then(|| PathBuf::from(backend.path()))
+}
+
 /// A handler used for proxying HTTP requests to a backend.
 pub(crate) struct ProxyHandlerHttp {
     /// The client to use for proxy logic.
-    client: reqwest::Client,
-    /// The URL of the backend to which requests are to be proxied.
-    backend: Uri,
+    client: ProxyClient,
+    /// The backends to which requests are to be proxied, of which there is at least one.
+    backends: Vec<ProxyBackend>,
+    /// The index of the next backend to select for round-robin load balancing.
+    next_backend: AtomicUsize,
     /// An optional rewrite path to be used as the listening URI prefix, but which will be
     /// stripped before being sent to the proxy backend.
     rewrite: Option<String>,
+    /// An optional regex based rewrite of the paths of requests sent to the backend.
+    path_rewrite: Option<PathRewrite>,
+    /// The options applied to all requests proxied by this handler.
+    options: ProxyHandlerOptions,
+}
+
+/// A backend of an HTTP proxy.
+struct ProxyBackend {
+    /// The URL of the backend to which requests are to be proxied.
+    uri: Uri,
+    /// Whether the backend has been reachable so far.
+    reachable: AtomicBool,
+    /// The handler to which WebSocket upgrade requests are handed off.
+    ws: Arc<ProxyHandlerWebSocket>,
+}
+
+/// Options applied by an HTTP proxy handler to all requests which it proxies.
+#[derive(Clone, Debug, Default)]
+pub(crate) struct ProxyHandlerOptions {
+    /// The strategy used to select the backend of each request.
+    pub load_balancing: LoadBalancing,
+    /// Translate gRPC-web requests into native gRPC requests, and their responses back.
+    pub grpc_web: bool,
+    /// An optional policy for retrying requests which failed to connect to the backend.
+    pub retry: Option<RetryPolicy>,
+    /// An optional duration to wait for the response of the backend, after which requests fail.
+    pub request_timeout: Option<Duration>,
+    /// The host header to send to the backend.
+    pub host: HostHeader,
+    /// Log the requests & responses of this proxy, if enabled.
+    pub log: Option<ProxyLog>,
+    /// An optional directory of fixtures to respond with, while the backend is unreachable.
+    pub mock_dir: Option<PathBuf>,
+    /// Rewrite the `Location` headers of responses redirecting to the backend to target the proxy.
+    pub rewrite_location: bool,
+    /// Rewrite the `Set-Cookie` headers of responses to set cookies for the proxy.
+    pub rewrite_cookies: bool,
+    /// Additional headers to set on all requests sent to the backend.
+    pub request_headers: HeaderMap,
+    /// Additional headers to set on all responses received from the backend.
+    pub response_headers: HeaderMap,
+}
+
+/// The host header which an HTTP proxy sends to the backend.
+#[derive(Clone, Debug, Default)]
+pub(crate) enum HostHeader {
+    /// Set the host of the backend.
+    #[default]
+    Backend,
+    /// Preserve the host header of the received request.
+    Preserve,
+    /// Set the given host.
+    Override(HeaderValue),
+}
+
+/// A policy for retrying requests which failed to connect to the backend.
+#[derive(Clone, Debug)]
+pub(crate) struct RetryPolicy {
+    /// The maximum number of attempts to send a request to the backend.
+    pub attempts: u32,
+    /// The backoff before the first retry, which doubles with each retry.
+    pub backoff: Duration,
+    /// The duration to wait for the backend to become reachable, before sending requests to a
+    /// backend which has not been reachable so far.
+    pub wait: Duration,
+}
+
+impl From<&ConfigOptsProxyRetry> for RetryPolicy {
+    fn from(opts: &ConfigOptsProxyRetry) -> Self {
+        Self {
+            attempts: opts.attempts.unwrap_or(3).max(1),
+            backoff: Duration::from_millis(opts.backoff.unwrap_or(250)),
+            wait: Duration::from_secs(opts.wait.unwrap_or(0)),
+        }
+    }
 }
 
 fn make_outbound_uri(backend: &Uri, request: &Uri) -> anyhow::Result<Uri> {
@@ -49,8 +286,31 @@ fn make_outbound_uri(backend: &Uri, request: &Uri) -> anyhow::Result<Uri> {
         segments[4] = "?";
         segments[5] = query;
     }
-    let path_and_query = segments.join("");
+    build_outbound_uri(backend, segments.join(""))
+}
 
+/// Build the URI to which the given request is to be proxied, using the regex based rewrite if the
+/// original path of the request matches it.
+fn outbound_uri_for<B>(
+    backend: &Uri,
+    path_rewrite: Option<&PathRewrite>,
+    req: &Request<B>,
+) -> anyhow::Result<Uri> {
+    if let Some(path_rewrite) = path_rewrite {
+        let original_uri = req
+            .extensions()
+            .get::<OriginalUri>()
+            .map(|original_uri| &original_uri.0)
+            .unwrap_or_else(|| req.uri());
+        if let Some(outbound_uri) = path_rewrite.outbound_uri(backend, original_uri) {
+            return outbound_uri;
+        }
+    }
+    make_outbound_uri(backend, req.uri())
+}
+
+/// Build the URI of the given path & query on the given backend.
+fn build_outbound_uri(backend: &Uri, path_and_query: String) -> anyhow::Result<Uri> {
     // Construct the outbound URI & build a new request to be sent to the proxy backend.
     Uri::builder()
         .scheme(backend.scheme_str().unwrap_or_default())
@@ -65,13 +325,222 @@ fn make_outbound_uri(backend: &Uri, request: &Uri) -> anyhow::Result<Uri> {
         .context("error building proxy request to backend")
 }
 
+/// Map the given path on the backend to the corresponding path which the proxy listens at, if the
+/// path is below the path of the backend.
+fn proxy_path_for(backend: &Uri, proxy_path: &str, path: &str) -> Option<String> {
+    let backend_path = backend.path().trim_end_matches('/');
+    let rest = path.strip_prefix(backend_path)?;
+    if !rest.is_empty() && !rest.starts_with('/') {
+        return None;
+    }
+    let proxy_path = proxy_path.trim_end_matches('/');
+    Some(match rest {
+        "" if proxy_path.is_empty() => "/".into(),
+        rest => format!("{proxy_path}{rest}"),
+    })
+}
+
+/// Rewrite the given `Location` header of a backend response, which redirects to the backend, to
+/// redirect to the proxy instead.
+///
+/// Redirects to other origins, as well as relative redirects, are preserved.
+fn rewrite_location(location: &str, backend: &Uri, proxy_path: &str) -> Option<String> {
+    let location = location.parse::<Uri>().ok()?;
+    if location.authority().is_some()
+        && (location.scheme() != backend.scheme() || location.authority() != backend.authority())
+    {
+        return None;
+    }
+    if !location.path().starts_with('/') {
+        return None;
+    }
+    let mut rewritten = proxy_path_for(backend, proxy_path, location.path())?;
+    if let Some(query) = location.query() {
+        rewritten.push('?');
+        rewritten.push_str(query);
+    }
+    Some(rewritten)
+}
+
+/// Rewrite the given `Set-Cookie` header of a backend response, so that the cookie is set for the
+/// proxy instead of the backend.
+///
+/// The `Domain` attribute is removed, so that the cookie applies to the host of the proxy, and the
+/// `Path` attribute is mapped to the path which the proxy listens at.
+fn rewrite_set_cookie(cookie: &str, backend: &Uri, proxy_path: &str) -> String {
+    let mut attributes = cookie.split(';').map(str::trim);
+    let mut rewritten = attributes.next().unwrap_or_default().to_owned();
+    for attribute in attributes {
+        let (name, value) = attribute.split_once('=').unwrap_or((attribute, ""));
+        let attribute = if name.eq_ignore_ascii_case("domain") {
+            continue;
+        } else if name.eq_ignore_ascii_case("path") {
+            match proxy_path_for(backend, proxy_path, value) {
+                Some(path) => format!("{name}={path}"),
+                None => attribute.to_owned(),
+            }
+        } else {
+            attribute.to_owned()
+        };
+        rewritten.push_str("; ");
+        rewritten.push_str(&attribute);
+    }
+    rewritten
+}
+
+/// Find the fixture in the given mock dir which matches the given request path.
+///
+/// The path itself, the path with a `.json` extension & an `index.json` file in the path are tried
+/// in that order. Paths which would escape the mock dir never match.
+async fn find_mock(mock_dir: &Path, path: &str) -> Option<PathBuf> {
+    let mut relative = PathBuf::new();
+    for segment in path.split('/').filter(|segment| !segment.is_empty()) {
+        if segment == "." || segment == ".." || segment.contains('\\') {
+            return None;
+        }
+        relative.push(segment);
+    }
+    let file = mock_dir.join(&relative);
+    let mut candidates = vec![file.join("index.json")];
+    if !relative.as_os_str().is_empty() {
+        candidates.insert(0, file.with_extension("json"));
+        candidates.insert(0, file);
+    }
+    for candidate in candidates {
+        if tokio::fs::metadata(&candidate)
+            .await
+            .is_ok_and(|metadata| metadata.is_file())
+        {
+            return Some(candidate);
+        }
+    }
+    None
+}
+
+/// Build a response from the fixture in the given mock dir which matches the given request path,
+/// if any.
+async fn mock_response(mock_dir: &Path, path: &str) -> Option<AxumResponse> {
+    let file = find_mock(mock_dir, path).await?;
+    let contents = match tokio::fs::read(&file).await {
+        Ok(contents) => contents,
+        Err(err) => {
+            tracing::error!(error = ?err, "error reading proxy mock {}", file.display());
+            return None;
+        }
+    };
+    let content_type = mime_guess::from_path(&file).first_or_octet_stream();
+    Some(([(CONTENT_TYPE, content_type.to_string())], contents).into_response())
+}
+
+/// Log the chunks of the given request or response body as they are streamed, truncating long
+/// chunks.
+fn log_body(body: Body, kind: &'static str, uri: Uri) -> Body {
+    Body::wrap_stream(body.inspect(move |chunk| {
+        if let Ok(chunk) = chunk {
+            let truncated = &chunk[..chunk.len().min(LOG_BODY_LIMIT)];
+            tracing::info!(
+                "proxy {} body {} ({} bytes): {}",
+                kind,
+                uri,
+                chunk.len(),
+                String::from_utf8_lossy(truncated)
+            );
+        }
+    }))
+}
+
+/// Check if the given request is a WebSocket upgrade request.
+fn is_websocket_upgrade<B>(req: &Request<B>) -> bool {
+    req.headers()
+        .get(UPGRADE)
+        .and_then(|upgrade| upgrade.to_str().ok())
+        .is_some_and(|upgrade| upgrade.eq_ignore_ascii_case("websocket"))
+}
+
+/// Convert the given HTTP backend URI into the corresponding WebSocket backend URI.
+fn websocket_backend(backend: &Uri) -> Uri {
+    let scheme = match backend.scheme_str() {
+        Some("https") => "wss",
+        Some("http") => "ws",
+        _ => return backend.clone(),
+    };
+    let mut parts = backend.clone().into_parts();
+    parts.scheme = scheme.parse().ok();
+    Uri::from_parts(parts).unwrap_or_else(|_| backend.clone())
+}
+
+/// A regex based rewrite of the paths of requests sent to a backend.
+#[derive(Clone, Debug)]
+pub(crate) struct PathRewrite {
+    /// The pattern matched against the full path of received requests.
+    pattern: Regex,
+    /// The replacement of matched paths, which may reference capture groups of the pattern.
+    replacement: String,
+}
+
+impl PathRewrite {
+    /// Construct a new instance.
+    pub fn new(pattern: &str, replacement: String) -> anyhow::Result<Self> {
+        let pattern = Regex::new(pattern)
+            .with_context(|| format!("invalid proxy path rewrite pattern {:?}", pattern))?;
+        Ok(Self {
+            pattern,
+            replacement,
+        })
+    }
+
+    /// Build the URI to be sent to the backend, if the path of the given request matches.
+    fn outbound_uri(&self, backend: &Uri, request: &Uri) -> Option<anyhow::Result<Uri>> {
+        if !self.pattern.is_match(request.path()) {
+            return None;
+        }
+        let mut path_and_query = self
+            .pattern
+            .replace(request.path(), self.replacement.as_str())
+            .into_owned();
+        if let Some(query) = request.query() {
+            path_and_query.push(if path_and_query.contains('?') {
+                '&'
+            } else {
+                '?'
+            });
+            path_and_query.push_str(query);
+        }
+        Some(build_outbound_uri(backend, path_and_query))
+    }
+}
+
 impl ProxyHandlerHttp {
     /// Construct a new instance.
-    pub fn new(client: reqwest::Client, backend: Uri, rewrite: Option<String>) -> Arc<Self> {
+    ///
+    /// Requests are balanced across the given backends, of which there must be at least one.
+    pub fn new(
+        client: ProxyClient,
+        backends: Vec<Uri>,
+        rewrite: Option<String>,
+        path_rewrite: Option<PathRewrite>,
+        options: ProxyHandlerOptions,
+    ) -> Arc<Self> {
+        assert!(!backends.is_empty(), "proxies require at least one backend");
+        let backends = backends
+            .into_iter()
+            .map(|uri| ProxyBackend {
+                ws: ProxyHandlerWebSocket::new(
+                    websocket_backend(&uri),
+                    rewrite.clone(),
+                    path_rewrite.clone(),
+                ),
+                uri,
+                reachable: AtomicBool::new(false),
+            })
+            .collect();
         Arc::new(Self {
             client,
-            backend,
+            backends,
+            next_backend: AtomicUsize::new(0),
             rewrite,
+            path_rewrite,
+            options,
         })
     }
 
@@ -86,49 +555,239 @@ impl ProxyHandlerHttp {
     }
 
     /// The path which this proxy backend listens at.
+    ///
+    /// Without a rewrite, this is the path of the first backend.
     pub fn path(&self) -> &str {
         self.rewrite
             .as_deref()
-            .unwrap_or_else(|| self.backend.path())
+            .unwrap_or_else(|| self.backends[0].uri.path())
     }
 
-    /// Proxy the given request to the target backend.
+    /// Select the backend to which the next request is to be proxied.
+    fn select_backend(&self) -> &ProxyBackend {
+        let index = match self.options.load_balancing {
+            LoadBalancing::RoundRobin => self.next_backend.fetch_add(1, Ordering::Relaxed),
+            LoadBalancing::Random => RandomState::new().build_hasher().finish() as usize,
+        };
+        &self.backends[index % self.backends.len()]
+    }
+
+    /// Proxy the given request to the selected backend.
+    ///
+    /// WebSocket upgrade requests are handed off to the WebSocket proxy for the same backend.
     #[tracing::instrument(level = "debug", skip(state, req))]
     async fn proxy_http_request(
         State(state): State<Arc<Self>>,
         req: Request<Body>,
-    ) -> ServerResult<Response<Body>> {
+    ) -> ServerResult<AxumResponse> {
+        let backend = state.select_backend();
+        if is_websocket_upgrade(&req) {
+            if !matches!(state.client, ProxyClient::Http(_) | ProxyClient::Grpc(_)) {
+                return Ok((
+                    StatusCode::NOT_IMPLEMENTED,
+                    "WebSockets can not be proxied to Unix domain socket backends",
+                )
+                    .into_response());
+            }
+            return Ok(backend.ws.clone().upgrade(req).await);
+        }
+
         // Construct the outbound URI & build a new request to be sent to the proxy backend.
-        let outbound_uri = make_outbound_uri(&state.backend, req.uri())?;
-        let mut outbound_req = state
-            .client
-            .request(req.method().clone(), outbound_uri.to_string())
-            .headers(req.headers().clone())
-            .body(req.into_body())
-            .build()
-            .context("error building outbound request to proxy backend")?;
+        let outbound_uri = outbound_uri_for(&backend.uri, state.path_rewrite.as_ref(), &req)?;
+        let path = req.uri().path().to_owned();
+        let (mut parts, body) = req.into_parts();
+        parts.uri = outbound_uri;
+        parts.extensions = Default::default();
+        let grpc_web = state
+            .options
+            .grpc_web
+            .then(|| grpc_web::Encoding::of(&parts))
+            .flatten();
+        let body = match grpc_web {
+            Some(encoding) => grpc_web::translate_request(&mut parts, encoding, body),
+            None => body,
+        };
 
-        // Ensure the host header is set to target the backend.
-        if let Some(host) = state.backend.authority().map(|authority| authority.host()) {
-            if let Ok(host) = HeaderValue::from_str(host) {
-                outbound_req.headers_mut().insert("host", host);
+        // Ensure the host header is set to target the backend, unless preserved or overridden.
+        match &state.options.host {
+            HostHeader::Backend => {
+                if let Some(host) = backend.uri.authority().map(|authority| authority.host()) {
+                    if let Ok(host) = HeaderValue::from_str(host) {
+                        parts.headers.insert(HOST, host);
+                    }
+                }
+            }
+            HostHeader::Preserve => {}
+            HostHeader::Override(host) => {
+                parts.headers.insert(HOST, host.clone());
             }
         }
+        parts.headers.extend(state.options.request_headers.clone());
+
+        // Log the request, if enabled.
+        let log = state.options.log;
+        let (method, uri) = (parts.method.clone(), parts.uri.clone());
+        if log >= Some(ProxyLog::Headers) {
+            tracing::info!(headers = ?parts.headers, "proxy request headers {} {}", method, uri);
+        }
+        let body = match log {
+            Some(ProxyLog::Body) => log_body(body, "request", uri.clone()),
+            _ => body,
+        };
 
         // Send the request & unpack the response.
-        let backend_res = state
-            .client
-            .execute(outbound_req)
+        let started = Instant::now();
+        let res = match state.options.request_timeout {
+            Some(timeout) => {
+                tokio::time::timeout(timeout, state.execute(backend, parts, body)).await
+            }
+            None => Ok(state.execute(backend, parts, body).await),
+        };
+        let mut res = match res {
+            Ok(Ok(res)) => res,
+            Err(_) => {
+                tracing::error!("timed out waiting for response of proxy backend");
+                return Ok((
+                    StatusCode::GATEWAY_TIMEOUT,
+                    "timed out waiting for response of backend",
+                )
+                    .into_response());
+            }
+            Ok(Err(err)) => {
+                if let Some(mock_dir) = state.options.mock_dir.as_ref() {
+                    if is_connect_error(&err) {
+                        if let Some(res) = mock_response(mock_dir, &path).await {
+                            tracing::warn!(
+                                "proxy backend unreachable, responding with mock {}",
+                                path
+                            );
+                            return Ok(res);
+                        }
+                    }
+                }
+                tracing::error!(error = ?err, "error proxying request to proxy backend");
+                return Ok(
+                    (StatusCode::BAD_GATEWAY, "error proxying request to backend").into_response(),
+                );
+            }
+        };
+        backend.reachable.store(true, Ordering::Relaxed);
+        if let Some(encoding) = grpc_web {
+            res = grpc_web::translate_response(res, encoding);
+        }
+        state.rewrite_response_headers(&backend.uri, res.headers_mut());
+        res.headers_mut()
+            .extend(state.options.response_headers.clone());
+
+        if log.is_some() {
+            tracing::info!(
+                "proxied {} {} -> {} in {:?}",
+                method,
+                uri,
+                res.status(),
+                started.elapsed()
+            );
+        }
+        if log >= Some(ProxyLog::Headers) {
+            tracing::info!(headers = ?res.headers(), "proxy response headers {} {}", method, uri);
+        }
+
+        // The body is streamed chunk by chunk, so server-sent events & long-polling are passed
+        // through.
+        if log == Some(ProxyLog::Body) {
+            res = res.map(|body| log_body(body, "response", uri));
+        }
+        Ok(res.map(axum::body::boxed))
+    }
+
+    /// Rewrite the redirects & cookies of the given backend response headers to target the proxy,
+    /// if enabled.
+    fn rewrite_response_headers(&self, backend: &Uri, headers: &mut HeaderMap) {
+        if self.options.rewrite_location {
+            let location = headers
+                .get(LOCATION)
+                .and_then(|location| location.to_str().ok())
+                .and_then(|location| rewrite_location(location, backend, self.path()))
+                .and_then(|location| HeaderValue::from_str(&location).ok());
+            if let Some(location) = location {
+                headers.insert(LOCATION, location);
+            }
+        }
+        if self.options.rewrite_cookies {
+            let cookies: Vec<_> = headers
+                .get_all(SET_COOKIE)
+                .iter()
+                .filter_map(|cookie| cookie.to_str().ok())
+                .filter_map(|cookie| {
+                    HeaderValue::from_str(&rewrite_set_cookie(cookie, backend, self.path())).ok()
+                })
+                .collect();
+            if !cookies.is_empty() {
+                headers.remove(SET_COOKIE);
+                for cookie in cookies {
+                    headers.append(SET_COOKIE, cookie);
+                }
+            }
+        }
+    }
+
+    /// Send the given request to the backend, retrying failed connections according to the
+    /// retry policy, if any.
+    async fn execute(
+        &self,
+        backend: &ProxyBackend,
+        parts: Parts,
+        body: Body,
+    ) -> anyhow::Result<Response<Body>> {
+        let Some(retry) = self.options.retry.as_ref() else {
+            return self.client.send(Request::from_parts(parts, body)).await;
+        };
+        if !backend.reachable.load(Ordering::Relaxed) && !retry.wait.is_zero() {
+            self.wait_for_backend(backend, retry.wait).await;
+        }
+
+        // Retried requests need to be sent again, so their body is buffered instead of streamed.
+        let body = hyper::body::to_bytes(body)
             .await
-            .context("error proxying request to proxy backend")?;
-        let mut res = Response::builder().status(backend_res.status());
-        for (key, val) in backend_res.headers() {
-            res = res.header(key, val);
+            .context("error reading request body")?;
+        let mut backoff = retry.backoff;
+        let mut attempt = 1;
+        loop {
+            let mut attempt_req = Request::new(Body::from(body.clone()));
+            *attempt_req.method_mut() = parts.method.clone();
+            *attempt_req.uri_mut() = parts.uri.clone();
+            *attempt_req.headers_mut() = parts.headers.clone();
+            match self.client.send(attempt_req).await {
+                Err(err) if is_connect_error(&err) && attempt < retry.attempts => {
+                    tracing::debug!(error = ?err, attempt, "retrying request to proxy backend");
+                    tokio::time::sleep(backoff).await;
+                    backoff *= 2;
+                    attempt += 1;
+                }
+                res => return res,
+            }
         }
+    }
 
-        Ok(res
-            .body(Body::wrap_stream(backend_res.bytes_stream()))
-            .context("error building proxy response")?)
+    /// Wait until a connection to the backend can be established, or the given duration elapsed.
+    async fn wait_for_backend(&self, backend: &ProxyBackend, wait: Duration) {
+        let deadline = tokio::time::Instant::now() + wait;
+        tracing::info!(
+            "waiting for proxy backend {} to become reachable",
+            backend.uri
+        );
+        while tokio::time::Instant::now() < deadline {
+            if self.client.probe(&backend.uri).await {
+                backend.reachable.store(true, Ordering::Relaxed);
+                return;
+            }
+            tokio::time::sleep(BACKEND_PROBE_INTERVAL).await;
+        }
+        tracing::warn!(
+            "proxy backend {} did not become reachable within {:?}",
+            backend.uri,
+            wait
+        );
     }
 }
 
@@ -139,12 +798,22 @@ pub struct ProxyHandlerWebSocket {
     /// An optional rewrite path to be used as the listening URI prefix, but which will be
     /// stripped before being sent to the proxy backend.
     rewrite: Option<String>,
+    /// An optional regex based rewrite of the paths of requests sent to the backend.
+    path_rewrite: Option<PathRewrite>,
 }
 
 impl ProxyHandlerWebSocket {
     /// Construct a new instance.
-    pub fn new(backend: Uri, rewrite: Option<String>) -> Arc<Self> {
-        Arc::new(Self { backend, rewrite })
+    pub fn new(
+        backend: Uri,
+        rewrite: Option<String>,
+        path_rewrite: Option<PathRewrite>,
+    ) -> Arc<Self> {
+        Arc::new(Self {
+            backend,
+            rewrite,
+            path_rewrite,
+        })
     }
 
     /// Build the sub-router for this proxy.
@@ -152,18 +821,22 @@ impl ProxyHandlerWebSocket {
         let proxy = self.clone();
         router.nest_service(
             self.path(),
-            get(|req: Request<Body>| async move {
-                let uri = req.uri().clone();
-                let ws = req.extract::<WebSocketUpgrade, _>().await;
-                ws.map(|e| {
-                    e.on_upgrade(|socket| async move {
-                        proxy.clone().proxy_ws_request(socket, uri).await
-                    })
-                })
-            }),
+            get(|req: Request<Body>| async move { proxy.upgrade(req).await }),
         )
     }
 
+    /// Upgrade the given request to a WebSocket, which is then proxied to the target backend.
+    async fn upgrade(self: Arc<Self>, req: Request<Body>) -> AxumResponse {
+        let uri = req.uri().clone();
+        let outbound_uri = outbound_uri_for(&self.backend, self.path_rewrite.as_ref(), &req);
+        match req.extract::<WebSocketUpgrade, _>().await {
+            Ok(ws) => ws.on_upgrade(|socket| async move {
+                self.proxy_ws_request(socket, uri, outbound_uri).await
+            }),
+            Err(rejection) => rejection.into_response(),
+        }
+    }
+
     /// The path which this proxy backend listens at.
     pub fn path(&self) -> &str {
         self.rewrite
@@ -172,12 +845,17 @@ impl ProxyHandlerWebSocket {
     }
 
     /// Proxy the given WebSocket request to the target backend.
-    #[tracing::instrument(level = "debug", skip(self, ws))]
-    async fn proxy_ws_request(self: Arc<Self>, ws: WebSocket, request_uri: Uri) {
+    #[tracing::instrument(level = "debug", skip(self, ws, outbound_uri))]
+    async fn proxy_ws_request(
+        self: Arc<Self>,
+        ws: WebSocket,
+        request_uri: Uri,
+        outbound_uri: anyhow::Result<Uri>,
+    ) {
         tracing::debug!("new websocket connection");
 
         // Build where request will be forwarded
-        let outbound_uri = match make_outbound_uri(&self.backend, &request_uri) {
+        let outbound_uri = match outbound_uri {
             Ok(outbound_uri) => outbound_uri,
             Err(err) => {
                 tracing::error!(error = ?err, "failed to build proxy uri from {:?}", &request_uri);
@@ -255,7 +933,168 @@ impl ProxyHandlerWebSocket {
 mod tests {
     use axum::http::Uri;
 
-    use crate::proxy::make_outbound_uri;
+    use crate::proxy::{
+        find_mock, make_outbound_uri, rewrite_location, rewrite_set_cookie, websocket_backend,
+        PathRewrite, ProxyClient, ProxyHandlerHttp,
+    };
+
+    #[tokio::test]
+    async fn find_mock_by_path() {
+        let mock_dir = tempfile::tempdir().expect("error creating temp dir");
+        std::fs::create_dir(mock_dir.path().join("users")).expect("error creating dir");
+        std::fs::write(mock_dir.path().join("users.json"), "[]").expect("error writing mock");
+        std::fs::write(mock_dir.path().join("users").join("index.json"), "[]")
+            .expect("error writing mock");
+        std::fs::write(mock_dir.path().join("index.json"), "{}").expect("error writing mock");
+
+        assert_eq!(
+            find_mock(mock_dir.path(), "/users").await,
+            Some(mock_dir.path().join("users.json"))
+        );
+        assert_eq!(
+            find_mock(mock_dir.path(), "/users/").await,
+            Some(mock_dir.path().join("users.json"))
+        );
+        assert_eq!(
+            find_mock(mock_dir.path(), "/").await,
+            Some(mock_dir.path().join("index.json"))
+        );
+        assert_eq!(
+            find_mock(mock_dir.path(), "/users/../index.json").await,
+            None
+        );
+        assert_eq!(find_mock(mock_dir.path(), "/missing").await, None);
+    }
+
+    #[test]
+    fn rewrite_location_to_proxy() {
+        let backend = Uri::from_static("http://localhost:9000/auth/");
+        assert_eq!(
+            rewrite_location(
+                "http://localhost:9000/auth/callback?code=1",
+                &backend,
+                "/api/"
+            ),
+            Some("/api/callback?code=1".into())
+        );
+        assert_eq!(
+            rewrite_location("/auth/login", &backend, "/api/"),
+            Some("/api/login".into())
+        );
+        assert_eq!(rewrite_location("/other", &backend, "/api/"), None);
+        assert_eq!(
+            rewrite_location("https://accounts.example.com/auth/", &backend, "/api/"),
+            None
+        );
+        assert_eq!(rewrite_location("callback", &backend, "/api/"), None);
+    }
+
+    #[test]
+    fn rewrite_set_cookie_to_proxy() {
+        let backend = Uri::from_static("http://localhost:9000/");
+        assert_eq!(
+            rewrite_set_cookie(
+                "session=abc; Domain=localhost; Path=/; HttpOnly",
+                &backend,
+                "/api/"
+            ),
+            "session=abc; Path=/api/; HttpOnly"
+        );
+        assert_eq!(
+            rewrite_set_cookie("session=abc; path=/account", &backend, "/api"),
+            "session=abc; path=/api/account"
+        );
+    }
+
+    #[test]
+    fn select_backend_round_robin() {
+        let handler = ProxyHandlerHttp::new(
+            ProxyClient::Http(reqwest::Client::new()),
+            vec![
+                Uri::from_static("http://localhost:9001/"),
+                Uri::from_static("http://localhost:9002/"),
+            ],
+            None,
+            None,
+            Default::default(),
+        );
+        let selected: Vec<_> = (0..4)
+            .map(|_| handler.select_backend().uri.to_string())
+            .collect();
+        assert_eq!(
+            selected,
+            [
+                "http://localhost:9001/",
+                "http://localhost:9002/",
+                "http://localhost:9001/",
+                "http://localhost:9002/"
+            ]
+        );
+    }
+
+    #[test]
+    fn websocket_backend_schemes() {
+        assert_eq!(
+            websocket_backend(&Uri::from_static("http://backend/api/ws")),
+            Uri::from_static("ws://backend/api/ws")
+        );
+        assert_eq!(
+            websocket_backend(&Uri::from_static("https://backend:8443/api")),
+            Uri::from_static("wss://backend:8443/api")
+        );
+        assert_eq!(
+            websocket_backend(&Uri::from_static("ws://backend/")),
+            Uri::from_static("ws://backend/")
+        );
+    }
+
+    #[test]
+    fn path_rewrite_with_capture_groups() {
+        let backend = Uri::from_static("https://backend/ignored");
+        let request = Uri::from_static("http://localhost/api/v2/users/1");
+        let path_rewrite = PathRewrite::new(r"^/api/v(\d+)/(.*)$", "/internal/$2?v=$1".into())
+            .expect("Invalid pattern");
+        assert_eq!(
+            path_rewrite
+                .outbound_uri(&backend, &request)
+                .expect("Expected a match")
+                .expect("Unexpected error"),
+            Uri::from_static("https://backend/internal/users/1?v=2")
+        )
+    }
+
+    #[test]
+    fn path_rewrite_appends_query() {
+        let backend = Uri::from_static("https://backend/");
+        let path_rewrite = PathRewrite::new("^/api/(.*)$", "/$1".into()).expect("Invalid pattern");
+
+        let request = Uri::from_static("http://localhost/api/users?page=2");
+        assert_eq!(
+            path_rewrite
+                .outbound_uri(&backend, &request)
+                .expect("Expected a match")
+                .expect("Unexpected error"),
+            Uri::from_static("https://backend/users?page=2")
+        );
+
+        let path_rewrite =
+            PathRewrite::new("^/api/(.*)$", "/$1?v=1".into()).expect("Invalid pattern");
+        assert_eq!(
+            path_rewrite
+                .outbound_uri(&backend, &request)
+                .expect("Expected a match")
+                .expect("Unexpected error"),
+            Uri::from_static("https://backend/users?v=1&page=2")
+        )
+    }
+
+    #[test]
+    fn path_rewrite_no_match() {
+        let backend = Uri::from_static("https://backend/");
+        let request = Uri::from_static("http://localhost/other/users");
+        let path_rewrite = PathRewrite::new("^/api/(.*)$", "/$1".into()).expect("Invalid pattern");
+        assert!(path_rewrite.outbound_uri(&backend, &request).is_none());
+    }
 
     #[test]
     fn make_outbound_uri_two_base_paths() {
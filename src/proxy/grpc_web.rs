@@ -0,0 +1,359 @@
+//! Translation of browser gRPC-web requests into native gRPC requests, and of the native gRPC
+//! responses back into gRPC-web responses.
+//!
+//! The messages of both protocols are framed identically, so only the content type, the encoding
+//! of `-text` bodies & the trailers need to be translated. Native gRPC sends its trailers as HTTP/2
+//! trailers, which gRPC-web sends as a final frame of the response body instead.
+//!
+//! The `-text` bodies are base64 encoded frame by frame, so that each frame is padded on its own,
+//! which is what clients expect of responses, and what they may send as requests.
+
+use anyhow::Context;
+use axum::body::Body;
+use axum::http::header::{HeaderMap, HeaderValue, ACCEPT, CONTENT_LENGTH, CONTENT_TYPE, TE};
+use axum::http::request::Parts;
+use axum::http::Response;
+use base64::engine::general_purpose::STANDARD;
+use base64::Engine;
+use bytes::{BufMut, Bytes, BytesMut};
+use futures_util::stream::{self, Stream, StreamExt};
+use hyper::body::HttpBody;
+
+/// The content type prefix of gRPC-web requests & responses.
+const GRPC_WEB: &str = "application/grpc-web";
+/// The content type prefix of base64 encoded gRPC-web requests & responses.
+const GRPC_WEB_TEXT: &str = "application/grpc-web-text";
+/// The content type prefix of native gRPC requests & responses.
+const GRPC: &str = "application/grpc";
+/// The flag marking the frame which holds the trailers of a gRPC-web response.
+const TRAILERS_FLAG: u8 = 0x80;
+
+/// The encoding of the body of a gRPC-web request, which is used for its response as well.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub(super) enum Encoding {
+    /// Binary framed messages.
+    Binary,
+    /// Base64 encoded framed messages.
+    Text,
+}
+
+impl Encoding {
+    /// Get the encoding of the given request, if it is a gRPC-web request.
+    pub fn of(parts: &Parts) -> Option<Self> {
+        let content_type = parts.headers.get(CONTENT_TYPE)?.to_str().ok()?;
+        if content_type.starts_with(GRPC_WEB_TEXT) {
+            Some(Self::Text)
+        } else if content_type.starts_with(GRPC_WEB) {
+            Some(Self::Binary)
+        } else {
+            None
+        }
+    }
+
+    /// The content type prefix of gRPC-web messages in this encoding.
+    fn content_type(self) -> &'static str {
+        match self {
+            Self::Binary => GRPC_WEB,
+            Self::Text => GRPC_WEB_TEXT,
+        }
+    }
+}
+
+/// Translate the given gRPC-web request into a native gRPC request.
+pub(super) fn translate_request(parts: &mut Parts, encoding: Encoding, body: Body) -> Body {
+    replace_content_type(&mut parts.headers, encoding.content_type(), GRPC);
+    parts.headers.remove(CONTENT_LENGTH);
+    parts.headers.remove(ACCEPT);
+    parts
+        .headers
+        .insert(TE, HeaderValue::from_static("trailers"));
+    match encoding {
+        Encoding::Binary => body,
+        Encoding::Text => Body::wrap_stream(regroup(data_stream(body), 4, decode_text)),
+    }
+}
+
+/// Translate the given native gRPC response into a gRPC-web response.
+pub(super) fn translate_response(res: Response<Body>, encoding: Encoding) -> Response<Body> {
+    let (mut parts, body) = res.into_parts();
+    replace_content_type(&mut parts.headers, GRPC, encoding.content_type());
+    parts.headers.remove(CONTENT_LENGTH);
+
+    let body = data_stream_with_trailers(body);
+    let body = match encoding {
+        Encoding::Binary => Body::wrap_stream(body),
+        Encoding::Text => Body::wrap_stream(
+            frames(body).map(|frame| frame.map(|frame| Bytes::from(STANDARD.encode(frame)))),
+        ),
+    };
+    Response::from_parts(parts, body)
+}
+
+/// Replace the given prefix of the content type in the given headers.
+fn replace_content_type(headers: &mut HeaderMap, from: &str, to: &str) {
+    let content_type = headers
+        .get(CONTENT_TYPE)
+        .and_then(|content_type| content_type.to_str().ok())
+        .and_then(|content_type| content_type.strip_prefix(from))
+        .and_then(|suffix| HeaderValue::from_str(&format!("{to}{suffix}")).ok());
+    if let Some(content_type) = content_type {
+        headers.insert(CONTENT_TYPE, content_type);
+    }
+}
+
+/// Encode the given trailers as the final frame of a gRPC-web response.
+fn encode_trailers(trailers: &HeaderMap) -> Bytes {
+    let mut block = Vec::new();
+    for (name, value) in trailers {
+        block.extend_from_slice(name.as_str().as_bytes());
+        block.extend_from_slice(b": ");
+        block.extend_from_slice(value.as_bytes());
+        block.extend_from_slice(b"\r\n");
+    }
+    let mut frame = BytesMut::with_capacity(5 + block.len());
+    frame.put_u8(TRAILERS_FLAG);
+    frame.put_u32(block.len() as u32);
+    frame.extend_from_slice(&block);
+    frame.freeze()
+}
+
+/// Decode the given base64 of a gRPC-web-text body, whose length is a multiple of 4, and which
+/// may consist of several separately padded segments, e.g. one per frame.
+fn decode_text(text: &[u8]) -> anyhow::Result<Bytes> {
+    let mut decoded = BytesMut::with_capacity(text.len() / 4 * 3);
+    let mut start = 0;
+    for end in (4..=text.len()).step_by(4) {
+        // A segment ends with the group which holds its padding, or with the text.
+        if text[end - 1] == b'=' || end == text.len() {
+            let segment = STANDARD
+                .decode(&text[start..end])
+                .context("error decoding gRPC-web-text request body")?;
+            decoded.extend_from_slice(&segment);
+            start = end;
+        }
+    }
+    Ok(decoded.freeze())
+}
+
+/// Split the given stream of framed messages into its frames, each of which is yielded once it
+/// is complete. Any incomplete frame at the end of the stream is yielded as is.
+fn frames<S>(stream: S) -> impl Stream<Item = anyhow::Result<Bytes>>
+where
+    S: Stream<Item = anyhow::Result<Bytes>> + Send,
+{
+    stream::unfold(
+        (Some(Box::pin(stream)), BytesMut::new()),
+        |(stream, mut pending)| async move {
+            let mut stream = stream?;
+            loop {
+                if let Some(len) = pending
+                    .get(1..5)
+                    .map(|len| 5 + u32::from_be_bytes([len[0], len[1], len[2], len[3]]) as usize)
+                    .filter(|len| pending.len() >= *len)
+                {
+                    let frame = pending.split_to(len).freeze();
+                    return Some((Ok(frame), (Some(stream), pending)));
+                }
+                match stream.next().await {
+                    Some(Ok(chunk)) => pending.extend_from_slice(&chunk),
+                    Some(Err(err)) => return Some((Err(err), (None, BytesMut::new()))),
+                    None if pending.is_empty() => return None,
+                    None => return Some((Ok(pending.freeze()), (None, BytesMut::new()))),
+                }
+            }
+        },
+    )
+}
+
+/// Stream the data of the given body.
+fn data_stream(body: Body) -> impl Stream<Item = anyhow::Result<Bytes>> {
+    body.map(|chunk| chunk.context("error reading gRPC-web request body"))
+}
+
+/// Stream the data of the given native gRPC response body, followed by its trailers encoded as a
+/// gRPC-web trailers frame, if any.
+fn data_stream_with_trailers(body: Body) -> impl Stream<Item = anyhow::Result<Bytes>> {
+    stream::unfold(Some(body), |body| async move {
+        let mut body = body?;
+        match body.data().await {
+            Some(Ok(chunk)) => Some((Ok(chunk), Some(body))),
+            Some(Err(err)) => Some((Err(err).context("error reading gRPC response body"), None)),
+            None => match body.trailers().await {
+                Ok(Some(trailers)) => Some((Ok(encode_trailers(&trailers)), None)),
+                Ok(None) => None,
+                Err(err) => Some((
+                    Err(err).context("error reading gRPC response trailers"),
+                    None,
+                )),
+            },
+        }
+    })
+}
+
+/// Transform the given stream in groups of a multiple of `size` bytes, carrying incomplete groups
+/// over to the next chunk, as is required to decode base64 chunk by chunk.
+fn regroup<S>(
+    stream: S,
+    size: usize,
+    transform: fn(&[u8]) -> anyhow::Result<Bytes>,
+) -> impl Stream<Item = anyhow::Result<Bytes>>
+where
+    S: Stream<Item = anyhow::Result<Bytes>> + Send,
+{
+    stream::unfold(
+        (Some(Box::pin(stream)), Vec::new()),
+        move |(stream, mut pending)| async move {
+            let mut stream = stream?;
+            loop {
+                match stream.next().await {
+                    Some(Ok(chunk)) => {
+                        pending.extend_from_slice(&chunk);
+                        let len = pending.len() - pending.len() % size;
+                        if len == 0 {
+                            continue;
+                        }
+                        let rest = pending.split_off(len);
+                        return Some((transform(&pending), (Some(stream), rest)));
+                    }
+                    Some(Err(err)) => return Some((Err(err), (None, Vec::new()))),
+                    None if pending.is_empty() => return None,
+                    None => return Some((transform(&pending), (None, Vec::new()))),
+                }
+            }
+        },
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use axum::body::Body;
+    use axum::http::header::{CONTENT_TYPE, TE};
+    use axum::http::{HeaderMap, HeaderValue, Request, Response};
+    use base64::engine::general_purpose::STANDARD;
+    use base64::Engine;
+
+    use super::{encode_trailers, translate_request, translate_response, Encoding};
+
+    #[test]
+    fn trailers_frame() {
+        let mut trailers = HeaderMap::new();
+        trailers.insert("grpc-status", HeaderValue::from_static("0"));
+        let frame = encode_trailers(&trailers);
+        assert_eq!(&frame[..], b"\x80\x00\x00\x00\x10grpc-status: 0\r\n");
+    }
+
+    #[tokio::test]
+    async fn text_request_is_decoded() {
+        let (mut parts, _) = Request::builder()
+            .header(CONTENT_TYPE, "application/grpc-web-text+proto")
+            .body(())
+            .expect("error building request")
+            .into_parts();
+        let encoding = Encoding::of(&parts).expect("expected gRPC-web request");
+        assert_eq!(encoding, Encoding::Text);
+
+        let encoded = STANDARD.encode(b"\x00\x00\x00\x00\x02hi");
+        let (first, second) = encoded.split_at(3);
+        let body = Body::wrap_stream(futures_util::stream::iter([
+            Ok::<_, std::io::Error>(first.to_owned()),
+            Ok(second.to_owned()),
+        ]));
+        let body = translate_request(&mut parts, encoding, body);
+        let body = hyper::body::to_bytes(body)
+            .await
+            .expect("error reading body");
+
+        assert_eq!(&body[..], b"\x00\x00\x00\x00\x02hi");
+        assert_eq!(parts.headers[CONTENT_TYPE], "application/grpc+proto");
+        assert_eq!(parts.headers[TE], "trailers");
+    }
+
+    #[tokio::test]
+    async fn text_request_of_separately_padded_frames_is_decoded() {
+        let (mut parts, _) = Request::builder()
+            .header(CONTENT_TYPE, "application/grpc-web-text")
+            .body(())
+            .expect("error building request")
+            .into_parts();
+
+        // Each frame is padded on its own, so the padding is in the middle of the body.
+        let encoded = format!(
+            "{}{}",
+            STANDARD.encode(b"\x00\x00\x00\x00\x02bc"),
+            STANDARD.encode(b"\x00\x00\x00\x00\x01a")
+        );
+        assert_eq!(encoded, "AAAAAAJiYw==AAAAAAFh");
+        let (first, second) = encoded.split_at(5);
+        let body = Body::wrap_stream(futures_util::stream::iter([
+            Ok::<_, std::io::Error>(first.to_owned()),
+            Ok(second.to_owned()),
+        ]));
+        let body = translate_request(&mut parts, Encoding::Text, body);
+        let body = hyper::body::to_bytes(body)
+            .await
+            .expect("error reading body");
+
+        assert_eq!(&body[..], b"\x00\x00\x00\x00\x02bc\x00\x00\x00\x00\x01a");
+    }
+
+    #[tokio::test]
+    async fn text_response_is_encoded_frame_by_frame() {
+        let body = Body::wrap_stream(futures_util::stream::iter([
+            Ok::<_, std::io::Error>(&b"\x00\x00\x00\x00\x02bc\x00\x00"[..]),
+            Ok(&b"\x00\x00\x01a"[..]),
+        ]));
+        let res = Response::builder()
+            .header(CONTENT_TYPE, "application/grpc")
+            .body(body)
+            .expect("error building response");
+
+        let res = translate_response(res, Encoding::Text);
+        let body = hyper::body::to_bytes(res.into_body())
+            .await
+            .expect("error reading body");
+
+        assert_eq!(
+            std::str::from_utf8(&body).expect("expected base64"),
+            "AAAAAAJiYw==AAAAAAFh"
+        );
+    }
+
+    #[tokio::test]
+    async fn response_trailers_are_appended() {
+        let (mut sender, body) = Body::channel();
+        tokio::spawn(async move {
+            let mut trailers = HeaderMap::new();
+            trailers.insert("grpc-status", HeaderValue::from_static("0"));
+            sender
+                .send_data("\x00\x00\x00\x00\x02hi".into())
+                .await
+                .expect("error sending data");
+            sender
+                .send_trailers(trailers)
+                .await
+                .expect("error sending trailers");
+        });
+        let res = Response::builder()
+            .header(CONTENT_TYPE, "application/grpc+proto")
+            .body(body)
+            .expect("error building response");
+
+        let res = translate_response(res, Encoding::Text);
+        assert_eq!(
+            res.headers()[CONTENT_TYPE],
+            "application/grpc-web-text+proto"
+        );
+        let body = hyper::body::to_bytes(res.into_body())
+            .await
+            .expect("error reading body");
+        assert_eq!(
+            &body[..],
+            format!(
+                "{}{}",
+                STANDARD.encode(b"\x00\x00\x00\x00\x02hi"),
+                STANDARD.encode(b"\x80\x00\x00\x00\x10grpc-status: 0\r\n")
+            )
+            .as_bytes()
+        );
+    }
+}
@@ -0,0 +1,113 @@
+//! Rendering QR codes in the terminal, for mobile devices to scan the network URL of the server.
+
+use anyhow::{Context, Result};
+use console::Style;
+use qrcode::{Color, EcLevel, QrCode};
+
+/// The width of the light border around the codes, which scanners need to find them.
+const QUIET_ZONE: usize = 4;
+
+/// Render a QR code of the given bytes at the error correction level M for the terminal, with two
+/// rows of modules per line of half blocks, and the quiet zone around it.
+///
+/// The code is rendered in black on white if the terminal has `colors`, so that it scans with dark
+/// themes as well. Otherwise, the light modules are drawn in the text color, assuming light text
+/// on a dark background as most terminals have, as a code drawn the other way around would be
+/// inverted.
+pub fn render(data: &[u8], colors: bool) -> Result<String> {
+    let code = QrCode::with_error_correction_level(data, EcLevel::M)
+        .with_context(|| format!("error encoding {} bytes in a QR code", data.len()))?;
+    let size = code.width();
+    let modules = code.to_colors();
+    let width = size + 2 * QUIET_ZONE;
+    // Whether the module at the given column & row is drawn as a block.
+    let block = |x: usize, y: usize| {
+        let dark = (QUIET_ZONE..QUIET_ZONE + size).contains(&x)
+            && (QUIET_ZONE..QUIET_ZONE + size).contains(&y)
+            && modules[(y - QUIET_ZONE) * size + x - QUIET_ZONE] == Color::Dark;
+        dark == colors
+    };
+    let style = Style::new().black().on_white().on_bright();
+    let lines = (0..width)
+        .step_by(2)
+        .map(|y| {
+            // The row below the last one of an odd width is not drawn.
+            let lower = |x| y + 1 < width && block(x, y + 1);
+            let line = (0..width)
+                .map(|x| match (block(x, y), lower(x)) {
+                    (true, true) => '█',
+                    (true, false) => '▀',
+                    (false, true) => '▄',
+                    (false, false) => ' ',
+                })
+                .collect::<String>();
+            if colors {
+                style.apply_to(line).force_styling(true).to_string()
+            } else {
+                line
+            }
+        })
+        .collect::<Vec<_>>();
+    Ok(lines.join("\n"))
+}
+
+#[cfg(test)]
+mod tests {
+    use qrcode::{Color, EcLevel, QrCode};
+
+    use super::{render, QUIET_ZONE};
+
+    /// The modules of the given rendered code, which are dark if drawn as blocks, without the
+    /// styles & the row below an odd number of rows.
+    fn drawn_modules(rendered: &str) -> Vec<Vec<bool>> {
+        let rendered = console::strip_ansi_codes(rendered);
+        let mut rows = Vec::new();
+        for line in rendered.lines() {
+            let (upper, lower): (Vec<_>, Vec<_>) = line
+                .chars()
+                .map(|c| (matches!(c, '█' | '▀'), matches!(c, '█' | '▄')))
+                .unzip();
+            rows.extend([upper, lower]);
+        }
+        rows
+    }
+
+    #[test]
+    fn codes_are_rendered_with_their_quiet_zone() {
+        let url = b"http://192.168.178.23:8080/";
+        let code =
+            QrCode::with_error_correction_level(url, EcLevel::M).expect("error encoding url");
+        let size = code.width();
+        let modules = code.to_colors();
+        let width = size + 2 * QUIET_ZONE;
+
+        for colors in [true, false] {
+            let rows = drawn_modules(&render(url, colors).expect("error rendering url"));
+            assert_eq!(rows.len(), width + 1);
+            assert!(rows.iter().all(|row| row.len() == width));
+            for (y, row) in rows.iter().take(width).enumerate() {
+                for (x, &block) in row.iter().enumerate() {
+                    let dark = (QUIET_ZONE..QUIET_ZONE + size).contains(&x)
+                        && (QUIET_ZONE..QUIET_ZONE + size).contains(&y)
+                        && modules[(y - QUIET_ZONE) * size + x - QUIET_ZONE] == Color::Dark;
+                    // Without colors, the light modules are drawn as blocks of the text color.
+                    assert_eq!(block, dark == colors, "module ({}, {})", x, y);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn codes_are_black_on_white_with_colors() {
+        let url = b"http://[::1]/";
+        let rendered = render(url, true).expect("error rendering url");
+        assert!(rendered.lines().all(|line| line.starts_with("\u{1b}[")));
+        let rendered = render(url, false).expect("error rendering url");
+        assert!(!rendered.contains('\u{1b}'));
+    }
+
+    #[test]
+    fn too_many_bytes_are_an_error() {
+        assert!(render(&[b'a'; 2400], false).is_err());
+    }
+}
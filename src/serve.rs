@@ -1,79 +1,104 @@
-use std::net::{IpAddr, Ipv4Addr};
-use std::path::PathBuf;
+use std::collections::hash_map::Entry;
+use std::collections::HashMap;
+use std::net::{IpAddr, Ipv4Addr, SocketAddr, TcpListener};
+use std::path::{Path, PathBuf};
 use std::sync::Arc;
-use std::time::Duration;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
 
 use anyhow::{Context, Result};
 use axum::body::{self, Body};
-use axum::extract::ws::{WebSocket, WebSocketUpgrade};
-use axum::http::StatusCode;
+use axum::extract::ws::{Message, WebSocket, WebSocketUpgrade};
+use axum::http::header::{HeaderMap, HeaderValue, ACCEPT_LANGUAGE, CONTENT_TYPE, HOST, LOCATION};
+use axum::http::uri::Authority;
+use axum::http::{Request, StatusCode, Uri};
+use axum::middleware::{self, Next};
 use axum::response::Response;
-use axum::routing::{get, get_service, Router};
+use axum::routing::{get, get_service, post, MethodRouter, Router};
 use axum::Server;
 use axum_server::Handle;
-use tokio::sync::broadcast;
+use serde::Serialize;
+use tokio::sync::{broadcast, mpsc};
 use tokio::task::JoinHandle;
+use tokio::time::{interval_at, Instant, Interval};
+use tower_http::compression::predicate::{DefaultPredicate, NotForContentType, Predicate};
+use tower_http::compression::CompressionLayer;
 use tower_http::services::{ServeDir, ServeFile};
 use tower_http::trace::TraceLayer;
+use tower_http::validate_request::ValidateRequestHeaderLayer;
 
+use crate::build::{BuildEvent, BuildReport};
 use crate::common::{LOCAL, NETWORK, SERVER};
-use crate::config::RtcServe;
-use crate::proxy::{ProxyHandlerHttp, ProxyHandlerWebSocket};
+use crate::config::{cross_origin_isolation_headers, header_map, CompressionAlgorithm, RtcServe};
+use crate::mdns::MdnsService;
+#[cfg(unix)]
+use crate::proxy::unix_socket_path;
+use crate::proxy::{
+    HostHeader, PathRewrite, ProxyClient, ProxyClientOptions, ProxyHandlerHttp,
+    ProxyHandlerOptions, ProxyHandlerWebSocket, RetryPolicy,
+};
 use crate::watch::WatchSystem;
+use crate::{messages, pipelines, qr};
 
 const INDEX_HTML: &str = "index.html";
+/// The path on which builds can be triggered.
+const BUILD_PATH: &str = "/_trunk/build";
+/// The version of the protocol spoken with the autoreload client.
+const WS_PROTOCOL_VERSION: u32 = 1;
+/// The interval at which the autoreload client should attempt to reconnect, once disconnected.
+const WS_RECONNECT_INTERVAL: Duration = Duration::from_secs(5);
 
 /// A system encapsulating a build & watch system, responsible for serving generated content.
 pub struct ServeSystem {
     cfg: Arc<RtcServe>,
     watch: WatchSystem,
-    http_addr: String,
     shutdown_tx: broadcast::Sender<()>,
     //  N.B. we use a broadcast channel here because a watch channel triggers a
     //  false positive on the first read of channel
-    build_done_chan: broadcast::Sender<()>,
+    build_done_chan: broadcast::Sender<BuildEvent>,
 }
 
 impl ServeSystem {
     /// Construct a new instance.
     pub async fn new(cfg: Arc<RtcServe>, shutdown: broadcast::Sender<()>) -> Result<Self> {
-        let (build_done_chan, _) = broadcast::channel(8);
-        let watch = WatchSystem::new(
-            cfg.watch.clone(),
-            shutdown.clone(),
-            Some(build_done_chan.clone()),
-        )
-        .await?;
-        let prefix = if cfg.tls.is_some() { "https" } else { "http" };
-        let http_addr = format!(
-            "{}://{}:{}{}",
-            prefix, cfg.address, cfg.port, &cfg.watch.build.public_url
-        );
+        let build_done_chan = cfg.watch.build.events.clone();
+        let watch = WatchSystem::new(cfg.watch.clone(), shutdown.clone()).await?;
         Ok(Self {
             cfg,
             watch,
-            http_addr,
             shutdown_tx: shutdown,
             build_done_chan,
         })
     }
 
+    /// Subscribe to the events of the builds.
+    pub fn subscribe(&self) -> broadcast::Receiver<BuildEvent> {
+        self.build_done_chan.subscribe()
+    }
+
     /// Run the serve system.
     #[tracing::instrument(level = "trace", skip(self))]
     pub async fn run(mut self) -> Result<()> {
         // Spawn the watcher & the server.
         let _build_res = self.watch.build().await; // TODO: only open after a successful build.
+        let build_trigger = self.watch.build_trigger();
         let watch_handle = tokio::spawn(self.watch.run());
-        let server_handle = Self::spawn_server(
+        let (addr, server_handle) = Self::spawn_server(
             self.cfg.clone(),
             self.shutdown_tx.subscribe(),
             self.build_done_chan,
+            build_trigger,
         )
         .await?;
 
         // Open the browser.
         if self.cfg.open {
-            if let Err(err) = open::that(self.http_addr) {
+            let prefix = if self.cfg.tls.is_some() {
+                "https"
+            } else {
+                "http"
+            };
+            let url = format!("{}://{}{}", prefix, addr, &self.cfg.watch.build.public_url);
+            if let Err(err) = open::that(url) {
                 tracing::error!(error = ?err, "error opening browser");
             }
         }
@@ -91,11 +116,14 @@ impl ServeSystem {
     async fn spawn_server(
         cfg: Arc<RtcServe>,
         mut shutdown_rx: broadcast::Receiver<()>,
-        build_done_chan: broadcast::Sender<()>,
-    ) -> Result<JoinHandle<()>> {
+        build_done_chan: broadcast::Sender<BuildEvent>,
+        build_trigger: mpsc::Sender<()>,
+    ) -> Result<(SocketAddr, JoinHandle<()>)> {
         // Build a shutdown signal for the warp server.
         let graceful_shutdown_handle = Handle::new();
         let handle_clone = graceful_shutdown_handle.clone();
+        let mut redirect_shutdown_rx = shutdown_rx.resubscribe();
+        let mdns_shutdown_rx = shutdown_rx.resubscribe();
         let shutdown_fut = async move {
             // Any event on this channel, even a drop, should trigger shutdown.
             let _res = shutdown_rx.recv().await;
@@ -103,50 +131,69 @@ impl ServeSystem {
             handle_clone.graceful_shutdown(Some(Duration::from_secs(0)));
         };
 
-        // Build the proxy client.
-        let client = reqwest::ClientBuilder::new()
-            .http1_only()
-            .build()
-            .context("error building proxy client")?;
-
-        let insecure_client = reqwest::ClientBuilder::new()
-            .http1_only()
-            .danger_accept_invalid_certs(true)
-            .build()
-            .context("error building insecure proxy client")?;
-
         // Build the server.
         let state = Arc::new(State::new(
             cfg.watch.build.final_dist.clone(),
             cfg.watch.build.public_url.clone(),
-            client,
-            insecure_client,
             &cfg,
             build_done_chan,
+            build_trigger,
         ));
-        let router = router(state, cfg.clone());
-        let addr = (cfg.address, cfg.port).into();
+        let router = router(state, cfg.clone())?;
+        // The listener is bound up front to serve on the actual port if the port is 0.
+        let listener = bind((cfg.address, cfg.port).into())?;
+        let addr = listener
+            .local_addr()
+            .context("error getting the address of the server")?;
 
         let mut http_server: Option<_> = None;
         let mut https_server: Option<_> = None;
+        let mut redirect_server: Option<_> = None;
         if let Some(tls_config) = cfg.tls.clone() {
+            if let Some(redirect_port) = cfg.tls_redirect_port {
+                let redirect_addr = SocketAddr::from((cfg.address, redirect_port));
+                let redirect_listener = bind(redirect_addr)?;
+                redirect_server = Some(
+                    Server::from_tcp(redirect_listener)
+                        .with_context(|| {
+                            format!("error serving the HTTPS redirect on {}", redirect_addr)
+                        })?
+                        .serve(https_redirect_router(addr.port()).into_make_service())
+                        .with_graceful_shutdown(async move {
+                            let _res = redirect_shutdown_rx.recv().await;
+                        }),
+                );
+                tracing::info!(
+                    "{} redirecting http://{} to https on port {}",
+                    SERVER,
+                    redirect_addr,
+                    addr.port()
+                );
+            }
             // Spawn a task to gracefully shutdown server.
             tokio::spawn(shutdown_fut);
             https_server = Some(
-                axum_server::bind_rustls(addr, tls_config)
+                axum_server::from_tcp_rustls(listener, tls_config)
                     .handle(graceful_shutdown_handle)
                     .serve(router.into_make_service()),
             );
         } else {
+            if cfg.tls_redirect_port.is_some() {
+                tracing::warn!("ignoring the TLS redirect port, as TLS is not enabled");
+            }
             http_server = Some(
-                Server::bind(&addr)
+                Server::from_tcp(listener)
+                    .with_context(|| format!("error serving on {}", addr))?
                     .serve(router.into_make_service())
                     .with_graceful_shutdown(shutdown_fut),
             );
         }
 
         let prefix = if cfg.tls.is_some() { "https" } else { "http" };
-        if addr.ip().is_unspecified() {
+        let path = Some(cfg.watch.build.public_url.as_str())
+            .filter(|url| url.starts_with('/'))
+            .unwrap_or("/");
+        let (urls, network_url, network_addresses) = if addr.ip().is_unspecified() {
             let addresses = local_ip_address::list_afinet_netifas()
                 .map(|addrs| {
                     addrs
@@ -172,44 +219,210 @@ impl ServeSystem {
                         },
                         prefix,
                         address,
-                        cfg.port
+                        addr.port()
                     ))
                     .collect::<Vec<_>>()
                     .join("\n")
             );
+            let url = |address| format!("{}://{}:{}{}", prefix, address, addr.port(), path);
+            let network_addresses = addresses
+                .iter()
+                .copied()
+                .filter(|address| !address.is_loopback())
+                .collect::<Vec<_>>();
+            let network_url = network_addresses.first().map(url);
+            let urls = addresses.iter().map(url).collect();
+            (urls, network_url, network_addresses)
         } else {
             tracing::info!("{} server listening at {}://{}", SERVER, prefix, addr);
+            let url = format!("{}://{}{}", prefix, addr, path);
+            let network_url = Some(url.clone()).filter(|_| !addr.ip().is_loopback());
+            let network_addresses = match addr.ip() {
+                IpAddr::V4(ip) if !ip.is_loopback() => vec![ip],
+                _ => Vec::new(),
+            };
+            (vec![url], network_url, network_addresses)
+        };
+        if cfg.qr {
+            match &network_url {
+                Some(url) => print_qr_code(url),
+                None if addr.ip().is_unspecified() => {
+                    tracing::warn!("no network address found to print a QR code of")
+                }
+                None => tracing::warn!(
+                    "no network address to print a QR code of, serve on an unspecified address \
+                     like `--address 0.0.0.0` to reach the server from other devices"
+                ),
+            }
         }
+        let mdns_handle = cfg.mdns.as_deref().and_then(|name| {
+            announce_mdns(
+                &cfg,
+                name,
+                addr.port(),
+                path,
+                network_addresses,
+                mdns_shutdown_rx,
+            )
+        });
+        messages::emit(&messages::Message::ServerListening { urls });
         // Block this routine on the server's completion.
-        Ok(tokio::spawn(async move {
+        let handle = tokio::spawn(async move {
             if let Some(server) = http_server {
                 if let Err(err) = server.await {
                     tracing::error!(error = ?err, "error from server task");
                 }
             }
             if let Some(server) = https_server {
-                if let Err(err) = server.await {
+                // Run the redirect server alongside the https server.
+                let redirect_fut = async move {
+                    if let Some(server) = redirect_server {
+                        if let Err(err) = server.await {
+                            tracing::error!(error = ?err, "error from redirect server task");
+                        }
+                    }
+                };
+                let (res, _) = tokio::join!(server, redirect_fut);
+                if let Err(err) = res {
                     tracing::error!(error = ?err, "error from server task");
                 }
             }
-        }))
+            if let Some(responder) = mdns_handle {
+                if let Err(err) = responder.await {
+                    tracing::error!(error = ?err, "error joining mDNS responder handle");
+                }
+            }
+        });
+        Ok((addr, handle))
+    }
+}
+
+/// Bind a listener to the given address, which is kept bound until it is served, so that a free
+/// port picked with port 0 can not be taken by another process in the meantime.
+fn bind(addr: SocketAddr) -> Result<TcpListener> {
+    TcpListener::bind(addr).with_context(|| format!("error binding to {}", addr))
+}
+
+/// Serve the static files of the given dir on a free port of localhost, returning the address of
+/// the server & its task, which is aborted to stop it.
+pub(crate) fn spawn_static_server(dir: &Path) -> Result<(SocketAddr, JoinHandle<()>)> {
+    let listener = bind((Ipv4Addr::LOCALHOST, 0).into())?;
+    let addr = listener
+        .local_addr()
+        .context("error getting the address of the server")?;
+    let router = Router::new().fallback_service(serve_dir(dir));
+    let server = Server::from_tcp(listener)
+        .with_context(|| format!("error serving on {}", addr))?
+        .serve(router.into_make_service());
+    let handle = tokio::spawn(async move {
+        if let Err(err) = server.await {
+            tracing::error!(error = ?err, "error from server task");
+        }
+    });
+    Ok((addr, handle))
+}
+
+/// Serve the static files of the given dir, logging the errors of reading them.
+fn serve_dir(dir: &Path) -> MethodRouter {
+    get_service(ServeDir::new(dir))
+        .handle_error(|error| async move {
+            tracing::error!(?error, "failed serving static file");
+            StatusCode::INTERNAL_SERVER_ERROR
+        })
+        .layer(TraceLayer::new_for_http())
+}
+
+/// Print a QR code of the given network URL of the server, for mobile devices to scan.
+fn print_qr_code(url: &str) {
+    let colors = if messages::is_json() {
+        console::colors_enabled_stderr()
+    } else {
+        console::colors_enabled()
+    };
+    match qr::render(url.as_bytes(), colors) {
+        Ok(code) => messages::print(format_args!("{}\n{}", code, url)),
+        Err(err) => tracing::warn!("error encoding a QR code of {}: {}", url, err),
     }
 }
 
+/// Announce the server via mDNS as the given name on the given network addresses, until it shuts
+/// down.
+///
+/// The server is served regardless of errors of the announcement, e.g. if the mDNS port can not
+/// be bound.
+fn announce_mdns(
+    cfg: &RtcServe,
+    name: &str,
+    port: u16,
+    path: &str,
+    addresses: Vec<Ipv4Addr>,
+    shutdown_rx: broadcast::Receiver<()>,
+) -> Option<JoinHandle<()>> {
+    if addresses.is_empty() {
+        tracing::warn!(
+            "not announcing the server via mDNS, as it has no network address, serve on an \
+             unspecified address like `--address 0.0.0.0` to reach it from other devices"
+        );
+        return None;
+    }
+    let tls = cfg.tls.is_some();
+    let announced = MdnsService::new(name, tls, port, path, addresses).and_then(|service| {
+        let host = service.host();
+        Ok((host, service.spawn(shutdown_rx)?))
+    });
+    match announced {
+        Ok((host, responder)) => {
+            let prefix = if tls { "https" } else { "http" };
+            tracing::info!(
+                "{} server announced via mDNS at {}://{}:{}{}",
+                NETWORK,
+                prefix,
+                host,
+                port,
+                path
+            );
+            Some(responder)
+        }
+        Err(err) => {
+            tracing::warn!("error announcing the server via mDNS: {:?}", err);
+            None
+        }
+    }
+}
+
+/// Build a router which permanently redirects all requests to HTTPS on the given port.
+fn https_redirect_router(https_port: u16) -> Router {
+    Router::new().fallback(move |headers: HeaderMap, uri: Uri| async move {
+        let host = headers
+            .get(HOST)
+            .and_then(|host| host.to_str().ok())
+            .and_then(|host| host.parse::<Authority>().ok())
+            .map(|authority| authority.host().to_owned())
+            .unwrap_or_else(|| Ipv4Addr::LOCALHOST.to_string());
+        let path = uri.path_and_query().map(|pq| pq.as_str()).unwrap_or("/");
+        let location = format!("https://{}:{}{}", host, https_port, path);
+        (StatusCode::MOVED_PERMANENTLY, [(LOCATION, location)])
+    })
+}
+
 /// Server state.
 pub struct State {
-    /// A client instance used by proxies.
-    pub client: reqwest::Client,
-    /// A client instance used by proxies to make insecure requests.
-    pub insecure_client: reqwest::Client,
     /// The location of the dist dir.
     pub dist_dir: PathBuf,
     /// The public URL from which assets are being served.
     pub public_url: String,
     /// The channel to receive build_done notifications on.
-    pub build_done_chan: broadcast::Sender<()>,
+    pub build_done_chan: broadcast::Sender<BuildEvent>,
+    /// The channel to trigger builds on.
+    pub build_trigger: mpsc::Sender<()>,
     /// Whether to disable autoreload
     pub no_autoreload: bool,
+    /// The interval at which to send keepalive pings on the autoreload WebSocket.
+    pub ws_ping_interval: Option<Duration>,
+    /// The locales for which the output HTML is built, if any.
+    pub locales: Vec<String>,
+    /// The locale of the output HTML at the root of the dist dir, if building per locale.
+    pub default_locale: Option<String>,
 }
 
 impl State {
@@ -217,25 +430,104 @@ impl State {
     pub fn new(
         dist_dir: PathBuf,
         public_url: String,
-        client: reqwest::Client,
-        insecure_client: reqwest::Client,
         cfg: &RtcServe,
-        build_done_chan: broadcast::Sender<()>,
+        build_done_chan: broadcast::Sender<BuildEvent>,
+        build_trigger: mpsc::Sender<()>,
     ) -> Self {
         Self {
-            client,
-            insecure_client,
             dist_dir,
             public_url,
             build_done_chan,
+            build_trigger,
             no_autoreload: cfg.no_autoreload,
+            ws_ping_interval: cfg.ws_ping_interval,
+            locales: cfg.watch.build.locales.clone(),
+            default_locale: cfg.watch.build.default_locale.clone(),
         }
     }
 }
 
+/// Rewrite the given request to the output HTML of a locale, if building per locale.
+///
+/// Requests of the root are rewritten to the output HTML of the locale which is accepted by the
+/// browser according to its `Accept-Language` header, and requests of missing files in the dir of
+/// a locale to the output HTML of that locale.
+fn localize_request(state: &State, mut req: Request<Body>) -> Request<Body> {
+    if state.locales.is_empty() {
+        return req;
+    }
+    let Some(rest) = req.uri().path().strip_prefix(&state.public_url) else {
+        return req;
+    };
+    let locale = if rest.is_empty() {
+        req.headers()
+            .get(ACCEPT_LANGUAGE)
+            .and_then(|header| header.to_str().ok())
+            .and_then(|header| {
+                pipelines::negotiate_locale(header, &state.locales, state.default_locale.as_deref())
+            })
+    } else {
+        state
+            .locales
+            .iter()
+            .find(|locale| {
+                rest.strip_prefix(locale.as_str())
+                    .is_some_and(|file| file.starts_with('/') && file.len() > 1)
+            })
+            .filter(|_| !state.dist_dir.join(rest).is_file())
+            .map(String::as_str)
+    };
+    let Some(locale) = locale else {
+        return req;
+    };
+
+    let path_and_query = match req.uri().query() {
+        Some(query) => format!("{}{}/?{}", state.public_url, locale, query),
+        None => format!("{}{}/", state.public_url, locale),
+    };
+    let mut parts = req.uri().clone().into_parts();
+    match path_and_query.parse() {
+        Ok(path_and_query) => parts.path_and_query = Some(path_and_query),
+        Err(_) => return req,
+    }
+    if let Ok(uri) = Uri::from_parts(parts) {
+        *req.uri_mut() = uri;
+    }
+    req
+}
+
+/// Set the configured MIME type of the extension of the requested file on the response, unless
+/// it responds with the `index.html` fallback instead.
+async fn apply_mime_type(
+    mime_types: &HashMap<String, HeaderValue>,
+    req: Request<Body>,
+    next: Next<Body>,
+) -> Response {
+    let ext = Path::new(req.uri().path())
+        .extension()
+        .and_then(|ext| ext.to_str())
+        .map(|ext| ext.to_ascii_lowercase());
+    let mut res = next.run(req).await;
+    let Some((ext, mime_type)) =
+        ext.and_then(|ext| mime_types.get(&ext).map(|mime_type| (ext, mime_type)))
+    else {
+        return res;
+    };
+    let is_fallback = !matches!(ext.as_str(), "html" | "htm")
+        && res
+            .headers()
+            .get(CONTENT_TYPE)
+            .and_then(|value| value.to_str().ok())
+            .is_some_and(|value| value.starts_with("text/html"));
+    if res.status().is_success() && !is_fallback {
+        res.headers_mut().insert(CONTENT_TYPE, mime_type.clone());
+    }
+    res
+}
+
 /// Build the Trunk router, this includes that static file server, the WebSocket server,
 /// (for autoreload & HMR in the future), as well as any user-defined proxies.
-fn router(state: Arc<State>, cfg: Arc<RtcServe>) -> Router {
+fn router(state: Arc<State>, cfg: Arc<RtcServe>) -> Result<Router> {
     // Build static file server, middleware, error handler & WS route for reloads.
     let public_route = if state.public_url == "/" {
         &state.public_url
@@ -246,12 +538,20 @@ fn router(state: Arc<State>, cfg: Arc<RtcServe>) -> Router {
             .unwrap_or(&state.public_url)
     };
 
-    let mut router = Router::new()
-        .fallback_service(
-            Router::new().nest_service(
+    let headers = cfg.headers.clone();
+    let mime_types = Arc::new(cfg.mime_types.clone());
+    let mime_type_layer = middleware::from_fn(move |req: Request<Body>, next: Next<Body>| {
+        let mime_types = mime_types.clone();
+        async move { apply_mime_type(&mime_types, req, next).await }
+    });
+    let router = Router::new().fallback_service(
+        Router::new()
+            .nest_service(
                 public_route,
                 get_service(
                     ServeDir::new(&state.dist_dir)
+                        .precompressed_br()
+                        .precompressed_gzip()
                         .fallback(ServeFile::new(state.dist_dir.join(INDEX_HTML))),
                 )
                 .handle_error(|error| async move {
@@ -259,10 +559,29 @@ fn router(state: Arc<State>, cfg: Arc<RtcServe>) -> Router {
                     StatusCode::INTERNAL_SERVER_ERROR
                 })
                 .layer(TraceLayer::new_for_http()),
-            ),
-        )
+            )
+            // Serve the output HTML of the locale accepted by the browser.
+            .layer(middleware::map_request({
+                let state = state.clone();
+                move |req: Request<Body>| {
+                    let state = state.clone();
+                    async move { localize_request(&state, req) }
+                }
+            }))
+            // Apply any additional headers configured for static file responses.
+            .layer(middleware::map_response(move |mut res: Response| {
+                let headers = headers.clone();
+                async move {
+                    res.headers_mut().extend(headers);
+                    res
+                }
+            }))
+            .layer(mime_type_layer.clone()),
+    );
+
+    let mut router = router
         .route(
-            "/_trunk/ws",
+            &cfg.ws_path,
             get(
                 |ws: WebSocketUpgrade, state: axum::extract::State<Arc<State>>| async move {
                     ws.on_upgrade(|socket| async move { handle_ws(socket, state.0).await })
@@ -277,10 +596,56 @@ fn router(state: Arc<State>, cfg: Arc<RtcServe>) -> Router {
         state.public_url.as_str()
     );
 
-    // Build proxies.
+    // Serve any additional static directories.
+    let isolation_headers = if cfg.cross_origin_isolation {
+        cross_origin_isolation_headers()
+    } else {
+        HeaderMap::new()
+    };
+    for static_dir in cfg.static_dirs.iter() {
+        let isolation_headers = isolation_headers.clone();
+        router = router.nest_service(
+            static_dir.path.trim_end_matches('/'),
+            serve_dir(&static_dir.dir)
+                .layer(middleware::map_response(move |mut res: Response| {
+                    let headers = isolation_headers.clone();
+                    async move {
+                        res.headers_mut().extend(headers);
+                        res
+                    }
+                }))
+                .layer(mime_type_layer.clone()),
+        );
+        tracing::info!(
+            "{} serving static dir {} -> {}",
+            SERVER,
+            static_dir.path,
+            static_dir.dir.display()
+        );
+    }
+
+    // Build proxies, sharing a client between all proxies which use the same client options.
+    // Unix domain socket backends are addressed as `localhost` in outbound requests.
+    let mut clients = HashMap::<_, ProxyClient>::new();
+    let mut get_client =
+        |backends: &[Uri], opts: ProxyClientOptions| -> Result<(ProxyClient, Vec<Uri>)> {
+            #[cfg(unix)]
+            if let Some(socket) = backends.first().and_then(unix_socket_path) {
+                return Ok((
+                    ProxyClient::unix(socket),
+                    vec![Uri::from_static("http://localhost/")],
+                ));
+            }
+            let client = match clients.entry(opts) {
+                Entry::Occupied(entry) => entry.get().clone(),
+                Entry::Vacant(entry) => entry.insert(opts.build()?).clone(),
+            };
+            Ok((client, backends.to_vec()))
+        };
     if let Some(backend) = &cfg.proxy_backend {
         if cfg.proxy_ws {
-            let handler = ProxyHandlerWebSocket::new(backend.clone(), cfg.proxy_rewrite.clone());
+            let handler =
+                ProxyHandlerWebSocket::new(backend.clone(), cfg.proxy_rewrite.clone(), None);
             router = handler.clone().register(router);
             tracing::info!(
                 "{} proxying websocket {} -> {}",
@@ -289,70 +654,335 @@ fn router(state: Arc<State>, cfg: Arc<RtcServe>) -> Router {
                 &backend
             );
         } else {
-            let client = if cfg.proxy_insecure {
-                state.insecure_client.clone()
-            } else {
-                state.client.clone()
-            };
+            let (client, outbound_backends) = get_client(
+                std::slice::from_ref(backend),
+                ProxyClientOptions {
+                    insecure: cfg.proxy_insecure,
+                    http2: cfg.proxy_http2,
+                    connect_timeout: None,
+                    rewrite_location: false,
+                    grpc_web: false,
+                },
+            )?;
 
-            let handler = ProxyHandlerHttp::new(client, backend.clone(), cfg.proxy_rewrite.clone());
+            let handler = ProxyHandlerHttp::new(
+                client,
+                outbound_backends,
+                cfg.proxy_rewrite.clone(),
+                None,
+                ProxyHandlerOptions {
+                    log: cfg.proxy_log,
+                    ..Default::default()
+                },
+            );
             router = handler.clone().register(router);
             tracing::info!("{} proxying {} -> {}", SERVER, handler.path(), &backend);
         }
     } else if let Some(proxies) = &cfg.proxies {
         for proxy in proxies.iter() {
+            let backends = proxy
+                .backend
+                .iter()
+                .map(ToString::to_string)
+                .collect::<Vec<_>>()
+                .join(", ");
+            let path_rewrite = proxy
+                .path_rewrite
+                .as_ref()
+                .map(|rewrite| PathRewrite::new(&rewrite.pattern, rewrite.replacement.clone()))
+                .transpose()?;
             if proxy.ws {
-                let handler =
-                    ProxyHandlerWebSocket::new(proxy.backend.clone(), proxy.rewrite.clone());
+                let handler = ProxyHandlerWebSocket::new(
+                    proxy.backend[0].clone(),
+                    proxy.rewrite.clone(),
+                    path_rewrite,
+                );
                 router = handler.clone().register(router);
                 tracing::info!(
                     "{} proxying websocket {} -> {}",
                     SERVER,
                     handler.path(),
-                    &proxy.backend
+                    backends
                 );
             } else {
-                let client = if proxy.insecure {
-                    state.insecure_client.clone()
+                let (client, outbound_backends) = get_client(
+                    &proxy.backend,
+                    ProxyClientOptions {
+                        insecure: proxy.insecure,
+                        http2: proxy.http2,
+                        connect_timeout: proxy.connect_timeout,
+                        rewrite_location: proxy.rewrite_location,
+                        grpc_web: proxy.grpc_web,
+                    },
+                )?;
+
+                let request_headers = header_map(proxy.request_headers.clone())
+                    .with_context(|| format!("invalid request headers for proxy {}", backends))?;
+                let mut response_headers = if proxy.cross_origin_isolation {
+                    cross_origin_isolation_headers()
                 } else {
-                    state.client.clone()
+                    HeaderMap::new()
                 };
-
-                let handler =
-                    ProxyHandlerHttp::new(client, proxy.backend.clone(), proxy.rewrite.clone());
-                router = handler.clone().register(router);
-                tracing::info!(
-                    "{} proxying {} -> {}",
-                    SERVER,
-                    handler.path(),
-                    &proxy.backend
+                response_headers.extend(
+                    header_map(proxy.response_headers.clone()).with_context(|| {
+                        format!("invalid response headers for proxy {}", backends)
+                    })?,
                 );
+                let host = match &proxy.host {
+                    Some(host) => {
+                        HostHeader::Override(HeaderValue::from_str(host).with_context(|| {
+                            format!("invalid host header {:?} for proxy {}", host, backends)
+                        })?)
+                    }
+                    None if proxy.preserve_host => HostHeader::Preserve,
+                    None => HostHeader::Backend,
+                };
+                let handler = ProxyHandlerHttp::new(
+                    client,
+                    outbound_backends,
+                    proxy.rewrite.clone(),
+                    path_rewrite,
+                    ProxyHandlerOptions {
+                        load_balancing: proxy.load_balancing,
+                        grpc_web: proxy.grpc_web,
+                        retry: proxy.retry.as_ref().map(RetryPolicy::from),
+                        request_timeout: proxy.request_timeout.map(Duration::from_secs),
+                        host,
+                        log: proxy.log.or(cfg.proxy_log),
+                        mock_dir: proxy.mock_dir.clone(),
+                        rewrite_location: proxy.rewrite_location,
+                        rewrite_cookies: proxy.rewrite_cookies,
+                        request_headers,
+                        response_headers,
+                    },
+                );
+                router = handler.clone().register(router);
+                tracing::info!("{} proxying {} -> {}", SERVER, handler.path(), backends);
             };
         }
     }
 
-    router
+    // Compress responses with any of the configured algorithms.
+    // Server-sent events are never compressed, as that would buffer the streamed events.
+    if !cfg.compression.is_empty() {
+        let enabled = |algorithm| cfg.compression.contains(&algorithm);
+        router = router.layer(
+            CompressionLayer::new()
+                .br(enabled(CompressionAlgorithm::Br))
+                .gzip(enabled(CompressionAlgorithm::Gzip))
+                .deflate(enabled(CompressionAlgorithm::Deflate))
+                .compress_when(
+                    DefaultPredicate::new().and(NotForContentType::const_new("text/event-stream")),
+                ),
+        );
+        tracing::info!(
+            "{} compressing responses with {:?}",
+            SERVER,
+            cfg.compression
+        );
+    }
+
+    // Require basic auth for all requests, if configured.
+    if let Some((username, password)) = cfg.basic_auth.as_ref() {
+        router = router.layer(ValidateRequestHeaderLayer::basic(username, password));
+        tracing::info!("{} requiring basic auth for user {}", SERVER, username);
+    }
+
+    // Expose the build trigger, if enabled. The route is added after the basic auth layer, so
+    // that it is only authenticated with the build token, as both use the authorization header.
+    if let Some(token) = &cfg.build_token {
+        let build_trigger = state.build_trigger.clone();
+        router = router.route(
+            BUILD_PATH,
+            post(move || async move {
+                // A full channel means that a triggered build is pending already.
+                let _ = build_trigger.try_send(());
+                StatusCode::ACCEPTED
+            })
+            .layer(ValidateRequestHeaderLayer::bearer(token)),
+        );
+        tracing::info!("{} triggering builds at -> {}", SERVER, BUILD_PATH);
+    }
+
+    Ok(router)
 }
 
 async fn handle_ws(mut ws: WebSocket, state: Arc<State>) {
     let mut rx = state.build_done_chan.subscribe();
+    let mut ping = state
+        .ws_ping_interval
+        .map(|period| interval_at(Instant::now() + period, period));
     tracing::debug!("autoreload websocket opened");
-    while tokio::select! {
-        _ = ws.recv() => {
-            tracing::debug!("autoreload websocket closed");
-            return
-        }
-        build_done = rx.recv() => build_done.is_ok(),
-    } {
-        let ws_send = ws.send(axum::extract::ws::Message::Text(
-            r#"{"reload": true}"#.to_owned(),
-        ));
-        if ws_send.await.is_err() {
+
+    // Advise the client on how to reconnect, before any reload is sent.
+    if ws
+        .send(Message::Text(ClientMessage::default().to_json()))
+        .await
+        .is_err()
+    {
+        return;
+    }
+    loop {
+        let msg = tokio::select! {
+            msg = ws.recv() => match msg {
+                Some(Ok(Message::Close(_)) | Err(_)) | None => {
+                    tracing::debug!("autoreload websocket closed");
+                    return
+                }
+                // Any other messages, including pongs, are ignored.
+                Some(Ok(_)) => continue,
+            },
+            event = rx.recv() => match event {
+                Ok(event) => match ClientMessage::from_event(event) {
+                    Some(msg) => Message::Text(msg.to_json()),
+                    None => continue,
+                },
+                // Events missed by a slow client are skipped, as the next ones supersede them.
+                Err(broadcast::error::RecvError::Lagged(_)) => continue,
+                Err(broadcast::error::RecvError::Closed) => break,
+            },
+            _ = tick(&mut ping) => Message::Ping(Vec::new()),
+        };
+        if ws.send(msg).await.is_err() {
             break;
         }
     }
 }
 
+/// A message sent to the autoreload client.
+#[derive(Serialize)]
+struct ClientMessage {
+    /// The version of the autoreload protocol.
+    version: u32,
+    /// Whether the client should reload the page.
+    reload: bool,
+    /// The interval in milliseconds at which the client should attempt to reconnect.
+    reconnect_interval: u128,
+    /// The public URLs of changed stylesheets, which the client should replace in place.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    css: Option<Vec<String>>,
+    /// Metadata on a successful build.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    build: Option<ClientBuild>,
+    /// The error of a failed build, to be displayed by the client.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    error: Option<ClientError>,
+}
+
+/// Metadata on a successful build, as sent to the autoreload client.
+#[derive(Serialize)]
+struct ClientBuild {
+    /// The sequential ID of the build.
+    id: u64,
+    /// The time at which the build started, in milliseconds since the Unix epoch.
+    started: u128,
+    /// The time at which the build finished, in milliseconds since the Unix epoch.
+    finished: u128,
+    /// The public URLs of all assets which were added or changed by the build.
+    changed_assets: Vec<String>,
+}
+
+impl From<BuildReport> for ClientBuild {
+    fn from(report: BuildReport) -> Self {
+        let unix_millis = |time: SystemTime| {
+            time.duration_since(UNIX_EPOCH)
+                .unwrap_or_default()
+                .as_millis()
+        };
+        Self {
+            id: report.id,
+            started: unix_millis(report.started),
+            finished: unix_millis(report.finished),
+            changed_assets: report.changed_assets,
+        }
+    }
+}
+
+/// The error of a failed build, as sent to the autoreload client.
+#[derive(Serialize)]
+struct ClientError {
+    /// The error chain of the failed build.
+    error: String,
+    /// The output of the compiler, if the build failed during compilation.
+    output: Option<String>,
+}
+
+impl Default for ClientMessage {
+    fn default() -> Self {
+        Self {
+            version: WS_PROTOCOL_VERSION,
+            reload: false,
+            reconnect_interval: WS_RECONNECT_INTERVAL.as_millis(),
+            css: None,
+            build: None,
+            error: None,
+        }
+    }
+}
+
+impl ClientMessage {
+    /// The message of the given build event, if the client is notified of it.
+    fn from_event(event: BuildEvent) -> Option<Self> {
+        match event {
+            BuildEvent::Started { .. } | BuildEvent::Progress { .. } => None,
+            BuildEvent::Succeeded {
+                report,
+                stylesheets_only: true,
+                ..
+            } => Some(Self {
+                css: Some(
+                    report
+                        .changed_assets
+                        .iter()
+                        .filter(|asset| asset.ends_with(".css"))
+                        .cloned()
+                        .collect(),
+                ),
+                build: Some(ClientBuild::from(report)),
+                ..Default::default()
+            }),
+            BuildEvent::Succeeded { report, .. } => Some(Self {
+                reload: true,
+                build: Some(ClientBuild::from(report)),
+                ..Default::default()
+            }),
+            BuildEvent::Failed { errors, output, .. } => Some(Self {
+                error: Some(ClientError {
+                    error: format_errors(&errors),
+                    output,
+                }),
+                ..Default::default()
+            }),
+        }
+    }
+
+    fn to_json(&self) -> String {
+        serde_json::to_string(self).expect("error serializing autoreload message")
+    }
+}
+
+/// Format the given error chain in the layout of the debug format of errors.
+fn format_errors(errors: &[String]) -> String {
+    let mut formatted = errors.first().cloned().unwrap_or_default();
+    if errors.len() > 1 {
+        formatted.push_str("\n\nCaused by:");
+        for (i, error) in errors[1..].iter().enumerate() {
+            formatted.push_str(&format!("\n    {}: {}", i, error));
+        }
+    }
+    formatted
+}
+
+/// Wait for the next tick of the given interval, or forever if there is none.
+async fn tick(interval: &mut Option<Interval>) {
+    match interval {
+        Some(interval) => {
+            interval.tick().await;
+        }
+        None => std::future::pending().await,
+    }
+}
+
 /// A result type used to work seamlessly with axum.
 pub(crate) type ServerResult<T> = std::result::Result<T, ServerError>;
 
@@ -373,3 +1003,822 @@ impl axum::response::IntoResponse for ServerError {
         res
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use axum::http::header::{ACCEPT_ENCODING, AUTHORIZATION, CONTENT_ENCODING};
+    use base64::engine::general_purpose::STANDARD;
+    use base64::Engine;
+    use futures_util::StreamExt;
+    use tower::ServiceExt;
+
+    use super::*;
+    use crate::config::ConfigOptsStatic;
+
+    /// Build the router of the given config, serving an output HTML, which is large enough to be
+    /// compressed.
+    async fn test_router(cfg: RtcServe) -> Result<Router> {
+        let dist = cfg.watch.build.final_dist.clone();
+        let html = format!("<html><body>{}</body></html>", "trunk ".repeat(64));
+        tokio::fs::write(dist.join(INDEX_HTML), html).await?;
+        let state = State::new(
+            dist,
+            cfg.watch.build.public_url.clone(),
+            &cfg,
+            broadcast::channel(1).0,
+            mpsc::channel(1).0,
+        );
+        router(Arc::new(state), Arc::new(cfg))
+    }
+
+    /// Spawn a server of the given router on a random local port.
+    fn spawn_router(router: Router) -> Result<std::net::SocketAddr> {
+        let listener = std::net::TcpListener::bind((Ipv4Addr::LOCALHOST, 0))?;
+        let addr = listener.local_addr()?;
+        tokio::spawn(Server::from_tcp(listener)?.serve(router.into_make_service()));
+        Ok(addr)
+    }
+
+    /// A buffer capturing the logs written while it is the default subscriber.
+    #[derive(Clone, Default)]
+    struct Logs(Arc<std::sync::Mutex<Vec<u8>>>);
+
+    impl Logs {
+        fn capture(&self) -> tracing::subscriber::DefaultGuard {
+            let logs = self.clone();
+            tracing::subscriber::set_default(
+                tracing_subscriber::fmt()
+                    .with_ansi(false)
+                    .with_writer(move || logs.clone())
+                    .finish(),
+            )
+        }
+
+        fn text(&self) -> String {
+            String::from_utf8_lossy(
+                &self
+                    .0
+                    .lock()
+                    .unwrap_or_else(std::sync::PoisonError::into_inner),
+            )
+            .into_owned()
+        }
+    }
+
+    impl std::io::Write for Logs {
+        fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+            self.0
+                .lock()
+                .unwrap_or_else(std::sync::PoisonError::into_inner)
+                .extend_from_slice(buf);
+            Ok(buf.len())
+        }
+
+        fn flush(&mut self) -> std::io::Result<()> {
+            Ok(())
+        }
+    }
+
+    /// Send a GET request for the given path to the router & read the response body.
+    async fn get_body(router: Router, path: &str) -> Result<(StatusCode, String)> {
+        let req = Request::get(path).body(Body::empty())?;
+        let res = router.oneshot(req).await?;
+        let status = res.status();
+        let body = hyper::body::to_bytes(res.into_body()).await?;
+        Ok((status, String::from_utf8(body.to_vec())?))
+    }
+
+    async fn get_gzip(router: Router) -> Result<Response> {
+        let req = Request::get("/index.html")
+            .header(ACCEPT_ENCODING, "gzip")
+            .body(Body::empty())?;
+        Ok(router.oneshot(req).await?)
+    }
+
+    #[tokio::test]
+    async fn applies_configured_headers_to_static_files() -> Result<()> {
+        // Assemble.
+        let tmpdir = tempfile::tempdir()?;
+        let mut cfg = RtcServe::new_test(tmpdir.path()).await?;
+        cfg.headers.insert(
+            "cross-origin-opener-policy",
+            HeaderValue::from_static("same-origin"),
+        );
+        let router = test_router(cfg).await?;
+
+        for path in ["/index.html", "/some/client/route"] {
+            // Action.
+            let req = Request::get(path).body(Body::empty())?;
+            let res = router.clone().oneshot(req).await?;
+
+            // Assert.
+            anyhow::ensure!(
+                res.status() == StatusCode::OK,
+                "unexpected status for {}",
+                path
+            );
+            anyhow::ensure!(
+                res.headers().get("cross-origin-opener-policy")
+                    == Some(&HeaderValue::from_static("same-origin")),
+                "expected the configured header for {}, got headers {:?}",
+                path,
+                res.headers()
+            );
+        }
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn compresses_responses_with_configured_algorithms() -> Result<()> {
+        // Assemble.
+        let tmpdir = tempfile::tempdir()?;
+        let mut cfg = RtcServe::new_test(tmpdir.path()).await?;
+        cfg.compression = vec![CompressionAlgorithm::Gzip];
+        let router = test_router(cfg).await?;
+
+        // Action.
+        let res = get_gzip(router).await?;
+
+        // Assert.
+        anyhow::ensure!(res.status() == StatusCode::OK);
+        anyhow::ensure!(
+            res.headers().get(CONTENT_ENCODING) == Some(&HeaderValue::from_static("gzip")),
+            "expected a gzip compressed response, got headers {:?}",
+            res.headers()
+        );
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn does_not_compress_responses_by_default() -> Result<()> {
+        // Assemble.
+        let tmpdir = tempfile::tempdir()?;
+        let cfg = RtcServe::new_test(tmpdir.path()).await?;
+        let router = test_router(cfg).await?;
+
+        // Action.
+        let res = get_gzip(router).await?;
+
+        // Assert.
+        anyhow::ensure!(res.status() == StatusCode::OK);
+        anyhow::ensure!(
+            res.headers().get(CONTENT_ENCODING).is_none(),
+            "expected an uncompressed response, got headers {:?}",
+            res.headers()
+        );
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn does_not_compress_with_other_algorithms() -> Result<()> {
+        // Assemble.
+        let tmpdir = tempfile::tempdir()?;
+        let mut cfg = RtcServe::new_test(tmpdir.path()).await?;
+        cfg.compression = vec![CompressionAlgorithm::Br];
+        let router = test_router(cfg).await?;
+
+        // Action.
+        let res = get_gzip(router).await?;
+
+        // Assert.
+        anyhow::ensure!(res.headers().get(CONTENT_ENCODING).is_none());
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn proxies_requests_using_http2_if_enabled() -> Result<()> {
+        // Assemble.
+        let backend = spawn_router(Router::new().route(
+            "/api/version",
+            get(|req: Request<Body>| async move { format!("{:?}", req.version()) }),
+        ))?;
+        let tmpdir = tempfile::tempdir()?;
+        let mut cfg = RtcServe::new_test(tmpdir.path()).await?;
+        cfg.proxies = Some(vec![
+            toml::from_str(&format!(r#"backend = "http://{}/api/""#, backend))?,
+            toml::from_str(&format!(
+                r#"
+                backend = "http://{}/api/"
+                rewrite = "/h2/"
+                http2 = true
+                "#,
+                backend
+            ))?,
+        ]);
+        let router = test_router(cfg).await?;
+
+        // Action.
+        let http1 = get_body(router.clone(), "/api/version").await?;
+        let http2 = get_body(router, "/h2/version").await?;
+
+        // Assert.
+        anyhow::ensure!(http1 == (StatusCode::OK, "HTTP/1.1".into()), "{:?}", http1);
+        anyhow::ensure!(http2 == (StatusCode::OK, "HTTP/2.0".into()), "{:?}", http2);
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn serves_static_dirs_alongside_dist_dir() -> Result<()> {
+        // Assemble.
+        let tmpdir = tempfile::tempdir()?;
+        let docs = tmpdir.path().join("book");
+        tokio::fs::create_dir(&docs).await?;
+        tokio::fs::write(docs.join("page.html"), "docs page").await?;
+        let mut cfg = RtcServe::new_test(tmpdir.path()).await?;
+        cfg.static_dirs = vec![ConfigOptsStatic {
+            path: "/docs/".into(),
+            dir: docs,
+        }];
+        let router = test_router(cfg).await?;
+
+        // Action.
+        let page = get_body(router.clone(), "/docs/page.html").await?;
+        let missing = get_body(router.clone(), "/docs/missing.html").await?;
+        let (index_status, index) = get_body(router, "/index.html").await?;
+
+        // Assert.
+        anyhow::ensure!(page == (StatusCode::OK, "docs page".into()), "{:?}", page);
+        anyhow::ensure!(missing.0 == StatusCode::NOT_FOUND, "{:?}", missing);
+        anyhow::ensure!(index_status == StatusCode::OK && index.starts_with("<html>"));
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn redirects_http_requests_to_https() -> Result<()> {
+        // Assemble.
+        let router = https_redirect_router(8443);
+        let req = Request::get("/some/path?query=1")
+            .header(HOST, "example.local:8080")
+            .body(Body::empty())?;
+
+        // Action.
+        let res = router.oneshot(req).await?;
+
+        // Assert.
+        anyhow::ensure!(res.status() == StatusCode::MOVED_PERMANENTLY);
+        anyhow::ensure!(
+            res.headers().get(LOCATION)
+                == Some(&HeaderValue::from_static(
+                    "https://example.local:8443/some/path?query=1"
+                )),
+            "unexpected headers {:?}",
+            res.headers()
+        );
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn pings_autoreload_websocket_clients() -> Result<()> {
+        use tokio_tungstenite::tungstenite::Message as WsMessage;
+
+        // Assemble.
+        let tmpdir = tempfile::tempdir()?;
+        let mut cfg = RtcServe::new_test(tmpdir.path()).await?;
+        cfg.ws_ping_interval = Some(Duration::from_millis(50));
+        let path = cfg.ws_path.clone();
+        let addr = spawn_router(test_router(cfg).await?)?;
+
+        // Action.
+        let url = format!("ws://{}{}", addr, path);
+        let (mut ws, _) = tokio_tungstenite::connect_async(url).await?;
+        let advice = ws.next().await.context("missing reconnect advice")??;
+        let ping = tokio::time::timeout(Duration::from_secs(5), ws.next())
+            .await?
+            .context("missing ping")??;
+
+        // Assert.
+        let WsMessage::Text(advice) = advice else {
+            anyhow::bail!("unexpected first message {:?}", advice);
+        };
+        let advice: serde_json::Value = serde_json::from_str(&advice)?;
+        anyhow::ensure!(
+            advice["reload"] == false
+                && advice["reconnect_interval"] == WS_RECONNECT_INTERVAL.as_millis() as u64,
+            "unexpected reconnect advice {}",
+            advice
+        );
+        anyhow::ensure!(matches!(ping, WsMessage::Ping(_)), "unexpected {:?}", ping);
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn serves_on_the_bound_free_port() -> Result<()> {
+        // Assemble.
+        let tmpdir = tempfile::tempdir()?;
+        let cfg = RtcServe::new_test(tmpdir.path()).await?;
+        let dist = cfg.watch.build.final_dist.clone();
+        tokio::fs::write(dist.join(INDEX_HTML), "<html></html>").await?;
+        let (shutdown_tx, shutdown_rx) = broadcast::channel(1);
+
+        // Action.
+        let (addr, server) = ServeSystem::spawn_server(
+            Arc::new(cfg),
+            shutdown_rx,
+            broadcast::channel(1).0,
+            mpsc::channel(1).0,
+        )
+        .await?;
+        let body = reqwest::get(format!("http://{}/", addr))
+            .await?
+            .text()
+            .await?;
+        drop(shutdown_tx);
+        server.await?;
+
+        // Assert.
+        anyhow::ensure!(addr.port() != 0, "unexpected port of {}", addr);
+        anyhow::ensure!(body == "<html></html>", "unexpected body {:?}", body);
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn taken_redirect_port_is_an_error() -> Result<()> {
+        // Assemble.
+        let tmpdir = tempfile::tempdir()?;
+        let mut cfg = RtcServe::new_test(tmpdir.path()).await?;
+        let cert = rcgen::generate_simple_self_signed(vec!["localhost".to_string()])?;
+        cfg.tls = Some(
+            axum_server::tls_rustls::RustlsConfig::from_pem(
+                cert.serialize_pem()?.into_bytes(),
+                cert.serialize_private_key_pem().into_bytes(),
+            )
+            .await?,
+        );
+        let taken = bind((cfg.address, 0).into())?;
+        cfg.tls_redirect_port = Some(taken.local_addr()?.port());
+
+        // Action.
+        let res = ServeSystem::spawn_server(
+            Arc::new(cfg),
+            broadcast::channel(1).1,
+            broadcast::channel(1).0,
+            mpsc::channel(1).0,
+        )
+        .await;
+
+        // Assert.
+        let err = res.expect_err("expected an error binding the redirect port");
+        anyhow::ensure!(
+            err.to_string().starts_with("error binding to"),
+            "unexpected error {:?}",
+            err
+        );
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn serves_static_files_on_a_free_port() -> Result<()> {
+        // Assemble.
+        let tmpdir = tempfile::tempdir()?;
+        tokio::fs::write(tmpdir.path().join("run.js"), "run();").await?;
+
+        // Action.
+        let (addr, server) = spawn_static_server(tmpdir.path())?;
+        let found = reqwest::get(format!("http://{}/run.js", addr)).await?;
+        let missing = reqwest::get(format!("http://{}/missing.js", addr)).await?;
+        server.abort();
+
+        // Assert.
+        anyhow::ensure!(
+            missing.status() == StatusCode::NOT_FOUND,
+            "unexpected status {}",
+            missing.status()
+        );
+        let body = found.text().await?;
+        anyhow::ensure!(body == "run();", "unexpected body {:?}", body);
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn serves_autoreload_websocket_at_configured_path() -> Result<()> {
+        // Assemble.
+        let tmpdir = tempfile::tempdir()?;
+        let mut cfg = RtcServe::new_test(tmpdir.path()).await?;
+        cfg.ws_path = "/dev/reload".into();
+        let addr = spawn_router(test_router(cfg).await?)?;
+
+        // Action.
+        let custom = tokio_tungstenite::connect_async(format!("ws://{}/dev/reload", addr)).await;
+        let default = tokio_tungstenite::connect_async(format!("ws://{}/_trunk/ws", addr)).await;
+
+        // Assert.
+        anyhow::ensure!(custom.is_ok(), "error connecting: {:?}", custom.err());
+        anyhow::ensure!(default.is_err(), "unexpected WebSocket at the default path");
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn proxies_inject_configured_headers() -> Result<()> {
+        // Assemble.
+        let backend = spawn_router(Router::new().route(
+            "/api/auth",
+            get(|headers: HeaderMap| async move {
+                let auth = headers.get("authorization").cloned();
+                ([("x-backend", "1")], format!("{:?}", auth))
+            }),
+        ))?;
+        let tmpdir = tempfile::tempdir()?;
+        let mut cfg = RtcServe::new_test(tmpdir.path()).await?;
+        cfg.proxies = Some(vec![toml::from_str(&format!(
+            r#"
+            backend = "http://{}/api/"
+            request_headers = {{ authorization = "Bearer token" }}
+            response_headers = {{ access-control-allow-origin = "*" }}
+            "#,
+            backend
+        ))?]);
+        let router = test_router(cfg).await?;
+
+        // Action.
+        let req = Request::get("/api/auth").body(Body::empty())?;
+        let res = router.oneshot(req).await?;
+
+        // Assert.
+        anyhow::ensure!(
+            res.headers().get("access-control-allow-origin")
+                == Some(&HeaderValue::from_static("*"))
+                && res.headers().contains_key("x-backend"),
+            "unexpected response headers {:?}",
+            res.headers()
+        );
+        let body = hyper::body::to_bytes(res.into_body()).await?;
+        anyhow::ensure!(
+            body == r#"Some("Bearer token")"#,
+            "unexpected request headers of the backend {:?}",
+            body
+        );
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn proxies_stream_server_sent_events_uncompressed() -> Result<()> {
+        use futures_util::stream;
+
+        // Assemble.
+        // The backend sends a single event & then keeps the stream open, as SSE endpoints do.
+        let backend = spawn_router(Router::new().route(
+            "/api/events",
+            get(|| async {
+                let events = stream::once(async {
+                    Ok::<_, std::io::Error>(bytes::Bytes::from_static(b"data: first\n\n"))
+                })
+                .chain(stream::pending());
+                (
+                    [(CONTENT_TYPE, "text/event-stream")],
+                    body::StreamBody::new(events),
+                )
+            }),
+        ))?;
+        let tmpdir = tempfile::tempdir()?;
+        let mut cfg = RtcServe::new_test(tmpdir.path()).await?;
+        cfg.compression = vec![CompressionAlgorithm::Gzip];
+        cfg.proxies = Some(vec![toml::from_str(&format!(
+            r#"backend = "http://{}/api/""#,
+            backend
+        ))?]);
+        let router = test_router(cfg).await?;
+
+        // Action.
+        let req = Request::get("/api/events")
+            .header(ACCEPT_ENCODING, "gzip")
+            .body(Body::empty())?;
+        let res = router.oneshot(req).await?;
+        let encoding = res.headers().get(CONTENT_ENCODING).cloned();
+        let mut body = res.into_body();
+        let first = tokio::time::timeout(
+            Duration::from_secs(5),
+            hyper::body::HttpBody::data(&mut body),
+        )
+        .await
+        .context("the first event was not streamed before the end of the response")?
+        .context("missing first event")??;
+
+        // Assert.
+        anyhow::ensure!(encoding.is_none(), "unexpected encoding {:?}", encoding);
+        anyhow::ensure!(first == "data: first\n\n", "unexpected event {:?}", first);
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn proxies_time_out_requests_to_hung_backends() -> Result<()> {
+        // Assemble.
+        let backend = spawn_router(
+            Router::new().route("/api/hung", get(std::future::pending::<&'static str>)),
+        )?;
+        let tmpdir = tempfile::tempdir()?;
+        let mut cfg = RtcServe::new_test(tmpdir.path()).await?;
+        cfg.proxies = Some(vec![toml::from_str(&format!(
+            r#"
+            backend = "http://{}/api/"
+            request_timeout = 1
+            "#,
+            backend
+        ))?]);
+        let router = test_router(cfg).await?;
+
+        // Action.
+        let res = tokio::time::timeout(Duration::from_secs(5), get_body(router, "/api/hung"))
+            .await
+            .context("the request to the hung backend did not time out")??;
+
+        // Assert.
+        anyhow::ensure!(res.0 == StatusCode::GATEWAY_TIMEOUT, "{:?}", res);
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn proxies_set_backend_preserved_or_overridden_host() -> Result<()> {
+        // Assemble.
+        let backend = spawn_router(
+            Router::new()
+                .fallback(|headers: HeaderMap| async move { format!("{:?}", headers.get(HOST)) }),
+        )?;
+        let tmpdir = tempfile::tempdir()?;
+        let mut cfg = RtcServe::new_test(tmpdir.path()).await?;
+        cfg.proxies = Some(
+            [
+                r#"rewrite = "/backend/""#,
+                r#"rewrite = "/preserve/"
+                preserve_host = true"#,
+                r#"rewrite = "/override/"
+                host = "example.local""#,
+            ]
+            .into_iter()
+            .map(|opts| toml::from_str(&format!("backend = \"http://{}/\"\n{}", backend, opts)))
+            .collect::<Result<_, _>>()?,
+        );
+        let router = test_router(cfg).await?;
+        let get_host = |path: &'static str| {
+            let router = router.clone();
+            async move {
+                let req = Request::get(path)
+                    .header(HOST, "localhost:8080")
+                    .body(Body::empty())?;
+                let body = hyper::body::to_bytes(router.oneshot(req).await?.into_body()).await?;
+                anyhow::Ok(String::from_utf8(body.to_vec())?)
+            }
+        };
+
+        // Action.
+        let backend_host = get_host("/backend/").await?;
+        let preserved = get_host("/preserve/").await?;
+        let overridden = get_host("/override/").await?;
+
+        // Assert.
+        anyhow::ensure!(backend_host == r#"Some("127.0.0.1")"#, "{}", backend_host);
+        anyhow::ensure!(preserved == r#"Some("localhost:8080")"#, "{}", preserved);
+        anyhow::ensure!(overridden == r#"Some("example.local")"#, "{}", overridden);
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn proxies_only_pass_redirects_through_if_rewriting_them() -> Result<()> {
+        // Assemble.
+        let backend = spawn_router(
+            Router::new()
+                .route(
+                    "/auth/login",
+                    get(|| async { (StatusCode::FOUND, [(LOCATION, "/auth/callback")]) }),
+                )
+                .route("/auth/callback", get(|| async { "callback" })),
+        )?;
+        let tmpdir = tempfile::tempdir()?;
+        let mut cfg = RtcServe::new_test(tmpdir.path()).await?;
+        cfg.proxies = Some(vec![
+            toml::from_str(&format!(r#"backend = "http://{}/auth/""#, backend))?,
+            toml::from_str(&format!(
+                r#"
+                backend = "http://{}/auth/"
+                rewrite = "/api/auth/"
+                rewrite_location = true
+                "#,
+                backend
+            ))?,
+        ]);
+        let router = test_router(cfg).await?;
+
+        // Action.
+        let followed = get_body(router.clone(), "/auth/login").await?;
+        let req = Request::get("/api/auth/login").body(Body::empty())?;
+        let rewritten = router.oneshot(req).await?;
+
+        // Assert.
+        anyhow::ensure!(
+            followed == (StatusCode::OK, "callback".into()),
+            "{:?}",
+            followed
+        );
+        anyhow::ensure!(
+            rewritten.status() == StatusCode::FOUND
+                && rewritten.headers().get(LOCATION)
+                    == Some(&HeaderValue::from_static("/api/auth/callback")),
+            "unexpected redirect {:?}",
+            rewritten
+        );
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn proxies_log_requests_if_enabled() -> Result<()> {
+        // Assemble.
+        let backend = spawn_router(Router::new().fallback(|| async { "response body" }))?;
+        let tmpdir = tempfile::tempdir()?;
+        let mut cfg = RtcServe::new_test(tmpdir.path()).await?;
+        cfg.proxies = Some(vec![
+            toml::from_str(&format!(
+                r#"
+                backend = "http://{}/logged/"
+                log = "body"
+                "#,
+                backend
+            ))?,
+            toml::from_str(&format!(r#"backend = "http://{}/quiet/""#, backend))?,
+        ]);
+        let router = test_router(cfg).await?;
+        let logs = Logs::default();
+        let _guard = logs.capture();
+
+        // Action.
+        get_body(router.clone(), "/quiet/").await?;
+        let quiet = logs.text();
+        get_body(router, "/logged/").await?;
+        let logged = logs.text();
+
+        // Assert.
+        anyhow::ensure!(!quiet.contains("proxied"), "unexpected logs {}", quiet);
+        anyhow::ensure!(
+            logged.contains(&format!("proxied GET http://{}/logged/ -> 200 OK", backend))
+                && logged.contains("proxy request headers")
+                && logged.contains("proxy response body")
+                && logged.contains("response body"),
+            "missing logs of the proxied request {}",
+            logged
+        );
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn triggers_builds_with_the_build_token() -> Result<()> {
+        // Assemble.
+        let tmpdir = tempfile::tempdir()?;
+        let mut cfg = RtcServe::new_test(tmpdir.path()).await?;
+        cfg.basic_auth = Some(("trunk".into(), "password".into()));
+        cfg.build_token = Some("token".into());
+        let (build_trigger, mut triggers) = mpsc::channel(1);
+        let state = State::new(
+            cfg.watch.build.final_dist.clone(),
+            cfg.watch.build.public_url.clone(),
+            &cfg,
+            broadcast::channel(1).0,
+            build_trigger,
+        );
+        let router = router(Arc::new(state), Arc::new(cfg))?;
+        let trigger = |authorization: Option<&str>| {
+            let mut req = Request::post(BUILD_PATH);
+            if let Some(authorization) = authorization {
+                req = req.header(AUTHORIZATION, authorization);
+            }
+            router
+                .clone()
+                .oneshot(req.body(Body::empty()).expect("error building request"))
+        };
+
+        // Action.
+        let unauthorized = trigger(None).await?.status();
+        let basic_auth = format!("Basic {}", STANDARD.encode("trunk:password"));
+        let with_basic_auth = trigger(Some(&basic_auth)).await?.status();
+        let wrong_token = trigger(Some("Bearer other")).await?.status();
+        let no_build_triggered = triggers.try_recv().is_err();
+        let triggered = trigger(Some("Bearer token")).await?.status();
+        let pending = trigger(Some("Bearer token")).await?.status();
+
+        // Assert.
+        anyhow::ensure!(unauthorized == StatusCode::UNAUTHORIZED);
+        anyhow::ensure!(
+            with_basic_auth == StatusCode::UNAUTHORIZED,
+            "basic auth must not authorize builds"
+        );
+        anyhow::ensure!(wrong_token == StatusCode::UNAUTHORIZED);
+        anyhow::ensure!(no_build_triggered, "unauthorized requests must not build");
+        anyhow::ensure!(triggered == StatusCode::ACCEPTED);
+        anyhow::ensure!(
+            pending == StatusCode::ACCEPTED,
+            "triggers must be accepted while a build is pending"
+        );
+        anyhow::ensure!(triggers.try_recv().is_ok(), "a build must be triggered");
+        anyhow::ensure!(
+            triggers.try_recv().is_err(),
+            "pending builds must not be triggered twice"
+        );
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn serves_precompressed_siblings_to_accepting_clients() -> Result<()> {
+        // Assemble.
+        let tmpdir = tempfile::tempdir()?;
+        let cfg = RtcServe::new_test(tmpdir.path()).await?;
+        let dist = cfg.watch.build.final_dist.clone();
+        let router = test_router(cfg).await?;
+        tokio::fs::write(dist.join("app.js"), "plain").await?;
+        tokio::fs::write(dist.join("app.js.gz"), "gzipped").await?;
+        let get = |accept_encoding: &'static str| {
+            let req = Request::get("/app.js")
+                .header(ACCEPT_ENCODING, accept_encoding)
+                .body(Body::empty())
+                .expect("error building request");
+            router.clone().oneshot(req)
+        };
+
+        // Action.
+        let gzip = get("gzip").await?;
+        let identity = get("identity").await?;
+
+        // Assert.
+        anyhow::ensure!(
+            gzip.headers().get(CONTENT_ENCODING) == Some(&HeaderValue::from_static("gzip"))
+        );
+        anyhow::ensure!(hyper::body::to_bytes(gzip.into_body()).await? == "gzipped");
+        anyhow::ensure!(identity.headers().get(CONTENT_ENCODING).is_none());
+        anyhow::ensure!(hyper::body::to_bytes(identity.into_body()).await? == "plain");
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn isolates_the_dist_and_static_dirs_cross_origin() -> Result<()> {
+        // Assemble.
+        let tmpdir = tempfile::tempdir()?;
+        let docs = tmpdir.path().join("book");
+        tokio::fs::create_dir(&docs).await?;
+        tokio::fs::write(docs.join("page.html"), "docs page").await?;
+        let mut cfg = RtcServe::new_test(tmpdir.path()).await?;
+        cfg.cross_origin_isolation = true;
+        cfg.headers = cross_origin_isolation_headers();
+        cfg.static_dirs = vec![ConfigOptsStatic {
+            path: "/docs/".into(),
+            dir: docs,
+        }];
+        let router = test_router(cfg).await?;
+
+        for path in ["/index.html", "/some/client/route", "/docs/page.html"] {
+            // Action.
+            let req = Request::get(path).body(Body::empty())?;
+            let res = router.clone().oneshot(req).await?;
+
+            // Assert.
+            anyhow::ensure!(
+                res.status() == StatusCode::OK,
+                "unexpected status for {}",
+                path
+            );
+            anyhow::ensure!(
+                res.headers().get("cross-origin-opener-policy")
+                    == Some(&HeaderValue::from_static("same-origin"))
+                    && res.headers().get("cross-origin-embedder-policy")
+                        == Some(&HeaderValue::from_static("require-corp")),
+                "expected the isolation headers for {}, got headers {:?}",
+                path,
+                res.headers()
+            );
+        }
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn overrides_mime_types_unless_falling_back_to_the_index() -> Result<()> {
+        // Assemble.
+        let tmpdir = tempfile::tempdir()?;
+        let mut cfg = RtcServe::new_test(tmpdir.path()).await?;
+        cfg.mime_types
+            .insert("glb".into(), HeaderValue::from_static("model/gltf-binary"));
+        tokio::fs::write(cfg.watch.build.final_dist.join("scene.GLB"), "glTF").await?;
+        let router = test_router(cfg).await?;
+
+        for (path, mime_type) in [
+            ("/scene.GLB", "model/gltf-binary"),
+            ("/missing.glb", "text/html"),
+        ] {
+            // Action.
+            let req = Request::get(path).body(Body::empty())?;
+            let res = router.clone().oneshot(req).await?;
+
+            // Assert.
+            anyhow::ensure!(
+                res.status() == StatusCode::OK,
+                "unexpected status for {}",
+                path
+            );
+            anyhow::ensure!(
+                res.headers()
+                    .get(CONTENT_TYPE)
+                    .and_then(|value| value.to_str().ok())
+                    .is_some_and(|value| value.starts_with(mime_type)),
+                "expected the MIME type {} for {}, got headers {:?}",
+                mime_type,
+                path,
+                res.headers()
+            );
+        }
+        Ok(())
+    }
+}
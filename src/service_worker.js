@@ -0,0 +1,39 @@
+// Service worker generated by Trunk, which precaches the build artifacts for offline support.
+var CACHE = '{{__TRUNK_CACHE__}}';
+var PRECACHE = {{__TRUNK_PRECACHE__}};
+var INDEX = '{{__TRUNK_INDEX__}}';
+
+self.addEventListener('install', (event) => {
+    event.waitUntil(
+        caches.open(CACHE)
+            .then((cache) => cache.addAll(PRECACHE))
+            .then(() => self.skipWaiting())
+    );
+});
+
+self.addEventListener('activate', (event) => {
+    // Remove the caches of previous builds.
+    event.waitUntil(
+        caches.keys()
+            .then((keys) => Promise.all(
+                keys
+                    .filter((key) => key.startsWith('trunk-') && key !== CACHE)
+                    .map((key) => caches.delete(key))
+            ))
+            .then(() => self.clients.claim())
+    );
+});
+
+self.addEventListener('fetch', (event) => {
+    var request = event.request;
+    if (request.method !== 'GET' || new URL(request.url).origin !== self.location.origin) {
+        return;
+    }
+    if (request.mode === 'navigate') {
+        // Prefer fresh pages, falling back to the cached index while offline.
+        event.respondWith(fetch(request).catch(() => caches.match(INDEX)));
+        return;
+    }
+    // The build artifacts are hashed, so that their cached versions can be served right away.
+    event.respondWith(caches.match(request).then((response) => response || fetch(request)));
+});
@@ -0,0 +1,560 @@
+//! Reports of the sizes of the build outputs.
+
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::fmt::Write;
+use std::path::PathBuf;
+
+use anyhow::{ensure, Context, Result};
+use regex::Regex;
+use serde::{Deserialize, Serialize};
+use tokio::fs;
+
+use crate::build::compress;
+use crate::common::escape_html;
+use crate::config::{CompressionAlgorithm, FileHashLayout, RtcBuild};
+
+/// The gzip level with which the sizes of outputs without precompressed siblings are estimated.
+const ESTIMATE_GZIP_LEVEL: u32 = 6;
+/// The brotli level with which the sizes of outputs without precompressed siblings are estimated.
+const ESTIMATE_BROTLI_LEVEL: u32 = 5;
+
+/// The raw & compressed sizes of a build output, in bytes.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Sizes {
+    pub raw: u64,
+    pub gzip: u64,
+    pub brotli: u64,
+}
+
+impl Sizes {
+    /// The changes of these sizes from the given previous sizes.
+    fn delta(&self, previous: &Sizes) -> SizesDelta {
+        SizesDelta {
+            raw: self.raw as i64 - previous.raw as i64,
+            gzip: self.gzip as i64 - previous.gzip as i64,
+            brotli: self.brotli as i64 - previous.brotli as i64,
+        }
+    }
+}
+
+/// The changes of the sizes of a build output since the previous build, in bytes.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct SizesDelta {
+    pub raw: i64,
+    pub gzip: i64,
+    pub brotli: i64,
+}
+
+/// The size of a section of a wasm module, in bytes.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct WasmSection {
+    pub name: String,
+    pub size: u64,
+}
+
+/// The sizes of a build output.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ArtifactReport {
+    /// The path of the output, relative to the dist dir.
+    pub path: String,
+    /// The path of the output without its content hash, which identifies it across builds.
+    pub name: String,
+    #[serde(flatten)]
+    pub sizes: Sizes,
+    /// The changes of the sizes since the previous build, unless the output is new.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub delta: Option<SizesDelta>,
+    /// The sizes of the sections of a wasm module, largest first.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub wasm_sections: Vec<WasmSection>,
+}
+
+/// A report of the sizes of all outputs of a build.
+#[derive(Clone, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct SizeReport {
+    /// The outputs, largest first.
+    pub artifacts: Vec<ArtifactReport>,
+    /// The total sizes of all outputs.
+    pub total: Sizes,
+    /// The changes of the total sizes since the previous build, if any.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub total_delta: Option<SizesDelta>,
+}
+
+/// Report the sizes of the given outputs of a build in the final dist dir, keyed by their path
+/// relative to it, comparing them to those of the previous build.
+///
+/// The report is printed, written to the configured size report file, if any, and kept in the
+/// cargo target dir for comparison with the next build.
+pub async fn report_sizes(cfg: &RtcBuild, paths: impl Iterator<Item = &String>) -> Result<()> {
+    let mut report = SizeReport::generate(cfg, paths).await?;
+
+    let previous_path = previous_report_path(cfg);
+    if let Ok(previous) = fs::read(&previous_path).await {
+        match serde_json::from_slice(&previous) {
+            Ok(previous) => report.compare(&previous),
+            Err(err) => tracing::debug!(?err, "error parsing previous size report"),
+        }
+    }
+    tracing::info!("output sizes\n{}", report.to_table());
+
+    let json = serde_json::to_vec_pretty(&report).context("error serializing size report")?;
+    if let Some(file) = cfg.size_report_file.as_deref() {
+        let contents = match file.extension().and_then(|ext| ext.to_str()) {
+            Some("html") => report.to_html().into_bytes(),
+            _ => json.clone(),
+        };
+        fs::write(file, contents)
+            .await
+            .with_context(|| format!("error writing size report to {:?}", file))?;
+    }
+    if let Some(parent) = previous_path.parent() {
+        fs::create_dir_all(parent)
+            .await
+            .context("error creating dir of previous size reports")?;
+    }
+    fs::write(&previous_path, json)
+        .await
+        .context("error keeping size report for comparison with the next build")?;
+    Ok(())
+}
+
+/// The size of the given bytes compressed using the given algorithm, which is the size of the
+/// given precompressed sibling if any, or else estimated with a fast compression level, as the
+/// highest levels take seconds for large wasm modules on each build.
+fn compressed_size(
+    bytes: &[u8],
+    precompressed: Option<PathBuf>,
+    algorithm: CompressionAlgorithm,
+) -> Result<u64> {
+    if let Some(file) = precompressed {
+        let meta = std::fs::metadata(&file).with_context(|| format!("error reading {:?}", file))?;
+        return Ok(meta.len());
+    }
+    let level = match algorithm {
+        CompressionAlgorithm::Br => ESTIMATE_BROTLI_LEVEL,
+        _ => ESTIMATE_GZIP_LEVEL,
+    };
+    Ok(compress(bytes, algorithm, Some(level))?.len() as u64)
+}
+
+/// The path at which the size report of the previous build into the configured dist dir is kept.
+fn previous_report_path(cfg: &RtcBuild) -> PathBuf {
+    let dist_hash = seahash::hash(cfg.final_dist.to_string_lossy().as_bytes());
+    cfg.reports_dir()
+        .join(format!("size-report-{:x}.json", dist_hash))
+}
+
+impl SizeReport {
+    /// Measure the given outputs in the final dist dir, skipping their precompressed siblings.
+    async fn generate(cfg: &RtcBuild, paths: impl Iterator<Item = &String>) -> Result<Self> {
+        let paths = paths.collect::<HashSet<_>>();
+        let hash_pattern = hash_pattern(cfg);
+        let mut tasks = Vec::new();
+        for path in paths.iter().copied() {
+            let is_precompressed = [".gz", ".br"].iter().any(|ext| {
+                path.strip_suffix(ext)
+                    .is_some_and(|original| paths.contains(&original.to_owned()))
+            });
+            if is_precompressed {
+                continue;
+            }
+            let file = cfg.final_dist.join(path);
+            // The sizes of the precompressed siblings are reported as they are, if any.
+            let sibling = |ext: &str| {
+                let sibling = format!("{}.{}", path, ext);
+                paths
+                    .contains(&sibling)
+                    .then(|| cfg.final_dist.join(sibling))
+            };
+            let (gzip_file, brotli_file) = (sibling("gz"), sibling("br"));
+            let path = path.clone();
+            let name = match &hash_pattern {
+                Some(pattern) => pattern.replace(&path, "$rest").into_owned(),
+                None => path.clone(),
+            };
+            tasks.push(tokio::task::spawn_blocking(move || {
+                let bytes =
+                    std::fs::read(&file).with_context(|| format!("error reading {:?}", file))?;
+                let wasm_sections = if path.ends_with(".wasm") {
+                    wasm_sections(&bytes)
+                        .with_context(|| format!("error reading sections of {:?}", file))?
+                } else {
+                    Vec::new()
+                };
+                Ok::<_, anyhow::Error>(ArtifactReport {
+                    path,
+                    name,
+                    sizes: Sizes {
+                        raw: bytes.len() as u64,
+                        gzip: compressed_size(&bytes, gzip_file, CompressionAlgorithm::Gzip)?,
+                        brotli: compressed_size(&bytes, brotli_file, CompressionAlgorithm::Br)?,
+                    },
+                    delta: None,
+                    wasm_sections,
+                })
+            }));
+        }
+
+        let mut report = Self::default();
+        for task in tasks {
+            let artifact = task
+                .await
+                .context("error awaiting spawned output measurement")??;
+            report.total.raw += artifact.sizes.raw;
+            report.total.gzip += artifact.sizes.gzip;
+            report.total.brotli += artifact.sizes.brotli;
+            report.artifacts.push(artifact);
+        }
+        report.artifacts.sort_by(|a, b| {
+            b.sizes
+                .raw
+                .cmp(&a.sizes.raw)
+                .then_with(|| a.path.cmp(&b.path))
+        });
+        Ok(report)
+    }
+
+    /// Set the changes of the sizes since the given report of the previous build.
+    pub fn compare(&mut self, previous: &SizeReport) {
+        let previous_sizes = previous
+            .artifacts
+            .iter()
+            .map(|artifact| (artifact.name.as_str(), &artifact.sizes))
+            .collect::<HashMap<_, _>>();
+        for artifact in &mut self.artifacts {
+            artifact.delta = previous_sizes
+                .get(artifact.name.as_str())
+                .map(|sizes| artifact.sizes.delta(sizes));
+        }
+        self.total_delta = Some(self.total.delta(&previous.total));
+    }
+
+    /// Render the report as a table for the terminal.
+    pub fn to_table(&self) -> String {
+        let mut rows = vec![[
+            "file".to_owned(),
+            "raw".to_owned(),
+            "gzip".to_owned(),
+            "brotli".to_owned(),
+            "change (gzip)".to_owned(),
+        ]];
+        for artifact in &self.artifacts {
+            rows.push([
+                artifact.path.clone(),
+                format_size(artifact.sizes.raw),
+                format_size(artifact.sizes.gzip),
+                format_size(artifact.sizes.brotli),
+                match artifact.delta {
+                    Some(delta) => format_delta(delta.gzip),
+                    None if self.total_delta.is_some() => "new".to_owned(),
+                    None => String::new(),
+                },
+            ]);
+            for section in &artifact.wasm_sections {
+                rows.push([
+                    format!("  {}", section.name),
+                    format_size(section.size),
+                    String::new(),
+                    String::new(),
+                    String::new(),
+                ]);
+            }
+        }
+        rows.push([
+            "total".to_owned(),
+            format_size(self.total.raw),
+            format_size(self.total.gzip),
+            format_size(self.total.brotli),
+            self.total_delta
+                .map(|delta| format_delta(delta.gzip))
+                .unwrap_or_default(),
+        ]);
+
+        let mut widths = [0; 5];
+        for row in &rows {
+            for (width, cell) in widths.iter_mut().zip(row) {
+                *width = (*width).max(cell.chars().count());
+            }
+        }
+        let mut table = String::new();
+        for row in &rows {
+            let _ = write!(table, "{:<width$}", row[0], width = widths[0]);
+            for (width, cell) in widths.iter().zip(row).skip(1) {
+                let _ = write!(table, "  {:>width$}", cell, width = width);
+            }
+            table.truncate(table.trim_end().len());
+            table.push('\n');
+        }
+        table.truncate(table.trim_end().len());
+        table
+    }
+
+    /// Render the report as a standalone HTML page.
+    pub fn to_html(&self) -> String {
+        let delta_cells = |delta: Option<SizesDelta>| match delta {
+            Some(delta) => format!(
+                "<td>{}</td><td>{}</td><td>{}</td>",
+                format_delta(delta.raw),
+                format_delta(delta.gzip),
+                format_delta(delta.brotli)
+            ),
+            None => "<td></td><td></td><td></td>".to_owned(),
+        };
+        let mut rows = String::new();
+        for artifact in &self.artifacts {
+            let _ = writeln!(
+                rows,
+                "<tr><td>{}</td><td>{}</td><td>{}</td><td>{}</td>{}</tr>",
+                escape_html(&artifact.path),
+                format_size(artifact.sizes.raw),
+                format_size(artifact.sizes.gzip),
+                format_size(artifact.sizes.brotli),
+                delta_cells(artifact.delta)
+            );
+            for section in &artifact.wasm_sections {
+                let _ = writeln!(
+                    rows,
+                    "<tr class=\"section\"><td>{}</td><td>{}</td><td colspan=\"5\"></td></tr>",
+                    escape_html(&section.name),
+                    format_size(section.size)
+                );
+            }
+        }
+        let _ = writeln!(
+            rows,
+            "<tr class=\"total\"><td>total</td><td>{}</td><td>{}</td><td>{}</td>{}</tr>",
+            format_size(self.total.raw),
+            format_size(self.total.gzip),
+            format_size(self.total.brotli),
+            delta_cells(self.total_delta)
+        );
+        format!(
+            r#"<!DOCTYPE html>
+<html>
+<head>
+<meta charset="utf-8">
+<title>Trunk size report</title>
+<style>
+body {{ font-family: sans-serif; }}
+table {{ border-collapse: collapse; }}
+th, td {{ padding: 0.25em 0.75em; text-align: right; }}
+th:first-child, td:first-child {{ text-align: left; }}
+tr.section td:first-child {{ padding-left: 2em; color: #666; }}
+tr.total {{ font-weight: bold; border-top: 1px solid #999; }}
+</style>
+</head>
+<body>
+<table>
+<tr><th>file</th><th>raw</th><th>gzip</th><th>brotli</th><th>change (raw)</th><th>change (gzip)</th><th>change (brotli)</th></tr>
+{}</table>
+</body>
+</html>
+"#,
+            rows
+        )
+    }
+}
+
+/// Build the pattern matching the content hash in the path of an output, which is captured
+/// around it as `rest`, if file hashing is enabled.
+pub(crate) fn hash_pattern(cfg: &RtcBuild) -> Option<Regex> {
+    if !cfg.filehash {
+        return None;
+    }
+    // The hex digits of a hash may be fewer than its length, if it has leading zeros.
+    let max = cfg.filehash_length.unwrap_or(16);
+    let min = max.saturating_sub(2).max(1);
+    let pattern = match cfg.filehash_layout {
+        FileHashLayout::Suffix => format!(
+            r"-[0-9a-f]{{{},{}}}(?P<rest>(?:_bg)?(?:\.[^/.]+)*)$",
+            min, max
+        ),
+        FileHashLayout::Dir => format!(r"^(?P<rest>assets/)[0-9a-f]{{{},{}}}/", min, max),
+    };
+    Regex::new(&pattern).ok()
+}
+
+/// Read the payload sizes of the sections of the given wasm module, summed by section type &
+/// the name of custom sections, largest first.
+pub fn wasm_sections(bytes: &[u8]) -> Result<Vec<WasmSection>> {
+    ensure!(
+        bytes.len() >= 8 && bytes.starts_with(b"\0asm"),
+        "not a wasm module"
+    );
+    let mut sizes = BTreeMap::<String, u64>::new();
+    let mut pos = 8;
+    while let Some(id) = bytes.get(pos).copied() {
+        pos += 1;
+        let size = read_leb128(bytes, &mut pos)? as usize;
+        let end = pos
+            .checked_add(size)
+            .filter(|end| *end <= bytes.len())
+            .context("wasm section exceeds the module")?;
+        let name = match id {
+            0 => {
+                let mut name_pos = pos;
+                let len = read_leb128(bytes, &mut name_pos)? as usize;
+                let name = bytes
+                    .get(name_pos..name_pos + len)
+                    .context("invalid name of wasm custom section")?;
+                format!("custom \"{}\"", String::from_utf8_lossy(name))
+            }
+            1 => "type".to_owned(),
+            2 => "import".to_owned(),
+            3 => "function".to_owned(),
+            4 => "table".to_owned(),
+            5 => "memory".to_owned(),
+            6 => "global".to_owned(),
+            7 => "export".to_owned(),
+            8 => "start".to_owned(),
+            9 => "element".to_owned(),
+            10 => "code".to_owned(),
+            11 => "data".to_owned(),
+            12 => "data count".to_owned(),
+            13 => "tag".to_owned(),
+            id => format!("unknown ({})", id),
+        };
+        *sizes.entry(name).or_default() += size as u64;
+        pos = end;
+    }
+    let mut sections = sizes
+        .into_iter()
+        .map(|(name, size)| WasmSection { name, size })
+        .collect::<Vec<_>>();
+    sections.sort_by(|a, b| b.size.cmp(&a.size).then_with(|| a.name.cmp(&b.name)));
+    Ok(sections)
+}
+
+/// Read an unsigned 32-bit LEB128 integer at the given position, advancing it.
+fn read_leb128(bytes: &[u8], pos: &mut usize) -> Result<u32> {
+    let mut value = 0u32;
+    for shift in (0..35).step_by(7) {
+        let byte = *bytes.get(*pos).context("truncated wasm module")?;
+        *pos += 1;
+        value |= u32::from(byte & 0x7f) << shift;
+        if byte & 0x80 == 0 {
+            return Ok(value);
+        }
+    }
+    anyhow::bail!("invalid LEB128 integer in wasm module")
+}
+
+/// Format the given size in bytes for humans.
+pub(crate) fn format_size(size: u64) -> String {
+    match size {
+        0..=1023 => format!("{} B", size),
+        1024..=1_048_575 => format!("{:.1} KiB", size as f64 / 1024.0),
+        _ => format!("{:.1} MiB", size as f64 / 1_048_576.0),
+    }
+}
+
+/// Format the given change of a size in bytes for humans.
+fn format_delta(delta: i64) -> String {
+    match delta {
+        0 => "±0".to_owned(),
+        delta if delta > 0 => format!("+{}", format_size(delta.unsigned_abs())),
+        delta => format!("-{}", format_size(delta.unsigned_abs())),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use anyhow::{ensure, Context, Result};
+
+    use super::*;
+
+    #[test]
+    fn wasm_sections_are_summed_by_type() -> Result<()> {
+        let module = [
+            b"\0asm\x01\0\0\0".as_slice(),
+            // A type section of 4 bytes.
+            &[1, 4, 1, 0x60, 0, 0],
+            // A custom `name` section of 6 bytes.
+            &[0, 6, 4, b'n', b'a', b'm', b'e', 0],
+            // Two code sections of 2 & 3 bytes.
+            &[10, 2, 0, 0],
+            &[10, 3, 0, 0, 0],
+        ]
+        .concat();
+        let sections = wasm_sections(&module)?;
+        let expected = [("custom \"name\"", 6), ("code", 5), ("type", 4)];
+        ensure!(
+            sections.len() == expected.len()
+                && sections
+                    .iter()
+                    .zip(expected)
+                    .all(|(section, (name, size))| section.name == name && section.size == size),
+            "unexpected sections {:?}",
+            sections
+        );
+        ensure!(wasm_sections(b"\0asm\x01\0\0\0\x0a\x05\0").is_err());
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn outputs_are_compared_by_name_without_hash() -> Result<()> {
+        // Assemble.
+        let tmpdir = tempfile::tempdir().context("error building tempdir for test")?;
+        let mut cfg = RtcBuild::new_test(tmpdir.path()).await?;
+        cfg.filehash_length = Some(8);
+        std::fs::create_dir_all(&cfg.final_dist)?;
+        std::fs::write(
+            cfg.final_dist.join("app-0123abcd_bg.wasm"),
+            b"\0asm\x01\0\0\0",
+        )?;
+        std::fs::write(cfg.final_dist.join("index.html"), "<html></html>")?;
+        std::fs::write(cfg.final_dist.join("index.html.gz"), "precompressed")?;
+        let paths = ["app-0123abcd_bg.wasm", "index.html", "index.html.gz"].map(String::from);
+        let previous = SizeReport {
+            artifacts: vec![ArtifactReport {
+                path: "app-89ef4567_bg.wasm".into(),
+                name: "app_bg.wasm".into(),
+                sizes: Sizes {
+                    raw: 10,
+                    gzip: 10,
+                    brotli: 10,
+                },
+                delta: None,
+                wasm_sections: Vec::new(),
+            }],
+            total: Sizes::default(),
+            total_delta: None,
+        };
+
+        // Action.
+        let mut report = SizeReport::generate(&cfg, paths.iter()).await?;
+        report.compare(&previous);
+
+        // Assert.
+        ensure!(
+            report.artifacts.len() == 2,
+            "unexpected report {:?}",
+            report
+        );
+        let wasm = report
+            .artifacts
+            .iter()
+            .find(|artifact| artifact.name == "app_bg.wasm")
+            .context("missing wasm output")?;
+        ensure!(
+            wasm.delta.map(|delta| delta.raw) == Some(-2),
+            "unexpected delta {:?}",
+            wasm.delta
+        );
+        let html = report
+            .artifacts
+            .iter()
+            .find(|artifact| artifact.name == "index.html")
+            .context("missing html output")?;
+        ensure!(html.delta.is_none(), "unexpected delta {:?}", html.delta);
+        ensure!(
+            html.sizes.gzip == "precompressed".len() as u64,
+            "the size of the precompressed sibling must be reported, got {:?}",
+            html.sizes
+        );
+        ensure!(html.sizes.brotli > 0, "unexpected sizes {:?}", html.sizes);
+        ensure!(report.to_table().contains("new"), "{}", report.to_table());
+        Ok(())
+    }
+}
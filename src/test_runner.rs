@@ -0,0 +1,369 @@
+//! Running the wasm tests of a crate in a headless browser.
+
+use std::net::SocketAddr;
+use std::path::{Path, PathBuf};
+use std::process::Stdio;
+use std::time::Duration;
+
+use anyhow::{anyhow, bail, Context, Result};
+use tokio::fs;
+
+use crate::config::{CargoEnv, CargoFlags, CargoMetadata, CargoProfile, ConfigOptsTools, Features};
+use crate::messages::{self, Message};
+use crate::pipelines::find_wasm_bindgen_version;
+use crate::tools::{self, Application};
+use crate::webdriver::{Browser, Driver, Session};
+use crate::{common, serve};
+
+/// The dir of cargo's target dir in which the test harnesses are generated.
+const TEST_DIR: &str = "trunk-test";
+/// The prefix of the exports of the tests of wasm-bindgen-test.
+const TEST_PREFIX: &[u8] = b"__wbgt_";
+/// The marker of the output of a harness whose tests could not be run.
+const ERROR_MARKER: &str = "error running tests: ";
+/// The interval in which the output of the tests is polled.
+const POLL_INTERVAL: Duration = Duration::from_millis(100);
+
+/// The HTML page of a test harness, capturing the console output for the test context.
+const HARNESS_HTML: &str = r#"<!DOCTYPE html>
+<html>
+  <head>
+    <meta charset="utf-8" />
+    <script>
+      for (const method of ["debug", "log", "info", "warn", "error"]) {
+        const original = console[method];
+        console[method] = function (...args) {
+          original.apply(this, args);
+          const hook = window[`on_console_${method}`];
+          if (hook) hook(args);
+        };
+      }
+      window.__wbg_test_invoke = (f) => f();
+    </script>
+  </head>
+  <body>
+    <pre id="output">Loading scripts...</pre>
+    <script type="module" src="run.js"></script>
+  </body>
+</html>
+"#;
+
+/// Everything needed to build & run the wasm tests of a crate.
+pub struct TestSystem {
+    /// The cargo metadata of the crate.
+    pub manifest: CargoMetadata,
+    /// The cargo profile to build the tests with.
+    pub cargo_profile: CargoProfile,
+    /// The flags of the cargo invocation, e.g. `--locked`.
+    pub cargo_flags: CargoFlags,
+    /// The env vars of the cargo invocation, e.g. the `RUSTFLAGS`.
+    pub cargo_env: CargoEnv,
+    /// The cargo features to build the tests with.
+    pub cargo_features: Features,
+    /// The config of the tools, to find wasm-bindgen.
+    pub tools: ConfigOptsTools,
+    /// The browser to run the tests in.
+    pub browser: Browser,
+    /// The URL of a running WebDriver server, instead of spawning one.
+    pub webdriver_url: Option<String>,
+    /// Run the browser headless.
+    pub headless: bool,
+    /// The time to wait for the tests of a test binary to finish.
+    pub timeout: Duration,
+    /// The args passed to the tests, filtering the tests to run.
+    pub args: Vec<String>,
+}
+
+impl TestSystem {
+    /// Build the tests & run them in the browser, failing if any of them fails.
+    pub async fn run(self) -> Result<()> {
+        let binaries = self.cargo_build().await?;
+        if binaries.is_empty() {
+            tracing::info!("no wasm tests to run");
+            return Ok(());
+        }
+
+        let version = find_wasm_bindgen_version(&self.tools, &self.manifest);
+        let wasm_bindgen =
+            tools::get(Application::WasmBindgen, version.as_deref(), &self.tools).await?;
+        let test_dir = self
+            .manifest
+            .metadata
+            .target_directory
+            .as_std_path()
+            .join(TEST_DIR);
+        let mut harnesses = Vec::new();
+        for (name, wasm) in &binaries {
+            // Binaries without any wasm-bindgen tests lack the test context to run them.
+            let bytes = fs::read(wasm)
+                .await
+                .with_context(|| format!("error reading test binary {:?}", wasm))?;
+            if !bytes
+                .windows(TEST_PREFIX.len())
+                .any(|window| window == TEST_PREFIX)
+            {
+                tracing::debug!("skipping {}, which has no wasm-bindgen tests", name);
+                continue;
+            }
+            let out_dir = test_dir.join(name);
+            self.write_harness(&wasm_bindgen, wasm, &out_dir).await?;
+            harnesses.push(name);
+        }
+
+        if harnesses.is_empty() {
+            tracing::info!("no wasm-bindgen tests to run");
+            return Ok(());
+        }
+
+        // Serve the harnesses, so that their modules can be loaded by the browser.
+        let (addr, server) =
+            serve::spawn_static_server(&test_dir).context("error starting the test server")?;
+
+        let result = self.run_harnesses(addr, &harnesses).await;
+        server.abort();
+        let failed = result?;
+        if !failed.is_empty() {
+            bail!(
+                "{} of {} test binaries failed: {}",
+                failed.len(),
+                harnesses.len(),
+                failed.join(", ")
+            );
+        }
+        Ok(())
+    }
+
+    /// Build the tests, returning the names & wasm files of the test binaries.
+    async fn cargo_build(&self) -> Result<Vec<(String, PathBuf)>> {
+        let mut args = vec![
+            "test",
+            "--no-run",
+            "--target=wasm32-unknown-unknown",
+            "--message-format=json-render-diagnostics",
+            "--manifest-path",
+            &self.manifest.manifest_path,
+        ];
+        args.extend(self.cargo_profile.cargo_args());
+        args.extend(self.cargo_flags.cargo_args());
+        args.extend(self.cargo_features.cargo_args());
+
+        tracing::info!("building wasm tests");
+        let output = common::cargo_command(&args, &self.cargo_env, &self.tools)
+            .stdout(Stdio::piped())
+            .stderr(Stdio::inherit())
+            .kill_on_drop(true)
+            .spawn()
+            .context("error spawning cargo test call")?
+            .wait_with_output()
+            .await
+            .context("error during cargo test call")?;
+        if !output.status.success() {
+            bail!("error building wasm tests");
+        }
+
+        let reader = std::io::BufReader::new(output.stdout.as_slice());
+        let mut binaries = Vec::new();
+        for msg in cargo_metadata::Message::parse_stream(reader) {
+            let msg = msg.context("error reading cargo messages")?;
+            if let cargo_metadata::Message::CompilerArtifact(artifact) = msg {
+                if !artifact.profile.test {
+                    continue;
+                }
+                let Some(wasm) = artifact.executable else {
+                    continue;
+                };
+                binaries.push((binary_name(&artifact.target), wasm.into_std_path_buf()));
+            }
+        }
+        Ok(binaries)
+    }
+
+    /// Generate the bindings of the given test binary with a harness page in the given dir.
+    async fn write_harness(&self, wasm_bindgen: &Path, wasm: &Path, out_dir: &Path) -> Result<()> {
+        if common::path_exists(out_dir).await? {
+            common::remove_dir_all(out_dir.to_owned()).await?;
+        }
+        fs::create_dir_all(out_dir)
+            .await
+            .with_context(|| format!("error creating test dir {:?}", out_dir))?;
+
+        let wasm_bindgen_name = Application::WasmBindgen.name();
+        let arg_out_dir = format!("--out-dir={}", out_dir.display());
+        let args = [
+            "--target=web",
+            "--no-typescript",
+            "--out-name=wasm",
+            &arg_out_dir,
+            &wasm.to_string_lossy(),
+        ];
+        common::run_command(wasm_bindgen_name, wasm_bindgen, &args).await?;
+
+        let script = harness_script(&self.args).context("error generating test harness")?;
+        fs::write(out_dir.join("index.html"), HARNESS_HTML)
+            .await
+            .context("error writing test harness")?;
+        fs::write(out_dir.join("run.js"), script)
+            .await
+            .context("error writing test harness")?;
+        Ok(())
+    }
+
+    /// Run the given harnesses in the browser, returning the names of the failed ones.
+    async fn run_harnesses<'a>(
+        &self,
+        addr: SocketAddr,
+        harnesses: &[&'a String],
+    ) -> Result<Vec<&'a str>> {
+        let driver = match &self.webdriver_url {
+            Some(url) => Driver::connect(url)?,
+            None => Driver::spawn(self.browser).await?,
+        };
+
+        let mut failed = Vec::new();
+        for name in harnesses {
+            tracing::info!("running {}", name);
+            let session = driver.session(self.browser, self.headless).await?;
+            let passed = self.run_harness(&session, addr, name).await;
+            if let Err(err) = session.close().await {
+                tracing::warn!("{:?}", err);
+            }
+            if !passed? {
+                failed.push(name.as_str());
+            }
+        }
+        Ok(failed)
+    }
+
+    /// Run the given harness in the session, printing its output as it is produced & returning if
+    /// its tests passed.
+    async fn run_harness(
+        &self,
+        session: &Session<'_>,
+        addr: SocketAddr,
+        name: &str,
+    ) -> Result<bool> {
+        session.goto(&format!("http://{}/{}/", addr, name)).await?;
+
+        let started = tokio::time::Instant::now();
+        let mut printed = 0;
+        loop {
+            let output = session.text("#output").await?;
+            // Skip the initial loading message, which the tests replace.
+            if let Some(new) = output
+                .get(printed..)
+                .filter(|new| !new.is_empty() && output != "Loading scripts...")
+            {
+                if messages::is_json() {
+                    messages::emit(&Message::TestOutput {
+                        binary: name,
+                        output: new,
+                    });
+                } else {
+                    print!("{}", new);
+                }
+                printed = output.len();
+            }
+            if let Some(passed) = outcome(&output) {
+                if messages::is_json() {
+                    messages::emit(&Message::TestFinished {
+                        binary: name,
+                        passed,
+                    });
+                } else {
+                    println!();
+                }
+                return Ok(passed);
+            }
+            if started.elapsed() > self.timeout {
+                return Err(anyhow!(
+                    "the tests of {} did not finish within {}",
+                    name,
+                    humantime::format_duration(self.timeout)
+                ));
+            }
+            tokio::time::sleep(POLL_INTERVAL).await;
+        }
+    }
+}
+
+/// The name of the harness of the given test target, which is unique across its kinds.
+fn binary_name(target: &cargo_metadata::Target) -> String {
+    match target.kind.first().map(String::as_str) {
+        Some("test") => format!("test-{}", target.name),
+        Some(kind) => format!("{}-{}", kind, target.name),
+        None => target.name.clone(),
+    }
+}
+
+/// Generate the script of a harness, which runs all tests of its bindings with the given args.
+fn harness_script(args: &[String]) -> Result<String> {
+    let args = serde_json::to_string(args)?;
+    Ok(format!(
+        r#"import init, * as bindings from "./wasm.js";
+
+const output = document.getElementById("output");
+try {{
+  const wasm = await init();
+  window.on_console_debug = bindings.__wbgtest_console_debug;
+  window.on_console_log = bindings.__wbgtest_console_log;
+  window.on_console_info = bindings.__wbgtest_console_info;
+  window.on_console_warn = bindings.__wbgtest_console_warn;
+  window.on_console_error = bindings.__wbgtest_console_error;
+
+  const cx = new bindings.WasmBindgenTestContext();
+  cx.args({args});
+  const tests = Object.keys(wasm).filter((name) => name.startsWith("__wbgt_"));
+  await cx.run(tests.map((name) => wasm[name]));
+}} catch (err) {{
+  output.textContent += "\n{marker}" + err;
+}}
+"#,
+        args = args,
+        marker = ERROR_MARKER,
+    ))
+}
+
+/// Get the outcome of the tests from the output of a harness, if they finished.
+fn outcome(output: &str) -> Option<bool> {
+    if output.contains(ERROR_MARKER) {
+        return Some(false);
+    }
+    output
+        .lines()
+        .find_map(|line| line.strip_prefix("test result: "))
+        .map(|result| result.starts_with("ok"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{harness_script, outcome};
+
+    #[test]
+    fn outcome_of_test_output() {
+        assert_eq!(outcome("Loading scripts..."), None);
+        assert_eq!(outcome("running 2 tests\ntest a ... ok\n"), None);
+        assert_eq!(
+            outcome("running 1 test\ntest a ... ok\n\ntest result: ok. 1 passed; 0 failed"),
+            Some(true)
+        );
+        assert_eq!(
+            outcome("running 1 test\ntest a ... FAIL\n\ntest result: FAILED. 0 passed; 1 failed"),
+            Some(false)
+        );
+        assert_eq!(
+            outcome("Loading scripts...\nerror running tests: TypeError: not a constructor"),
+            Some(false)
+        );
+    }
+
+    #[test]
+    fn harness_script_passes_args() {
+        let script = harness_script(&["parse".into(), "--include-ignored".into()])
+            .expect("expected a harness script");
+        assert!(
+            script.contains(r#"cx.args(["parse","--include-ignored"]);"#),
+            "unexpected script: {}",
+            script
+        );
+    }
+}
@@ -0,0 +1,263 @@
+//! Timings of the stages of builds.
+
+use std::fmt::Write;
+use std::path::PathBuf;
+use std::sync::{Mutex, PoisonError};
+use std::time::{Duration, Instant};
+
+use anyhow::{Context, Result};
+use serde::Serialize;
+use tokio::fs;
+
+use crate::common::escape_html;
+use crate::config::TimingsFormat;
+
+/// The number of the slowest stages which are printed after a build.
+const SLOWEST_STAGES: usize = 10;
+
+/// A recorder of the timings of the stages of a build.
+#[derive(Debug)]
+pub struct Timings {
+    /// The format of the written timings report.
+    format: TimingsFormat,
+    /// The time at which the current build started.
+    started: Mutex<Instant>,
+    /// The timings of the finished stages of the current build.
+    stages: Mutex<Vec<StageTiming>>,
+}
+
+/// The timing of a stage of a build.
+#[derive(Clone, Debug, PartialEq, Serialize)]
+pub struct StageTiming {
+    /// The kind of the stage, e.g. `cargo` or `asset`.
+    pub category: &'static str,
+    /// The name of the stage, e.g. the asset which is processed.
+    pub name: String,
+    /// The time at which the stage started since the start of the build, in seconds.
+    pub start: f64,
+    /// The wall-clock duration of the stage, in seconds.
+    pub duration: f64,
+}
+
+/// The timings of a build.
+#[derive(Debug, Serialize)]
+pub struct TimingsReport {
+    /// The wall-clock duration of the build, in seconds.
+    pub total: f64,
+    /// The timings of the stages of the build, in the order they started.
+    pub stages: Vec<StageTiming>,
+}
+
+/// A guard recording the timing of a stage once dropped.
+pub struct TimingGuard<'a> {
+    timings: &'a Timings,
+    category: &'static str,
+    name: String,
+    started: Instant,
+}
+
+impl Drop for TimingGuard<'_> {
+    fn drop(&mut self) {
+        let build_started = *self
+            .timings
+            .started
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner);
+        let timing = StageTiming {
+            category: self.category,
+            name: std::mem::take(&mut self.name),
+            start: self
+                .started
+                .saturating_duration_since(build_started)
+                .as_secs_f64(),
+            duration: self.started.elapsed().as_secs_f64(),
+        };
+        self.timings
+            .stages
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .push(timing);
+    }
+}
+
+impl Timings {
+    /// Create a new instance, writing reports in the given format.
+    pub fn new(format: TimingsFormat) -> Self {
+        Self {
+            format,
+            started: Mutex::new(Instant::now()),
+            stages: Mutex::new(Vec::new()),
+        }
+    }
+
+    /// Start recording the timings of a new build.
+    pub fn reset(&self) {
+        *self.started.lock().unwrap_or_else(PoisonError::into_inner) = Instant::now();
+        self.stages
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .clear();
+    }
+
+    /// Start timing a stage, which is recorded once the returned guard is dropped.
+    pub fn start(&self, category: &'static str, name: impl Into<String>) -> TimingGuard<'_> {
+        TimingGuard {
+            timings: self,
+            category,
+            name: name.into(),
+            started: Instant::now(),
+        }
+    }
+
+    /// Get the report of the timings of the current build.
+    pub fn report(&self) -> TimingsReport {
+        let total = self
+            .started
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .elapsed()
+            .as_secs_f64();
+        let mut stages = self
+            .stages
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .clone();
+        stages.sort_by(|a, b| a.start.total_cmp(&b.start));
+        TimingsReport { total, stages }
+    }
+
+    /// Print the slowest stages of the current build, and write its timings report into the
+    /// given dir.
+    pub async fn write_report(&self, dir: PathBuf) -> Result<()> {
+        let report = self.report();
+        let mut slowest = report.stages.iter().collect::<Vec<_>>();
+        slowest.sort_by(|a, b| b.duration.total_cmp(&a.duration));
+        let slowest =
+            slowest
+                .into_iter()
+                .take(SLOWEST_STAGES)
+                .fold(String::new(), |mut table, stage| {
+                    let _ = writeln!(
+                        table,
+                        "{:>8}  {:<8}  {}",
+                        format_duration(stage.duration),
+                        stage.category,
+                        stage.name
+                    );
+                    table
+                });
+
+        let (file_name, contents) = match self.format {
+            TimingsFormat::Html => ("timings.html", report.to_html()),
+            TimingsFormat::Json => (
+                "timings.json",
+                serde_json::to_string_pretty(&report).context("error serializing timings")?,
+            ),
+        };
+        fs::create_dir_all(&dir)
+            .await
+            .context("error creating dir of timings report")?;
+        let path = dir.join(file_name);
+        fs::write(&path, contents)
+            .await
+            .with_context(|| format!("error writing timings report to {:?}", path))?;
+        tracing::info!(
+            "build took {}, slowest stages:\n{}timings report written to {}",
+            format_duration(report.total),
+            slowest,
+            path.display()
+        );
+        Ok(())
+    }
+}
+
+impl TimingsReport {
+    /// Render the report as a standalone HTML page, with a bar spanning the duration of each
+    /// stage on the timeline of the build.
+    pub fn to_html(&self) -> String {
+        let total = self.total.max(f64::EPSILON);
+        let bars = self.stages.iter().fold(String::new(), |mut bars, stage| {
+            let _ = writeln!(
+                bars,
+                r#"<div class="stage {category}" style="margin-left: {left:.3}%; width: {width:.3}%" title="{name} ({category}): {start} + {duration}"><span>{name} {duration}</span></div>"#,
+                category = stage.category,
+                name = escape_html(&stage.name),
+                left = stage.start / total * 100.0,
+                width = (stage.duration / total * 100.0).max(0.1),
+                start = format_duration(stage.start),
+                duration = format_duration(stage.duration),
+            );
+            bars
+        });
+        format!(
+            r#"<!DOCTYPE html>
+<html>
+<head>
+<meta charset="utf-8">
+<title>Trunk build timings</title>
+<style>
+body {{ font-family: sans-serif; }}
+.timeline {{ position: relative; border-left: 1px solid #999; }}
+.stage {{ box-sizing: border-box; margin: 2px 0; padding: 2px 4px; white-space: nowrap; overflow: visible; font-size: 12px; background: #ccc; border-radius: 2px; }}
+.stage.cargo {{ background: #f4a261; }}
+.stage.bindgen {{ background: #e9c46a; }}
+.stage.wasm-opt {{ background: #e76f51; }}
+.stage.asset {{ background: #8ecae6; }}
+.stage.hook {{ background: #b5e48c; }}
+.stage.html {{ background: #a8dadc; }}
+.stage.dist {{ background: #cdb4db; }}
+</style>
+</head>
+<body>
+<h1>Build timings</h1>
+<p>Total: {total}</p>
+<div class="timeline">
+{bars}</div>
+</body>
+</html>
+"#,
+            total = format_duration(self.total),
+            bars = bars
+        )
+    }
+}
+
+/// Format the given duration in seconds for humans.
+fn format_duration(secs: f64) -> String {
+    if secs < 1.0 {
+        format!("{}ms", Duration::from_secs_f64(secs).as_millis())
+    } else {
+        format!("{:.2}s", secs)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use anyhow::{ensure, Result};
+
+    use super::*;
+
+    #[test]
+    fn stages_are_recorded_once_finished() -> Result<()> {
+        let timings = Timings::new(TimingsFormat::Json);
+        {
+            let _cargo = timings.start("cargo", "app");
+            let _asset = timings.start("asset", "css style.css");
+        }
+        let report = timings.report();
+        ensure!(report.stages.len() == 2, "unexpected stages {:?}", report);
+        ensure!(
+            report
+                .stages
+                .iter()
+                .all(|stage| stage.start + stage.duration <= report.total),
+            "stages exceed the build {:?}",
+            report
+        );
+        ensure!(report.to_html().contains("css style.css"));
+
+        timings.reset();
+        ensure!(timings.report().stages.is_empty());
+        Ok(())
+    }
+}
@@ -2,12 +2,17 @@
 //! applications (if needed) to use them in the build pipeline.
 
 use std::collections::HashMap;
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
 
 use anyhow::{bail, ensure, Context, Result};
+use cargo_lock::Version;
+use clap::ValueEnum;
 use directories::ProjectDirs;
 use futures_util::stream::StreamExt;
 use once_cell::sync::Lazy;
+use serde::Deserialize;
+use sha2::{Digest, Sha256};
 use tokio::fs::File;
 use tokio::io::AsyncWriteExt;
 use tokio::process::Command;
@@ -15,13 +20,17 @@ use tokio::sync::{Mutex, OnceCell};
 
 use self::archive::Archive;
 use crate::common::is_executable;
+use crate::config::ConfigOptsTools;
 
 /// The application to locate and eventually download when calling [`get`].
-#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, ValueEnum)]
 pub enum Application {
+    /// esbuild for transpiling TypeScript
+    Esbuild,
     /// sass for generating css
     Sass,
     /// tailwindcss for generating css
+    #[value(name = "tailwindcss")]
     TailwindCss,
     /// wasm-bindgen for generating the JS bindings.
     WasmBindgen,
@@ -33,6 +42,7 @@ impl Application {
     /// Base name of the executable without extension.
     pub(crate) fn name(&self) -> &str {
         match self {
+            Self::Esbuild => "esbuild",
             Self::Sass => "sass",
             Self::TailwindCss => "tailwindcss",
             Self::WasmBindgen => "wasm-bindgen",
@@ -44,6 +54,7 @@ impl Application {
     fn path(&self) -> &str {
         if cfg!(target_os = "windows") {
             match self {
+                Self::Esbuild => "esbuild.exe",
                 Self::Sass => "sass.bat",
                 Self::TailwindCss => "tailwindcss.exe",
                 Self::WasmBindgen => "wasm-bindgen.exe",
@@ -51,6 +62,7 @@ impl Application {
             }
         } else {
             match self {
+                Self::Esbuild => "bin/esbuild",
                 Self::Sass => "sass",
                 Self::TailwindCss => "tailwindcss",
                 Self::WasmBindgen => "wasm-bindgen",
@@ -62,6 +74,7 @@ impl Application {
     /// Additional files included in the archive that are required to run the main binary.
     fn extra_paths(&self) -> &[&str] {
         match self {
+            Self::Esbuild => &[],
             Self::Sass => {
                 if cfg!(target_os = "windows") {
                     &["src/dart.exe", "src/sass.snapshot"]
@@ -84,6 +97,7 @@ impl Application {
     /// Default version to use if not set by the user.
     fn default_version(&self) -> &str {
         match self {
+            Self::Esbuild => "0.19.2",
             Self::Sass => "1.63.6",
             Self::TailwindCss => "3.3.2",
             Self::WasmBindgen => "0.2.87",
@@ -112,6 +126,15 @@ impl Application {
         };
 
         Ok(match self {
+            Self::Esbuild => match (target_os, target_arch) {
+                ("windows", "x86_64") => format!("https://registry.npmjs.org/@esbuild/win32-x64/-/win32-x64-{version}.tgz"),
+                ("macos", "x86_64") => format!("https://registry.npmjs.org/@esbuild/darwin-x64/-/darwin-x64-{version}.tgz"),
+                ("macos", "aarch64") => format!("https://registry.npmjs.org/@esbuild/darwin-arm64/-/darwin-arm64-{version}.tgz"),
+                ("linux", "x86_64") => format!("https://registry.npmjs.org/@esbuild/linux-x64/-/linux-x64-{version}.tgz"),
+                ("linux", "aarch64") => format!("https://registry.npmjs.org/@esbuild/linux-arm64/-/linux-arm64-{version}.tgz"),
+                _ => bail!("Unable to download esbuild for {target_os} {target_arch}")
+            },
+
             Self::Sass => match (target_os, target_arch) {
               ("windows", "x86_64") => format!("https://github.com/sass/dart-sass/releases/download/{version}/dart-sass-{version}-windows-x64.zip"),
               ("macos" | "linux", "x86_64") => format!("https://github.com/sass/dart-sass/releases/download/{version}/dart-sass-{version}-{target_os}-x64.tar.gz"),
@@ -142,9 +165,67 @@ impl Application {
         })
     }
 
+    /// Normalize a version pinned by the user to the format of the release tags, e.g. `v0.2.87` to
+    /// `0.2.87`, or `113` to `version_113` for wasm-opt.
+    fn normalize_version(&self, version: &str) -> String {
+        let version = version.trim();
+        match self {
+            Self::WasmOpt if version.starts_with("version_") => version.to_owned(),
+            Self::WasmOpt => format!("version_{}", version.trim_start_matches('v')),
+            _ => version.trim_start_matches('v').to_owned(),
+        }
+    }
+
+    /// URL of the SHA-256 checksums published with the release of an application, if any.
+    fn checksum_url(&self, version: &str) -> Result<Option<String>> {
+        Ok(match self {
+            Self::WasmBindgen | Self::WasmOpt => Some(format!("{}.sha256", self.url(version)?)),
+            Self::TailwindCss => Some(format!(
+                "https://github.com/tailwindlabs/tailwindcss/releases/download/v{version}/sha256sums.txt"
+            )),
+            Self::Esbuild | Self::Sass => None,
+        })
+    }
+
+    /// The GitHub repository of the releases of an application.
+    fn repository(&self) -> &str {
+        match self {
+            Self::Esbuild => "evanw/esbuild",
+            Self::Sass => "sass/dart-sass",
+            Self::TailwindCss => "tailwindlabs/tailwindcss",
+            Self::WasmBindgen => "rustwasm/wasm-bindgen",
+            Self::WasmOpt => "WebAssembly/binaryen",
+        }
+    }
+
+    /// Parse a normalized version of an application as semver, where the versions of wasm-opt are
+    /// major versions.
+    fn semver(&self, version: &str) -> Option<Version> {
+        match self {
+            Self::WasmOpt => Some(Version::new(
+                version.strip_prefix("version_")?.parse().ok()?,
+                0,
+                0,
+            )),
+            _ => Version::parse(version).ok(),
+        }
+    }
+
+    /// Whether a release of the given version can replace the current version without breaking
+    /// changes, which for wasm-opt is any later release.
+    fn is_compatible(&self, current: &Version, version: &Version) -> bool {
+        let compatible = match self {
+            Self::WasmOpt => true,
+            _ if current.major == 0 => version.major == 0 && version.minor == current.minor,
+            _ => version.major == current.major,
+        };
+        compatible && version.pre.is_empty() && version >= current
+    }
+
     /// The CLI subcommand, flag or option used to check the application's version.
     fn version_test(&self) -> &'static str {
         match self {
+            Application::Esbuild => "--version",
             Application::Sass => "--version",
             Application::TailwindCss => "--help",
             Application::WasmBindgen => "--version",
@@ -156,6 +237,11 @@ impl Application {
     fn format_version_output(&self, text: &str) -> Result<String> {
         let text = text.trim();
         let formatted_version = match self {
+            Application::Esbuild => text
+                .lines()
+                .next()
+                .with_context(|| format!("missing or malformed version output: {}", text))?
+                .to_owned(),
             Application::Sass => text
                 .lines()
                 .next()
@@ -183,6 +269,9 @@ impl Application {
     }
 }
 
+/// The timeout of connecting to the servers of downloads.
+const CONNECT_TIMEOUT: Duration = Duration::from_secs(30);
+
 /// Global, application wide app cache that keeps track of what tools have already been
 /// downloaded and installed to avoid duplicate installation runs.
 static GLOBAL_APP_CACHE: Lazy<Mutex<AppCache>> = Lazy::new(|| Mutex::new(AppCache::new()));
@@ -208,26 +297,39 @@ impl AppCache {
         app: Application,
         version: &str,
         app_dir: PathBuf,
+        cfg: &ConfigOptsTools,
     ) -> Result<()> {
-        let cached = self
-            .0
-            .entry((app, version.to_owned()))
-            .or_insert_with(OnceCell::new);
+        let cached = self.0.entry((app, version.to_owned())).or_default();
 
         cached
             .get_or_try_init(|| async move {
-                let path = download(app, version)
-                    .await
-                    .context("failed downloading release archive")?;
+                let path = download(app, version, cfg).await.with_context(|| {
+                    format!(
+                        "failed downloading {} {}, install it on the `PATH`, preload the tools \
+                         cache with `trunk tools install --from <dir|tarball>`, or download the \
+                         tools from a mirror set with `tools.mirror` or `TRUNK_TOOLS_MIRROR`",
+                        app.name(),
+                        version
+                    )
+                })?;
 
                 let file = File::open(&path)
                     .await
                     .context("failed opening downloaded file")?;
-                install(app, file, app_dir).await?;
+                install(app, file, app_dir.clone()).await?;
                 tokio::fs::remove_file(path)
                     .await
                     .context("failed deleting temporary archive")?;
 
+                // Don't keep an installation that doesn't report the expected version, as it
+                // would otherwise be used by all following builds.
+                if let Err(err) = verify_version(app, &app_dir.join(app.path()), version).await {
+                    tokio::fs::remove_dir_all(&app_dir)
+                        .await
+                        .context("failed removing unverified installation")?;
+                    return Err(err);
+                }
+
                 Ok(())
             })
             .await
@@ -236,15 +338,35 @@ impl AppCache {
 }
 
 /// Locate the given application and download it if missing.
-#[tracing::instrument(level = "trace")]
-pub async fn get(app: Application, version: Option<&str>) -> Result<PathBuf> {
-    if let Some((path, version)) = find_system(app, version).await {
+///
+/// A pinned version is only satisfied by a system installed binary of exactly that version,
+/// otherwise the version is downloaded to the cache.
+#[tracing::instrument(level = "trace", skip(cfg))]
+pub async fn get(
+    app: Application,
+    version: Option<&str>,
+    cfg: &ConfigOptsTools,
+) -> Result<PathBuf> {
+    let version = version.map(|version| app.normalize_version(version));
+    if let Some((path, version)) = find_system(app, version.as_deref()).await {
         tracing::info!(app = %app.name(), %version, "using system installed binary");
         return Ok(path);
     }
 
+    install_cached(app, version.as_deref(), cfg).await
+}
+
+/// Install the given application to the cache, if it isn't cached yet, regardless of any system
+/// installed version.
+#[tracing::instrument(level = "trace", skip(cfg))]
+pub async fn install_cached(
+    app: Application,
+    version: Option<&str>,
+    cfg: &ConfigOptsTools,
+) -> Result<PathBuf> {
+    let version = resolve_version(app, version);
+    let version = version.as_str();
     let cache_dir = cache_dir().await?;
-    let version = version.unwrap_or_else(|| app.default_version());
     let app_dir = cache_dir.join(format!("{}-{}", app.name(), version));
     let bin_path = app_dir.join(app.path());
 
@@ -252,31 +374,167 @@ pub async fn get(app: Application, version: Option<&str>) -> Result<PathBuf> {
         GLOBAL_APP_CACHE
             .lock()
             .await
-            .install_once(app, version, app_dir)
+            .install_once(app, version, app_dir, cfg)
             .await?;
     }
 
     Ok(bin_path)
 }
 
+/// Get the file name of the release archive of the given application, which is looked up in the
+/// mirror of the tools.
+pub fn release_file_name(app: Application, version: Option<&str>) -> Result<String> {
+    let url = app.url(&resolve_version(app, version))?;
+    Ok(download_file_name(&url).to_owned())
+}
+
+/// Get the version of the given application to use, which is the normalized pinned version, or the
+/// default version.
+pub fn resolve_version(app: Application, version: Option<&str>) -> String {
+    version
+        .map(|version| app.normalize_version(version))
+        .unwrap_or_else(|| app.default_version().to_owned())
+}
+
+/// A version of an application installed in the cache.
+#[derive(Clone, Debug)]
+pub struct CachedApp {
+    /// The application.
+    pub app: Application,
+    /// The installed version.
+    pub version: String,
+    /// The dir of the installation.
+    pub path: PathBuf,
+    /// The disk usage of the installation in bytes.
+    pub size: u64,
+}
+
+/// List the versions of the applications installed in the cache, sorted by application & version.
+pub async fn cached_apps() -> Result<Vec<CachedApp>> {
+    let cache_dir = cache_dir().await?;
+    tokio::task::spawn_blocking(move || {
+        let mut apps = Vec::new();
+        let entries = std::fs::read_dir(&cache_dir).context("error reading cache dir")?;
+        for entry in entries {
+            let entry = entry.context("error reading cache dir entry")?;
+            let path = entry.path();
+            let name = entry.file_name().to_string_lossy().into_owned();
+            if !path.is_dir() {
+                continue;
+            }
+            let cached = Application::value_variants().iter().find_map(|&app| {
+                let version = name.strip_prefix(app.name())?.strip_prefix('-')?;
+                Some((app, version.to_owned()))
+            });
+            if let Some((app, version)) = cached {
+                let size = disk_usage(&path)?;
+                apps.push(CachedApp {
+                    app,
+                    version,
+                    path,
+                    size,
+                });
+            }
+        }
+        apps.sort_by(|a, b| {
+            (a.app.name(), a.app.semver(&a.version), &a.version).cmp(&(
+                b.app.name(),
+                b.app.semver(&b.version),
+                &b.version,
+            ))
+        });
+        Ok(apps)
+    })
+    .await?
+}
+
+/// List the temporary files of interrupted downloads in the cache.
+pub async fn stale_downloads() -> Result<Vec<PathBuf>> {
+    let mut stale = Vec::new();
+    let mut entries = tokio::fs::read_dir(cache_dir().await?)
+        .await
+        .context("error reading cache dir")?;
+    while let Some(entry) = entries
+        .next_entry()
+        .await
+        .context("error reading cache dir entry")?
+    {
+        let path = entry.path();
+        if path.is_file() && path.extension().is_some_and(|ext| ext == "tmp") {
+            stale.push(path);
+        }
+    }
+    Ok(stale)
+}
+
+/// Get the disk usage of the files of the given dir, in bytes.
+fn disk_usage(path: &Path) -> Result<u64> {
+    let mut size = 0;
+    for entry in std::fs::read_dir(path).with_context(|| format!("error reading dir {:?}", path))? {
+        let entry = entry.with_context(|| format!("error reading entry of dir {:?}", path))?;
+        let meta = entry
+            .metadata()
+            .with_context(|| format!("error reading metadata of {:?}", entry.path()))?;
+        size += if meta.is_dir() {
+            disk_usage(&entry.path())?
+        } else {
+            meta.len()
+        };
+    }
+    Ok(size)
+}
+
+/// A release of an application on GitHub.
+#[derive(Deserialize)]
+struct Release {
+    tag_name: String,
+    #[serde(default)]
+    draft: bool,
+    #[serde(default)]
+    prerelease: bool,
+}
+
+/// Get the latest release of the given application which is compatible with the given version,
+/// i.e. semver compatible, or any later version for wasm-opt, whose versions are plain numbers.
+pub async fn latest_compatible_version(
+    app: Application,
+    version: &str,
+    cfg: &ConfigOptsTools,
+) -> Result<String> {
+    let current = app
+        .semver(version)
+        .with_context(|| format!("unsupported version {} of {}", version, app.name()))?;
+    let url = format!(
+        "https://api.github.com/repos/{}/releases?per_page=100",
+        app.repository()
+    );
+    let text = fetch_text(&url, cfg)
+        .await
+        .with_context(|| format!("error fetching the releases of {}", app.name()))?;
+    let releases: Vec<Release> = serde_json::from_str(&text)
+        .with_context(|| format!("error parsing the releases of {}", app.name()))?;
+
+    let latest = releases
+        .into_iter()
+        .filter(|release| !release.draft && !release.prerelease)
+        .filter_map(|release| {
+            let version = app.normalize_version(&release.tag_name);
+            let semver = app.semver(&version)?;
+            app.is_compatible(&current, &semver)
+                .then_some((semver, version))
+        })
+        .max();
+    Ok(latest.map_or_else(|| version.to_owned(), |(_, version)| version))
+}
+
 /// Try to find a globally system installed version of the application and ensure it is the needed
 /// release version.
 #[tracing::instrument(level = "trace")]
 async fn find_system(app: Application, version: Option<&str>) -> Option<(PathBuf, String)> {
     let result = || async {
         let path = which::which(app.name())?;
-        let output = Command::new(&path).arg(app.version_test()).output().await?;
-        ensure!(
-            output.status.success(),
-            "running command `{} {}` failed",
-            path.display(),
-            app.version_test()
-        );
-
-        let text = String::from_utf8_lossy(&output.stdout);
-        let system_version = app.format_version_output(&text)?;
-
-        Ok((path, system_version))
+        let system_version = installed_version(app, &path).await?;
+        Ok::<_, anyhow::Error>((path, system_version))
     };
 
     match result().await {
@@ -291,36 +549,263 @@ async fn find_system(app: Application, version: Option<&str>) -> Option<(PathBuf
     }
 }
 
+/// Get the version of the application installed at the given path.
+async fn installed_version(app: Application, path: &Path) -> Result<String> {
+    let output = Command::new(path).arg(app.version_test()).output().await?;
+    ensure!(
+        output.status.success(),
+        "running command `{} {}` failed",
+        path.display(),
+        app.version_test()
+    );
+
+    let text = String::from_utf8_lossy(&output.stdout);
+    app.format_version_output(&text)
+}
+
+/// Ensure the application installed at the given path reports the expected version.
+async fn verify_version(app: Application, path: &Path, version: &str) -> Result<()> {
+    let installed = installed_version(app, path).await.with_context(|| {
+        format!(
+            "failed checking the version of the installed {}",
+            app.name()
+        )
+    })?;
+    ensure!(
+        installed == version,
+        "the downloaded {} reports version {}, but version {} was expected",
+        app.name(),
+        installed,
+        version
+    );
+    Ok(())
+}
+
 /// Download a file from its remote location in the given version, extract it and make it ready for
 /// execution at the given location.
-#[tracing::instrument(level = "trace")]
-async fn download(app: Application, version: &str) -> Result<PathBuf> {
+///
+/// The download is verified against its SHA-256 checksum, either pinned in the config or published
+/// with the release.
+#[tracing::instrument(level = "trace", skip(cfg))]
+async fn download(app: Application, version: &str, cfg: &ConfigOptsTools) -> Result<PathBuf> {
     tracing::info!(version = version, "downloading {}", app.name());
 
+    let url = app.url(version)?;
+    let location = locate(&url, cfg);
+    let expected = expected_checksum(app, version, &url, cfg).await?;
+    if expected.is_none() {
+        ensure!(
+            !cfg.require_checksums.unwrap_or(false),
+            "no checksum to verify the download of {} {} against, as `tools.require_checksums` is \
+             set, add the SHA-256 checksum of {:?} to `tools.checksums`\n{}",
+            app.name(),
+            version,
+            download_file_name(&url),
+            location
+        );
+        tracing::warn!(
+            "no checksum to verify the download of {} {} against, add the SHA-256 checksum of \
+             {:?} to `tools.checksums`",
+            app.name(),
+            version,
+            download_file_name(&url)
+        );
+    }
+
     let cache_dir = cache_dir()
         .await
         .context("failed getting the cache directory")?;
     let temp_out = cache_dir.join(format!("{}-{}.tmp", app.name(), version));
-    let mut file = File::create(&temp_out)
-        .await
-        .context("failed creating temporary output file")?;
+    let checksum = fetch_file(&location, &temp_out, cfg).await?;
+
+    if let Some(expected) = expected {
+        if checksum != expected {
+            tokio::fs::remove_file(&temp_out)
+                .await
+                .context("failed deleting temporary archive")?;
+            bail!(
+                "checksum mismatch of the download of {} {}, expected SHA-256 {}, but got {}\n{}",
+                app.name(),
+                version,
+                expected,
+                checksum,
+                location
+            );
+        }
+    }
+
+    Ok(temp_out)
+}
+
+/// Get the SHA-256 checksum to verify the download from the given URL against, if any, preferring
+/// the checksum pinned in the config over the one published with the release.
+async fn expected_checksum(
+    app: Application,
+    version: &str,
+    url: &str,
+    cfg: &ConfigOptsTools,
+) -> Result<Option<String>> {
+    let file_name = download_file_name(url);
+    if let Some(checksum) = cfg.checksums.as_ref().and_then(|sums| sums.get(file_name)) {
+        return parse_checksum(checksum, file_name)
+            .with_context(|| {
+                format!(
+                    "invalid SHA-256 checksum of {:?} in `tools.checksums`",
+                    file_name
+                )
+            })
+            .map(Some);
+    }
+
+    let Some(checksum_url) = app.checksum_url(version)? else {
+        return Ok(None);
+    };
+    let checksum_url = locate(&checksum_url, cfg);
+    let text = match fetch_text(&checksum_url, cfg).await {
+        Ok(text) => text,
+        Err(err) if !cfg.require_checksums.unwrap_or(false) => {
+            tracing::warn!(
+                "error downloading the published checksum of {:?}, which is not verified: {:#}",
+                file_name,
+                err
+            );
+            return Ok(None);
+        }
+        Err(err) => return Err(err.context("error downloading published checksum")),
+    };
+    parse_checksum(&text, file_name)
+        .with_context(|| {
+            format!(
+                "missing or malformed checksum of {:?} in the published checksums\n{}",
+                file_name, checksum_url
+            )
+        })
+        .map(Some)
+}
+
+/// Get the location to download the file at the given URL from, which is the file of the same
+/// name in the mirror of the tools, if any.
+fn locate(url: &str, cfg: &ConfigOptsTools) -> String {
+    match cfg.mirror.as_deref() {
+        Some(mirror) => format!(
+            "{}/{}",
+            mirror.trim_end_matches('/'),
+            download_file_name(url)
+        ),
+        None => url.to_owned(),
+    }
+}
+
+/// Get the local path of the given download location, unless it is a remote URL.
+fn local_path(location: &str) -> Option<&Path> {
+    if location.starts_with("http://") || location.starts_with("https://") {
+        return None;
+    }
+    Some(Path::new(
+        location.strip_prefix("file://").unwrap_or(location),
+    ))
+}
+
+/// Build the HTTP client for downloads, which fails to connect after a timeout, instead of hanging
+/// without network access.
+///
+/// Requests are sent through the proxy of the config, or otherwise the proxy of the `HTTPS_PROXY`,
+/// `HTTP_PROXY` & `ALL_PROXY` env vars, except for the hosts of the `NO_PROXY` env var.
+fn http_client(cfg: &ConfigOptsTools) -> Result<reqwest::Client> {
+    let mut builder = reqwest::Client::builder()
+        .connect_timeout(CONNECT_TIMEOUT)
+        .user_agent(concat!("trunk/", env!("CARGO_PKG_VERSION")));
+    if let Some(proxy) = &cfg.proxy {
+        let proxy = reqwest::Proxy::all(proxy)
+            .with_context(|| format!("invalid proxy URL {:?} of `tools.proxy`", proxy))?
+            .no_proxy(reqwest::NoProxy::from_env());
+        builder = builder.proxy(proxy);
+    }
+    builder.build().context("error building HTTP client")
+}
 
-    let resp = reqwest::get(app.url(version)?)
+/// Send a GET request for the given URL, ensuring a successful response.
+async fn http_get(url: &str, cfg: &ConfigOptsTools) -> Result<reqwest::Response> {
+    let resp = http_client(cfg)?
+        .get(url)
+        .send()
         .await
-        .context("error sending HTTP request")?;
+        .with_context(|| format!("error sending HTTP request\n{}", url))?;
     ensure!(
         resp.status().is_success(),
-        "error downloading archive file: {:?}\n{}",
+        "error downloading file: {:?}\n{}",
         resp.status(),
-        app.url(version)?
+        url
     );
-    let mut res_bytes = resp.bytes_stream();
-    while let Some(chunk_res) = res_bytes.next().await {
-        let chunk = chunk_res.context("error reading chunk from download")?;
-        let _res = file.write(chunk.as_ref()).await;
+    Ok(resp)
+}
+
+/// Fetch the file at the given location to the given path, returning its hex SHA-256 checksum.
+async fn fetch_file(location: &str, out: &Path, cfg: &ConfigOptsTools) -> Result<String> {
+    let mut file = File::create(out)
+        .await
+        .context("failed creating temporary output file")?;
+    let mut hasher = Sha256::new();
+    if let Some(path) = local_path(location) {
+        let bytes = tokio::fs::read(path)
+            .await
+            .with_context(|| format!("error reading file of the tools mirror {:?}", path))?;
+        hasher.update(&bytes);
+        file.write_all(&bytes)
+            .await
+            .context("failed writing temporary output file")?;
+    } else {
+        let mut res_bytes = http_get(location, cfg).await?.bytes_stream();
+        while let Some(chunk_res) = res_bytes.next().await {
+            let chunk = chunk_res.context("error reading chunk from download")?;
+            hasher.update(&chunk);
+            file.write_all(chunk.as_ref())
+                .await
+                .context("failed writing temporary output file")?;
+        }
+    }
+    file.flush()
+        .await
+        .context("failed writing temporary output file")?;
+    Ok(format!("{:x}", hasher.finalize()))
+}
+
+/// Fetch the text file at the given location.
+async fn fetch_text(location: &str, cfg: &ConfigOptsTools) -> Result<String> {
+    match local_path(location) {
+        Some(path) => tokio::fs::read_to_string(path)
+            .await
+            .with_context(|| format!("error reading file of the tools mirror {:?}", path)),
+        None => http_get(location, cfg)
+            .await?
+            .text()
+            .await
+            .with_context(|| format!("error reading download\n{}", location)),
     }
+}
 
-    Ok(temp_out)
+/// Get the file name of a download from its URL.
+fn download_file_name(url: &str) -> &str {
+    url.rsplit('/').next().unwrap_or(url)
+}
+
+/// Parse the hex SHA-256 checksum of the given file from the output of `sha256sum`, which is
+/// either the sole checksum, or one of the lines of checksums & file names.
+fn parse_checksum(text: &str, file_name: &str) -> Option<String> {
+    let is_checksum = |sum: &str| sum.len() == 64 && sum.chars().all(|c| c.is_ascii_hexdigit());
+    let mut lines = text.lines().filter(|line| !line.trim().is_empty());
+    let checksum = lines.find_map(|line| {
+        let mut parts = line.split_whitespace();
+        let sum = parts.next()?;
+        match parts.next() {
+            None => Some(sum),
+            Some(name) => {
+                let name = name.trim_start_matches('*').trim_start_matches("./");
+                (name == file_name).then_some(sum)
+            }
+        }
+    })?;
+    is_checksum(checksum).then(|| checksum.to_ascii_lowercase())
 }
 
 /// Install an application from a downloaded archive locating and copying it to the given target
@@ -471,7 +956,7 @@ mod archive {
     fn find_tar_entry(
         archive: &mut TarArchive<impl Read>,
         path: impl AsRef<Path>,
-    ) -> Result<Option<TarEntry<impl Read>>> {
+    ) -> Result<Option<TarEntry<'_, impl Read>>> {
         let entries = archive
             .entries()
             .context("failed getting archive entries")?;
@@ -559,7 +1044,7 @@ mod tests {
             Application::WasmOpt,
             Application::TailwindCss,
         ] {
-            let path = download(app, app.default_version())
+            let path = download(app, app.default_version(), &Default::default())
                 .await
                 .context("error downloading app")?;
             let file = File::open(&path).await.context("error opening file")?;
@@ -618,6 +1103,118 @@ mod tests {
         "0.2.74"
     );
 
+    #[test]
+    fn parse_published_checksums() {
+        let sum = "a".repeat(64);
+        let other = "b".repeat(64);
+        assert_eq!(
+            parse_checksum(&format!("{sum}\n"), "app.tar.gz"),
+            Some(sum.clone())
+        );
+        assert_eq!(
+            parse_checksum(
+                &format!("{other}  ./app-x64\n{}  *app.tar.gz\n", sum.to_uppercase()),
+                "app.tar.gz"
+            ),
+            Some(sum)
+        );
+        assert_eq!(
+            parse_checksum(&format!("{other}  app-x64"), "app.tar.gz"),
+            None
+        );
+        assert_eq!(parse_checksum("not a checksum", "app.tar.gz"), None);
+    }
+
+    #[tokio::test]
+    async fn missing_published_checksums_are_only_required_if_configured() -> Result<()> {
+        let tmpdir = tempfile::tempdir()?;
+        let version = Application::WasmBindgen.default_version();
+        let url = Application::WasmBindgen.url(version)?;
+        let mut cfg = ConfigOptsTools {
+            mirror: Some(format!("file://{}", tmpdir.path().display())),
+            ..Default::default()
+        };
+
+        let expected = expected_checksum(Application::WasmBindgen, version, &url, &cfg).await?;
+        ensure!(expected.is_none(), "unexpected checksum {:?}", expected);
+
+        cfg.require_checksums = Some(true);
+        let err = expected_checksum(Application::WasmBindgen, version, &url, &cfg)
+            .await
+            .expect_err("expected a missing checksum to err");
+        ensure!(
+            err.to_string() == "error downloading published checksum",
+            "unexpected error {:#}",
+            err
+        );
+
+        let sum = "c".repeat(64);
+        let checksum_file = format!("{}.sha256", download_file_name(&url));
+        std::fs::write(tmpdir.path().join(checksum_file), format!("{sum}\n"))?;
+        let expected = expected_checksum(Application::WasmBindgen, version, &url, &cfg).await?;
+        ensure!(expected == Some(sum), "unexpected checksum {:?}", expected);
+        Ok(())
+    }
+
+    #[test]
+    fn locate_downloads_in_mirror() {
+        let url = "https://github.com/org/app/releases/download/1.0.0/app-1.0.0.tar.gz";
+        let mut cfg = ConfigOptsTools::default();
+        assert_eq!(locate(url, &cfg), url);
+        assert_eq!(local_path(url), None);
+
+        cfg.mirror = Some("https://mirror.example.com/tools/".to_owned());
+        assert_eq!(
+            locate(url, &cfg),
+            "https://mirror.example.com/tools/app-1.0.0.tar.gz"
+        );
+        cfg.mirror = Some("file:///srv/tools".to_owned());
+        assert_eq!(
+            local_path(&locate(url, &cfg)),
+            Some(Path::new("/srv/tools/app-1.0.0.tar.gz"))
+        );
+    }
+
+    #[test]
+    fn compatible_releases() -> Result<()> {
+        let compatible = |app: Application, current: &str, version: &str| -> Result<bool> {
+            let current = app.semver(current).context("invalid current version")?;
+            let version = app.semver(version).context("invalid version")?;
+            Ok(app.is_compatible(&current, &version))
+        };
+        ensure!(compatible(Application::Sass, "1.63.6", "1.69.0")?);
+        ensure!(!compatible(Application::Sass, "1.63.6", "2.0.0")?);
+        ensure!(!compatible(Application::Sass, "1.63.6", "1.62.0")?);
+        ensure!(!compatible(Application::Sass, "1.63.6", "1.70.0-beta.1")?);
+        ensure!(compatible(Application::Esbuild, "0.19.2", "0.19.8")?);
+        ensure!(!compatible(Application::Esbuild, "0.19.2", "0.20.0")?);
+        ensure!(compatible(
+            Application::WasmOpt,
+            "version_113",
+            "version_116"
+        )?);
+        ensure!(!compatible(
+            Application::WasmOpt,
+            "version_113",
+            "version_112"
+        )?);
+        Ok(())
+    }
+
+    #[test]
+    fn normalize_pinned_versions() {
+        assert_eq!(
+            Application::WasmBindgen.normalize_version("v0.2.87"),
+            "0.2.87"
+        );
+        assert_eq!(Application::Sass.normalize_version(" 1.63.6 "), "1.63.6");
+        assert_eq!(Application::WasmOpt.normalize_version("113"), "version_113");
+        assert_eq!(
+            Application::WasmOpt.normalize_version("version_113"),
+            "version_113"
+        );
+    }
+
     table_test_format_version!(sass_pre_compiled, Application::Sass, "1.37.5", "1.37.5");
     table_test_format_version!(
         tailwindcss_pre_compiled,
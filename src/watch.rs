@@ -1,28 +1,41 @@
-use std::path::PathBuf;
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
 use std::sync::Arc;
-use std::time::Duration;
+use std::time::{Duration, SystemTime};
 
 use anyhow::{Context, Result};
+use console::{style, Term};
 use futures_util::stream::StreamExt;
-use notify::event::ModifyKind;
-use notify::{EventKind, RecommendedWatcher, RecursiveMode, Watcher};
+use globset::GlobSet;
+use ignore::gitignore::Gitignore;
+use notify::event::{MetadataKind, ModifyKind};
+use notify::{EventKind, PollWatcher, RecommendedWatcher, RecursiveMode, Watcher};
 use notify_debouncer_full::{
-    new_debouncer, DebounceEventResult, DebouncedEvent, Debouncer, FileIdMap,
+    new_debouncer_opt, DebounceEventResult, DebouncedEvent, Debouncer, FileIdMap,
 };
+use notify_rust::Notification;
+use tokio::process::Command;
 use tokio::sync::{broadcast, mpsc};
 use tokio::time::Instant;
 use tokio_stream::wrappers::BroadcastStream;
 
-use crate::build::BuildSystem;
-use crate::config::RtcWatch;
+use crate::build::{BuildEvent, BuildReport, BuildSystem};
+use crate::config::{CargoMetadata, RtcWatch};
+use crate::{common, messages};
 
-/// The debouncer type used in this module.
-type FsDebouncer = Debouncer<RecommendedWatcher, FileIdMap>;
+/// The debouncer types used in this module, which stop watching when dropped.
+///
+/// The debouncers are only held to keep watching, so they are never read.
+#[allow(dead_code)]
+enum FsDebouncer {
+    /// A debouncer of native file system events.
+    Native(Debouncer<RecommendedWatcher, FileIdMap>),
+    /// A debouncer of file system changes detected by polling.
+    Poll(Debouncer<PollWatcher, FileIdMap>),
+}
 
 /// Blacklisted path segments which are ignored by the watcher by default.
 const BLACKLIST: [&str; 1] = [".git"];
-/// The duration of time to debounce FS events.
-const DEBOUNCE_DURATION: Duration = Duration::from_millis(25);
 /// The duration of time during which watcher events will be ignored following a build.
 const WATCHER_COOLDOWN: Duration = Duration::from_secs(1);
 
@@ -30,18 +43,26 @@ const WATCHER_COOLDOWN: Duration = Duration::from_secs(1);
 pub struct WatchSystem {
     /// The build system.
     build: BuildSystem,
-    /// The current vector of paths to be ignored.
-    ignored_paths: Vec<PathBuf>,
+    /// The paths to be ignored.
+    ignored: IgnoredPaths,
+    /// Whether to clear the terminal & print a header before each build.
+    clear_screen: bool,
+    /// Whether to show a desktop notification with the result of each build.
+    notifications: bool,
+    /// Commands to run after each successful build.
+    exec: Vec<String>,
     /// A channel of FS watch events.
     watch_rx: mpsc::Receiver<DebouncedEvent>,
     /// A channel of new paths to ignore from the build system.
     build_rx: mpsc::Receiver<PathBuf>,
+    /// A channel of requests to trigger a build.
+    trigger_rx: mpsc::Receiver<()>,
+    /// The sender of build trigger requests, which is handed out to trigger builds.
+    trigger_tx: mpsc::Sender<()>,
     /// The watch system used for watching the filesystem.
     _debouncer: FsDebouncer,
     /// The application shutdown channel.
     shutdown: BroadcastStream<()>,
-    /// Channel that is sent on whenever a build completes.
-    build_done_tx: Option<broadcast::Sender<()>>,
     /// An instant used to track the last build time, used to implement the watcher cooldown
     /// to avoid infinite build loops.
     ///
@@ -54,28 +75,48 @@ pub struct WatchSystem {
 
 impl WatchSystem {
     /// Create a new instance.
-    pub async fn new(
-        cfg: Arc<RtcWatch>,
-        shutdown: broadcast::Sender<()>,
-        build_done_tx: Option<broadcast::Sender<()>>,
-    ) -> Result<Self> {
+    pub async fn new(cfg: Arc<RtcWatch>, shutdown: broadcast::Sender<()>) -> Result<Self> {
         // Create a channel for being able to listen for new paths to ignore while running.
         let (watch_tx, watch_rx) = mpsc::channel(1);
         let (build_tx, build_rx) = mpsc::channel(1);
+        let (trigger_tx, trigger_rx) = mpsc::channel(1);
 
         // Build the watcher.
-        let _debouncer = build_watcher(watch_tx, cfg.paths.clone())?;
+        let paths = watch_paths(&cfg).await;
+        let _debouncer = match cfg.poll {
+            Some(interval) => FsDebouncer::Poll(build_watcher(
+                watch_tx,
+                paths,
+                cfg.debounce,
+                notify::Config::default().with_poll_interval(interval),
+            )?),
+            None => FsDebouncer::Native(build_watcher(
+                watch_tx,
+                paths,
+                cfg.debounce,
+                notify::Config::default(),
+            )?),
+        };
 
         // Build dependencies.
         let build = BuildSystem::new(cfg.build.clone(), Some(build_tx)).await?;
         Ok(Self {
             build,
-            ignored_paths: cfg.ignored_paths.clone(),
+            ignored: IgnoredPaths {
+                paths: cfg.ignored_paths.clone(),
+                globs: cfg.ignore_globs.clone(),
+                gitignores: cfg.gitignores.clone(),
+                contents: HashMap::new(),
+            },
             watch_rx,
+            clear_screen: cfg.clear_screen,
+            notifications: cfg.notifications,
+            exec: cfg.exec.clone(),
             build_rx,
+            trigger_rx,
+            trigger_tx,
             _debouncer,
             shutdown: BroadcastStream::new(shutdown.subscribe()),
-            build_done_tx,
             last_build_finished: Instant::now(),
         })
     }
@@ -83,7 +124,27 @@ impl WatchSystem {
     /// Run a build.
     #[tracing::instrument(level = "trace", skip(self))]
     pub async fn build(&mut self) -> Result<()> {
-        self.build.build().await
+        let started = SystemTime::now();
+        let res = self.build.build().await;
+        if self.notifications {
+            notify_build_done(&res, started);
+        }
+        if res.is_ok() {
+            exec_commands(&self.exec).await;
+        }
+        res.map(|_| ())
+    }
+
+    /// Subscribe to the events of the builds.
+    pub fn subscribe(&self) -> broadcast::Receiver<BuildEvent> {
+        self.build.subscribe()
+    }
+
+    /// Get a channel on which builds can be triggered, regardless of any file system changes.
+    ///
+    /// Triggers sent while a build is running start another build once it finishes.
+    pub fn build_trigger(&self) -> mpsc::Sender<()> {
+        self.trigger_tx.clone()
     }
 
     /// Run the watch system, responding to events and triggering builds.
@@ -91,8 +152,12 @@ impl WatchSystem {
     pub async fn run(mut self) {
         loop {
             tokio::select! {
-                Some(ign) = self.build_rx.recv() => self.update_ignore_list(ign),
+                Some(ign) = self.build_rx.recv() => self.ignored.add(ign),
                 Some(ev) = self.watch_rx.recv() => self.handle_watch_event(ev).await,
+                Some(()) = self.trigger_rx.recv() => {
+                    tracing::info!("build triggered");
+                    self.run_build_and_exec(vec![]).await;
+                }
                 _ = self.shutdown.next() => break, // Any event, even a drop, will trigger shutdown.
             }
         }
@@ -115,28 +180,138 @@ impl WatchSystem {
             return;
         }
 
+        // If a build is not needed, then return.
+        let changes = self.ignored.changes(&event, None).await;
+        if changes.is_empty() {
+            return;
+        }
+
+        // Else, time to trigger a build.
+        self.run_build_and_exec(changes).await;
+    }
+
+    /// Run a build for the given changed paths, followed by the exec commands if it succeeded.
+    ///
+    /// Any changes detected while the commands run cancel them, and trigger another build.
+    async fn run_build_and_exec(&mut self, mut changes: Vec<PathBuf>) {
+        while self.run_build(changes).await && !self.exec.is_empty() {
+            let started = SystemTime::now();
+            let exec = exec_commands(&self.exec);
+            tokio::pin!(exec);
+            changes = loop {
+                tokio::select! {
+                    _ = &mut exec => return,
+                    Some(ign) = self.build_rx.recv() => self.ignored.add(ign),
+                    Some(ev) = self.watch_rx.recv() => {
+                        let changes = self.ignored.changes(&ev, Some(started)).await;
+                        if !changes.is_empty() {
+                            tracing::info!("change detected, cancelling the running commands");
+                            break changes;
+                        }
+                    }
+                }
+            };
+        }
+    }
+
+    /// Run a build for the given changed paths & notify about its completion.
+    ///
+    /// Returns whether the build succeeded.
+    async fn run_build(&mut self, mut changes: Vec<PathBuf>) -> bool {
+        // Any changes detected while building cancel the build,
+        // which is started again to include them.
+        let first_started = SystemTime::now();
+        let res = loop {
+            let started = SystemTime::now();
+            if self.clear_screen {
+                print_banner(started, &changes);
+            }
+            // The build borrows the changes, so it is dropped before they are extended.
+            let res = {
+                let build = self.build.rebuild(&changes);
+                tokio::pin!(build);
+                loop {
+                    tokio::select! {
+                        res = &mut build => break Ok(res),
+                        Some(ign) = self.build_rx.recv() => self.ignored.add(ign),
+                        Some(ev) = self.watch_rx.recv() => {
+                            // Only changes made after the build started cancel it, as the build
+                            // itself may trigger FS events on its sources, see above.
+                            let new_changes = self.ignored.changes(&ev, Some(started)).await;
+                            if !new_changes.is_empty() {
+                                break Err(new_changes);
+                            }
+                        }
+                    }
+                }
+            };
+            match res {
+                Ok(res) => break res,
+                Err(new_changes) => {
+                    tracing::info!("change detected, cancelling the running build");
+                    changes.extend(new_changes);
+                }
+            }
+        };
+        self.last_build_finished = tokio::time::Instant::now();
+        if self.notifications {
+            notify_build_done(&res, first_started);
+        }
+        res.is_ok()
+    }
+}
+
+/// The paths which are ignored by the watcher.
+struct IgnoredPaths {
+    /// The current vector of paths to be ignored.
+    paths: Vec<PathBuf>,
+    /// Globs of paths to be ignored.
+    globs: GlobSet,
+    /// The `.gitignore` files which apply to the watched paths.
+    gitignores: Vec<Gitignore>,
+    /// The hashes of the contents of the paths whose write time changed, used to ignore changes
+    /// of their write time which leave their contents as they were.
+    contents: HashMap<PathBuf, u64>,
+}
+
+impl IgnoredPaths {
+    /// Check the paths of the given event for changes which are not ignored, and which have been
+    /// modified since the given time, if any.
+    ///
+    /// Returns the changed paths, which is empty if a build is not needed.
+    async fn changes(&mut self, event: &DebouncedEvent, since: Option<SystemTime>) -> Vec<PathBuf> {
         // Check each path in the event for a match.
-        match event.event.kind {
+        let write_time = match event.event.kind {
             EventKind::Modify(ModifyKind::Name(_) | ModifyKind::Data(_))
             | EventKind::Create(_)
-            | EventKind::Remove(_) => (),
-            _ => return,
+            | EventKind::Remove(_) => false,
+            // The polling watcher reports modifications as changes of the write time, which are
+            // only changes if the contents changed as well.
+            EventKind::Modify(ModifyKind::Metadata(MetadataKind::WriteTime)) => true,
+            _ => return vec![],
         };
-        let mut found_matching_path = false;
+        let mut changes = Vec::new();
         for ev_path in &event.paths {
-            let ev_path = match tokio::fs::canonicalize(&ev_path).await {
-                Ok(ev_path) => ev_path,
-                // Ignore errors here, as this would only take place for a resource which has
-                // been removed, which will happen for each of our dist/.stage entries.
-                Err(_) => continue,
+            let (ev_path, removed) = match tokio::fs::canonicalize(&ev_path).await {
+                Ok(ev_path) => (ev_path, false),
+                // A removed path can not be canonicalized, so its parent is instead. The removals
+                // of our dist/.stage entries are then ignored along with the dist dir.
+                Err(_) => match canonicalize_removed(ev_path).await {
+                    Some(ev_path) => (ev_path, true),
+                    None => continue,
+                },
             };
 
             // Check ignored paths.
-            if ev_path.ancestors().any(|path| {
-                self.ignored_paths
-                    .iter()
-                    .any(|ignored_path| ignored_path == path)
-            }) {
+            if ev_path
+                .ancestors()
+                .any(|path| self.paths.iter().any(|ignored_path| ignored_path == path))
+            {
+                continue; // Don't emit a notification if path is ignored.
+            }
+
+            // Check ignored globs & gitignored paths.
+            if self.globs.is_match(&ev_path) || self.is_gitignored(&ev_path) {
                 continue; // Don't emit a notification if path is ignored.
             }
 
@@ -149,47 +324,218 @@ impl WatchSystem {
                 continue; // Don't emit a notification as path is on the blacklist.
             }
 
+            // Check the modification time, if needed. Removals are always recent changes, as
+            // they are only reported while the removed path is missing.
+            if let (Some(since), false) = (since, removed) {
+                let modified = tokio::fs::metadata(&ev_path)
+                    .await
+                    .and_then(|metadata| metadata.modified());
+                if !matches!(modified, Ok(modified) if modified >= since) {
+                    continue;
+                }
+            }
+
+            // Check the contents for changes, if only the write time is known to have changed.
+            if write_time && !self.contents_changed(&ev_path).await {
+                continue;
+            }
+
             // If all of the above checks have passed, then we need to trigger a build.
             tracing::debug!("change detected in {:?} of type {:?}", ev_path, event.kind);
-            found_matching_path = true;
+            changes.push(ev_path);
         }
 
-        // If a build is not needed, then return.
-        if !found_matching_path {
-            return;
-        }
+        changes
+    }
 
-        // Else, time to trigger a build.
-        let _res = self.build.build().await;
-        self.last_build_finished = tokio::time::Instant::now();
+    /// Check if the contents of the given path changed since they were last checked, recording
+    /// their hash for the next check.
+    ///
+    /// Contents which have not been checked before, or which can not be read, are considered
+    /// changed.
+    async fn contents_changed(&mut self, path: &Path) -> bool {
+        let Ok(contents) = tokio::fs::read(path).await else {
+            return true;
+        };
+        let hash = seahash::hash(&contents);
+        self.contents.insert(path.to_owned(), hash) != Some(hash)
+    }
 
-        // TODO/NOTE: in the future, we will want to be able to pass along error info and other
-        // diagnostics info over the socket for use in an error overlay or console logging.
-        if let Some(tx) = self.build_done_tx.as_mut() {
-            let _ = tx.send(());
-        }
+    /// Check if the given path is ignored by any of the `.gitignore` files.
+    fn is_gitignored(&self, path: &Path) -> bool {
+        self.gitignores
+            .iter()
+            .filter(|gitignore| path.starts_with(gitignore.path()))
+            .any(|gitignore| {
+                gitignore
+                    .matched_path_or_any_parents(path, path.is_dir())
+                    .is_ignore()
+            })
     }
 
-    fn update_ignore_list(&mut self, arg_path: PathBuf) {
+    /// Add the given path to the ignored paths.
+    fn add(&mut self, arg_path: PathBuf) {
         let path = match arg_path.canonicalize() {
             Ok(canon_path) => canon_path,
             Err(_) => arg_path,
         };
 
-        if !self.ignored_paths.contains(&path) {
-            self.ignored_paths.push(path);
+        if !self.paths.contains(&path) {
+            self.paths.push(path);
         }
     }
 }
 
+/// Get the canonical path of the given removed path, based on the canonical path of its parent.
+async fn canonicalize_removed(path: &Path) -> Option<PathBuf> {
+    let parent = tokio::fs::canonicalize(path.parent()?).await.ok()?;
+    Some(parent.join(path.file_name()?))
+}
+
+/// Clear the terminal & print a header for a build started at the given time, listing the changed
+/// paths which triggered it.
+fn print_banner(started: SystemTime, changes: &[PathBuf]) {
+    let term = if messages::is_json() {
+        Term::stderr()
+    } else {
+        Term::stdout()
+    };
+    let _ = term.clear_screen();
+    messages::print(style(banner(started, changes)).bold());
+}
+
+/// The header for a build started at the given time, listing the changed paths which triggered it.
+fn banner(started: SystemTime, changes: &[PathBuf]) -> String {
+    let trigger = match changes {
+        [] => "build triggered".to_owned(),
+        changes => changes
+            .iter()
+            .map(|path| common::strip_prefix(path).display().to_string())
+            .collect::<Vec<_>>()
+            .join(", "),
+    };
+    format!(
+        "── {} ── {}",
+        humantime::format_rfc3339_seconds(started),
+        trigger
+    )
+}
+
+/// Run the given commands one after another using the system shell, until one of them fails.
+async fn exec_commands(commands: &[String]) {
+    let (shell, flag) = if cfg!(windows) {
+        ("cmd", "/C")
+    } else {
+        ("sh", "-c")
+    };
+    for command in commands {
+        tracing::info!("running {:?}", command);
+        let status = Command::new(shell)
+            .arg(flag)
+            .arg(command)
+            .kill_on_drop(true)
+            .status()
+            .await;
+        match status {
+            Ok(status) if status.success() => tracing::info!("finished {:?}", command),
+            Ok(status) => {
+                tracing::error!("{:?} returned a bad status: {}", command, status);
+                return;
+            }
+            Err(err) => {
+                tracing::error!(error = ?err, "error running {:?}", command);
+                return;
+            }
+        }
+    }
+}
+
+/// Show a desktop notification with the result of the given build, which started at the given time.
+fn notify_build_done(res: &Result<BuildReport>, started: SystemTime) {
+    let (summary, body) = build_done_message(res, started.elapsed().unwrap_or_default());
+    let mut notification = Notification::new();
+    notification.summary(summary).body(&body).appname("trunk");
+    // Showing notifications may block, e.g. on D-Bus, so it is done off of the async runtime.
+    tokio::task::spawn_blocking(move || {
+        if let Err(err) = notification.show() {
+            tracing::debug!(error = ?err, "error showing desktop notification");
+        }
+    });
+}
+
+/// The summary & body of the notification about the given build, which took the given duration.
+fn build_done_message(res: &Result<BuildReport>, duration: Duration) -> (&'static str, String) {
+    let duration = humantime::format_duration(Duration::from_millis(duration.as_millis() as u64));
+    match res {
+        Ok(_) => ("Build succeeded", format!("Finished in {}", duration)),
+        Err(err) => (
+            "Build failed",
+            format!("Failed after {}: {}", duration, err.root_cause()),
+        ),
+    }
+}
+
+/// Get the paths to watch, which include the configured sass load paths & the `src` dirs of all
+/// local crates the Rust app of the build target's parent dir depends on, unless they are watched
+/// or ignored already.
+async fn watch_paths(cfg: &RtcWatch) -> Vec<PathBuf> {
+    let mut paths = cfg.paths.clone();
+    for load_path in &cfg.build.sass.load_paths {
+        let Ok(load_path) = load_path.canonicalize() else {
+            continue;
+        };
+        if paths
+            .iter()
+            .chain(&cfg.ignored_paths)
+            .any(|path| load_path.starts_with(path))
+        {
+            continue;
+        }
+        tracing::debug!("watching sass load path {:?}", load_path);
+        paths.push(load_path);
+    }
+
+    let manifest = cfg.build.target_parent.join("Cargo.toml");
+    if !manifest.is_file() {
+        return paths;
+    }
+    let metadata = match CargoMetadata::new(&manifest, cfg.build.cargo_flags, &cfg.build.cargo_env)
+        .await
+    {
+        Ok(metadata) => metadata,
+        Err(err) => {
+            tracing::warn!(error = ?err, "error getting cargo metadata, local dependencies will not be watched");
+            return paths;
+        }
+    };
+
+    for dir in metadata.local_dependency_dirs() {
+        let Ok(src) = dir.join("src").canonicalize() else {
+            continue;
+        };
+        if paths
+            .iter()
+            .chain(&cfg.ignored_paths)
+            .any(|path| src.starts_with(path))
+        {
+            continue;
+        }
+        tracing::debug!("watching local dependency {:?}", src);
+        paths.push(src);
+    }
+    paths
+}
+
 /// Build a FS watcher, when the watcher is dropped, it will stop watching for events.
-fn build_watcher(
+fn build_watcher<W: Watcher>(
     watch_tx: mpsc::Sender<DebouncedEvent>,
     paths: Vec<PathBuf>,
-) -> Result<FsDebouncer> {
+    debounce: Duration,
+    config: notify::Config,
+) -> Result<Debouncer<W, FileIdMap>> {
     // Build the filesystem watcher & debouncer.
-    let mut debouncer = new_debouncer(
-        DEBOUNCE_DURATION,
+    let mut debouncer: Debouncer<W, FileIdMap> = new_debouncer_opt(
+        debounce,
         None,
         move |result: DebounceEventResult| match result {
             Ok(events) => events.into_iter().for_each(|event| {
@@ -199,6 +545,8 @@ fn build_watcher(
                 .into_iter()
                 .for_each(|err| tracing::warn!(error=?err, "error from filesystem watcher")),
         },
+        FileIdMap::new(),
+        config,
     )
     .context("failed to build file system watcher")?;
 
@@ -218,3 +566,301 @@ fn build_watcher(
 
     Ok(debouncer)
 }
+
+#[cfg(test)]
+mod tests {
+    use notify::event::{AccessKind, DataChange, MetadataKind, ModifyKind, RemoveKind};
+    use notify::{Event, EventKind};
+    use notify_debouncer_full::DebouncedEvent;
+
+    use super::*;
+    use crate::config::RtcServe;
+
+    fn ignored() -> IgnoredPaths {
+        IgnoredPaths {
+            paths: vec![],
+            globs: GlobSet::empty(),
+            gitignores: vec![],
+            contents: HashMap::new(),
+        }
+    }
+
+    fn event(kind: EventKind, path: &Path) -> DebouncedEvent {
+        DebouncedEvent::new(
+            Event::new(kind).add_path(path.to_owned()),
+            std::time::Instant::now(),
+        )
+    }
+
+    #[tokio::test]
+    async fn ignores_metadata_only_changes() -> Result<()> {
+        // Assemble.
+        let tmpdir = tempfile::tempdir()?;
+        let path = tmpdir.path().canonicalize()?.join("index.html");
+        std::fs::write(&path, "<html></html>")?;
+        let mut ignored = ignored();
+
+        // Action & Assert.
+        for kind in [
+            EventKind::Access(AccessKind::Any),
+            EventKind::Modify(ModifyKind::Metadata(MetadataKind::Any)),
+            EventKind::Modify(ModifyKind::Metadata(MetadataKind::Permissions)),
+        ] {
+            let changes = ignored.changes(&event(kind, &path), None).await;
+            anyhow::ensure!(changes.is_empty(), "{:?} is not a change", kind);
+        }
+        let kind = EventKind::Modify(ModifyKind::Data(DataChange::Content));
+        let changes = ignored.changes(&event(kind, &path), None).await;
+        anyhow::ensure!(changes == [path.clone()], "{:?} is a change", kind);
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn ignores_write_time_changes_unless_contents_changed() -> Result<()> {
+        // Assemble.
+        let tmpdir = tempfile::tempdir()?;
+        let path = tmpdir.path().canonicalize()?.join("index.html");
+        std::fs::write(&path, "<html></html>")?;
+        let write_time = event(
+            EventKind::Modify(ModifyKind::Metadata(MetadataKind::WriteTime)),
+            &path,
+        );
+        let mut ignored = ignored();
+
+        // Action.
+        let first = ignored.changes(&write_time, None).await;
+        let touched = ignored.changes(&write_time, None).await;
+        std::fs::write(&path, "<html><body></body></html>")?;
+        let modified = ignored.changes(&write_time, None).await;
+
+        // Assert.
+        anyhow::ensure!(
+            first == [path.clone()],
+            "unknown contents must be considered changed"
+        );
+        anyhow::ensure!(touched.is_empty(), "unchanged contents must be ignored");
+        anyhow::ensure!(modified == [path], "changed contents must be detected");
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn detects_removals_while_building() -> Result<()> {
+        // Assemble.
+        let tmpdir = tempfile::tempdir()?;
+        let dir = tmpdir.path().canonicalize()?;
+        let path = dir.join("style.css");
+        let dist = dir.join("dist");
+        std::fs::create_dir(&dist)?;
+        let mut ignored = ignored();
+        ignored.add(dist.clone());
+        let started = SystemTime::now();
+        let removal = |path: &Path| event(EventKind::Remove(RemoveKind::File), path);
+
+        // Action.
+        let changes = ignored.changes(&removal(&path), Some(started)).await;
+        let ignored_changes = ignored
+            .changes(&removal(&dist.join("index.html")), Some(started))
+            .await;
+
+        // Assert.
+        anyhow::ensure!(changes == [path], "removals must be detected");
+        anyhow::ensure!(
+            ignored_changes.is_empty(),
+            "removals in ignored paths must be ignored"
+        );
+        Ok(())
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn holds_back_changes_for_the_debounce_duration() -> Result<()> {
+        // Assemble.
+        let tmpdir = tempfile::tempdir()?;
+        let dir = tmpdir.path().canonicalize()?;
+        let path = dir.join("index.html");
+        std::fs::write(&path, "<html></html>")?;
+        let debounce = Duration::from_millis(500);
+        let (watch_tx, mut watch_rx) = mpsc::channel(16);
+        let _debouncer = build_watcher::<RecommendedWatcher>(
+            watch_tx,
+            vec![dir],
+            debounce,
+            notify::Config::default(),
+        )?;
+
+        // Action.
+        let first_write = std::time::Instant::now();
+        for i in 0..5 {
+            std::fs::write(&path, format!("<html>{}</html>", i))?;
+            tokio::time::sleep(Duration::from_millis(20)).await;
+        }
+        let mut events = Vec::new();
+        while let Ok(Some(ev)) = tokio::time::timeout(debounce * 3, watch_rx.recv()).await {
+            events.push((ev, std::time::Instant::now()));
+        }
+
+        // Assert.
+        let mut ignored = ignored();
+        let mut changes = Vec::new();
+        for (ev, received) in &events {
+            anyhow::ensure!(
+                received.duration_since(first_write) >= debounce
+                    && received.duration_since(ev.time) >= debounce,
+                "events must be held back for the debounce duration, got {:?}",
+                events
+            );
+            changes.extend(ignored.changes(ev, None).await);
+        }
+        changes.dedup();
+        anyhow::ensure!(changes == [path], "the changes must be reported");
+        Ok(())
+    }
+
+    /// Write a crate with the given name & dependencies to the given dir.
+    fn write_crate(dir: &Path, name: &str, dependencies: &[(&str, &Path)]) -> Result<()> {
+        let dependencies = dependencies
+            .iter()
+            .map(|(name, path)| format!("{} = {{ path = {:?} }}\n", name, path))
+            .collect::<String>();
+        std::fs::create_dir_all(dir.join("src"))?;
+        std::fs::write(
+            dir.join("Cargo.toml"),
+            format!(
+                "[package]\nname = {:?}\nversion = \"0.1.0\"\nedition = \
+                 \"2021\"\n\n[dependencies]\n{}",
+                name, dependencies
+            ),
+        )?;
+        std::fs::write(dir.join("src").join("lib.rs"), "")?;
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn watches_src_dirs_of_local_dependencies() -> Result<()> {
+        // Assemble.
+        let tmpdir = tempfile::tempdir()?;
+        let app = tmpdir.path().canonicalize()?;
+        let depsdir = tempfile::tempdir()?;
+        let deps = depsdir.path().canonicalize()?;
+        write_crate(&deps.join("core"), "core-dep", &[])?;
+        write_crate(
+            &deps.join("util"),
+            "util-dep",
+            &[("core-dep", &deps.join("core"))],
+        )?;
+        write_crate(&app.join("nested"), "nested-dep", &[])?;
+        write_crate(
+            &app,
+            "app",
+            &[
+                ("util-dep", &deps.join("util")),
+                ("nested-dep", &app.join("nested")),
+            ],
+        )?;
+        let cfg = RtcServe::new_test(&app).await?;
+
+        // Action.
+        let mut paths = watch_paths(&cfg.watch).await;
+
+        // Assert.
+        paths.sort();
+        let mut expected = vec![
+            app.clone(),
+            deps.join("core").join("src"),
+            deps.join("util").join("src"),
+        ];
+        expected.sort();
+        anyhow::ensure!(
+            paths == expected,
+            "the src dirs of the local dependencies which are not watched yet must be watched, \
+             got {:?}",
+            paths
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn banner_lists_the_changed_paths() {
+        let started = SystemTime::UNIX_EPOCH + Duration::from_secs(1_700_000_000);
+        let cwd = std::env::current_dir().expect("error getting cwd");
+        let changes = [
+            cwd.join("src").join("main.rs"),
+            PathBuf::from("/abs/style.css"),
+        ];
+
+        assert_eq!(
+            banner(started, &changes),
+            format!(
+                "── 2023-11-14T22:13:20Z ── {}, /abs/style.css",
+                Path::new("src").join("main.rs").display()
+            )
+        );
+        assert_eq!(
+            banner(started, &[]),
+            "── 2023-11-14T22:13:20Z ── build triggered"
+        );
+    }
+
+    #[test]
+    fn notifies_about_the_result_of_builds() {
+        let report = BuildReport {
+            id: 1,
+            started: SystemTime::UNIX_EPOCH,
+            finished: SystemTime::UNIX_EPOCH,
+            changed_assets: vec![],
+        };
+        let err = Err(anyhow::anyhow!("error: expected `;`").context("error building wasm"));
+
+        assert_eq!(
+            build_done_message(&Ok(report), Duration::from_micros(1_250_400)),
+            ("Build succeeded", "Finished in 1s 250ms".to_owned())
+        );
+        assert_eq!(
+            build_done_message(&err, Duration::from_millis(300)),
+            (
+                "Build failed",
+                "Failed after 300ms: error: expected `;`".to_owned()
+            )
+        );
+    }
+
+    #[cfg(unix)]
+    #[tokio::test]
+    async fn exec_commands_run_in_order_until_one_fails() -> Result<()> {
+        // Assemble.
+        let tmpdir = tempfile::tempdir()?;
+        let out = tmpdir.path().join("out.txt");
+        let commands = [
+            format!("echo first >> {:?}", out),
+            format!("echo second >> {:?}", out),
+            "exit 1".to_owned(),
+            format!("echo third >> {:?}", out),
+        ];
+
+        // Action.
+        exec_commands(&commands).await;
+
+        // Assert.
+        let out = std::fs::read_to_string(out)?;
+        anyhow::ensure!(out == "first\nsecond\n", "unexpected output {:?}", out);
+        Ok(())
+    }
+
+    #[cfg(unix)]
+    #[tokio::test]
+    async fn exec_commands_are_killed_if_cancelled() -> Result<()> {
+        // Assemble.
+        let tmpdir = tempfile::tempdir()?;
+        let out = tmpdir.path().join("out.txt");
+        let commands = [format!("sleep 1; echo done >> {:?}", out)];
+
+        // Action.
+        let cancelled =
+            tokio::time::timeout(Duration::from_millis(200), exec_commands(&commands)).await;
+        tokio::time::sleep(Duration::from_millis(1500)).await;
+
+        // Assert.
+        anyhow::ensure!(cancelled.is_err(), "the commands must still be running");
+        anyhow::ensure!(!out.exists(), "cancelled commands must be killed");
+        Ok(())
+    }
+}
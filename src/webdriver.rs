@@ -0,0 +1,253 @@
+//! A minimal WebDriver client, for driving headless browsers through chromedriver & geckodriver.
+
+use std::net::TcpListener;
+use std::path::{Path, PathBuf};
+use std::process::Stdio;
+use std::time::Duration;
+
+use anyhow::{bail, Context, Result};
+use clap::ValueEnum;
+use serde_json::{json, Value};
+use tokio::process::{Child, Command};
+
+/// The key of the ID of an element in the responses of WebDriver.
+const ELEMENT_KEY: &str = "element-6066-11e4-a52e-4f735466cecf";
+/// The time to wait for a spawned WebDriver server to be ready.
+const STARTUP_TIMEOUT: Duration = Duration::from_secs(10);
+/// The number of free ports on which a WebDriver server is spawned, until it does not exit.
+const SPAWN_ATTEMPTS: usize = 3;
+
+/// The browsers which can be driven.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, ValueEnum)]
+pub enum Browser {
+    Chrome,
+    Firefox,
+}
+
+impl Browser {
+    /// The name of the WebDriver server of the browser.
+    fn driver_name(self) -> &'static str {
+        match self {
+            Self::Chrome => "chromedriver",
+            Self::Firefox => "geckodriver",
+        }
+    }
+
+    /// The env var which may hold the path to the WebDriver server of the browser.
+    fn driver_env(self) -> &'static str {
+        match self {
+            Self::Chrome => "CHROMEDRIVER",
+            Self::Firefox => "GECKODRIVER",
+        }
+    }
+
+    /// The capabilities to request a session of the browser with.
+    fn capabilities(self, headless: bool) -> Value {
+        match self {
+            Self::Chrome => {
+                let mut args = vec!["no-sandbox", "disable-dev-shm-usage"];
+                if headless {
+                    args.push("headless");
+                }
+                json!({ "browserName": "chrome", "goog:chromeOptions": { "args": args } })
+            }
+            Self::Firefox => {
+                let args: &[&str] = if headless { &["-headless"] } else { &[] };
+                json!({ "browserName": "firefox", "moz:firefoxOptions": { "args": args } })
+            }
+        }
+    }
+}
+
+/// A WebDriver server, which is killed when dropped if it was spawned by Trunk.
+pub struct Driver {
+    url: String,
+    client: reqwest::Client,
+    child: Option<Child>,
+}
+
+impl Driver {
+    /// Connect to the WebDriver server running at the given URL.
+    pub fn connect(url: &str) -> Result<Self> {
+        Ok(Self {
+            url: url.trim_end_matches('/').to_owned(),
+            client: client()?,
+            child: None,
+        })
+    }
+
+    /// Spawn the WebDriver server of the given browser on a free port, waiting for it to be ready.
+    pub async fn spawn(browser: Browser) -> Result<Self> {
+        let path = match std::env::var_os(browser.driver_env()) {
+            Some(path) => PathBuf::from(path),
+            None => which::which(browser.driver_name()).with_context(|| {
+                format!(
+                    "could not find {} in the PATH, install it or set the {} env var to its path",
+                    browser.driver_name(),
+                    browser.driver_env()
+                )
+            })?,
+        };
+        // The free port is released before the driver binds it, so another process may take it
+        // in the meantime, in which case the driver exits & is spawned on another free port.
+        for _ in 1..SPAWN_ATTEMPTS {
+            if let Some(driver) = Self::spawn_on_free_port(browser, &path).await? {
+                return Ok(driver);
+            }
+            tracing::debug!("{} exited before getting ready", browser.driver_name());
+        }
+        match Self::spawn_on_free_port(browser, &path).await? {
+            Some(driver) => Ok(driver),
+            None => bail!("{} exited before getting ready", browser.driver_name()),
+        }
+    }
+
+    /// Spawn the WebDriver server at the given path on a free port, waiting for it to be ready,
+    /// unless it exits before.
+    async fn spawn_on_free_port(browser: Browser, path: &Path) -> Result<Option<Self>> {
+        let port = TcpListener::bind("127.0.0.1:0")
+            .and_then(|listener| listener.local_addr())
+            .context("error finding a free port for the WebDriver server")?
+            .port();
+        tracing::debug!(?path, port, "spawning {}", browser.driver_name());
+        let child = Command::new(path)
+            .arg(format!("--port={}", port))
+            .stdout(Stdio::null())
+            .stderr(Stdio::null())
+            .kill_on_drop(true)
+            .spawn()
+            .with_context(|| format!("error spawning {} call", browser.driver_name()))?;
+        let mut driver = Self {
+            url: format!("http://127.0.0.1:{}", port),
+            client: client()?,
+            child: Some(child),
+        };
+
+        let started = tokio::time::Instant::now();
+        loop {
+            match driver.request(reqwest::Method::GET, "/status", None).await {
+                Ok(status) if status["ready"].as_bool().unwrap_or(true) => return Ok(Some(driver)),
+                _ if started.elapsed() > STARTUP_TIMEOUT => bail!(
+                    "{} did not get ready within {:?}",
+                    browser.driver_name(),
+                    STARTUP_TIMEOUT
+                ),
+                _ => {}
+            }
+            let exited = match &mut driver.child {
+                Some(child) => child.try_wait().ok().flatten().is_some(),
+                None => false,
+            };
+            if exited {
+                return Ok(None);
+            }
+            tokio::time::sleep(Duration::from_millis(100)).await;
+        }
+    }
+
+    /// Start a new session of the given browser.
+    pub async fn session(&self, browser: Browser, headless: bool) -> Result<Session<'_>> {
+        let body = json!({
+            "capabilities": { "alwaysMatch": browser.capabilities(headless) }
+        });
+        let value = self
+            .request(reqwest::Method::POST, "/session", Some(body))
+            .await
+            .context("error starting a WebDriver session")?;
+        let id = value["sessionId"]
+            .as_str()
+            .context("WebDriver session has no ID")?
+            .to_owned();
+        Ok(Session { driver: self, id })
+    }
+
+    /// Send a request to the WebDriver server, returning the `value` of its response.
+    async fn request(
+        &self,
+        method: reqwest::Method,
+        path: &str,
+        body: Option<Value>,
+    ) -> Result<Value> {
+        let mut req = self.client.request(method, format!("{}{}", self.url, path));
+        if let Some(body) = body {
+            req = req
+                .header(reqwest::header::CONTENT_TYPE, "application/json")
+                .body(body.to_string());
+        }
+        let text = req
+            .send()
+            .await
+            .context("error sending WebDriver request")?
+            .text()
+            .await
+            .context("error reading WebDriver response")?;
+        let mut resp: Value =
+            serde_json::from_str(&text).context("error parsing WebDriver response")?;
+        let value = resp["value"].take();
+        if let Some(error) = value["error"].as_str() {
+            bail!(
+                "WebDriver error {}: {}",
+                error,
+                value["message"].as_str().unwrap_or_default()
+            );
+        }
+        Ok(value)
+    }
+}
+
+/// Build an HTTP client for WebDriver servers, which are local and so never proxied.
+fn client() -> Result<reqwest::Client> {
+    reqwest::Client::builder()
+        .no_proxy()
+        .build()
+        .context("error building HTTP client")
+}
+
+/// A session of a browser, which should be closed when done.
+pub struct Session<'a> {
+    driver: &'a Driver,
+    id: String,
+}
+
+impl Session<'_> {
+    /// Navigate to the given URL, waiting for the page to load.
+    pub async fn goto(&self, url: &str) -> Result<()> {
+        let path = format!("/session/{}/url", self.id);
+        self.driver
+            .request(reqwest::Method::POST, &path, Some(json!({ "url": url })))
+            .await
+            .with_context(|| format!("error navigating to {}", url))?;
+        Ok(())
+    }
+
+    /// Get the text of the first element matching the given CSS selector.
+    pub async fn text(&self, selector: &str) -> Result<String> {
+        let path = format!("/session/{}/element", self.id);
+        let body = json!({ "using": "css selector", "value": selector });
+        let element = self
+            .driver
+            .request(reqwest::Method::POST, &path, Some(body))
+            .await
+            .with_context(|| format!("error finding element {:?}", selector))?;
+        let element = element[ELEMENT_KEY]
+            .as_str()
+            .with_context(|| format!("element {:?} has no ID", selector))?;
+        let path = format!("/session/{}/element/{}/text", self.id, element);
+        let text = self
+            .driver
+            .request(reqwest::Method::GET, &path, None)
+            .await
+            .with_context(|| format!("error getting text of element {:?}", selector))?;
+        Ok(text.as_str().unwrap_or_default().to_owned())
+    }
+
+    /// Close the session, which closes its browser.
+    pub async fn close(self) -> Result<()> {
+        let path = format!("/session/{}", self.id);
+        self.driver
+            .request(reqwest::Method::DELETE, &path, None)
+            .await
+            .context("error closing the WebDriver session")?;
+        Ok(())
+    }
+}
@@ -0,0 +1,71 @@
+//! The stdout of `--message-format json` is reserved for the JSON messages.
+
+use std::path::Path;
+use std::process::Command;
+
+/// Run trunk in the given dir with JSON messages, returning its stdout & stderr.
+fn run_json(dir: &Path, args: &[&str]) -> (String, String) {
+    let output = Command::new(env!("CARGO_BIN_EXE_trunk"))
+        .args(["--message-format", "json"])
+        .args(args)
+        .current_dir(dir)
+        .env("TRUNK_NO_DOTENV", "true")
+        .output()
+        .expect("error running trunk");
+    (
+        String::from_utf8_lossy(&output.stdout).into_owned(),
+        String::from_utf8_lossy(&output.stderr).into_owned(),
+    )
+}
+
+/// Assert that each line of the given stdout is a JSON message.
+fn assert_json_lines(stdout: &str) {
+    for line in stdout.lines() {
+        let message: serde_json::Value = serde_json::from_str(line)
+            .unwrap_or_else(|err| panic!("invalid JSON line {:?}: {}", line, err));
+        assert!(
+            message["reason"].is_string(),
+            "unexpected message {}",
+            message
+        );
+    }
+}
+
+#[test]
+fn human_output_goes_to_stderr() {
+    let tmpdir = tempfile::tempdir().expect("error building tempdir for test");
+    std::fs::write(tmpdir.path().join("index.html"), "<html></html>")
+        .expect("error writing test page");
+    std::fs::write(
+        tmpdir.path().join("Trunk.toml"),
+        "[build]\ntarget = \"index.html\"\n",
+    )
+    .expect("error writing test config");
+
+    let (stdout, stderr) = run_json(tmpdir.path(), &["config", "show"]);
+    assert_json_lines(&stdout);
+    assert!(stderr.contains("[build]"), "missing config in {}", stderr);
+
+    let (stdout, stderr) = run_json(tmpdir.path(), &["config", "schema"]);
+    assert_json_lines(&stdout);
+    assert!(
+        stderr.contains("Trunk.toml"),
+        "missing schema in {}",
+        stderr
+    );
+
+    let (stdout, _) = run_json(tmpdir.path(), &["tools", "list"]);
+    assert_json_lines(&stdout);
+
+    // The errors of failed commands are messages themselves.
+    let (stdout, _) = run_json(
+        tmpdir.path(),
+        &["config", "show", "--dist", "index.html", "--check"],
+    );
+    assert_json_lines(&stdout);
+    assert!(
+        stdout.contains(r#""reason":"error""#),
+        "missing error in {}",
+        stdout
+    );
+}